@@ -1,7 +1,9 @@
 use core::panic;
+use std::str::FromStr;
 
 use clap::{Parser, Subcommand};
-use ddk::bitcoin::Transaction;
+use ddk::transport::PeerInformation;
+use ddk::wallet::TransactionDetails;
 use ddk::dlc::{EnumerationPayout, Payout};
 use ddk::dlc_manager::contract::contract_input::ContractInput;
 use ddk::dlc_manager::contract::offered_contract::OfferedContract;
@@ -217,7 +219,7 @@ async fn main() -> anyhow::Result<()> {
                 let txns = transactions.transactions
                     .iter()
                     .map(|txn| serde_json::from_slice(txn).unwrap())
-                    .collect::<Vec<Transaction>>();
+                    .collect::<Vec<TransactionDetails>>();
                 let txns = serde_json::to_string_pretty(&txns)?;
                 print!("{}", txns)
             }
@@ -226,7 +228,12 @@ async fn main() -> anyhow::Result<()> {
                     .list_utxos(ListUtxosRequest::default())
                     .await?
                     .into_inner();
-                let utxos = serde_json::to_string_pretty(&utxos.utxos)?;
+                let utxos = utxos
+                    .utxos
+                    .iter()
+                    .map(|utxo| serde_json::from_slice(utxo))
+                    .collect::<Result<Vec<serde_json::Value>, _>>()?;
+                let utxos = serde_json::to_string_pretty(&utxos)?;
                 print!("{}", utxos)
             }
         },
@@ -237,9 +244,9 @@ async fn main() -> anyhow::Result<()> {
              
         }
         CliCommand::Connect { connect_string } => {
-            let parts = connect_string.split("@").collect::<Vec<&str>>();
-            client.connect_peer(ConnectRequest { pubkey: parts[0].to_string(), host: parts[1].to_string() }).await?;
-            println!("Connected to {}", parts[0])
+            let peer = PeerInformation::from_str(&connect_string)?;
+            client.connect_peer(ConnectRequest { pubkey: peer.pubkey.clone(), host: peer.host }).await?;
+            println!("Connected to {}", peer.pubkey)
         }
     }
 
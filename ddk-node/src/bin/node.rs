@@ -4,6 +4,7 @@ use std::sync::Arc;
 use clap::Parser;
 use ddk::config::{DdkConfig, SeedConfig};
 use ddk::builder::DdkBuilder;
+use ddk::logging::DdkSubscriberBuilder;
 use ddk::storage::SledStorageProvider;
 use ddk::oracle::KormirOracleClient;
 use ddk::transport::lightning::LightningTransport;
@@ -25,6 +26,9 @@ struct NodeArgs {
     #[arg(default_value = "info")]
     #[arg(value_parser = ["info", "debug"])]
     log: String,
+    #[arg(long)]
+    #[arg(help = "Log one JSON object per line.")]
+    log_json: bool,
     #[arg(short, long)]
     #[arg(help = "Set the Bitcoin network for DDK")]
     #[arg(default_value = "regtest")]
@@ -54,7 +58,10 @@ struct NodeArgs {
     #[arg(help = "Seed config strategy.")]
     #[arg(default_value = "file")]
     #[arg(value_parser = ["file", "bytes"])]
-    seed: String
+    seed: String,
+    #[arg(long)]
+    #[arg(help = "TOML config file. Replaces the network, storage, esplora, oracle, port, and seed flags.")]
+    config: Option<PathBuf>,
 }
 
 #[tokio::main]
@@ -62,41 +69,31 @@ async fn main() -> anyhow::Result<()> {
     let args = NodeArgs::parse();
 
     let level = LevelFilter::from_str(&args.log).unwrap_or(LevelFilter::INFO);
-    let subscriber = tracing_subscriber::fmt()
-        .with_line_number(true)
-        .with_max_level(level)
-        .finish();
-    tracing::subscriber::set_global_default(subscriber).unwrap();
+    DdkSubscriberBuilder::new()
+        .set_level(level)
+        .set_json(args.log_json)
+        .init()?;
 
-    let mut config = DdkConfig::default();
-    let storage_path = match args.storage_dir {
-        Some(storage) => storage,
-        None => homedir::my_home().expect("Provide a directory for ddk.").unwrap().join(".ddk").join("default-ddk")
-    };
-    config.storage_path = storage_path.clone();
-    config.esplora_host = args.esplora_host;
-    config.network = Network::from_str(&args.network)?;
-    config.seed_config = match args.seed.as_str() {
-        "bytes" => SeedConfig::Bytes([0u8; 64]),
-        _ => SeedConfig::File(storage_path.to_str().unwrap().to_string()),
+    let (config, oracle_host) = match &args.config {
+        Some(path) => {
+            let config = DdkConfig::from_file(path)?;
+            let oracle_host = config.oracle_url.clone().unwrap_or(args.oracle_host.clone());
+            (config, oracle_host)
+        }
+        None => (config_from_args(&args)?, args.oracle_host.clone()),
     };
 
-    std::fs::create_dir_all(storage_path)?;
+    std::fs::create_dir_all(&config.storage_path)?;
 
     tracing::info!("Starting DDK node.");
 
-    let transport = Arc::new(LightningTransport::new(&config.seed_config, args.listening_port, config.network)?);
-    let storage = Arc::new(SledStorageProvider::new(
-        config.storage_path.join("sled_db").to_str().unwrap(),
-    )?);
+    let transport = Arc::new(LightningTransport::new(&config.seed_config, config.transport.listening_port, config.network)?);
 
     // let oracle = Arc::new(P2PDOracleClient::new(&oracle_host).await?);
-    let oracle = Arc::new(KormirOracleClient::new(&args.oracle_host).await?);
+    let oracle = Arc::new(KormirOracleClient::new(&oracle_host).await?);
 
-    let mut builder = DdkBuilder::new();
-    builder.set_config(config);
+    let mut builder = DdkBuilder::from_config(config);
     builder.set_transport(transport.clone());
-    builder.set_storage(storage.clone());
     builder.set_oracle(oracle.clone());
 
     let ddk: DdkServer = builder.finish()?;
@@ -112,3 +109,20 @@ async fn main() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+fn config_from_args(args: &NodeArgs) -> anyhow::Result<DdkConfig> {
+    let mut config = DdkConfig::default();
+    let storage_path = match &args.storage_dir {
+        Some(storage) => storage.clone(),
+        None => homedir::my_home().expect("Provide a directory for ddk.").unwrap().join(".ddk").join("default-ddk")
+    };
+    config.storage_path = storage_path.clone();
+    config.esplora_host = args.esplora_host.clone();
+    config.network = Network::from_str(&args.network)?;
+    config.transport.listening_port = args.listening_port;
+    config.seed_config = match args.seed.as_str() {
+        "bytes" => SeedConfig::Bytes([0u8; 64]),
+        _ => SeedConfig::File(storage_path.to_str().unwrap().to_string()),
+    };
+    Ok(config)
+}
@@ -42,9 +42,9 @@ impl DdkRpc for DdkNode {
     #[tracing::instrument(skip(self, _request), name = "grpc_server")]
     async fn info(&self, _request: Request<InfoRequest>) -> Result<Response<InfoResponse>, Status> {
         tracing::info!("Request for node info.");
-        let pubkey = self.inner.transport.node_id.to_string();
-        let transport = self.inner.transport.name();
-        let oracle = self.inner.oracle.name();
+        let pubkey = self.inner.transport().node_id.to_string();
+        let transport = self.inner.transport().name();
+        let oracle = self.inner.oracle().name();
         let response = InfoResponse {
             pubkey,
             transport,
@@ -67,14 +67,14 @@ impl DdkRpc for DdkNode {
             serde_json::from_slice(&contract_input).expect("couldn't get bytes correct");
         let mut oracle_announcements = Vec::new();
         for info in &contract_input.contract_infos {
-            let announcement = self.inner.oracle.get_announcement_async(&info.oracles.event_id).await.unwrap();
+            let announcement = self.inner.oracle().get_announcement_async(&info.oracles.event_id).await.unwrap();
             oracle_announcements.push(announcement)
         }
 
         let counter_party = PublicKey::from_str(&counter_party).expect("no public key");
         let offer_msg = self
             .inner
-            .send_dlc_offer(&contract_input, counter_party, oracle_announcements).map_err(|e| Status::new(Code::Cancelled, format!("Contract offer could not be sent to counterparty. error={:?}", e)))?;
+            .send_dlc_offer_async(&contract_input, counter_party, oracle_announcements).await.map_err(|e| Status::new(Code::Cancelled, format!("Contract offer could not be sent to counterparty. error={:?}", e)))?;
 
         let offer_dlc =
             serde_json::to_vec(&offer_msg).expect("OfferDlc could not be converted to vec.");
@@ -93,7 +93,7 @@ impl DdkRpc for DdkNode {
         println!("{:?}", contract_id);
         let (contract_id, counter_party, accept_dlc) = self
             .inner
-            .accept_dlc_offer(contract_id).map_err(|_| Status::new(Code::Cancelled, "Contract could not be accepted."))?;
+            .accept_dlc_offer_async(contract_id).await.map_err(|_| Status::new(Code::Cancelled, "Contract could not be accepted."))?;
 
         let accept_dlc = serde_json::to_vec(&accept_dlc).map_err(|_| Status::new(Code::Cancelled, "Accept DLC is malformed to create bytes."))?;
 
@@ -126,7 +126,7 @@ impl DdkRpc for DdkNode {
         _request: Request<ListOffersRequest>,
     ) -> Result<Response<ListOffersResponse>, Status> {
         tracing::info!("Request for offers to the node.");
-        let offers = self.inner.storage.get_contract_offers().unwrap();
+        let offers = self.inner.storage().get_contract_offers().unwrap();
         let offers: Vec<Vec<u8>> = offers
             .iter()
             .map(|offer| serde_json::to_vec(offer).unwrap())
@@ -141,7 +141,7 @@ impl DdkRpc for DdkNode {
         _request: Request<WalletBalanceRequest>,
     ) -> Result<Response<WalletBalanceResponse>, Status> {
         tracing::info!("Request for wallet balance.");
-        let wallet_balance = self.inner.wallet.get_balance().unwrap();
+        let wallet_balance = self.inner.wallet().get_balance().unwrap();
 
         let response = WalletBalanceResponse {
             confirmed: wallet_balance.confirmed.to_sat(),
@@ -156,7 +156,7 @@ impl DdkRpc for DdkNode {
         _request: Request<GetWalletTransactionsRequest>,
     ) -> Result<Response<GetWalletTransactionsResponse>, Status> {
         tracing::info!("Request for all wallet transactions.");
-        let wallet_transactions = self.inner.wallet.get_transactions().unwrap();
+        let wallet_transactions = self.inner.wallet().get_transactions().unwrap();
         let transactions: Vec<Vec<u8>> = wallet_transactions
             .iter()
             .map(|t| serde_json::to_vec(&t).unwrap())
@@ -172,7 +172,7 @@ impl DdkRpc for DdkNode {
         _request: Request<ListUtxosRequest>,
     ) -> Result<Response<ListUtxosResponse>, Status> {
         tracing::info!("Request to list all wallet utxos");
-        let utxos = self.inner.wallet.list_utxos().unwrap();
+        let utxos = self.inner.wallet().list_utxos().unwrap();
         let utxos: Vec<Vec<u8>> = utxos
             .iter()
             .map(|utxo| serde_json::to_vec(utxo).unwrap())
@@ -184,7 +184,7 @@ impl DdkRpc for DdkNode {
     #[tracing::instrument(skip(self, _request), name = "grpc_server")]
     async fn list_peers(&self, _request: Request<ListPeersRequest>) -> Result<Response<ListPeersResponse>, Status> {
         tracing::info!("List peers request");
-        let peers = self.inner.transport.ln_peer_manager().list_peers();
+        let peers = self.inner.transport().ln_peer_manager().list_peers();
         let peers = peers.iter()
             .map(|peer| {
                 let host = match &peer.socket_address {
@@ -206,18 +206,21 @@ impl DdkRpc for DdkNode {
     async fn connect_peer(&self, request: Request<ConnectRequest>) -> Result<Response<ConnectResponse>, Status> {
         let ConnectRequest { pubkey, host } = request.into_inner();
         let pubkey = PublicKey::from_str(&pubkey).unwrap();
-        self.inner.transport.connect_outbound(pubkey, &host).await;
+        self.inner
+            .connect_peer(pubkey, &host)
+            .await
+            .map_err(|e| Status::new(Code::Internal, e.to_string()))?;
         Ok(Response::new(ConnectResponse {}))
     }
 
     async fn list_oracles(&self, _request: Request<ListOraclesRequest>) -> Result<Response<ListOraclesResponse>, Status> {
-        let pubkey = self.inner.oracle.get_public_key_async().await.unwrap().to_string();
-        let name = self.inner.oracle.name();
+        let pubkey = self.inner.oracle().get_public_key_async().await.unwrap().to_string();
+        let name = self.inner.oracle().name();
         Ok(Response::new(ListOraclesResponse { name, pubkey }))
     }
 
     async fn list_contracts(&self, _request: Request<ListContractsRequest>) -> Result<Response<ListContractsResponse>, Status> {
-        let contracts = self.inner.storage.get_contracts().map_err(|e| Status::new(Code::Cancelled, e.to_string()))?;
+        let contracts = self.inner.storage().get_contracts().map_err(|e| Status::new(Code::Cancelled, e.to_string()))?;
         let contract_bytes: Vec<Vec<u8>> = contracts.iter()
             .map(|contract| serialize_contract(contract).unwrap())
             .collect();
@@ -0,0 +1,9 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    #[cfg(feature = "grpc")]
+    tonic_build::configure()
+        .build_client(true)
+        .build_server(true)
+        .compile(&["proto/ddk.proto"], &["proto"])?;
+
+    Ok(())
+}
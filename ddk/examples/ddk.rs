@@ -2,7 +2,7 @@ use anyhow::Result;
 use ddk::builder::DdkBuilder;
 use ddk::config::DdkConfig;
 use ddk::oracle::P2PDOracleClient;
-use ddk::storage::SledStorageProvider;
+use ddk::storage::{SledStorageProvider, SLED_DB_DIR};
 use ddk::transport::lightning::LightningTransport;
 use std::sync::Arc;
 
@@ -20,7 +20,7 @@ async fn main() -> Result<()> {
     let storage = Arc::new(SledStorageProvider::new(
         config
             .storage_path
-            .join("sled_db")
+            .join(SLED_DB_DIR)
             .to_str()
             .expect("No storage."),
     )?);
@@ -35,7 +35,7 @@ async fn main() -> Result<()> {
 
     let ddk: ApplicationDdk = builder.finish()?;
 
-    let wallet = ddk.wallet.new_external_address();
+    let wallet = ddk.wallet().new_external_address();
 
     assert!(wallet.is_ok());
 
@@ -0,0 +1,392 @@
+//! Realized profit and loss of settled contracts.
+//!
+//! Closed contracts no longer store their collateral or funding transaction, so what a contract
+//! paid us is recorded as a [ContractSettlement] when it settles. Reports are built from these
+//! records instead of re-deriving payouts from CETs.
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::{ScriptBuf, Transaction, Txid};
+use dlc_manager::contract::signed_contract::SignedContract;
+use dlc_manager::contract::Contract;
+use dlc_manager::ContractId;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::io::Write;
+
+/// How a contract settled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SettlementKind {
+    Cet,
+    Refund,
+}
+
+impl std::fmt::Display for SettlementKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SettlementKind::Cet => write!(f, "cet"),
+            SettlementKind::Refund => write!(f, "refund"),
+        }
+    }
+}
+
+/// What a contract paid us, recorded when it moved to PreClosed, Closed, or Refunded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContractSettlement {
+    pub contract_id: ContractId,
+    pub counterparty: PublicKey,
+    /// Event id of the first oracle announcement.
+    pub event_id: Option<String>,
+    pub kind: SettlementKind,
+    /// The CET or refund transaction.
+    pub closing_txid: Txid,
+    /// Our collateral in sats.
+    pub collateral: u64,
+    /// Sats the closing transaction pays to our payout script.
+    pub payout: u64,
+    /// Our funding inputs less our collateral and change, in sats. Covers our share of the
+    /// funding and closing transaction fees.
+    pub fees: u64,
+    /// Unix timestamp in seconds the settlement was recorded at.
+    pub settled_at: u64,
+}
+
+impl ContractSettlement {
+    /// Realized profit and loss in sats, the payout less collateral and fees.
+    pub fn pnl(&self) -> i64 {
+        self.payout as i64 - self.collateral as i64 - self.fees as i64
+    }
+}
+
+/// The settlement of a contract that is `after` now and was `before`. Closed contracts are
+/// settled from the contract before it closed, which still has the collateral.
+pub(crate) fn settlement(
+    before: Option<&Contract>,
+    after: &Contract,
+    now: u64,
+) -> Option<ContractSettlement> {
+    let (signed, closing, kind) = match after {
+        Contract::PreClosed(p) => (&p.signed_contract, &p.signed_cet, SettlementKind::Cet),
+        Contract::Refunded(s) => (
+            s,
+            &s.accepted_contract.dlc_transactions.refund,
+            SettlementKind::Refund,
+        ),
+        Contract::Closed(c) => {
+            let signed = match before? {
+                Contract::Signed(s) | Contract::Confirmed(s) => s,
+                Contract::PreClosed(p) => &p.signed_contract,
+                _ => return None,
+            };
+            (signed, c.signed_cet.as_ref()?, SettlementKind::Cet)
+        }
+        _ => return None,
+    };
+    Some(settle(signed, closing, kind, now))
+}
+
+/// Whether a contract was settled and its settlement recorded on the way.
+pub(crate) fn is_settled(contract: &Contract) -> bool {
+    matches!(
+        contract,
+        Contract::PreClosed(_) | Contract::Closed(_) | Contract::Refunded(_)
+    )
+}
+
+fn settle(
+    signed: &SignedContract,
+    closing: &Transaction,
+    kind: SettlementKind,
+    now: u64,
+) -> ContractSettlement {
+    let accepted = &signed.accepted_contract;
+    let offered = &accepted.offered_contract;
+    let params = if offered.is_offer_party {
+        &offered.offer_params
+    } else {
+        &accepted.accept_params
+    };
+    let paid_to = |tx: &Transaction, script: &ScriptBuf| -> u64 {
+        tx.output
+            .iter()
+            .filter(|output| output.script_pubkey == *script)
+            .map(|output| output.value.to_sat())
+            .sum()
+    };
+    let change = paid_to(
+        &accepted.dlc_transactions.fund,
+        &params.change_script_pubkey,
+    );
+    ContractSettlement {
+        contract_id: accepted.get_contract_id(),
+        counterparty: offered.counter_party,
+        event_id: offered
+            .contract_info
+            .iter()
+            .flat_map(|info| info.oracle_announcements.iter())
+            .map(|announcement| announcement.oracle_event.event_id.clone())
+            .next(),
+        kind,
+        closing_txid: closing.compute_txid(),
+        collateral: params.collateral,
+        payout: paid_to(closing, &params.payout_script_pubkey),
+        fees: params
+            .input_amount
+            .saturating_sub(params.collateral)
+            .saturating_sub(change),
+        settled_at: now,
+    }
+}
+
+/// Settlement times to report on, as unix timestamps in seconds. The end is exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimeRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl TimeRange {
+    pub fn contains(&self, time: u64) -> bool {
+        self.start <= time && time < self.end
+    }
+}
+
+/// Sums over a group of settled contracts. Amounts are in sats.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PnlTotals {
+    pub contracts: usize,
+    pub collateral: u64,
+    pub payout: u64,
+    pub fees: u64,
+    pub pnl: i64,
+}
+
+impl PnlTotals {
+    fn add(&mut self, settlement: &ContractSettlement) {
+        self.contracts += 1;
+        self.collateral += settlement.collateral;
+        self.payout += settlement.payout;
+        self.fees += settlement.fees;
+        self.pnl += settlement.pnl();
+    }
+}
+
+/// A settled contract in a [PnlReport].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContractPnl {
+    #[serde(flatten)]
+    pub settlement: ContractSettlement,
+    pub pnl: i64,
+}
+
+/// Realized profit and loss of the contracts settled in a time range.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PnlReport {
+    pub range: Option<TimeRange>,
+    /// Settled contracts, oldest settlement first.
+    pub contracts: Vec<ContractPnl>,
+    pub total: PnlTotals,
+    /// Totals keyed by counterparty public key.
+    pub by_counterparty: BTreeMap<String, PnlTotals>,
+    /// Totals keyed by oracle event id.
+    pub by_event: BTreeMap<String, PnlTotals>,
+    /// Hex ids of closed contracts without a recorded settlement, e.g. closed before
+    /// settlements were recorded. They are not part of the totals.
+    pub unrecorded: Vec<String>,
+}
+
+impl PnlReport {
+    /// Report on the recorded `settlements`. PreClosed and Refunded `contracts` without a
+    /// record are settled from the contract, at `now`. Closed contracts without a record are
+    /// listed as unrecorded.
+    pub(crate) fn build(
+        mut settlements: Vec<ContractSettlement>,
+        contracts: &[Contract],
+        range: Option<TimeRange>,
+        now: u64,
+    ) -> PnlReport {
+        let recorded = settlements
+            .iter()
+            .map(|settlement| settlement.contract_id)
+            .collect::<HashSet<_>>();
+        let mut unrecorded = vec![];
+        for contract in contracts.iter().filter(|c| is_settled(c)) {
+            if recorded.contains(&contract.get_id()) {
+                continue;
+            }
+            match settlement(None, contract, now) {
+                Some(settlement) => settlements.push(settlement),
+                None => unrecorded.push(hex::encode(contract.get_id())),
+            }
+        }
+
+        if let Some(range) = range {
+            settlements.retain(|settlement| range.contains(settlement.settled_at));
+        }
+        settlements.sort_by_key(|settlement| settlement.settled_at);
+        let mut total = PnlTotals::default();
+        let mut by_counterparty = BTreeMap::<String, PnlTotals>::new();
+        let mut by_event = BTreeMap::<String, PnlTotals>::new();
+        for settlement in &settlements {
+            total.add(settlement);
+            by_counterparty
+                .entry(settlement.counterparty.to_string())
+                .or_default()
+                .add(settlement);
+            if let Some(event_id) = &settlement.event_id {
+                by_event
+                    .entry(event_id.clone())
+                    .or_default()
+                    .add(settlement);
+            }
+        }
+        PnlReport {
+            range,
+            contracts: settlements
+                .into_iter()
+                .map(|settlement| ContractPnl {
+                    pnl: settlement.pnl(),
+                    settlement,
+                })
+                .collect(),
+            total,
+            by_counterparty,
+            by_event,
+            unrecorded,
+        }
+    }
+}
+
+const CSV_HEADER: &str =
+    "contract_id,counterparty,event_id,kind,closing_txid,settled_at,collateral,payout,fees,pnl";
+
+/// Write the contracts of `report` as CSV with a header row. Returns the number of contracts
+/// written.
+pub fn write_pnl_csv<W: Write>(report: &PnlReport, mut writer: W) -> anyhow::Result<usize> {
+    writeln!(writer, "{}", CSV_HEADER)?;
+    for ContractPnl { settlement, pnl } in &report.contracts {
+        writeln!(
+            writer,
+            "{},{},{},{},{},{},{},{},{},{}",
+            hex::encode(settlement.contract_id),
+            settlement.counterparty,
+            csv_field(settlement.event_id.as_deref().unwrap_or_default()),
+            settlement.kind,
+            settlement.closing_txid,
+            settlement.settled_at,
+            settlement.collateral,
+            settlement.payout,
+            settlement.fees,
+            pnl
+        )?;
+    }
+    writer.flush()?;
+    Ok(report.contracts.len())
+}
+
+/// Quote fields that contain a separator, quote, or line break.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dlc_manager::contract::ser::Serializable;
+    use dlc_manager::contract::{ClosedContract, PreClosedContract};
+
+    fn deserialize_object<T: Serializable>(serialized: &[u8]) -> T {
+        let mut cursor = ::lightning::io::Cursor::new(&serialized);
+        T::deserialize(&mut cursor).unwrap()
+    }
+
+    fn pre_closed() -> PreClosedContract {
+        deserialize_object(include_bytes!("../tests/data/dlc_storage/sled/PreClosed"))
+    }
+
+    #[test]
+    fn settlements_take_the_payout_from_the_closing_transaction() {
+        let pre_closed = pre_closed();
+        let signed = pre_closed.signed_contract.clone();
+        let settlement = settlement(None, &Contract::PreClosed(pre_closed.clone()), 10).unwrap();
+        let offered = &signed.accepted_contract.offered_contract;
+        let params = if offered.is_offer_party {
+            &offered.offer_params
+        } else {
+            &signed.accepted_contract.accept_params
+        };
+        let payout = pre_closed
+            .signed_cet
+            .output
+            .iter()
+            .find(|output| output.script_pubkey == params.payout_script_pubkey)
+            .map_or(0, |output| output.value.to_sat());
+
+        assert_eq!(settlement.kind, SettlementKind::Cet);
+        assert_eq!(settlement.collateral, params.collateral);
+        assert_eq!(settlement.payout, payout);
+        assert_eq!(
+            settlement.pnl(),
+            payout as i64 - params.collateral as i64 - settlement.fees as i64
+        );
+
+        // A closed contract only settles with the contract it closed from.
+        let closed = Contract::Closed(deserialize_object(include_bytes!(
+            "../tests/data/dlc_storage/sled/Closed"
+        )));
+        assert!(settlement(None, &closed, 0).is_none());
+        let from_confirmed = settlement(Some(&Contract::Confirmed(signed)), &closed, 0);
+        assert_eq!(
+            from_confirmed.map(|s| s.collateral),
+            Some(params.collateral)
+        );
+    }
+
+    #[test]
+    fn report_aggregates_and_filters_by_time() {
+        let first = settlement(None, &Contract::PreClosed(pre_closed()), 100).unwrap();
+        let second = ContractSettlement {
+            contract_id: [9u8; 32],
+            event_id: Some("other,event".into()),
+            settled_at: 200,
+            ..first.clone()
+        };
+        let report = PnlReport::build(vec![second.clone(), first.clone()], &[], None, 300);
+        assert_eq!(report.contracts.len(), 2);
+        assert_eq!(report.contracts[0].settlement, first);
+        assert_eq!(report.total.pnl, first.pnl() + second.pnl());
+        assert_eq!(report.by_counterparty.len(), 1);
+        assert_eq!(report.by_event["other,event"].contracts, 1);
+
+        let range = TimeRange {
+            start: 150,
+            end: 250,
+        };
+        let report = PnlReport::build(vec![first, second], &[], Some(range), 300);
+        assert_eq!(report.contracts.len(), 1);
+        assert_eq!(report.total.contracts, 1);
+
+        let mut out = Vec::new();
+        assert_eq!(write_pnl_csv(&report, &mut out).unwrap(), 1);
+        let csv = String::from_utf8(out).unwrap();
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], CSV_HEADER);
+        assert!(lines[1].contains("\"other,event\""));
+    }
+
+    #[test]
+    fn unrecorded_contracts_are_settled_from_the_contract() {
+        let closed: ClosedContract =
+            deserialize_object(include_bytes!("../tests/data/dlc_storage/sled/Closed"));
+        let contracts = [
+            Contract::PreClosed(pre_closed()),
+            Contract::Closed(closed.clone()),
+        ];
+        let report = PnlReport::build(vec![], &contracts, None, 42);
+        assert_eq!(report.contracts.len(), 1);
+        assert_eq!(report.contracts[0].settlement.settled_at, 42);
+        assert_eq!(report.unrecorded, vec![hex::encode(closed.contract_id)]);
+    }
+}
@@ -0,0 +1,223 @@
+//! Append-only audit trail of contract state changes, exchanged messages, and broadcast
+//! transactions.
+//!
+//! Storage providers record a state change in the same write as the contract, so the log
+//! cannot disagree with the stored state. The manager records messages and broadcasts as it
+//! sends and receives them.
+use crate::contract::summary::ContractDetails;
+use crate::contract::ContractState;
+use crate::transport::{encode_message, message_contract_id, message_id, message_kind};
+use crate::DdkStorage;
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::Txid;
+use dlc_manager::contract::Contract;
+use dlc_manager::ContractId;
+use dlc_messages::Message;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditEventType {
+    /// A contract was stored for the first time or changed state.
+    StateChanged,
+    MessageSent,
+    MessageReceived,
+    /// A received message that could not be processed and will not be replayed. The entry
+    /// keeps the error and the wire encoded message.
+    MessageDeadLettered,
+    TransactionBroadcast,
+}
+
+/// One line of the audit log.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Position in the log, assigned by the storage when the entry is appended.
+    pub sequence: u64,
+    /// Unix timestamp in seconds.
+    pub timestamp: u64,
+    /// Hex encoded contract id. Entries written before the offer was accepted carry the
+    /// temporary id. None for channel messages.
+    pub contract_id: Option<String>,
+    pub event_type: AuditEventType,
+    pub peer: Option<PublicKey>,
+    pub details: serde_json::Value,
+}
+
+impl AuditEntry {
+    pub fn new(
+        contract_id: Option<&ContractId>,
+        event_type: AuditEventType,
+        peer: Option<PublicKey>,
+        details: serde_json::Value,
+    ) -> AuditEntry {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        AuditEntry {
+            sequence: 0,
+            timestamp,
+            contract_id: contract_id.map(hex::encode),
+            event_type,
+            peer,
+            details,
+        }
+    }
+
+    /// The entry for storing `new` over `old`, or `None` when the state did not change.
+    pub fn state_change(old: Option<&Contract>, new: &Contract) -> Option<AuditEntry> {
+        let from = old.map(ContractState::from);
+        let to = ContractState::from(new);
+        if from == Some(to) {
+            return None;
+        }
+        let contract = ContractDetails::from(new);
+        let details = json!({
+            "from": from,
+            "to": to,
+            "temporary_id": contract.temporary_id,
+            "is_offer_party": contract.summary.is_offer_party,
+            "funding_txid": contract.funding_txid,
+            "closing_txid": contract.summary.closing_txid,
+            "error": contract.error,
+        });
+        Some(AuditEntry::new(
+            Some(&new.get_id()),
+            AuditEventType::StateChanged,
+            Some(new.get_counter_party_id()),
+            details,
+        ))
+    }
+
+    /// The entry for a message sent to or received from `counterparty`.
+    pub fn message(
+        event_type: AuditEventType,
+        counterparty: PublicKey,
+        message: &Message,
+    ) -> AuditEntry {
+        let contract_id = message_contract_id(message);
+        let details = json!({
+            "kind": message_kind(message),
+            "message_id": message_id(message),
+        });
+        AuditEntry::new(
+            contract_id.as_ref(),
+            event_type,
+            Some(counterparty),
+            details,
+        )
+    }
+
+    /// The entry for a received message given up on after `attempts` failed attempts.
+    pub fn dead_letter(counterparty: PublicKey, message: &Message, error: &str, attempts: u32) -> AuditEntry {
+        let contract_id = message_contract_id(message);
+        let details = json!({
+            "kind": message_kind(message),
+            "message_id": message_id(message),
+            "error": error,
+            "attempts": attempts,
+            "message": hex::encode(encode_message(message)),
+        });
+        AuditEntry::new(
+            contract_id.as_ref(),
+            AuditEventType::MessageDeadLettered,
+            Some(counterparty),
+            details,
+        )
+    }
+
+    /// The entry for a contract transaction we broadcast. `kind` names the transaction, e.g.
+    /// `funding` or `refund`.
+    pub fn broadcast(contract_id: &ContractId, txid: Txid, kind: &str) -> AuditEntry {
+        AuditEntry::new(
+            Some(contract_id),
+            AuditEventType::TransactionBroadcast,
+            None,
+            json!({ "txid": txid, "kind": kind }),
+        )
+    }
+
+    /// Whether the entry is about the contract with one of `ids`.
+    pub fn is_for(&self, ids: &[String]) -> bool {
+        self.contract_id
+            .as_ref()
+            .map_or(false, |id| ids.contains(id))
+    }
+}
+
+/// Write `entries` as JSON Lines, one entry per line. Returns the number written.
+pub fn write_json_lines<W: Write>(entries: &[AuditEntry], mut writer: W) -> anyhow::Result<usize> {
+    for entry in entries {
+        serde_json::to_writer(&mut writer, entry)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()?;
+    Ok(entries.len())
+}
+
+/// Appends to the audit log. A failed write is logged and does not stop the caller.
+pub(crate) fn append<S: DdkStorage>(storage: &S, entry: AuditEntry) {
+    if let Err(e) = storage.append_audit_entry(entry) {
+        tracing::error!(error = e.to_string(), "Could not append audit entry.");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::Hash;
+    use dlc_manager::contract::offered_contract::OfferedContract;
+    use dlc_manager::contract::ser::Serializable;
+    use dlc_manager::contract::signed_contract::SignedContract;
+
+    fn deserialize_object<T: Serializable>(serialized: &[u8]) -> T {
+        let mut cursor = ::lightning::io::Cursor::new(&serialized);
+        T::deserialize(&mut cursor).unwrap()
+    }
+
+    #[test]
+    fn only_state_changes_are_recorded() {
+        let offered: OfferedContract =
+            deserialize_object(include_bytes!("../tests/data/dlc_storage/sled/Offered"));
+        let signed: SignedContract =
+            deserialize_object(include_bytes!("../tests/data/dlc_storage/sled/Signed"));
+        let offered = Contract::Offered(offered);
+        let signed = Contract::Signed(signed);
+
+        let created = AuditEntry::state_change(None, &offered).unwrap();
+        assert_eq!(created.event_type, AuditEventType::StateChanged);
+        assert_eq!(created.contract_id, Some(hex::encode(offered.get_id())));
+        assert_eq!(created.peer, Some(offered.get_counter_party_id()));
+        assert_eq!(created.details["from"], serde_json::Value::Null);
+        assert_eq!(created.details["to"], "Offered");
+
+        assert!(AuditEntry::state_change(Some(&signed), &signed).is_none());
+        let entry = AuditEntry::state_change(Some(&offered), &signed).unwrap();
+        assert_eq!(entry.details["from"], "Offered");
+        assert_eq!(entry.details["to"], "Signed");
+        assert!(entry.details["funding_txid"].is_string());
+    }
+
+    #[test]
+    fn entries_export_one_per_line() {
+        let txid = Txid::all_zeros();
+        let entries = vec![
+            AuditEntry::broadcast(&[1u8; 32], txid, "funding"),
+            AuditEntry::broadcast(&[2u8; 32], txid, "refund"),
+        ];
+        let mut out = Vec::new();
+        assert_eq!(write_json_lines(&entries, &mut out).unwrap(), 2);
+
+        let lines = String::from_utf8(out).unwrap();
+        let parsed = lines
+            .lines()
+            .map(|line| serde_json::from_str::<AuditEntry>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(parsed, entries);
+        assert_eq!(parsed[1].details["kind"], "refund");
+        assert!(parsed[0].is_for(&[hex::encode([1u8; 32])]));
+    }
+}
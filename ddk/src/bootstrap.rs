@@ -0,0 +1,301 @@
+//! Inspect a data directory and config before building a node, for first-run setup screens.
+//!
+//! Nothing is created or written. The esplora endpoint is queried with the blocking client,
+//! so call it outside of an async runtime or from `spawn_blocking`.
+use crate::chain::{EsploraClient, EsploraOptions};
+use crate::config::{DdkConfig, SeedConfig};
+use crate::io::{FileKeyStorage, KeyStorage};
+use crate::storage::{SledStorageProvider, SLED_DB_DIR, SLED_SCHEMA_VERSION};
+use crate::wallet::accounts::SPENDING_WALLET_DB_DIR;
+use crate::wallet::WALLET_DB_DIR;
+use crate::DdkStorage;
+use bitcoin::constants::genesis_block;
+use bitcoin::Network;
+use std::path::{Path, PathBuf};
+
+/// An existing node found in the data directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExistingStorage {
+    /// Network of the stored wallet, if a wallet was persisted.
+    pub network: Option<Network>,
+    /// Contracts in the contract store.
+    pub contract_count: usize,
+    /// Layout version of the contract store. `None` when it was written before versioning.
+    pub schema_version: Option<u32>,
+}
+
+/// The configured esplora answered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EsploraReachable {
+    pub tip_height: u32,
+}
+
+/// Everything a setup screen needs to offer a restore or create choice.
+#[derive(Debug, Clone, Default)]
+pub struct BootstrapInfo {
+    pub seed_exists: bool,
+    /// `None` when the data directory holds no wallet or contract store.
+    pub storage_exists: Option<ExistingStorage>,
+    /// `None` when the esplora endpoint could not be reached.
+    pub esplora_reachable: Option<EsploraReachable>,
+    /// Paths `build()` would create.
+    pub would_create: Vec<PathBuf>,
+    /// Misconfigurations found. Building may still succeed.
+    pub warnings: Vec<String>,
+}
+
+/// Inspect `config` and its data directory without creating anything.
+pub fn bootstrap_info(config: &DdkConfig) -> BootstrapInfo {
+    let mut info = BootstrapInfo::default();
+    let storage_path = &config.storage_path;
+    let wallet_path = storage_path.join(WALLET_DB_DIR);
+    let contract_path = storage_path.join(SLED_DB_DIR);
+
+    if !storage_path.exists() {
+        info.would_create.push(storage_path.clone());
+    }
+
+    match seed_exists(&config.seed_config) {
+        Ok(exists) => info.seed_exists = exists,
+        Err(e) => info.warnings.push(format!("Could not read the seed. error={}", e)),
+    }
+    match &config.seed_config {
+        SeedConfig::Bytes(seed) if *seed == [0u8; 64] && config.network != Network::Regtest => {
+            info.warnings
+                .push("Using the default all zero seed outside of regtest.".to_string());
+        }
+        SeedConfig::File(dir) | SeedConfig::EncryptedFile { path: dir, .. } if !info.seed_exists => {
+            info.would_create.push(Path::new(dir).join("seed.ddk"));
+        }
+        _ => {}
+    }
+
+    if !wallet_path.exists() {
+        info.would_create.push(wallet_path.clone());
+    }
+    let spending_path = storage_path.join(SPENDING_WALLET_DB_DIR);
+    if !spending_path.exists() {
+        info.would_create.push(spending_path);
+    }
+    if wallet_path.exists() || contract_path.exists() {
+        let storage = existing_storage(&wallet_path, &contract_path, &mut info.warnings);
+        if let Some(stored) = storage.network {
+            if stored != config.network {
+                info.warnings.push(format!(
+                    "Stored wallet is on {} but the config is for {}.",
+                    stored, config.network
+                ));
+            }
+        }
+        if !info.seed_exists {
+            info.warnings
+                .push("Storage exists but no seed was found. A new seed will not match the stored wallet.".to_string());
+        }
+        info.storage_exists = Some(storage);
+    }
+
+    info.esplora_reachable = probe_esplora(config, &mut info.warnings);
+    info
+}
+
+fn seed_exists(seed_config: &SeedConfig) -> anyhow::Result<bool> {
+    match seed_config {
+        SeedConfig::Bytes(_) | SeedConfig::Mnemonic { .. } => Ok(true),
+        SeedConfig::File(dir) | SeedConfig::EncryptedFile { path: dir, .. } => {
+            Ok(FileKeyStorage::new(dir).seed_path().exists())
+        }
+        SeedConfig::KeyStorage(key_storage) => Ok(key_storage.load()?.is_some()),
+    }
+}
+
+fn existing_storage(
+    wallet_path: &Path,
+    contract_path: &Path,
+    warnings: &mut Vec<String>,
+) -> ExistingStorage {
+    let mut storage = ExistingStorage {
+        network: None,
+        contract_count: 0,
+        schema_version: None,
+    };
+
+    if wallet_path.exists() {
+        match open_sled(wallet_path).and_then(|db| Ok(db.stored_changeset()?)) {
+            Ok(changeset) => storage.network = changeset.network,
+            Err(e) => warnings.push(format!("Could not read the wallet store. error={}", e)),
+        }
+    }
+
+    if contract_path.exists() {
+        match open_sled(contract_path)
+            .and_then(|db| Ok((db.schema_version()?, db.storage_stats()?)))
+        {
+            Ok((version, stats)) => {
+                storage.schema_version = version;
+                storage.contract_count = stats.contracts_by_state.values().sum();
+            }
+            Err(e) => warnings.push(format!("Could not read the contract store. error={}", e)),
+        }
+        if storage.schema_version.is_some_and(|version| version > SLED_SCHEMA_VERSION) {
+            warnings.push(format!(
+                "Contract store schema version {} is newer than this build supports ({}).",
+                storage.schema_version.unwrap_or_default(),
+                SLED_SCHEMA_VERSION
+            ));
+        }
+    }
+
+    storage
+}
+
+/// Opens without migrating so inspecting never rewrites the store. Fails while a running node
+/// holds the store lock.
+fn open_sled(path: &Path) -> anyhow::Result<SledStorageProvider> {
+    let path = path
+        .to_str()
+        .ok_or_else(|| anyhow::anyhow!("Storage path is not valid utf-8."))?;
+    Ok(SledStorageProvider::open_unmigrated(path)?)
+}
+
+fn probe_esplora(config: &DdkConfig, warnings: &mut Vec<String>) -> Option<EsploraReachable> {
+    // A probe reports an unreachable esplora instead of waiting on retries.
+    let options = EsploraOptions {
+        max_retries: 0,
+        ..config.esplora_options
+    };
+    let client = match EsploraClient::with_options(&config.esplora_host, config.network, options) {
+        Ok(client) => client,
+        Err(e) => {
+            warnings.push(format!("Invalid esplora host {}. error={}", config.esplora_host, e));
+            return None;
+        }
+    };
+
+    let tip_height = match client.blocking_client.get_height() {
+        Ok(height) => height,
+        Err(e) => {
+            warnings.push(format!("Esplora {} is unreachable. error={}", config.esplora_host, e));
+            return None;
+        }
+    };
+
+    // Custom signets have their own genesis, so only check signet when it is configured.
+    let expected = match (config.expected_genesis_hash, config.network) {
+        (Some(expected), _) => Some(expected),
+        (None, Network::Signet) => None,
+        (None, network) => Some(genesis_block(network).block_hash()),
+    };
+    if let Some(expected) = expected {
+        if let Err(e) = client.verify_genesis(expected) {
+            warnings.push(e.to_string());
+        }
+    }
+
+    Some(EsploraReachable { tip_height })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bdk_wallet::template::Bip84;
+    use bdk_wallet::{KeychainKind, Wallet};
+    use bitcoin::bip32::Xpriv;
+    use dlc_manager::contract::offered_contract::OfferedContract;
+    use dlc_manager::contract::ser::Serializable;
+    use dlc_manager::Storage;
+
+    fn config(dir: &str) -> DdkConfig {
+        DdkConfig {
+            network: Network::Regtest,
+            esplora_host: "http://127.0.0.1:1".to_string(),
+            storage_path: dir.into(),
+            seed_config: SeedConfig::File(dir.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn existing_node(dir: &Path, network: Network) {
+        std::fs::create_dir_all(dir).unwrap();
+        FileKeyStorage::new(dir).store(&[3u8; 64]).unwrap();
+
+        let mut wallet_db =
+            SledStorageProvider::new(dir.join(WALLET_DB_DIR).to_str().unwrap()).unwrap();
+        let xprv = Xpriv::new_master(network, &[3u8; 64]).unwrap();
+        Wallet::create(
+            Bip84(xprv, KeychainKind::External),
+            Bip84(xprv, KeychainKind::Internal),
+        )
+        .network(network)
+        .create_wallet(&mut wallet_db)
+        .unwrap();
+
+        let contracts = SledStorageProvider::new(dir.join(SLED_DB_DIR).to_str().unwrap()).unwrap();
+        let mut cursor = ::lightning::io::Cursor::new(include_bytes!(
+            "../tests/data/dlc_storage/sled/Offered"
+        ));
+        let offered = OfferedContract::deserialize(&mut cursor).unwrap();
+        contracts.create_contract(&offered).unwrap();
+    }
+
+    #[test]
+    fn empty_dir() {
+        let dir = "tests/data/bootstrap_empty";
+        let info = bootstrap_info(&config(dir));
+        assert!(!info.seed_exists);
+        assert!(info.storage_exists.is_none());
+        assert!(info.esplora_reachable.is_none());
+        assert_eq!(
+            info.would_create,
+            vec![
+                PathBuf::from(dir),
+                Path::new(dir).join("seed.ddk"),
+                Path::new(dir).join(WALLET_DB_DIR),
+                Path::new(dir).join(SPENDING_WALLET_DB_DIR)
+            ]
+        );
+        assert!(!Path::new(dir).exists());
+    }
+
+    #[test]
+    fn existing_node_dir() {
+        let dir = "tests/data/bootstrap_existing";
+        existing_node(Path::new(dir), Network::Regtest);
+
+        let info = bootstrap_info(&config(dir));
+        assert!(info.seed_exists);
+        assert_eq!(
+            info.storage_exists,
+            Some(ExistingStorage {
+                network: Some(Network::Regtest),
+                contract_count: 1,
+                schema_version: Some(SLED_SCHEMA_VERSION),
+            })
+        );
+        // The node predates wallet accounts.
+        assert_eq!(
+            info.would_create,
+            vec![Path::new(dir).join(SPENDING_WALLET_DB_DIR)]
+        );
+        assert!(!info.warnings.iter().any(|w| w.contains("Stored wallet")));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn mismatched_network_store() {
+        let dir = "tests/data/bootstrap_mismatched";
+        existing_node(Path::new(dir), Network::Testnet);
+
+        let info = bootstrap_info(&config(dir));
+        assert_eq!(
+            info.storage_exists.as_ref().and_then(|s| s.network),
+            Some(Network::Testnet)
+        );
+        assert!(info
+            .warnings
+            .iter()
+            .any(|w| w == "Stored wallet is on testnet but the config is for regtest."));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
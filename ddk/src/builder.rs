@@ -3,24 +3,45 @@ use core::fmt;
 use crossbeam::channel::unbounded;
 use dlc_manager::manager::Manager;
 use dlc_manager::SystemTimeProvider;
-use std::collections::HashMap;
-use std::sync::{Arc, RwLock};
+use bitcoin::Network;
+use std::sync::{Arc, Mutex, RwLock};
 
+use crate::chain::network::default_esplora_host;
+use crate::chain::rebroadcast::BroadcastTracker;
 use crate::chain::EsploraClient;
-use crate::config::DdkConfig;
+use crate::config::{DdkConfig, SeedConfig};
+use crate::contract::confirmations::ConfirmationPolicy;
+use crate::contract::policy::{ManualOnly, OfferPolicy};
+use crate::io::KeyStorage;
+use crate::oracle::{AnnouncementCache, CachingOracle, OracleSet, VerifyingOracle};
 use crate::ddk::{DlcDevKit, DlcManagerMessage};
-use crate::wallet::DlcDevKitWallet;
-use crate::{DdkOracle, DdkStorage, DdkTransport};
+use crate::events::EventBus;
+use crate::status::StatusTracker;
+use crate::rates::{NoopRateProvider, RateProvider};
+use crate::storage::ArchivePolicy;
+use crate::transport::outbox::Outbox;
+use crate::error::StorageError;
+use crate::wallet::{CoinSelectionStrategy, DlcDevKitWallet, SignerBackend};
+use crate::{DdkBlockchain, DdkError, DdkOracle, DdkStorage, DdkTransport};
 
 /// Builder pattern for creating a [crate::ddk::DlcDevKit] process.
 #[derive(Clone, Debug)]
-pub struct DdkBuilder<T, S, O> {
+pub struct DdkBuilder<T, S, O, B = EsploraClient> {
     name: Option<String>,
     config: Option<DdkConfig>,
+    network: Option<Network>,
+    esplora_url: Option<String>,
+    seed_config: Option<SeedConfig>,
     transport: Option<Arc<T>>,
     storage: Option<Arc<S>>,
     oracle: Option<Arc<O>>,
+    oracles: Vec<Arc<O>>,
     wallet_storage: Option<S>,
+    rate_provider: Option<Arc<dyn RateProvider>>,
+    offer_policy: Option<Arc<dyn OfferPolicy>>,
+    blockchain: Option<Arc<B>>,
+    signer_backend: Option<SignerBackend>,
+    coin_selection: Option<CoinSelectionStrategy>,
 }
 
 /// An error that could be thrown while building [crate::ddk::DlcDevKit]
@@ -38,6 +59,18 @@ pub enum BuilderError {
     NoConfig,
     /// No wallet storage provided.
     NoWalletStorage,
+    /// No blockchain provided and it can not be created from the config.
+    NoBlockchain,
+    /// The esplora url is not an http or https url.
+    InvalidEsploraUrl,
+    /// The config has no threads to process messages with.
+    NoMessageWorkers,
+    /// An interval in the config is zero.
+    ZeroInterval(&'static str),
+    /// The peer limits would drop every message.
+    InvalidPeerLimits,
+    /// The config has no wallet account to fund contracts from.
+    NoFundingAccount,
 }
 
 impl fmt::Display for BuilderError {
@@ -49,6 +82,14 @@ impl fmt::Display for BuilderError {
             BuilderError::NoSeed => write!(f, "No seed configuration was provided."),
             BuilderError::NoConfig => write!(f, "No config was provided"),
             BuilderError::NoWalletStorage => write!(f, "No wallet storage was provided."),
+            BuilderError::NoBlockchain => write!(f, "No blockchain client was provided."),
+            BuilderError::InvalidEsploraUrl => write!(f, "The esplora url must start with http:// or https://."),
+            BuilderError::NoMessageWorkers => write!(f, "At least one message worker is required."),
+            BuilderError::ZeroInterval(name) => write!(f, "The {} must not be zero.", name),
+            BuilderError::InvalidPeerLimits => {
+                write!(f, "Peer limits need a positive message rate and a burst of at least one.")
+            }
+            BuilderError::NoFundingAccount => write!(f, "At least one wallet account must fund contracts."),
         }
     }
 }
@@ -58,26 +99,43 @@ impl std::error::Error for BuilderError {}
 /// Defaults when creating a DDK application
 /// Transport, storage, and oracle is set to none.
 /// Default [crate::config::DdkConfig] to mutiny net.
-impl<T: DdkTransport, S: DdkStorage, O: DdkOracle> Default for DdkBuilder<T, S, O> {
+impl<T: DdkTransport, S: DdkStorage, O: DdkOracle, B: DdkBlockchain> Default for DdkBuilder<T, S, O, B> {
     fn default() -> Self {
         let config = Some(DdkConfig::default());
         Self {
             name: None,
             config,
+            network: None,
+            esplora_url: None,
+            seed_config: None,
             transport: None,
             storage: None,
             oracle: None,
+            oracles: Vec::new(),
             wallet_storage: None,
+            rate_provider: None,
+            offer_policy: None,
+            blockchain: None,
+            signer_backend: None,
+            coin_selection: None,
         }
     }
 }
 
-impl<T: DdkTransport, S: DdkStorage, O: DdkOracle> DdkBuilder<T, S, O> {
+impl<T: DdkTransport, S: DdkStorage, O: DdkOracle, B: DdkBlockchain> DdkBuilder<T, S, O, B> {
     /// Create a new, default DDK builder.
     pub fn new() -> Self {
         DdkBuilder::default()
     }
 
+    /// A builder for `config`, for example one loaded with [DdkConfig::from_file]. Storage
+    /// and the blockchain client are created from the config unless they are set.
+    pub fn from_config(config: DdkConfig) -> Self {
+        let mut builder = DdkBuilder::default();
+        builder.set_config(config);
+        builder
+    }
+
     /// Set the name of the DDK process. Used as an identifier for the process created.
     /// Creates a directory for the process with the name specifed. All file-based components
     /// will be stored in a directory under the storage path set in the `DdkConfig` and the `name`.
@@ -87,6 +145,27 @@ impl<T: DdkTransport, S: DdkStorage, O: DdkOracle> DdkBuilder<T, S, O> {
         self
     }
 
+    /// Bitcoin network to run on. Overrides the network of the config. Unless an esplora url
+    /// is set, the public esplora of the network is used.
+    pub fn set_network(&mut self, network: Network) -> &mut Self {
+        self.network = Some(network);
+        self
+    }
+
+    /// Esplora API for the wallet and the default blockchain client. Overrides the esplora host
+    /// of the config.
+    pub fn set_esplora_url(&mut self, esplora_url: &str) -> &mut Self {
+        self.esplora_url = Some(esplora_url.into());
+        self
+    }
+
+    /// Where the master seed is loaded from. Overrides the seed config of the config.
+    /// Transports should be created with the same seed config.
+    pub fn set_seed_config(&mut self, seed_config: SeedConfig) -> &mut Self {
+        self.seed_config = Some(seed_config);
+        self
+    }
+
     /// The communication layer of DDK. Type MUST implement [crate::DdkTransport].
     /// Transport sets up listeners, communicates with counterparties, and passes
     /// DLC messages to the `Manager`.
@@ -96,7 +175,8 @@ impl<T: DdkTransport, S: DdkStorage, O: DdkOracle> DdkBuilder<T, S, O> {
     }
 
     /// DLC contract storage. Storage is used by the [dlc_manager::manager::Manager] to create, update, retrieve, and
-    /// delete contracts. MUST implement [crate::DdkStorage]
+    /// delete contracts. MUST implement [crate::DdkStorage]. Defaults to the storage opened
+    /// from the config, in the storage path.
     pub fn set_storage(&mut self, storage: Arc<S>) -> &mut Self {
         self.storage = Some(storage);
         self
@@ -118,25 +198,208 @@ impl<T: DdkTransport, S: DdkStorage, O: DdkOracle> DdkBuilder<T, S, O> {
         self
     }
 
+    /// An additional oracle, for contracts that settle on several oracles. Oracles must be
+    /// added before building since the [dlc_manager::manager::Manager] cannot take new ones.
+    pub fn add_oracle(&mut self, oracle: Arc<O>) -> &mut Self {
+        self.oracles.push(oracle);
+        self
+    }
+
+    /// Chain backend for the wallet and the [dlc_manager::manager::Manager]. MUST implement
+    /// [crate::DdkBlockchain]. Defaults to an esplora client for the configured esplora host.
+    pub fn set_blockchain(&mut self, blockchain: Arc<B>) -> &mut Self {
+        self.blockchain = Some(blockchain);
+        self
+    }
+
+    /// Exchange rate provider used to record fiat values when contracts are accepted and closed.
+    /// Defaults to [crate::rates::NoopRateProvider] which records nothing.
+    pub fn set_rate_provider(&mut self, rate_provider: Arc<dyn RateProvider>) -> &mut Self {
+        self.rate_provider = Some(rate_provider);
+        self
+    }
+
+    /// Decides on incoming offers once they are stored.
+    /// Defaults to [crate::contract::policy::ManualOnly] which leaves every offer to the user.
+    pub fn set_offer_policy(&mut self, offer_policy: Arc<dyn OfferPolicy>) -> &mut Self {
+        self.offer_policy = Some(offer_policy);
+        self
+    }
+
+    /// Load the master seed from a platform [crate::io::KeyStorage] instead of the configured seed.
+    /// Transports should be created with the same [crate::config::SeedConfig::KeyStorage].
+    pub fn set_key_storage(&mut self, key_storage: Arc<dyn KeyStorage>) -> &mut Self {
+        self.set_seed_config(SeedConfig::KeyStorage(key_storage))
+    }
+
+    /// How the wallet selects UTXOs to fund DLCs and sends. Overrides the coin selection of
+    /// the config.
+    pub fn set_coin_selection(&mut self, coin_selection: CoinSelectionStrategy) -> &mut Self {
+        self.coin_selection = Some(coin_selection);
+        self
+    }
+
+    /// Who signs the wallet's inputs of DLC funding transactions. With
+    /// [SignerBackend::External] accepts and signs pause until the signer answers, emitting
+    /// [crate::events::DdkEvent::AwaitingSignature]. Defaults to [SignerBackend::Local].
+    pub fn set_signer_backend(&mut self, signer_backend: SignerBackend) -> &mut Self {
+        self.signer_backend = Some(signer_backend);
+        self
+    }
+
+    /// Archive and prune finished contracts in the background. Must be called after
+    /// [DdkBuilder::set_config].
+    pub fn set_archive_policy(&mut self, policy: ArchivePolicy) -> &mut Self {
+        let mut config = self.config.clone().unwrap_or_default();
+        config.archive_policy = Some(policy);
+        self.config = Some(config);
+        self
+    }
+
+    /// Refund contracts on the periodic check once their refund locktime is reached. Must be
+    /// called after [DdkBuilder::set_config].
+    pub fn set_auto_refund(&mut self, auto_refund: bool) -> &mut Self {
+        let mut config = self.config.clone().unwrap_or_default();
+        config.auto_refund = auto_refund;
+        self.config = Some(config);
+        self
+    }
+
+    /// Confirmations funding transactions need before contracts are confirmed, by contract
+    /// size. Must be called after [DdkBuilder::set_config].
+    pub fn set_confirmation_policy(&mut self, policy: ConfirmationPolicy) -> &mut Self {
+        let mut config = self.config.clone().unwrap_or_default();
+        config.confirmation_policy = policy;
+        self.config = Some(config);
+        self
+    }
+
+    /// Verify the storage before building the node and fail if it finds issues. Must be called
+    /// after [DdkBuilder::set_config].
+    pub fn set_verify_storage_on_start(&mut self, verify: bool) -> &mut Self {
+        let mut config = self.config.clone().unwrap_or_default();
+        config.verify_storage_on_start = verify;
+        self.config = Some(config);
+        self
+    }
+
     /// Configuration for `DlcDevKit`. Storage dir, seed config, network, and esplora host.
     pub fn set_config(&mut self, config: DdkConfig) -> &mut Self {
         self.config = Some(config);
         self
     }
 
-    /// Builds the `DlcDevKit` instance. Fails if any components are missing.
-    pub fn finish(&self) -> anyhow::Result<DlcDevKit<T, S, O>> {
-        let config = self
-            .config
+    /// The config with the network, esplora url, and seed set on the builder applied.
+    fn resolve_config(&self) -> Result<DdkConfig, BuilderError> {
+        let mut config = self.config.clone().ok_or(BuilderError::NoConfig)?;
+        if let Some(network) = self.network.filter(|network| *network != config.network) {
+            config.network = network;
+            config.esplora_host = default_esplora_host(network).to_string();
+            config.expected_genesis_hash = None;
+        }
+        if let Some(esplora_url) = &self.esplora_url {
+            config.esplora_host = esplora_url.clone();
+        }
+        if let Some(seed_config) = &self.seed_config {
+            config.seed_config = seed_config.clone();
+        }
+        if let Some(coin_selection) = self.coin_selection {
+            config.coin_selection = coin_selection;
+        }
+
+        if !config.esplora_host.starts_with("http://") && !config.esplora_host.starts_with("https://") {
+            return Err(BuilderError::InvalidEsploraUrl);
+        }
+        if config.message_workers == 0 {
+            return Err(BuilderError::NoMessageWorkers);
+        }
+        if config.wallet_accounts.funding.is_empty() {
+            return Err(BuilderError::NoFundingAccount);
+        }
+        if config.fee_refresh_interval.is_zero() {
+            return Err(BuilderError::ZeroInterval("fee refresh interval"));
+        }
+        if config.periodic_check_interval.is_zero() {
+            return Err(BuilderError::ZeroInterval("periodic check interval"));
+        }
+        if config.chain_watch.interval.is_zero() {
+            return Err(BuilderError::ZeroInterval("chain watch interval"));
+        }
+        if config.rebroadcast.interval.is_zero() {
+            return Err(BuilderError::ZeroInterval("rebroadcast interval"));
+        }
+        if config.outbound_retry.interval.is_zero() {
+            return Err(BuilderError::ZeroInterval("outbound retry interval"));
+        }
+        if config.wallet_sync_interval.is_zero() {
+            return Err(BuilderError::ZeroInterval("wallet sync interval"));
+        }
+        if config.manager_response_timeout.is_zero() {
+            return Err(BuilderError::ZeroInterval("manager response timeout"));
+        }
+        if config.external_signer_timeout.is_zero() {
+            return Err(BuilderError::ZeroInterval("external signer timeout"));
+        }
+        let limits = &config.peer_limits;
+        if limits.messages_per_second.is_nan() || limits.messages_per_second <= 0.0 || limits.burst == 0 {
+            return Err(BuilderError::InvalidPeerLimits);
+        }
+        if config
+            .archive_policy
             .as_ref()
-            .map_or_else(|| Err(BuilderError::NoConfig), |c| Ok(c))?;
+            .is_some_and(|policy| policy.interval.is_zero())
+        {
+            return Err(BuilderError::ZeroInterval("archive interval"));
+        }
+        Ok(config)
+    }
+
+    /// Builds the `DlcDevKit` instance. Fails if any components are missing or the config is
+    /// invalid.
+    pub fn finish(&self) -> Result<DlcDevKit<T, S, O, B>, DdkError> {
+        let config = &self.resolve_config()?;
         tracing::info!("Using network {}", config.network);
 
+        let transport = self
+            .transport
+            .as_ref()
+            .map_or_else(|| Err(BuilderError::NoTransport), |t| Ok(t.clone()))?;
+
+        let storage = match &self.storage {
+            Some(storage) => storage.clone(),
+            None => Arc::new(S::from_config(config).map_err(StorageError::new)?),
+        };
+        if config.verify_storage_on_start {
+            let report = storage.verify_integrity().map_err(StorageError::new)?;
+            for issue in &report.issues {
+                tracing::error!(
+                    tree = %issue.tree,
+                    key = %issue.key,
+                    prefix = ?issue.prefix,
+                    kind = ?issue.kind,
+                    "Storage integrity issue."
+                );
+            }
+            if !report.is_ok() {
+                return Err(DdkError::StorageIntegrity(report));
+            }
+            tracing::info!(
+                contracts = report.contracts_checked,
+                channels = report.channels_checked,
+                "Verified storage."
+            );
+        }
+
+        let oracle = self
+            .oracle
+            .as_ref()
+            .map_or_else(|| Err(BuilderError::NoOracle), |o| Ok(o.clone()))?;
+
         // Creates the DDK directory.
         //
         // TODO: Should have a storage config for no-std builds.
         // TODO: should be nested with the DDK name.
-        std::fs::create_dir_all(&config.storage_path)?;
+        std::fs::create_dir_all(&config.storage_path).map_err(StorageError::new)?;
         tracing::info!(path=?config.storage_path, "Created directory for ddk node.");
 
         let xprv = io::xprv_from_config(&config.seed_config, config.network)?;
@@ -145,51 +408,90 @@ impl<T: DdkTransport, S: DdkStorage, O: DdkOracle> DdkBuilder<T, S, O> {
             "Loaded private key"
         );
 
-        let transport = self
-            .transport
-            .as_ref()
-            .map_or_else(|| Err(BuilderError::NoTransport), |t| Ok(t.clone()))?;
-
-        let storage = self
-            .storage
-            .as_ref()
-            .map_or_else(|| Err(BuilderError::NoStorage), |s| Ok(s.clone()))?;
-
-        let oracle = self
-            .oracle
-            .as_ref()
-            .map_or_else(|| Err(BuilderError::NoOracle), |o| Ok(o.clone()))?;
-
         let name = self
             .name
             .clone()
             .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
 
+        let blockchain = match &self.blockchain {
+            Some(blockchain) => blockchain.clone(),
+            None => {
+                let blockchain = B::from_config(config)?;
+                tracing::info!(host = config.esplora_host, "Connected to blockchain.");
+                Arc::new(blockchain)
+            }
+        };
+
         let wallet = Arc::new(DlcDevKitWallet::new(
             &name,
             xprv,
-            &config.esplora_host,
+            blockchain.clone(),
             config.network,
             &config.storage_path,
             storage.clone(),
-        )?);
+        )?
+        .with_coin_selection(config.coin_selection)
+        .with_fee_estimates_max_age(config.fee_estimates_max_age)
+        .with_accounts(config.wallet_accounts.clone())
+        .with_signer_backend(
+            self.signer_backend.clone().unwrap_or_default(),
+            config.external_signer_timeout,
+        ));
         tracing::info!("Opened BDK wallet. name={}", name);
 
-        let mut oracles = HashMap::new();
-        oracles.insert(oracle.get_public_key(), oracle.clone());
-        tracing::info!(name = oracle.name(), "Connected to oracle.");
+        let mut oracles = OracleSet::new(oracle.clone());
+        for additional in &self.oracles {
+            if !oracles.insert(additional.clone()) {
+                tracing::warn!(name = additional.name(), "Oracle was added twice.");
+            }
+        }
+        for oracle in oracles.list() {
+            tracing::info!(name = oracle.name(), pubkey = oracle.get_public_key().to_string(), "Connected to oracle.");
+        }
 
-        let esplora_client = Arc::new(EsploraClient::new(&config.esplora_host, config.network)?);
-        tracing::info!(host = config.esplora_host, "Connected to esplora client.");
+        let rate_provider = self
+            .rate_provider
+            .clone()
+            .unwrap_or_else(|| Arc::new(NoopRateProvider));
+        let offer_policy = self
+            .offer_policy
+            .clone()
+            .unwrap_or_else(|| Arc::new(ManualOnly));
 
         let (sender, receiver) = unbounded::<DlcManagerMessage>();
 
+        let events = Arc::new(EventBus::default());
+        let status = Arc::new(StatusTracker::default());
+        let announcement_cache = Arc::new(Mutex::new(AnnouncementCache::new(
+            config.announcement_cache_size,
+            config.announcement_max_age,
+        )));
+        let verifying_oracles = oracles
+            .to_map()
+            .into_iter()
+            .map(|(public_key, oracle)| {
+                let oracle = CachingOracle::with_grace_period(
+                    oracle,
+                    storage.clone(),
+                    config.announcement_max_age,
+                )
+                .with_announcement_cache(announcement_cache.clone());
+                let oracle = VerifyingOracle::new(
+                    Arc::new(oracle),
+                    storage.clone(),
+                    events.clone(),
+                    status.clone(),
+                );
+                (public_key, Arc::new(oracle))
+            })
+            .collect();
+        let broadcasts = Arc::new(BroadcastTracker::new(blockchain.clone(), storage.clone()));
         let manager = Arc::new(Manager::new(
             wallet.clone(),
             wallet.clone(),
-            esplora_client.clone(),
+            broadcasts.clone(),
             storage.clone(),
-            oracles,
+            verifying_oracles,
             Arc::new(SystemTimeProvider {}),
             wallet.clone(),
         )?);
@@ -202,9 +504,104 @@ impl<T: DdkTransport, S: DdkStorage, O: DdkOracle> DdkBuilder<T, S, O> {
             sender: Arc::new(sender),
             receiver: Arc::new(receiver),
             transport,
+            outbox: Arc::new(Outbox::new()),
             storage,
             oracle,
+            oracles,
             network: config.network,
+            channel_reserve_sats: config.channel_reserve_sats,
+            rate_provider,
+            fiat_currency: config.fiat_currency.clone(),
+            risk_limits: config.risk_limits,
+            offer_policy,
+            auto_refund: config.auto_refund,
+            funding_broadcast_window: config.funding_broadcast_window,
+            announcement_cache,
+            signer_vacuum: config.signer_vacuum.clone(),
+            archive_policy: config.archive_policy.clone(),
+            negotiation_timeouts: config.negotiation_timeouts,
+            message_workers: config.message_workers,
+            peer_limits: config.peer_limits,
+            fee_refresh_interval: config.fee_refresh_interval,
+            periodic_check_interval: config.periodic_check_interval,
+            chain_watch: config.chain_watch,
+            broadcasts,
+            rebroadcast: config.rebroadcast,
+            outbound_retry: config.outbound_retry,
+            status,
+            confirmation_policy: config.confirmation_policy.clone(),
+            maturity_notice: config.maturity_notice,
+            wallet_sync_interval: config.wallet_sync_interval,
+            wallet_sync_warning_after: config.wallet_sync_warning_after,
+            recovery_stop_gap: config.recovery_stop_gap,
+            manager_response_timeout: config.manager_response_timeout,
+            sign_progress: Arc::new(RwLock::new(None)),
+            events,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::network::MAINNET_ESPLORA_HOST;
+    use crate::chain::MockBlockchain;
+    use crate::oracle::P2PDOracleClient;
+    use crate::storage::SledStorageProvider;
+    use crate::transport::lightning::LightningTransport;
+    use std::time::Duration;
+
+    type TestBuilder = DdkBuilder<LightningTransport, SledStorageProvider, P2PDOracleClient, MockBlockchain>;
+
+    #[test]
+    fn network_defaults_to_its_esplora() {
+        let mut builder = TestBuilder::new();
+        builder.set_network(Network::Bitcoin);
+        let config = builder.resolve_config().unwrap();
+        assert_eq!(config.network, Network::Bitcoin);
+        assert_eq!(config.esplora_host, MAINNET_ESPLORA_HOST);
+
+        builder.set_esplora_url("http://localhost:3000");
+        builder.set_seed_config(SeedConfig::Bytes([7u8; 64]));
+        let config = builder.resolve_config().unwrap();
+        assert_eq!(config.esplora_host, "http://localhost:3000");
+        assert!(matches!(config.seed_config, SeedConfig::Bytes(seed) if seed == [7u8; 64]));
+    }
+
+    #[test]
+    fn coin_selection_survives_a_later_config() {
+        let mut builder = TestBuilder::new();
+        builder
+            .set_coin_selection(CoinSelectionStrategy::OldestFirst)
+            .set_config(DdkConfig::default());
+        let config = builder.resolve_config().unwrap();
+        assert_eq!(config.coin_selection, CoinSelectionStrategy::OldestFirst);
+    }
+
+    #[test]
+    fn misconfiguration_is_typed() {
+        let mut builder = TestBuilder::new();
+        builder.set_esplora_url("localhost:3000");
+        assert!(matches!(builder.resolve_config(), Err(BuilderError::InvalidEsploraUrl)));
+
+        let mut builder = TestBuilder::new();
+        builder.set_config(DdkConfig {
+            periodic_check_interval: Duration::ZERO,
+            ..Default::default()
+        });
+        assert!(matches!(builder.resolve_config(), Err(BuilderError::ZeroInterval(_))));
+
+        let mut builder = TestBuilder::new();
+        builder.set_archive_policy(ArchivePolicy {
+            interval: Duration::ZERO,
+            ..ArchivePolicy::new(Duration::from_secs(60))
+        });
+        assert!(matches!(
+            builder.resolve_config(),
+            Err(BuilderError::ZeroInterval("archive interval"))
+        ));
+
+        let error = TestBuilder::new().finish().err().unwrap();
+        assert!(matches!(error, DdkError::Builder(BuilderError::NoTransport)));
+    }
+}
@@ -1,27 +1,218 @@
-use crate::error::esplora_err_to_manager_err;
+use crate::config::DdkConfig;
+use crate::error::{BroadcastErrorKind, ChainError};
+use crate::metrics;
+use crate::DdkBlockchain;
 use bdk_esplora::esplora_client::Error as EsploraError;
+use bdk_chain::spk_client::{FullScanRequest, FullScanResult};
 use bdk_esplora::esplora_client::{AsyncClient, BlockingClient, Builder};
-use bitcoin::Network;
-use bitcoin::{Transaction, Txid};
+use bdk_esplora::EsploraExt;
+use bdk_wallet::KeychainKind;
+use bitcoin::{BlockHash, Network};
+use bitcoin::{OutPoint, Transaction, Txid};
 use dlc_manager::error::Error as ManagerError;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Concurrent requests made while scanning the wallet.
+const PARALLEL_REQUESTS: usize = 5;
+
+/// Timeouts, retries, and the circuit breaker of the esplora client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EsploraOptions {
+    /// Timeout of a single request.
+    pub timeout: Duration,
+    /// Retries of a request that timed out, failed to connect, or got a 429 or 5xx response.
+    pub max_retries: u32,
+    /// Delay before the first retry. Doubles on each retry up to `max_backoff`.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Requests that must fail, after retries, in a row before the breaker opens. While open,
+    /// requests fail with [ChainError::Unavailable] without reaching esplora.
+    pub failure_threshold: u32,
+    /// How long the breaker stays open before letting a request through again.
+    pub open_for: Duration,
+}
+
+impl Default for EsploraOptions {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(30),
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(10),
+            failure_threshold: 5,
+            open_for: Duration::from_secs(60),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Breaker {
+    failures: u32,
+    open_until: Option<Instant>,
+}
 
 pub struct EsploraClient {
     pub blocking_client: BlockingClient,
     pub async_client: AsyncClient,
     network: Network,
+    options: EsploraOptions,
+    breaker: Mutex<Breaker>,
 }
 
 impl EsploraClient {
     pub fn new(esplora_host: &str, network: Network) -> anyhow::Result<EsploraClient> {
-        let builder = Builder::new(esplora_host);
+        Self::with_options(esplora_host, network, EsploraOptions::default())
+    }
+
+    pub fn with_options(
+        esplora_host: &str,
+        network: Network,
+        options: EsploraOptions,
+    ) -> anyhow::Result<EsploraClient> {
+        let builder = Builder::new(esplora_host).timeout(options.timeout.as_secs().max(1));
         let blocking_client = builder.clone().build_blocking();
         let async_client = builder.build_async()?;
         Ok(EsploraClient {
             blocking_client,
             async_client,
             network,
+            options,
+            breaker: Mutex::new(Breaker::default()),
         })
     }
+
+    /// Check that the endpoint serves the expected chain. Custom signets share the signet
+    /// network but not its genesis block.
+    pub fn verify_genesis(&self, expected: BlockHash) -> anyhow::Result<()> {
+        let genesis = self.call("get_block_hash", |client| client.get_block_hash(0))?;
+        if genesis != expected {
+            return Err(anyhow::anyhow!(
+                "Esplora serves a different chain. genesis={} expected={}",
+                genesis,
+                expected
+            ));
+        }
+        Ok(())
+    }
+
+    /// Run a request with retries, unless the breaker is open.
+    fn call<R>(
+        &self,
+        operation: &'static str,
+        request: impl Fn(&BlockingClient) -> Result<R, EsploraError>,
+    ) -> Result<R, ChainError> {
+        if let Some(open_until) = self.breaker.lock().unwrap().open_until {
+            let now = Instant::now();
+            if now < open_until {
+                return Err(ChainError::Unavailable {
+                    retry_in: open_until - now,
+                });
+            }
+        }
+
+        let mut delay = self.options.initial_backoff;
+        let mut retries = 0;
+        let result = loop {
+            let timer = metrics::Timer::start();
+            let attempt = request(&self.blocking_client);
+            metrics::esplora_request(operation, timer, attempt.is_ok());
+            match attempt {
+                Err(e) if is_transient(&e) && retries < self.options.max_retries => {
+                    retries += 1;
+                    tracing::warn!(
+                        retries,
+                        retry_in_ms = delay.as_millis() as u64,
+                        error = e.to_string(),
+                        "Esplora request failed. Retrying."
+                    );
+                    std::thread::sleep(delay);
+                    delay = (delay * 2).min(self.options.max_backoff);
+                }
+                result => break result,
+            }
+        };
+
+        let mut breaker = self.breaker.lock().unwrap();
+        match &result {
+            Err(e) if is_transient(e) => {
+                breaker.failures += 1;
+                if breaker.failures >= self.options.failure_threshold {
+                    tracing::error!(
+                        failures = breaker.failures,
+                        open_for_secs = self.options.open_for.as_secs(),
+                        error = e.to_string(),
+                        "Esplora is unavailable. Pausing requests."
+                    );
+                    breaker.open_until = Some(Instant::now() + self.options.open_for);
+                }
+            }
+            // Any answer from esplora, even an error, means it is reachable.
+            _ => *breaker = Breaker::default(),
+        }
+        result.map_err(ChainError::from)
+    }
+}
+
+/// Timeouts, connection failures, rate limits, and server errors are worth retrying.
+fn is_transient(error: &EsploraError) -> bool {
+    match error {
+        EsploraError::Minreq(_) => true,
+        EsploraError::HttpResponse { status, .. } => *status == 429 || *status >= 500,
+        _ => false,
+    }
+}
+
+/// Esplora relays the node's rejection when a transaction was broadcast before.
+fn is_already_broadcast(error: &EsploraError) -> bool {
+    match error {
+        EsploraError::HttpResponse { message, .. } => {
+            BroadcastErrorKind::from_message(message) == BroadcastErrorKind::AlreadyKnown
+        }
+        _ => false,
+    }
+}
+
+impl DdkBlockchain for EsploraClient {
+    fn find_transaction(&self, txid: &Txid) -> Result<Option<Transaction>, ManagerError> {
+        Ok(self.call("get_tx", |client| client.get_tx(txid))?)
+    }
+
+    fn find_spending_transaction(&self, outpoint: &OutPoint) -> Result<Option<Transaction>, ManagerError> {
+        let status = self.call("get_output_status", |client| client.get_output_status(&outpoint.txid, outpoint.vout as u64))?;
+        match status.and_then(|status| status.txid) {
+            Some(txid) => self.find_transaction(&txid),
+            None => Ok(None),
+        }
+    }
+
+    fn fee_estimates(&self) -> Result<HashMap<u16, f64>, ManagerError> {
+        Ok(self.call("get_fee_estimates", |client| client.get_fee_estimates())?)
+    }
+
+    /// Not retried. A scan request can only be used once.
+    fn full_scan(
+        &self,
+        request: FullScanRequest<KeychainKind>,
+        stop_gap: usize,
+    ) -> Result<FullScanResult<KeychainKind>, ManagerError> {
+        let timer = metrics::Timer::start();
+        let result = self
+            .blocking_client
+            .full_scan(request, stop_gap, PARALLEL_REQUESTS);
+        metrics::esplora_request("full_scan", timer, result.is_ok());
+        result.map_err(|e| ChainError::from(*e).into())
+    }
+
+    fn from_config(config: &DdkConfig) -> anyhow::Result<Self> {
+        let client =
+            EsploraClient::with_options(&config.esplora_host, config.network, config.esplora_options)?;
+        if let Some(expected) = config.expected_genesis_hash {
+            client.verify_genesis(expected)?;
+        }
+        Ok(client)
+    }
 }
 
 impl dlc_manager::Blockchain for EsploraClient {
@@ -30,69 +221,154 @@ impl dlc_manager::Blockchain for EsploraClient {
     }
 
     fn get_transaction(&self, tx_id: &Txid) -> Result<Transaction, ManagerError> {
-        let txn = self
-            .blocking_client
-            .get_tx(&tx_id)
-            .map_err(esplora_err_to_manager_err)?;
-
-        match txn {
+        match self.call("get_tx", |client| client.get_tx(tx_id))? {
             Some(txn) => Ok(txn),
-            None => Err(esplora_err_to_manager_err(
-                EsploraError::TransactionNotFound(*tx_id),
-            )),
+            None => Err(ChainError::from(EsploraError::TransactionNotFound(*tx_id)).into()),
         }
     }
 
     fn send_transaction(&self, transaction: &bitcoin::Transaction) -> Result<(), ManagerError> {
-        Ok(self
-            .blocking_client
-            .broadcast(transaction)
-            .map_err(esplora_err_to_manager_err)?)
+        match self.call("broadcast", |client| client.broadcast(transaction)) {
+            Ok(()) => Ok(()),
+            Err(ChainError::Esplora(e)) if is_already_broadcast(&e) => {
+                tracing::info!(
+                    txid = transaction.compute_txid().to_string(),
+                    "Transaction was already broadcast."
+                );
+                Ok(())
+            }
+            Err(e) => Err(e.into()),
+        }
     }
 
     fn get_block_at_height(&self, height: u64) -> Result<bitcoin::Block, ManagerError> {
-        let block_hash = self
-            .blocking_client
-            .get_block_hash(height as u32)
-            .map_err(esplora_err_to_manager_err)?;
-
-        let block = self
-            .blocking_client
-            .get_block_by_hash(&block_hash)
-            .map_err(esplora_err_to_manager_err)?;
-
-        match block {
+        let block_hash = self.call("get_block_hash", |client| client.get_block_hash(height as u32))?;
+        match self.call("get_block_by_hash", |client| client.get_block_by_hash(&block_hash))? {
             Some(block) => Ok(block),
-            None => Err(esplora_err_to_manager_err(EsploraError::HttpResponse { status: 404, message: "Block not found in esplore".into() })),
+            None => Err(ChainError::from(EsploraError::HttpResponse {
+                status: 404,
+                message: "Block not found in esplora".into(),
+            })
+            .into()),
         }
     }
 
     fn get_blockchain_height(&self) -> Result<u64, ManagerError> {
-        Ok(self
-            .blocking_client
-            .get_height()
-            .map_err(esplora_err_to_manager_err)? as u64)
+        Ok(self.call("get_height", |client| client.get_height())? as u64)
     }
 
     fn get_transaction_confirmations(&self, tx_id: &bitcoin::Txid) -> Result<u32, ManagerError> {
-        let txn = self
-            .blocking_client
-            .get_tx_status(tx_id)
-            .map_err(esplora_err_to_manager_err)?;
-        let tip_height = self
-            .blocking_client
-            .get_height()
-            .map_err(esplora_err_to_manager_err)?;
+        let txn = self.call("get_tx_status", |client| client.get_tx_status(tx_id))?;
+        // Transactions in the mempool, or dropped from a block in a reorg, have none.
+        if !txn.confirmed {
+            return Ok(0);
+        }
+        let tip_height = self.call("get_height", |client| client.get_height())?;
+        // The block the transaction is in counts as the first confirmation.
+        Ok(txn
+            .block_height
+            .map_or(0, |height| (tip_height + 1).saturating_sub(height)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::absolute::LockTime;
+    use bitcoin::transaction::Version;
+    use dlc_manager::Blockchain;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
 
-        if txn.confirmed {
-            match txn.block_height {
-                Some(height) => Ok(tip_height - height),
-                None => Ok(0),
+    /// Answer requests with `responses` in order, then with 404. Returns the host and the
+    /// number of requests served.
+    fn serve(responses: Vec<(u16, &'static str)>) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let host = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let served = requests.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut content_length = 0;
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    if let Some(length) = line.to_lowercase().strip_prefix("content-length:") {
+                        content_length = length.trim().parse().unwrap();
+                    }
+                    line.clear();
+                }
+                reader.read_exact(&mut vec![0u8; content_length]).unwrap();
+
+                let index = served.fetch_add(1, Ordering::SeqCst);
+                let (status, body) = responses.get(index).copied().unwrap_or((404, ""));
+                let response = format!(
+                    "HTTP/1.1 {status} Status\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                stream.write_all(response.as_bytes()).unwrap();
             }
-        } else {
-            Err(esplora_err_to_manager_err(
-                EsploraError::TransactionNotFound(*tx_id),
-            ))
-        }
+        });
+        (host, requests)
+    }
+
+    fn client(host: &str) -> EsploraClient {
+        let options = EsploraOptions {
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(2),
+            failure_threshold: 2,
+            ..Default::default()
+        };
+        EsploraClient::with_options(host, Network::Regtest, options).unwrap()
+    }
+
+    #[test]
+    fn retries_rate_limits_and_server_errors() {
+        let (host, requests) = serve(vec![(429, "slow down"), (503, "busy"), (200, "850000")]);
+        assert_eq!(client(&host).get_blockchain_height().unwrap(), 850_000);
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn breaker_opens_after_repeated_failures() {
+        let (host, requests) = serve(vec![(500, "down"); 8]);
+        let client = client(&host);
+        assert!(client.get_blockchain_height().is_err());
+        assert!(client.get_blockchain_height().is_err());
+        assert_eq!(requests.load(Ordering::SeqCst), 8);
+
+        // Open: fails without reaching esplora.
+        let error = client.call("get_height", |client| client.get_height()).unwrap_err();
+        assert!(matches!(error, ChainError::Unavailable { .. }));
+        assert_eq!(requests.load(Ordering::SeqCst), 8);
+    }
+
+    #[test]
+    fn client_errors_are_not_retried() {
+        let (host, requests) = serve(vec![(400, "bad request"), (200, "1")]);
+        assert!(client(&host).get_blockchain_height().is_err());
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn rebroadcast_is_success() {
+        let transaction = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: vec![],
+        };
+        let (host, _) = serve(vec![
+            (400, r#"sendrawtransaction RPC error: {"code":-27,"message":"Transaction already in block chain"}"#),
+            (400, r#"sendrawtransaction RPC error: {"code":-26,"message":"txn-already-in-mempool"}"#),
+            (400, r#"sendrawtransaction RPC error: {"code":-26,"message":"min relay fee not met"}"#),
+        ]);
+        let client = client(&host);
+        assert!(client.send_transaction(&transaction).is_ok());
+        assert!(client.send_transaction(&transaction).is_ok());
+        assert!(client.send_transaction(&transaction).is_err());
     }
 }
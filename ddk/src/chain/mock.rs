@@ -0,0 +1,340 @@
+use crate::DdkBlockchain;
+use bdk_chain::spk_client::{FullScanRequest, FullScanResult};
+use bdk_chain::TxGraph;
+use bdk_wallet::KeychainKind;
+use bitcoin::block::{Header, Version};
+use bitcoin::hashes::Hash;
+use bitcoin::{
+    Amount, Block, BlockHash, CompactTarget, Network, OutPoint, ScriptBuf, Transaction,
+    TxMerkleNode, TxOut, Txid,
+};
+use dlc_manager::error::Error as ManagerError;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// In-memory chain for tests. Broadcast transactions are kept in a mempool until
+/// [MockBlockchain::mine] confirms them.
+#[derive(Debug)]
+pub struct MockBlockchain {
+    network: Network,
+    inner: Mutex<MockChainState>,
+}
+
+#[derive(Debug, Default)]
+struct MockChainState {
+    height: u64,
+    transactions: HashMap<Txid, Transaction>,
+    /// Height each transaction was confirmed at.
+    confirmed: HashMap<Txid, u64>,
+    broadcasts: Vec<Txid>,
+    fee_estimates: HashMap<u16, f64>,
+    broadcast_rejection: Option<String>,
+    scan_delay: Duration,
+    query_delay: Duration,
+    /// How far [DdkBlockchain::unix_time] is ahead of the wall clock.
+    time_offset: Duration,
+}
+
+impl MockBlockchain {
+    pub fn new(network: Network) -> MockBlockchain {
+        MockBlockchain {
+            network,
+            inner: Mutex::new(MockChainState::default()),
+        }
+    }
+
+    /// Mine `blocks` blocks, confirming every transaction in the mempool in the first one.
+    pub fn mine(&self, blocks: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        if blocks == 0 {
+            return;
+        }
+        let height = inner.height + 1;
+        let unconfirmed = inner
+            .transactions
+            .keys()
+            .filter(|txid| !inner.confirmed.contains_key(*txid))
+            .copied()
+            .collect::<Vec<_>>();
+        for txid in unconfirmed {
+            inner.confirmed.insert(txid, height);
+        }
+        inner.height += blocks;
+    }
+
+    /// Replace the last `blocks` blocks with as many empty ones, the way a reorg to a chain
+    /// that did not include their transactions yet does. The transactions go back to the
+    /// mempool and the height stays the same.
+    pub fn reorg(&self, blocks: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        let fork_height = inner.height.saturating_sub(blocks);
+        inner.confirmed.retain(|_, height| *height <= fork_height);
+    }
+
+    /// Drop every unconfirmed transaction, the way a node restarted without its mempool does.
+    pub fn clear_mempool(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        let MockChainState {
+            transactions,
+            confirmed,
+            ..
+        } = &mut *inner;
+        transactions.retain(|txid, _| confirmed.contains_key(txid));
+    }
+
+    /// Replace the unconfirmed transaction `txid` with one spending the same inputs, the way a
+    /// double-spend does. Returns the txid of the replacement, None if `txid` is not in the
+    /// mempool.
+    pub fn double_spend(&self, txid: &Txid) -> Option<Txid> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.confirmed.contains_key(txid) {
+            return None;
+        }
+        let original = inner.transactions.remove(txid)?;
+        let replacement = Transaction {
+            output: vec![TxOut {
+                value: Amount::ZERO,
+                script_pubkey: ScriptBuf::new(),
+            }],
+            ..original
+        };
+        let replacement_txid = replacement.compute_txid();
+        inner.transactions.insert(replacement_txid, replacement);
+        Some(replacement_txid)
+    }
+
+    /// Fee estimates returned by [DdkBlockchain::fee_estimates].
+    pub fn set_fee_estimates(&self, estimates: HashMap<u16, f64>) {
+        self.inner.lock().unwrap().fee_estimates = estimates;
+    }
+
+    /// Reject every broadcast with `reason`, the way esplora relays a bitcoind reject reason.
+    /// None accepts broadcasts again.
+    pub fn reject_broadcasts(&self, reason: Option<&str>) {
+        self.inner.lock().unwrap().broadcast_rejection = reason.map(str::to_string);
+    }
+
+    /// Make every [DdkBlockchain::full_scan] take at least `delay`, like a scan of a large
+    /// wallet against a remote esplora.
+    pub fn set_scan_delay(&self, delay: Duration) {
+        self.inner.lock().unwrap().scan_delay = delay;
+    }
+
+    /// Make every transaction, height and block lookup take at least `delay`, like queries
+    /// to a remote esplora under load.
+    pub fn set_query_delay(&self, delay: Duration) {
+        self.inner.lock().unwrap().query_delay = delay;
+    }
+
+    /// Move the chain's clock `by` ahead of the wall clock, so time locktimes are reached
+    /// without waiting for them.
+    pub fn advance_time(&self, by: Duration) {
+        self.inner.lock().unwrap().time_offset += by;
+    }
+
+    /// Every transaction handed to [dlc_manager::Blockchain::send_transaction], in order.
+    pub fn broadcasts(&self) -> Vec<Txid> {
+        self.inner.lock().unwrap().broadcasts.clone()
+    }
+
+    /// The chain state for a lookup, once the query delay passed.
+    fn query(&self) -> MutexGuard<'_, MockChainState> {
+        let query_delay = self.inner.lock().unwrap().query_delay;
+        std::thread::sleep(query_delay);
+        self.inner.lock().unwrap()
+    }
+}
+
+impl DdkBlockchain for MockBlockchain {
+    fn find_transaction(&self, txid: &Txid) -> Result<Option<Transaction>, ManagerError> {
+        Ok(self.query().transactions.get(txid).cloned())
+    }
+
+    fn find_spending_transaction(&self, outpoint: &OutPoint) -> Result<Option<Transaction>, ManagerError> {
+        Ok(self
+            .query()
+            .transactions
+            .values()
+            .find(|tx| tx.input.iter().any(|input| input.previous_output == *outpoint))
+            .cloned())
+    }
+
+    fn fee_estimates(&self) -> Result<HashMap<u16, f64>, ManagerError> {
+        Ok(self.inner.lock().unwrap().fee_estimates.clone())
+    }
+
+    fn from_config(config: &crate::config::DdkConfig) -> anyhow::Result<Self> {
+        Ok(MockBlockchain::new(config.network))
+    }
+
+    fn unix_time(&self) -> u64 {
+        let offset = self.inner.lock().unwrap().time_offset;
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs())
+            + offset.as_secs()
+    }
+
+    /// Returns transactions paying to the requested scripts and the transactions spending
+    /// them, all as unconfirmed.
+    fn full_scan(
+        &self,
+        request: FullScanRequest<KeychainKind>,
+        stop_gap: usize,
+    ) -> Result<FullScanResult<KeychainKind>, ManagerError> {
+        let scan_delay = self.inner.lock().unwrap().scan_delay;
+        std::thread::sleep(scan_delay);
+        let inner = self.inner.lock().unwrap();
+        let mut found = HashSet::new();
+        let mut last_active_indices = BTreeMap::new();
+        for (keychain, spks) in request.spks_by_keychain {
+            let mut unused = 0;
+            for (index, spk) in spks {
+                let paying = inner
+                    .transactions
+                    .iter()
+                    .filter(|(_, tx)| tx.output.iter().any(|output| output.script_pubkey == spk))
+                    .map(|(txid, _)| *txid)
+                    .collect::<Vec<_>>();
+                if paying.is_empty() {
+                    unused += 1;
+                    if unused >= stop_gap {
+                        break;
+                    }
+                    continue;
+                }
+                unused = 0;
+                last_active_indices.insert(keychain, index);
+                found.extend(paying);
+            }
+        }
+        let spending = inner
+            .transactions
+            .iter()
+            .filter(|(_, tx)| tx.input.iter().any(|input| found.contains(&input.previous_output.txid)))
+            .map(|(txid, _)| *txid)
+            .collect::<Vec<_>>();
+        found.extend(spending);
+
+        let mut graph_update = TxGraph::default();
+        for txid in found {
+            let _ = graph_update.insert_tx(inner.transactions[&txid].clone());
+            let _ = graph_update.insert_seen_at(txid, 1);
+        }
+        Ok(FullScanResult {
+            graph_update,
+            chain_update: request.chain_tip,
+            last_active_indices,
+        })
+    }
+}
+
+impl dlc_manager::Blockchain for MockBlockchain {
+    fn get_network(&self) -> Result<Network, ManagerError> {
+        Ok(self.network)
+    }
+
+    fn get_transaction(&self, tx_id: &Txid) -> Result<Transaction, ManagerError> {
+        self.find_transaction(tx_id)?
+            .ok_or_else(|| ManagerError::BlockchainError(format!("Transaction {} not found.", tx_id)))
+    }
+
+    fn send_transaction(&self, transaction: &Transaction) -> Result<(), ManagerError> {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(reason) = &inner.broadcast_rejection {
+            return Err(ManagerError::BlockchainError(format!(
+                "sendrawtransaction RPC error: {{\"code\":-26,\"message\":\"{}\"}}",
+                reason
+            )));
+        }
+        let txid = transaction.compute_txid();
+        inner.transactions.insert(txid, transaction.clone());
+        inner.broadcasts.push(txid);
+        Ok(())
+    }
+
+    /// A block with the transactions confirmed at `height`. Headers do not link up, only the
+    /// transactions are meaningful.
+    fn get_block_at_height(&self, height: u64) -> Result<Block, ManagerError> {
+        let inner = self.query();
+        if height > inner.height {
+            return Err(ManagerError::BlockchainError(format!(
+                "Mock blockchain has no block at height {}.",
+                height
+            )));
+        }
+        let txdata = inner
+            .confirmed
+            .iter()
+            .filter(|(_, confirmed)| **confirmed == height)
+            .map(|(txid, _)| inner.transactions[txid].clone())
+            .collect();
+        Ok(Block {
+            header: Header {
+                version: Version::TWO,
+                prev_blockhash: BlockHash::all_zeros(),
+                merkle_root: TxMerkleNode::all_zeros(),
+                time: height as u32,
+                bits: CompactTarget::from_consensus(0x207fffff),
+                nonce: 0,
+            },
+            txdata,
+        })
+    }
+
+    fn get_blockchain_height(&self) -> Result<u64, ManagerError> {
+        Ok(self.query().height)
+    }
+
+    fn get_transaction_confirmations(&self, tx_id: &Txid) -> Result<u32, ManagerError> {
+        let inner = self.query();
+        if !inner.transactions.contains_key(tx_id) {
+            return Err(ManagerError::BlockchainError(format!(
+                "Transaction {} not found.",
+                tx_id
+            )));
+        }
+        Ok(inner
+            .confirmed
+            .get(tx_id)
+            .map_or(0, |height| (inner.height - height + 1) as u32))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::{absolute::LockTime, transaction::Version};
+    use dlc_manager::Blockchain;
+
+    #[test]
+    fn broadcast_confirm_and_reorg() {
+        let chain = MockBlockchain::new(Network::Regtest);
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: vec![],
+        };
+        let txid = tx.compute_txid();
+        assert!(chain.find_transaction(&txid).unwrap().is_none());
+
+        chain.send_transaction(&tx).unwrap();
+        assert_eq!(chain.get_transaction_confirmations(&txid).unwrap(), 0);
+        assert_eq!(chain.broadcasts(), vec![txid]);
+
+        chain.mine(6);
+        assert_eq!(chain.get_blockchain_height().unwrap(), 6);
+        assert_eq!(chain.get_transaction_confirmations(&txid).unwrap(), 6);
+        assert_eq!(chain.get_block_at_height(1).unwrap().txdata, vec![tx]);
+        assert!(chain.get_block_at_height(2).unwrap().txdata.is_empty());
+        assert!(chain.get_block_at_height(7).is_err());
+
+        chain.reorg(6);
+        assert_eq!(chain.get_blockchain_height().unwrap(), 6);
+        assert_eq!(chain.get_transaction_confirmations(&txid).unwrap(), 0);
+        chain.mine(1);
+        assert_eq!(chain.get_transaction_confirmations(&txid).unwrap(), 1);
+    }
+}
@@ -1,3 +1,10 @@
 mod esplora;
+pub mod network;
+pub mod rebroadcast;
+pub mod watch;
+#[cfg(any(test, feature = "test-util"))]
+mod mock;
 
-pub use esplora::EsploraClient;
+pub use esplora::{EsploraClient, EsploraOptions};
+#[cfg(any(test, feature = "test-util"))]
+pub use mock::MockBlockchain;
@@ -0,0 +1,100 @@
+//! Default endpoints and chain identity for each network.
+use bitcoin::constants::genesis_block;
+use bitcoin::{BlockHash, Network};
+use std::str::FromStr;
+
+/// Public esplora for mainnet.
+pub const MAINNET_ESPLORA_HOST: &str = "https://blockstream.info/api";
+/// Public esplora for testnet.
+pub const TESTNET_ESPLORA_HOST: &str = "https://blockstream.info/testnet/api";
+/// Public esplora for the default signet.
+pub const SIGNET_ESPLORA_HOST: &str = "https://mempool.space/signet/api";
+/// Esplora for mutinynet, a custom signet with 30 second blocks.
+pub const MUTINYNET_ESPLORA_HOST: &str = "https://mutinynet.com/api";
+/// Faucet paying out mutinynet coins.
+pub const MUTINYNET_FAUCET: &str = "https://faucet.mutinynet.com/api/onchain";
+/// Mutinynet genesis block. Custom signets do not share the default signet genesis.
+pub const MUTINYNET_GENESIS_HASH: &str =
+    "00000008819873e925422c1ff0f99f7cc9bbb232af63a077a480a3633bee1ef6";
+
+/// A chain known by name, for configs that say `mutinynet` instead of a URL and network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChainName {
+    Mainnet,
+    Testnet,
+    Signet,
+    Mutinynet,
+    Regtest,
+}
+
+impl ChainName {
+    pub fn network(&self) -> Network {
+        match self {
+            ChainName::Mainnet => Network::Bitcoin,
+            ChainName::Testnet => Network::Testnet,
+            ChainName::Signet | ChainName::Mutinynet => Network::Signet,
+            ChainName::Regtest => Network::Regtest,
+        }
+    }
+
+    pub fn esplora_host(&self) -> &'static str {
+        match self {
+            ChainName::Mainnet => MAINNET_ESPLORA_HOST,
+            ChainName::Testnet => TESTNET_ESPLORA_HOST,
+            ChainName::Signet => SIGNET_ESPLORA_HOST,
+            ChainName::Mutinynet => MUTINYNET_ESPLORA_HOST,
+            ChainName::Regtest => crate::ESPLORA_HOST,
+        }
+    }
+
+    /// The genesis hash the esplora endpoint should report.
+    pub fn genesis_hash(&self) -> BlockHash {
+        match self {
+            ChainName::Mutinynet => BlockHash::from_str(MUTINYNET_GENESIS_HASH).unwrap(),
+            name => genesis_block(name.network()).block_hash(),
+        }
+    }
+}
+
+impl FromStr for ChainName {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "mainnet" | "bitcoin" => Ok(ChainName::Mainnet),
+            "testnet" => Ok(ChainName::Testnet),
+            "signet" => Ok(ChainName::Signet),
+            "mutinynet" => Ok(ChainName::Mutinynet),
+            "regtest" => Ok(ChainName::Regtest),
+            _ => Err(anyhow::anyhow!("Unknown chain {}.", s)),
+        }
+    }
+}
+
+/// Default esplora endpoint for a network. Signet defaults to the public signet.
+pub fn default_esplora_host(network: Network) -> &'static str {
+    match network {
+        Network::Bitcoin => MAINNET_ESPLORA_HOST,
+        Network::Testnet => TESTNET_ESPLORA_HOST,
+        Network::Signet => SIGNET_ESPLORA_HOST,
+        _ => crate::ESPLORA_HOST,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn named_chains() {
+        let mutinynet = ChainName::from_str("Mutinynet").unwrap();
+        assert_eq!(mutinynet.network(), Network::Signet);
+        assert_eq!(mutinynet.esplora_host(), MUTINYNET_ESPLORA_HOST);
+        assert_ne!(mutinynet.genesis_hash(), ChainName::Signet.genesis_hash());
+        assert_eq!(
+            ChainName::Signet.genesis_hash(),
+            genesis_block(Network::Signet).block_hash()
+        );
+        assert!(ChainName::from_str("liquid").is_err());
+    }
+}
@@ -0,0 +1,373 @@
+//! Resubmit the node's transactions until they confirm. Funding transactions, CETs, and refunds
+//! can be evicted from mempools during fee spikes, and nothing else broadcasts them again.
+use crate::events::{DdkEvent, EventBus};
+use crate::metrics;
+use crate::DdkBlockchain;
+use crate::DdkStorage;
+use bitcoin::{Block, Network, Transaction, Txid};
+use dlc_manager::error::Error as ManagerError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Default time between checks of the pending broadcasts.
+pub const DEFAULT_REBROADCAST_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often broadcast transactions are checked and when they are given up on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RebroadcastOptions {
+    /// Time between checks. Defaults to 60 seconds.
+    pub interval: Duration,
+    /// Rebroadcasts of a transaction that is neither in the mempool nor the chain before it is
+    /// reported as rejected with [crate::events::DdkEvent::BroadcastRejected]. Defaults to 10.
+    pub max_attempts: u32,
+    /// Confirmations after which a transaction is no longer tracked. Defaults to 6, so a
+    /// transaction dropped in a shallow reorg is still rebroadcast.
+    pub confirmations: u32,
+}
+
+impl Default for RebroadcastOptions {
+    fn default() -> Self {
+        Self {
+            interval: DEFAULT_REBROADCAST_INTERVAL,
+            max_attempts: 10,
+            confirmations: 6,
+        }
+    }
+}
+
+/// A transaction the node broadcast that has not confirmed yet.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingBroadcast {
+    pub txid: Txid,
+    pub transaction: Transaction,
+    /// Unix timestamp in seconds of the first broadcast.
+    pub first_broadcast: u64,
+    /// Unix timestamp in seconds of the last broadcast.
+    pub last_broadcast: u64,
+    /// Rebroadcasts so far, not counting the first broadcast.
+    pub rebroadcasts: u32,
+    /// Why the last rebroadcast failed. None if it succeeded.
+    pub last_error: Option<String>,
+}
+
+impl PendingBroadcast {
+    pub fn new(transaction: Transaction, now: u64) -> PendingBroadcast {
+        PendingBroadcast {
+            txid: transaction.compute_txid(),
+            transaction,
+            first_broadcast: now,
+            last_broadcast: now,
+            rebroadcasts: 0,
+            last_error: None,
+        }
+    }
+}
+
+/// Wraps the chain backend handed to the [dlc_manager::manager::Manager] and records every
+/// transaction it broadcasts, so [BroadcastTracker::check] can rebroadcast it until it
+/// confirms. Pending transactions are kept in storage and survive restarts.
+pub struct BroadcastTracker<B, S> {
+    blockchain: Arc<B>,
+    storage: Arc<S>,
+    /// Held while the stored list is read and replaced.
+    update: Mutex<()>,
+}
+
+impl<B: DdkBlockchain, S: DdkStorage> BroadcastTracker<B, S> {
+    pub fn new(blockchain: Arc<B>, storage: Arc<S>) -> BroadcastTracker<B, S> {
+        BroadcastTracker {
+            blockchain,
+            storage,
+            update: Mutex::new(()),
+        }
+    }
+
+    /// Track a transaction that was broadcast. Tracking it again keeps the first record.
+    pub fn track(&self, transaction: &Transaction) -> anyhow::Result<()> {
+        let _update = self.update.lock().unwrap();
+        let mut pending = self.storage.list_pending_broadcasts()?;
+        let txid = transaction.compute_txid();
+        if pending.iter().any(|p| p.txid == txid) {
+            return Ok(());
+        }
+        pending.push(PendingBroadcast::new(transaction.clone(), unix_now()));
+        self.storage.save_pending_broadcasts(&pending)
+    }
+
+    /// Broadcast `transaction` and track it.
+    pub fn broadcast(&self, transaction: &Transaction) -> Result<(), ManagerError> {
+        let _span = tracing::info_span!("broadcast", txid = %transaction.compute_txid()).entered();
+        let sent = self.blockchain.send_transaction(transaction);
+        metrics::broadcast(sent.is_ok());
+        sent?;
+        if let Err(e) = self.track(transaction) {
+            tracing::error!(
+                txid = transaction.compute_txid().to_string(),
+                error = e.to_string(),
+                "Could not track broadcast transaction."
+            );
+        }
+        Ok(())
+    }
+
+    /// Stop tracking a transaction that can no longer confirm, e.g. because another
+    /// transaction spent one of its inputs.
+    pub fn forget(&self, txid: &Txid) -> anyhow::Result<()> {
+        let _update = self.update.lock().unwrap();
+        let mut pending = self.storage.list_pending_broadcasts()?;
+        pending.retain(|p| p.txid != *txid);
+        self.storage.save_pending_broadcasts(&pending)
+    }
+
+    /// Transactions broadcast and not confirmed yet.
+    pub fn pending(&self) -> anyhow::Result<Vec<PendingBroadcast>> {
+        self.storage.list_pending_broadcasts()
+    }
+
+    /// Check every pending transaction once. Confirmed ones are forgotten, ones missing from
+    /// the mempool and the chain are rebroadcast. Returns the transactions that were still
+    /// missing after `max_attempts` rebroadcasts, which are no longer tracked.
+    pub fn check(
+        &self,
+        options: &RebroadcastOptions,
+        now: u64,
+    ) -> anyhow::Result<Vec<PendingBroadcast>> {
+        // The chain is queried without holding the lock, so broadcasts are not held up.
+        let mut updates = HashMap::new();
+        let mut rejected = vec![];
+        for mut pending in self.storage.list_pending_broadcasts()? {
+            let txid = pending.txid;
+            match self.blockchain.find_transaction(&txid) {
+                Ok(Some(_)) => match self.blockchain.get_transaction_confirmations(&txid) {
+                    Ok(confirmations) if confirmations >= options.confirmations => {
+                        tracing::debug!(
+                            txid = txid.to_string(),
+                            confirmations,
+                            "Broadcast transaction confirmed."
+                        );
+                        updates.insert(txid, None);
+                    }
+                    Ok(_) => {}
+                    Err(e) => tracing::warn!(
+                        txid = txid.to_string(),
+                        error = e.to_string(),
+                        "Could not get confirmations of broadcast transaction."
+                    ),
+                },
+                Ok(None) if pending.rebroadcasts >= options.max_attempts => {
+                    tracing::error!(
+                        txid = txid.to_string(),
+                        rebroadcasts = pending.rebroadcasts,
+                        error = pending.last_error,
+                        "Giving up on transaction missing from the mempool and the chain."
+                    );
+                    updates.insert(txid, None);
+                    rejected.push(pending);
+                }
+                Ok(None) => {
+                    pending.rebroadcasts += 1;
+                    pending.last_broadcast = now;
+                    let sent = self.blockchain.send_transaction(&pending.transaction);
+                    metrics::broadcast(sent.is_ok());
+                    pending.last_error = match sent {
+                        Ok(()) => {
+                            tracing::info!(
+                                txid = txid.to_string(),
+                                rebroadcasts = pending.rebroadcasts,
+                                "Rebroadcast transaction missing from the mempool."
+                            );
+                            None
+                        }
+                        Err(e) => {
+                            tracing::warn!(
+                                txid = txid.to_string(),
+                                rebroadcasts = pending.rebroadcasts,
+                                error = e.to_string(),
+                                "Could not rebroadcast transaction."
+                            );
+                            Some(e.to_string())
+                        }
+                    };
+                    updates.insert(txid, Some(pending));
+                }
+                Err(e) => tracing::warn!(
+                    txid = txid.to_string(),
+                    error = e.to_string(),
+                    "Could not check broadcast transaction."
+                ),
+            }
+        }
+
+        if !updates.is_empty() {
+            let _update = self.update.lock().unwrap();
+            let pending = self
+                .storage
+                .list_pending_broadcasts()?
+                .into_iter()
+                .filter_map(|pending| match updates.remove(&pending.txid) {
+                    Some(update) => update,
+                    None => Some(pending),
+                })
+                .collect::<Vec<_>>();
+            self.storage.save_pending_broadcasts(&pending)?;
+        }
+        Ok(rejected)
+    }
+}
+
+impl<B: DdkBlockchain, S: DdkStorage> dlc_manager::Blockchain for BroadcastTracker<B, S> {
+    fn get_network(&self) -> Result<Network, ManagerError> {
+        self.blockchain.get_network()
+    }
+
+    fn get_transaction(&self, tx_id: &Txid) -> Result<Transaction, ManagerError> {
+        self.blockchain.get_transaction(tx_id)
+    }
+
+    fn send_transaction(&self, transaction: &Transaction) -> Result<(), ManagerError> {
+        self.broadcast(transaction)
+    }
+
+    fn get_block_at_height(&self, height: u64) -> Result<Block, ManagerError> {
+        self.blockchain.get_block_at_height(height)
+    }
+
+    fn get_blockchain_height(&self) -> Result<u64, ManagerError> {
+        self.blockchain.get_blockchain_height()
+    }
+
+    fn get_transaction_confirmations(&self, tx_id: &Txid) -> Result<u32, ManagerError> {
+        self.blockchain.get_transaction_confirmations(tx_id)
+    }
+}
+
+/// Rebroadcast the node's transactions that left the mempool and report the ones given up on.
+pub(crate) fn rebroadcast_pending<B: DdkBlockchain, S: DdkStorage>(
+    broadcasts: &BroadcastTracker<B, S>,
+    events: &EventBus,
+    options: &RebroadcastOptions,
+) {
+    let now = unix_now();
+    match broadcasts.check(options, now) {
+        Ok(rejected) => {
+            for pending in rejected {
+                events.emit(DdkEvent::BroadcastRejected {
+                    txid: pending.txid,
+                    rebroadcasts: pending.rebroadcasts,
+                    reason: pending
+                        .last_error
+                        .unwrap_or_else(|| "Not in the mempool or the chain.".to_string()),
+                });
+            }
+        }
+        Err(e) => tracing::error!(error = e.to_string(), "Could not check pending broadcasts."),
+    }
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::MockBlockchain;
+    use crate::storage::MemoryStorageProvider;
+    use bitcoin::absolute::LockTime;
+    use bitcoin::transaction::Version;
+    use dlc_manager::Blockchain;
+
+    fn transaction(lock_time: u32) -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::from_consensus(lock_time),
+            input: vec![],
+            output: vec![],
+        }
+    }
+
+    fn tracker(
+        chain: &Arc<MockBlockchain>,
+    ) -> BroadcastTracker<MockBlockchain, MemoryStorageProvider> {
+        BroadcastTracker::new(chain.clone(), Arc::new(MemoryStorageProvider::new()))
+    }
+
+    #[test]
+    fn confirmed_transactions_are_forgotten() {
+        let chain = Arc::new(MockBlockchain::new(Network::Regtest));
+        let tracker = tracker(&chain);
+        let options = RebroadcastOptions::default();
+        let tx = transaction(0);
+        tracker.send_transaction(&tx).unwrap();
+        tracker.send_transaction(&tx).unwrap();
+        assert_eq!(tracker.pending().unwrap().len(), 1);
+
+        chain.mine(5);
+        assert!(tracker.check(&options, 0).unwrap().is_empty());
+        assert_eq!(tracker.pending().unwrap().len(), 1);
+        chain.mine(1);
+        assert!(tracker.check(&options, 0).unwrap().is_empty());
+        assert!(tracker.pending().unwrap().is_empty());
+    }
+
+    #[test]
+    fn missing_transactions_are_rebroadcast_until_rejected() {
+        let chain = Arc::new(MockBlockchain::new(Network::Regtest));
+        let tracker = tracker(&chain);
+        let options = RebroadcastOptions {
+            max_attempts: 2,
+            ..Default::default()
+        };
+        let tx = transaction(1);
+        tracker.send_transaction(&tx).unwrap();
+
+        chain.clear_mempool();
+        assert!(tracker.check(&options, 10).unwrap().is_empty());
+        assert_eq!(chain.broadcasts(), vec![tx.compute_txid(); 2]);
+        let pending = &tracker.pending().unwrap()[0];
+        assert_eq!((pending.rebroadcasts, pending.last_broadcast), (1, 10));
+
+        // Back in the mempool, nothing to do.
+        assert!(tracker.check(&options, 20).unwrap().is_empty());
+        assert_eq!(chain.broadcasts().len(), 2);
+
+        chain.clear_mempool();
+        chain.reject_broadcasts(Some("bad-txns-inputs-missingorspent"));
+        assert!(tracker.check(&options, 30).unwrap().is_empty());
+        let pending = &tracker.pending().unwrap()[0];
+        assert_eq!(pending.rebroadcasts, 2);
+        assert!(pending
+            .last_error
+            .as_ref()
+            .is_some_and(|error| error.contains("missingorspent")));
+
+        let rejected = tracker.check(&options, 40).unwrap();
+        assert_eq!(rejected.len(), 1);
+        assert_eq!(rejected[0].txid, tx.compute_txid());
+        assert!(tracker.pending().unwrap().is_empty());
+    }
+
+    #[test]
+    fn pending_broadcasts_serialize() {
+        let mut tx = transaction(2);
+        tx.input.push(bitcoin::TxIn {
+            witness: bitcoin::Witness::from_slice(&[vec![1u8; 72], vec![2u8; 33]]),
+            ..Default::default()
+        });
+        tx.output.push(bitcoin::TxOut {
+            value: bitcoin::Amount::from_sat(10_000),
+            script_pubkey: bitcoin::ScriptBuf::new_op_return([3u8; 20]),
+        });
+        let pending = vec![PendingBroadcast::new(tx, 5)];
+        let bytes = bincode::serialize(&pending).unwrap();
+        assert_eq!(
+            bincode::deserialize::<Vec<PendingBroadcast>>(&bytes).unwrap(),
+            pending
+        );
+    }
+}
@@ -0,0 +1,112 @@
+//! Watch the chain for transactions the counterparty broadcasts. The DLC manager only learns
+//! about blocks on its periodic check and never looks for CETs it did not broadcast itself, so
+//! a counterparty closing a contract would go unnoticed until the refund locktime.
+use bitcoin::{OutPoint, Transaction, Txid};
+use dlc_manager::contract::signed_contract::SignedContract;
+use dlc_manager::ContractId;
+use std::collections::HashSet;
+use std::time::Duration;
+
+/// Default time between chain polls.
+pub const DEFAULT_CHAIN_WATCH_INTERVAL: Duration = Duration::from_secs(15);
+
+/// How often the chain is polled and how deep transactions must be before they are acted on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChainWatchOptions {
+    /// Time between polls. Defaults to 15 seconds.
+    pub interval: Duration,
+    /// Confirmations of a funding transaction before
+    /// [crate::events::DdkEvent::FundingConfirmed] is emitted. Defaults to 1.
+    pub funding_confirmations: u32,
+    /// Confirmations of a counterparty's CET before the contract moves to PreClosed. Zero acts
+    /// on CETs in the mempool, which is safe as only CETs and the refund can spend the funding
+    /// output. Defaults to 0.
+    pub cet_confirmations: u32,
+}
+
+impl Default for ChainWatchOptions {
+    fn default() -> Self {
+        Self {
+            interval: DEFAULT_CHAIN_WATCH_INTERVAL,
+            funding_confirmations: 1,
+            cet_confirmations: 0,
+        }
+    }
+}
+
+/// What the watcher saw between polls, so events are emitted once per contract.
+#[derive(Debug, Default)]
+pub(crate) struct ChainWatchState {
+    /// Chain height at the last poll. None before the first poll.
+    pub(crate) height: Option<u64>,
+    pub(crate) funding_confirmed: HashSet<ContractId>,
+    pub(crate) cets_seen: HashSet<ContractId>,
+}
+
+impl ChainWatchState {
+    /// Record the height. True when blocks were found since the last poll.
+    pub(crate) fn new_blocks(&mut self, height: u64) -> bool {
+        let new_blocks = self.height.is_some_and(|last| height > last);
+        self.height = Some(height);
+        new_blocks
+    }
+
+    /// Forget contracts that are no longer watched.
+    pub(crate) fn retain(&mut self, watched: &HashSet<ContractId>) {
+        self.funding_confirmed.retain(|id| watched.contains(id));
+        self.cets_seen.retain(|id| watched.contains(id));
+    }
+}
+
+/// The output of the funding transaction CETs and the refund spend. Read from the refund, which
+/// spends nothing else.
+pub(crate) fn funding_outpoint(contract: &SignedContract) -> OutPoint {
+    contract.accepted_contract.dlc_transactions.refund.input[0].previous_output
+}
+
+/// Whether `spending` is one of the contract's CETs. CETs are segwit, so the signed CET has
+/// the txid of the unsigned one stored with the contract.
+pub(crate) fn is_cet(contract: &SignedContract, spending: &Transaction) -> bool {
+    let txid = spending.compute_txid();
+    cet_txids(contract).any(|cet| cet == txid)
+}
+
+fn cet_txids(contract: &SignedContract) -> impl Iterator<Item = Txid> + '_ {
+    contract
+        .accepted_contract
+        .dlc_transactions
+        .cets
+        .iter()
+        .map(|cet| cet.compute_txid())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dlc_manager::contract::ser::Serializable;
+
+    fn confirmed() -> SignedContract {
+        let mut cursor = ::lightning::io::Cursor::new(include_bytes!(
+            "../../tests/data/dlc_storage/sled/Confirmed"
+        ));
+        SignedContract::deserialize(&mut cursor).unwrap()
+    }
+
+    #[test]
+    fn cets_are_recognized() {
+        let contract = confirmed();
+        let dlc_transactions = &contract.accepted_contract.dlc_transactions;
+        let cet = dlc_transactions.cets[0].clone();
+        assert_eq!(cet.input[0].previous_output, funding_outpoint(&contract));
+        assert!(is_cet(&contract, &cet));
+        assert!(!is_cet(&contract, &dlc_transactions.refund));
+    }
+
+    #[test]
+    fn new_blocks_after_the_first_poll() {
+        let mut state = ChainWatchState::default();
+        assert!(!state.new_blocks(100));
+        assert!(!state.new_blocks(100));
+        assert!(state.new_blocks(101));
+    }
+}
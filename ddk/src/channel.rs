@@ -0,0 +1,164 @@
+//! Serializable views of stored DLC channels, so applications do not need to match on the
+//! [Channel] enum.
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::Txid;
+use dlc_manager::channel::signed_channel::SignedChannelStateType;
+use dlc_manager::channel::Channel;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// State of a channel. Signed channels are split by their [SignedChannelStateType].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ChannelState {
+    Offered,
+    Accepted,
+    Established,
+    SettledOffered,
+    SettledReceived,
+    SettledAccepted,
+    SettledConfirmed,
+    Settled,
+    RenewOffered,
+    RenewAccepted,
+    RenewConfirmed,
+    RenewFinalized,
+    Closing,
+    CollaborativeCloseOffered,
+    Closed,
+    CounterClosed,
+    ClosedPunished,
+    CollaborativelyClosed,
+    FailedAccept,
+    FailedSign,
+    Cancelled,
+}
+
+impl From<SignedChannelStateType> for ChannelState {
+    fn from(state: SignedChannelStateType) -> ChannelState {
+        match state {
+            SignedChannelStateType::Established => ChannelState::Established,
+            SignedChannelStateType::SettledOffered => ChannelState::SettledOffered,
+            SignedChannelStateType::SettledReceived => ChannelState::SettledReceived,
+            SignedChannelStateType::SettledAccepted => ChannelState::SettledAccepted,
+            SignedChannelStateType::SettledConfirmed => ChannelState::SettledConfirmed,
+            SignedChannelStateType::Settled => ChannelState::Settled,
+            SignedChannelStateType::RenewOffered => ChannelState::RenewOffered,
+            SignedChannelStateType::RenewAccepted => ChannelState::RenewAccepted,
+            SignedChannelStateType::RenewConfirmed => ChannelState::RenewConfirmed,
+            SignedChannelStateType::RenewFinalized => ChannelState::RenewFinalized,
+            SignedChannelStateType::Closing => ChannelState::Closing,
+            SignedChannelStateType::CollaborativeCloseOffered => {
+                ChannelState::CollaborativeCloseOffered
+            }
+        }
+    }
+}
+
+impl From<&Channel> for ChannelState {
+    fn from(channel: &Channel) -> ChannelState {
+        match channel {
+            Channel::Offered(_) => ChannelState::Offered,
+            Channel::Accepted(_) => ChannelState::Accepted,
+            Channel::Signed(s) => s.state.get_type().into(),
+            Channel::FailedAccept(_) => ChannelState::FailedAccept,
+            Channel::FailedSign(_) => ChannelState::FailedSign,
+            Channel::Closing(_) => ChannelState::Closing,
+            Channel::Closed(_) => ChannelState::Closed,
+            Channel::CounterClosed(_) => ChannelState::CounterClosed,
+            Channel::ClosedPunished(_) => ChannelState::ClosedPunished,
+            Channel::CollaborativelyClosed(_) => ChannelState::CollaborativelyClosed,
+            Channel::Cancelled(_) => ChannelState::Cancelled,
+        }
+    }
+}
+
+impl fmt::Display for ChannelState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// A channel as shown in a list.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChannelSummary {
+    /// Hex encoded channel id. The temporary id until the channel is accepted.
+    pub id: String,
+    pub state: ChannelState,
+    pub counterparty: PublicKey,
+    /// Hex encoded id of the contract currently in the channel. None when the channel is
+    /// settled or closed.
+    pub contract_id: Option<String>,
+    /// Funding transaction of a signed channel.
+    pub funding_txid: Option<Txid>,
+}
+
+impl From<&Channel> for ChannelSummary {
+    fn from(channel: &Channel) -> ChannelSummary {
+        let (contract_id, funding_txid) = match channel {
+            Channel::Offered(o) => (Some(o.offered_contract_id), None),
+            Channel::Accepted(a) => (Some(a.accepted_contract_id), None),
+            Channel::Signed(s) => (s.get_contract_id(), Some(s.fund_tx.compute_txid())),
+            _ => (None, None),
+        };
+        ChannelSummary {
+            id: hex::encode(channel.get_id()),
+            state: channel.into(),
+            counterparty: channel.get_counter_party_id(),
+            contract_id: contract_id.map(hex::encode),
+            funding_txid,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dlc_manager::channel::offered_channel::OfferedChannel;
+    use dlc_manager::channel::signed_channel::SignedChannel;
+    use dlc_manager::contract::ser::Serializable;
+
+    fn deserialize_object<T: Serializable>(serialized: &[u8]) -> T {
+        let mut cursor = ::lightning::io::Cursor::new(&serialized);
+        T::deserialize(&mut cursor).unwrap()
+    }
+
+    #[test]
+    fn summaries_follow_the_signed_state() {
+        let offered: OfferedChannel = deserialize_object(include_bytes!(
+            "../tests/data/dlc_storage/sled/OfferedChannel"
+        ));
+        let summary = ChannelSummary::from(&Channel::Offered(offered.clone()));
+        assert_eq!(summary.state, ChannelState::Offered);
+        assert_eq!(summary.id, hex::encode(offered.temporary_channel_id));
+        assert_eq!(
+            summary.contract_id,
+            Some(hex::encode(offered.offered_contract_id))
+        );
+
+        let established: SignedChannel = deserialize_object(include_bytes!(
+            "../tests/data/dlc_storage/sled/SignedChannelEstablished"
+        ));
+        let summary = ChannelSummary::from(&Channel::Signed(established.clone()));
+        assert_eq!(summary.state, ChannelState::Established);
+        assert_eq!(summary.id, hex::encode(established.channel_id));
+        assert_eq!(summary.counterparty, established.counter_party);
+        assert_eq!(
+            summary.funding_txid,
+            Some(established.fund_tx.compute_txid())
+        );
+        assert!(summary.contract_id.is_some());
+
+        let settled: SignedChannel = deserialize_object(include_bytes!(
+            "../tests/data/dlc_storage/sled/SignedChannelSettled"
+        ));
+        let summary = ChannelSummary::from(&Channel::Signed(settled));
+        assert_eq!(summary.state, ChannelState::Settled);
+        assert!(summary.contract_id.is_none());
+
+        let json = serde_json::to_string(&summary).unwrap();
+        assert_eq!(
+            serde_json::from_str::<ChannelSummary>(&json).unwrap(),
+            summary
+        );
+    }
+}
@@ -0,0 +1,393 @@
+//! Loading a [DdkConfig] from a TOML file and `DDK_` environment variables.
+//!
+//! ```toml
+//! data_dir = "/var/lib/ddk"
+//! network = "regtest"
+//! esplora_url = "http://127.0.0.1:30000"
+//! oracle_url = "http://127.0.0.1:8082"
+//!
+//! [seed]
+//! type = "file"
+//!
+//! [fees]
+//! refresh_interval_secs = 60
+//! estimates_max_age_secs = 3600
+//! channel_reserve_sats = 10000
+//!
+//! [sync]
+//! wallet_interval_secs = 10
+//! wallet_warning_after_secs = 600
+//! periodic_check_interval_secs = 60
+//!
+//! [transport]
+//! listening_port = 1776
+//! ```
+//!
+//! Everything is stored under `data_dir/{network}/`. Environment variables override the file:
+//! `DDK_DATA_DIR`, `DDK_NETWORK`, `DDK_ESPLORA_URL`, `DDK_ORACLE_URL`, `DDK_SEED_FILE`,
+//! `DDK_SEED_MNEMONIC`, `DDK_FEE_REFRESH_INTERVAL_SECS`, `DDK_FEE_ESTIMATES_MAX_AGE_SECS`,
+//! `DDK_CHANNEL_RESERVE_SATS`, `DDK_WALLET_SYNC_INTERVAL_SECS`,
+//! `DDK_PERIODIC_CHECK_INTERVAL_SECS`, and `DDK_LISTENING_PORT`.
+use super::{network_dir, DdkConfig, SeedConfig, DEFAULT_STORAGE_DIR};
+use crate::chain::network::ChainName;
+use crate::ConfigError;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    data_dir: Option<PathBuf>,
+    network: Option<String>,
+    esplora_url: Option<String>,
+    oracle_url: Option<String>,
+    seed: Option<SeedFile>,
+    fees: FeeSection,
+    sync: SyncSection,
+    transport: TransportSection,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum SeedFile {
+    /// Seed file in `path`, or in the network directory when no path is set.
+    File { path: Option<String> },
+    Mnemonic {
+        phrase: String,
+        passphrase: Option<String>,
+    },
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FeeSection {
+    refresh_interval_secs: Option<u64>,
+    estimates_max_age_secs: Option<u64>,
+    channel_reserve_sats: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct SyncSection {
+    wallet_interval_secs: Option<u64>,
+    wallet_warning_after_secs: Option<u64>,
+    periodic_check_interval_secs: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct TransportSection {
+    listening_port: Option<u16>,
+}
+
+impl DdkConfig {
+    /// Load a config from a TOML file, with `DDK_` environment variables taking precedence.
+    /// Every invalid value is reported in one [ConfigError::Invalid].
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<DdkConfig, ConfigError> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path).map_err(|source| ConfigError::ReadFile {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        load(&contents, std::env::vars())
+    }
+
+    /// Load a config from `DDK_` environment variables alone. Unset values keep their defaults.
+    pub fn from_env() -> Result<DdkConfig, ConfigError> {
+        load("", std::env::vars())
+    }
+}
+
+fn load<I>(contents: &str, vars: I) -> Result<DdkConfig, ConfigError>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    let mut file: ConfigFile = toml::from_str(contents)?;
+    let mut problems = Vec::new();
+    file.apply_env(vars, &mut problems);
+    file.into_config(problems)
+}
+
+impl ConfigFile {
+    fn apply_env<I>(&mut self, vars: I, problems: &mut Vec<String>)
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        for (key, value) in vars {
+            match key.as_str() {
+                "DDK_DATA_DIR" => self.data_dir = Some(value.into()),
+                "DDK_NETWORK" => self.network = Some(value),
+                "DDK_ESPLORA_URL" => self.esplora_url = Some(value),
+                "DDK_ORACLE_URL" => self.oracle_url = Some(value),
+                "DDK_SEED_FILE" => self.seed = Some(SeedFile::File { path: Some(value) }),
+                "DDK_SEED_MNEMONIC" => {
+                    self.seed = Some(SeedFile::Mnemonic {
+                        phrase: value,
+                        passphrase: None,
+                    })
+                }
+                "DDK_FEE_REFRESH_INTERVAL_SECS" => {
+                    self.fees.refresh_interval_secs =
+                        env_number(&key, &value, problems).or(self.fees.refresh_interval_secs)
+                }
+                "DDK_FEE_ESTIMATES_MAX_AGE_SECS" => {
+                    self.fees.estimates_max_age_secs =
+                        env_number(&key, &value, problems).or(self.fees.estimates_max_age_secs)
+                }
+                "DDK_CHANNEL_RESERVE_SATS" => {
+                    self.fees.channel_reserve_sats =
+                        env_number(&key, &value, problems).or(self.fees.channel_reserve_sats)
+                }
+                "DDK_WALLET_SYNC_INTERVAL_SECS" => {
+                    self.sync.wallet_interval_secs =
+                        env_number(&key, &value, problems).or(self.sync.wallet_interval_secs)
+                }
+                "DDK_PERIODIC_CHECK_INTERVAL_SECS" => {
+                    self.sync.periodic_check_interval_secs = env_number(&key, &value, problems)
+                        .or(self.sync.periodic_check_interval_secs)
+                }
+                "DDK_LISTENING_PORT" => {
+                    self.transport.listening_port =
+                        env_number(&key, &value, problems).or(self.transport.listening_port)
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn into_config(self, mut problems: Vec<String>) -> Result<DdkConfig, ConfigError> {
+        let mut config = match self.network.as_deref().map(ChainName::from_str) {
+            Some(Ok(chain)) => DdkConfig::for_chain(chain),
+            Some(Err(_)) => {
+                problems.push(format!(
+                    "Unknown network {}.",
+                    self.network.as_deref().unwrap_or_default()
+                ));
+                DdkConfig::default()
+            }
+            None => DdkConfig::default(),
+        };
+
+        if let Some(dir) = self.data_dir.as_ref().filter(|dir| !dir.is_dir()) {
+            problems.push(format!("Data directory {} does not exist.", dir.display()));
+        }
+        let data_dir = self
+            .data_dir
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_STORAGE_DIR));
+        config.storage_path = network_dir(&data_dir, config.network);
+
+        if let Some(url) = self.esplora_url {
+            check_url("esplora_url", &url, &mut problems);
+            config.esplora_host = url;
+        }
+        if let Some(url) = &self.oracle_url {
+            check_url("oracle_url", url, &mut problems);
+        }
+        config.oracle_url = self.oracle_url;
+
+        config.seed_config = match self.seed {
+            Some(SeedFile::File { path: Some(path) }) => SeedConfig::File(path),
+            Some(SeedFile::File { path: None }) | None => {
+                SeedConfig::File(config.storage_path.to_string_lossy().to_string())
+            }
+            Some(SeedFile::Mnemonic { phrase, passphrase }) => {
+                if let Err(e) = bip39::Mnemonic::parse(&phrase) {
+                    problems.push(format!("Invalid seed mnemonic. error={}", e));
+                }
+                SeedConfig::Mnemonic { phrase, passphrase }
+            }
+        };
+
+        if let Some(secs) = self.fees.refresh_interval_secs {
+            config.fee_refresh_interval = Duration::from_secs(secs);
+        }
+        if let Some(secs) = self.fees.estimates_max_age_secs {
+            config.fee_estimates_max_age = Duration::from_secs(secs);
+        }
+        if let Some(sats) = self.fees.channel_reserve_sats {
+            config.channel_reserve_sats = sats;
+        }
+        if let Some(secs) = self.sync.wallet_interval_secs {
+            config.wallet_sync_interval = Duration::from_secs(secs);
+        }
+        if let Some(secs) = self.sync.wallet_warning_after_secs {
+            config.wallet_sync_warning_after = Duration::from_secs(secs);
+        }
+        if let Some(secs) = self.sync.periodic_check_interval_secs {
+            config.periodic_check_interval = Duration::from_secs(secs);
+        }
+        if let Some(port) = self.transport.listening_port {
+            config.transport.listening_port = port;
+        }
+
+        if !problems.is_empty() {
+            return Err(ConfigError::Invalid(problems));
+        }
+        Ok(config)
+    }
+}
+
+fn env_number<T: FromStr>(key: &str, value: &str, problems: &mut Vec<String>) -> Option<T> {
+    match value.parse() {
+        Ok(number) => Some(number),
+        Err(_) => {
+            problems.push(format!("{} is not a valid number. value={}", key, value));
+            None
+        }
+    }
+}
+
+fn check_url(field: &str, url: &str, problems: &mut Vec<String>) {
+    match reqwest::Url::parse(url) {
+        Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => {}
+        Ok(_) => problems.push(format!(
+            "{} must be an http or https url. url={}",
+            field, url
+        )),
+        Err(e) => problems.push(format!(
+            "{} is not a valid url. url={} error={}",
+            field, url, e
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{SledStorageProvider, SLED_DB_DIR};
+    use crate::DdkStorage;
+    use bitcoin::Network;
+
+    fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn file_is_loaded_under_the_network_dir() {
+        let dir = "tests/data/config_from_file";
+        std::fs::create_dir_all(dir).unwrap();
+        let path = Path::new(dir).join("ddk.toml");
+        std::fs::write(
+            &path,
+            format!(
+                r#"
+data_dir = "{dir}"
+network = "regtest"
+esplora_url = "http://127.0.0.1:30000"
+oracle_url = "http://127.0.0.1:8082"
+
+[fees]
+refresh_interval_secs = 30
+estimates_max_age_secs = 600
+channel_reserve_sats = 5000
+
+[sync]
+wallet_interval_secs = 5
+periodic_check_interval_secs = 20
+
+[transport]
+listening_port = 9735
+"#
+            ),
+        )
+        .unwrap();
+
+        let config = DdkConfig::from_file(&path).unwrap();
+        assert_eq!(config.network, Network::Regtest);
+        assert_eq!(config.storage_path, Path::new(dir).join("regtest"));
+        assert_eq!(config.esplora_host, "http://127.0.0.1:30000");
+        assert_eq!(config.oracle_url.as_deref(), Some("http://127.0.0.1:8082"));
+        assert_eq!(config.fee_refresh_interval, Duration::from_secs(30));
+        assert_eq!(config.fee_estimates_max_age, Duration::from_secs(600));
+        assert_eq!(config.channel_reserve_sats, 5000);
+        assert_eq!(config.wallet_sync_interval, Duration::from_secs(5));
+        assert_eq!(config.periodic_check_interval, Duration::from_secs(20));
+        assert_eq!(config.transport.listening_port, 9735);
+        assert!(matches!(
+            &config.seed_config,
+            SeedConfig::File(seed) if Path::new(seed) == config.storage_path
+        ));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn environment_overrides_the_file() {
+        let contents = r#"
+network = "regtest"
+esplora_url = "http://127.0.0.1:30000"
+
+[transport]
+listening_port = 9735
+"#;
+        let config = load(
+            contents,
+            vars(&[
+                ("DDK_NETWORK", "mutinynet"),
+                ("DDK_ESPLORA_URL", "https://mutinynet.com/api"),
+                ("DDK_LISTENING_PORT", "1777"),
+                ("DDK_SEED_MNEMONIC", "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about"),
+                ("PATH", "/usr/bin"),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(config.network, Network::Signet);
+        assert!(config.expected_genesis_hash.is_some());
+        assert_eq!(config.esplora_host, "https://mutinynet.com/api");
+        assert_eq!(config.transport.listening_port, 1777);
+        assert_eq!(
+            config.storage_path,
+            Path::new(DEFAULT_STORAGE_DIR).join("signet")
+        );
+        assert!(matches!(config.seed_config, SeedConfig::Mnemonic { .. }));
+    }
+
+    #[test]
+    fn problems_are_reported_together() {
+        let contents = r#"
+data_dir = "tests/data/config_missing_dir"
+network = "moonnet"
+esplora_url = "mutinynet.com/api"
+oracle_url = "ftp://127.0.0.1"
+"#;
+        let err = load(contents, vars(&[("DDK_LISTENING_PORT", "port")])).unwrap_err();
+        let ConfigError::Invalid(problems) = err else {
+            panic!("Expected an invalid config. error={}", err);
+        };
+        assert_eq!(problems.len(), 5, "{:?}", problems);
+        assert!(problems.iter().any(|p| p.contains("moonnet")));
+        assert!(problems.iter().any(|p| p.contains("config_missing_dir")));
+        assert!(problems.iter().any(|p| p.starts_with("esplora_url")));
+        assert!(problems.iter().any(|p| p.starts_with("oracle_url")));
+        assert!(problems.iter().any(|p| p.starts_with("DDK_LISTENING_PORT")));
+
+        assert!(matches!(
+            load("unknown = 1", vars(&[])),
+            Err(ConfigError::ParseFile(_))
+        ));
+    }
+
+    #[test]
+    fn storage_is_rooted_in_the_network_dir() {
+        let dir = "tests/data/config_layout";
+        std::fs::create_dir_all(dir).unwrap();
+        let config = load(
+            &format!("data_dir = \"{dir}\"\nnetwork = \"regtest\""),
+            vars(&[]),
+        )
+        .unwrap();
+
+        let network_dir = Path::new(dir).join("regtest");
+        assert_eq!(config.storage_path, network_dir);
+
+        let storage = SledStorageProvider::from_config(&config).unwrap();
+        drop(storage);
+        assert!(network_dir.join(SLED_DB_DIR).is_dir());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,278 @@
+mod file;
+
+use std::{fmt, path::Path, path::PathBuf, sync::Arc, time::Duration};
+
+use bitcoin::{BlockHash, Network};
+
+use crate::chain::network::{ChainName, MUTINYNET_ESPLORA_HOST};
+use crate::chain::rebroadcast::RebroadcastOptions;
+use crate::chain::watch::ChainWatchOptions;
+use crate::chain::EsploraOptions;
+
+use crate::io::{KeyStorage, PassphraseProvider};
+use crate::contract::confirmations::ConfirmationPolicy;
+use crate::contract::maturity::DEFAULT_MATURITY_NOTICE;
+use crate::contract::timeout::NegotiationTimeouts;
+use crate::dispatch::DEFAULT_MESSAGE_WORKERS;
+use crate::recovery::DEFAULT_RECOVERY_STOP_GAP;
+use crate::risk::RiskLimits;
+use crate::storage::{ArchivePolicy, SignerVacuumOptions};
+use crate::transport::rate_limit::PeerLimits;
+use crate::transport::retry::OutboundRetryOptions;
+use crate::wallet::{
+    CoinSelectionStrategy, WalletAccounts, DEFAULT_EXTERNAL_SIGNER_TIMEOUT, DEFAULT_FEE_ESTIMATES_MAX_AGE,
+};
+
+pub const DEFAULT_STORAGE_DIR: &str = "/tmp/ddk";
+/// Default sats kept in the wallet to fee-bump a channel close.
+pub const DEFAULT_CHANNEL_RESERVE_SATS: u64 = 10_000;
+/// Default maximum number of cached oracle announcements.
+pub const DEFAULT_ANNOUNCEMENT_CACHE_SIZE: usize = 1_000;
+/// Default time an announcement is cached past its event maturity.
+pub const DEFAULT_ANNOUNCEMENT_MAX_AGE: Duration = Duration::from_secs(60 * 60 * 24 * 7);
+/// Default time between fee estimate refreshes.
+pub const DEFAULT_FEE_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+/// Default time between settlement checks of contracts.
+pub const DEFAULT_PERIODIC_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// Default time between wallet syncs.
+pub const DEFAULT_WALLET_SYNC_INTERVAL: Duration = Duration::from_secs(10);
+/// Default time wallet syncs may fail before a warning event is emitted.
+pub const DEFAULT_WALLET_SYNC_WARNING_AFTER: Duration = Duration::from_secs(10 * 60);
+/// Default time to wait for the manager thread to answer a request.
+pub const DEFAULT_MANAGER_RESPONSE_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+/// Default time to wait for a funding transaction to appear before acting on it.
+pub const DEFAULT_FUNDING_BROADCAST_WINDOW: Duration = Duration::from_secs(120);
+/// Default port the lightning transport listens on.
+pub const DEFAULT_LISTENING_PORT: u16 = 1776;
+
+/// Configuration values for creating a DDK process.
+///
+/// I think this should be a requirement for all implementations in some way.
+/// Need to have the directory created. Maybe instead when config is set, create the dir?
+/// As well some might rely on seed. Ex. transport with nostr & ln.
+#[derive(Debug, Clone)]
+pub struct DdkConfig {
+    /// The bitcoin network to run on. Defaults to mutiny net.
+    pub network: Network,
+    /// The esplora API to call to. Defaults to mutiny net
+    pub esplora_host: String,
+    /// Request timeout, retries, and circuit breaker of the esplora client.
+    pub esplora_options: EsploraOptions,
+    /// Genesis hash the esplora endpoint must report, checked when the client is created.
+    /// Set it for custom signets. Defaults to no check.
+    pub expected_genesis_hash: Option<BlockHash>,
+    /// The directory the DDK instance will be stored at. Defaults to /tmp/ddk/.
+    /// Probably an enum? Or is this even used? Maybe wallet_storage_path?
+    /// TODO: no-std config
+    pub storage_path: PathBuf,
+    /// The seed bytes, file, or mnemonic services will use. Defaults to [0u8; 64].
+    pub seed_config: SeedConfig,
+    /// Oracle the application connects to. The builder does not create oracle clients, this
+    /// is carried for applications loading their config from a file. Defaults to none.
+    pub oracle_url: Option<String>,
+    /// Options for transports created with [crate::DdkTransport::from_config].
+    pub transport: TransportOptions,
+    /// Sats that must remain spendable in the wallet after funding a DLC channel. The reserve
+    /// is used to fee-bump buffer transactions when force closing. Defaults to 10,000 sats.
+    pub channel_reserve_sats: u64,
+    /// Currency used to annotate contract amounts with exchange rates. Defaults to USD.
+    pub fiat_currency: String,
+    /// Global caps on open contracts and collateral at risk. Defaults to unlimited.
+    pub risk_limits: RiskLimits,
+    /// How long a signed contract's funding transaction may be missing from the mempool before
+    /// the accepting party broadcasts it in place of the offering party. See
+    /// [crate::contract::FundingBroadcastRole]. Defaults to two minutes.
+    pub funding_broadcast_window: Duration,
+    /// Maximum number of oracle announcements kept in memory. Defaults to 1,000.
+    pub announcement_cache_size: usize,
+    /// How long an announcement is kept past its event maturity. Defaults to one week.
+    pub announcement_max_age: Duration,
+    /// How the wallet picks UTXOs for DLC funding and sends. Defaults to branch and bound.
+    pub coin_selection: CoinSelectionStrategy,
+    /// Wallet accounts that fund contracts and pay sends. Defaults to the DLC account for both.
+    pub wallet_accounts: WalletAccounts,
+    /// When signer keys of closed contracts are deleted. Defaults to 30 days after derivation.
+    pub signer_vacuum: SignerVacuumOptions,
+    /// When finished contracts are archived and pruned by a background task. Defaults to none,
+    /// keeping every contract in the contract store.
+    pub archive_policy: Option<ArchivePolicy>,
+    /// How long negotiations wait on the counterparty before failing. Defaults to one hour for
+    /// outgoing offers and ten minutes for a sign message.
+    pub negotiation_timeouts: NegotiationTimeouts,
+    /// Threads processing received messages. Each counterparty's messages are handled in
+    /// order by one thread at a time. As many threads create offers and accepts. Defaults to 4.
+    pub message_workers: usize,
+    /// How often fee estimates are fetched from the chain backend. Defaults to 60 seconds.
+    pub fee_refresh_interval: Duration,
+    /// Age up to which the fee estimates stored at the last refresh are used on startup, until
+    /// the first refresh. Older ones fall back to the default fee rates. Defaults to one hour.
+    pub fee_estimates_max_age: Duration,
+    /// How often contracts are checked for attestations, confirmations, and refunds. CETs
+    /// and refund transactions are broadcast on these checks. Defaults to 60 seconds.
+    pub periodic_check_interval: Duration,
+    /// How often the wallet syncs with the chain backend. Failed syncs back off up to five
+    /// minutes. Defaults to 10 seconds.
+    pub wallet_sync_interval: Duration,
+    /// How long syncs may fail before [crate::events::DdkEvent::WalletSyncFailing] is
+    /// emitted. Defaults to ten minutes.
+    pub wallet_sync_warning_after: Duration,
+    /// Unused scripts in a row that end the chain scan of [crate::DlcDevKit::recover_from_seed].
+    /// Defaults to 50.
+    pub recovery_stop_gap: usize,
+    /// How long offers, accepts, and other calls answered by the manager thread wait before
+    /// failing with [crate::DdkError::ManagerUnresponsive]. Signing large contracts takes a
+    /// while, so keep it generous. Defaults to five minutes.
+    pub manager_response_timeout: Duration,
+    /// Refund confirmed contracts on the periodic check once their refund locktime is reached
+    /// on chain. Without it contracts are refunded with [crate::DlcDevKit::refund_contract].
+    /// The DLC manager still refunds contracts itself once the locktime passes by the local
+    /// clock. Defaults to true.
+    pub auto_refund: bool,
+    /// How long an external signer set with [crate::builder::DdkBuilder::set_signer_backend]
+    /// has to sign a funding input before the contract message fails. Defaults to ten minutes.
+    pub external_signer_timeout: Duration,
+    /// Per-peer message rate and unanswered offer limits. Peers exceeding them are banned
+    /// for [PeerLimits::ban_duration]. Defaults to 5 messages per second with bursts of 50
+    /// and 20 pending offers.
+    pub peer_limits: PeerLimits,
+    /// How often the chain is polled for confirmations and counterparty broadcasts, and the
+    /// confirmations required before acting on them. Defaults to every 15 seconds, one
+    /// confirmation for funding transactions, and CETs as soon as they are in the mempool.
+    pub chain_watch: ChainWatchOptions,
+    /// How often transactions the node broadcast are checked and rebroadcast if they left the
+    /// mempool before confirming. Defaults to every minute, giving up after 10 rebroadcasts.
+    pub rebroadcast: RebroadcastOptions,
+    /// How often offers, accepts, and signs the counterparty has not acted on are resent.
+    /// Defaults to every minute, giving up after 30 sends or 24 hours.
+    pub outbound_retry: OutboundRetryOptions,
+    /// Confirmations a funding transaction needs before its contract is confirmed, by the
+    /// contract's total collateral. Defaults to 6 for every contract, like the DLC manager.
+    pub confirmation_policy: ConfirmationPolicy,
+    /// How long before a confirmed contract's oracle event matures
+    /// [crate::events::DdkEvent::ContractMaturing] is emitted, on the periodic check. Zero
+    /// disables the event. Defaults to 24 hours.
+    pub maturity_notice: Duration,
+    /// Run [crate::DdkStorage::verify_integrity] when the node is built and fail with
+    /// [crate::DdkError::StorageIntegrity] if it finds issues. Reads every stored contract, so
+    /// it slows down the start of nodes with many. Defaults to false.
+    pub verify_storage_on_start: bool,
+}
+
+impl Default for DdkConfig {
+    fn default() -> Self {
+        Self {
+            network: Network::Signet,
+            esplora_host: MUTINYNET_ESPLORA_HOST.to_string(),
+            esplora_options: EsploraOptions::default(),
+            expected_genesis_hash: None,
+            storage_path: DEFAULT_STORAGE_DIR.into(),
+            seed_config: SeedConfig::default(),
+            oracle_url: None,
+            transport: TransportOptions::default(),
+            channel_reserve_sats: DEFAULT_CHANNEL_RESERVE_SATS,
+            fiat_currency: "USD".to_string(),
+            risk_limits: RiskLimits::default(),
+            funding_broadcast_window: DEFAULT_FUNDING_BROADCAST_WINDOW,
+            announcement_cache_size: DEFAULT_ANNOUNCEMENT_CACHE_SIZE,
+            announcement_max_age: DEFAULT_ANNOUNCEMENT_MAX_AGE,
+            coin_selection: CoinSelectionStrategy::default(),
+            wallet_accounts: WalletAccounts::default(),
+            signer_vacuum: SignerVacuumOptions::default(),
+            archive_policy: None,
+            negotiation_timeouts: NegotiationTimeouts::default(),
+            message_workers: DEFAULT_MESSAGE_WORKERS,
+            fee_refresh_interval: DEFAULT_FEE_REFRESH_INTERVAL,
+            fee_estimates_max_age: DEFAULT_FEE_ESTIMATES_MAX_AGE,
+            periodic_check_interval: DEFAULT_PERIODIC_CHECK_INTERVAL,
+            wallet_sync_interval: DEFAULT_WALLET_SYNC_INTERVAL,
+            wallet_sync_warning_after: DEFAULT_WALLET_SYNC_WARNING_AFTER,
+            recovery_stop_gap: DEFAULT_RECOVERY_STOP_GAP,
+            manager_response_timeout: DEFAULT_MANAGER_RESPONSE_TIMEOUT,
+            auto_refund: true,
+            external_signer_timeout: DEFAULT_EXTERNAL_SIGNER_TIMEOUT,
+            peer_limits: PeerLimits::default(),
+            chain_watch: ChainWatchOptions::default(),
+            rebroadcast: RebroadcastOptions::default(),
+            outbound_retry: OutboundRetryOptions::default(),
+            confirmation_policy: ConfirmationPolicy::default(),
+            maturity_notice: DEFAULT_MATURITY_NOTICE,
+            verify_storage_on_start: false,
+        }
+    }
+}
+
+impl DdkConfig {
+    /// Defaults for a named chain, with its public esplora and genesis check.
+    pub fn for_chain(chain: ChainName) -> Self {
+        let expected_genesis_hash = match chain {
+            ChainName::Regtest => None,
+            chain => Some(chain.genesis_hash()),
+        };
+        Self {
+            network: chain.network(),
+            esplora_host: chain.esplora_host().to_string(),
+            expected_genesis_hash,
+            ..Default::default()
+        }
+    }
+}
+
+/// Directory of a network below a data directory, `data_dir/{network}`. Configs loaded with
+/// [DdkConfig::from_file] store everything there so networks never share a wallet.
+pub fn network_dir(data_dir: &Path, network: Network) -> PathBuf {
+    data_dir.join(network.to_string())
+}
+
+/// Transport settings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransportOptions {
+    /// Port the lightning transport listens on. Defaults to 1776.
+    pub listening_port: u16,
+}
+
+impl Default for TransportOptions {
+    fn default() -> Self {
+        Self {
+            listening_port: DEFAULT_LISTENING_PORT,
+        }
+    }
+}
+
+/// Seed configuration for DDK.
+#[derive(Debug, Clone)]
+pub enum SeedConfig {
+    /// Seed bytes
+    Bytes([u8; 64]),
+    /// File path to a seed.
+    File(String),
+    /// Platform supplied seed storage. Ex. a secure enclave.
+    KeyStorage(Arc<dyn KeyStorage>),
+    /// Directory of a seed file encrypted with a passphrase. A seed is generated on first boot.
+    EncryptedFile {
+        path: String,
+        passphrase_provider: Arc<dyn PassphraseProvider>,
+    },
+    /// BIP-39 mnemonic phrase and optional passphrase.
+    Mnemonic {
+        phrase: String,
+        passphrase: Option<String>,
+    },
+}
+
+impl fmt::Display for SeedConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::File(_) => write!(f, "file"),
+            Self::Bytes(_) => write!(f, "bytes"),
+            Self::KeyStorage(_) => write!(f, "key storage"),
+            Self::EncryptedFile { .. } => write!(f, "encrypted file"),
+            Self::Mnemonic { .. } => write!(f, "mnemonic"),
+        }
+    }
+}
+
+impl Default for SeedConfig {
+    fn default() -> Self {
+        Self::Bytes([0u8; 64])
+    }
+}
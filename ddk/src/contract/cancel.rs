@@ -0,0 +1,203 @@
+//! Withdraw an offer we sent before the counterparty accepts it, or reject one we received.
+//!
+//! The DLC protocol has no message to reject a contract offer. A cancelled offer is announced
+//! with the channel Reject message carrying the temporary contract id, and an accept that
+//! arrives for a cancelled offer is answered the same way instead of signed. The counterparty
+//! moves its side to rejected on receipt, see [counterparty_rejected_contract].
+use super::timeout::end_negotiation;
+use super::ContractState;
+use crate::events::{DdkEvent, EventBus};
+use crate::transport::outbox::{send_pending, Outbox};
+use crate::wallet::DlcDevKitWallet;
+use crate::{DdkBlockchain, DdkStorage};
+use bitcoin::secp256k1::PublicKey;
+use dlc_manager::contract::Contract;
+use dlc_manager::ContractId;
+use dlc_messages::channel::{ChannelMessage, Reject};
+use dlc_messages::Message;
+
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CancelError {
+    #[error("Contract not found.")]
+    NotFound,
+    #[error("Only offers we sent can be cancelled.")]
+    NotOfferParty,
+    #[error("Only unaccepted offers can be cancelled. state={0}")]
+    NotOffered(ContractState),
+}
+
+/// The rejected contract a cancelled offer moves to.
+pub fn cancelled_contract(contract: &Contract) -> Result<Contract, CancelError> {
+    match contract {
+        Contract::Offered(o) if o.is_offer_party => Ok(Contract::Rejected(o.clone())),
+        Contract::Offered(_) => Err(CancelError::NotOfferParty),
+        contract => Err(CancelError::NotOffered(contract.into())),
+    }
+}
+
+/// The rejected contract an offer moves to when we decline it.
+pub fn rejected_contract(contract: &Contract) -> Result<Contract, CancelError> {
+    match contract {
+        Contract::Offered(o) => Ok(Contract::Rejected(o.clone())),
+        contract => Err(CancelError::NotOffered(contract.into())),
+    }
+}
+
+/// The rejected contract a negotiation moves to when the counterparty rejects it: an offer
+/// they withdrew, or our accept of an offer they cancelled. An accepted contract goes back to
+/// its temporary id, like a timed out one.
+pub fn counterparty_rejected_contract(contract: &Contract) -> Option<Contract> {
+    match contract {
+        Contract::Offered(o) if !o.is_offer_party => Some(Contract::Rejected(o.clone())),
+        Contract::Accepted(a) => Some(Contract::Rejected(a.offered_contract.clone())),
+        _ => None,
+    }
+}
+
+/// Whether the stored contract is an offer we sent and then cancelled.
+pub fn is_cancelled_offer(contract: Option<&Contract>) -> bool {
+    matches!(contract, Some(Contract::Rejected(o)) if o.is_offer_party)
+}
+
+/// Tells the counterparty we are done with the offer `temporary_contract_id`. The DLC
+/// protocol has no contract reject message, so the channel Reject carries the temporary
+/// contract id. It is stored until the transport takes it, like other messages.
+pub(crate) fn send_reject<S: DdkStorage>(
+    storage: &S,
+    outbox: &Outbox,
+    counter_party: PublicKey,
+    temporary_contract_id: ContractId,
+) {
+    let reject = Reject { channel_id: temporary_contract_id };
+    send_pending(storage, outbox, counter_party, Message::Channel(ChannelMessage::Reject(reject)));
+}
+
+/// Ends our side of a negotiation `counter_party` rejected with [send_reject]: an offer they
+/// withdrew, or our accept of an offer they cancelled. Returns false when `id` is no contract
+/// negotiated with `counter_party`, e.g. because the Reject is for a channel.
+pub(crate) fn on_contract_reject<S: DdkStorage, B: DdkBlockchain>(
+    storage: &S,
+    wallet: &DlcDevKitWallet<S, B>,
+    events: &EventBus,
+    counter_party: PublicKey,
+    id: ContractId,
+) -> bool {
+    let contract = match storage.get_contract(&id) {
+        Ok(Some(contract)) => Some(contract),
+        // Our accept is stored under the contract id, the Reject names the offer.
+        _ => storage.get_contracts().ok().and_then(|contracts| {
+            contracts
+                .into_iter()
+                .find(|c| matches!(c, Contract::Accepted(a) if a.offered_contract.id == id))
+        }),
+    };
+    let Some(contract) = contract.filter(|c| c.get_counter_party_id() == counter_party) else {
+        return false;
+    };
+    let Some(rejected) = counterparty_rejected_contract(&contract) else {
+        // Already over, e.g. a second Reject for an offer and its late accept.
+        return true;
+    };
+    if let Err(e) = end_negotiation(storage, wallet, &contract, &rejected) {
+        tracing::error!(error = e.to_string(), "Could not reject negotiation.");
+        return false;
+    }
+    tracing::info!(
+        counter_party = counter_party.to_string(),
+        temporary_contract_id = hex::encode(id),
+        state = ContractState::from(&contract).to_string(),
+        "Counterparty rejected the negotiation."
+    );
+    events.emit(DdkEvent::OfferRejected {
+        contract_id: id,
+        reason: "Rejected by the counterparty.".to_string(),
+    });
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dlc_manager::contract::accepted_contract::AcceptedContract;
+    use dlc_manager::contract::offered_contract::OfferedContract;
+    use dlc_manager::contract::signed_contract::SignedContract;
+    use dlc_manager::contract::ser::Serializable;
+
+    fn deserialize_object<T: Serializable>(serialized: &[u8]) -> T {
+        let mut cursor = ::lightning::io::Cursor::new(&serialized);
+        T::deserialize(&mut cursor).unwrap()
+    }
+
+    fn offer(is_offer_party: bool) -> Contract {
+        let mut offered: OfferedContract =
+            deserialize_object(include_bytes!("../../tests/data/dlc_storage/sled/Offered"));
+        offered.is_offer_party = is_offer_party;
+        Contract::Offered(offered)
+    }
+
+    #[test]
+    fn cancel_then_late_accept() {
+        let offer = offer(true);
+        assert!(!is_cancelled_offer(Some(&offer)));
+
+        let cancelled = cancelled_contract(&offer).unwrap();
+        assert_eq!(ContractState::from(&cancelled), ContractState::Rejected);
+        assert_eq!(cancelled.get_id(), offer.get_id());
+        assert!(crate::contract::validate_transition(&offer, &cancelled).is_ok());
+        assert!(is_cancelled_offer(Some(&cancelled)));
+        assert!(!is_cancelled_offer(None));
+    }
+
+    #[test]
+    fn reject_incoming_offer() {
+        let offer = offer(false);
+        let rejected = rejected_contract(&offer).unwrap();
+        assert_eq!(ContractState::from(&rejected), ContractState::Rejected);
+        assert_eq!(rejected.get_id(), offer.get_id());
+        assert!(!is_cancelled_offer(Some(&rejected)));
+
+        assert_eq!(
+            rejected_contract(&rejected).unwrap_err(),
+            CancelError::NotOffered(ContractState::Rejected)
+        );
+    }
+
+    #[test]
+    fn counterparty_reject_of_incoming_offer_or_our_accept() {
+        let incoming = offer(false);
+        let rejected = counterparty_rejected_contract(&incoming).unwrap();
+        assert_eq!(ContractState::from(&rejected), ContractState::Rejected);
+        assert_eq!(rejected.get_id(), incoming.get_id());
+
+        let accepted: AcceptedContract =
+            deserialize_object(include_bytes!("../../tests/data/dlc_storage/sled/Accepted"));
+        let temporary_id = accepted.offered_contract.id;
+        let rejected = counterparty_rejected_contract(&Contract::Accepted(accepted)).unwrap();
+        assert_eq!(ContractState::from(&rejected), ContractState::Rejected);
+        assert_eq!(rejected.get_id(), temporary_id);
+
+        // Our own offer is only rejected by us.
+        assert!(counterparty_rejected_contract(&offer(true)).is_none());
+    }
+
+    #[test]
+    fn cannot_cancel_progressed_or_incoming() {
+        let signed = Contract::Signed(deserialize_object::<SignedContract>(include_bytes!(
+            "../../tests/data/dlc_storage/sled/Signed"
+        )));
+        assert_eq!(
+            cancelled_contract(&signed).unwrap_err(),
+            CancelError::NotOffered(ContractState::Signed)
+        );
+        assert_eq!(
+            cancelled_contract(&offer(false)).unwrap_err(),
+            CancelError::NotOfferParty
+        );
+
+        // A rejected incoming offer is not ours to drop accepts for.
+        let Contract::Offered(incoming) = offer(false) else {
+            unreachable!()
+        };
+        assert!(!is_cancelled_offer(Some(&Contract::Rejected(incoming))));
+    }
+}
@@ -0,0 +1,185 @@
+//! Close a confirmed contract with oracle attestations on demand, instead of waiting for the
+//! periodic check. Useful when the counterparty is offline after the oracle attested.
+use super::ContractState;
+use crate::oracle::verify::AttestationError;
+use bitcoin::key::XOnlyPublicKey;
+use bitcoin::Txid;
+use dlc_manager::contract::signed_contract::SignedContract;
+use dlc_manager::contract::Contract;
+use dlc_messages::oracle_msgs::OracleAttestation;
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum CloseError {
+    #[error("Contract not found.")]
+    NotFound,
+    #[error("Only confirmed contracts can be closed. state={0}")]
+    NotConfirmed(ContractState),
+    #[error(
+        "Event {event_id} is not attested yet. Need {threshold} attestations, have {attested}."
+    )]
+    NotAttested {
+        event_id: String,
+        threshold: usize,
+        attested: usize,
+    },
+    #[error("Attestation of event {event_id} from oracle {oracle} is invalid. {error}")]
+    InvalidAttestation {
+        oracle: XOnlyPublicKey,
+        event_id: String,
+        error: AttestationError,
+    },
+    #[error("Could not close contract. {0}")]
+    Manager(String),
+}
+
+/// The confirmed contract to close.
+pub fn confirmed_contract(contract: Option<&Contract>) -> Result<&SignedContract, CloseError> {
+    match contract {
+        Some(Contract::Confirmed(signed)) => Ok(signed),
+        Some(contract) => Err(CloseError::NotConfirmed(contract.into())),
+        None => Err(CloseError::NotFound),
+    }
+}
+
+/// Pair `attestations` with the index of their oracle in the announcements of the first
+/// contract info they reach the threshold of. The manager closes with those indexes.
+pub fn indexed_attestations(
+    contract: &SignedContract,
+    attestations: &[OracleAttestation],
+) -> Result<Vec<(usize, OracleAttestation)>, CloseError> {
+    let contract_info = &contract.accepted_contract.offered_contract.contract_info;
+    let mut best: Option<(usize, usize, &str)> = None;
+    for info in contract_info {
+        let indexed = info
+            .oracle_announcements
+            .iter()
+            .enumerate()
+            .filter_map(|(index, announcement)| {
+                attestations
+                    .iter()
+                    .find(|a| a.oracle_public_key == announcement.oracle_public_key)
+                    .map(|attestation| (index, attestation.clone()))
+            })
+            .collect::<Vec<_>>();
+        if !indexed.is_empty() && indexed.len() >= info.threshold {
+            return Ok(indexed);
+        }
+        let event_id = info
+            .oracle_announcements
+            .first()
+            .map(|a| a.oracle_event.event_id.as_str())
+            .unwrap_or_default();
+        match best {
+            Some((attested, _, _)) if attested >= indexed.len() => {}
+            _ => best = Some((indexed.len(), info.threshold, event_id)),
+        }
+    }
+    let (attested, threshold, event_id) = best.unwrap_or((0, 1, ""));
+    Err(CloseError::NotAttested {
+        event_id: event_id.to_string(),
+        threshold,
+        attested,
+    })
+}
+
+/// Oracles and event ids whose attestations can close `contract`.
+pub fn attesting_oracles(contract: &SignedContract) -> Vec<(XOnlyPublicKey, String)> {
+    let mut oracles = Vec::new();
+    for info in &contract.accepted_contract.offered_contract.contract_info {
+        for announcement in &info.oracle_announcements {
+            let oracle = (
+                announcement.oracle_public_key,
+                announcement.oracle_event.event_id.clone(),
+            );
+            if !oracles.contains(&oracle) {
+                oracles.push(oracle);
+            }
+        }
+    }
+    oracles
+}
+
+/// Txid of the CET the manager broadcast for a closed contract.
+pub fn cet_txid(contract: &Contract) -> Option<Txid> {
+    match contract {
+        Contract::PreClosed(c) => Some(c.signed_cet.compute_txid()),
+        Contract::Closed(c) => c.signed_cet.as_ref().map(|cet| cet.compute_txid()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::secp256k1::schnorr::Signature;
+    use dlc_manager::contract::ser::Serializable;
+
+    fn deserialize_object<T: Serializable>(serialized: &[u8]) -> T {
+        let mut cursor = ::lightning::io::Cursor::new(&serialized);
+        T::deserialize(&mut cursor).unwrap()
+    }
+
+    fn confirmed() -> SignedContract {
+        deserialize_object(include_bytes!(
+            "../../tests/data/dlc_storage/sled/Confirmed"
+        ))
+    }
+
+    fn attestation(oracle_public_key: XOnlyPublicKey) -> OracleAttestation {
+        OracleAttestation {
+            oracle_public_key,
+            signatures: vec![Signature::from_slice(&[1u8; 64]).unwrap()],
+            outcomes: vec!["1".to_string()],
+        }
+    }
+
+    #[test]
+    fn only_confirmed_contracts_close() {
+        let signed = confirmed();
+        let contract = Contract::Confirmed(signed.clone());
+        assert!(confirmed_contract(Some(&contract)).is_ok());
+        assert_eq!(confirmed_contract(None).unwrap_err(), CloseError::NotFound);
+        assert_eq!(
+            confirmed_contract(Some(&Contract::Signed(signed))).unwrap_err(),
+            CloseError::NotConfirmed(ContractState::Signed)
+        );
+    }
+
+    #[test]
+    fn attestations_are_indexed_by_oracle() {
+        let contract = confirmed();
+        let oracles = attesting_oracles(&contract);
+        assert!(!oracles.is_empty());
+
+        let attestations = oracles
+            .iter()
+            .map(|(oracle, _)| attestation(*oracle))
+            .collect::<Vec<_>>();
+        let indexed = indexed_attestations(&contract, &attestations).unwrap();
+        let announcements =
+            &contract.accepted_contract.offered_contract.contract_info[0].oracle_announcements;
+        for (index, attestation) in indexed {
+            assert_eq!(
+                announcements[index].oracle_public_key,
+                attestation.oracle_public_key
+            );
+        }
+    }
+
+    #[test]
+    fn missing_attestations_name_the_event() {
+        let contract = confirmed();
+        let (_, event_id) = attesting_oracles(&contract).remove(0);
+        match indexed_attestations(&contract, &[]).unwrap_err() {
+            CloseError::NotAttested {
+                event_id: missing,
+                attested,
+                ..
+            } => {
+                assert_eq!(missing, event_id);
+                assert_eq!(attested, 0);
+            }
+            e => panic!("unexpected error {e}"),
+        }
+    }
+}
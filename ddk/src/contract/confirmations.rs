@@ -0,0 +1,239 @@
+//! Confirmations a funding transaction needs before its contract counts as confirmed.
+//!
+//! The DLC manager confirms every contract at six confirmations. Larger contracts are worth
+//! more to a miner reorganizing the chain, so a [ConfirmationPolicy] asks for more of them.
+//! A single contract can ask for its own number with [AcceptanceParams], down to zero for
+//! counterparties trusted not to double-spend the funding transaction.
+use crate::DdkBlockchain;
+use bitcoin::{Transaction, Txid};
+use dlc_manager::contract::signed_contract::SignedContract;
+use dlc_manager::contract::{Contract, FailedSignContract};
+use dlc_manager::error::Error as ManagerError;
+use dlc_messages::{CetAdaptorSignatures, SignDlc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Confirmations the DLC manager confirms contracts at.
+pub const DEFAULT_REQUIRED_CONFIRMATIONS: u32 = 6;
+
+/// Confirmations required per contract size.
+///
+/// A contract needs the confirmations of the highest threshold its total collateral reaches,
+/// or `base` when it reaches none.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfirmationPolicy {
+    /// Confirmations for contracts below every threshold. Defaults to 6.
+    pub base: u32,
+    /// Minimum total collateral in sats to the confirmations required from it on.
+    pub thresholds: BTreeMap<u64, u32>,
+}
+
+impl Default for ConfirmationPolicy {
+    fn default() -> Self {
+        ConfirmationPolicy::new(DEFAULT_REQUIRED_CONFIRMATIONS)
+    }
+}
+
+impl ConfirmationPolicy {
+    /// A policy requiring `base` confirmations regardless of size.
+    pub fn new(base: u32) -> ConfirmationPolicy {
+        ConfirmationPolicy {
+            base,
+            thresholds: BTreeMap::new(),
+        }
+    }
+
+    /// Require `confirmations` for contracts with at least `min_collateral` sats.
+    pub fn with_threshold(mut self, min_collateral: u64, confirmations: u32) -> Self {
+        self.thresholds.insert(min_collateral, confirmations);
+        self
+    }
+
+    /// Confirmations required for a contract with `total_collateral` sats.
+    pub fn required(&self, total_collateral: u64) -> u32 {
+        self.thresholds
+            .range(..=total_collateral)
+            .next_back()
+            .map_or(self.base, |(_, confirmations)| *confirmations)
+    }
+
+    /// Confirmations required for the funding transaction of `contract`.
+    pub fn required_for(&self, contract: &SignedContract) -> u32 {
+        self.required(contract.accepted_contract.offered_contract.total_collateral)
+    }
+
+    /// Confirmations required for `contract`, from its `acceptance` parameters if it has them.
+    pub fn required_with(
+        &self,
+        contract: &SignedContract,
+        acceptance: Option<AcceptanceParams>,
+    ) -> u32 {
+        acceptance.map_or_else(
+            || self.required_for(contract),
+            |params| params.min_confirmations,
+        )
+    }
+}
+
+/// Confirmations one contract needs, in place of the [ConfirmationPolicy]. Set them with
+/// [crate::DlcDevKit::send_dlc_offer_with_params] or
+/// [crate::contract::progress::AcceptOptions::acceptance]. They are our own and not sent to
+/// the counterparty, which confirms the contract by its own policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AcceptanceParams {
+    /// Confirmations before the contract counts as confirmed. Zero confirms it as soon as it is
+    /// signed. Until the funding transaction is in a block the contract fails if one of its
+    /// inputs is spent elsewhere, see [crate::events::DdkEvent::FundingDoubleSpent].
+    pub min_confirmations: u32,
+}
+
+impl AcceptanceParams {
+    pub fn new(min_confirmations: u32) -> AcceptanceParams {
+        AcceptanceParams { min_confirmations }
+    }
+
+    /// Confirm the contract with its funding transaction still in the mempool.
+    pub fn zero_conf() -> AcceptanceParams {
+        AcceptanceParams::new(0)
+    }
+}
+
+/// What the policy does to a contract after the manager's periodic check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ConfirmationChange {
+    /// The funding transaction has the required confirmations.
+    Confirm,
+    /// The manager confirmed the contract before it had the required confirmations.
+    Unconfirm,
+    /// The funding transaction of a confirmed contract is no longer in a block.
+    Reorged,
+}
+
+/// The change for a contract that was `was_confirmed` before the check and is `is_confirmed`
+/// after it, with `confirmations` of the `required`. None leaves the contract as it is.
+pub(crate) fn confirmation_change(
+    was_confirmed: bool,
+    is_confirmed: bool,
+    confirmations: u32,
+    required: u32,
+) -> Option<ConfirmationChange> {
+    match (was_confirmed, is_confirmed) {
+        // A confirmed contract keeps its state while the funding transaction stays in a block,
+        // even if the policy asks for more now. Zero-conf contracts never needed a block.
+        (true, true) if confirmations == 0 && required > 0 => Some(ConfirmationChange::Reorged),
+        (false, true) if confirmations < required => Some(ConfirmationChange::Unconfirm),
+        (false, false) if confirmations >= required => Some(ConfirmationChange::Confirm),
+        _ => None,
+    }
+}
+
+/// The transaction that spent an input of the unconfirmed `fund` transaction in its place.
+pub(crate) fn conflicting_spend<B: DdkBlockchain>(
+    blockchain: &B,
+    fund: &Transaction,
+) -> Result<Option<Txid>, ManagerError> {
+    let txid = fund.compute_txid();
+    for input in &fund.input {
+        if let Some(spending) = blockchain.find_spending_transaction(&input.previous_output)? {
+            let spending_txid = spending.compute_txid();
+            if spending_txid != txid {
+                return Ok(Some(spending_txid));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// A zero-conf contract whose funding transaction was double-spent by `conflicting_txid`.
+/// No sign message is missing, the placeholder only records why the contract failed.
+pub(crate) fn double_spent_contract(signed: &SignedContract, conflicting_txid: Txid) -> Contract {
+    let sign_message = SignDlc {
+        protocol_version: 1,
+        contract_id: signed.accepted_contract.get_contract_id(),
+        cet_adaptor_signatures: CetAdaptorSignatures {
+            ecdsa_adaptor_signatures: vec![],
+        },
+        refund_signature: signed.offer_refund_signature,
+        funding_signatures: signed.funding_signatures.clone(),
+    };
+    Contract::FailedSign(FailedSignContract {
+        accepted_contract: signed.accepted_contract.clone(),
+        sign_message,
+        error_message: format!(
+            "Funding transaction was double-spent by {} before it confirmed.",
+            conflicting_txid
+        ),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::Hash;
+    use dlc_manager::contract::ser::Serializable;
+
+    #[test]
+    fn thresholds_apply_from_their_collateral_on() {
+        let policy = ConfirmationPolicy::new(1)
+            .with_threshold(1_000_000, 3)
+            .with_threshold(100_000_000, 12);
+        assert_eq!(policy.required(50_000), 1);
+        assert_eq!(policy.required(999_999), 1);
+        assert_eq!(policy.required(1_000_000), 3);
+        assert_eq!(policy.required(99_999_999), 3);
+        assert_eq!(policy.required(500_000_000), 12);
+        assert_eq!(ConfirmationPolicy::default().required(500_000_000), 6);
+    }
+
+    #[test]
+    fn changes() {
+        use ConfirmationChange::*;
+        assert_eq!(confirmation_change(false, false, 2, 3), None);
+        assert_eq!(confirmation_change(false, false, 3, 3), Some(Confirm));
+        assert_eq!(confirmation_change(false, true, 6, 12), Some(Unconfirm));
+        assert_eq!(confirmation_change(false, true, 12, 12), None);
+        assert_eq!(confirmation_change(true, true, 2, 12), None);
+        assert_eq!(confirmation_change(true, true, 0, 12), Some(Reorged));
+        assert_eq!(confirmation_change(true, false, 0, 3), None);
+        assert_eq!(confirmation_change(false, false, 0, 0), Some(Confirm));
+        assert_eq!(confirmation_change(true, true, 0, 0), None);
+    }
+
+    fn confirmed() -> SignedContract {
+        let mut cursor = ::lightning::io::Cursor::new(include_bytes!(
+            "../../tests/data/dlc_storage/sled/Confirmed"
+        ));
+        SignedContract::deserialize(&mut cursor).unwrap()
+    }
+
+    #[test]
+    fn acceptance_params_replace_the_policy() {
+        let signed = confirmed();
+        let policy = ConfirmationPolicy::new(3);
+        assert_eq!(policy.required_with(&signed, None), 3);
+        assert_eq!(
+            policy.required_with(&signed, Some(AcceptanceParams::zero_conf())),
+            0
+        );
+        assert_eq!(
+            policy.required_with(&signed, Some(AcceptanceParams::new(12))),
+            12
+        );
+    }
+
+    #[test]
+    fn double_spent_contracts_fail() {
+        let signed = confirmed();
+        let failed = double_spent_contract(&signed, Txid::all_zeros());
+        assert_eq!(
+            failed.get_id(),
+            Contract::Confirmed(signed.clone()).get_id()
+        );
+        assert!(crate::contract::validate_transition(
+            &Contract::Confirmed(signed.clone()),
+            &failed
+        )
+        .is_ok());
+        assert!(crate::contract::validate_transition(&Contract::Signed(signed), &failed).is_ok());
+    }
+}
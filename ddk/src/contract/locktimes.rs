@@ -0,0 +1,251 @@
+//! Locktime helpers for CET and refund transactions.
+//!
+//! Bitcoin reads a locktime below [LOCKTIME_THRESHOLD] as a block height and anything above
+//! as a unix timestamp, so a wrong unit silently produces a locktime that is years off.
+use std::fmt;
+use std::time::Duration;
+
+/// Locktimes at or above this value are unix timestamps, below are block heights.
+pub const LOCKTIME_THRESHOLD: u32 = 500_000_000;
+/// Delay between event maturity and the refund locktime. Matches the dlc_manager default.
+pub const DEFAULT_REFUND_DELAY: Duration = Duration::from_secs(60 * 60 * 24 * 7);
+/// Height locktimes further ahead of the tip than this (about ten years of blocks) are
+/// almost certainly timestamps given in the wrong unit.
+pub const MAX_HEIGHT_AHEAD: u32 = 525_600;
+
+/// A transaction locktime in either unit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Locktime {
+    Height(u32),
+    Time(u32),
+}
+
+impl Locktime {
+    /// A block height locktime. Fails for values that would be read as a timestamp.
+    pub fn height(height: u32) -> Result<Locktime, LocktimeError> {
+        if height >= LOCKTIME_THRESHOLD {
+            return Err(LocktimeError::WrongUnit {
+                which: LocktimeKind::Cet,
+                value: height,
+            });
+        }
+        Ok(Locktime::Height(height))
+    }
+
+    /// A unix timestamp locktime. Fails for values that would be read as a block height.
+    pub fn time(unix: u32) -> Result<Locktime, LocktimeError> {
+        if unix < LOCKTIME_THRESHOLD {
+            return Err(LocktimeError::WrongUnit {
+                which: LocktimeKind::Cet,
+                value: unix,
+            });
+        }
+        Ok(Locktime::Time(unix))
+    }
+
+    /// Interpret a raw locktime the way consensus does.
+    pub fn from_consensus(value: u32) -> Locktime {
+        if value < LOCKTIME_THRESHOLD {
+            Locktime::Height(value)
+        } else {
+            Locktime::Time(value)
+        }
+    }
+
+    pub fn to_consensus_u32(self) -> u32 {
+        match self {
+            Locktime::Height(value) | Locktime::Time(value) => value,
+        }
+    }
+
+    fn same_unit(&self, other: &Locktime) -> bool {
+        matches!(
+            (self, other),
+            (Locktime::Height(_), Locktime::Height(_)) | (Locktime::Time(_), Locktime::Time(_))
+        )
+    }
+
+    fn is_reached(&self, tip: &ChainTip) -> bool {
+        match *self {
+            Locktime::Height(height) => height <= tip.height,
+            Locktime::Time(time) => time <= tip.time,
+        }
+    }
+}
+
+impl fmt::Display for Locktime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Locktime::Height(height) => write!(f, "height {}", height),
+            Locktime::Time(time) => write!(f, "time {}", time),
+        }
+    }
+}
+
+/// The current chain height and time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChainTip {
+    pub height: u32,
+    pub time: u32,
+}
+
+/// Which locktime of a contract is invalid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LocktimeKind {
+    Cet,
+    Refund,
+}
+
+impl fmt::Display for LocktimeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LocktimeKind::Cet => write!(f, "cet"),
+            LocktimeKind::Refund => write!(f, "refund"),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LocktimeError {
+    #[error("The {which} locktime {value} is in the wrong unit.")]
+    WrongUnit { which: LocktimeKind, value: u32 },
+    #[error("The {which} locktime {locktime} has already passed.")]
+    InPast { which: LocktimeKind, locktime: Locktime },
+    #[error("The refund locktime {refund} is not after the cet locktime {cet}.")]
+    RefundBeforeMaturity { cet: Locktime, refund: Locktime },
+    #[error("The cet locktime {cet} and refund locktime {refund} use different units.")]
+    MixedUnits { cet: Locktime, refund: Locktime },
+}
+
+/// Locktimes for a new contract.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SuggestedLocktimes {
+    pub cet_locktime: Locktime,
+    pub refund_locktime: Locktime,
+}
+
+/// CETs unlock at event maturity and the refund `refund_delay` later. Events that already
+/// matured are counted from the tip so the refund is never immediately spendable.
+pub fn suggest_locktimes(
+    event_maturity: u32,
+    current_tip: ChainTip,
+    refund_delay: Duration,
+) -> SuggestedLocktimes {
+    let maturity = event_maturity.max(current_tip.time).max(LOCKTIME_THRESHOLD);
+    let refund_delay = u32::try_from(refund_delay.as_secs()).unwrap_or(u32::MAX);
+    SuggestedLocktimes {
+        cet_locktime: Locktime::Time(maturity),
+        refund_locktime: Locktime::Time(maturity.saturating_add(refund_delay)),
+    }
+}
+
+/// Validate the raw locktimes of a contract against the chain tip.
+pub fn validate_locktimes(
+    cet_locktime: u32,
+    refund_locktime: u32,
+    tip: &ChainTip,
+) -> Result<(), LocktimeError> {
+    let cet = Locktime::from_consensus(cet_locktime);
+    let refund = Locktime::from_consensus(refund_locktime);
+
+    for (which, locktime) in [(LocktimeKind::Cet, cet), (LocktimeKind::Refund, refund)] {
+        if let Locktime::Height(height) = locktime {
+            if height > tip.height.saturating_add(MAX_HEIGHT_AHEAD) {
+                return Err(LocktimeError::WrongUnit {
+                    which,
+                    value: height,
+                });
+            }
+        }
+    }
+    if !cet.same_unit(&refund) {
+        return Err(LocktimeError::MixedUnits { cet, refund });
+    }
+    if refund.to_consensus_u32() <= cet.to_consensus_u32() {
+        return Err(LocktimeError::RefundBeforeMaturity { cet, refund });
+    }
+    if refund.is_reached(tip) {
+        return Err(LocktimeError::InPast {
+            which: LocktimeKind::Refund,
+            locktime: refund,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIP: ChainTip = ChainTip {
+        height: 850_000,
+        time: 1_720_000_000,
+    };
+
+    #[test]
+    fn constructors_check_units() {
+        assert_eq!(Locktime::height(850_000), Ok(Locktime::Height(850_000)));
+        assert!(Locktime::height(1_720_000_000).is_err());
+        assert_eq!(Locktime::time(1_720_000_000), Ok(Locktime::Time(1_720_000_000)));
+        assert!(Locktime::time(850_000).is_err());
+        assert_eq!(Locktime::from_consensus(LOCKTIME_THRESHOLD), Locktime::Time(LOCKTIME_THRESHOLD));
+        assert_eq!(Locktime::from_consensus(LOCKTIME_THRESHOLD - 1).to_consensus_u32(), LOCKTIME_THRESHOLD - 1);
+    }
+
+    #[test]
+    fn suggestion_math() {
+        let maturity = TIP.time + 3600;
+        let suggested = suggest_locktimes(maturity, TIP, DEFAULT_REFUND_DELAY);
+        assert_eq!(suggested.cet_locktime, Locktime::Time(maturity));
+        assert_eq!(suggested.refund_locktime, Locktime::Time(maturity + 604_800));
+        assert!(validate_locktimes(maturity, maturity + 604_800, &TIP).is_ok());
+
+        // Matured events count from the tip.
+        let suggested = suggest_locktimes(TIP.time - 3600, TIP, Duration::from_secs(60));
+        assert_eq!(suggested.cet_locktime, Locktime::Time(TIP.time));
+        assert_eq!(suggested.refund_locktime, Locktime::Time(TIP.time + 60));
+    }
+
+    #[test]
+    fn refund_in_past() {
+        assert_eq!(
+            validate_locktimes(TIP.time - 200, TIP.time - 100, &TIP),
+            Err(LocktimeError::InPast {
+                which: LocktimeKind::Refund,
+                locktime: Locktime::Time(TIP.time - 100),
+            })
+        );
+        assert!(matches!(
+            validate_locktimes(TIP.height - 10, TIP.height, &TIP),
+            Err(LocktimeError::InPast { .. })
+        ));
+    }
+
+    #[test]
+    fn refund_before_maturity() {
+        assert!(matches!(
+            validate_locktimes(TIP.time + 100, TIP.time + 100, &TIP),
+            Err(LocktimeError::RefundBeforeMaturity { .. })
+        ));
+        assert!(matches!(
+            validate_locktimes(TIP.height + 100, TIP.height + 10, &TIP),
+            Err(LocktimeError::RefundBeforeMaturity { .. })
+        ));
+    }
+
+    #[test]
+    fn height_time_confusion() {
+        // A timestamp divided by ten lands below the threshold and reads as a far off height.
+        assert_eq!(
+            validate_locktimes(TIP.height + 10, 172_000_000, &TIP),
+            Err(LocktimeError::WrongUnit {
+                which: LocktimeKind::Refund,
+                value: 172_000_000,
+            })
+        );
+        assert!(matches!(
+            validate_locktimes(TIP.height + 10, TIP.time + 100, &TIP),
+            Err(LocktimeError::MixedUnits { .. })
+        ));
+    }
+}
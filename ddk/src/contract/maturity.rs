@@ -0,0 +1,189 @@
+//! Maturities of confirmed contracts, so operators can make sure the oracles are reachable
+//! and the wallet is funded before contracts settle.
+use crate::events::DdkEvent;
+use bitcoin::secp256k1::PublicKey;
+use dlc_manager::contract::signed_contract::SignedContract;
+use dlc_manager::contract::Contract;
+use dlc_manager::ContractId;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+/// Default time before maturity that [DdkEvent::ContractMaturing] is emitted.
+pub const DEFAULT_MATURITY_NOTICE: Duration = Duration::from_secs(60 * 60 * 24);
+
+/// A confirmed contract and the oracle event it matures on.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MaturityInfo {
+    /// Hex encoded contract id.
+    pub contract_id: String,
+    pub counterparty: PublicKey,
+    /// Our collateral in sats.
+    pub collateral: u64,
+    pub total_collateral: u64,
+    /// Event id of the earliest maturing oracle event.
+    pub event_id: String,
+    /// Earliest maturity of the oracle events, as a unix timestamp.
+    pub maturity: u32,
+}
+
+/// The earliest maturing oracle event of a contract. None for contracts without announcements.
+pub fn maturity_info(contract: &SignedContract) -> Option<MaturityInfo> {
+    let offered = &contract.accepted_contract.offered_contract;
+    let event = offered
+        .contract_info
+        .iter()
+        .flat_map(|info| info.oracle_announcements.iter())
+        .map(|announcement| &announcement.oracle_event)
+        .min_by_key(|event| event.event_maturity_epoch)?;
+    let collateral = if offered.is_offer_party {
+        offered.offer_params.collateral
+    } else {
+        offered.total_collateral - offered.offer_params.collateral
+    };
+    Some(MaturityInfo {
+        contract_id: hex::encode(contract.accepted_contract.get_contract_id()),
+        counterparty: offered.counter_party,
+        collateral,
+        total_collateral: offered.total_collateral,
+        event_id: event.event_id.clone(),
+        maturity: event.event_maturity_epoch,
+    })
+}
+
+/// Confirmed contracts maturing before `now + within` (unix seconds), earliest first. Contracts
+/// past maturity that are not settled yet are included, they need the oracle the most.
+pub fn upcoming_maturities(
+    contracts: &[SignedContract],
+    now: u64,
+    within: Duration,
+) -> Vec<MaturityInfo> {
+    let until = now.saturating_add(within.as_secs());
+    let mut upcoming = contracts
+        .iter()
+        .filter_map(maturity_info)
+        .filter(|info| info.maturity as u64 <= until)
+        .collect::<Vec<_>>();
+    upcoming.sort_by_key(|info| info.maturity);
+    upcoming
+}
+
+/// Tells when confirmed contracts come within `notice` of their maturity. The maturity of each
+/// contract is extracted once and cached while the contract stays confirmed.
+#[derive(Debug)]
+pub struct MaturityWatch {
+    notice: Duration,
+    maturities: HashMap<ContractId, Option<MaturityInfo>>,
+    notified: HashSet<ContractId>,
+}
+
+impl MaturityWatch {
+    /// A zero `notice` never notifies.
+    pub fn new(notice: Duration) -> MaturityWatch {
+        MaturityWatch {
+            notice,
+            maturities: HashMap::new(),
+            notified: HashSet::new(),
+        }
+    }
+
+    /// [DdkEvent::ContractMaturing] for the confirmed contracts that came within the notice of
+    /// their maturity since the last check. Each contract is reported once per process, and not
+    /// at all when it is already past maturity.
+    pub fn check(&mut self, contracts: &[Contract], now: u64) -> Vec<DdkEvent> {
+        let mut confirmed = HashSet::new();
+        let mut events = vec![];
+        for contract in contracts {
+            let Contract::Confirmed(signed) = contract else {
+                continue;
+            };
+            let contract_id = contract.get_id();
+            confirmed.insert(contract_id);
+            let Some(info) = self
+                .maturities
+                .entry(contract_id)
+                .or_insert_with(|| maturity_info(signed))
+            else {
+                continue;
+            };
+            let maturity = info.maturity as u64;
+            if now < maturity
+                && maturity <= now.saturating_add(self.notice.as_secs())
+                && self.notified.insert(contract_id)
+            {
+                events.push(DdkEvent::ContractMaturing {
+                    contract_id,
+                    event_id: info.event_id.clone(),
+                    maturity: info.maturity,
+                });
+            }
+        }
+        self.maturities.retain(|id, _| confirmed.contains(id));
+        self.notified.retain(|id| confirmed.contains(id));
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dlc_manager::contract::ser::Serializable;
+
+    fn confirmed() -> SignedContract {
+        let mut cursor = ::lightning::io::Cursor::new(include_bytes!(
+            "../../tests/data/dlc_storage/sled/Confirmed"
+        ));
+        SignedContract::deserialize(&mut cursor).unwrap()
+    }
+
+    #[test]
+    fn maturities_within_the_window() {
+        let contract = confirmed();
+        let info = maturity_info(&contract).unwrap();
+        let offered = &contract.accepted_contract.offered_contract;
+        assert_eq!(info.total_collateral, offered.total_collateral);
+        assert!(info.collateral <= info.total_collateral);
+        let maturity = info.maturity as u64;
+
+        let within = Duration::from_secs(3600);
+        let contracts = [contract];
+        assert_eq!(
+            upcoming_maturities(&contracts, maturity - 3600, within),
+            vec![info.clone()]
+        );
+        assert!(upcoming_maturities(&contracts, maturity - 3601, within).is_empty());
+        assert_eq!(
+            upcoming_maturities(&contracts, maturity + 1, within),
+            vec![info]
+        );
+    }
+
+    #[test]
+    fn maturing_contracts_are_reported_once() {
+        let contract = Contract::Confirmed(confirmed());
+        let info = maturity_info(&confirmed()).unwrap();
+        let maturity = info.maturity as u64;
+        let mut watch = MaturityWatch::new(Duration::from_secs(60));
+        let contracts = [contract.clone()];
+
+        assert!(watch.check(&contracts, maturity - 61).is_empty());
+        assert_eq!(
+            watch.check(&contracts, maturity - 60),
+            vec![DdkEvent::ContractMaturing {
+                contract_id: contract.get_id(),
+                event_id: info.event_id,
+                maturity: info.maturity,
+            }]
+        );
+        assert!(watch.check(&contracts, maturity - 30).is_empty());
+
+        // Contracts that are no longer confirmed are forgotten.
+        assert!(watch.check(&[], maturity - 30).is_empty());
+        assert!(watch.maturities.is_empty() && watch.notified.is_empty());
+        // Matured contracts are settled, not maturing.
+        assert!(watch.check(&contracts, maturity).is_empty());
+        assert!(MaturityWatch::new(Duration::ZERO)
+            .check(&contracts, maturity - 1)
+            .is_empty());
+    }
+}
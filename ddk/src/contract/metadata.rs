@@ -0,0 +1,61 @@
+//! Application data attached to a contract, stored next to it without changing the
+//! [Contract](dlc_manager::contract::Contract) type.
+use crate::contract::confirmations::AcceptanceParams;
+use crate::contract::timeout::NegotiationClock;
+use crate::contract::FundingBroadcastRole;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Labels and tags an application keeps for a contract. Set it on the temporary id of an offer
+/// and it follows the contract to its final id once the offer is accepted.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContractMetadata {
+    /// Human readable name shown in contract lists.
+    pub label: Option<String>,
+    /// When the metadata was created, as a unix timestamp.
+    pub created_at: u64,
+    /// Free form tags, e.g. the order id of the trade that opened the contract.
+    #[serde(default)]
+    pub tags: BTreeMap<String, serde_json::Value>,
+    pub notes: Option<String>,
+    /// Confirmations the contract needs in place of the confirmation policy. Kept when the
+    /// metadata is replaced with metadata that has none.
+    #[serde(default)]
+    pub acceptance: Option<AcceptanceParams>,
+    /// Our part in broadcasting the funding transaction, recorded once the contract is
+    /// signed. Kept when the metadata is replaced.
+    #[serde(default)]
+    pub funding_broadcast_role: Option<FundingBroadcastRole>,
+    /// How long the negotiation has waited on the counterparty, while it is offered or
+    /// accepted. Kept when the metadata is replaced.
+    #[serde(default)]
+    pub negotiation: Option<NegotiationClock>,
+    /// The offer was received in maintenance mode and held instead of going to the offer
+    /// policy. Kept when the metadata is replaced.
+    #[serde(default)]
+    pub held_for_maintenance: bool,
+}
+
+impl ContractMetadata {
+    /// Empty metadata created now.
+    pub fn new() -> ContractMetadata {
+        let created_at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        ContractMetadata {
+            created_at,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_label(mut self, label: impl Into<String>) -> ContractMetadata {
+        self.label = Some(label.into());
+        self
+    }
+
+    pub fn with_tag(mut self, key: impl Into<String>, value: serde_json::Value) -> ContractMetadata {
+        self.tags.insert(key.into(), value);
+        self
+    }
+}
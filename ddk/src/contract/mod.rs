@@ -0,0 +1,309 @@
+//! Contract helpers on top of [dlc_manager::contract::Contract].
+pub mod cancel;
+pub mod close;
+pub mod confirmations;
+pub mod locktimes;
+pub mod maturity;
+pub mod metadata;
+pub mod policy;
+pub mod progress;
+pub mod refund;
+pub mod summary;
+pub mod timeout;
+
+use bitcoin::{Amount, Transaction};
+use dlc_manager::contract::signed_contract::SignedContract;
+use dlc_manager::contract::Contract;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Which party is responsible for broadcasting the funding transaction. Recorded in the
+/// contract's [metadata::ContractMetadata] once it is signed.
+///
+/// The offering party broadcasts. The accepting party watches the mempool and broadcasts
+/// itself when the transaction does not show up within
+/// [crate::config::DdkConfig::funding_broadcast_window]. A party can only broadcast a
+/// funding transaction that carries the witness of every input, which the offering party
+/// only has once the counterparty's signatures reached it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FundingBroadcastRole {
+    /// We broadcast the funding transaction as soon as it is missing.
+    Broadcaster,
+    /// The counterparty broadcasts. We broadcast once the window passed without seeing it.
+    Fallback,
+}
+
+impl FundingBroadcastRole {
+    pub fn for_contract(contract: &SignedContract) -> FundingBroadcastRole {
+        if contract.accepted_contract.offered_contract.is_offer_party {
+            FundingBroadcastRole::Broadcaster
+        } else {
+            FundingBroadcastRole::Fallback
+        }
+    }
+}
+
+/// Whether every input of `transaction` has a witness, so it can be broadcast.
+pub fn is_fully_signed(transaction: &Transaction) -> bool {
+    transaction.input.iter().all(|input| !input.witness.is_empty())
+}
+
+/// Fee paid by the funding transaction. `None` when a funding input's previous transaction
+/// cannot be decoded.
+pub fn funding_fee(contract: &SignedContract) -> Option<Amount> {
+    let accepted = &contract.accepted_contract;
+    let mut inputs = Amount::ZERO;
+    for input in accepted
+        .offered_contract
+        .funding_inputs
+        .iter()
+        .chain(accepted.funding_inputs.iter())
+    {
+        let prev_tx: Transaction = bitcoin::consensus::deserialize(&input.funding_input.prev_tx).ok()?;
+        let prev_out = prev_tx.output.get(input.funding_input.prev_tx_vout as usize)?;
+        inputs = inputs.checked_add(prev_out.value)?;
+    }
+    let outputs = accepted
+        .dlc_transactions
+        .fund
+        .output
+        .iter()
+        .map(|output| output.value)
+        .sum::<Amount>();
+    inputs.checked_sub(outputs)
+}
+
+/// Parts per million of the total collateral, the unit of collateral shares.
+pub const PPM: u32 = 1_000_000;
+
+/// Offer and accept collateral when we put up `our_share_ppm` of `total_collateral`. Our share
+/// is rounded down. None for shares above one million.
+pub fn collateral_split(total_collateral: u64, our_share_ppm: u32) -> Option<(u64, u64)> {
+    if our_share_ppm > PPM {
+        return None;
+    }
+    let ours = (total_collateral as u128 * our_share_ppm as u128 / PPM as u128) as u64;
+    Some((ours, total_collateral - ours))
+}
+
+/// The state of a [Contract] without its data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub enum ContractState {
+    Offered,
+    Accepted,
+    Signed,
+    Confirmed,
+    PreClosed,
+    Closed,
+    Refunded,
+    FailedAccept,
+    FailedSign,
+    Rejected,
+}
+
+impl ContractState {
+    pub const ALL: [ContractState; 10] = [
+        ContractState::Offered,
+        ContractState::Accepted,
+        ContractState::Signed,
+        ContractState::Confirmed,
+        ContractState::PreClosed,
+        ContractState::Closed,
+        ContractState::Refunded,
+        ContractState::FailedAccept,
+        ContractState::FailedSign,
+        ContractState::Rejected,
+    ];
+
+    /// Whether the contract can not change anymore.
+    pub fn is_final(&self) -> bool {
+        matches!(
+            self,
+            ContractState::Closed
+                | ContractState::Refunded
+                | ContractState::FailedAccept
+                | ContractState::FailedSign
+                | ContractState::Rejected
+        )
+    }
+}
+
+impl From<&Contract> for ContractState {
+    fn from(contract: &Contract) -> ContractState {
+        match contract {
+            Contract::Offered(_) => ContractState::Offered,
+            Contract::Accepted(_) => ContractState::Accepted,
+            Contract::Signed(_) => ContractState::Signed,
+            Contract::Confirmed(_) => ContractState::Confirmed,
+            Contract::PreClosed(_) => ContractState::PreClosed,
+            Contract::Closed(_) => ContractState::Closed,
+            Contract::Refunded(_) => ContractState::Refunded,
+            Contract::FailedAccept(_) => ContractState::FailedAccept,
+            Contract::FailedSign(_) => ContractState::FailedSign,
+            Contract::Rejected(_) => ContractState::Rejected,
+        }
+    }
+}
+
+impl fmt::Display for ContractState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl std::str::FromStr for ContractState {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ContractState::ALL
+            .into_iter()
+            .find(|state| state.to_string() == s)
+            .ok_or_else(|| anyhow::anyhow!("Unknown contract state {}.", s))
+    }
+}
+
+/// A legal change of contract state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransitionKind {
+    /// The contract data was updated without changing state.
+    Unchanged,
+    Accept,
+    Reject,
+    FailAccept,
+    Sign,
+    FailSign,
+    Confirm,
+    /// The unconfirmed funding transaction of a zero-conf contract was double-spent.
+    FailFunding,
+    /// The funding transaction lost the confirmations the contract was confirmed with, or
+    /// left the chain in a reorg.
+    Unconfirm,
+    PreClose,
+    Close,
+    Refund,
+}
+
+/// A contract update that does not follow the contract state machine.
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("Invalid contract transition from {from} to {to}.")]
+pub struct InvalidTransition {
+    pub from: ContractState,
+    pub to: ContractState,
+}
+
+/// Validate a contract update against the contract state machine.
+pub fn validate_transition(old: &Contract, new: &Contract) -> Result<TransitionKind, InvalidTransition> {
+    validate_state_transition(old.into(), new.into())
+}
+
+/// Validate a change of contract state.
+///
+/// The offering party moves from offered straight to signed when it receives the accept message.
+/// The accepting party moves through accepted.
+pub fn validate_state_transition(
+    from: ContractState,
+    to: ContractState,
+) -> Result<TransitionKind, InvalidTransition> {
+    use ContractState::*;
+    let kind = match (from, to) {
+        (from, to) if from == to => TransitionKind::Unchanged,
+        (Offered, Accepted) => TransitionKind::Accept,
+        (Offered, Rejected) | (Accepted, Rejected) => TransitionKind::Reject,
+        (Offered, FailedAccept) => TransitionKind::FailAccept,
+        (Offered, Signed) | (Accepted, Signed) => TransitionKind::Sign,
+        (Accepted, FailedSign) => TransitionKind::FailSign,
+        (Signed, Confirmed) => TransitionKind::Confirm,
+        (Signed, FailedSign) | (Confirmed, FailedSign) => TransitionKind::FailFunding,
+        (Confirmed, Signed) => TransitionKind::Unconfirm,
+        (Confirmed, PreClosed) => TransitionKind::PreClose,
+        (Confirmed, Closed) | (PreClosed, Closed) => TransitionKind::Close,
+        (Signed, Refunded) | (Confirmed, Refunded) => TransitionKind::Refund,
+        _ => return Err(InvalidTransition { from, to }),
+    };
+    Ok(kind)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ContractState::*;
+    use super::*;
+
+    const LEGAL: [(ContractState, ContractState, TransitionKind); 16] = [
+        (Offered, Accepted, TransitionKind::Accept),
+        (Offered, Rejected, TransitionKind::Reject),
+        (Accepted, Rejected, TransitionKind::Reject),
+        (Offered, FailedAccept, TransitionKind::FailAccept),
+        (Offered, Signed, TransitionKind::Sign),
+        (Accepted, Signed, TransitionKind::Sign),
+        (Accepted, FailedSign, TransitionKind::FailSign),
+        (Signed, Confirmed, TransitionKind::Confirm),
+        (Signed, FailedSign, TransitionKind::FailFunding),
+        (Confirmed, FailedSign, TransitionKind::FailFunding),
+        (Confirmed, Signed, TransitionKind::Unconfirm),
+        (Signed, Refunded, TransitionKind::Refund),
+        (Confirmed, PreClosed, TransitionKind::PreClose),
+        (Confirmed, Closed, TransitionKind::Close),
+        (Confirmed, Refunded, TransitionKind::Refund),
+        (PreClosed, Closed, TransitionKind::Close),
+    ];
+
+    #[test]
+    fn legal_transitions() {
+        for (from, to, kind) in LEGAL {
+            assert_eq!(validate_state_transition(from, to), Ok(kind), "{from} -> {to}");
+        }
+    }
+
+    #[test]
+    fn same_state_is_unchanged() {
+        for state in ContractState::ALL {
+            assert_eq!(validate_state_transition(state, state), Ok(TransitionKind::Unchanged));
+        }
+    }
+
+    #[test]
+    fn every_other_transition_is_illegal() {
+        for from in ContractState::ALL {
+            for to in ContractState::ALL {
+                if from == to || LEGAL.iter().any(|(f, t, _)| *f == from && *t == to) {
+                    continue;
+                }
+                assert_eq!(
+                    validate_state_transition(from, to),
+                    Err(InvalidTransition { from, to }),
+                    "{from} -> {to}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn closed_cannot_be_offered() {
+        assert!(validate_state_transition(Closed, Offered).is_err());
+        assert!(validate_state_transition(Refunded, Confirmed).is_err());
+        assert!(validate_state_transition(Rejected, Accepted).is_err());
+    }
+
+    #[test]
+    fn collateral_splits() {
+        assert_eq!(collateral_split(100_000, 500_000), Some((50_000, 50_000)));
+        assert_eq!(collateral_split(100_000, PPM), Some((100_000, 0)));
+        assert_eq!(collateral_split(100_000, 0), Some((0, 100_000)));
+        assert_eq!(collateral_split(3, 333_334), Some((1, 2)));
+        assert_eq!(collateral_split(u64::MAX, 999_999).map(|(o, a)| o + a), Some(u64::MAX));
+        assert_eq!(collateral_split(100_000, PPM + 1), None);
+    }
+
+    #[test]
+    fn funding_fee_of_signed_contract() {
+        use dlc_manager::contract::ser::Serializable;
+        let mut cursor = ::lightning::io::Cursor::new(include_bytes!(
+            "../../tests/data/dlc_storage/sled/Signed"
+        ));
+        let signed = SignedContract::deserialize(&mut cursor).unwrap();
+        let fee = funding_fee(&signed).unwrap();
+        let fund = &signed.accepted_contract.dlc_transactions.fund;
+        assert!(fee > Amount::ZERO);
+        assert!(fee < Amount::from_sat(1_000 * fund.vsize() as u64));
+    }
+}
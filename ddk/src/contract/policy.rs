@@ -0,0 +1,247 @@
+//! Decide on incoming offers without waiting for the user.
+use bitcoin::secp256k1::{PublicKey, XOnlyPublicKey};
+use dlc_manager::contract::offered_contract::OfferedContract;
+use std::collections::HashSet;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// What to do with an incoming offer once it is stored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OfferDecision {
+    /// Leave the offer for the user to accept or reject.
+    Manual,
+    /// Accept the offer. Risk limits still apply.
+    Accept,
+    /// Reject the offer with the reason.
+    Reject(String),
+}
+
+/// Evaluated by the manager for every incoming offer it stores.
+pub trait OfferPolicy: std::fmt::Debug + std::marker::Send + std::marker::Sync + 'static {
+    fn evaluate(&self, offer: &OfferedContract) -> OfferDecision;
+}
+
+/// Default policy that leaves every offer to the user.
+#[derive(Debug, Clone, Default)]
+pub struct ManualOnly;
+
+impl OfferPolicy for ManualOnly {
+    fn evaluate(&self, _offer: &OfferedContract) -> OfferDecision {
+        OfferDecision::Manual
+    }
+}
+
+/// Accepts offers that pass every configured rule and rejects the others. Rules left unset are
+/// not checked, so a policy without rules accepts everything.
+#[derive(Debug, Clone, Default)]
+pub struct RuleBasedPolicy {
+    /// Maximum collateral, in sats, we put up.
+    pub max_collateral: Option<u64>,
+    /// Counterparties we accept offers from.
+    pub allowed_counterparties: Option<HashSet<PublicKey>>,
+    /// Oracles every announcement of the offer must come from.
+    pub allowed_oracles: Option<HashSet<XOnlyPublicKey>>,
+    /// Events every announcement of the offer must be for.
+    pub allowed_event_ids: Option<HashSet<String>>,
+    /// Minimum fee rate, in sats per vbyte.
+    pub min_fee_rate: Option<u64>,
+    /// Maximum time until the last event of the offer matures.
+    pub max_duration: Option<Duration>,
+}
+
+impl RuleBasedPolicy {
+    pub fn with_max_collateral(mut self, sats: u64) -> Self {
+        self.max_collateral = Some(sats);
+        self
+    }
+
+    pub fn with_allowed_counterparties(
+        mut self,
+        counterparties: impl IntoIterator<Item = PublicKey>,
+    ) -> Self {
+        self.allowed_counterparties = Some(counterparties.into_iter().collect());
+        self
+    }
+
+    pub fn with_allowed_oracles(
+        mut self,
+        oracles: impl IntoIterator<Item = XOnlyPublicKey>,
+    ) -> Self {
+        self.allowed_oracles = Some(oracles.into_iter().collect());
+        self
+    }
+
+    pub fn with_allowed_event_ids(mut self, event_ids: impl IntoIterator<Item = String>) -> Self {
+        self.allowed_event_ids = Some(event_ids.into_iter().collect());
+        self
+    }
+
+    pub fn with_min_fee_rate(mut self, sats_per_vbyte: u64) -> Self {
+        self.min_fee_rate = Some(sats_per_vbyte);
+        self
+    }
+
+    pub fn with_max_duration(mut self, duration: Duration) -> Self {
+        self.max_duration = Some(duration);
+        self
+    }
+
+    /// Evaluate `offer` at `now` (unix seconds).
+    pub(crate) fn evaluate_at(&self, offer: &OfferedContract, now: u64) -> OfferDecision {
+        match self.rejection(offer, now) {
+            Some(reason) => OfferDecision::Reject(reason),
+            None => OfferDecision::Accept,
+        }
+    }
+
+    fn rejection(&self, offer: &OfferedContract, now: u64) -> Option<String> {
+        if let Some(allowed) = &self.allowed_counterparties {
+            if !allowed.contains(&offer.counter_party) {
+                return Some(format!(
+                    "Counterparty {} is not allowed.",
+                    offer.counter_party
+                ));
+            }
+        }
+        if let Some(max) = self.max_collateral {
+            let collateral = offer.total_collateral - offer.offer_params.collateral;
+            if collateral > max {
+                return Some(format!(
+                    "Collateral of {} sats is above the maximum of {} sats.",
+                    collateral, max
+                ));
+            }
+        }
+        if let Some(min) = self.min_fee_rate {
+            if offer.fee_rate_per_vb < min {
+                return Some(format!(
+                    "Fee rate of {} sats/vbyte is below the minimum of {} sats/vbyte.",
+                    offer.fee_rate_per_vb, min
+                ));
+            }
+        }
+
+        let announcements = offer
+            .contract_info
+            .iter()
+            .flat_map(|info| &info.oracle_announcements);
+        let mut last_maturity = 0u64;
+        for announcement in announcements {
+            if let Some(allowed) = &self.allowed_oracles {
+                if !allowed.contains(&announcement.oracle_public_key) {
+                    return Some(format!(
+                        "Oracle {} is not allowed.",
+                        announcement.oracle_public_key
+                    ));
+                }
+            }
+            let event = &announcement.oracle_event;
+            if let Some(allowed) = &self.allowed_event_ids {
+                if !allowed.contains(&event.event_id) {
+                    return Some(format!("Event {} is not allowed.", event.event_id));
+                }
+            }
+            last_maturity = last_maturity.max(event.event_maturity_epoch as u64);
+        }
+        if let Some(max) = self.max_duration {
+            let duration = last_maturity.saturating_sub(now);
+            if duration > max.as_secs() {
+                return Some(format!(
+                    "Contract matures in {} seconds, above the maximum of {} seconds.",
+                    duration,
+                    max.as_secs()
+                ));
+            }
+        }
+        None
+    }
+}
+
+impl OfferPolicy for RuleBasedPolicy {
+    fn evaluate(&self, offer: &OfferedContract) -> OfferDecision {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        self.evaluate_at(offer, now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dlc_manager::contract::ser::Serializable;
+
+    fn offer() -> OfferedContract {
+        let serialized = include_bytes!("../../tests/data/dlc_storage/sled/Offered");
+        OfferedContract::deserialize(&mut ::lightning::io::Cursor::new(&serialized)).unwrap()
+    }
+
+    fn maturity(offer: &OfferedContract) -> u64 {
+        offer.contract_info[0].oracle_announcements[0]
+            .oracle_event
+            .event_maturity_epoch as u64
+    }
+
+    fn is_rejected(decision: OfferDecision) -> bool {
+        matches!(decision, OfferDecision::Reject(_))
+    }
+
+    #[test]
+    fn manual_only_leaves_offers_to_the_user() {
+        assert_eq!(ManualOnly.evaluate(&offer()), OfferDecision::Manual);
+    }
+
+    #[test]
+    fn policy_without_rules_accepts() {
+        let offer = offer();
+        assert_eq!(
+            RuleBasedPolicy::default().evaluate_at(&offer, maturity(&offer)),
+            OfferDecision::Accept
+        );
+    }
+
+    #[test]
+    fn offers_outside_the_rules_are_rejected() {
+        let offer = offer();
+        let now = maturity(&offer);
+        let collateral = offer.total_collateral - offer.offer_params.collateral;
+        let announcement = &offer.contract_info[0].oracle_announcements[0];
+
+        let policy = RuleBasedPolicy::default().with_max_collateral(collateral);
+        assert_eq!(policy.evaluate_at(&offer, now), OfferDecision::Accept);
+        let policy = RuleBasedPolicy::default().with_max_collateral(collateral - 1);
+        assert!(is_rejected(policy.evaluate_at(&offer, now)));
+
+        let policy = RuleBasedPolicy::default().with_min_fee_rate(offer.fee_rate_per_vb + 1);
+        assert!(is_rejected(policy.evaluate_at(&offer, now)));
+
+        let policy = RuleBasedPolicy::default().with_allowed_counterparties([offer.counter_party]);
+        assert_eq!(policy.evaluate_at(&offer, now), OfferDecision::Accept);
+        let policy = RuleBasedPolicy::default().with_allowed_counterparties([]);
+        assert!(is_rejected(policy.evaluate_at(&offer, now)));
+
+        let policy =
+            RuleBasedPolicy::default().with_allowed_oracles([announcement.oracle_public_key]);
+        assert_eq!(policy.evaluate_at(&offer, now), OfferDecision::Accept);
+        let policy = RuleBasedPolicy::default().with_allowed_oracles([]);
+        assert!(is_rejected(policy.evaluate_at(&offer, now)));
+
+        let policy = RuleBasedPolicy::default()
+            .with_allowed_event_ids([announcement.oracle_event.event_id.clone()]);
+        assert_eq!(policy.evaluate_at(&offer, now), OfferDecision::Accept);
+        let policy = RuleBasedPolicy::default().with_allowed_event_ids(["other".to_string()]);
+        assert!(is_rejected(policy.evaluate_at(&offer, now)));
+    }
+
+    #[test]
+    fn duration_counts_until_the_last_maturity() {
+        let offer = offer();
+        let policy = RuleBasedPolicy::default().with_max_duration(Duration::from_secs(60));
+        let maturity = maturity(&offer);
+        assert_eq!(
+            policy.evaluate_at(&offer, maturity - 60),
+            OfferDecision::Accept
+        );
+        assert!(is_rejected(policy.evaluate_at(&offer, maturity - 61)));
+    }
+}
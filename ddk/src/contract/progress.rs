@@ -0,0 +1,200 @@
+//! Progress of the slow steps of a negotiation, for UIs that need to show the node is working.
+use crate::contract::confirmations::AcceptanceParams;
+use crate::events::{DdkEvent, EventBus};
+use dlc_manager::contract::offered_contract::OfferedContract;
+use dlc_manager::ContractId;
+use std::fmt;
+use std::sync::Arc;
+
+/// Callback receiving negotiation progress.
+pub type ProgressCallback = Arc<dyn Fn(AcceptProgress) + Send + Sync>;
+
+/// Calls to the callback per phase at most, however many CETs the contract has.
+pub const PROGRESS_STEPS: u64 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NegotiationPhase {
+    /// Creating or verifying the adaptor signature of every CET.
+    VerifyingCets,
+    BuildingFunding,
+    Signing,
+}
+
+impl fmt::Display for NegotiationPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AcceptProgress {
+    pub phase: NegotiationPhase,
+    pub done: u64,
+    pub total: u64,
+}
+
+/// Options for accepting an offer.
+#[derive(Clone, Default)]
+pub struct AcceptOptions {
+    pub progress: Option<ProgressCallback>,
+    /// Confirmations the contract needs in place of the confirmation policy.
+    pub acceptance: Option<AcceptanceParams>,
+}
+
+impl fmt::Debug for AcceptOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AcceptOptions")
+            .field("progress", &self.progress.is_some())
+            .field("acceptance", &self.acceptance)
+            .finish()
+    }
+}
+
+/// Reports progress of one phase at a time, batched to [PROGRESS_STEPS] calls per phase.
+///
+/// With [ProgressReporter::with_events], the start and end of every phase is also emitted as
+/// a [DdkEvent::NegotiationProgress].
+pub struct ProgressReporter {
+    callback: Option<ProgressCallback>,
+    events: Option<(Arc<EventBus>, ContractId)>,
+    phase: NegotiationPhase,
+    done: u64,
+    total: u64,
+    reported: u64,
+}
+
+impl ProgressReporter {
+    pub fn new(callback: Option<ProgressCallback>) -> ProgressReporter {
+        ProgressReporter {
+            callback,
+            events: None,
+            phase: NegotiationPhase::VerifyingCets,
+            done: 0,
+            total: 0,
+            reported: 0,
+        }
+    }
+
+    /// Emit phase boundaries for the negotiation of `contract_id` on `events`.
+    pub fn with_events(mut self, events: Arc<EventBus>, contract_id: ContractId) -> Self {
+        self.events = Some((events, contract_id));
+        self
+    }
+
+    /// Start a phase of `total` steps and report that nothing is done yet.
+    pub fn start(&mut self, phase: NegotiationPhase, total: u64) {
+        self.phase = phase;
+        self.done = 0;
+        self.total = total;
+        self.report();
+    }
+
+    /// Count `steps` more steps of the current phase.
+    pub fn advance(&mut self, steps: u64) {
+        self.done = self.done.saturating_add(steps).min(self.total);
+        let batch = (self.total / PROGRESS_STEPS).max(1);
+        if self.done == self.total || self.done - self.reported >= batch {
+            self.report();
+        }
+    }
+
+    /// Mark the current phase as done.
+    pub fn finish(&mut self) {
+        if self.done < self.total || self.reported < self.total {
+            self.done = self.total;
+            self.report();
+        }
+    }
+
+    /// Run a phase that cannot report intermediate steps.
+    pub fn phase<T>(&mut self, phase: NegotiationPhase, total: u64, f: impl FnOnce() -> T) -> T {
+        self.start(phase, total);
+        let result = f();
+        self.finish();
+        result
+    }
+
+    fn report(&mut self) {
+        self.reported = self.done;
+        let progress = AcceptProgress {
+            phase: self.phase,
+            done: self.done,
+            total: self.total,
+        };
+        if let Some(callback) = &self.callback {
+            callback(progress);
+        }
+        match &self.events {
+            Some((events, contract_id)) if progress.done == 0 || progress.done == progress.total => {
+                events.emit(DdkEvent::NegotiationProgress {
+                    contract_id: *contract_id,
+                    progress,
+                })
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Number of CETs, and so adaptor signatures, of an offered contract.
+pub fn cet_count(offered: &OfferedContract) -> u64 {
+    offered
+        .contract_info
+        .iter()
+        .filter_map(|info| info.get_payouts(offered.total_collateral).ok())
+        .map(|payouts| payouts.len() as u64)
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    fn recorder() -> (ProgressCallback, Arc<Mutex<Vec<AcceptProgress>>>) {
+        let events = Arc::new(Mutex::new(vec![]));
+        let sink = events.clone();
+        let callback: ProgressCallback = Arc::new(move |p| sink.lock().unwrap().push(p));
+        (callback, events)
+    }
+
+    #[test]
+    fn large_contract_progress_is_batched_and_monotonic() {
+        let (callback, events) = recorder();
+        let mut reporter = ProgressReporter::new(Some(callback));
+        let total = 1u64 << 20;
+        reporter.start(NegotiationPhase::VerifyingCets, total);
+        for _ in 0..total {
+            reporter.advance(1);
+        }
+        reporter.finish();
+        reporter.phase(NegotiationPhase::Signing, 1, || ());
+
+        let events = events.lock().unwrap();
+        let verifying: Vec<_> = events
+            .iter()
+            .filter(|p| p.phase == NegotiationPhase::VerifyingCets)
+            .collect();
+        assert!(verifying.len() as u64 <= PROGRESS_STEPS + 2);
+        assert!(verifying.windows(2).all(|w| w[0].done < w[1].done));
+        assert_eq!(verifying.last().unwrap().done, total);
+        assert_eq!(
+            events.last(),
+            Some(&AcceptProgress {
+                phase: NegotiationPhase::Signing,
+                done: 1,
+                total: 1
+            })
+        );
+    }
+
+    #[test]
+    fn fixture_cet_count() {
+        let mut cursor = ::lightning::io::Cursor::new(include_bytes!(
+            "../../tests/data/dlc_storage/sled/Offered"
+        ));
+        let offered: OfferedContract =
+            dlc_manager::contract::ser::Serializable::deserialize(&mut cursor).unwrap();
+        assert!(cet_count(&offered) > 0);
+    }
+}
@@ -0,0 +1,235 @@
+//! Refund confirmed contracts that were never attested once their refund locktime passes.
+//!
+//! Both parties signed the refund transaction while negotiating, so either can broadcast it
+//! alone. The locktime is checked against the chain tip first so the transaction is not
+//! rejected as non-final.
+use super::locktimes::{ChainTip, Locktime};
+use super::ContractState;
+use bitcoin::secp256k1::{Secp256k1, SecretKey, Signing};
+use bitcoin::Transaction;
+use dlc_manager::contract::signed_contract::SignedContract;
+use dlc_manager::contract::Contract;
+use dlc_manager::ContractId;
+use std::fmt;
+use std::time::Duration;
+
+/// Average time between blocks, to estimate the wait on height locktimes.
+const BLOCK_INTERVAL: Duration = Duration::from_secs(600);
+
+/// Time left until a refund locktime is reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RefundWait {
+    Blocks(u32),
+    Time(Duration),
+}
+
+impl RefundWait {
+    /// The wait as a duration. Blocks are counted as ten minutes each.
+    pub fn estimate(&self) -> Duration {
+        match *self {
+            RefundWait::Blocks(blocks) => BLOCK_INTERVAL * blocks,
+            RefundWait::Time(wait) => wait,
+        }
+    }
+}
+
+impl fmt::Display for RefundWait {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RefundWait::Blocks(blocks) => write!(f, "{} blocks", blocks),
+            RefundWait::Time(wait) => write!(f, "{}s", wait.as_secs()),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum RefundError {
+    #[error("Contract not found.")]
+    NotFound,
+    #[error("Only confirmed contracts can be refunded. state={0}")]
+    NotConfirmed(ContractState),
+    #[error("The refund locktime {locktime} is not reached. Wait {remaining}.")]
+    NotYetRefundable {
+        locktime: Locktime,
+        remaining: RefundWait,
+    },
+    #[error("Could not get the chain tip. {0}")]
+    Chain(String),
+    #[error("Storage error. {0}")]
+    Storage(String),
+    #[error("Could not sign the refund transaction. {0}")]
+    Signing(String),
+    #[error("Could not broadcast the refund transaction. {0}")]
+    Broadcast(String),
+}
+
+/// The confirmed contract to refund.
+pub fn refundable_contract(contract: Option<&Contract>) -> Result<&SignedContract, RefundError> {
+    match contract {
+        Some(Contract::Confirmed(signed)) => Ok(signed),
+        Some(contract) => Err(RefundError::NotConfirmed(contract.into())),
+        None => Err(RefundError::NotFound),
+    }
+}
+
+pub fn refund_locktime(contract: &SignedContract) -> Locktime {
+    Locktime::from_consensus(contract.accepted_contract.offered_contract.refund_locktime)
+}
+
+/// Time left until `locktime`, or `None` once the tip reached it.
+pub fn refund_wait(locktime: Locktime, tip: &ChainTip) -> Option<RefundWait> {
+    match locktime {
+        Locktime::Height(height) if height > tip.height => {
+            Some(RefundWait::Blocks(height - tip.height))
+        }
+        Locktime::Time(time) if time > tip.time => Some(RefundWait::Time(Duration::from_secs(
+            (time - tip.time) as u64,
+        ))),
+        _ => None,
+    }
+}
+
+/// Check the contract's refund locktime is reached at `tip`.
+pub fn check_refund_locktime(contract: &SignedContract, tip: &ChainTip) -> Result<(), RefundError> {
+    let locktime = refund_locktime(contract);
+    match refund_wait(locktime, tip) {
+        Some(remaining) => Err(RefundError::NotYetRefundable {
+            locktime,
+            remaining,
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Confirmed contracts whose refund locktime is reached at `tip`.
+pub fn refundable(contracts: &[Contract], tip: &ChainTip) -> Vec<ContractId> {
+    contracts
+        .iter()
+        .filter_map(|c| match c {
+            Contract::Confirmed(signed) if check_refund_locktime(signed, tip).is_ok() => {
+                Some(c.get_id())
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// The refund transaction with our signature added to the counterparty's.
+pub fn signed_refund<C: Signing>(
+    secp: &Secp256k1<C>,
+    contract: &SignedContract,
+    fund_secret_key: &SecretKey,
+) -> Result<Transaction, RefundError> {
+    let accepted = &contract.accepted_contract;
+    let offered = &accepted.offered_contract;
+    let (other_fund_pubkey, other_signature) = if offered.is_offer_party {
+        (
+            &accepted.accept_params.fund_pubkey,
+            &accepted.accept_refund_signature,
+        )
+    } else {
+        (
+            &offered.offer_params.fund_pubkey,
+            &contract.offer_refund_signature,
+        )
+    };
+    let transactions = &accepted.dlc_transactions;
+    let mut refund = transactions.refund.clone();
+    dlc::util::sign_multi_sig_input(
+        secp,
+        &mut refund,
+        other_signature,
+        other_fund_pubkey,
+        fund_secret_key,
+        &transactions.funding_script_pubkey,
+        transactions.get_fund_output().value,
+        0,
+    )
+    .map_err(|e| RefundError::Signing(e.to_string()))?;
+    Ok(refund)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dlc_manager::contract::ser::Serializable;
+
+    fn confirmed() -> SignedContract {
+        let serialized = include_bytes!("../../tests/data/dlc_storage/sled/Confirmed");
+        SignedContract::deserialize(&mut ::lightning::io::Cursor::new(&serialized)).unwrap()
+    }
+
+    fn tip_at(locktime: Locktime, offset: i64) -> ChainTip {
+        let at = |value: u32| (value as i64 + offset) as u32;
+        match locktime {
+            Locktime::Height(height) => ChainTip {
+                height: at(height),
+                time: 0,
+            },
+            Locktime::Time(time) => ChainTip {
+                height: 0,
+                time: at(time),
+            },
+        }
+    }
+
+    #[test]
+    fn refund_waits_for_the_locktime() {
+        let contract = confirmed();
+        let locktime = refund_locktime(&contract);
+
+        match check_refund_locktime(&contract, &tip_at(locktime, -2)).unwrap_err() {
+            RefundError::NotYetRefundable {
+                locktime: reported,
+                remaining,
+            } => {
+                assert_eq!(reported, locktime);
+                assert!(matches!(
+                    remaining,
+                    RefundWait::Blocks(2) | RefundWait::Time(_)
+                ));
+                assert!(remaining.estimate() >= Duration::from_secs(2));
+            }
+            e => panic!("unexpected error {e}"),
+        }
+        assert!(check_refund_locktime(&contract, &tip_at(locktime, 0)).is_ok());
+    }
+
+    #[test]
+    fn remaining_wait_in_the_locktime_unit() {
+        let tip = ChainTip {
+            height: 100,
+            time: 1_700_000_000,
+        };
+        assert_eq!(
+            refund_wait(Locktime::Height(106), &tip),
+            Some(RefundWait::Blocks(6))
+        );
+        assert_eq!(refund_wait(Locktime::Height(100), &tip), None);
+        assert_eq!(
+            refund_wait(Locktime::Time(1_700_000_060), &tip),
+            Some(RefundWait::Time(Duration::from_secs(60)))
+        );
+        assert_eq!(refund_wait(Locktime::Time(1_699_999_999), &tip), None);
+        assert_eq!(RefundWait::Blocks(6).estimate(), Duration::from_secs(3600));
+    }
+
+    #[test]
+    fn only_confirmed_contracts_past_the_locktime_are_refundable() {
+        let signed = confirmed();
+        let locktime = refund_locktime(&signed);
+        let contracts = vec![
+            Contract::Confirmed(signed.clone()),
+            Contract::Signed(signed.clone()),
+        ];
+        assert!(refundable(&contracts, &tip_at(locktime, -1)).is_empty());
+        assert_eq!(
+            refundable(&contracts, &tip_at(locktime, 1)),
+            vec![contracts[0].get_id()]
+        );
+        assert_eq!(
+            refundable_contract(Some(&contracts[1])).unwrap_err(),
+            RefundError::NotConfirmed(ContractState::Signed)
+        );
+    }
+}
@@ -0,0 +1,279 @@
+//! Serializable views of stored contracts, so applications do not need to match on the
+//! [Contract] enum.
+use super::metadata::ContractMetadata;
+use super::ContractState;
+use bitcoin::key::XOnlyPublicKey;
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::Txid;
+use dlc_manager::contract::accepted_contract::AcceptedContract;
+use dlc_manager::contract::offered_contract::OfferedContract;
+use dlc_manager::contract::Contract;
+use serde::{Deserialize, Serialize};
+
+/// A contract as shown in a list.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ContractSummary {
+    /// Hex encoded contract id. The temporary id until the contract is accepted.
+    pub id: String,
+    pub state: ContractState,
+    pub counterparty: PublicKey,
+    /// Whether we sent the offer. Unknown once the contract is closed.
+    pub is_offer_party: Option<bool>,
+    /// Collateral in sats. Closed contracts no longer store their collateral.
+    pub offer_collateral: Option<u64>,
+    pub accept_collateral: Option<u64>,
+    pub total_collateral: Option<u64>,
+    /// Event id of the first oracle announcement.
+    pub event_id: Option<String>,
+    /// Earliest maturity of the oracle events, as a unix timestamp.
+    pub maturity: Option<u32>,
+    /// Profit and loss in sats of a closed contract.
+    pub pnl: Option<i64>,
+    /// The CET or refund transaction that closed the contract.
+    pub closing_txid: Option<Txid>,
+    /// Label and tags the application stored for the contract.
+    pub metadata: Option<ContractMetadata>,
+    /// Confirmations of the funding transaction of a signed or confirmed contract.
+    pub confirmations: Option<u32>,
+    /// Confirmations the [super::confirmations::ConfirmationPolicy] requires before the
+    /// contract is confirmed.
+    pub required_confirmations: Option<u32>,
+    /// Currency of the exchange rates recorded for the contract, see
+    /// [crate::rates::RateProvider].
+    pub fiat_currency: Option<String>,
+    /// Total collateral in `fiat_currency` at the rate recorded when the offer was accepted.
+    pub fiat_collateral: Option<f64>,
+    /// Profit and loss in `fiat_currency` at the rate recorded when the contract closed.
+    pub fiat_pnl: Option<f64>,
+}
+
+/// An oracle event a contract settles on.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OracleEventSummary {
+    pub oracle_public_key: XOnlyPublicKey,
+    pub event_id: String,
+    pub maturity: u32,
+}
+
+/// Everything about a single contract.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ContractDetails {
+    #[serde(flatten)]
+    pub summary: ContractSummary,
+    pub temporary_id: String,
+    pub oracle_events: Vec<OracleEventSummary>,
+    pub fee_rate_per_vb: Option<u64>,
+    pub cet_locktime: Option<u32>,
+    pub refund_locktime: Option<u32>,
+    pub funding_txid: Option<Txid>,
+    /// Why the negotiation failed, for failed contracts.
+    pub error: Option<String>,
+}
+
+impl From<&Contract> for ContractSummary {
+    fn from(contract: &Contract) -> ContractSummary {
+        ContractDetails::from(contract).summary
+    }
+}
+
+impl From<&Contract> for ContractDetails {
+    fn from(contract: &Contract) -> ContractDetails {
+        let offered = offered_contract(contract);
+        let accepted = accepted_contract(contract);
+        let oracle_events = offered.map(oracle_events).unwrap_or_default();
+        let (pnl, closing_txid) = match contract {
+            Contract::Closed(c) => (Some(c.pnl), c.signed_cet.as_ref().map(|cet| cet.compute_txid())),
+            Contract::PreClosed(c) => (None, Some(c.signed_cet.compute_txid())),
+            Contract::Refunded(s) => (
+                None,
+                Some(s.accepted_contract.dlc_transactions.refund.compute_txid()),
+            ),
+            _ => (None, None),
+        };
+        let error = match contract {
+            Contract::FailedAccept(f) => Some(f.error_message.clone()),
+            Contract::FailedSign(f) => Some(f.error_message.clone()),
+            _ => None,
+        };
+
+        let summary = ContractSummary {
+            id: hex::encode(contract.get_id()),
+            state: ContractState::from(contract),
+            counterparty: contract.get_counter_party_id(),
+            is_offer_party: offered.map(|o| o.is_offer_party),
+            offer_collateral: offered.map(|o| o.offer_params.collateral),
+            accept_collateral: offered.map(|o| o.total_collateral - o.offer_params.collateral),
+            total_collateral: offered.map(|o| o.total_collateral),
+            event_id: oracle_events.first().map(|e| e.event_id.clone()),
+            maturity: oracle_events.iter().map(|e| e.maturity).min(),
+            pnl,
+            closing_txid,
+            metadata: None,
+            confirmations: None,
+            required_confirmations: None,
+            fiat_currency: None,
+            fiat_collateral: None,
+            fiat_pnl: None,
+        };
+
+        ContractDetails {
+            summary,
+            temporary_id: hex::encode(contract.get_temporary_id()),
+            oracle_events,
+            fee_rate_per_vb: offered.map(|o| o.fee_rate_per_vb),
+            cet_locktime: offered.map(|o| o.cet_locktime),
+            refund_locktime: offered.map(|o| o.refund_locktime),
+            funding_txid: accepted.map(|a| a.dlc_transactions.fund.compute_txid()),
+            error,
+        }
+    }
+}
+
+/// Open contracts past the maturity of their oracle events, which a check should settle.
+pub fn awaiting_settlement(contracts: &[Contract], now: u64) -> Vec<ContractSummary> {
+    contracts
+        .iter()
+        .filter(|c| matches!(c, Contract::Confirmed(_) | Contract::PreClosed(_)))
+        .map(ContractSummary::from)
+        .filter(|summary| summary.maturity.is_some_and(|maturity| maturity as u64 <= now))
+        .collect()
+}
+
+pub(crate) fn accepted_contract(contract: &Contract) -> Option<&AcceptedContract> {
+    match contract {
+        Contract::Accepted(a) => Some(a),
+        Contract::Signed(s) | Contract::Confirmed(s) | Contract::Refunded(s) => {
+            Some(&s.accepted_contract)
+        }
+        Contract::PreClosed(p) => Some(&p.signed_contract.accepted_contract),
+        Contract::FailedSign(f) => Some(&f.accepted_contract),
+        _ => None,
+    }
+}
+
+pub(crate) fn offered_contract(contract: &Contract) -> Option<&OfferedContract> {
+    match contract {
+        Contract::Offered(o) | Contract::Rejected(o) => Some(o),
+        Contract::FailedAccept(f) => Some(&f.offered_contract),
+        Contract::Closed(_) => None,
+        _ => accepted_contract(contract).map(|a| &a.offered_contract),
+    }
+}
+
+fn oracle_events(offered: &OfferedContract) -> Vec<OracleEventSummary> {
+    offered
+        .contract_info
+        .iter()
+        .flat_map(|info| info.oracle_announcements.iter())
+        .map(|announcement| OracleEventSummary {
+            oracle_public_key: announcement.oracle_public_key,
+            event_id: announcement.oracle_event.event_id.clone(),
+            maturity: announcement.oracle_event.event_maturity_epoch,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dlc_manager::contract::ser::Serializable;
+    use dlc_manager::contract::signed_contract::SignedContract;
+    use dlc_manager::contract::{ClosedContract, PreClosedContract};
+
+    fn deserialize_object<T: Serializable>(serialized: &[u8]) -> T {
+        let mut cursor = ::lightning::io::Cursor::new(&serialized);
+        T::deserialize(&mut cursor).unwrap()
+    }
+
+    fn contracts() -> Vec<Contract> {
+        let offered: OfferedContract =
+            deserialize_object(include_bytes!("../../tests/data/dlc_storage/sled/Offered"));
+        let accepted: AcceptedContract =
+            deserialize_object(include_bytes!("../../tests/data/dlc_storage/sled/Accepted"));
+        let signed: SignedContract =
+            deserialize_object(include_bytes!("../../tests/data/dlc_storage/sled/Signed"));
+        let confirmed: SignedContract =
+            deserialize_object(include_bytes!("../../tests/data/dlc_storage/sled/Confirmed"));
+        let pre_closed: PreClosedContract =
+            deserialize_object(include_bytes!("../../tests/data/dlc_storage/sled/PreClosed"));
+        let closed: ClosedContract =
+            deserialize_object(include_bytes!("../../tests/data/dlc_storage/sled/Closed"));
+        vec![
+            Contract::Offered(offered.clone()),
+            Contract::Rejected(offered),
+            Contract::Accepted(accepted),
+            Contract::Signed(signed.clone()),
+            Contract::Confirmed(confirmed),
+            Contract::Refunded(signed),
+            Contract::PreClosed(pre_closed),
+            Contract::Closed(closed),
+        ]
+    }
+
+    #[test]
+    fn every_state_serializes_to_json() {
+        for contract in contracts() {
+            let details = ContractDetails::from(&contract);
+            let json = serde_json::to_value(&details).unwrap();
+            let state = ContractState::from(&contract);
+
+            assert_eq!(json["state"], state.to_string(), "{state}");
+            assert_eq!(json["id"], hex::encode(contract.get_id()), "{state}");
+            assert_eq!(
+                serde_json::from_value::<ContractDetails>(json).unwrap(),
+                details,
+                "{state}"
+            );
+        }
+    }
+
+    #[test]
+    fn open_contracts_have_terms() {
+        for contract in contracts() {
+            let summary = ContractSummary::from(&contract);
+            if summary.state == ContractState::Closed {
+                continue;
+            }
+            let (offer, accept, total) = (
+                summary.offer_collateral.unwrap(),
+                summary.accept_collateral.unwrap(),
+                summary.total_collateral.unwrap(),
+            );
+            assert_eq!(offer + accept, total);
+            assert!(summary.event_id.is_some());
+            assert!(summary.maturity.is_some());
+        }
+    }
+
+    #[test]
+    fn matured_open_contracts_await_settlement() {
+        let contracts = contracts();
+        let matured = awaiting_settlement(&contracts, u64::MAX);
+        let states = matured.iter().map(|c| c.state).collect::<Vec<_>>();
+        assert_eq!(states, vec![ContractState::Confirmed, ContractState::PreClosed]);
+        assert!(awaiting_settlement(&contracts, 0).is_empty());
+    }
+
+    #[test]
+    fn closed_contracts_have_outcome() {
+        for contract in contracts() {
+            let summary = ContractSummary::from(&contract);
+            match &contract {
+                Contract::Closed(closed) => {
+                    assert_eq!(summary.pnl, Some(closed.pnl));
+                    assert_eq!(
+                        summary.closing_txid,
+                        closed.signed_cet.as_ref().map(|cet| cet.compute_txid())
+                    );
+                }
+                Contract::PreClosed(_) | Contract::Refunded(_) => {
+                    assert!(summary.closing_txid.is_some())
+                }
+                _ => {
+                    assert!(summary.pnl.is_none());
+                    assert!(summary.closing_txid.is_none());
+                }
+            }
+        }
+    }
+}
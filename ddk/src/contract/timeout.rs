@@ -0,0 +1,397 @@
+//! Timeouts for contract negotiations that stop making progress.
+use super::metadata::ContractMetadata;
+use super::ContractState;
+use crate::events::{DdkEvent, EventBus};
+use crate::wallet::DlcDevKitWallet;
+use crate::{DdkBlockchain, DdkStorage};
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::{OutPoint, Transaction};
+use dlc_manager::contract::Contract;
+use dlc_manager::ContractId;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Default time for a counterparty to accept an offer we sent.
+pub const DEFAULT_OFFER_TTL: Duration = Duration::from_secs(60 * 60);
+/// Default time for a counterparty to sign a contract we accepted.
+pub const DEFAULT_SIGN_TIMEOUT: Duration = Duration::from_secs(60 * 10);
+
+/// How long a negotiation may wait on the counterparty before it is failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NegotiationTimeouts {
+    /// Outgoing offers not accepted within this time are rejected.
+    pub offer_ttl: Duration,
+    /// Accepted contracts not signed within this time fail to sign.
+    pub sign_timeout: Duration,
+    /// Do not count time while the counterparty is disconnected.
+    pub pause_when_offline: bool,
+}
+
+impl Default for NegotiationTimeouts {
+    fn default() -> Self {
+        Self {
+            offer_ttl: DEFAULT_OFFER_TTL,
+            sign_timeout: DEFAULT_SIGN_TIMEOUT,
+            pause_when_offline: true,
+        }
+    }
+}
+
+/// A negotiation that waited longer than its timeout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NegotiationTimedOut {
+    pub contract_id: ContractId,
+    pub stalled_state: ContractState,
+    pub counterparty: PublicKey,
+}
+
+/// How long a negotiation has waited on the counterparty. Kept in the contract's
+/// [super::metadata::ContractMetadata] so a restart does not reset it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NegotiationClock {
+    /// The state the negotiation waits in.
+    pub state: ContractState,
+    /// When the negotiation was first seen in `state`, as a unix timestamp.
+    pub started_at: u64,
+    /// Seconds counted against the timeout.
+    pub elapsed: u64,
+}
+
+#[derive(Debug)]
+struct Waiting {
+    clock: NegotiationClock,
+    last_check: u64,
+}
+
+/// Tracks how long each negotiation has waited on the counterparty.
+#[derive(Debug, Default)]
+pub struct NegotiationTimer {
+    waiting: HashMap<ContractId, Waiting>,
+}
+
+impl NegotiationTimer {
+    /// Advance the timer to `now` (unix seconds) and return the negotiations past their timeout.
+    /// Time only counts while `is_online` returns true for the counterparty when
+    /// `pause_when_offline` is set. Negotiations the timer has not seen yet resume from the
+    /// clock `persisted` returns, so time before a restart counts and downtime does not.
+    pub fn check(
+        &mut self,
+        contracts: &[Contract],
+        now: u64,
+        timeouts: &NegotiationTimeouts,
+        is_online: impl Fn(&PublicKey) -> bool,
+        persisted: impl Fn(&ContractId) -> Option<NegotiationClock>,
+    ) -> Vec<NegotiationTimedOut> {
+        let mut timed_out = vec![];
+        let mut seen = vec![];
+        for contract in contracts {
+            let timeout = match contract {
+                Contract::Offered(o) if o.is_offer_party => timeouts.offer_ttl,
+                Contract::Accepted(_) => timeouts.sign_timeout,
+                _ => continue,
+            };
+            let contract_id = contract.get_id();
+            let state = ContractState::from(contract);
+            let counterparty = contract.get_counter_party_id();
+            seen.push(contract_id);
+
+            let started = NegotiationClock {
+                state,
+                started_at: now,
+                elapsed: 0,
+            };
+            let waiting = self.waiting.entry(contract_id).or_insert_with(|| Waiting {
+                clock: persisted(&contract_id).unwrap_or(started),
+                last_check: now,
+            });
+            if waiting.clock.state != state {
+                waiting.clock = started;
+            }
+            if !timeouts.pause_when_offline || is_online(&counterparty) {
+                waiting.clock.elapsed += now.saturating_sub(waiting.last_check);
+            }
+            waiting.last_check = now;
+
+            if waiting.clock.elapsed >= timeout.as_secs() {
+                timed_out.push(NegotiationTimedOut {
+                    contract_id,
+                    stalled_state: state,
+                    counterparty,
+                });
+            }
+        }
+        self.waiting.retain(|id, _| seen.contains(id));
+        timed_out
+    }
+
+    /// The clocks of the negotiations being timed, to persist them.
+    pub fn clocks(&self) -> impl Iterator<Item = (&ContractId, &NegotiationClock)> {
+        self.waiting.iter().map(|(id, waiting)| (id, &waiting.clock))
+    }
+}
+
+/// The failed contract a stalled negotiation moves to. Both outgoing offers and accepted
+/// contracts are rejected. FailedSign would need the sign message that never arrived. An
+/// accepted contract is rejected by its offer, so it goes back to its temporary id.
+pub fn timed_out_contract(contract: &Contract) -> Option<Contract> {
+    match contract {
+        Contract::Offered(o) => Some(Contract::Rejected(o.clone())),
+        Contract::Accepted(a) => Some(Contract::Rejected(a.offered_contract.clone())),
+        _ => None,
+    }
+}
+
+/// Outpoints of our funding inputs, to be released when the negotiation fails or the funding
+/// transaction of a signed contract is double-spent.
+pub fn own_funding_outpoints(contract: &Contract) -> Vec<OutPoint> {
+    let funding_inputs = match contract {
+        Contract::Offered(o) => &o.funding_inputs,
+        Contract::Accepted(a) => &a.funding_inputs,
+        Contract::Signed(s) | Contract::Confirmed(s) => {
+            let offered = &s.accepted_contract.offered_contract;
+            if offered.is_offer_party {
+                &offered.funding_inputs
+            } else {
+                &s.accepted_contract.funding_inputs
+            }
+        }
+        _ => return vec![],
+    };
+    funding_inputs
+        .iter()
+        .filter_map(|input| {
+            let prev_tx: Transaction =
+                bitcoin::consensus::deserialize(&input.funding_input.prev_tx).ok()?;
+            Some(OutPoint::new(
+                prev_tx.compute_txid(),
+                input.funding_input.prev_tx_vout,
+            ))
+        })
+        .collect()
+}
+
+/// Store the clock of every negotiation being timed in its metadata, when it changed.
+pub(crate) fn save_negotiation_clocks<S: DdkStorage>(storage: &S, timer: &NegotiationTimer) {
+    for (contract_id, clock) in timer.clocks() {
+        let mut metadata = match storage.get_contract_metadata(contract_id) {
+            Ok(metadata) => metadata.unwrap_or_else(ContractMetadata::new),
+            Err(e) => {
+                tracing::error!(error = e.to_string(), "Could not get contract metadata.");
+                continue;
+            }
+        };
+        if metadata.negotiation == Some(*clock) {
+            continue;
+        }
+        metadata.negotiation = Some(*clock);
+        if let Err(e) = storage.set_contract_metadata(contract_id, metadata) {
+            tracing::error!(error = e.to_string(), "Could not save negotiation clock.");
+        }
+    }
+}
+
+/// Moves a stalled negotiation to rejected and releases our funding inputs. An accepted
+/// contract is rejected under its temporary id, so the record under its contract id is
+/// removed and its metadata moved back.
+pub(crate) fn fail_negotiation<S: DdkStorage, B: DdkBlockchain>(
+    storage: &S,
+    wallet: &DlcDevKitWallet<S, B>,
+    events: &EventBus,
+    contracts: &[Contract],
+    stalled: NegotiationTimedOut,
+) {
+    let Some(contract) = contracts.iter().find(|c| c.get_id() == stalled.contract_id) else {
+        return;
+    };
+    let Some(failed) = timed_out_contract(contract) else {
+        return;
+    };
+    if let Err(e) = end_negotiation(storage, wallet, contract, &failed) {
+        tracing::error!(error = e.to_string(), "Could not fail stalled negotiation.");
+        return;
+    }
+    tracing::warn!(
+        contract_id = hex::encode(stalled.contract_id),
+        counter_party = stalled.counterparty.to_string(),
+        stalled_state = stalled.stalled_state.to_string(),
+        "Negotiation timed out."
+    );
+    events.emit(DdkEvent::NegotiationTimedOut {
+        contract_id: stalled.contract_id,
+        stalled_state: stalled.stalled_state,
+    });
+}
+
+/// Stores the rejected contract `failed` a negotiation ends in and releases our funding
+/// inputs of `contract`. An accepted contract is rejected under its temporary id, so the
+/// record under its contract id is removed and its metadata moved back.
+pub(crate) fn end_negotiation<S: DdkStorage, B: DdkBlockchain>(
+    storage: &S,
+    wallet: &DlcDevKitWallet<S, B>,
+    contract: &Contract,
+    failed: &Contract,
+) -> Result<(), dlc_manager::error::Error> {
+    let contract_id = contract.get_id();
+    storage.update_contract(failed)?;
+    let metadata = storage.get_contract_metadata(&contract_id).ok().flatten();
+    if failed.get_id() != contract_id {
+        if let Err(e) = storage.delete_contract(&contract_id) {
+            tracing::error!(error = e.to_string(), "Could not remove the accepted contract.");
+        }
+    }
+    if let Some(mut metadata) = metadata {
+        metadata.negotiation = None;
+        if let Err(e) = storage.set_contract_metadata(&failed.get_id(), metadata) {
+            tracing::error!(error = e.to_string(), "Could not update contract metadata.");
+        }
+    }
+    if let Err(e) = dlc_manager::Wallet::unreserve_utxos(wallet, &own_funding_outpoints(contract)) {
+        tracing::error!(error = e.to_string(), "Could not release funding inputs.");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dlc_manager::contract::accepted_contract::AcceptedContract;
+    use dlc_manager::contract::offered_contract::OfferedContract;
+    use dlc_manager::contract::ser::Serializable;
+
+    fn deserialize_object<T: Serializable>(serialized: &[u8]) -> T {
+        let mut cursor = ::lightning::io::Cursor::new(&serialized);
+        T::deserialize(&mut cursor).unwrap()
+    }
+
+    fn outgoing_offer() -> Contract {
+        let mut offered: OfferedContract =
+            deserialize_object(include_bytes!("../../tests/data/dlc_storage/sled/Offered"));
+        offered.is_offer_party = true;
+        Contract::Offered(offered)
+    }
+
+    fn accepted() -> Contract {
+        Contract::Accepted(deserialize_object::<AcceptedContract>(include_bytes!(
+            "../../tests/data/dlc_storage/sled/Accepted"
+        )))
+    }
+
+    const TIMEOUTS: NegotiationTimeouts = NegotiationTimeouts {
+        offer_ttl: Duration::from_secs(3600),
+        sign_timeout: Duration::from_secs(600),
+        pause_when_offline: true,
+    };
+
+    #[test]
+    fn outgoing_offer_times_out_to_rejected() {
+        let contracts = vec![outgoing_offer()];
+        let mut timer = NegotiationTimer::default();
+        assert!(timer.check(&contracts, 0, &TIMEOUTS, |_| true, |_| None).is_empty());
+        assert!(timer.check(&contracts, 3599, &TIMEOUTS, |_| true, |_| None).is_empty());
+
+        let timed_out = timer.check(&contracts, 3600, &TIMEOUTS, |_| true, |_| None);
+        assert_eq!(timed_out.len(), 1);
+        assert_eq!(timed_out[0].stalled_state, ContractState::Offered);
+
+        let failed = timed_out_contract(&contracts[0]).unwrap();
+        assert_eq!(ContractState::from(&failed), ContractState::Rejected);
+        assert!(crate::contract::validate_transition(&contracts[0], &failed).is_ok());
+    }
+
+    #[test]
+    fn incoming_offer_never_times_out() {
+        let Contract::Offered(mut offered) = outgoing_offer() else {
+            unreachable!()
+        };
+        offered.is_offer_party = false;
+        let contracts = vec![Contract::Offered(offered)];
+        let mut timer = NegotiationTimer::default();
+        timer.check(&contracts, 0, &TIMEOUTS, |_| true, |_| None);
+        assert!(timer
+            .check(&contracts, 1_000_000, &TIMEOUTS, |_| true, |_| None)
+            .is_empty());
+    }
+
+    #[test]
+    fn accepted_times_out_to_rejected() {
+        let contracts = vec![accepted()];
+        let mut timer = NegotiationTimer::default();
+        timer.check(&contracts, 0, &TIMEOUTS, |_| true, |_| None);
+        let timed_out = timer.check(&contracts, 600, &TIMEOUTS, |_| true, |_| None);
+        assert_eq!(timed_out[0].stalled_state, ContractState::Accepted);
+
+        let failed = timed_out_contract(&contracts[0]).unwrap();
+        assert_eq!(ContractState::from(&failed), ContractState::Rejected);
+        assert_eq!(failed.get_id(), contracts[0].get_temporary_id());
+        assert!(crate::contract::validate_transition(&contracts[0], &failed).is_ok());
+        assert!(!own_funding_outpoints(&contracts[0]).is_empty());
+    }
+
+    #[test]
+    fn offline_time_does_not_count() {
+        let contracts = vec![accepted()];
+        let mut timer = NegotiationTimer::default();
+        timer.check(&contracts, 0, &TIMEOUTS, |_| true, |_| None);
+        assert!(timer
+            .check(&contracts, 10_000, &TIMEOUTS, |_| false, |_| None)
+            .is_empty());
+        assert!(timer
+            .check(&contracts, 10_599, &TIMEOUTS, |_| true, |_| None)
+            .is_empty());
+        assert_eq!(
+            timer
+                .check(&contracts, 10_600, &TIMEOUTS, |_| true, |_| None)
+                .len(),
+            1
+        );
+
+        let always = NegotiationTimeouts {
+            pause_when_offline: false,
+            ..TIMEOUTS
+        };
+        let mut timer = NegotiationTimer::default();
+        timer.check(&contracts, 0, &always, |_| false, |_| None);
+        assert_eq!(
+            timer
+                .check(&contracts, 600, &always, |_| false, |_| None)
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn persisted_clock_survives_a_restart() {
+        let contracts = vec![accepted()];
+        let mut timer = NegotiationTimer::default();
+        timer.check(&contracts, 0, &TIMEOUTS, |_| true, |_| None);
+        timer.check(&contracts, 500, &TIMEOUTS, |_| true, |_| None);
+        let (_, clock) = timer.clocks().next().unwrap();
+        let clock = *clock;
+        assert_eq!(clock.elapsed, 500);
+        assert_eq!(clock.started_at, 0);
+
+        // Restarted an hour later. The downtime does not count, the time before it does.
+        let mut restarted = NegotiationTimer::default();
+        assert!(restarted
+            .check(&contracts, 4_100, &TIMEOUTS, |_| true, |_| Some(clock))
+            .is_empty());
+        assert_eq!(
+            restarted
+                .check(&contracts, 4_200, &TIMEOUTS, |_| true, |_| None)
+                .len(),
+            1
+        );
+
+        // A clock of another state starts over.
+        let offered = NegotiationClock {
+            state: ContractState::Offered,
+            ..clock
+        };
+        let mut restarted = NegotiationTimer::default();
+        restarted.check(&contracts, 4_100, &TIMEOUTS, |_| true, |_| Some(offered));
+        assert!(restarted
+            .check(&contracts, 4_200, &TIMEOUTS, |_| true, |_| None)
+            .is_empty());
+    }
+}
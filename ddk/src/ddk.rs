@@ -1,29 +1,108 @@
+use crate::accounting::{is_settled, settlement, PnlReport, TimeRange};
+use crate::audit::{self, write_json_lines, AuditEntry};
+use crate::bootstrap::BootstrapInfo;
+use crate::chain::rebroadcast::{rebroadcast_pending, BroadcastTracker, PendingBroadcast, RebroadcastOptions};
+use crate::chain::watch::{funding_outpoint, is_cet, ChainWatchOptions, ChainWatchState};
 use crate::chain::EsploraClient;
-use crate::wallet::DlcDevKitWallet;
-use crate::{DdkOracle, DdkStorage, DdkTransport};
+use crate::channel::{ChannelState, ChannelSummary};
+use crate::config::DdkConfig;
+use crate::contract::locktimes::{
+    suggest_locktimes, validate_locktimes, ChainTip, SuggestedLocktimes, DEFAULT_REFUND_DELAY,
+};
+use crate::contract::progress::{
+    cet_count, AcceptOptions, NegotiationPhase, ProgressCallback, ProgressReporter,
+};
+use crate::contract::timeout::{
+    fail_negotiation, own_funding_outpoints, save_negotiation_clocks, NegotiationTimeouts,
+    NegotiationTimer,
+};
+use crate::contract::cancel::{
+    cancelled_contract, is_cancelled_offer, on_contract_reject, rejected_contract, send_reject, CancelError,
+};
+use crate::contract::close::{
+    attesting_oracles, cet_txid, confirmed_contract, indexed_attestations, CloseError,
+};
+use crate::contract::confirmations::{
+    confirmation_change, conflicting_spend, double_spent_contract, AcceptanceParams, ConfirmationChange,
+    ConfirmationPolicy,
+};
+use crate::contract::maturity::{upcoming_maturities, MaturityInfo, MaturityWatch};
+use crate::contract::metadata::ContractMetadata;
+use crate::contract::refund::{
+    check_refund_locktime, refundable, refundable_contract, signed_refund, RefundError,
+};
+use crate::contract::policy::{OfferDecision, OfferPolicy};
+use crate::contract::summary::{awaiting_settlement, ContractDetails, ContractSummary};
+use crate::contract::{collateral_split, is_fully_signed, ContractState, FundingBroadcastRole};
+use crate::dispatch::{
+    process_by_peer, ContractLocks, LockKey, RecentMessages, RequestPool, RECENT_MESSAGES_PER_PEER,
+};
+use crate::events::{contract_states, newly_closed, state_change_events, DdkEvent, EventBus};
+use crate::logging;
+use crate::metrics::{self, OfferOutcome};
+use crate::oracle::cache::{referenced_announcements, AnnouncementCache};
+use crate::oracle::caching::CachingOracle;
+use crate::oracle::set::{announcements_for_input, OracleSet};
+use crate::oracle::verify::{verify_contract_attestations, VerifyingOracle};
+use crate::rates::{ContractRates, RatePoint, RateProvider, RateRecorder};
+use crate::recovery::RecoveryReport;
+use crate::risk::{RiskLimits, RiskUtilization};
+use crate::status::{instant_to_unix, DdkStatus, StatusTracker, StorageHealth};
+use crate::storage::{
+    ArchivePolicy, ArchiveReport, ContractFilter, IntegrityReport, SignerVacuumOptions,
+    SignerVacuumReport, StorageStats,
+};
+use crate::transport::rate_limit::{PeerBan, PeerLimits, PeerRateLimiter};
+use crate::transport::inbound::{
+    ack_inbound, fail_inbound, journal_inbound, limit_inbound, pending_inbound, save_inbound_cursor,
+};
+use crate::transport::outbox::{self, send_outbound, send_pending, Outbox};
+use crate::transport::retry::{self, OutboundRetryOptions};
+use crate::transport::{
+    message_contract_id, message_id, message_kind, reconnect, PeerInformation, PendingOutbound,
+    TransportEvent,
+};
+use crate::validation::validate_contract_input;
+use crate::wallet::history::{contract_transactions, ContractTransactionKind};
+use crate::wallet::sync::sync_loop;
+use crate::wallet::{AddressProof, DlcDevKitWallet, SyncStatus};
+use crate::error::{OracleError, StorageError, WalletError};
+use crate::{DdkBlockchain, DdkError, DdkOracle, DdkStorage, DdkTransport};
 use anyhow::anyhow;
-use bitcoin::secp256k1::PublicKey;
-use bitcoin::Network;
+use bitcoin::key::XOnlyPublicKey;
+use bitcoin::secp256k1::{PublicKey, Secp256k1};
+use bitcoin::{Address, Amount, FeeRate, Network, Transaction, Txid};
+use dlc_manager::channel::signed_channel::{SignedChannel, SignedChannelState, SignedChannelStateType};
+use dlc_manager::channel::Channel;
+use dlc_manager::contract::signed_contract::SignedContract;
+use dlc_manager::contract::{Contract, ContractDescriptor, PreClosedContract};
 use dlc_manager::{
-    contract::contract_input::ContractInput, CachedContractSignerProvider, ContractId,
-    SimpleSigner, SystemTimeProvider,
+    contract::contract_input::{ContractInput, ContractInputInfo, OracleInput}, Blockchain,
+    CachedContractSignerProvider, ChannelId, ContractId, ContractSigner, ContractSignerProvider,
+    Oracle, SimpleSigner, Storage, SystemTimeProvider, Time,
 };
-use dlc_messages::oracle_msgs::OracleAnnouncement;
+use dlc_messages::channel::{ChannelMessage, Reject};
+use dlc_messages::oracle_msgs::{OracleAnnouncement, OracleAttestation};
 use dlc_messages::{AcceptDlc, Message, OfferDlc};
-use std::sync::{Arc, RwLock};
-use std::time::Duration;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tokio::runtime::Runtime;
-use crossbeam::channel::{unbounded, Sender, Receiver};
+use crossbeam::channel::{unbounded, Sender, Receiver, RecvTimeoutError};
+use tokio::sync::oneshot;
 
-/// DlcDevKit type alias for the [dlc_manager::manager::Manager]
-pub type DlcDevKitDlcManager<S, O> = dlc_manager::manager::Manager<
-    Arc<DlcDevKitWallet<S>>,
-    Arc<CachedContractSignerProvider<Arc<DlcDevKitWallet<S>>, SimpleSigner>>,
-    Arc<EsploraClient>,
+/// DlcDevKit type alias for the [dlc_manager::manager::Manager]. Its oracles answer from the
+/// node's caches and verify attestations before the manager settles with them, and the
+/// transactions it broadcasts are rebroadcast until they confirm.
+pub type DlcDevKitDlcManager<S, O, B = EsploraClient> = dlc_manager::manager::Manager<
+    Arc<DlcDevKitWallet<S, B>>,
+    Arc<CachedContractSignerProvider<Arc<DlcDevKitWallet<S, B>>, SimpleSigner>>,
+    Arc<BroadcastTracker<B, S>>,
     Arc<S>,
-    Arc<O>,
+    Arc<VerifyingOracle<CachingOracle<O, S>, S>>,
     Arc<SystemTimeProvider>,
-    Arc<DlcDevKitWallet<S>>,
+    Arc<DlcDevKitWallet<S, B>>,
     SimpleSigner,
 >;
 
@@ -31,64 +110,320 @@ pub type DlcDevKitDlcManager<S, O> = dlc_manager::manager::Manager<
 pub enum DlcManagerMessage {
     AcceptDlc {
         contract: ContractId,
-        responder: Sender<(ContractId, PublicKey, AcceptDlc)>
+        options: AcceptOptions,
+        responder: Responder<Result<(ContractId, PublicKey, AcceptDlc), dlc_manager::error::Error>>,
     },
     OfferDlc {
         contract_input: ContractInput,
         counter_party: PublicKey,
-        oracle_announcements: Vec<OracleAnnouncement>,
-        responder: Sender<OfferDlc>,
+        /// Announcements for each contract info of the input.
+        oracle_announcements: Vec<Vec<OracleAnnouncement>>,
+        responder: Responder<Result<OfferDlc, dlc_manager::error::Error>>,
     },
     ProcessMessages,
+    /// Something the transport reported from [DdkTransport::start].
+    Transport(TransportEvent),
+    /// Withdraw an offer we sent that has not been accepted.
+    CancelOffer {
+        contract_id: ContractId,
+        responder: Sender<Result<(), CancelError>>,
+    },
+    /// Decline an offer without accepting it.
+    RejectDlc {
+        contract_id: ContractId,
+        responder: Sender<Result<(), CancelError>>,
+    },
+    /// Broadcast the CET of a confirmed contract with the attestations, indexed by the position
+    /// of their oracle in the contract info.
+    CloseContract {
+        contract_id: ContractId,
+        attestations: Vec<(usize, OracleAttestation)>,
+        responder: Sender<Result<Txid, CloseError>>,
+    },
+    /// Broadcast the refund transaction of a confirmed contract past its refund locktime.
+    RefundContract {
+        contract_id: ContractId,
+        responder: Sender<Result<Txid, RefundError>>,
+    },
+    /// Offer a DLC channel funded with the first contract of `contract_input`.
+    OfferChannel {
+        contract_input: ContractInput,
+        counter_party: PublicKey,
+        responder: Sender<Result<(ChannelId, ContractId), dlc_manager::error::Error>>,
+    },
+    /// Accept a channel offer. Answers with the final channel and contract ids.
+    AcceptChannel {
+        channel_id: ChannelId,
+        responder: Sender<Result<(ChannelId, ContractId), dlc_manager::error::Error>>,
+    },
+    /// Offer to settle the channel's contract, paying `counter_payout` to the counterparty.
+    SettleChannel {
+        channel_id: ChannelId,
+        counter_payout: u64,
+        responder: Sender<Result<(), dlc_manager::error::Error>>,
+    },
+    /// Offer a new contract in the channel. Answers with the temporary id of the contract.
+    RenewChannel {
+        channel_id: ChannelId,
+        counter_payout: u64,
+        contract_input: ContractInput,
+        responder: Sender<Result<ContractId, dlc_manager::error::Error>>,
+    },
+    /// Accept the settle, renew, or collaborative close offer the counterparty made.
+    AcceptChannelUpdate {
+        channel_id: ChannelId,
+        responder: Sender<Result<(), dlc_manager::error::Error>>,
+    },
+    /// Offer to close a settled channel with its settled balances.
+    CloseChannel {
+        channel_id: ChannelId,
+        responder: Sender<Result<(), dlc_manager::error::Error>>,
+    },
+    /// Fail negotiations that waited on the counterparty for too long.
+    TimeoutNegotiations,
+    /// Let the manager check the chain for confirmations and closes. With a responder the
+    /// caller waits for the check to finish.
+    PeriodicCheck {
+        responder: Option<Sender<Result<(), dlc_manager::error::Error>>>,
+    },
+    /// The chain watcher found a CET of a confirmed contract that the counterparty broadcast.
+    CetSeen {
+        contract_id: ContractId,
+        cet: Transaction,
+    },
+}
+
+/// Where the manager thread sends the answer to a request. Blocking callers wait on a crossbeam
+/// channel and async callers on a oneshot, so they do not hold a runtime worker.
+#[derive(Debug)]
+pub enum Responder<R> {
+    Blocking(Sender<R>),
+    Async(oneshot::Sender<R>),
+    /// Nobody waits for the answer, as for requests the manager enqueues itself.
+    Detached,
+}
+
+impl<R> Responder<R> {
+    /// Send the answer, handing it back if the requester went away.
+    pub fn send(self, response: R) -> Result<(), R> {
+        match self {
+            Responder::Blocking(sender) => sender.send(response).map_err(|e| e.0),
+            Responder::Async(sender) => sender.send(response),
+            Responder::Detached => Ok(()),
+        }
+    }
+}
+
+/// The funding input a message waits on when the manager failed because the external signer
+/// has not answered yet.
+fn awaited_signature(e: &dlc_manager::error::Error) -> Option<(Txid, usize)> {
+    match e {
+        dlc_manager::error::Error::WalletError(e) => match e.downcast_ref::<WalletError>() {
+            Some(WalletError::AwaitingSignature { txid, input_index }) => Some((*txid, *input_index)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Wait up to `timeout` for the manager thread to answer a request to do `action`.
+fn wait_for_manager<R>(receiver: Receiver<R>, timeout: Duration, action: &str) -> Result<R, DdkError> {
+    match receiver.recv_timeout(timeout) {
+        Ok(response) => Ok(response),
+        Err(RecvTimeoutError::Timeout) => Err(DdkError::ManagerUnresponsive { waited: timeout }),
+        Err(RecvTimeoutError::Disconnected) => Err(DdkError::ManagerStopped { action: action.to_string() }),
+    }
 }
 
-pub struct DlcDevKit<T: DdkTransport, S: DdkStorage, O: DdkOracle> {
-    pub runtime: Arc<RwLock<Option<Runtime>>>,
-    pub wallet: Arc<DlcDevKitWallet<S>>,
-    pub manager: Arc<DlcDevKitDlcManager<S, O>>,
-    pub sender: Arc<Sender<DlcManagerMessage>>,
-    pub receiver: Arc<Receiver<DlcManagerMessage>>,
-    pub transport: Arc<T>,
-    pub storage: Arc<S>,
-    pub oracle: Arc<O>,
-    pub network: Network,
+/// [wait_for_manager] for async callers.
+async fn wait_for_manager_async<R>(
+    receiver: oneshot::Receiver<R>,
+    timeout: Duration,
+    action: &str,
+) -> Result<R, DdkError> {
+    match tokio::time::timeout(timeout, receiver).await {
+        Ok(Ok(response)) => Ok(response),
+        Ok(Err(_)) => Err(DdkError::ManagerStopped { action: action.to_string() }),
+        Err(_) => Err(DdkError::ManagerUnresponsive { waited: timeout }),
+    }
+}
+
+pub struct DlcDevKit<T: DdkTransport, S: DdkStorage, O: DdkOracle, B: DdkBlockchain = EsploraClient> {
+    pub(crate) runtime: Arc<RwLock<Option<Runtime>>>,
+    pub(crate) wallet: Arc<DlcDevKitWallet<S, B>>,
+    pub(crate) manager: Arc<DlcDevKitDlcManager<S, O, B>>,
+    pub(crate) sender: Arc<Sender<DlcManagerMessage>>,
+    pub(crate) receiver: Arc<Receiver<DlcManagerMessage>>,
+    pub(crate) transport: Arc<T>,
+    /// Messages for the transport, delivered in order once the node runs.
+    pub(crate) outbox: Arc<Outbox>,
+    pub(crate) storage: Arc<S>,
+    /// The primary oracle, used for announcement lookups that do not name an oracle.
+    pub(crate) oracle: Arc<O>,
+    pub(crate) oracles: OracleSet<O>,
+    pub(crate) network: Network,
+    pub(crate) channel_reserve_sats: u64,
+    pub(crate) rate_provider: Arc<dyn RateProvider>,
+    pub(crate) fiat_currency: String,
+    pub(crate) risk_limits: RiskLimits,
+    pub(crate) offer_policy: Arc<dyn OfferPolicy>,
+    pub(crate) auto_refund: bool,
+    pub(crate) funding_broadcast_window: Duration,
+    pub(crate) announcement_cache: Arc<Mutex<AnnouncementCache>>,
+    pub(crate) signer_vacuum: SignerVacuumOptions,
+    pub(crate) archive_policy: Option<ArchivePolicy>,
+    pub(crate) negotiation_timeouts: NegotiationTimeouts,
+    pub(crate) message_workers: usize,
+    pub(crate) peer_limits: PeerLimits,
+    pub(crate) fee_refresh_interval: Duration,
+    pub(crate) periodic_check_interval: Duration,
+    pub(crate) chain_watch: ChainWatchOptions,
+    /// Transactions broadcast by the node, rebroadcast until they confirm.
+    pub(crate) broadcasts: Arc<BroadcastTracker<B, S>>,
+    pub(crate) rebroadcast: RebroadcastOptions,
+    pub(crate) outbound_retry: OutboundRetryOptions,
+    /// Liveness of the background tasks for [DlcDevKit::status].
+    pub(crate) status: Arc<StatusTracker>,
+    pub(crate) confirmation_policy: ConfirmationPolicy,
+    pub(crate) maturity_notice: Duration,
+    pub(crate) wallet_sync_interval: Duration,
+    pub(crate) wallet_sync_warning_after: Duration,
+    pub(crate) recovery_stop_gap: usize,
+    pub(crate) manager_response_timeout: Duration,
+    /// Progress of verifying the counterparty's signatures when an offer we sent is accepted.
+    pub(crate) sign_progress: Arc<RwLock<Option<ProgressCallback>>>,
+    /// Contract lifecycle and peer events.
+    pub(crate) events: Arc<EventBus>,
+}
+
+/// Handles the manager thread shares with the node.
+struct ManagerContext<T: DdkTransport, S: DdkStorage, O: DdkOracle, B: DdkBlockchain> {
+    manager: Arc<DlcDevKitDlcManager<S, O, B>>,
+    transport: Arc<T>,
+    outbox: Arc<Outbox>,
+    wallet: Arc<DlcDevKitWallet<S, B>>,
+    broadcasts: Arc<BroadcastTracker<B, S>>,
+    sender: Arc<Sender<DlcManagerMessage>>,
+    events: Arc<EventBus>,
+    rates: RateRecorder,
+    sign_progress: Arc<RwLock<Option<ProgressCallback>>>,
+}
+
+/// How the manager thread decides on offers, settles contracts and limits peers.
+struct ManagerSettings {
+    offer_policy: Arc<dyn OfferPolicy>,
+    risk_limits: RiskLimits,
+    auto_refund: bool,
+    confirmation_policy: ConfirmationPolicy,
+    maturity_notice: Duration,
+    channel_reserve_sats: u64,
+    negotiation_timeouts: NegotiationTimeouts,
+    message_workers: usize,
+    peer_limits: PeerLimits,
 }
 
-impl<T, S, O> DlcDevKit<T, S, O>
+impl<T, S, O, B> DlcDevKit<T, S, O, B>
 where 
-    T: DdkTransport, S: DdkStorage, O: DdkOracle
+    T: DdkTransport, S: DdkStorage, O: DdkOracle, B: DdkBlockchain
 {
-    pub fn start(&self) -> anyhow::Result<()> {
+    /// Inspect the config and data directory before building, without creating anything.
+    pub fn bootstrap_info(config: &DdkConfig) -> BootstrapInfo {
+        crate::bootstrap::bootstrap_info(config)
+    }
+
+    pub fn start(&self) -> Result<(), DdkError> {
         let mut runtime_lock = self.runtime.write().unwrap();
 
         if runtime_lock.is_some() {
-            return Err(anyhow!("DDK is still running."));
+            return Err(DdkError::AlreadyRunning);
         }
 
         let runtime = tokio::runtime::Builder::new_multi_thread()
             .enable_all()
-            .build()?;
+            .build()
+            .map_err(|e| DdkError::Other(e.into()))?;
 
         
-        let manager_transport = self.transport.clone();
-        let manager_clone = self.manager.clone();
+        let context = ManagerContext {
+            manager: self.manager.clone(),
+            transport: self.transport.clone(),
+            outbox: self.outbox.clone(),
+            wallet: self.wallet.clone(),
+            broadcasts: self.broadcasts.clone(),
+            sender: self.sender.clone(),
+            events: self.events.clone(),
+            rates: RateRecorder::new(
+                self.rate_provider.clone(),
+                self.fiat_currency.clone(),
+                runtime.handle().clone(),
+            ),
+            sign_progress: self.sign_progress.clone(),
+        };
+        let settings = ManagerSettings {
+            offer_policy: self.offer_policy.clone(),
+            risk_limits: self.risk_limits,
+            auto_refund: self.auto_refund,
+            confirmation_policy: self.confirmation_policy.clone(),
+            maturity_notice: self.maturity_notice,
+            channel_reserve_sats: self.channel_reserve_sats,
+            negotiation_timeouts: self.negotiation_timeouts,
+            message_workers: self.message_workers,
+            peer_limits: self.peer_limits,
+        };
         let receiver_clone = self.receiver.clone();
-        std::thread::spawn(move || Self::run_manager(manager_clone, manager_transport, receiver_clone));
+        std::thread::spawn(move || Self::run_manager(context, settings, receiver_clone));
+
+        // Transport events are handled on the manager thread, in order with its other work.
+        let (event_sink, transport_events) = unbounded();
+        let event_forwarder = self.sender.clone();
+        std::thread::spawn(move || {
+            for event in transport_events {
+                if event_forwarder.send(DlcManagerMessage::Transport(event)).is_err() {
+                    return;
+                }
+            }
+        });
 
+        match self.storage.get_transport_cursor(&self.transport.name()) {
+            Ok(Some(cursor)) => self.transport.resume_inbound(cursor),
+            Ok(None) => {}
+            Err(e) => tracing::error!(error = e.to_string(), "Could not read transport cursor."),
+        }
         let transport_clone = self.transport.clone();
+        let listen_status = self.status.clone();
         runtime.spawn(async move {
-            transport_clone.listen().await;
+            listen_status.set_listening(true);
+            transport_clone.start(event_sink).await;
+            listen_status.set_listening(false);
         });
 
+        if let Some(queue) = self.outbox.take_queue() {
+            runtime.spawn(outbox::deliver(self.transport.clone(), self.storage.clone(), queue));
+        }
+
         let wallet_clone = self.wallet.clone();
+        runtime.spawn(sync_loop(
+            move || wallet_clone.sync(),
+            self.wallet.sync_tracker(),
+            self.events.clone(),
+            self.wallet_sync_interval,
+            self.wallet_sync_warning_after,
+        ));
+
+        let fee_wallet = self.wallet.clone();
+        let fee_refresh_interval = self.fee_refresh_interval;
         runtime.spawn(async move {
-            let mut timer = tokio::time::interval(Duration::from_secs(10));
+            let mut timer = tokio::time::interval(fee_refresh_interval);
             loop {
                 timer.tick().await;
-                wallet_clone.sync().unwrap();
+                if let Err(e) = fee_wallet.update_fee_estimates() {
+                    tracing::warn!(error = e.to_string(), "Could not refresh fee estimates.");
+                }
             }
         });
 
+        // Received messages are processed as the transport reports them. The timer retries
+        // messages waiting for an external signer.
         let processor = self.sender.clone();
         runtime.spawn(async move {
             let mut timer = tokio::time::interval(Duration::from_secs(5));
@@ -98,100 +433,3506 @@ where
             }
         });
 
-        // TODO: connect stored peers.
+        let timeout_processor = self.sender.clone();
+        runtime.spawn(async move {
+            let mut timer = tokio::time::interval(Duration::from_secs(30));
+            loop {
+                timer.tick().await;
+                if timeout_processor.send(DlcManagerMessage::TimeoutNegotiations).is_err() {
+                    tracing::error!("DDK manager stopped. Stopping negotiation timeouts.");
+                    return;
+                }
+            }
+        });
+
+        let check_processor = self.sender.clone();
+        let periodic_check_interval = self.periodic_check_interval;
+        runtime.spawn(async move {
+            let mut timer = tokio::time::interval(periodic_check_interval);
+            loop {
+                timer.tick().await;
+                if check_processor.send(DlcManagerMessage::PeriodicCheck { responder: None }).is_err() {
+                    tracing::error!("DDK manager stopped. Stopping periodic checks.");
+                    return;
+                }
+            }
+        });
+
+        let funding_storage = self.storage.clone();
+        let funding_blockchain = self.wallet.blockchain.clone();
+        let funding_broadcasts = self.broadcasts.clone();
+        let funding_events = self.events.clone();
+        let funding_broadcast_window = self.funding_broadcast_window;
+        runtime.spawn(async move {
+            let period = funding_broadcast_window.clamp(Duration::from_millis(100), Duration::from_secs(30));
+            let mut timer = tokio::time::interval(period);
+            let mut missing_since = HashMap::new();
+            loop {
+                timer.tick().await;
+                let (storage, blockchain, broadcasts, events) = (
+                    funding_storage.clone(),
+                    funding_blockchain.clone(),
+                    funding_broadcasts.clone(),
+                    funding_events.clone(),
+                );
+                // The chain client blocks on esplora.
+                let checked = tokio::task::spawn_blocking(move || {
+                    Self::check_funding_broadcasts(
+                        &storage,
+                        &blockchain,
+                        &broadcasts,
+                        &events,
+                        funding_broadcast_window,
+                        &mut missing_since,
+                    );
+                    missing_since
+                })
+                .await;
+                missing_since = match checked {
+                    Ok(missing_since) => missing_since,
+                    Err(e) => {
+                        tracing::error!(error = e.to_string(), "Funding broadcast check failed.");
+                        HashMap::new()
+                    }
+                };
+            }
+        });
+
+        let rebroadcaster = self.broadcasts.clone();
+        let rebroadcast_events = self.events.clone();
+        let rebroadcast = self.rebroadcast;
+        runtime.spawn(async move {
+            let mut timer = tokio::time::interval(rebroadcast.interval);
+            loop {
+                timer.tick().await;
+                rebroadcast_pending(&rebroadcaster, &rebroadcast_events, &rebroadcast);
+            }
+        });
+
+        let retry_storage = self.storage.clone();
+        let retry_outbox = self.outbox.clone();
+        let retry_events = self.events.clone();
+        let outbound_retry = self.outbound_retry;
+        runtime.spawn(async move {
+            let mut timer = tokio::time::interval(outbound_retry.interval);
+            loop {
+                timer.tick().await;
+                retry::retry_outbound(retry_storage.as_ref(), &retry_outbox, &retry_events, &outbound_retry);
+            }
+        });
+
+        let watch_storage = self.storage.clone();
+        let watch_blockchain = self.wallet.blockchain.clone();
+        let watch_sender = self.sender.clone();
+        let watch_events = self.events.clone();
+        let watch_status = self.status.clone();
+        let chain_watch = self.chain_watch;
+        runtime.spawn(async move {
+            let mut timer = tokio::time::interval(chain_watch.interval);
+            let mut state = ChainWatchState::default();
+            loop {
+                timer.tick().await;
+                Self::watch_chain(
+                    &watch_storage,
+                    &watch_blockchain,
+                    &watch_sender,
+                    &watch_events,
+                    &watch_status,
+                    &chain_watch,
+                    &mut state,
+                );
+            }
+        });
+
+        let vacuum_storage = self.storage.clone();
+        let vacuum_cache = self.announcement_cache.clone();
+        runtime.spawn(async move {
+            let mut timer = tokio::time::interval(Duration::from_secs(600));
+            loop {
+                timer.tick().await;
+                if let Err(e) = Self::vacuum_announcement_cache(&vacuum_storage, &vacuum_cache) {
+                    tracing::error!(error = e.to_string(), "Could not vacuum announcement cache.");
+                }
+            }
+        });
+
+        let signer_storage = self.storage.clone();
+        let signer_vacuum = self.signer_vacuum.clone();
+        runtime.spawn(async move {
+            let mut timer = tokio::time::interval(Duration::from_secs(60 * 60 * 24));
+            loop {
+                timer.tick().await;
+                match signer_storage.vacuum_signers(&signer_vacuum) {
+                    Ok(report) => tracing::info!(
+                        removed = report.removed.len(),
+                        kept = report.kept,
+                        unattributed = report.unattributed,
+                        "Vacuumed signers."
+                    ),
+                    Err(e) => tracing::error!(error = e.to_string(), "Could not vacuum signers."),
+                }
+            }
+        });
+
+        if let Some(policy) = self.archive_policy.clone() {
+            let archive_storage = self.storage.clone();
+            runtime.spawn(async move {
+                let mut timer = tokio::time::interval(policy.interval);
+                loop {
+                    timer.tick().await;
+                    match Self::apply_archive_policy(&archive_storage, &policy) {
+                        Ok(report) => tracing::info!(
+                            archived = report.archived,
+                            pruned = report.pruned,
+                            "Archived finished contracts."
+                        ),
+                        Err(e) => tracing::error!(error = e.to_string(), "Could not archive contracts."),
+                    }
+                }
+            });
+        }
+
+        match self.storage.list_peers() {
+            Ok(peers) => {
+                for peer in peers {
+                    runtime.spawn(reconnect::connect_with_backoff(
+                        self.transport.clone(),
+                        self.events.clone(),
+                        peer,
+                        reconnect::INITIAL_RETRY_DELAY,
+                        reconnect::MAX_RETRY_DELAY,
+                    ));
+                }
+            }
+            Err(e) => tracing::error!(error = e.to_string(), "Could not list stored peers."),
+        }
 
         *runtime_lock = Some(runtime);
 
         Ok(())
     }
 
-    fn run_manager(manager: Arc<DlcDevKitDlcManager<S, O>>, transport: Arc<T>, receiver: Arc<Receiver<DlcManagerMessage>>) {
+    fn run_manager(
+        context: ManagerContext<T, S, O, B>,
+        settings: ManagerSettings,
+        receiver: Arc<Receiver<DlcManagerMessage>>,
+    ) {
+        let ManagerContext {
+            manager,
+            transport,
+            outbox,
+            wallet,
+            broadcasts,
+            sender,
+            events,
+            rates,
+            sign_progress,
+        } = context;
+        let ManagerSettings {
+            offer_policy,
+            risk_limits,
+            auto_refund,
+            confirmation_policy,
+            maturity_notice,
+            channel_reserve_sats,
+            negotiation_timeouts,
+            message_workers,
+            peer_limits,
+        } = settings;
+        let mut negotiation_timer = NegotiationTimer::default();
+        let mut maturity_watch = MaturityWatch::new(maturity_notice);
+        let mut rate_limiter = PeerRateLimiter::new(peer_limits);
+        let contract_locks = Arc::new(ContractLocks::default());
+        // Offers and accepts run here, so received messages and other negotiations go on
+        // while their adaptor signatures are created.
+        let requests = RequestPool::new(message_workers);
+        let recent_messages = Mutex::new(RecentMessages::new(RECENT_MESSAGES_PER_PEER));
+
+        // Messages that were produced but never handed to the transport before shutdown.
+        retry::resend_pending(manager.get_store().as_ref(), &outbox, None);
+
+        // Messages that were received but not processed before shutdown. Replayed with the
+        // first batch.
+        let mut replay = pending_inbound(manager.get_store().as_ref());
+        // Messages the transport reported since the last batch.
+        let mut inbound: Vec<(PublicKey, Message)> = vec![];
+        // Messages waiting for an external signer, retried every round until it answers.
+        let awaiting_signature: Mutex<HashMap<String, (PublicKey, Message)>> = Mutex::new(HashMap::new());
+
         while let Ok(msg) = receiver.recv() {
             match msg {
                 DlcManagerMessage::OfferDlc { contract_input, counter_party, oracle_announcements, responder } => {
-                    let offer = manager.send_offer_with_announcements(&contract_input, counter_party, vec![oracle_announcements]).expect("can't create offerdlc");
-                    responder.send(offer).expect("send offer error")
+                    let manager = manager.clone();
+                    let events = events.clone();
+                    // Takes no contract lock. The offer creates its temporary id, so no message
+                    // or request can reference the contract before the offer is stored, and
+                    // concurrent offers get their own signer keys from the wallet's counter.
+                    requests.spawn(move || {
+                        let span = logging::offer_span(&counter_party);
+                        let _entered = span.enter();
+                        let offer = manager.send_offer_with_announcements(&contract_input, counter_party, oracle_announcements);
+                        match &offer {
+                            Ok(offer) => {
+                                logging::record_temporary_id(&span, &offer.temporary_contract_id);
+                                tracing::info!("Created offer.");
+                                events.emit(DdkEvent::ContractOffered(offer.temporary_contract_id))
+                            }
+                            Err(e) => tracing::error!(
+                                counter_party = counter_party.to_string(),
+                                error = e.to_string(),
+                                "Could not create offer."
+                            ),
+                        }
+                        if responder.send(offer).is_err() {
+                            tracing::warn!("Offer requester went away before the offer was created.");
+                        }
+                    });
                 },
-                DlcManagerMessage::AcceptDlc { contract, responder } => {
-                    let accept = manager.accept_contract_offer(&contract).expect("can't accept offer");
-                    responder.send(accept).expect("can't send")
+                DlcManagerMessage::AcceptDlc { contract, options, responder } => {
+                    let manager = manager.clone();
+                    let outbox = outbox.clone();
+                    let rates = rates.clone();
+                    let events = events.clone();
+                    let contract_locks = contract_locks.clone();
+                    requests.spawn(move || contract_locks.with_lock(LockKey::Contract(contract), || {
+                        let span = logging::accept_span(&contract);
+                        let _entered = span.enter();
+                        let total = match manager.get_store().get_contract(&contract) {
+                            Ok(Some(Contract::Offered(offered))) => cet_count(&offered),
+                            _ => 0,
+                        };
+                        // The manager creates every adaptor signature in one call, so only the
+                        // start and end of each phase are reported.
+                        let mut progress = ProgressReporter::new(options.progress).with_events(events.clone(), contract);
+                        let accept = progress
+                            .phase(NegotiationPhase::VerifyingCets, total, || manager.accept_contract_offer(&contract));
+                        match &accept {
+                            Ok((contract_id, counter_party, accept_dlc)) => {
+                                logging::record_contract_id(&span, contract_id);
+                                progress.phase(NegotiationPhase::BuildingFunding, 1, || ());
+                                progress.phase(NegotiationPhase::Signing, 1, || ());
+                                let message = Message::Accept(accept_dlc.clone());
+                                let pending = PendingOutbound::new(*counter_party, &message).for_contract(*contract_id);
+                                send_outbound(manager.get_store().as_ref(), &outbox, pending, message);
+                                rates.record(manager.get_store().clone(), *contract_id, RatePoint::Accept);
+                                metrics::offer(OfferOutcome::Accepted);
+                                events.emit(DdkEvent::ContractAccepted(*contract_id));
+                            }
+                            Err(e) => tracing::error!(
+                                contract_id = hex::encode(contract),
+                                error = e.to_string(),
+                                "Could not accept offer."
+                            ),
+                        }
+                        if responder.send(accept).is_err() {
+                            tracing::warn!("Accept requester went away before the offer was accepted.");
+                        }
+                    }));
+                }
+                DlcManagerMessage::CancelOffer { contract_id, responder } => {
+                    let cancelled = Self::close_offer_in_store(&manager, &wallet, contract_id, cancelled_contract);
+                    if cancelled.is_ok() {
+                        match manager.get_store().get_contract(&contract_id) {
+                            Ok(Some(contract)) => {
+                                send_reject(manager.get_store().as_ref(), &outbox, contract.get_counter_party_id(), contract_id)
+                            }
+                            _ => tracing::error!(
+                                contract_id = hex::encode(contract_id),
+                                "Could not find cancelled offer to tell the counterparty."
+                            ),
+                        }
+                    }
+                    if responder.send(cancelled).is_err() {
+                        tracing::warn!("Cancel requester went away before the offer was cancelled.");
+                    }
+                }
+                DlcManagerMessage::RejectDlc { contract_id, responder } => {
+                    let rejected = Self::close_offer_in_store(&manager, &wallet, contract_id, rejected_contract);
+                    if rejected.is_ok() {
+                        metrics::offer(OfferOutcome::Rejected);
+                    }
+                    if responder.send(rejected).is_err() {
+                        tracing::warn!("Reject requester went away before the offer was rejected.");
+                    }
+                }
+                DlcManagerMessage::CloseContract { contract_id, attestations, responder } => {
+                    let closed = Self::close_confirmed(&manager, &events, &rates, contract_id, attestations);
+                    if responder.send(closed).is_err() {
+                        tracing::warn!("Close requester went away before the contract was closed.");
+                    }
+                }
+                DlcManagerMessage::RefundContract { contract_id, responder } => {
+                    let refunded = Self::refund(&manager, &wallet, &broadcasts, &events, contract_id);
+                    if responder.send(refunded).is_err() {
+                        tracing::warn!("Refund requester went away before the contract was refunded.");
+                    }
+                }
+                DlcManagerMessage::OfferChannel { contract_input, counter_party, responder } => {
+                    let offered = manager.offer_channel(&contract_input, counter_party).map(|offer| {
+                        let ids = (offer.temporary_channel_id, offer.temporary_contract_id);
+                        send_pending(manager.get_store().as_ref(), &outbox, counter_party, Message::Channel(ChannelMessage::Offer(offer)));
+                        ids
+                    });
+                    if responder.send(offered).is_err() {
+                        tracing::warn!("Channel offer requester went away before the channel was offered.");
+                    }
+                }
+                DlcManagerMessage::AcceptChannel { channel_id, responder } => {
+                    let accepted = manager.accept_channel(&channel_id).map(|(accept, channel_id, contract_id, counter_party)| {
+                        send_pending(manager.get_store().as_ref(), &outbox, counter_party, Message::Channel(ChannelMessage::Accept(accept)));
+                        (channel_id, contract_id)
+                    });
+                    if responder.send(accepted).is_err() {
+                        tracing::warn!("Channel accept requester went away before the channel was accepted.");
+                    }
+                }
+                DlcManagerMessage::SettleChannel { channel_id, counter_payout, responder } => {
+                    let settled = manager.settle_offer(&channel_id, counter_payout).map(|(settle, counter_party)| {
+                        send_pending(manager.get_store().as_ref(), &outbox, counter_party, Message::Channel(ChannelMessage::SettleOffer(settle)));
+                    });
+                    if responder.send(settled).is_err() {
+                        tracing::warn!("Settle requester went away before the settlement was offered.");
+                    }
+                }
+                DlcManagerMessage::RenewChannel { channel_id, counter_payout, contract_input, responder } => {
+                    let renewed = manager.renew_offer(&channel_id, counter_payout, &contract_input).map(|(renew, counter_party)| {
+                        let contract_id = renew.temporary_contract_id;
+                        send_pending(manager.get_store().as_ref(), &outbox, counter_party, Message::Channel(ChannelMessage::RenewOffer(renew)));
+                        contract_id
+                    });
+                    if responder.send(renewed).is_err() {
+                        tracing::warn!("Renew requester went away before the renewal was offered.");
+                    }
+                }
+                DlcManagerMessage::AcceptChannelUpdate { channel_id, responder } => {
+                    let accepted = Self::accept_channel_update(&manager, &outbox, channel_id);
+                    if responder.send(accepted).is_err() {
+                        tracing::warn!("Channel update requester went away before the update was accepted.");
+                    }
+                }
+                DlcManagerMessage::CloseChannel { channel_id, responder } => {
+                    let closed = Self::offer_channel_close(&manager, &outbox, channel_id);
+                    if responder.send(closed).is_err() {
+                        tracing::warn!("Close requester went away before the close was offered.");
+                    }
+                }
+                DlcManagerMessage::PeriodicCheck { responder } => {
+                    let checked = Self::periodic_check(
+                        &manager,
+                        &wallet,
+                        &broadcasts,
+                        &events,
+                        &rates,
+                        auto_refund,
+                        &confirmation_policy,
+                        &mut maturity_watch,
+                    );
+                    if let Some(responder) = responder {
+                        if responder.send(checked).is_err() {
+                            tracing::warn!("Check requester went away before the check finished.");
+                        }
+                    }
+                }
+                DlcManagerMessage::CetSeen { contract_id, cet } => {
+                    if let Err(e) = Self::counterparty_cet(&manager, &events, &rates, contract_id, cet) {
+                        tracing::error!(
+                            contract_id = hex::encode(contract_id),
+                            error = e.to_string(),
+                            "Could not store the counterparty's CET."
+                        );
+                    }
+                }
+                DlcManagerMessage::TimeoutNegotiations => {
+                    let contracts = match manager.get_store().get_contracts() {
+                        Ok(contracts) => contracts,
+                        Err(e) => {
+                            tracing::error!(error = e.to_string(), "Could not get contracts.");
+                            continue;
+                        }
+                    };
+                    let now = SystemTimeProvider {}.unix_time_now();
+                    let timed_out = negotiation_timer.check(
+                        &contracts,
+                        now,
+                        &negotiation_timeouts,
+                        |counterparty| transport.is_connected(counterparty),
+                        |contract_id| {
+                            manager.get_store().get_contract_metadata(contract_id).ok().flatten().and_then(|m| m.negotiation)
+                        },
+                    );
+                    save_negotiation_clocks(manager.get_store().as_ref(), &negotiation_timer);
+                    for stalled in timed_out {
+                        fail_negotiation(manager.get_store().as_ref(), &wallet, &events, &contracts, stalled);
+                    }
+                }
+                DlcManagerMessage::Transport(TransportEvent::MessageReceived(counter_party, message)) => {
+                    // The first message starts a batch. Messages received before it is
+                    // processed join it.
+                    if inbound.is_empty() && sender.send(DlcManagerMessage::ProcessMessages).is_err() {
+                        tracing::error!("Could not schedule processing of received messages.");
+                    }
+                    inbound.push((counter_party, message));
+                }
+                DlcManagerMessage::Transport(TransportEvent::PeerConnected(counter_party)) => {
+                    tracing::info!(counter_party = counter_party.to_string(), "Peer connected.");
+                    retry::resend_pending(manager.get_store().as_ref(), &outbox, Some(counter_party));
+                }
+                DlcManagerMessage::Transport(TransportEvent::PeerDisconnected(counter_party)) => {
+                    tracing::warn!(
+                        counter_party = counter_party.to_string(),
+                        "Peer disconnected. Messages for it are sent again when it reconnects."
+                    );
+                    events.emit(DdkEvent::PeerDisconnected(counter_party));
                 }
                 DlcManagerMessage::ProcessMessages => {
-                    let messages = transport.get_and_clear_received_messages();
+                    let mut messages = std::mem::take(&mut replay);
+                    messages.extend(awaiting_signature.lock().unwrap().values().cloned());
+                    let received = std::mem::take(&mut inbound);
+                    messages.extend(limit_inbound(manager.get_store().as_ref(), &mut rate_limiter, &events, received));
+                    let messages = journal_inbound(manager.get_store().as_ref(), &recent_messages, messages);
+                    save_inbound_cursor(manager.get_store().as_ref(), &*transport);
 
-                    for (counter_party, message) in messages {
+                    process_by_peer(messages, message_workers, &contract_locks, |message| Self::lock_key(manager.get_store(), message), |counter_party, message| {
+                        let span = logging::message_span(&counter_party, &message);
+                        let _entered = span.enter();
                         tracing::info!(
                             counter_party = counter_party.to_string(),
                             "Processing DLC message"
                         );
+                        let id = message_id(&message);
+                        let was_awaiting = awaiting_signature.lock().unwrap().remove(&id).is_some();
+
+                        let held_for_maintenance = manager.get_store().maintenance().unwrap_or(false);
+                        if let Message::Offer(offer) = &message {
+                            // Without the tip the offer cannot be checked. It is kept pending and
+                            // retried like any message the chain backend failed.
+                            let tip = match Self::chain_tip(&wallet.blockchain) {
+                                Ok(tip) => tip,
+                                Err(e) => {
+                                    let e = dlc_manager::error::Error::BlockchainError(e.to_string());
+                                    fail_inbound(manager.get_store().as_ref(), &recent_messages, counter_party, &message, &e);
+                                    return;
+                                }
+                            };
+                            if let Err(e) = validate_locktimes(offer.cet_locktime, offer.refund_locktime, &tip) {
+                                tracing::warn!(
+                                    counter_party = counter_party.to_string(),
+                                    error = e.to_string(),
+                                    "Ignoring offer with invalid locktimes."
+                                );
+                                ack_inbound(manager.get_store().as_ref(), &recent_messages, counter_party, &message);
+                                return;
+                            }
+                            if held_for_maintenance {
+                                tracing::warn!(
+                                    counter_party = counter_party.to_string(),
+                                    temporary_contract_id = hex::encode(offer.temporary_contract_id),
+                                    "Holding offer received in maintenance mode."
+                                );
+                            }
+                        }
+
+                        if let Message::Accept(accept) = &message {
+                            let stored = manager.get_store().get_contract(&accept.temporary_contract_id).ok().flatten();
+                            if is_cancelled_offer(stored.as_ref()) {
+                                tracing::warn!(
+                                    counter_party = counter_party.to_string(),
+                                    temporary_contract_id = hex::encode(accept.temporary_contract_id),
+                                    "Rejecting accept for a cancelled offer."
+                                );
+                                send_reject(manager.get_store().as_ref(), &outbox, counter_party, accept.temporary_contract_id);
+                                ack_inbound(manager.get_store().as_ref(), &recent_messages, counter_party, &message);
+                                return;
+                            }
+                        }
+
+                        // A Reject for a contract rather than a channel ends the negotiation.
+                        if let Message::Channel(ChannelMessage::Reject(reject)) = &message {
+                            if on_contract_reject(manager.get_store().as_ref(), &wallet, &events, counter_party, reject.channel_id) {
+                                ack_inbound(manager.get_store().as_ref(), &recent_messages, counter_party, &message);
+                                return;
+                            }
+                        }
+
+                        // Checked before the manager stores the offer, so a rejected offer never
+                        // shows up as offered.
+                        if let Message::Channel(ChannelMessage::Offer(offer)) = &message {
+                            if let Err(e) = Self::check_channel_reserve(&wallet, offer.accept_collateral, channel_reserve_sats) {
+                                tracing::warn!(error = e.to_string(), "Rejecting channel offer.");
+                                let reject = Reject { channel_id: offer.temporary_channel_id };
+                                outbox.send(counter_party, Message::Channel(ChannelMessage::Reject(reject)), None);
+                                ack_inbound(manager.get_store().as_ref(), &recent_messages, counter_party, &message);
+                                return;
+                            }
+                        }
+
+                        let processed = Self::on_message_with_progress(&manager, &message, counter_party, &sign_progress, &events);
+                        metrics::message_processed(message_kind(&message), processed.is_ok());
+                        let message_response = match processed {
+                            Ok(response) => response,
+                            // A resent message for a contract that moved on. Replaying it cannot
+                            // succeed, so it is acknowledged.
+                            Err(dlc_manager::error::Error::InvalidState(e)) => {
+                                tracing::warn!(
+                                    counter_party = counter_party.to_string(),
+                                    kind = message_kind(&message),
+                                    error = e,
+                                    "Ignoring message for a contract in another state."
+                                );
+                                ack_inbound(manager.get_store().as_ref(), &recent_messages, counter_party, &message);
+                                return;
+                            }
+                            Err(e) => {
+                                // The contract stays in its current state until the external
+                                // signer answers. The message is left pending and retried.
+                                if let Some((txid, input_index)) = awaited_signature(&e) {
+                                    tracing::info!(
+                                        counter_party = counter_party.to_string(),
+                                        kind = message_kind(&message),
+                                        txid = txid.to_string(),
+                                        input_index,
+                                        "Waiting for external signer."
+                                    );
+                                    if let (false, Some(contract_id)) = (was_awaiting, message_contract_id(&message)) {
+                                        events.emit(DdkEvent::AwaitingSignature { contract_id, txid, input_index });
+                                    }
+                                    awaiting_signature.lock().unwrap().insert(id, (counter_party, message));
+                                    return;
+                                }
+                                fail_inbound(manager.get_store().as_ref(), &recent_messages, counter_party, &message, &e);
+                                return;
+                            }
+                        };
+
+                        if let Some(Message::Sign(sign)) = &message_response {
+                            logging::record_contract_id(&span, &sign.contract_id);
+                        }
+                        if let (Message::Accept(_), Some(Message::Sign(sign))) = (&message, &message_response) {
+                            rates.record(manager.get_store().clone(), sign.contract_id, RatePoint::Accept);
+                        }
+                        match (&message, &message_response) {
+                            (Message::Offer(offer), _) => {
+                                events.emit(DdkEvent::ContractOffered(offer.temporary_contract_id));
+                                if held_for_maintenance {
+                                    Self::hold_offer(manager.get_store(), offer.temporary_contract_id);
+                                } else {
+                                    Self::apply_offer_policy(
+                                        &manager,
+                                        &wallet,
+                                        &sender,
+                                        offer_policy.as_ref(),
+                                        &risk_limits,
+                                        &events,
+                                        offer.temporary_contract_id,
+                                    );
+                                }
+                            }
+                            (Message::Accept(_), Some(Message::Sign(sign))) | (Message::Sign(sign), _) => {
+                                events.emit(DdkEvent::ContractSigned(sign.contract_id))
+                            }
+                            _ => {}
+                        }
 
-                        let message_response = manager.on_dlc_message(&message, counter_party).expect("no on dlc message");
                         if let Some(msg) = message_response {
                             tracing::info!("Responding to message received.");
                             tracing::debug!(message=?msg);
-                            transport.send_message(counter_party, msg);
+                            send_pending(manager.get_store().as_ref(), &outbox, counter_party, msg);
                         }
-                    }
-
-                    if transport.has_pending_messages() {
-                        transport.process_messages()
-                    }
+                        // Acknowledged once the response is journaled, so a crash before
+                        // this point replays the message instead of losing the response.
+                        ack_inbound(manager.get_store().as_ref(), &recent_messages, counter_party, &message);
+                    });
                 }
             }
         }
 
     }
 
-    pub fn connect_if_necessary(&self) -> anyhow::Result<()> {
-        let _known_peers = self.storage.list_peers()?;
+    fn vacuum_announcement_cache(
+        storage: &S,
+        cache: &Mutex<AnnouncementCache>,
+    ) -> anyhow::Result<usize> {
+        let protected = referenced_announcements(&storage.get_contracts()?);
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let mut cache = cache.lock().unwrap();
+        cache.set_protected(protected);
+        let evicted = cache.vacuum(now);
+        tracing::debug!(evicted, remaining = cache.len(), "Vacuumed announcement cache.");
+        Ok(evicted)
+    }
 
-        // check from already connected
+    /// Evict stale oracle announcements from the cache. Announcements referenced by open
+    /// contracts are kept. Returns the number of evicted announcements.
+    pub fn vacuum_announcements(&self) -> Result<usize, DdkError> {
+        Ok(Self::vacuum_announcement_cache(&self.storage, &self.announcement_cache).map_err(StorageError::new)?)
+    }
 
-        Ok(())
+    fn chain_tip(blockchain: &B) -> Result<ChainTip, DdkError> {
+        Ok(ChainTip {
+            height: blockchain.get_blockchain_height()? as u32,
+            time: blockchain.unix_time() as u32,
+        })
     }
 
-    pub fn network(&self) -> Network {
-        self.network
+    /// CET and refund locktimes for a contract on `event_id`, from the announced maturity
+    /// and the current chain tip.
+    pub async fn suggest_locktimes(&self, event_id: &str) -> Result<SuggestedLocktimes, DdkError> {
+        let announcement = self.get_announcement(event_id).await?;
+        let tip = Self::chain_tip(&self.wallet.blockchain)?;
+        Ok(suggest_locktimes(
+            announcement.oracle_event.event_maturity_epoch,
+            tip,
+            DEFAULT_REFUND_DELAY,
+        ))
     }
 
-    pub fn send_dlc_offer(
-        &self,
-        contract_input: &ContractInput,
-        counter_party: PublicKey,
-        oracle_announcements: Vec<OracleAnnouncement>,
-    ) -> anyhow::Result<OfferDlc> {
-        let (responder, receiver) = unbounded();
-        self.sender.send(DlcManagerMessage::OfferDlc { contract_input: contract_input.to_owned(), counter_party, oracle_announcements, responder }).expect("sending offer message");
-        let offer = receiver.recv().expect("no offer dlc");
+    /// Report progress of verifying the counterparty's signatures when they accept our offers.
+    pub fn set_sign_progress(&self, callback: Option<ProgressCallback>) {
+        *self.sign_progress.write().unwrap() = callback;
+    }
 
-        let contract_id = hex::encode(&offer.temporary_contract_id);
-        self.transport
-            .send_message(counter_party, Message::Offer(offer.clone()));
-        tracing::info!(
-            counterparty = counter_party.to_string(),
-            contract_id,
-            "Sent DLC offer to counterparty."
-        );
+    /// Prove that `address` belongs to this node by signing the counterparty's `challenge`.
+    pub fn sign_address_proof(&self, address: &Address, challenge: &[u8]) -> Result<AddressProof, DdkError> {
+        Ok(self.wallet.sign_address_proof(address, challenge)?)
+    }
 
-        Ok(offer)
+    /// Verify a counterparty's proof that they control `address`.
+    pub fn verify_address_proof(&self, address: &Address, challenge: &[u8], proof: &AddressProof) -> bool {
+        self.wallet.verify_address_proof(address, challenge, proof)
     }
 
-    pub fn accept_dlc_offer(
+    /// Move finished contracts out of the contract store and prune the archive as `policy`
+    /// says. Archived contracts are listed by [DdkStorage::get_archived_contracts].
+    pub fn archive(&self, policy: &ArchivePolicy) -> Result<ArchiveReport, DdkError> {
+        Ok(Self::apply_archive_policy(&self.storage, policy).map_err(StorageError::new)?)
+    }
+
+    fn apply_archive_policy(storage: &S, policy: &ArchivePolicy) -> anyhow::Result<ArchiveReport> {
+        let archived = storage.archive_closed_contracts(policy.archive_after)?;
+        let pruned = match policy.prune_after {
+            Some(prune_after) => storage.prune_archive(prune_after)?,
+            None => 0,
+        };
+        Ok(ArchiveReport { archived, pruned })
+    }
+
+    /// Delete signer keys of closed contracts that are past the configured retention.
+    pub fn vacuum_signers(&self) -> Result<SignerVacuumReport, DdkError> {
+        Ok(self.storage.vacuum_signers(&self.signer_vacuum).map_err(StorageError::new)?)
+    }
+
+    /// Every oracle contracts can settle on, the primary one first.
+    pub fn list_oracles(&self) -> Vec<Arc<O>> {
+        self.oracles.list()
+    }
+
+    /// Get an announcement of the primary oracle from the cache or the oracle.
+    pub async fn get_announcement(&self, event_id: &str) -> Result<OracleAnnouncement, DdkError> {
+        self.get_oracle_announcement(&self.oracle.get_public_key(), event_id).await
+    }
+
+    /// Get an announcement of one of our oracles from the cache or the oracle.
+    pub async fn get_oracle_announcement(
         &self,
-        contract: [u8; 32],
-    ) -> anyhow::Result<(String, String, AcceptDlc)> {
-        let (responder, receiver) = unbounded();
-        self.sender.send(DlcManagerMessage::AcceptDlc { contract, responder }).expect("couldnt send accept");
-        let (contract_id, public_key, accept_dlc) = receiver.recv().expect("coudlnt accept dlc");
+        oracle_pubkey: &XOnlyPublicKey,
+        event_id: &str,
+    ) -> Result<OracleAnnouncement, DdkError> {
+        let oracle = self
+            .oracles
+            .get(oracle_pubkey)
+            .ok_or(OracleError::Unknown(*oracle_pubkey))?;
+        if let Some(announcement) = self
+            .announcement_cache
+            .lock()
+            .unwrap()
+            .get(oracle_pubkey, event_id)
+        {
+            return Ok(announcement);
+        }
 
-        self.transport
-            .send_message(public_key, Message::Accept(accept_dlc.clone()));
+        let announcement = oracle
+            .get_announcement_async(event_id)
+            .await
+            .map_err(|e| OracleError::Request(e.to_string()))?;
+        self.status.record_oracle_contact();
+        self.announcement_cache
+            .lock()
+            .unwrap()
+            .insert(announcement.clone());
+        Ok(announcement)
+    }
 
-        let contract_id = hex::encode(&contract_id);
-        let counter_party = public_key.to_string();
-        tracing::info!(counter_party, contract_id, "Accepted DLC contract.");
+    /// Makes sure the funding transaction of every signed contract reaches the mempool, by the
+    /// contract's [FundingBroadcastRole]. The broadcaster broadcasts as soon as the transaction
+    /// is missing and it holds every signature, the fallback party once it was missing for
+    /// `window`. `missing_since` tracks when each transaction was found missing.
+    fn check_funding_broadcasts(
+        storage: &S,
+        blockchain: &B,
+        broadcasts: &BroadcastTracker<B, S>,
+        events: &EventBus,
+        window: Duration,
+        missing_since: &mut HashMap<ContractId, Instant>,
+    ) {
+        let signed_contracts = match storage.get_signed_contracts() {
+            Ok(contracts) => contracts,
+            Err(e) => {
+                tracing::error!(error = e.to_string(), "Could not get signed contracts.");
+                return;
+            }
+        };
+
+        missing_since.retain(|id, _| signed_contracts.iter().any(|c| c.accepted_contract.get_contract_id() == *id));
+
+        for contract in signed_contracts {
+            let contract_id = contract.accepted_contract.get_contract_id();
+            let fund = &contract.accepted_contract.dlc_transactions.fund;
+            let txid = fund.compute_txid();
+            let role = Self::funding_broadcast_role(storage, &contract);
+            match blockchain.find_transaction(&txid) {
+                Ok(Some(_)) => {
+                    // Missing on an earlier check and not broadcast by us since.
+                    if missing_since.remove(&contract_id).is_some() {
+                        events.emit(DdkEvent::FundingBroadcast { contract_id, txid, by_us: false });
+                    }
+                    continue;
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    tracing::warn!(error = e.to_string(), "Could not check funding transaction.");
+                    continue;
+                }
+            }
+
+            let since = *missing_since.entry(contract_id).or_insert_with(Instant::now);
+            let due = match role {
+                FundingBroadcastRole::Broadcaster => is_fully_signed(fund),
+                FundingBroadcastRole::Fallback => since.elapsed() >= window,
+            };
+            if !due {
+                tracing::debug!(
+                    contract_id = hex::encode(contract_id),
+                    txid = txid.to_string(),
+                    "Waiting for the counterparty to broadcast the funding transaction."
+                );
+                continue;
+            }
 
-        Ok((contract_id, counter_party, accept_dlc))
+            match broadcasts.broadcast(fund) {
+                Ok(()) => {
+                    missing_since.remove(&contract_id);
+                    audit::append(storage, AuditEntry::broadcast(&contract_id, txid, "funding"));
+                    tracing::info!(
+                        contract_id = hex::encode(contract_id),
+                        txid = txid.to_string(),
+                        role = ?role,
+                        "Broadcast funding transaction."
+                    );
+                    events.emit(DdkEvent::FundingBroadcast { contract_id, txid, by_us: true });
+                }
+                Err(e) => tracing::error!(error = e.to_string(), "Could not broadcast funding transaction."),
+            }
+        }
+    }
+
+    /// The funding broadcast role stored with the contract, recorded from
+    /// [FundingBroadcastRole::for_contract] the first time it is asked for.
+    fn funding_broadcast_role(storage: &S, contract: &SignedContract) -> FundingBroadcastRole {
+        let contract_id = contract.accepted_contract.get_contract_id();
+        let metadata = match storage.get_contract_metadata(&contract_id) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                tracing::error!(error = e.to_string(), "Could not get contract metadata.");
+                return FundingBroadcastRole::for_contract(contract);
+            }
+        };
+        if let Some(role) = metadata.as_ref().and_then(|m| m.funding_broadcast_role) {
+            return role;
+        }
+        let role = FundingBroadcastRole::for_contract(contract);
+        let mut metadata = metadata.unwrap_or_else(ContractMetadata::new);
+        metadata.funding_broadcast_role = Some(role);
+        if let Err(e) = storage.set_contract_metadata(&contract_id, metadata) {
+            tracing::error!(error = e.to_string(), "Could not record funding broadcast role.");
+        }
+        role
+    }
+
+    /// Record the exchange rate for a contract that has closed. Contracts closed by the node
+    /// get it when they close, this is for closes the node did not see. A contract keeps the
+    /// first close rate recorded.
+    pub fn record_close_rate(&self, contract_id: ContractId) -> Result<(), DdkError> {
+        let runtime = self.runtime.read().unwrap();
+        let runtime = runtime.as_ref().ok_or(DdkError::ManagerNotRunning)?;
+        RateRecorder::new(self.rate_provider.clone(), self.fiat_currency.clone(), runtime.handle().clone())
+            .record(self.storage.clone(), contract_id, RatePoint::Close);
+        Ok(())
+    }
+
+    /// Exchange rates recorded when the contract was accepted and closed.
+    pub fn contract_rates(&self, contract_id: ContractId) -> Result<Option<ContractRates>, DdkError> {
+        Ok(self.storage.get_contract_rates(&contract_id).map_err(StorageError::new)?)
+    }
+
+    /// Realized profit and loss of the contracts settled in `range`, per contract and summed
+    /// per counterparty and oracle event. Without a range every settled contract is reported.
+    /// Export it with [crate::accounting::write_pnl_csv].
+    pub fn pnl_report(&self, range: Option<TimeRange>) -> Result<PnlReport, DdkError> {
+        let settlements = self.storage.list_contract_settlements().map_err(StorageError::new)?;
+        let contracts = self.storage.get_contracts().map_err(StorageError::new)?;
+        let now = SystemTimeProvider {}.unix_time_now();
+        Ok(PnlReport::build(settlements, &contracts, range, now))
     }
-}
 
+    /// The lock a received message takes while it is processed, see [LockKey::for_message].
+    fn lock_key(storage: &S, message: &Message) -> Option<LockKey> {
+        LockKey::for_message(message, |contract_id| {
+            storage
+                .get_contract(contract_id)
+                .ok()
+                .flatten()
+                .map(|contract| contract.get_temporary_id())
+        })
+    }
+
+    /// Process a message, reporting progress when an accept for one of our offers is verified.
+    fn on_message_with_progress(
+        manager: &DlcDevKitDlcManager<S, O, B>,
+        message: &Message,
+        counter_party: PublicKey,
+        sign_progress: &RwLock<Option<ProgressCallback>>,
+        events: &Arc<EventBus>,
+    ) -> Result<Option<Message>, dlc_manager::error::Error> {
+        let Message::Accept(accept) = message else {
+            return manager.on_dlc_message(message, counter_party);
+        };
+        let total = match manager.get_store().get_contract(&accept.temporary_contract_id) {
+            Ok(Some(Contract::Offered(offered))) => cet_count(&offered),
+            _ => 0,
+        };
+        let callback = sign_progress.read().unwrap().clone();
+        let mut progress = ProgressReporter::new(callback)
+            .with_events(events.clone(), accept.temporary_contract_id);
+        let response = progress.phase(NegotiationPhase::VerifyingCets, total, || {
+            manager.on_dlc_message(message, counter_party)
+        })?;
+        progress.phase(NegotiationPhase::BuildingFunding, 1, || ());
+        progress.phase(NegotiationPhase::Signing, 1, || ());
+        Ok(response)
+    }
+
+    /// Flag an offer received in maintenance mode in its metadata, see [DlcDevKit::held_offers].
+    fn hold_offer(storage: &S, temporary_id: ContractId) {
+        let metadata = match storage.get_contract_metadata(&temporary_id) {
+            Ok(metadata) => metadata.unwrap_or_else(ContractMetadata::new),
+            Err(e) => {
+                tracing::error!(error = e.to_string(), "Could not get contract metadata.");
+                return;
+            }
+        };
+        let metadata = ContractMetadata {
+            held_for_maintenance: true,
+            ..metadata
+        };
+        if let Err(e) = storage.set_contract_metadata(&temporary_id, metadata) {
+            tracing::error!(error = e.to_string(), "Could not hold offer.");
+        }
+    }
+
+    /// Settle contracts whose attestations or refund locktimes are reached, and emit events
+    /// for the contracts that changed. Contracts that could not be settled are retried on the
+    /// next check. Contracts are confirmed by the `confirmation_policy` rather than the
+    /// manager. With `auto_refund` contracts still unattested past their refund locktime
+    /// are refunded afterwards. Contracts coming within the notice of their maturity are
+    /// reported by the `maturity_watch`.
+    fn periodic_check(
+        manager: &DlcDevKitDlcManager<S, O, B>,
+        wallet: &DlcDevKitWallet<S, B>,
+        broadcasts: &BroadcastTracker<B, S>,
+        events: &EventBus,
+        rates: &RateRecorder,
+        auto_refund: bool,
+        confirmation_policy: &ConfirmationPolicy,
+        maturity_watch: &mut MaturityWatch,
+    ) -> Result<(), dlc_manager::error::Error> {
+        let before_contracts = manager.get_store().get_contracts()?;
+        // Closed contracts drop their funding transaction, so record it while it is known.
+        Self::record_contract_transactions(manager, &before_contracts);
+        let before = contract_states(&before_contracts);
+        let signed_channels = manager
+            .get_store()
+            .get_signed_channels(None)?
+            .iter()
+            .map(|channel| channel.channel_id)
+            .collect::<Vec<_>>();
+        if let Err(e) = manager.periodic_check(true) {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+            let contracts = manager.get_store().get_contracts().unwrap_or_default();
+            for contract in awaiting_settlement(&contracts, now) {
+                tracing::error!(
+                    contract_id = contract.id,
+                    maturity = contract.maturity,
+                    error = e.to_string(),
+                    "Could not settle contract. Retrying on the next check."
+                );
+            }
+            tracing::error!(error = e.to_string(), "Periodic check failed.");
+            return Err(e);
+        }
+        Self::apply_confirmation_policy(manager, wallet, broadcasts, events, confirmation_policy, &before);
+        let after_contracts = manager.get_store().get_contracts()?;
+        Self::record_contract_transactions(manager, &after_contracts);
+        Self::record_settlements(manager, &before_contracts, &after_contracts);
+        for contract_id in newly_closed(&before, &after_contracts) {
+            rates.record(manager.get_store().clone(), contract_id, RatePoint::Close);
+        }
+        for event in state_change_events(&before, &after_contracts) {
+            events.emit(event);
+        }
+        for event in maturity_watch.check(&after_contracts, SystemTimeProvider {}.unix_time_now()) {
+            events.emit(event);
+        }
+        Self::report_punished_channels(manager, events, &signed_channels);
+        if auto_refund {
+            Self::refund_eligible(manager, wallet, broadcasts, events, &after_contracts);
+        }
+        Ok(())
+    }
+
+    fn refund_eligible(
+        manager: &DlcDevKitDlcManager<S, O, B>,
+        wallet: &DlcDevKitWallet<S, B>,
+        broadcasts: &BroadcastTracker<B, S>,
+        events: &EventBus,
+        contracts: &[Contract],
+    ) {
+        let tip = match Self::chain_tip(&wallet.blockchain) {
+            Ok(tip) => tip,
+            Err(e) => {
+                tracing::error!(error = e.to_string(), "Could not get chain tip to check refunds.");
+                return;
+            }
+        };
+        for contract_id in refundable(contracts, &tip) {
+            if let Err(e) = Self::refund(manager, wallet, broadcasts, events, contract_id) {
+                tracing::error!(
+                    contract_id = hex::encode(contract_id),
+                    error = e.to_string(),
+                    "Could not refund contract. Retrying on the next check."
+                );
+            }
+        }
+    }
+
+    /// Sign and broadcast the refund transaction of a confirmed contract and store it as
+    /// refunded. Returns the refund txid.
+    fn refund(
+        manager: &DlcDevKitDlcManager<S, O, B>,
+        wallet: &DlcDevKitWallet<S, B>,
+        broadcasts: &BroadcastTracker<B, S>,
+        events: &EventBus,
+        contract_id: ContractId,
+    ) -> Result<Txid, RefundError> {
+        let stored = manager
+            .get_store()
+            .get_contract(&contract_id)
+            .map_err(|e| RefundError::Storage(e.to_string()))?;
+        let contract = refundable_contract(stored.as_ref())?;
+        let tip = Self::chain_tip(&wallet.blockchain).map_err(|e| RefundError::Chain(e.to_string()))?;
+        check_refund_locktime(contract, &tip)?;
+
+        let fund_secret_key = wallet
+            .derive_contract_signer(contract.accepted_contract.offered_contract.keys_id)
+            .and_then(|signer| signer.get_secret_key())
+            .map_err(|e| RefundError::Signing(e.to_string()))?;
+        let refund = signed_refund(&Secp256k1::signing_only(), contract, &fund_secret_key)?;
+        broadcasts
+            .broadcast(&refund)
+            .map_err(|e| RefundError::Broadcast(e.to_string()))?;
+
+        let refunded = Contract::Refunded(contract.clone());
+        manager
+            .get_store()
+            .update_contract(&refunded)
+            .map_err(|e| RefundError::Storage(e.to_string()))?;
+        Self::record_settlements(manager, &[], std::slice::from_ref(&refunded));
+        let txid = refund.compute_txid();
+        audit::append(manager.get_store().as_ref(), AuditEntry::broadcast(&contract_id, txid, "refund"));
+        tracing::info!(contract_id = hex::encode(contract_id), txid = txid.to_string(), "Refunded contract.");
+        events.emit(DdkEvent::ContractRefunded(contract_id));
+        Ok(txid)
+    }
+
+    fn signed_channel(
+        manager: &DlcDevKitDlcManager<S, O, B>,
+        channel_id: &ChannelId,
+    ) -> Result<SignedChannel, dlc_manager::error::Error> {
+        match manager.get_store().get_channel(channel_id)? {
+            Some(Channel::Signed(signed)) => Ok(signed),
+            Some(_) => Err(dlc_manager::error::Error::InvalidState(
+                "Channel is not signed.".to_string(),
+            )),
+            None => Err(dlc_manager::error::Error::InvalidParameters(format!(
+                "Channel {} not found.",
+                hex::encode(channel_id)
+            ))),
+        }
+    }
+
+    /// Accept the pending settle, renew, or collaborative close offer of a channel and send
+    /// our answer to the counterparty.
+    fn accept_channel_update(
+        manager: &DlcDevKitDlcManager<S, O, B>,
+        outbox: &Outbox,
+        channel_id: ChannelId,
+    ) -> Result<(), dlc_manager::error::Error> {
+        let signed = Self::signed_channel(manager, &channel_id)?;
+        match signed.state.get_type() {
+            SignedChannelStateType::SettledReceived => {
+                let (accept, counter_party) = manager.accept_settle_offer(&channel_id)?;
+                send_pending(manager.get_store().as_ref(), outbox, counter_party, Message::Channel(ChannelMessage::SettleAccept(accept)));
+            }
+            SignedChannelStateType::RenewOffered => {
+                let (accept, counter_party) = manager.accept_renew_offer(&channel_id)?;
+                send_pending(manager.get_store().as_ref(), outbox, counter_party, Message::Channel(ChannelMessage::RenewAccept(accept)));
+            }
+            SignedChannelStateType::CollaborativeCloseOffered => {
+                manager.accept_collaborative_close(&channel_id)?;
+            }
+            state => {
+                return Err(dlc_manager::error::Error::InvalidState(format!(
+                    "Channel has no update to accept. state={}",
+                    ChannelState::from(state)
+                )))
+            }
+        }
+        Ok(())
+    }
+
+    /// Offer to close a settled channel, paying each party its settled balance.
+    fn offer_channel_close(
+        manager: &DlcDevKitDlcManager<S, O, B>,
+        outbox: &Outbox,
+        channel_id: ChannelId,
+    ) -> Result<(), dlc_manager::error::Error> {
+        let signed = Self::signed_channel(manager, &channel_id)?;
+        let SignedChannelState::Settled { counter_payout, .. } = signed.state else {
+            return Err(dlc_manager::error::Error::InvalidState(format!(
+                "Settle the channel before closing it. state={}",
+                ChannelState::from(signed.state.get_type())
+            )));
+        };
+        let close = manager.offer_collaborative_close(&channel_id, counter_payout)?;
+        send_pending(
+            manager.get_store().as_ref(),
+            outbox,
+            signed.counter_party,
+            Message::Channel(ChannelMessage::CollaborativeCloseOffer(close)),
+        );
+        Ok(())
+    }
+
+    /// Close a confirmed contract with its CET and return the CET txid.
+    fn close_confirmed(
+        manager: &DlcDevKitDlcManager<S, O, B>,
+        events: &EventBus,
+        rates: &RateRecorder,
+        contract_id: ContractId,
+        attestations: Vec<(usize, OracleAttestation)>,
+    ) -> Result<Txid, CloseError> {
+        let stored = manager
+            .get_store()
+            .get_contract(&contract_id)
+            .map_err(|e| CloseError::Manager(e.to_string()))?;
+        let contract = Contract::Confirmed(confirmed_contract(stored.as_ref())?.clone());
+        // Closed contracts drop their funding transaction, so record it while it is known.
+        Self::record_contract_transactions(manager, std::slice::from_ref(&contract));
+        let before = contract_states(std::slice::from_ref(&contract));
+
+        let closed = manager
+            .close_confirmed_contract(&contract_id, attestations)
+            .map_err(|e| CloseError::Manager(e.to_string()))?;
+        Self::record_contract_transactions(manager, std::slice::from_ref(&closed));
+        Self::record_settlements(manager, std::slice::from_ref(&contract), std::slice::from_ref(&closed));
+        for contract_id in newly_closed(&before, std::slice::from_ref(&closed)) {
+            rates.record(manager.get_store().clone(), contract_id, RatePoint::Close);
+        }
+        for event in state_change_events(&before, std::slice::from_ref(&closed)) {
+            events.emit(event);
+        }
+        cet_txid(&closed).ok_or_else(|| {
+            CloseError::Manager(format!("Contract is {} after closing.", ContractState::from(&closed)))
+        })
+    }
+
+    /// Confirm signed contracts once their funding transaction has the confirmations the
+    /// policy requires, and undo the manager confirming them earlier. Confirmed contracts whose
+    /// funding transaction left the chain in a reorg move back to Signed with
+    /// [DdkEvent::FundingReorged]. `before` holds the states before the manager's check.
+    ///
+    /// Contracts with [AcceptanceParams] need their confirmations instead of the policy's.
+    /// Zero-conf contracts whose funding transaction is double-spent before it is in a block
+    /// fail with [DdkEvent::FundingDoubleSpent] and release our funding inputs. One that was
+    /// only evicted is rebroadcast and fails once something else spends its inputs.
+    ///
+    /// The manager may settle a contract in the check that confirmed it, when its event is
+    /// attested already. Such contracts are left closed.
+    fn apply_confirmation_policy(
+        manager: &DlcDevKitDlcManager<S, O, B>,
+        wallet: &DlcDevKitWallet<S, B>,
+        broadcasts: &BroadcastTracker<B, S>,
+        events: &EventBus,
+        policy: &ConfirmationPolicy,
+        before: &HashMap<ContractId, ContractState>,
+    ) {
+        let contracts = match manager.get_store().get_contracts() {
+            Ok(contracts) => contracts,
+            Err(e) => {
+                tracing::error!(error = e.to_string(), "Could not get contracts to confirm.");
+                return;
+            }
+        };
+        for contract in contracts {
+            let contract_id = contract.get_id();
+            let (signed, is_confirmed) = match contract {
+                Contract::Signed(signed) => (signed, false),
+                Contract::Confirmed(signed) => (signed, true),
+                _ => continue,
+            };
+            let was_confirmed = before.get(&contract_id) == Some(&ContractState::Confirmed);
+            let fund = &signed.accepted_contract.dlc_transactions.fund;
+            let txid = fund.compute_txid();
+            let acceptance = match manager.get_store().get_contract_metadata(&contract_id) {
+                Ok(metadata) => metadata.and_then(|metadata| metadata.acceptance),
+                Err(e) => {
+                    tracing::error!(
+                        contract_id = hex::encode(contract_id),
+                        error = e.to_string(),
+                        "Could not get contract metadata."
+                    );
+                    None
+                }
+            };
+            let required = policy.required_with(&signed, acceptance);
+            let confirmations = wallet.blockchain.get_transaction_confirmations(&txid);
+            if required == 0 && !matches!(confirmations, Ok(confirmations) if confirmations > 0) {
+                match conflicting_spend(&*wallet.blockchain, fund) {
+                    Ok(Some(conflicting_txid)) => {
+                        Self::fail_double_spent(manager, wallet, broadcasts, events, &signed, conflicting_txid);
+                        continue;
+                    }
+                    Ok(None) => {}
+                    Err(e) => tracing::debug!(
+                        contract_id = hex::encode(contract_id),
+                        error = e.to_string(),
+                        "Could not check funding inputs for double-spends."
+                    ),
+                }
+            }
+            let confirmations = match confirmations {
+                Ok(confirmations) => confirmations,
+                Err(e) => {
+                    tracing::debug!(
+                        contract_id = hex::encode(contract_id),
+                        error = e.to_string(),
+                        "Could not get funding confirmations."
+                    );
+                    continue;
+                }
+            };
+            let Some(change) = confirmation_change(was_confirmed, is_confirmed, confirmations, required) else {
+                continue;
+            };
+            let updated = match change {
+                ConfirmationChange::Confirm => Contract::Confirmed(signed),
+                ConfirmationChange::Unconfirm | ConfirmationChange::Reorged => Contract::Signed(signed),
+            };
+            if let Err(e) = manager.get_store().update_contract(&updated) {
+                tracing::error!(
+                    contract_id = hex::encode(contract_id),
+                    error = e.to_string(),
+                    "Could not update contract confirmation."
+                );
+                continue;
+            }
+            if change == ConfirmationChange::Reorged {
+                tracing::warn!(
+                    contract_id = hex::encode(contract_id),
+                    txid = txid.to_string(),
+                    "Funding transaction was reorged out. Contract moved back to Signed."
+                );
+                events.emit(DdkEvent::FundingReorged { contract_id, txid });
+            }
+        }
+    }
+
+    /// Fail a zero-conf contract whose funding transaction was double-spent by
+    /// `conflicting_txid`, release our funding inputs, and stop rebroadcasting the funding
+    /// transaction.
+    fn fail_double_spent(
+        manager: &DlcDevKitDlcManager<S, O, B>,
+        wallet: &DlcDevKitWallet<S, B>,
+        broadcasts: &BroadcastTracker<B, S>,
+        events: &EventBus,
+        signed: &SignedContract,
+        conflicting_txid: Txid,
+    ) {
+        let contract_id = signed.accepted_contract.get_contract_id();
+        let txid = signed.accepted_contract.dlc_transactions.fund.compute_txid();
+        let failed = double_spent_contract(signed, conflicting_txid);
+        if let Err(e) = manager.get_store().update_contract(&failed) {
+            tracing::error!(
+                contract_id = hex::encode(contract_id),
+                error = e.to_string(),
+                "Could not fail double-spent contract."
+            );
+            return;
+        }
+        let outpoints = own_funding_outpoints(&Contract::Signed(signed.clone()));
+        if let Err(e) = dlc_manager::Wallet::unreserve_utxos(wallet, &outpoints) {
+            tracing::error!(error = e.to_string(), "Could not release funding inputs.");
+        }
+        if let Err(e) = broadcasts.forget(&txid) {
+            tracing::error!(error = e.to_string(), "Could not stop rebroadcasting funding transaction.");
+        }
+        tracing::warn!(
+            contract_id = hex::encode(contract_id),
+            txid = txid.to_string(),
+            conflicting_txid = conflicting_txid.to_string(),
+            "Funding transaction of zero-conf contract was double-spent. Contract failed."
+        );
+        events.emit(DdkEvent::FundingDoubleSpent {
+            contract_id,
+            txid,
+            conflicting_txid,
+        });
+    }
+
+    /// Emit [DdkEvent::RevokedChannelState] for channels the manager punished because the
+    /// counterparty broadcast a revoked state.
+    fn report_punished_channels(
+        manager: &DlcDevKitDlcManager<S, O, B>,
+        events: &EventBus,
+        signed_channels: &[ChannelId],
+    ) {
+        for channel_id in signed_channels {
+            match manager.get_store().get_channel(channel_id) {
+                Ok(Some(Channel::ClosedPunished(punished))) => {
+                    tracing::warn!(
+                        channel_id = hex::encode(channel_id),
+                        punishment_txid = punished.punish_txid.to_string(),
+                        "Counterparty broadcast a revoked channel state. Punished."
+                    );
+                    events.emit(DdkEvent::RevokedChannelState {
+                        channel_id: *channel_id,
+                        punishment_txid: punished.punish_txid,
+                    });
+                }
+                Ok(_) => {}
+                Err(e) => tracing::error!(error = e.to_string(), "Could not get channel."),
+            }
+        }
+    }
+
+    /// Store a confirmed contract as PreClosed with the CET the counterparty broadcast. The
+    /// manager closes it once the CET confirms, like a CET we broadcast.
+    fn counterparty_cet(
+        manager: &DlcDevKitDlcManager<S, O, B>,
+        events: &EventBus,
+        rates: &RateRecorder,
+        contract_id: ContractId,
+        cet: Transaction,
+    ) -> Result<(), dlc_manager::error::Error> {
+        // We may have closed it ourselves since the watcher looked.
+        let Some(Contract::Confirmed(signed)) = manager.get_store().get_contract(&contract_id)? else {
+            return Ok(());
+        };
+        let confirmed = Contract::Confirmed(signed.clone());
+        Self::record_contract_transactions(manager, std::slice::from_ref(&confirmed));
+
+        let txid = cet.compute_txid();
+        let pre_closed = Contract::PreClosed(PreClosedContract {
+            signed_contract: signed,
+            attestations: None,
+            signed_cet: cet,
+        });
+        manager.get_store().update_contract(&pre_closed)?;
+        Self::record_settlements(manager, std::slice::from_ref(&confirmed), std::slice::from_ref(&pre_closed));
+        rates.record(manager.get_store().clone(), contract_id, RatePoint::Close);
+        tracing::info!(
+            contract_id = hex::encode(contract_id),
+            txid = txid.to_string(),
+            "Counterparty broadcast a CET."
+        );
+        events.emit(DdkEvent::CetSeen { contract_id, txid });
+        Ok(())
+    }
+
+    /// Poll the chain for new blocks, confirmed funding transactions, and CETs the counterparty
+    /// broadcast. New blocks trigger a periodic check so the manager processes them, which is
+    /// how the chain monitor sees revoked channel states.
+    fn watch_chain(
+        storage: &S,
+        blockchain: &B,
+        sender: &Sender<DlcManagerMessage>,
+        events: &EventBus,
+        status: &StatusTracker,
+        options: &ChainWatchOptions,
+        state: &mut ChainWatchState,
+    ) {
+        match blockchain.get_blockchain_height() {
+            Ok(height) => {
+                status.record_chain_tip(height);
+                if state.new_blocks(height)
+                    && sender.send(DlcManagerMessage::PeriodicCheck { responder: None }).is_err()
+                {
+                    tracing::error!("DDK manager stopped. New blocks are not processed.");
+                }
+            }
+            Err(e) => tracing::warn!(error = e.to_string(), "Could not get chain height."),
+        }
+
+        let contracts = match storage.get_contracts() {
+            Ok(contracts) => contracts,
+            Err(e) => {
+                tracing::error!(error = e.to_string(), "Could not get contracts to watch.");
+                return;
+            }
+        };
+        let watched = contracts
+            .iter()
+            .filter(|c| matches!(c, Contract::Signed(_) | Contract::Confirmed(_)))
+            .map(Contract::get_id)
+            .collect::<HashSet<_>>();
+        state.retain(&watched);
+
+        for contract in &contracts {
+            let contract_id = contract.get_id();
+            match contract {
+                Contract::Signed(signed) if !state.funding_confirmed.contains(&contract_id) => {
+                    let txid = signed.accepted_contract.dlc_transactions.fund.compute_txid();
+                    // Transactions the chain backend has not seen yet error.
+                    let Ok(confirmations) = blockchain.get_transaction_confirmations(&txid) else {
+                        continue;
+                    };
+                    if confirmations >= options.funding_confirmations {
+                        state.funding_confirmed.insert(contract_id);
+                        events.emit(DdkEvent::FundingConfirmed { contract_id, txid, confirmations });
+                    }
+                }
+                Contract::Confirmed(signed) if !state.cets_seen.contains(&contract_id) => {
+                    let spending = match blockchain.find_spending_transaction(&funding_outpoint(signed)) {
+                        Ok(Some(spending)) => spending,
+                        Ok(None) => continue,
+                        Err(e) => {
+                            tracing::warn!(error = e.to_string(), "Could not check the funding output.");
+                            continue;
+                        }
+                    };
+                    if !is_cet(signed, &spending) {
+                        tracing::debug!(
+                            contract_id = hex::encode(contract_id),
+                            txid = spending.compute_txid().to_string(),
+                            "Funding output is spent by a transaction that is not a CET."
+                        );
+                        continue;
+                    }
+                    if options.cet_confirmations > 0 {
+                        match blockchain.get_transaction_confirmations(&spending.compute_txid()) {
+                            Ok(confirmations) if confirmations >= options.cet_confirmations => {}
+                            _ => continue,
+                        }
+                    }
+                    state.cets_seen.insert(contract_id);
+                    if sender.send(DlcManagerMessage::CetSeen { contract_id, cet: spending }).is_err() {
+                        tracing::error!("DDK manager stopped. Could not hand over the CET.");
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn record_contract_transactions(manager: &DlcDevKitDlcManager<S, O, B>, contracts: &[Contract]) {
+        let records = contracts
+            .iter()
+            .flat_map(contract_transactions)
+            .collect::<Vec<_>>();
+        if let Err(e) = manager.get_store().save_contract_transactions(&records) {
+            tracing::error!(error = e.to_string(), "Could not record contract transactions.");
+        }
+    }
+
+    /// Record what contracts that settled between `before` and `after` paid us. Closed
+    /// contracts drop their collateral, so this runs while the contract before closing is known.
+    fn record_settlements(manager: &DlcDevKitDlcManager<S, O, B>, before: &[Contract], after: &[Contract]) {
+        let now = SystemTimeProvider {}.unix_time_now();
+        let before = before.iter().map(|c| (c.get_id(), c)).collect::<HashMap<_, _>>();
+        for contract in after {
+            let previous = before.get(&contract.get_id()).copied();
+            // Settled before, the settlement was recorded then.
+            if previous.is_some_and(is_settled) {
+                continue;
+            }
+            let Some(settlement) = settlement(previous, contract, now) else {
+                continue;
+            };
+            if let Err(e) = manager.get_store().save_contract_settlement(&settlement) {
+                tracing::error!(
+                    contract_id = hex::encode(settlement.contract_id),
+                    error = e.to_string(),
+                    "Could not record contract settlement."
+                );
+            }
+        }
+    }
+
+    /// Let the offer policy decide on a stored incoming offer. Accepted offers are enqueued
+    /// like a user accept and must stay within the risk limits.
+    fn apply_offer_policy(
+        manager: &DlcDevKitDlcManager<S, O, B>,
+        wallet: &DlcDevKitWallet<S, B>,
+        sender: &Sender<DlcManagerMessage>,
+        offer_policy: &dyn OfferPolicy,
+        risk_limits: &RiskLimits,
+        events: &EventBus,
+        contract_id: ContractId,
+    ) {
+        let offer = match manager.get_store().get_contract(&contract_id) {
+            Ok(Some(Contract::Offered(offer))) => offer,
+            Ok(_) => return,
+            Err(e) => {
+                tracing::error!(error = e.to_string(), "Could not get offer for the offer policy.");
+                return;
+            }
+        };
+        let decision = match offer_policy.evaluate(&offer) {
+            OfferDecision::Accept => {
+                let collateral = offer.total_collateral - offer.offer_params.collateral;
+                let within_limits = manager
+                    .get_store()
+                    .get_contracts()
+                    .map_err(anyhow::Error::from)
+                    .and_then(|contracts| {
+                        let utilization = RiskUtilization::from_contracts(&contracts);
+                        Ok(risk_limits.check(&utilization, &offer.counter_party, collateral)?)
+                    });
+                match within_limits {
+                    Ok(()) => OfferDecision::Accept,
+                    Err(e) => OfferDecision::Reject(e.to_string()),
+                }
+            }
+            decision => decision,
+        };
+        let temporary_contract_id = hex::encode(contract_id);
+        match decision {
+            OfferDecision::Manual => {}
+            OfferDecision::Accept => {
+                let accept = DlcManagerMessage::AcceptDlc {
+                    contract: contract_id,
+                    options: AcceptOptions::default(),
+                    responder: Responder::Detached,
+                };
+                if sender.send(accept).is_err() {
+                    tracing::error!(temporary_contract_id, "Could not enqueue accept of offer.");
+                    return;
+                }
+                tracing::info!(temporary_contract_id, "Offer policy accepted offer.");
+                events.emit(DdkEvent::OfferAutoAccepted(contract_id));
+            }
+            OfferDecision::Reject(reason) => {
+                if let Err(e) = Self::close_offer_in_store(manager, wallet, contract_id, rejected_contract) {
+                    tracing::error!(temporary_contract_id, error = e.to_string(), "Could not reject offer.");
+                    return;
+                }
+                tracing::info!(temporary_contract_id, reason, "Offer policy rejected offer.");
+                metrics::offer(OfferOutcome::Rejected);
+                events.emit(DdkEvent::OfferRejected { contract_id, reason });
+            }
+        }
+    }
+
+    /// Move an offer to rejected with `transition` and release our funding inputs.
+    fn close_offer_in_store(
+        manager: &DlcDevKitDlcManager<S, O, B>,
+        wallet: &DlcDevKitWallet<S, B>,
+        contract_id: ContractId,
+        transition: fn(&Contract) -> Result<Contract, CancelError>,
+    ) -> Result<(), CancelError> {
+        let contract = match manager.get_store().get_contract(&contract_id) {
+            Ok(Some(contract)) => contract,
+            Ok(None) => return Err(CancelError::NotFound),
+            Err(e) => {
+                tracing::error!(error = e.to_string(), "Could not get offer.");
+                return Err(CancelError::NotFound);
+            }
+        };
+        let rejected = transition(&contract)?;
+        if let Err(e) = manager.get_store().update_contract(&rejected) {
+            tracing::error!(error = e.to_string(), "Could not reject offer.");
+            return Err(CancelError::NotOffered(contract.into()));
+        }
+        // Incoming offers are funded by the counterparty. Nothing of ours is reserved.
+        if matches!(&contract, Contract::Offered(o) if o.is_offer_party) {
+            if let Err(e) = dlc_manager::Wallet::unreserve_utxos(wallet, &own_funding_outpoints(&contract)) {
+                tracing::error!(error = e.to_string(), "Could not release funding inputs.");
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that the wallet keeps `channel_reserve_sats` spendable after funding `collateral`.
+    fn check_channel_reserve(
+        wallet: &DlcDevKitWallet<S, B>,
+        collateral: u64,
+        channel_reserve_sats: u64,
+    ) -> Result<(), DdkError> {
+        let spendable = wallet.get_balance()?.trusted_spendable().to_sat();
+        let required = collateral.saturating_add(channel_reserve_sats);
+        if spendable < required {
+            return Err(DdkError::InsufficientChannelReserve {
+                spendable,
+                collateral,
+                reserve: channel_reserve_sats,
+            });
+        }
+        Ok(())
+    }
+
+    /// Validate that funding a channel with `collateral` leaves the configured channel reserve.
+    pub fn validate_channel_reserve(&self, collateral: u64) -> Result<(), DdkError> {
+        Self::check_channel_reserve(&self.wallet, collateral, self.channel_reserve_sats)
+    }
+
+    /// Offer a DLC channel to `counter_party`. The channel is funded with the collateral of
+    /// the input's first contract. `oracle_announcements` are used to validate the input, the
+    /// manager fetches its own from our oracles. Returns the temporary channel and contract
+    /// ids.
+    pub fn offer_channel(
+        &self,
+        contract_input: &ContractInput,
+        counter_party: PublicKey,
+        oracle_announcements: Vec<OracleAnnouncement>,
+    ) -> Result<(ChannelId, ContractId), DdkError> {
+        self.check_maintenance()?;
+        validate_contract_input(contract_input, &oracle_announcements)
+            .map_err(DdkError::InvalidContractInput)?;
+        self.validate_channel_reserve(contract_input.offer_collateral)?;
+
+        let (responder, receiver) = unbounded();
+        self.sender
+            .send(DlcManagerMessage::OfferChannel { contract_input: contract_input.to_owned(), counter_party, responder })
+            .map_err(|_| DdkError::ManagerNotRunning)?;
+        let (channel_id, contract_id) =
+            wait_for_manager(receiver, self.manager_response_timeout, "offering the channel")??;
+
+        tracing::info!(
+            counterparty = counter_party.to_string(),
+            channel_id = hex::encode(channel_id),
+            "Sent DLC channel offer to counterparty."
+        );
+        Ok((channel_id, contract_id))
+    }
+
+    /// Accept a channel offer we received. Returns the channel and contract ids.
+    pub fn accept_channel(&self, channel_id: ChannelId) -> Result<(ChannelId, ContractId), DdkError> {
+        self.check_maintenance()?;
+        let (responder, receiver) = unbounded();
+        self.sender
+            .send(DlcManagerMessage::AcceptChannel { channel_id, responder })
+            .map_err(|_| DdkError::ManagerNotRunning)?;
+        let (channel_id, contract_id) =
+            wait_for_manager(receiver, self.manager_response_timeout, "accepting the channel")??;
+
+        tracing::info!(channel_id = hex::encode(channel_id), "Accepted DLC channel.");
+        Ok((channel_id, contract_id))
+    }
+
+    /// Offer to settle the channel's contract off chain, paying `accept_settlement_amount`
+    /// sats to the counterparty. The counterparty accepts with
+    /// [DlcDevKit::accept_channel_update].
+    pub fn settle_channel(&self, channel_id: ChannelId, accept_settlement_amount: u64) -> Result<(), DdkError> {
+        let (responder, receiver) = unbounded();
+        self.sender
+            .send(DlcManagerMessage::SettleChannel { channel_id, counter_payout: accept_settlement_amount, responder })
+            .map_err(|_| DdkError::ManagerNotRunning)?;
+        wait_for_manager(receiver, self.manager_response_timeout, "offering the settlement")??;
+
+        tracing::info!(channel_id = hex::encode(channel_id), "Offered channel settlement.");
+        Ok(())
+    }
+
+    /// Offer a new contract in a settled or established channel. The counterparty keeps the
+    /// input's accept collateral. Returns the temporary id of the new contract. The
+    /// counterparty accepts with [DlcDevKit::accept_channel_update].
+    pub fn renew_channel(&self, channel_id: ChannelId, contract_input: &ContractInput) -> Result<ContractId, DdkError> {
+        self.check_maintenance()?;
+        let (responder, receiver) = unbounded();
+        self.sender
+            .send(DlcManagerMessage::RenewChannel {
+                channel_id,
+                counter_payout: contract_input.accept_collateral,
+                contract_input: contract_input.to_owned(),
+                responder,
+            })
+            .map_err(|_| DdkError::ManagerNotRunning)?;
+        let contract_id = wait_for_manager(receiver, self.manager_response_timeout, "offering the renewal")??;
+
+        tracing::info!(
+            channel_id = hex::encode(channel_id),
+            contract_id = hex::encode(contract_id),
+            "Offered channel renewal."
+        );
+        Ok(contract_id)
+    }
+
+    /// Accept the settle, renew, or collaborative close offer the counterparty made for a
+    /// channel.
+    pub fn accept_channel_update(&self, channel_id: ChannelId) -> Result<(), DdkError> {
+        let (responder, receiver) = unbounded();
+        self.sender
+            .send(DlcManagerMessage::AcceptChannelUpdate { channel_id, responder })
+            .map_err(|_| DdkError::ManagerNotRunning)?;
+        wait_for_manager(receiver, self.manager_response_timeout, "accepting the channel update")??;
+
+        tracing::info!(channel_id = hex::encode(channel_id), "Accepted channel update.");
+        Ok(())
+    }
+
+    /// Offer to close a settled channel on chain with its settled balances. The close
+    /// transaction is broadcast once the counterparty accepts with
+    /// [DlcDevKit::accept_channel_update].
+    pub fn collaborative_close_channel(&self, channel_id: ChannelId) -> Result<(), DdkError> {
+        let (responder, receiver) = unbounded();
+        self.sender
+            .send(DlcManagerMessage::CloseChannel { channel_id, responder })
+            .map_err(|_| DdkError::ManagerNotRunning)?;
+        wait_for_manager(receiver, self.manager_response_timeout, "offering the channel close")??;
+
+        tracing::info!(channel_id = hex::encode(channel_id), "Offered collaborative channel close.");
+        Ok(())
+    }
+
+    /// Offered and signed channels, with signed channels by their state. Closed and failed
+    /// channels are not listed.
+    pub fn list_channels(&self) -> Result<Vec<ChannelSummary>, DdkError> {
+        let mut channels = self
+            .storage
+            .get_offered_channels()
+            .map_err(StorageError::new)?
+            .into_iter()
+            .map(Channel::Offered)
+            .collect::<Vec<_>>();
+        channels.extend(
+            self.storage
+                .get_signed_channels(None)
+                .map_err(StorageError::new)?
+                .into_iter()
+                .map(Channel::Signed),
+        );
+        Ok(channels.iter().map(ChannelSummary::from).collect())
+    }
+
+    /// Fee-bump a force closed channel through the CET it broadcast. The CET pays our payout
+    /// to a wallet address, so a wallet child of the CET brings the package to `target_rate`,
+    /// adding wallet UTXOs when our payout does not cover the fee. The child is rebroadcast
+    /// until it confirms.
+    ///
+    /// The buffer transaction cannot be bumped: its only output is shared with the
+    /// counterparty until a CET or a punishment spends it. Settle transaction outputs are
+    /// timelocked for their owner, so they cannot pay for a child before they confirm either.
+    /// Fails until the CET of the channel is broadcast, and when it pays us nothing.
+    pub fn bump_channel_close(
+        &self,
+        channel_id: ChannelId,
+        target_rate: FeeRate,
+    ) -> Result<Txid, DdkError> {
+        let preclosed = self
+            .storage
+            .get_preclosed_contracts()
+            .map_err(StorageError::new)?
+            .into_iter()
+            .find(|contract| contract.signed_contract.channel_id == Some(channel_id));
+        let Some(preclosed) = preclosed else {
+            let channel = self
+                .storage
+                .get_channel(&channel_id)
+                .map_err(StorageError::new)?
+                .ok_or_else(|| anyhow!("Channel {} not found.", hex::encode(channel_id)))?;
+            let reason = match channel {
+                Channel::Closing(_)
+                | Channel::Signed(SignedChannel {
+                    state: SignedChannelState::Closing { .. },
+                    ..
+                }) => "Buffer transaction outputs are shared, bump once the CET is broadcast.",
+                Channel::Signed(SignedChannel {
+                    state: SignedChannelState::Settled { .. },
+                    ..
+                }) => "Settle transaction outputs are timelocked and cannot pay for a child.",
+                _ => "Channel has no unconfirmed CET to bump.",
+            };
+            return Err(anyhow!("{}", reason).into());
+        };
+
+        let cet = preclosed.signed_cet;
+        let cet_txid = cet.compute_txid();
+        let buffer_outpoint = cet
+            .input
+            .first()
+            .map(|input| input.previous_output)
+            .ok_or_else(|| anyhow!("CET {} has no input.", cet_txid))?;
+        let buffer = self
+            .wallet
+            .blockchain
+            .find_transaction(&buffer_outpoint.txid)?
+            .ok_or_else(|| anyhow!("Buffer transaction {} not found.", buffer_outpoint.txid))?;
+        let buffer_output = buffer
+            .output
+            .get(buffer_outpoint.vout as usize)
+            .ok_or_else(|| anyhow!("Buffer output {} not found.", buffer_outpoint))?;
+        let cet_output_value: Amount = cet.output.iter().map(|o| o.value).sum();
+        let parent_fee = buffer_output
+            .value
+            .checked_sub(cet_output_value)
+            .ok_or_else(|| anyhow!("CET spends more than the buffer output."))?;
+
+        let child = self.wallet.bump_fee_cpfp(cet, parent_fee, target_rate)?;
+        let txid = child.compute_txid();
+        if let Err(e) = self.broadcasts.track(&child) {
+            tracing::error!(
+                txid = txid.to_string(),
+                error = e.to_string(),
+                "Could not track child transaction for channel close."
+            );
+        }
+        tracing::info!(
+            channel_id = hex::encode(channel_id),
+            parent = cet_txid.to_string(),
+            txid = txid.to_string(),
+            "Broadcast child transaction for channel close."
+        );
+
+        Ok(txid)
+    }
+
+    /// Fee-bump the unconfirmed funding transaction of a signed contract to `fee_rate` with a
+    /// child transaction that spends our change output. The child is rebroadcast until it
+    /// confirms.
+    ///
+    /// The funding transaction cannot be replaced even though we co-sign it. The CET adaptor
+    /// signatures and the refund signature both parties exchanged commit to its txid, so a
+    /// replacement needs the counterparty to sign its inputs, every CET and the refund again.
+    /// The DLC protocol has no message for that renegotiation. Fails when the funding
+    /// transaction has no change output of ours, e.g. because our inputs matched the
+    /// collateral and fees; the counterparty can still bump from their change.
+    pub fn bump_funding_fee(&self, contract_id: ContractId, fee_rate: FeeRate) -> Result<Txid, DdkError> {
+        let contract = self
+            .storage
+            .get_contract(&contract_id)
+            .map_err(StorageError::new)?
+            .ok_or(DdkError::ContractNotFound(contract_id))?;
+        let signed = match contract {
+            Contract::Signed(signed) => signed,
+            contract => {
+                return Err(DdkError::InvalidState {
+                    expected: ContractState::Signed,
+                    actual: ContractState::from(&contract),
+                })
+            }
+        };
+
+        let funding_fee = crate::contract::funding_fee(&signed)
+            .ok_or_else(|| anyhow!("Could not compute the fee of the funding transaction."))?;
+        let fund = signed.accepted_contract.dlc_transactions.fund.clone();
+        let child = self.wallet.bump_fee_cpfp(fund, funding_fee, fee_rate)?;
+        let txid = child.compute_txid();
+        if let Err(e) = self.broadcasts.track(&child) {
+            tracing::error!(
+                txid = txid.to_string(),
+                error = e.to_string(),
+                "Could not track child transaction for contract funding."
+            );
+        }
+        tracing::info!(
+            contract_id = hex::encode(contract_id),
+            txid = txid.to_string(),
+            "Broadcast child transaction for contract funding."
+        );
+
+        Ok(txid)
+    }
+
+    /// Rebuild what the seed can recover after the data directory was lost or damaged.
+    ///
+    /// Scans the chain for the wallet's scripts, re-derives signer keys for key ids left in
+    /// storage, and reports outputs that may fund open contracts. Contract state itself is not
+    /// on chain, so the reported contracts have to be settled with the counterparty by hand.
+    pub fn recover_from_seed(&self, birthday_height: u32) -> Result<RecoveryReport, DdkError> {
+        let scan = self
+            .wallet
+            .scan_for_recovery(self.recovery_stop_gap, birthday_height)?;
+        let signers = self.wallet.rebuild_signers(self.recovery_stop_gap as u32)?;
+
+        // Surviving contract records name the contracts of their funding transactions.
+        let mut records = self.storage.list_contract_transactions().unwrap_or_else(|e| {
+            tracing::warn!(error = e.to_string(), "Could not read recorded contract transactions.");
+            vec![]
+        });
+        match self.storage.get_contracts() {
+            Ok(contracts) => records.extend(contracts.iter().flat_map(contract_transactions)),
+            Err(e) => tracing::warn!(error = e.to_string(), "Could not read stored contracts."),
+        }
+        let funding = records
+            .into_iter()
+            .filter(|record| record.kind == ContractTransactionKind::Funding)
+            .map(|record| (record.txid, record.contract_id))
+            .collect::<HashMap<_, _>>();
+        let mut potential_contracts = scan.potential_contracts;
+        for potential in potential_contracts.iter_mut() {
+            potential.contract_id = funding.get(&potential.funding_outpoint.txid).copied();
+        }
+
+        let report = RecoveryReport {
+            birthday_height,
+            stop_gap: self.recovery_stop_gap,
+            utxos: scan.utxos,
+            potential_contracts,
+            signers,
+        };
+        tracing::info!(
+            utxos = report.utxos.len(),
+            potential_contracts = report.potential_contracts.len(),
+            unrecovered_signers = report.signers.unrecovered.len(),
+            "Recovered wallet from seed."
+        );
+        Ok(report)
+    }
+
+    /// Subscribe to contract lifecycle and peer events.
+    pub fn subscribe(&self) -> Receiver<DdkEvent> {
+        self.events.subscribe()
+    }
+
+    /// Connect to a peer and remember it.
+    pub async fn connect_peer(&self, pubkey: PublicKey, host: &str) -> Result<(), DdkError> {
+        self.transport.connect_outbound(pubkey, host).await;
+        self.storage
+            .save_peer(PeerInformation {
+                pubkey: pubkey.to_string(),
+                host: host.to_string(),
+            })
+            .map_err(StorageError::new)?;
+        if self.transport.is_connected(&pubkey) {
+            self.events.emit(DdkEvent::PeerConnected(pubkey));
+        }
+        Ok(())
+    }
+
+    /// Try once to connect to every stored peer that is not connected.
+    pub async fn connect_if_necessary(&self) -> Result<(), DdkError> {
+        for peer in self.storage.list_peers().map_err(StorageError::new)? {
+            let pubkey = PublicKey::from_str(&peer.pubkey).map_err(StorageError::new)?;
+            if self.transport.is_connected(&pubkey) {
+                continue;
+            }
+            match self.transport.connect(peer.clone()).await {
+                Ok(()) => self.events.emit(DdkEvent::PeerConnected(pubkey)),
+                Err(e) => {
+                    tracing::warn!(pubkey = peer.pubkey, host = peer.host, error = e.to_string(), "Could not connect to stored peer.");
+                    self.events.emit(DdkEvent::PeerConnectionFailed { pubkey, attempts: 1 });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Stored peers the transport is connected to.
+    pub fn list_connected_peers(&self) -> Result<Vec<PeerInformation>, DdkError> {
+        Ok(self
+            .storage
+            .list_peers()
+            .map_err(StorageError::new)?
+            .into_iter()
+            .filter(|peer| {
+                PublicKey::from_str(&peer.pubkey)
+                    .map(|pubkey| self.transport.is_connected(&pubkey))
+                    .unwrap_or(false)
+            })
+            .collect())
+    }
+
+    /// Every stored contract.
+    pub fn list_contracts(&self) -> Result<Vec<ContractSummary>, DdkError> {
+        self.storage
+            .get_contracts()
+            .map_err(StorageError::new)?
+            .iter()
+            .map(|contract| self.summarize(contract))
+            .collect()
+    }
+
+    /// A page of the contracts passing `filter`, oldest first.
+    pub fn list_contracts_paginated(
+        &self,
+        filter: ContractFilter,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<ContractSummary>, DdkError> {
+        self.storage
+            .get_contracts_paginated(filter, offset, limit)
+            .map_err(StorageError::new)?
+            .iter()
+            .map(|contract| self.summarize(contract))
+            .collect()
+    }
+
+    /// Offers sent or received that were not accepted or rejected yet.
+    pub fn list_offers(&self) -> Result<Vec<ContractSummary>, DdkError> {
+        self.storage
+            .get_contract_offers()
+            .map_err(StorageError::new)?
+            .into_iter()
+            .map(|offer| self.summarize(&Contract::Offered(offer)))
+            .collect()
+    }
+
+    /// Confirmed contracts whose oracle event matures within `within`, earliest first. Matured
+    /// contracts that are not settled yet come first.
+    pub fn upcoming_maturities(&self, within: Duration) -> Result<Vec<MaturityInfo>, DdkError> {
+        let contracts = self.storage.get_confirmed_contracts().map_err(StorageError::new)?;
+        let now = SystemTimeProvider {}.unix_time_now();
+        Ok(upcoming_maturities(&contracts, now, within))
+    }
+
+    /// A contract by its id, or its temporary id while it is an offer.
+    pub fn get_contract(&self, contract_id: ContractId) -> Result<Option<ContractDetails>, DdkError> {
+        let Some(contract) = self.storage.get_contract(&contract_id).map_err(StorageError::new)? else {
+            return Ok(None);
+        };
+        let mut details = ContractDetails::from(&contract);
+        details.summary.metadata = self
+            .storage
+            .get_contract_metadata(&contract.get_id())
+            .map_err(StorageError::new)?;
+        self.add_confirmations(&contract, &mut details.summary);
+        self.add_fiat_values(&contract, &mut details.summary)?;
+        Ok(Some(details))
+    }
+
+    /// Attach a label, tags and notes to a contract. Offers can be labelled by their temporary
+    /// id, the metadata moves to the contract id when the offer is accepted. The contract's
+    /// [AcceptanceParams], funding broadcast role and negotiation clock are kept unless
+    /// `metadata` has its own.
+    pub fn set_contract_metadata(
+        &self,
+        contract_id: ContractId,
+        mut metadata: ContractMetadata,
+    ) -> Result<(), DdkError> {
+        if let Some(stored) = self.get_contract_metadata(contract_id)? {
+            metadata.acceptance = metadata.acceptance.or(stored.acceptance);
+            metadata.funding_broadcast_role = metadata.funding_broadcast_role.or(stored.funding_broadcast_role);
+            metadata.negotiation = metadata.negotiation.or(stored.negotiation);
+            metadata.held_for_maintenance |= stored.held_for_maintenance;
+        }
+        Ok(self
+            .storage
+            .set_contract_metadata(&contract_id, metadata)
+            .map_err(StorageError::new)?)
+    }
+
+    pub fn get_contract_metadata(
+        &self,
+        contract_id: ContractId,
+    ) -> Result<Option<ContractMetadata>, DdkError> {
+        Ok(self
+            .storage
+            .get_contract_metadata(&contract_id)
+            .map_err(StorageError::new)?)
+    }
+
+    /// Audit entries of a contract in the order they were recorded, including the ones
+    /// written under its temporary id before it was accepted.
+    pub fn audit_log(&self, contract_id: ContractId) -> Result<Vec<AuditEntry>, DdkError> {
+        let mut ids = vec![hex::encode(contract_id)];
+        if let Some(contract) = self.storage.get_contract(&contract_id).map_err(StorageError::new)? {
+            ids.push(hex::encode(contract.get_id()));
+            ids.push(hex::encode(contract.get_temporary_id()));
+        }
+        Ok(self
+            .storage
+            .audit_entries(0)
+            .map_err(StorageError::new)?
+            .into_iter()
+            .filter(|entry| entry.is_for(&ids))
+            .collect())
+    }
+
+    /// Write the whole audit log to `writer` as JSON Lines. Returns the number of entries.
+    pub fn export_audit_log<W: std::io::Write>(&self, writer: W) -> Result<usize, DdkError> {
+        let entries = self.storage.audit_entries(0).map_err(StorageError::new)?;
+        Ok(write_json_lines(&entries, writer)?)
+    }
+
+    fn summarize(&self, contract: &Contract) -> Result<ContractSummary, DdkError> {
+        let mut summary = ContractSummary::from(contract);
+        summary.metadata = self
+            .storage
+            .get_contract_metadata(&contract.get_id())
+            .map_err(StorageError::new)?;
+        self.add_confirmations(contract, &mut summary);
+        self.add_fiat_values(contract, &mut summary)?;
+        Ok(summary)
+    }
+
+    /// Collateral and profit and loss at the exchange rates recorded for the contract. Left
+    /// out without a recorded rate.
+    fn add_fiat_values(&self, contract: &Contract, summary: &mut ContractSummary) -> Result<(), DdkError> {
+        let Some(rates) = self
+            .storage
+            .get_contract_rates(&contract.get_id())
+            .map_err(StorageError::new)?
+        else {
+            return Ok(());
+        };
+        summary.fiat_collateral = summary.total_collateral.and_then(|sats| rates.fiat_at_accept(sats));
+        summary.fiat_pnl = summary.pnl.and_then(|pnl| rates.pnl_at_close(pnl));
+        summary.fiat_currency = Some(rates.currency);
+        Ok(())
+    }
+
+    /// Funding confirmations of signed and confirmed contracts. Left out when the chain
+    /// backend does not answer, so listing contracts does not depend on it.
+    fn add_confirmations(&self, contract: &Contract, summary: &mut ContractSummary) {
+        let (Contract::Signed(signed) | Contract::Confirmed(signed)) = contract else {
+            return;
+        };
+        let acceptance = summary.metadata.as_ref().and_then(|metadata| metadata.acceptance);
+        summary.required_confirmations = Some(self.confirmation_policy.required_with(signed, acceptance));
+        let txid = signed.accepted_contract.dlc_transactions.fund.compute_txid();
+        match self.wallet.blockchain.get_transaction_confirmations(&txid) {
+            Ok(confirmations) => summary.confirmations = Some(confirmations),
+            Err(e) => tracing::debug!(
+                contract_id = summary.id,
+                error = e.to_string(),
+                "Could not get funding confirmations."
+            ),
+        }
+    }
+
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    pub fn wallet(&self) -> Arc<DlcDevKitWallet<S, B>> {
+        self.wallet.clone()
+    }
+
+    /// When the wallet last synced and whether the background sync is failing.
+    pub fn sync_status(&self) -> SyncStatus {
+        self.wallet.sync_status()
+    }
+
+    pub fn manager(&self) -> Arc<DlcDevKitDlcManager<S, O, B>> {
+        self.manager.clone()
+    }
+
+    pub fn transport(&self) -> Arc<T> {
+        self.transport.clone()
+    }
+
+    pub fn storage(&self) -> Arc<S> {
+        self.storage.clone()
+    }
+
+    /// The primary oracle. See [DlcDevKit::list_oracles] for every oracle.
+    pub fn oracle(&self) -> Arc<O> {
+        self.oracle.clone()
+    }
+
+    /// Storage sizes, contract counts by state, and wallet counts. Cheap enough to poll.
+    pub fn storage_stats(&self) -> Result<StorageStats, DdkError> {
+        let mut stats = self.storage.storage_stats().map_err(StorageError::new)?;
+        stats.wallet = self.wallet.stats()?;
+        Ok(stats)
+    }
+
+    /// Check that stored contracts, channels, and the chain monitor can be read and are
+    /// consistent. Nothing is changed, see [crate::storage::SledStorageProvider::repair] to fix
+    /// what the sled storage reports.
+    pub fn verify_storage(&self) -> Result<IntegrityReport, DdkError> {
+        Ok(self.storage.verify_integrity().map_err(StorageError::new)?)
+    }
+
+    /// Liveness of the node for health checks. Reads what the background tasks recorded and
+    /// local storage, without calling the chain backend, oracles, or peers. Storage failures are
+    /// reported in [DdkStatus::storage] instead of failing the call.
+    pub fn status(&self) -> DdkStatus {
+        let sync = self.wallet.sync_status();
+        let stats = self.storage.storage_stats().map_err(|e| e.to_string());
+        let maintenance = self.storage.maintenance().map_err(|e| e.to_string());
+        let held_offers = self
+            .held_offers()
+            .map(|offers| offers.len())
+            .map_err(|e| e.to_string());
+        let connected_peers = self
+            .list_connected_peers()
+            .map(|peers| peers.len())
+            .map_err(|e| e.to_string());
+        let pending_inbound_messages = self
+            .storage
+            .pending_inbound_messages()
+            .map(|messages| messages.len())
+            .map_err(|e| e.to_string());
+        let pending_outbound_messages = self
+            .storage
+            .list_pending_outbound()
+            .map(|messages| messages.len())
+            .map_err(|e| e.to_string());
+        let storage = StorageHealth {
+            size_on_disk: stats.as_ref().ok().and_then(|stats| stats.size_on_disk),
+            tree_sizes: stats.as_ref().map(|stats| stats.tree_sizes.clone()).unwrap_or_default(),
+            wallet_changesets: stats.as_ref().map(|stats| stats.wallet_changesets).unwrap_or_default(),
+            error: [
+                stats.as_ref().err(),
+                maintenance.as_ref().err(),
+                held_offers.as_ref().err(),
+                connected_peers.as_ref().err(),
+                pending_inbound_messages.as_ref().err(),
+                pending_outbound_messages.as_ref().err(),
+            ]
+            .into_iter()
+            .flatten()
+            .next()
+            .cloned(),
+        };
+
+        DdkStatus {
+            running: self.runtime.read().unwrap().is_some(),
+            last_wallet_sync: sync.last_successful_sync.map(instant_to_unix),
+            wallet_sync_failures: sync.consecutive_failures,
+            chain_tip: self.status.chain_tip(),
+            transport: self.transport.name(),
+            listening: self.status.listening(),
+            connected_peers: connected_peers.unwrap_or_default(),
+            pending_inbound_messages: pending_inbound_messages.unwrap_or_default(),
+            pending_outbound_messages: pending_outbound_messages.unwrap_or_default(),
+            contracts_by_state: stats.map(|stats| stats.contracts_by_state).unwrap_or_default(),
+            last_oracle_contact: self.status.last_oracle_contact(),
+            maintenance: maintenance.unwrap_or_default(),
+            held_offers: held_offers.unwrap_or_default(),
+            storage,
+        }
+    }
+
+    /// Metrics in the Prometheus text exposition format, to serve on a scrape endpoint. The
+    /// metrics are shared by every node in the process, the storage gauges are those of the
+    /// node scraped last. Empty without the `metrics` feature.
+    pub fn gather_metrics(&self) -> String {
+        if cfg!(feature = "metrics") {
+            match self.storage_stats() {
+                Ok(stats) => metrics::storage_stats(&stats),
+                Err(e) => tracing::warn!(error = e.to_string(), "Could not read storage stats."),
+            }
+        }
+        metrics::gather()
+    }
+
+    /// Transactions the node broadcast that have not confirmed yet. Ones missing from the
+    /// mempool are rebroadcast every [RebroadcastOptions::interval].
+    pub fn pending_broadcasts(&self) -> Result<Vec<PendingBroadcast>, DdkError> {
+        Ok(self.broadcasts.pending().map_err(StorageError::new)?)
+    }
+
+    /// Messages waiting to be delivered, with the sends the transport took. Contract messages
+    /// are resent every [OutboundRetryOptions::interval] until the counterparty acts on them.
+    pub fn pending_outbound(&self) -> Result<Vec<PendingOutbound>, DdkError> {
+        Ok(self.storage.list_pending_outbound().map_err(StorageError::new)?)
+    }
+
+    /// Current open contracts and collateral at risk, computed from storage.
+    pub fn risk_utilization(&self) -> Result<RiskUtilization, DdkError> {
+        let contracts = self.storage.get_contracts().map_err(StorageError::new)?;
+        Ok(RiskUtilization::from_contracts(&contracts))
+    }
+
+    /// Stop creating and accepting contracts while existing contracts keep settling.
+    /// The mode is persisted and survives restarts.
+    pub fn set_maintenance(&self, enabled: bool) -> Result<(), DdkError> {
+        self.storage.set_maintenance(enabled).map_err(StorageError::new)?;
+        tracing::info!(enabled, "Set maintenance mode.");
+        Ok(())
+    }
+
+    pub fn is_maintenance(&self) -> Result<bool, DdkError> {
+        Ok(self.storage.maintenance().map_err(StorageError::new)?)
+    }
+
+    /// Offers received in maintenance mode that were not accepted or rejected yet. They never
+    /// went to the offer policy, so they wait for the application.
+    pub fn held_offers(&self) -> Result<Vec<ContractSummary>, DdkError> {
+        let mut offers = self.list_offers()?;
+        offers.retain(|offer| offer.metadata.as_ref().is_some_and(|m| m.held_for_maintenance));
+        Ok(offers)
+    }
+
+    /// Peers whose messages are currently dropped for exceeding the
+    /// [crate::transport::rate_limit::PeerLimits].
+    pub fn banned_peers(&self) -> Result<Vec<PeerBan>, DdkError> {
+        let now = SystemTimeProvider {}.unix_time_now();
+        let mut bans = self.storage.list_peer_bans().map_err(StorageError::new)?;
+        bans.retain(|ban| ban.is_active(now));
+        Ok(bans)
+    }
+
+    /// Lift the ban of a peer before it expires. Returns false if the peer was not banned.
+    pub fn unban_peer(&self, pubkey: &PublicKey) -> Result<bool, DdkError> {
+        let mut bans = self.storage.list_peer_bans().map_err(StorageError::new)?;
+        let banned = bans.len();
+        bans.retain(|ban| ban.pubkey != *pubkey);
+        if bans.len() == banned {
+            return Ok(false);
+        }
+        self.storage.save_peer_bans(&bans).map_err(StorageError::new)?;
+        tracing::info!(pubkey = pubkey.to_string(), "Unbanned peer.");
+        Ok(true)
+    }
+
+    fn check_maintenance(&self) -> Result<(), DdkError> {
+        if self.storage.maintenance().map_err(StorageError::new)? {
+            return Err(DdkError::Maintenance);
+        }
+        Ok(())
+    }
+
+    fn check_risk_limits(&self, counter_party: &PublicKey, collateral: u64) -> Result<(), DdkError> {
+        let utilization = self.risk_utilization()?;
+        self.risk_limits.check(&utilization, counter_party, collateral)
+    }
+
+    /// Offer a contract to `counter_party`. `oracle_announcements` must hold an announcement
+    /// of the input's event from every oracle it references, and those oracles must be ours.
+    /// The input is checked with [crate::validation::validate_contract_input] first.
+    pub fn send_dlc_offer(
+        &self,
+        contract_input: &ContractInput,
+        counter_party: PublicKey,
+        oracle_announcements: Vec<OracleAnnouncement>,
+    ) -> Result<OfferDlc, DdkError> {
+        let (responder, receiver) = unbounded();
+        self.request_offer(contract_input, counter_party, &oracle_announcements, Responder::Blocking(responder))?;
+        let offer = wait_for_manager(receiver, self.manager_response_timeout, "creating the offer")??;
+        self.deliver_offer(counter_party, offer)
+    }
+
+    /// [DlcDevKit::send_dlc_offer] with the confirmations we want on the funding transaction
+    /// before the contract counts as confirmed. With zero the contract is confirmed as soon as
+    /// it is signed, for counterparties trusted not to double-spend it.
+    pub fn send_dlc_offer_with_params(
+        &self,
+        contract_input: &ContractInput,
+        counter_party: PublicKey,
+        oracle_announcements: Vec<OracleAnnouncement>,
+        params: AcceptanceParams,
+    ) -> Result<OfferDlc, DdkError> {
+        let (responder, receiver) = unbounded();
+        self.request_offer(contract_input, counter_party, &oracle_announcements, Responder::Blocking(responder))?;
+        let offer = wait_for_manager(receiver, self.manager_response_timeout, "creating the offer")??;
+        self.set_acceptance_params(offer.temporary_contract_id, params)?;
+        self.deliver_offer(counter_party, offer)
+    }
+
+    /// [DlcDevKit::send_dlc_offer] without blocking the runtime while the manager creates
+    /// the offer.
+    pub async fn send_dlc_offer_async(
+        &self,
+        contract_input: &ContractInput,
+        counter_party: PublicKey,
+        oracle_announcements: Vec<OracleAnnouncement>,
+    ) -> Result<OfferDlc, DdkError> {
+        let (responder, receiver) = oneshot::channel();
+        self.request_offer(contract_input, counter_party, &oracle_announcements, Responder::Async(responder))?;
+        let offer =
+            wait_for_manager_async(receiver, self.manager_response_timeout, "creating the offer").await??;
+        self.deliver_offer(counter_party, offer)
+    }
+
+    /// Offer a contract on `total_collateral`, of which we put up `our_share_ppm` parts per
+    /// million and the counterparty the rest. Every announced oracle must attest. Fails with
+    /// [DdkError::InsufficientFunds] before the offer is built when the wallet cannot fund our
+    /// collateral and fees.
+    pub fn send_dlc_offer_with_split(
+        &self,
+        contract_descriptor: ContractDescriptor,
+        total_collateral: u64,
+        our_share_ppm: u32,
+        fee_rate: u64,
+        counter_party: PublicKey,
+        oracle_announcements: Vec<OracleAnnouncement>,
+    ) -> Result<OfferDlc, DdkError> {
+        let contract_input = self.split_contract_input(contract_descriptor, total_collateral, our_share_ppm, fee_rate, &oracle_announcements)?;
+        self.send_dlc_offer(&contract_input, counter_party, oracle_announcements)
+    }
+
+    /// [DlcDevKit::send_dlc_offer_with_split] without blocking the runtime.
+    pub async fn send_dlc_offer_with_split_async(
+        &self,
+        contract_descriptor: ContractDescriptor,
+        total_collateral: u64,
+        our_share_ppm: u32,
+        fee_rate: u64,
+        counter_party: PublicKey,
+        oracle_announcements: Vec<OracleAnnouncement>,
+    ) -> Result<OfferDlc, DdkError> {
+        let contract_input = self.split_contract_input(contract_descriptor, total_collateral, our_share_ppm, fee_rate, &oracle_announcements)?;
+        self.send_dlc_offer_async(&contract_input, counter_party, oracle_announcements).await
+    }
+
+    fn split_contract_input(
+        &self,
+        contract_descriptor: ContractDescriptor,
+        total_collateral: u64,
+        our_share_ppm: u32,
+        fee_rate: u64,
+        oracle_announcements: &[OracleAnnouncement],
+    ) -> Result<ContractInput, DdkError> {
+        let (offer_collateral, accept_collateral) =
+            collateral_split(total_collateral, our_share_ppm).ok_or(DdkError::InvalidCollateralShare(our_share_ppm))?;
+        self.check_funding(offer_collateral, fee_rate)?;
+        let oracles = OracleInput {
+            public_keys: oracle_announcements.iter().map(|a| a.oracle_public_key).collect(),
+            event_id: oracle_announcements.first().map(|a| a.oracle_event.event_id.clone()).unwrap_or_default(),
+            threshold: oracle_announcements.len() as u16,
+        };
+        Ok(ContractInput {
+            offer_collateral,
+            accept_collateral,
+            fee_rate,
+            contract_infos: vec![ContractInputInfo { contract_descriptor, oracles }],
+        })
+    }
+
+    /// Check that the wallet can fund `collateral` and our share of the fees at `fee_rate`
+    /// sats/vbyte from UTXOs that are not reserved or frozen, selecting them like the DLC
+    /// manager will.
+    pub fn check_funding(&self, collateral: u64, fee_rate: u64) -> Result<(), DdkError> {
+        match self.wallet.check_dlc_funding(collateral, fee_rate) {
+            Ok(_) => Ok(()),
+            Err(WalletError::InsufficientFunds { needed, available }) => Err(DdkError::InsufficientFunds {
+                collateral,
+                fees: needed.to_sat().saturating_sub(collateral),
+                available: available.to_sat(),
+                shortfall: needed.to_sat().saturating_sub(available.to_sat()),
+            }),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn request_offer(
+        &self,
+        contract_input: &ContractInput,
+        counter_party: PublicKey,
+        oracle_announcements: &[OracleAnnouncement],
+        responder: Responder<Result<OfferDlc, dlc_manager::error::Error>>,
+    ) -> Result<(), DdkError> {
+        self.check_maintenance()?;
+        validate_contract_input(contract_input, oracle_announcements)
+            .map_err(DdkError::InvalidContractInput)?;
+        self.check_risk_limits(&counter_party, contract_input.offer_collateral)?;
+        let oracle_announcements = announcements_for_input(
+            contract_input,
+            oracle_announcements,
+            |pubkey| self.oracles.contains(pubkey),
+        )
+        .map_err(OracleError::Input)?;
+
+        self.sender
+            .send(DlcManagerMessage::OfferDlc { contract_input: contract_input.to_owned(), counter_party, oracle_announcements, responder })
+            .map_err(|_| DdkError::ManagerNotRunning)?;
+        Ok(())
+    }
+
+    /// Store `params` with the metadata of the contract. Set on the temporary id, they move to
+    /// the contract id with the rest of the metadata.
+    fn set_acceptance_params(&self, contract_id: ContractId, params: AcceptanceParams) -> Result<(), DdkError> {
+        let mut metadata = self.get_contract_metadata(contract_id)?.unwrap_or_else(ContractMetadata::new);
+        metadata.acceptance = Some(params);
+        self.set_contract_metadata(contract_id, metadata)
+    }
+
+    /// Check the locktimes of an offer the manager created and send it to the counterparty.
+    /// The manager derives the locktimes from the announcements, so they are only known once
+    /// the offer is stored. An offer that fails the check is cancelled, which releases its
+    /// funding inputs.
+    fn deliver_offer(&self, counter_party: PublicKey, offer: OfferDlc) -> Result<OfferDlc, DdkError> {
+        let valid = Self::chain_tip(&self.wallet.blockchain)
+            .and_then(|tip| Ok(validate_locktimes(offer.cet_locktime, offer.refund_locktime, &tip)?));
+        if let Err(e) = valid {
+            if let Err(cancel) =
+                Self::close_offer_in_store(&self.manager, &self.wallet, offer.temporary_contract_id, cancelled_contract)
+            {
+                tracing::error!(
+                    temporary_contract_id = hex::encode(offer.temporary_contract_id),
+                    error = cancel.to_string(),
+                    "Could not cancel offer that was not sent."
+                );
+            }
+            return Err(e);
+        }
+
+        let contract_id = hex::encode(&offer.temporary_contract_id);
+        send_pending(self.manager.get_store().as_ref(), &self.outbox, counter_party, Message::Offer(offer.clone()));
+        metrics::offer(OfferOutcome::Sent);
+        tracing::info!(
+            counterparty = counter_party.to_string(),
+            contract_id,
+            "Sent DLC offer to counterparty."
+        );
+
+        Ok(offer)
+    }
+
+    /// Withdraw an offer we sent before the counterparty accepts it. The offer is rejected
+    /// locally, its funding inputs released, and the counterparty sent a Reject. A later
+    /// accept from them is answered with another Reject instead of signed.
+    pub fn cancel_offer(&self, contract_id: ContractId) -> Result<(), DdkError> {
+        let (responder, receiver) = unbounded();
+        self.sender
+            .send(DlcManagerMessage::CancelOffer { contract_id, responder })
+            .map_err(|_| DdkError::ManagerNotRunning)?;
+        wait_for_manager(receiver, self.manager_response_timeout, "cancelling the offer")??;
+
+        tracing::info!(contract_id = hex::encode(contract_id), "Cancelled DLC offer.");
+        Ok(())
+    }
+
+    /// Close a confirmed contract now by broadcasting the CET for the attested outcome, without
+    /// the counterparty. Without `attestations` they are fetched from our oracles. The contract
+    /// moves to PreClosed until the CET confirms. Returns the CET txid.
+    pub fn close_contract(
+        &self,
+        contract_id: ContractId,
+        attestations: Vec<OracleAttestation>,
+    ) -> Result<Txid, DdkError> {
+        let stored = self.storage.get_contract(&contract_id).map_err(StorageError::new)?;
+        let contract = confirmed_contract(stored.as_ref())?;
+        let attestations = if attestations.is_empty() {
+            self.fetch_attestations(contract)
+        } else {
+            attestations
+        };
+        let attestations = indexed_attestations(contract, &attestations)?;
+        let verified = attestations.iter().map(|(_, a)| a.clone()).collect::<Vec<_>>();
+        if let Err((oracle, event_id, error)) = verify_contract_attestations(contract, &verified) {
+            self.events.emit(DdkEvent::InvalidAttestation {
+                contract_id,
+                oracle,
+                event_id: event_id.clone(),
+                reason: error.to_string(),
+            });
+            return Err(CloseError::InvalidAttestation { oracle, event_id, error }.into());
+        }
+
+        let (responder, receiver) = unbounded();
+        self.sender
+            .send(DlcManagerMessage::CloseContract { contract_id, attestations, responder })
+            .map_err(|_| DdkError::ManagerNotRunning)?;
+        let txid = wait_for_manager(receiver, self.manager_response_timeout, "closing the contract")??;
+
+        tracing::info!(contract_id = hex::encode(contract_id), txid = txid.to_string(), "Broadcast CET.");
+        Ok(txid)
+    }
+
+    /// Attestations of the contract's events from the oracles we know. Events that are not
+    /// attested yet are left out.
+    fn fetch_attestations(&self, contract: &SignedContract) -> Vec<OracleAttestation> {
+        let mut attestations = Vec::new();
+        for (public_key, event_id) in attesting_oracles(contract) {
+            let Some(oracle) = self.oracles.get(&public_key) else {
+                tracing::warn!(oracle = public_key.to_string(), event_id, "Contract oracle is not configured.");
+                continue;
+            };
+            match oracle.get_attestation(&event_id) {
+                Ok(attestation) => attestations.push(attestation),
+                Err(e) => tracing::warn!(
+                    oracle = public_key.to_string(),
+                    event_id,
+                    error = e.to_string(),
+                    "Could not get attestation."
+                ),
+            }
+        }
+        attestations
+    }
+
+    /// Refund a confirmed contract whose refund locktime is reached on chain, returning both
+    /// parties their collateral. Fails with [DdkError::Refund] holding
+    /// [RefundError::NotYetRefundable] and the remaining wait before the locktime. Returns the
+    /// refund txid.
+    pub fn refund_contract(&self, contract_id: ContractId) -> Result<Txid, DdkError> {
+        let (responder, receiver) = unbounded();
+        self.sender
+            .send(DlcManagerMessage::RefundContract { contract_id, responder })
+            .map_err(|_| DdkError::ManagerNotRunning)?;
+        Ok(wait_for_manager(receiver, self.manager_response_timeout, "refunding the contract")??)
+    }
+
+    /// Run a settlement check now instead of waiting for the next periodic one.
+    pub fn force_check(&self) -> Result<(), DdkError> {
+        let (responder, receiver) = unbounded();
+        self.sender
+            .send(DlcManagerMessage::PeriodicCheck { responder: Some(responder) })
+            .map_err(|_| DdkError::ManagerNotRunning)?;
+        wait_for_manager(receiver, self.manager_response_timeout, "the check finished")??;
+        Ok(())
+    }
+
+    /// Decline an offer we received. The offer is stored as rejected. The DLC protocol has no
+    /// reject message, so the counterparty is not notified and their offer times out.
+    pub fn reject_dlc_offer(&self, contract_id: ContractId) -> Result<(), DdkError> {
+        let (responder, receiver) = unbounded();
+        self.sender
+            .send(DlcManagerMessage::RejectDlc { contract_id, responder })
+            .map_err(|_| DdkError::ManagerNotRunning)?;
+        wait_for_manager(receiver, self.manager_response_timeout, "rejecting the offer")??;
+
+        tracing::info!(contract_id = hex::encode(contract_id), "Rejected DLC offer.");
+        Ok(())
+    }
+
+    pub fn accept_dlc_offer(
+        &self,
+        contract: [u8; 32],
+    ) -> Result<(String, String, AcceptDlc), DdkError> {
+        self.accept_dlc_offer_with_options(contract, AcceptOptions::default())
+    }
+
+    /// Accept an offer, reporting progress through `options.progress` and confirming the
+    /// contract by `options.acceptance` when set.
+    pub fn accept_dlc_offer_with_options(
+        &self,
+        contract: [u8; 32],
+        options: AcceptOptions,
+    ) -> Result<(String, String, AcceptDlc), DdkError> {
+        let (responder, receiver) = unbounded();
+        self.request_accept(contract, options, Responder::Blocking(responder))?;
+        let accepted = wait_for_manager(receiver, self.manager_response_timeout, "accepting the offer")??;
+        Ok(Self::accepted(accepted))
+    }
+
+    /// [DlcDevKit::accept_dlc_offer] without blocking the runtime while the manager signs the
+    /// contract.
+    pub async fn accept_dlc_offer_async(
+        &self,
+        contract: [u8; 32],
+    ) -> Result<(String, String, AcceptDlc), DdkError> {
+        let (responder, receiver) = oneshot::channel();
+        self.request_accept(contract, AcceptOptions::default(), Responder::Async(responder))?;
+        let accepted =
+            wait_for_manager_async(receiver, self.manager_response_timeout, "accepting the offer").await??;
+        Ok(Self::accepted(accepted))
+    }
+
+    fn request_accept(
+        &self,
+        contract: [u8; 32],
+        options: AcceptOptions,
+        responder: Responder<Result<(ContractId, PublicKey, AcceptDlc), dlc_manager::error::Error>>,
+    ) -> Result<(), DdkError> {
+        self.check_maintenance()?;
+        if let Some(Contract::Offered(offer)) = self.storage.get_contract(&contract).map_err(StorageError::new)? {
+            let collateral = offer.total_collateral - offer.offer_params.collateral;
+            self.check_risk_limits(&offer.counter_party, collateral)?;
+        }
+        if let Some(params) = options.acceptance {
+            self.set_acceptance_params(contract, params)?;
+        }
+
+        self.sender
+            .send(DlcManagerMessage::AcceptDlc { contract, options, responder })
+            .map_err(|_| DdkError::ManagerNotRunning)?;
+        Ok(())
+    }
+
+    fn accepted(
+        (contract_id, public_key, accept_dlc): (ContractId, PublicKey, AcceptDlc),
+    ) -> (String, String, AcceptDlc) {
+        let contract_id = hex::encode(&contract_id);
+        let counter_party = public_key.to_string();
+        tracing::info!(counter_party, contract_id, "Accepted DLC contract.");
+
+        (contract_id, counter_party, accept_dlc)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::AuditEventType;
+    use crate::chain::MockBlockchain;
+    use crate::contract::timeout::NegotiationTimedOut;
+    use crate::oracle::P2PDOracleClient;
+    use crate::rates::NoopRateProvider;
+    use crate::storage::SledStorageProvider;
+    use crate::test_util::TestWallet;
+    use crate::transport::inbound::is_transient_error;
+    use crate::transport::lightning::LightningTransport;
+    use crate::transport::MAX_INBOUND_ATTEMPTS;
+    use bitcoin::secp256k1::{Secp256k1, SecretKey};
+    use dlc_manager::contract::accepted_contract::AcceptedContract;
+    use dlc_manager::contract::ser::Serializable;
+    use dlc_messages::{CetAdaptorSignatures, FundingSignatures, SignDlc};
+
+    type TestDdk = DlcDevKit<LightningTransport, SledStorageProvider, P2PDOracleClient, MockBlockchain>;
+
+    /// An Accept for an offer that was never made.
+    fn unknown_accept() -> Message {
+        let mut cursor = ::lightning::io::Cursor::new(include_bytes!(
+            "../tests/data/dlc_storage/sled/Accepted"
+        ));
+        let accepted = AcceptedContract::deserialize(&mut cursor).unwrap();
+        let params = accepted.accept_params;
+        Message::Accept(AcceptDlc {
+            protocol_version: 1,
+            temporary_contract_id: [9u8; 32],
+            accept_collateral: params.collateral,
+            funding_pubkey: params.fund_pubkey,
+            payout_spk: params.payout_script_pubkey,
+            payout_serial_id: params.payout_serial_id,
+            funding_inputs: vec![],
+            change_spk: params.change_script_pubkey,
+            change_serial_id: params.change_serial_id,
+            cet_adaptor_signatures: CetAdaptorSignatures {
+                ecdsa_adaptor_signatures: vec![],
+            },
+            refund_signature: accepted.accept_refund_signature,
+            negotiation_fields: None,
+        })
+    }
+
+    fn unknown_sign() -> Message {
+        let mut cursor = ::lightning::io::Cursor::new(include_bytes!(
+            "../tests/data/dlc_storage/sled/Accepted"
+        ));
+        let accepted = AcceptedContract::deserialize(&mut cursor).unwrap();
+        Message::Sign(SignDlc {
+            protocol_version: 1,
+            contract_id: [9u8; 32],
+            cet_adaptor_signatures: CetAdaptorSignatures {
+                ecdsa_adaptor_signatures: vec![],
+            },
+            refund_signature: accepted.accept_refund_signature,
+            funding_signatures: FundingSignatures {
+                funding_signatures: vec![],
+            },
+        })
+    }
+
+    #[test]
+    fn rejected_offer_is_no_longer_offered() {
+        let test = TestWallet::create_wallet("reject_offer");
+        let manager = test.manager();
+        let counter_party = PublicKey::from_secret_key(
+            &Secp256k1::new(),
+            &SecretKey::from_slice(&[1u8; 32]).unwrap(),
+        );
+        let offer: OfferDlc =
+            serde_json::from_str(include_str!("../tests/data/dlc/offer.json")).unwrap();
+        let contract_id = offer.temporary_contract_id;
+        TestDdk::on_message_with_progress(&manager, &Message::Offer(offer), counter_party, &RwLock::new(None), &Arc::default())
+            .unwrap();
+        assert_eq!(test.storage.get_contract_offers().unwrap().len(), 1);
+
+        TestDdk::close_offer_in_store(&manager, &test.wallet, contract_id, rejected_contract).unwrap();
+        assert!(test.storage.get_contract_offers().unwrap().is_empty());
+        assert!(matches!(
+            test.storage.get_contract(&contract_id).unwrap(),
+            Some(Contract::Rejected(_))
+        ));
+        assert_eq!(
+            TestDdk::close_offer_in_store(&manager, &test.wallet, [7u8; 32], rejected_contract),
+            Err(CancelError::NotFound)
+        );
+    }
+
+    #[test]
+    fn stalled_accept_is_rejected_under_its_temporary_id() {
+        let test = TestWallet::create_wallet("stalled_accept");
+        let manager = test.manager();
+        let mut cursor = ::lightning::io::Cursor::new(include_bytes!(
+            "../tests/data/dlc_storage/sled/Accepted"
+        ));
+        let accepted = Contract::Accepted(AcceptedContract::deserialize(&mut cursor).unwrap());
+        let (contract_id, temporary_id) = (accepted.get_id(), accepted.get_temporary_id());
+        test.storage.update_contract(&accepted).unwrap();
+        test.storage
+            .set_contract_metadata(&contract_id, ContractMetadata::new().with_label("stalled"))
+            .unwrap();
+        let events = EventBus::default();
+        let received = events.subscribe();
+
+        let stalled = NegotiationTimedOut {
+            contract_id,
+            stalled_state: ContractState::Accepted,
+            counterparty: accepted.get_counter_party_id(),
+        };
+        fail_negotiation(manager.get_store().as_ref(), &test.wallet, &events, &[accepted], stalled);
+
+        assert!(test.storage.get_contract(&contract_id).unwrap().is_none());
+        assert!(matches!(
+            test.storage.get_contract(&temporary_id).unwrap(),
+            Some(Contract::Rejected(_))
+        ));
+        let metadata = test.storage.get_contract_metadata(&temporary_id).unwrap().unwrap();
+        assert_eq!(metadata.label.as_deref(), Some("stalled"));
+        assert_eq!(
+            received.try_recv().unwrap(),
+            DdkEvent::NegotiationTimedOut { contract_id, stalled_state: ContractState::Accepted }
+        );
+    }
+
+    #[test]
+    fn only_confirmed_contracts_are_closed_or_refunded() {
+        let test = TestWallet::create_wallet("close_unconfirmed");
+        let manager = test.manager();
+        let counter_party = PublicKey::from_secret_key(
+            &Secp256k1::new(),
+            &SecretKey::from_slice(&[1u8; 32]).unwrap(),
+        );
+        let offer: OfferDlc =
+            serde_json::from_str(include_str!("../tests/data/dlc/offer.json")).unwrap();
+        let contract_id = offer.temporary_contract_id;
+        TestDdk::on_message_with_progress(&manager, &Message::Offer(offer), counter_party, &RwLock::new(None), &Arc::default())
+            .unwrap();
+        let events = EventBus::default();
+        let runtime = Runtime::new().unwrap();
+        let rates = RateRecorder::new(Arc::new(NoopRateProvider), "USD".into(), runtime.handle().clone());
+        let broadcasts = BroadcastTracker::new(test.blockchain.clone(), test.storage.clone());
+
+        assert_eq!(
+            TestDdk::close_confirmed(&manager, &events, &rates, contract_id, vec![]),
+            Err(CloseError::NotConfirmed(ContractState::Offered))
+        );
+        assert_eq!(
+            TestDdk::close_confirmed(&manager, &events, &rates, [7u8; 32], vec![]),
+            Err(CloseError::NotFound)
+        );
+        assert_eq!(
+            TestDdk::refund(&manager, &test.wallet, &broadcasts, &events, contract_id),
+            Err(RefundError::NotConfirmed(ContractState::Offered))
+        );
+    }
+
+    #[test]
+    fn offer_policy_accepts_or_rejects_stored_offers() {
+        use crate::contract::policy::{ManualOnly, RuleBasedPolicy};
+
+        let test = TestWallet::create_wallet("offer_policy");
+        let manager = test.manager();
+        let counter_party = PublicKey::from_secret_key(
+            &Secp256k1::new(),
+            &SecretKey::from_slice(&[1u8; 32]).unwrap(),
+        );
+        let offer: OfferDlc =
+            serde_json::from_str(include_str!("../tests/data/dlc/offer.json")).unwrap();
+        let contract_id = offer.temporary_contract_id;
+        TestDdk::on_message_with_progress(&manager, &Message::Offer(offer), counter_party, &RwLock::new(None), &Arc::default())
+            .unwrap();
+        let (sender, receiver) = unbounded();
+        let events = EventBus::default();
+        let subscriber = events.subscribe();
+        let apply = |policy: &dyn OfferPolicy| {
+            TestDdk::apply_offer_policy(&manager, &test.wallet, &sender, policy, &RiskLimits::default(), &events, contract_id)
+        };
+
+        apply(&ManualOnly);
+        assert!(receiver.try_recv().is_err());
+        assert!(subscriber.try_recv().is_err());
+
+        apply(&RuleBasedPolicy::default());
+        assert!(matches!(
+            receiver.try_recv().unwrap(),
+            DlcManagerMessage::AcceptDlc { contract, responder: Responder::Detached, .. } if contract == contract_id
+        ));
+        assert_eq!(subscriber.try_recv().unwrap(), DdkEvent::OfferAutoAccepted(contract_id));
+
+        apply(&RuleBasedPolicy::default().with_allowed_counterparties([]));
+        assert!(receiver.try_recv().is_err());
+        assert!(matches!(
+            subscriber.try_recv().unwrap(),
+            DdkEvent::OfferRejected { contract_id: rejected, .. } if rejected == contract_id
+        ));
+        assert!(matches!(
+            test.storage.get_contract(&contract_id).unwrap(),
+            Some(Contract::Rejected(_))
+        ));
+    }
+
+    #[test]
+    fn invalid_message_does_not_stop_processing() {
+        let test = TestWallet::create_wallet("invalid_message_processing");
+        let manager = test.manager();
+        let counter_party = PublicKey::from_secret_key(
+            &Secp256k1::new(),
+            &SecretKey::from_slice(&[1u8; 32]).unwrap(),
+        );
+        let offer: OfferDlc =
+            serde_json::from_str(include_str!("../tests/data/dlc/offer.json")).unwrap();
+        let messages = vec![
+            (counter_party, unknown_sign()),
+            (counter_party, Message::Offer(offer.clone())),
+        ];
+
+        let sign_progress = RwLock::new(None);
+        let handled = Mutex::new(vec![]);
+        process_by_peer(messages, 1, &ContractLocks::default(), |message| TestDdk::lock_key(manager.get_store(), message), |counter_party, message| {
+            let result = TestDdk::on_message_with_progress(&manager, &message, counter_party, &sign_progress, &Arc::default());
+            handled.lock().unwrap().push((message_kind(&message), result.is_ok()));
+        });
+
+        assert_eq!(
+            handled.into_inner().unwrap(),
+            vec![("sign", false), ("offer", true)]
+        );
+        assert!(matches!(
+            test.storage.get_contract(&offer.temporary_contract_id).unwrap(),
+            Some(Contract::Offered(_))
+        ));
+    }
+
+    /// Two peers against a slow chain backend. The slow peer's offers each wait on the chain
+    /// tip, the fast peer's messages do not and are handled while those offers wait.
+    #[test]
+    fn slow_chain_peer_does_not_delay_other_peer() {
+        let test = TestWallet::create_wallet("slow_chain_peers");
+        let manager = test.manager();
+        let query_delay = Duration::from_millis(200);
+        test.blockchain.set_query_delay(query_delay);
+        let peer = |byte| {
+            PublicKey::from_secret_key(&Secp256k1::new(), &SecretKey::from_slice(&[byte; 32]).unwrap())
+        };
+        let (slow, fast) = (peer(1), peer(2));
+        let offer: OfferDlc =
+            serde_json::from_str(include_str!("../tests/data/dlc/offer.json")).unwrap();
+        let mut messages = vec![];
+        for i in 0..3u8 {
+            let offer = OfferDlc {
+                temporary_contract_id: [i; 32],
+                ..offer.clone()
+            };
+            messages.push((slow, Message::Offer(offer)));
+            messages.push((fast, unknown_sign()));
+        }
+
+        let sign_progress = RwLock::new(None);
+        let handled = Mutex::new(vec![]);
+        let start = Instant::now();
+        process_by_peer(
+            messages,
+            2,
+            &ContractLocks::default(),
+            |message| TestDdk::lock_key(manager.get_store(), message),
+            |counter_party, message| {
+                if let Message::Offer(_) = &message {
+                    TestDdk::chain_tip(&test.blockchain).unwrap();
+                }
+                let _ = TestDdk::on_message_with_progress(&manager, &message, counter_party, &sign_progress, &Arc::default());
+                handled.lock().unwrap().push((counter_party, start.elapsed()));
+            },
+        );
+
+        let handled = handled.into_inner().unwrap();
+        let done = |peer| handled.iter().filter(|(c, _)| *c == peer).map(|(_, t)| *t).max().unwrap();
+        assert!(done(slow) >= query_delay * 3);
+        assert!(done(fast) < query_delay);
+    }
+
+    #[test]
+    fn invalid_accept_is_not_replayed_after_restart() {
+        let test = TestWallet::create_wallet("invalid_accept_replay");
+        let manager = test.manager();
+        let storage = manager.get_store().as_ref();
+        let counter_party = PublicKey::from_secret_key(
+            &Secp256k1::new(),
+            &SecretKey::from_slice(&[1u8; 32]).unwrap(),
+        );
+        let accept = unknown_accept();
+
+        let recent = Mutex::new(RecentMessages::new(RECENT_MESSAGES_PER_PEER));
+        let journaled = journal_inbound(storage, &recent, vec![(counter_party, accept.clone())]);
+        assert_eq!(journaled.len(), 1);
+        let error = TestDdk::on_message_with_progress(&manager, &accept, counter_party, &RwLock::new(None), &Arc::default())
+            .unwrap_err();
+        assert!(!is_transient_error(&error));
+        fail_inbound(storage, &recent, counter_party, &accept, &error);
+
+        // After a restart nothing is replayed, and a redelivered copy is not journaled again.
+        assert!(pending_inbound(storage).is_empty());
+        let recent = Mutex::new(RecentMessages::new(RECENT_MESSAGES_PER_PEER));
+        assert!(journal_inbound(storage, &recent, vec![(counter_party, accept.clone())]).is_empty());
+        let dead_lettered = storage
+            .audit_entries(0)
+            .unwrap()
+            .into_iter()
+            .filter(|entry| entry.event_type == AuditEventType::MessageDeadLettered)
+            .collect::<Vec<_>>();
+        assert_eq!(dead_lettered.len(), 1);
+        assert_eq!(dead_lettered[0].details["kind"], "accept");
+    }
+
+    #[test]
+    fn transiently_failing_message_is_retried_then_dead_lettered() {
+        let test = TestWallet::create_wallet("transient_inbound_failure");
+        let manager = test.manager();
+        let storage = manager.get_store().as_ref();
+        let counter_party = PublicKey::from_secret_key(
+            &Secp256k1::new(),
+            &SecretKey::from_slice(&[1u8; 32]).unwrap(),
+        );
+        let sign = unknown_sign();
+        let recent = Mutex::new(RecentMessages::new(RECENT_MESSAGES_PER_PEER));
+        journal_inbound(storage, &recent, vec![(counter_party, sign.clone())]);
+        let error = dlc_manager::error::Error::BlockchainError("esplora unreachable".to_string());
+
+        for _ in 1..MAX_INBOUND_ATTEMPTS {
+            fail_inbound(storage, &recent, counter_party, &sign, &error);
+            assert_eq!(pending_inbound(storage).len(), 1);
+        }
+        fail_inbound(storage, &recent, counter_party, &sign, &error);
+        assert!(pending_inbound(storage).is_empty());
+    }
+
+    #[test]
+    fn accept_stored_before_a_crash_is_delivered_once_after_restart() {
+        use crate::builder::DdkBuilder;
+        use crate::config::{DdkConfig, SeedConfig};
+        use crate::transport::memory::harness::{contract_input, is_signed, wait_for, TwoNodes};
+
+        let nodes = TwoNodes::new("accept_after_crash");
+        nodes.alice.start().unwrap();
+        let alice_key = nodes.alice.transport().public_key();
+        let bob_key = nodes.bob.transport().public_key();
+        let announcement = nodes.oracle.announcement.clone();
+        let offer = nodes
+            .alice
+            .manager()
+            .send_offer_with_announcements(&contract_input(&announcement), bob_key, vec![vec![announcement]])
+            .unwrap();
+        nodes
+            .bob
+            .manager()
+            .on_dlc_message(&Message::Offer(offer.clone()), alice_key)
+            .unwrap();
+
+        // Injected fault: Bob stops after the manager stored the Accept and before the node
+        // handed it to the outbox.
+        let (contract_id, _, _) = nodes
+            .bob
+            .manager()
+            .accept_contract_offer(&offer.temporary_contract_id)
+            .unwrap();
+        let pending = nodes.bob.storage().list_pending_outbound().unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].kind, "accept");
+
+        let mut builder = DdkBuilder::new();
+        builder
+            .set_config(DdkConfig {
+                network: Network::Regtest,
+                storage_path: "tests/data/accept_after_crash_bob".into(),
+                seed_config: SeedConfig::Bytes([2; 64]),
+                ..Default::default()
+            })
+            .set_name("accept_after_crash_bob")
+            .set_transport(nodes.bob.transport())
+            .set_storage(nodes.bob.storage())
+            .set_oracle(nodes.oracle.clone())
+            .set_blockchain(nodes.blockchain.clone());
+        let restarted = builder.finish().unwrap();
+        restarted.start().unwrap();
+
+        wait_for("both nodes to sign", || {
+            is_signed(&nodes.alice, &contract_id) && is_signed(&restarted, &contract_id)
+        });
+        let accepts_received = nodes
+            .alice
+            .storage()
+            .audit_entries(0)
+            .unwrap()
+            .into_iter()
+            .filter(|entry| {
+                entry.event_type == AuditEventType::MessageReceived && entry.details["kind"] == "accept"
+            })
+            .count();
+        assert_eq!(accepts_received, 1);
+        assert!(restarted
+            .storage()
+            .list_pending_outbound()
+            .unwrap()
+            .iter()
+            .all(|pending| pending.kind != "accept"));
+    }
+
+    #[test]
+    fn unanswered_manager_request_times_out() {
+        let (responder, receiver) = unbounded::<()>();
+        let error = wait_for_manager(receiver, Duration::from_millis(10), "testing").unwrap_err();
+        assert!(matches!(error, DdkError::ManagerUnresponsive { .. }));
+        assert!(error.is_retryable());
+
+        // A manager that drops the request fails right away instead of hanging.
+        drop(responder);
+        let (responder, receiver) = unbounded::<()>();
+        drop(responder);
+        let error = wait_for_manager(receiver, Duration::from_secs(60), "testing").unwrap_err();
+        assert!(matches!(error, DdkError::ManagerStopped { .. }));
+        assert!(!error.is_retryable());
+
+        let (responder, receiver) = unbounded();
+        Responder::Blocking(responder).send(7).unwrap();
+        assert_eq!(wait_for_manager(receiver, Duration::from_secs(60), "testing").unwrap(), 7);
+    }
+
+    #[tokio::test]
+    async fn unanswered_async_manager_request_times_out() {
+        let (_responder, receiver) = oneshot::channel::<()>();
+        let error = wait_for_manager_async(receiver, Duration::from_millis(10), "testing")
+            .await
+            .unwrap_err();
+        assert!(matches!(error, DdkError::ManagerUnresponsive { .. }));
+
+        let (responder, receiver) = oneshot::channel::<()>();
+        drop(responder);
+        let error = wait_for_manager_async(receiver, Duration::from_secs(60), "testing")
+            .await
+            .unwrap_err();
+        assert!(matches!(error, DdkError::ManagerStopped { .. }));
+
+        let (responder, receiver) = oneshot::channel();
+        Responder::Async(responder).send(7).unwrap();
+        assert_eq!(
+            wait_for_manager_async(receiver, Duration::from_secs(60), "testing").await.unwrap(),
+            7
+        );
+    }
+
+    #[test]
+    fn split_offers_need_collateral_and_fees() {
+        use crate::contract::PPM;
+        use crate::testkit::harness::{yes_no_payouts, TestHarness};
+        use crate::wallet::coin_selection::dlc_funding_target;
+        use dlc_manager::contract::enum_descriptor::EnumDescriptor;
+
+        let harness = TestHarness::new_pair();
+        let alice = &harness.alice;
+        let fee_rate = 2;
+        let available = alice.wallet().max_collateral(FeeRate::from_sat_per_vb_unchecked(fee_rate)).unwrap().to_sat();
+        let fees = dlc_funding_target(0, fee_rate).unwrap().to_sat();
+
+        // Funds the collateral when the fees are counted, and no more.
+        alice.check_funding(available - fees, fee_rate).unwrap();
+        let error = alice.check_funding(available - fees + 1, fee_rate).unwrap_err();
+        assert!(matches!(
+            error,
+            DdkError::InsufficientFunds { fees: f, shortfall: 1, .. } if f == fees
+        ));
+        assert!(matches!(
+            alice.check_funding(available, fee_rate),
+            Err(DdkError::InsufficientFunds { shortfall, .. }) if shortfall == fees
+        ));
+
+        let announcement = harness.oracle.create_enum_event("split", &["yes", "no"], 1_900_000_000).unwrap();
+        let descriptor = ContractDescriptor::Enum(EnumDescriptor {
+            outcome_payouts: yes_no_payouts(available),
+        });
+        let bob = harness.bob.transport().public_key();
+        let error = alice
+            .send_dlc_offer_with_split(descriptor.clone(), available, PPM, fee_rate, bob, vec![announcement.clone()])
+            .unwrap_err();
+        assert!(matches!(error, DdkError::InsufficientFunds { collateral, .. } if collateral == available));
+        assert!(alice.storage().get_contract_offers().unwrap().is_empty());
+        assert!(matches!(
+            alice.send_dlc_offer_with_split(descriptor.clone(), available, PPM + 1, fee_rate, bob, vec![announcement.clone()]),
+            Err(DdkError::InvalidCollateralShare(_))
+        ));
+
+        let offer = alice
+            .send_dlc_offer_with_split(descriptor, available, 250_000, fee_rate, bob, vec![announcement])
+            .unwrap();
+        assert_eq!(offer.offer_collateral, available / 4);
+    }
+
+    #[test]
+    fn offer_with_past_locktimes_is_cancelled() {
+        use crate::testkit::harness::{enum_contract_input, yes_no_payouts, TestHarness};
+
+        let harness = TestHarness::new_pair();
+        // Matured long before the refund delay ran out, so the refund locktime is in the past.
+        let announcement = harness.oracle.create_enum_event("past", &["yes", "no"], 500_000_001).unwrap();
+        let bob = harness.bob.transport().public_key();
+
+        let error = harness
+            .alice
+            .send_dlc_offer(&enum_contract_input(&announcement, yes_no_payouts(100_000)), bob, vec![announcement])
+            .unwrap_err();
+        assert!(matches!(error, DdkError::Locktime(_)));
+        assert!(harness.alice.storage().get_contract_offers().unwrap().is_empty());
+        assert!(harness.bob.storage().get_contract_offers().unwrap().is_empty());
+    }
+
+    #[test]
+    fn cancelled_offer_is_rejected_at_the_counterparty() {
+        use crate::testkit::harness::{enum_contract_input, wait_for_state, yes_no_payouts, TestHarness};
+
+        let harness = TestHarness::new_pair();
+        let announcement = harness.oracle.create_enum_event("cancel", &["yes", "no"], 1_900_000_000).unwrap();
+        let bob = harness.bob.transport().public_key();
+        let offer = harness
+            .alice
+            .send_dlc_offer(&enum_contract_input(&announcement, yes_no_payouts(100_000)), bob, vec![announcement])
+            .unwrap();
+        let temporary_id = offer.temporary_contract_id;
+        wait_for_state(&harness.bob, temporary_id, ContractState::Offered, Duration::from_secs(10)).unwrap();
+
+        let events = harness.bob.subscribe();
+        harness.alice.cancel_offer(temporary_id).unwrap();
+        wait_for_state(&harness.bob, temporary_id, ContractState::Rejected, Duration::from_secs(10)).unwrap();
+        assert!(events.try_iter().any(|event| matches!(
+            event,
+            DdkEvent::OfferRejected { contract_id, .. } if contract_id == temporary_id
+        )));
+        assert!(harness.bob.accept_dlc_offer(temporary_id).is_err());
+    }
+
+    #[test]
+    fn late_accept_of_a_cancelled_offer_is_rejected() {
+        use crate::testkit::harness::{enum_contract_input, wait_for_state, yes_no_payouts, TestHarness};
+        use crate::transport::memory::LinkFaults;
+
+        let harness = TestHarness::new_pair();
+        let announcement = harness.oracle.create_enum_event("late", &["yes", "no"], 1_900_000_000).unwrap();
+        let bob = harness.bob.transport().public_key();
+        let offer = harness
+            .alice
+            .send_dlc_offer(&enum_contract_input(&announcement, yes_no_payouts(100_000)), bob, vec![announcement])
+            .unwrap();
+        let temporary_id = offer.temporary_contract_id;
+        wait_for_state(&harness.bob, temporary_id, ContractState::Offered, Duration::from_secs(10)).unwrap();
+
+        // Bob misses the Reject of the cancel and accepts the offer anyway.
+        harness.alice.transport().set_faults(LinkFaults { drop_probability: 1.0, ..Default::default() });
+        harness.alice.cancel_offer(temporary_id).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while harness.alice.pending_outbound().unwrap().iter().any(|pending| pending.kind == "channel") {
+            assert!(Instant::now() < deadline, "Reject was not sent.");
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        harness.alice.transport().set_faults(LinkFaults::default());
+        assert!(matches!(harness.bob.storage().get_contract(&temporary_id), Ok(Some(Contract::Offered(_)))));
+
+        let (contract_id, _, _) = harness.bob.accept_dlc_offer(temporary_id).unwrap();
+        wait_for_state(&harness.bob, temporary_id, ContractState::Rejected, Duration::from_secs(10)).unwrap();
+        let contract_id: ContractId = hex::decode(contract_id).unwrap().try_into().unwrap();
+        assert!(harness.bob.storage().get_contract(&contract_id).unwrap().is_none());
+        assert!(matches!(harness.alice.storage().get_contract(&temporary_id), Ok(Some(Contract::Rejected(_)))));
+    }
+
+    #[test]
+    fn evicted_funding_is_rebroadcast() {
+        use crate::testkit::harness::{enum_contract_input, yes_no_payouts, TestHarness, TestNode};
+
+        let harness = TestHarness::with_config(DdkConfig {
+            rebroadcast: RebroadcastOptions {
+                interval: Duration::from_millis(100),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        let announcement = harness.oracle.create_enum_event("evicted", &["yes", "no"], 1_900_000_000).unwrap();
+        let contract_id = harness.offer_and_accept(&enum_contract_input(&announcement, yes_no_payouts(100_000))).unwrap();
+        let Some(Contract::Signed(signed)) = harness.bob.storage().get_contract(&contract_id).unwrap() else {
+            panic!("Contract is not signed.");
+        };
+        let fund_txid = signed.accepted_contract.dlc_transactions.fund.compute_txid();
+        // Bob accepted the offer, so Bob broadcast the funding transaction.
+        let pending = |node: &TestNode| {
+            node.pending_broadcasts()
+                .unwrap()
+                .into_iter()
+                .find(|pending| pending.txid == fund_txid)
+        };
+        assert!(pending(&harness.bob).is_some());
+        assert!(pending(&harness.alice).is_none());
+
+        harness.blockchain.clear_mempool();
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while pending(&harness.bob).unwrap().rebroadcasts == 0 {
+            assert!(Instant::now() < deadline, "Funding transaction was not rebroadcast.");
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        assert!(harness.blockchain.find_transaction(&fund_txid).unwrap().is_some());
+
+        harness.mine_blocks(6);
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while pending(&harness.bob).is_some() {
+            assert!(Instant::now() < deadline, "Confirmed funding transaction is still pending.");
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    #[test]
+    fn acceptor_broadcasts_missing_funding_after_the_window() {
+        use crate::testkit::harness::{enum_contract_input, wait_for_state, yes_no_payouts, TestHarness, TestNode};
+
+        let window = Duration::from_millis(300);
+        let harness = TestHarness::with_config(DdkConfig {
+            funding_broadcast_window: window,
+            ..Default::default()
+        });
+        let alice_events = harness.alice.subscribe();
+        let bob_events = harness.bob.subscribe();
+        let announcement = harness.oracle.create_enum_event("fallback", &["yes", "no"], 1_900_000_000).unwrap();
+        let contract_id = harness.offer_and_accept(&enum_contract_input(&announcement, yes_no_payouts(100_000))).unwrap();
+        let Some(Contract::Signed(signed)) = harness.bob.storage().get_contract(&contract_id).unwrap() else {
+            panic!("Contract is not signed.");
+        };
+        let fund_txid = signed.accepted_contract.dlc_transactions.fund.compute_txid();
+
+        // The funding transaction never reached the mempool. Alice, the offerer, does not hold
+        // Bob's funding signatures, so Bob broadcasts once the window passed.
+        let missing_at = Instant::now();
+        harness.blockchain.clear_mempool();
+        let funding_broadcast = |events: &Receiver<DdkEvent>| loop {
+            match events.recv_timeout(Duration::from_secs(10)) {
+                Ok(DdkEvent::FundingBroadcast { contract_id: id, txid, by_us }) if id == contract_id => {
+                    assert_eq!(txid, fund_txid);
+                    return by_us;
+                }
+                Ok(_) => continue,
+                Err(e) => panic!("No funding broadcast event. {}", e),
+            }
+        };
+        assert!(funding_broadcast(&bob_events));
+        assert!(missing_at.elapsed() >= window);
+        assert!(!funding_broadcast(&alice_events));
+        assert!(harness.blockchain.find_transaction(&fund_txid).unwrap().is_some());
+
+        let role = |node: &TestNode| node.get_contract_metadata(contract_id).unwrap().unwrap().funding_broadcast_role;
+        assert_eq!(role(&harness.alice), Some(FundingBroadcastRole::Broadcaster));
+        assert_eq!(role(&harness.bob), Some(FundingBroadcastRole::Fallback));
+
+        harness.mine_blocks(6);
+        for node in [&harness.alice, &harness.bob] {
+            node.force_check().unwrap();
+            wait_for_state(node, contract_id, ContractState::Confirmed, Duration::from_secs(10)).unwrap();
+        }
+    }
+
+    fn zero_conf_contract(harness: &crate::testkit::harness::TestHarness, event_id: &str) -> ContractId {
+        use crate::testkit::harness::{enum_contract_input, yes_no_payouts};
+
+        let announcement = harness.oracle.create_enum_event(event_id, &["yes", "no"], 1_900_000_000).unwrap();
+        harness
+            .offer_and_accept_with_params(&enum_contract_input(&announcement, yes_no_payouts(100_000)), AcceptanceParams::zero_conf())
+            .unwrap()
+    }
+
+    #[test]
+    fn zero_conf_contract_settles_unconfirmed() {
+        use crate::testkit::harness::{wait_for_state, TestHarness};
+
+        let harness = TestHarness::new_pair();
+        let contract_id = zero_conf_contract(&harness, "zero-conf");
+        let metadata = harness.alice.get_contract_metadata(contract_id).unwrap().unwrap();
+        assert_eq!(metadata.acceptance, Some(AcceptanceParams::zero_conf()));
+
+        harness.alice.force_check().unwrap();
+        wait_for_state(&harness.alice, contract_id, ContractState::Confirmed, Duration::from_secs(10)).unwrap();
+        let Some(Contract::Confirmed(signed)) = harness.alice.storage().get_contract(&contract_id).unwrap() else {
+            panic!("Contract is not confirmed.");
+        };
+        let fund_txid = signed.accepted_contract.dlc_transactions.fund.compute_txid();
+        assert_eq!(harness.blockchain.get_transaction_confirmations(&fund_txid).unwrap(), 0);
+        let summary = harness.alice.get_contract(contract_id).unwrap().unwrap().summary;
+        assert_eq!(summary.required_confirmations, Some(0));
+
+        // A later check with the funding transaction still in the mempool keeps it confirmed.
+        harness.alice.force_check().unwrap();
+        assert!(matches!(harness.alice.storage().get_contract(&contract_id), Ok(Some(Contract::Confirmed(_)))));
+
+        harness.attest("zero-conf", "yes").unwrap();
+        let cet_txid = harness.alice.close_contract(contract_id, vec![]).unwrap();
+        wait_for_state(&harness.alice, contract_id, ContractState::PreClosed, Duration::from_secs(10)).unwrap();
+        assert!(harness.blockchain.broadcasts().contains(&cet_txid));
+        assert_eq!(harness.blockchain.get_transaction_confirmations(&fund_txid).unwrap(), 0);
+    }
+
+    #[test]
+    fn double_spent_zero_conf_funding_fails_the_contract() {
+        use crate::testkit::harness::{wait_for_state, TestHarness};
+
+        let harness = TestHarness::new_pair();
+        let events = harness.alice.subscribe();
+        let contract_id = zero_conf_contract(&harness, "double-spent");
+        harness.alice.force_check().unwrap();
+        wait_for_state(&harness.alice, contract_id, ContractState::Confirmed, Duration::from_secs(10)).unwrap();
+        let Some(Contract::Confirmed(signed)) = harness.alice.storage().get_contract(&contract_id).unwrap() else {
+            panic!("Contract is not confirmed.");
+        };
+        let txid = signed.accepted_contract.dlc_transactions.fund.compute_txid();
+
+        let conflicting_txid = harness.blockchain.double_spend(&txid).unwrap();
+        harness.alice.force_check().unwrap();
+        wait_for_state(&harness.alice, contract_id, ContractState::FailedSign, Duration::from_secs(10)).unwrap();
+        let expected = DdkEvent::FundingDoubleSpent { contract_id, txid, conflicting_txid };
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !events.try_iter().any(|event| event == expected) {
+            assert!(Instant::now() < deadline, "Double-spend was not reported.");
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        let details = harness.alice.get_contract(contract_id).unwrap().unwrap();
+        assert!(details.error.unwrap().contains(&conflicting_txid.to_string()));
+    }
+
+    #[test]
+    fn status_reports_background_tasks() {
+        use crate::testkit::harness::{enum_contract_input, yes_no_payouts, TestHarness};
+
+        let harness = TestHarness::with_config(DdkConfig {
+            chain_watch: ChainWatchOptions {
+                interval: Duration::from_millis(100),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        let announcement = harness.oracle.create_enum_event("status", &["yes", "no"], 1_900_000_000).unwrap();
+        let status = harness.alice.status();
+        assert!(status.running);
+        assert_eq!(status.transport, harness.alice.transport().name());
+        assert_eq!(status.last_oracle_contact, None);
+        assert_eq!(status.storage.error, None);
+
+        let runtime = Runtime::new().unwrap();
+        runtime.block_on(harness.alice.get_announcement("status")).unwrap();
+        harness.offer_and_accept(&enum_contract_input(&announcement, yes_no_payouts(100_000))).unwrap();
+        harness.mine_blocks(2);
+        let tip = harness.blockchain.get_blockchain_height().unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while harness.alice.status().chain_tip != Some(tip) {
+            assert!(Instant::now() < deadline, "Chain tip was not recorded.");
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        let status = harness.alice.status();
+        assert!(status.last_oracle_contact.is_some());
+        assert!(status.last_wallet_sync.is_some());
+        assert_eq!(status.contracts_by_state.values().sum::<usize>(), 1);
+        assert_eq!(status.storage.error, None);
+    }
+
+    #[test]
+    fn force_closed_channel_is_bumped_through_its_cet() {
+        use crate::channel::ChannelState;
+        use crate::testkit::harness::{
+            enum_contract_input, wait_for_channel_state, wait_for_state, yes_no_payouts, TestHarness,
+            TestNode,
+        };
+        use bitcoin::OutPoint;
+        use dlc_manager::manager::CET_NSEQUENCE;
+
+        let harness = TestHarness::new_pair();
+        let maturity = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as u32 + 5;
+        let announcement = harness.oracle.create_enum_event("bump", &["yes", "no"], maturity).unwrap();
+        let input = enum_contract_input(&announcement, yes_no_payouts(100_000));
+        let bob_key = harness.bob.transport().public_key();
+        let (temporary_id, _) = harness.alice.offer_channel(&input, bob_key, vec![announcement]).unwrap();
+
+        let channel = |node: &TestNode, channel_id: ChannelId| node.storage().get_channel(&channel_id).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !matches!(channel(&harness.bob, temporary_id), Some(Channel::Offered(_))) {
+            assert!(Instant::now() < deadline, "Channel offer was not received.");
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        let (channel_id, contract_id) = harness.bob.accept_channel(temporary_id).unwrap();
+        while !matches!(channel(&harness.alice, channel_id), Some(Channel::Signed(_))) {
+            assert!(Instant::now() < deadline, "Channel was not signed.");
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        harness.mine_blocks(6);
+
+        // The buffer transaction pays only the shared output, so there is nothing to bump yet.
+        harness.alice.manager().force_close_channel(&channel_id).unwrap();
+        wait_for_channel_state(&harness.alice, channel_id, ChannelState::Closing, Duration::from_secs(10)).unwrap();
+        let target_rate = FeeRate::from_sat_per_vb_unchecked(20);
+        assert!(harness.alice.bump_channel_close(channel_id, target_rate).is_err());
+
+        harness.mine_blocks(CET_NSEQUENCE as u64);
+        while SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() <= maturity as u64 {
+            std::thread::sleep(Duration::from_millis(200));
+        }
+        harness.attest("bump", "yes").unwrap();
+        harness.alice.force_check().unwrap();
+        wait_for_channel_state(&harness.alice, channel_id, ChannelState::Closed, Duration::from_secs(10)).unwrap();
+        wait_for_state(&harness.alice, contract_id, ContractState::PreClosed, Duration::from_secs(10)).unwrap();
+        let Some(Contract::PreClosed(preclosed)) = harness.alice.storage().get_contract(&contract_id).unwrap() else {
+            panic!("Contract is not pre-closed.");
+        };
+        let cet = preclosed.signed_cet;
+        let cet_txid = cet.compute_txid();
+        harness.alice.wallet().sync().unwrap();
+
+        let child_txid = harness.alice.bump_channel_close(channel_id, target_rate).unwrap();
+        let child = harness.blockchain.find_transaction(&child_txid).unwrap().unwrap();
+        assert!(child.input.iter().any(|input| input.previous_output.txid == cet_txid));
+        let previous_value = |outpoint: &OutPoint| {
+            let previous = harness.blockchain.find_transaction(&outpoint.txid).unwrap().unwrap();
+            previous.output[outpoint.vout as usize].value
+        };
+        let fee = |tx: &Transaction| {
+            let spent: Amount = tx.input.iter().map(|input| previous_value(&input.previous_output)).sum();
+            spent - tx.output.iter().map(|output| output.value).sum::<Amount>()
+        };
+        let package_fee = target_rate.fee_wu(cet.weight() + child.weight()).unwrap();
+        assert!(fee(&cet) + fee(&child) >= package_fee);
+        assert!(harness.alice.pending_broadcasts().unwrap().iter().any(|pending| pending.txid == child_txid));
+
+        harness.mine_blocks(1);
+        assert_eq!(harness.blockchain.get_transaction_confirmations(&cet_txid).unwrap(), 1);
+        assert_eq!(harness.blockchain.get_transaction_confirmations(&child_txid).unwrap(), 1);
+    }
+
+    #[test]
+    fn funding_is_bumped_from_change_only() {
+        use crate::testkit::harness::{enum_contract_input, yes_no_payouts, TestHarness};
+        use crate::wallet::coin_selection::dlc_funding_target;
+
+        let harness = TestHarness::new_pair();
+        let fee_rate = 2;
+        let available = harness
+            .alice
+            .wallet()
+            .max_collateral(FeeRate::from_sat_per_vb_unchecked(fee_rate))
+            .unwrap()
+            .to_sat();
+        // Alice puts up all she has. What is left for her change, after the fees of her change
+        // and payout outputs, is dust and dropped from the funding transaction.
+        let collateral = available - dlc_funding_target(0, fee_rate).unwrap().to_sat() - 300;
+        let total = collateral + 50_000;
+        let announcement = harness.oracle.create_enum_event("no-change", &["yes", "no"], 1_900_000_000).unwrap();
+        let input = ContractInput {
+            offer_collateral: collateral,
+            accept_collateral: 50_000,
+            fee_rate,
+            ..enum_contract_input(&announcement, yes_no_payouts(total))
+        };
+        let contract_id = harness.offer_and_accept(&input).unwrap();
+
+        let target_rate = FeeRate::from_sat_per_vb_unchecked(20);
+        let error = harness.alice.bump_funding_fee(contract_id, target_rate).unwrap_err();
+        assert!(matches!(error, DdkError::Wallet(WalletError::Cpfp(_))), "{}", error);
+
+        // Bob's change pays for the child instead.
+        let child_txid = harness.bob.bump_funding_fee(contract_id, target_rate).unwrap();
+        assert!(harness.bob.pending_broadcasts().unwrap().iter().any(|pending| pending.txid == child_txid));
+    }
+}
@@ -0,0 +1,325 @@
+//! Process received messages of different counterparties in parallel.
+//!
+//! Messages of one counterparty are always handled in the order they were received. Messages
+//! that touch the same contract, and all channel messages, never run at the same time.
+//! Offer and accept requests run on a [RequestPool] next to the received messages.
+use bitcoin::secp256k1::PublicKey;
+use crossbeam::channel::{unbounded, Sender};
+use dlc_messages::Message;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+/// Default number of threads processing received messages.
+pub const DEFAULT_MESSAGE_WORKERS: usize = 4;
+
+/// Processed message ids remembered per counterparty.
+pub const RECENT_MESSAGES_PER_PEER: usize = 256;
+
+/// Ids of the messages recently processed from each counterparty, least recently seen first.
+/// Exact duplicates are dropped here without a storage lookup. The inbound journal remembers
+/// processed ids across restarts.
+#[derive(Debug)]
+pub struct RecentMessages {
+    capacity: usize,
+    peers: HashMap<PublicKey, VecDeque<String>>,
+}
+
+impl RecentMessages {
+    pub fn new(capacity: usize) -> RecentMessages {
+        RecentMessages {
+            capacity: capacity.max(1),
+            peers: HashMap::new(),
+        }
+    }
+
+    /// Whether `id` was processed from `counter_party`. A hit makes it the most recent.
+    pub fn seen(&mut self, counter_party: &PublicKey, id: &str) -> bool {
+        let Some(ids) = self.peers.get_mut(counter_party) else {
+            return false;
+        };
+        match ids.iter().position(|seen| seen == id) {
+            Some(position) => {
+                let id = ids.remove(position).unwrap();
+                ids.push_back(id);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Remember a processed message, evicting the least recent once the peer is at capacity.
+    pub fn insert(&mut self, counter_party: PublicKey, id: String) {
+        if self.seen(&counter_party, &id) {
+            return;
+        }
+        let ids = self.peers.entry(counter_party).or_default();
+        if ids.len() == self.capacity {
+            ids.pop_front();
+        }
+        ids.push_back(id);
+    }
+}
+
+/// What a message needs exclusive access to while it is processed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LockKey {
+    Contract([u8; 32]),
+    /// Channels can reference the same contracts, so channel messages are serialized.
+    Channels,
+}
+
+impl LockKey {
+    /// Contracts are locked by their temporary id, which offers, accepts and accept requests
+    /// all carry. A sign only has the contract id, `temporary_id` looks up the temporary id
+    /// for it. Contracts it does not know are locked by their contract id.
+    pub fn for_message(
+        message: &Message,
+        temporary_id: impl Fn(&[u8; 32]) -> Option<[u8; 32]>,
+    ) -> Option<LockKey> {
+        match message {
+            Message::Offer(offer) => Some(LockKey::Contract(offer.temporary_contract_id)),
+            Message::Accept(accept) => Some(LockKey::Contract(accept.temporary_contract_id)),
+            Message::Sign(sign) => Some(LockKey::Contract(
+                temporary_id(&sign.contract_id).unwrap_or(sign.contract_id),
+            )),
+            Message::Channel(_) => Some(LockKey::Channels),
+            _ => None,
+        }
+    }
+}
+
+/// A lock per contract, created on first use.
+#[derive(Debug, Default)]
+pub struct ContractLocks {
+    locks: Mutex<HashMap<LockKey, Arc<Mutex<()>>>>,
+}
+
+impl ContractLocks {
+    fn get(&self, key: LockKey) -> Arc<Mutex<()>> {
+        self.locks.lock().unwrap().entry(key).or_default().clone()
+    }
+
+    /// Drop locks nobody holds.
+    fn prune(&self) {
+        self.locks
+            .lock()
+            .unwrap()
+            .retain(|_, lock| Arc::strong_count(lock) > 1);
+    }
+
+    /// Run `f` holding the lock of `key`.
+    pub fn with_lock<R>(&self, key: LockKey, f: impl FnOnce() -> R) -> R {
+        let lock = self.get(key);
+        let result = {
+            let _guard = lock.lock().unwrap();
+            f()
+        };
+        drop(lock);
+        self.prune();
+        result
+    }
+}
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// Threads running requests off the manager thread, so that signing one contract does not
+/// hold up received messages or other negotiations. The threads stop once the pool is dropped
+/// and the queued requests are done.
+#[derive(Debug)]
+pub struct RequestPool {
+    sender: Sender<Job>,
+}
+
+impl RequestPool {
+    pub fn new(workers: usize) -> RequestPool {
+        let (sender, jobs) = unbounded::<Job>();
+        for _ in 0..workers.max(1) {
+            let jobs = jobs.clone();
+            std::thread::spawn(move || {
+                for job in jobs {
+                    job();
+                }
+            });
+        }
+        RequestPool { sender }
+    }
+
+    pub fn spawn(&self, job: impl FnOnce() + Send + 'static) {
+        if self.sender.send(Box::new(job)).is_err() {
+            tracing::error!("Request pool stopped. Dropping request.");
+        }
+    }
+}
+
+/// Group messages by counterparty, keeping the receive order within each group.
+pub fn group_by_peer<M>(messages: Vec<(PublicKey, M)>) -> Vec<(PublicKey, Vec<M>)> {
+    let mut groups: Vec<(PublicKey, Vec<M>)> = vec![];
+    let mut index = HashMap::new();
+    for (counter_party, message) in messages {
+        let i = *index.entry(counter_party).or_insert_with(|| {
+            groups.push((counter_party, vec![]));
+            groups.len() - 1
+        });
+        groups[i].1.push(message);
+    }
+    groups
+}
+
+/// Run `handle` for every message on up to `workers` threads. Each counterparty is taken by
+/// one worker at a time so its messages run in order.
+pub fn process_by_peer<M, K, F>(
+    messages: Vec<(PublicKey, M)>,
+    workers: usize,
+    locks: &ContractLocks,
+    lock_key: K,
+    handle: F,
+) where
+    M: Send,
+    K: Fn(&M) -> Option<LockKey> + Sync,
+    F: Fn(PublicKey, M) + Sync,
+{
+    let groups = group_by_peer(messages);
+    let workers = workers.max(1).min(groups.len());
+    let queue = Mutex::new(groups.into_iter().collect::<VecDeque<_>>());
+
+    let work = || loop {
+        let Some((counter_party, messages)) = queue.lock().unwrap().pop_front() else {
+            return;
+        };
+        for message in messages {
+            let lock = lock_key(&message).map(|key| locks.get(key));
+            let _guard = lock.as_ref().map(|lock| lock.lock().unwrap());
+            handle(counter_party, message);
+        }
+    };
+
+    std::thread::scope(|scope| {
+        for _ in 1..workers {
+            scope.spawn(work);
+        }
+        work();
+    });
+    locks.prune();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::secp256k1::{Message as SecpMessage, Secp256k1, SecretKey};
+    use dlc_messages::{CetAdaptorSignatures, FundingSignatures, SignDlc};
+    use std::time::{Duration, Instant};
+
+    fn peer(byte: u8) -> PublicKey {
+        PublicKey::from_secret_key(&Secp256k1::new(), &SecretKey::from_slice(&[byte; 32]).unwrap())
+    }
+
+    #[test]
+    fn recent_messages_evict_the_least_recent() {
+        let (a, b) = (peer(1), peer(2));
+        let mut recent = RecentMessages::new(2);
+        recent.insert(a, "1".into());
+        recent.insert(a, "2".into());
+        assert!(recent.seen(&a, "1"));
+        assert!(!recent.seen(&b, "1"));
+
+        // "2" is now the least recent.
+        recent.insert(a, "3".into());
+        assert!(recent.seen(&a, "1"));
+        assert!(recent.seen(&a, "3"));
+        assert!(!recent.seen(&a, "2"));
+    }
+
+    #[test]
+    fn groups_keep_receive_order() {
+        let (a, b) = (peer(1), peer(2));
+        let groups = group_by_peer(vec![(a, 1), (b, 2), (a, 3), (b, 4), (a, 5)]);
+        assert_eq!(groups, vec![(a, vec![1, 3, 5]), (b, vec![2, 4])]);
+    }
+
+    #[test]
+    fn slow_peer_does_not_delay_fast_peer() {
+        let (slow, fast) = (peer(1), peer(2));
+        let messages = vec![(slow, 0), (fast, 0), (slow, 1), (fast, 1), (slow, 2), (fast, 2)];
+        let handled = Mutex::new(vec![]);
+        let start = Instant::now();
+
+        process_by_peer(messages, 2, &ContractLocks::default(), |_| None, |counter_party, n| {
+            if counter_party == slow {
+                std::thread::sleep(Duration::from_millis(100));
+            }
+            handled.lock().unwrap().push((counter_party, n, start.elapsed()));
+        });
+
+        let handled = handled.into_inner().unwrap();
+        let order = |p| handled.iter().filter(|(c, ..)| *c == p).map(|(_, n, _)| *n).collect::<Vec<_>>();
+        assert_eq!(order(slow), vec![0, 1, 2]);
+        assert_eq!(order(fast), vec![0, 1, 2]);
+
+        let fast_done = handled.iter().filter(|(c, ..)| *c == fast).map(|(.., t)| *t).max().unwrap();
+        assert!(fast_done < Duration::from_millis(100));
+    }
+
+    #[test]
+    fn requests_run_next_to_each_other() {
+        let pool = RequestPool::new(4);
+        let (done, finished) = std::sync::mpsc::channel();
+        let start = Instant::now();
+        for _ in 0..4 {
+            let done = done.clone();
+            pool.spawn(move || {
+                std::thread::sleep(Duration::from_millis(100));
+                done.send(()).unwrap();
+            });
+        }
+        for _ in 0..4 {
+            finished.recv_timeout(Duration::from_secs(1)).unwrap();
+        }
+        assert!(start.elapsed() < Duration::from_millis(300));
+    }
+
+    #[test]
+    fn sign_locks_the_contract_by_its_temporary_id() {
+        let secp = Secp256k1::new();
+        let sign = Message::Sign(SignDlc {
+            protocol_version: 1,
+            contract_id: [2u8; 32],
+            cet_adaptor_signatures: CetAdaptorSignatures {
+                ecdsa_adaptor_signatures: vec![],
+            },
+            refund_signature: secp.sign_ecdsa(
+                &SecpMessage::from_digest([1u8; 32]),
+                &SecretKey::from_slice(&[3u8; 32]).unwrap(),
+            ),
+            funding_signatures: FundingSignatures {
+                funding_signatures: vec![],
+            },
+        });
+
+        // The same lock as the accept request of the contract.
+        assert_eq!(
+            LockKey::for_message(&sign, |_| Some([1u8; 32])),
+            Some(LockKey::Contract([1u8; 32]))
+        );
+        assert_eq!(
+            LockKey::for_message(&sign, |_| None),
+            Some(LockKey::Contract([2u8; 32]))
+        );
+    }
+
+    #[test]
+    fn shared_contract_is_exclusive() {
+        let messages: Vec<_> = (1..=4).map(|p| (peer(p), ())).collect();
+        let busy = Mutex::new(false);
+        process_by_peer(
+            messages,
+            4,
+            &ContractLocks::default(),
+            |_| Some(LockKey::Channels),
+            |_, _| {
+                assert!(!std::mem::replace(&mut *busy.lock().unwrap(), true));
+                std::thread::sleep(Duration::from_millis(10));
+                *busy.lock().unwrap() = false;
+            },
+        );
+    }
+}
@@ -1,43 +1,281 @@
 use bdk_esplora::esplora_client::Error as EsploraError;
 use dlc_manager::error::Error as ManagerError;
+use dlc_manager::ContractId;
+use std::time::Duration;
 
-#[derive(Debug)]
-enum DlcDevKitError {
-    // Bdk(BdkError),
-    Esplora(EsploraError),
+use crate::builder::BuilderError;
+use crate::contract::cancel::CancelError;
+use crate::contract::close::CloseError;
+use crate::contract::locktimes::LocktimeError;
+use crate::contract::refund::RefundError;
+use crate::contract::ContractState;
+use crate::oracle::set::OracleInputError;
+use crate::risk::RiskLimitKind;
+use crate::validation::ValidationError;
+
+/// Errors returned by [crate::DlcDevKit] and [crate::builder::DdkBuilder].
+///
+/// [DdkError::is_retryable] tells failures that may pass when the same call is made again
+/// apart from ones that need different input or an operator. Errors of dependencies that are
+/// not typed are kept in [DdkError::Other].
+#[derive(thiserror::Error, Debug)]
+pub enum DdkError {
+    #[error("Risk limit {which} exceeded. current={current} limit={limit}")]
+    RiskLimit {
+        which: RiskLimitKind,
+        current: u64,
+        limit: u64,
+    },
+    #[error("Node is in maintenance mode and does not take new contracts.")]
+    Maintenance,
+    #[error("DDK manager did not answer within {}s.", waited.as_secs())]
+    ManagerUnresponsive { waited: Duration },
+    #[error("Invalid contract input. {}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join(" "))]
+    InvalidContractInput(Vec<ValidationError>),
+    #[error("DDK is still running.")]
+    AlreadyRunning,
+    #[error("DDK manager is not running.")]
+    ManagerNotRunning,
+    #[error("DDK manager stopped before {action}.")]
+    ManagerStopped { action: String },
+    #[error("Insufficient channel reserve. spendable={spendable} collateral={collateral} reserve={reserve}")]
+    InsufficientChannelReserve {
+        spendable: u64,
+        collateral: u64,
+        reserve: u64,
+    },
+    #[error("Insufficient funds for the contract. collateral={collateral} fees={fees} available={available} shortfall={shortfall}")]
+    InsufficientFunds {
+        collateral: u64,
+        /// Our share of the funding and CET fees.
+        fees: u64,
+        /// Spendable value of unreserved UTXOs after the fees of spending them.
+        available: u64,
+        shortfall: u64,
+    },
+    #[error("Collateral share of {0} ppm is above 1000000.")]
+    InvalidCollateralShare(u32),
+    #[error("Contract {} not found.", hex::encode(.0))]
+    ContractNotFound(ContractId),
+    #[error("Contract is {actual}, expected {expected}.")]
+    InvalidState {
+        expected: ContractState,
+        actual: ContractState,
+    },
+    #[error(transparent)]
+    Wallet(#[from] WalletError),
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+    #[error("Storage integrity check found {} issues.", .0.issues.len())]
+    StorageIntegrity(crate::storage::IntegrityReport),
+    #[error(transparent)]
+    Transport(#[from] TransportError),
+    #[error(transparent)]
+    Oracle(#[from] OracleError),
+    #[error(transparent)]
+    Chain(#[from] ChainError),
+    #[error(transparent)]
+    Manager(#[from] ManagerError),
+    #[error(transparent)]
+    Locktime(#[from] LocktimeError),
+    #[error(transparent)]
+    Close(#[from] CloseError),
+    #[error(transparent)]
+    Refund(#[from] RefundError),
+    #[error(transparent)]
+    Cancel(#[from] CancelError),
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error(transparent)]
+    Builder(#[from] BuilderError),
+    #[error(transparent)]
+    Other(anyhow::Error),
 }
 
-// #[derive(Debug)]
-// enum BdkError {
-//     WriteError
-// }
-// impl<D: PersistBackend<ChangeSet>> From<D::WriteError> for DlcDevKitError {
-//     fn from(value: D::WriteError) -> DlcDevKitError {
-//         DlcDevKitError::Bdk(value)
-//     }
-// }
-
-impl From<EsploraError> for DlcDevKitError {
-    fn from(value: EsploraError) -> Self {
-        DlcDevKitError::Esplora(value)
+impl DdkError {
+    /// Whether the same call may succeed later without changes.
+    ///
+    /// Retryable are a manager that did not answer in time, transport failures, an
+    /// unavailable chain backend or oracle, waiting for the external signer, and refunds
+    /// before their locktime. Everything
+    /// else, including insufficient funds, invalid input, risk limits, maintenance mode and
+    /// contracts in the wrong state, fails again until the input or the node changes.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            DdkError::ManagerUnresponsive { .. } => true,
+            DdkError::Transport(_) => true,
+            DdkError::Chain(_) => true,
+            DdkError::Oracle(OracleError::Request(_)) => true,
+            DdkError::Wallet(e) => matches!(
+                e,
+                WalletError::SyncError
+                    | WalletError::Esplora(_)
+                    | WalletError::Blockchain(_)
+                    | WalletError::AwaitingSignature { .. }
+            ),
+            DdkError::Manager(e) => matches!(
+                e,
+                ManagerError::BlockchainError(_) | ManagerError::OracleError(_)
+            ),
+            DdkError::Refund(e) => matches!(
+                e,
+                RefundError::NotYetRefundable { .. } | RefundError::Chain(_)
+            ),
+            _ => false,
+        }
     }
 }
 
-impl From<DlcDevKitError> for ManagerError {
-    fn from(e: DlcDevKitError) -> ManagerError {
-        match e {
-            // DlcDevKitError::Bdk(e) => ManagerError::WalletError(Box::new(e)),
-            DlcDevKitError::Esplora(e) => ManagerError::BlockchainError(e.to_string()),
+/// Keeps typed errors that passed through [anyhow] inside the crate.
+impl From<anyhow::Error> for DdkError {
+    fn from(e: anyhow::Error) -> DdkError {
+        let e = match e.downcast::<DdkError>() {
+            Ok(e) => return e,
+            Err(e) => e,
+        };
+        let e = match e.downcast::<WalletError>() {
+            Ok(e) => return DdkError::Wallet(e),
+            Err(e) => e,
+        };
+        let e = match e.downcast::<ManagerError>() {
+            Ok(e) => return DdkError::Manager(e),
+            Err(e) => e,
+        };
+        let e = match e.downcast::<ChainError>() {
+            Ok(e) => return DdkError::Chain(e),
+            Err(e) => e,
+        };
+        let e = match e.downcast::<ConfigError>() {
+            Ok(e) => return DdkError::Config(e),
+            Err(e) => e,
+        };
+        match e.downcast::<BuilderError>() {
+            Ok(e) => DdkError::Builder(e),
+            Err(e) => DdkError::Other(e),
         }
     }
 }
 
-// pub fn bdk_err_to_manager_err(e: impl PersistBackend<ChangeSet>::WriteError) -> ManagerError {
-//     DlcDevKitError::Bdk(e).into()
-// }
+/// A contract, peer or setting could not be read or written.
+#[derive(thiserror::Error, Debug)]
+#[error("Storage error: {0}")]
+pub struct StorageError(pub anyhow::Error);
+
+impl StorageError {
+    pub fn new(e: impl Into<anyhow::Error>) -> StorageError {
+        StorageError(e.into())
+    }
+}
+
+/// A peer could not be reached.
+#[derive(thiserror::Error, Debug)]
+#[error("Transport error: {0}")]
+pub struct TransportError(pub anyhow::Error);
 
-pub fn esplora_err_to_manager_err(e: EsploraError) -> ManagerError {
-    DlcDevKitError::Esplora(e).into()
+/// Errors from the oracles contracts settle on.
+#[derive(thiserror::Error, Debug)]
+pub enum OracleError {
+    #[error("Oracle {0} is not one of the node's oracles.")]
+    Unknown(bitcoin::key::XOnlyPublicKey),
+    #[error(transparent)]
+    Input(#[from] OracleInputError),
+    #[error("Oracle request failed: {0}")]
+    Request(String),
+}
+
+/// Errors loading the config or reading the configured seed.
+#[derive(thiserror::Error, Debug)]
+pub enum ConfigError {
+    #[error("Seed file is {got} bytes, expected {expected}.")]
+    InvalidSeedLength { expected: usize, got: usize },
+    #[error("Invalid mnemonic: {0}")]
+    InvalidMnemonic(#[from] bip39::Error),
+    #[error("Wrong passphrase for the encrypted seed.")]
+    WrongPassphrase,
+    #[error("Seed file is not encrypted. Encrypt it with encrypt_seed_file.")]
+    SeedNotEncrypted,
+    #[error("Unsupported encrypted seed version {0}.")]
+    UnsupportedSeedVersion(u8),
+    #[error("Invalid encrypted seed file: {0}")]
+    InvalidSeedFile(String),
+    #[error("Could not read config file {}: {source}", path.display())]
+    ReadFile {
+        path: std::path::PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Invalid config file: {0}")]
+    ParseFile(#[from] toml::de::Error),
+    #[error("Invalid config. {}", .0.join(" "))]
+    Invalid(Vec<String>),
+}
+
+/// Why the chain backend rejected a transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BroadcastErrorKind {
+    /// An input is spent by a mempool transaction that this one cannot replace.
+    MempoolConflict,
+    /// The fee rate is below the minimum relay or mempool fee rate.
+    MinRelayFee,
+    /// The transaction is already in the mempool or the chain.
+    AlreadyKnown,
+    Other,
+}
+
+impl BroadcastErrorKind {
+    /// Classify a rejection by the bitcoind reject reason in the backend's error message.
+    pub fn from_message(message: &str) -> BroadcastErrorKind {
+        let message = message.to_lowercase();
+        let contains_any = |reasons: &[&str]| reasons.iter().any(|reason| message.contains(reason));
+        if contains_any(&[
+            "txn-already-in-mempool",
+            "txn-already-known",
+            "already in block chain",
+            "outputs already in utxo set",
+        ]) {
+            BroadcastErrorKind::AlreadyKnown
+        } else if contains_any(&[
+            "min relay fee not met",
+            "mempool min fee not met",
+            "min-fee-not-met",
+        ]) {
+            BroadcastErrorKind::MinRelayFee
+        } else if contains_any(&[
+            "txn-mempool-conflict",
+            "insufficient fee",
+            "bad-txns-spends-conflicting-tx",
+        ]) {
+            BroadcastErrorKind::MempoolConflict
+        } else {
+            BroadcastErrorKind::Other
+        }
+    }
+}
+
+impl std::fmt::Display for BroadcastErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self {
+            BroadcastErrorKind::MempoolConflict => "mempool conflict",
+            BroadcastErrorKind::MinRelayFee => "min relay fee",
+            BroadcastErrorKind::AlreadyKnown => "already known",
+            BroadcastErrorKind::Other => "other",
+        };
+        write!(f, "{}", kind)
+    }
+}
+
+/// Errors from the chain backend.
+#[derive(thiserror::Error, Debug)]
+pub enum ChainError {
+    #[error("Chain backend is unavailable after repeated failures. Retrying in {}s.", retry_in.as_secs())]
+    Unavailable { retry_in: Duration },
+    #[error("Esplora request failed: {0}")]
+    Esplora(#[from] EsploraError),
+}
+
+impl From<ChainError> for ManagerError {
+    fn from(e: ChainError) -> ManagerError {
+        ManagerError::BlockchainError(e.to_string())
+    }
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -50,8 +288,13 @@ pub enum WalletError {
     SignerError(String),
     #[error("Wallet call to esplora: {0}")]
     Esplora(#[from] Box<bdk_esplora::esplora_client::Error>),
-    #[error("Broadcast to esplora: {0}")]
-    Broadcast(#[from] bdk_esplora::esplora_client::Error),
+    #[error("Broadcast rejected ({kind}): {message}")]
+    Broadcast {
+        kind: BroadcastErrorKind,
+        message: String,
+    },
+    #[error("Could not calculate the fee of the transaction: {0}")]
+    Fee(String),
     #[error("Could not extract txn from psbt. {0}")]
     ExtractTx(#[from] bitcoin::psbt::ExtractTxError),
     #[error("Applying an update to the wallet.")]
@@ -64,4 +307,83 @@ pub enum WalletError {
     SendMessage(String),
     #[error("Bincode error")]
     Bincode(#[from] bincode::Error),
+    #[error("Error building transaction: {0}")]
+    CreateTx(#[from] bdk_wallet::error::CreateTxError),
+    #[error("Error adding utxo to transaction: {0}")]
+    AddUtxo(#[from] bdk_wallet::tx_builder::AddUtxoError),
+    #[error("Could not build replacement transaction: {0}")]
+    FeeBump(#[from] bdk_wallet::error::BuildFeeBumpError),
+    #[error("Could not bump fee with child transaction: {0}")]
+    Cpfp(String),
+    #[error("Address proof: {0}")]
+    AddressProof(String),
+    #[error("Blockchain error: {0}")]
+    Blockchain(String),
+    #[error("Insufficient funds. needed={needed} available={available}")]
+    InsufficientFunds {
+        needed: bitcoin::Amount,
+        available: bitcoin::Amount,
+    },
+    #[error("Amount is below the dust limit. amount={amount} dust_limit={dust_limit}")]
+    BelowDustLimit {
+        amount: bitcoin::Amount,
+        dust_limit: bitcoin::Amount,
+    },
+    #[error("Could not read contracts from storage: {0}")]
+    StorageLookup(String),
+    #[error("Could not persist UTXO reservations: {0}")]
+    Reservation(String),
+    #[error("UTXO is not in the wallet. outpoint={0}")]
+    UnknownUtxo(bitcoin::OutPoint),
+    #[error("Could not migrate legacy wallet store: {0}")]
+    Migration(String),
+    #[error("Watch-only wallet has no keys to sign with.")]
+    WatchOnly,
+    #[error("Wallet has no {0} account.")]
+    AccountUnavailable(crate::wallet::Account),
+    #[error("Invalid descriptor: {0}")]
+    InvalidDescriptor(String),
+    #[error("Waiting for the external signer. txid={txid} input_index={input_index}")]
+    AwaitingSignature {
+        txid: bitcoin::Txid,
+        input_index: usize,
+    },
+    #[error("External signer: {0}")]
+    ExternalSigner(String),
+    #[error("Address is for another network. address={address} expected={expected}")]
+    WrongNetwork {
+        address: String,
+        expected: bitcoin::Network,
+    },
+    #[error("Invalid payment URI: {0}")]
+    InvalidPaymentUri(String),
+    #[cfg(feature = "sqlite")]
+    #[error("Sqlite error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+    #[cfg(feature = "postgres")]
+    #[error("Postgres error: {0}")]
+    Postgres(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn typed_errors_survive_anyhow() {
+        let error = DdkError::from(anyhow::Error::from(DdkError::Maintenance));
+        assert!(matches!(error, DdkError::Maintenance));
+
+        let error = DdkError::from(anyhow::Error::from(WalletError::SyncError));
+        assert!(matches!(error, DdkError::Wallet(WalletError::SyncError)));
+        assert!(error.is_retryable());
+
+        let error = DdkError::from(anyhow::Error::from(BuilderError::NoSeed));
+        assert!(matches!(error, DdkError::Builder(BuilderError::NoSeed)));
+        assert!(!error.is_retryable());
+
+        let error = DdkError::from(anyhow::anyhow!("Something else."));
+        assert!(matches!(error, DdkError::Other(_)));
+        assert_eq!(error.to_string(), "Something else.");
+    }
 }
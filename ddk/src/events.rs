@@ -0,0 +1,283 @@
+//! Contract lifecycle and peer events, so applications do not have to poll storage.
+use crate::contract::progress::AcceptProgress;
+use crate::contract::ContractState;
+use bitcoin::key::XOnlyPublicKey;
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::Txid;
+use crossbeam::channel::{unbounded, Receiver, Sender};
+use dlc_manager::contract::Contract;
+use dlc_manager::{ChannelId, ContractId};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DdkEvent {
+    /// An offer was sent or received. Carries the temporary contract id.
+    ContractOffered(ContractId),
+    /// We accepted an offer. Carries the final contract id.
+    ContractAccepted(ContractId),
+    /// The offer policy accepted an incoming offer. Carries the temporary contract id.
+    OfferAutoAccepted(ContractId),
+    /// The offer policy rejected an incoming offer. Carries the temporary contract id.
+    OfferRejected { contract_id: ContractId, reason: String },
+    ContractSigned(ContractId),
+    ContractConfirmed(ContractId),
+    ContractClosed { contract_id: ContractId, pnl: i64 },
+    ContractRefunded(ContractId),
+    PeerConnected(PublicKey),
+    /// The transport lost its connection to a peer. Messages for it wait until it reconnects.
+    PeerDisconnected(PublicKey),
+    /// Connecting to a stored peer failed. It is retried with backoff.
+    PeerConnectionFailed { pubkey: PublicKey, attempts: u32 },
+    /// Processing a contract message waits for an external signer to sign the funding input.
+    /// The message is retried until the signature arrives or the signer times out.
+    AwaitingSignature {
+        contract_id: ContractId,
+        txid: Txid,
+        input_index: usize,
+    },
+    /// A peer exceeded the [crate::transport::rate_limit::PeerLimits]. Its messages are dropped
+    /// until `until`, a unix timestamp in seconds.
+    PeerBanned {
+        pubkey: PublicKey,
+        until: u64,
+        reason: String,
+    },
+    /// The oracle's attestation of a contract's event does not verify against the announcement
+    /// the contract was built on. No CET is broadcast and the contract stays open for refund.
+    InvalidAttestation {
+        contract_id: ContractId,
+        oracle: XOnlyPublicKey,
+        event_id: String,
+        reason: String,
+    },
+    /// A contract's funding transaction reached
+    /// [crate::chain::watch::ChainWatchOptions::funding_confirmations].
+    FundingConfirmed {
+        contract_id: ContractId,
+        txid: Txid,
+        confirmations: u32,
+    },
+    /// The funding transaction of a confirmed contract left the chain in a reorg. The
+    /// contract moved back to Signed and is confirmed again once the transaction has the
+    /// confirmations of the [crate::contract::confirmations::ConfirmationPolicy].
+    FundingReorged { contract_id: ContractId, txid: Txid },
+    /// An input of the unconfirmed funding transaction of a zero-conf contract was spent by
+    /// `conflicting_txid` before any CET was broadcast. The funding transaction can no longer
+    /// confirm and the contract moved to FailedSign.
+    FundingDoubleSpent {
+        contract_id: ContractId,
+        txid: Txid,
+        conflicting_txid: Txid,
+    },
+    /// A negotiation waited on the counterparty longer than its
+    /// [crate::contract::timeout::NegotiationTimeouts] allow. The contract was rejected and our
+    /// funding inputs released. `contract_id` is the id it had in `stalled_state`.
+    NegotiationTimedOut {
+        contract_id: ContractId,
+        stalled_state: ContractState,
+    },
+    /// The funding transaction of a signed contract was broadcast. `by_us` is false when it
+    /// showed up in the mempool after we found it missing, without us broadcasting it. See
+    /// [crate::contract::FundingBroadcastRole].
+    FundingBroadcast {
+        contract_id: ContractId,
+        txid: Txid,
+        by_us: bool,
+    },
+    /// A slow phase of accepting an offer, or of verifying the accept to one of ours, started
+    /// (`done` is 0) or finished (`done` is `total`). `contract_id` is the temporary id of the
+    /// offer on both sides. Finer progress is reported through
+    /// [crate::contract::progress::AcceptOptions::progress].
+    NegotiationProgress {
+        contract_id: ContractId,
+        progress: AcceptProgress,
+    },
+    /// The counterparty broadcast a CET of a confirmed contract. The contract moved to
+    /// PreClosed and closes once the CET confirms.
+    CetSeen { contract_id: ContractId, txid: Txid },
+    /// The counterparty broadcast a revoked state of the channel. The manager broadcast
+    /// `punishment_txid`, claiming the channel funds.
+    RevokedChannelState {
+        channel_id: ChannelId,
+        punishment_txid: Txid,
+    },
+    /// A transaction the node broadcast was still neither in the mempool nor the chain after
+    /// [crate::chain::rebroadcast::RebroadcastOptions::max_attempts] rebroadcasts and is no
+    /// longer rebroadcast. `reason` is the backend's last rejection.
+    BroadcastRejected {
+        txid: Txid,
+        rebroadcasts: u32,
+        reason: String,
+    },
+    /// The wallet has not synced for longer than the configured warning threshold.
+    WalletSyncFailing { failing_for: Duration, error: String },
+    /// The wallet synced again after [DdkEvent::WalletSyncFailing].
+    WalletSyncRecovered,
+    /// A contract message was given up on after
+    /// [crate::transport::retry::OutboundRetryOptions::max_attempts] sends or the expiry,
+    /// without the counterparty acting on it. `kind` is the message type, ex. `accept`.
+    DeliveryFailed {
+        counterparty: PublicKey,
+        contract_id: ContractId,
+        kind: String,
+        attempts: u32,
+    },
+    /// A confirmed contract's oracle event matures within
+    /// [crate::config::DdkConfig::maturity_notice]. `maturity` is a unix timestamp.
+    ContractMaturing {
+        contract_id: ContractId,
+        event_id: String,
+        maturity: u32,
+    },
+}
+
+/// Fans events out to every subscriber. Subscribers that dropped their receiver are removed
+/// on the next event.
+#[derive(Debug, Default)]
+pub struct EventBus {
+    subscribers: Mutex<Vec<Sender<DdkEvent>>>,
+}
+
+impl EventBus {
+    pub fn subscribe(&self) -> Receiver<DdkEvent> {
+        let (sender, receiver) = unbounded();
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    pub fn emit(&self, event: DdkEvent) {
+        tracing::debug!(event = ?event, "Emitting event.");
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+}
+
+/// States of the stored contracts, to diff against after the manager updates them.
+pub fn contract_states(contracts: &[Contract]) -> HashMap<ContractId, ContractState> {
+    contracts
+        .iter()
+        .map(|c| (c.get_id(), ContractState::from(c)))
+        .collect()
+}
+
+/// Contracts that closed since `before`, with their CET still unconfirmed or confirmed. A
+/// closed contract's CET confirming is not a new close.
+pub fn newly_closed(before: &HashMap<ContractId, ContractState>, after: &[Contract]) -> Vec<ContractId> {
+    after
+        .iter()
+        .filter(|c| matches!(c, Contract::PreClosed(_) | Contract::Closed(_)))
+        .filter(|c| {
+            !matches!(
+                before.get(&c.get_id()),
+                Some(ContractState::PreClosed | ContractState::Closed)
+            )
+        })
+        .map(|c| c.get_id())
+        .collect()
+}
+
+/// Events for contracts that were confirmed, closed, or refunded since `before`.
+pub fn state_change_events(
+    before: &HashMap<ContractId, ContractState>,
+    after: &[Contract],
+) -> Vec<DdkEvent> {
+    after
+        .iter()
+        .filter(|c| before.get(&c.get_id()) != Some(&ContractState::from(*c)))
+        .filter_map(|c| match c {
+            Contract::Confirmed(_) => Some(DdkEvent::ContractConfirmed(c.get_id())),
+            Contract::Closed(closed) => Some(DdkEvent::ContractClosed {
+                contract_id: c.get_id(),
+                pnl: closed.pnl,
+            }),
+            Contract::Refunded(_) => Some(DdkEvent::ContractRefunded(c.get_id())),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dlc_manager::contract::ser::Serializable;
+    use dlc_manager::contract::signed_contract::SignedContract;
+    use dlc_manager::contract::{ClosedContract, PreClosedContract};
+
+    fn deserialize_object<T: Serializable>(serialized: &[u8]) -> T {
+        let mut cursor = ::lightning::io::Cursor::new(&serialized);
+        T::deserialize(&mut cursor).unwrap()
+    }
+
+    #[test]
+    fn confirmations_and_closes_are_detected() {
+        let signed: SignedContract =
+            deserialize_object(include_bytes!("../tests/data/dlc_storage/sled/Signed"));
+        let closed: ClosedContract =
+            deserialize_object(include_bytes!("../tests/data/dlc_storage/sled/Closed"));
+        let before = contract_states(&[Contract::Signed(signed.clone())]);
+        let confirmed_id = Contract::Confirmed(signed.clone()).get_id();
+        let closed_id = closed.contract_id;
+        let pnl = closed.pnl;
+
+        let events = state_change_events(
+            &before,
+            &[Contract::Confirmed(signed.clone()), Contract::Closed(closed)],
+        );
+        assert_eq!(
+            events,
+            vec![
+                DdkEvent::ContractConfirmed(confirmed_id),
+                DdkEvent::ContractClosed {
+                    contract_id: closed_id,
+                    pnl
+                }
+            ]
+        );
+
+        // Nothing changed, nothing emitted.
+        let after = [Contract::Confirmed(signed)];
+        assert!(state_change_events(&contract_states(&after), &after).is_empty());
+    }
+
+    #[test]
+    fn closes_are_detected_once() {
+        let confirmed: SignedContract =
+            deserialize_object(include_bytes!("../tests/data/dlc_storage/sled/Confirmed"));
+        let pre_closed: PreClosedContract =
+            deserialize_object(include_bytes!("../tests/data/dlc_storage/sled/PreClosed"));
+        let closed: ClosedContract =
+            deserialize_object(include_bytes!("../tests/data/dlc_storage/sled/Closed"));
+        let pre_closed = Contract::PreClosed(pre_closed);
+        let pre_closed_id = pre_closed.get_id();
+
+        let before = contract_states(&[Contract::Confirmed(confirmed)]);
+        assert_eq!(newly_closed(&before, std::slice::from_ref(&pre_closed)), vec![pre_closed_id]);
+        // Contracts missing from `before` closed since.
+        assert_eq!(
+            newly_closed(&before, &[Contract::Closed(closed.clone())]),
+            vec![closed.contract_id]
+        );
+
+        let before = contract_states(std::slice::from_ref(&pre_closed));
+        assert!(newly_closed(&before, &[pre_closed]).is_empty());
+        let closed = Contract::Closed(ClosedContract {
+            contract_id: pre_closed_id,
+            ..closed
+        });
+        assert!(newly_closed(&before, &[closed]).is_empty());
+    }
+
+    #[test]
+    fn dropped_subscribers_are_removed() {
+        let bus = EventBus::default();
+        let kept = bus.subscribe();
+        drop(bus.subscribe());
+        bus.emit(DdkEvent::ContractOffered([1u8; 32]));
+        assert_eq!(kept.try_recv(), Ok(DdkEvent::ContractOffered([1u8; 32])));
+        assert_eq!(bus.subscribers.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,767 @@
+//! gRPC service exposing a [DlcDevKit] to applications in other languages. The service is
+//! defined in `proto/ddk.proto` and works with any transport, storage, and oracle.
+//!
+//! ```ignore
+//! let server = DdkServer::new(ddk.clone());
+//! server.serve("127.0.0.1:3030".parse()?).await?;
+//! ```
+use crate::chain::EsploraClient;
+use crate::contract::summary::{ContractDetails, ContractSummary};
+use crate::error::WalletError;
+use crate::events::DdkEvent;
+use crate::{DdkBlockchain, DdkError, DdkOracle, DdkStorage, DdkTransport, DlcDevKit};
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::{Address, Amount, FeeRate};
+use crossbeam::channel::RecvTimeoutError;
+use dlc_manager::contract::contract_input::ContractInput;
+use dlc_manager::error::Error as ManagerError;
+use dlc_manager::ContractId;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status};
+
+/// Types generated from `proto/ddk.proto`.
+pub mod proto {
+    tonic::include_proto!("ddk");
+}
+
+use proto::ddk_service_server::{DdkService, DdkServiceServer};
+use proto::*;
+
+/// Events buffered per subscriber before the forwarding thread waits for the client.
+const EVENT_BUFFER: usize = 64;
+/// How often an idle subscription checks whether the client went away.
+const SUBSCRIPTION_POLL: Duration = Duration::from_secs(1);
+
+/// Serves [DdkService] for a running [DlcDevKit].
+pub struct DdkServer<T: DdkTransport, S: DdkStorage, O: DdkOracle, B: DdkBlockchain = EsploraClient>
+{
+    ddk: Arc<DlcDevKit<T, S, O, B>>,
+}
+
+impl<T, S, O, B> DdkServer<T, S, O, B>
+where
+    T: DdkTransport,
+    S: DdkStorage,
+    O: DdkOracle,
+    B: DdkBlockchain,
+{
+    pub fn new(ddk: Arc<DlcDevKit<T, S, O, B>>) -> Self {
+        Self { ddk }
+    }
+
+    /// The service to add to a [tonic::transport::Server] next to other services.
+    pub fn into_service(self) -> DdkServiceServer<Self> {
+        DdkServiceServer::new(self)
+    }
+
+    /// Serve the service alone on `addr` until the server fails.
+    pub async fn serve(self, addr: SocketAddr) -> Result<(), tonic::transport::Error> {
+        tracing::info!(addr = addr.to_string(), "Starting gRPC server.");
+        tonic::transport::Server::builder()
+            .add_service(self.into_service())
+            .serve(addr)
+            .await
+    }
+
+    /// Run a call that waits on the manager or wallet thread without blocking the runtime.
+    async fn blocking<R, F>(&self, call: F) -> Result<R, Status>
+    where
+        R: Send + 'static,
+        F: FnOnce(&DlcDevKit<T, S, O, B>) -> Result<R, DdkError> + Send + 'static,
+    {
+        let ddk = self.ddk.clone();
+        tokio::task::spawn_blocking(move || call(&ddk))
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?
+            .map_err(status)
+    }
+
+    fn find_contract(&self, contract_id: &ContractId) -> Result<ContractDetails, Status> {
+        self.ddk
+            .get_contract(*contract_id)
+            .map_err(status)?
+            .ok_or_else(|| {
+                Status::not_found(format!("Contract {} not found.", hex::encode(contract_id)))
+            })
+    }
+}
+
+#[tonic::async_trait]
+impl<T, S, O, B> DdkService for DdkServer<T, S, O, B>
+where
+    T: DdkTransport,
+    S: DdkStorage,
+    O: DdkOracle,
+    B: DdkBlockchain,
+{
+    async fn get_info(
+        &self,
+        _request: Request<GetInfoRequest>,
+    ) -> Result<Response<GetInfoResponse>, Status> {
+        let oracles = self
+            .ddk
+            .list_oracles()
+            .iter()
+            .map(|oracle| Oracle {
+                name: oracle.name(),
+                pubkey: oracle.get_public_key().to_string(),
+            })
+            .collect();
+        Ok(Response::new(GetInfoResponse {
+            network: self.ddk.network().to_string(),
+            transport: self.ddk.transport().name(),
+            oracles,
+            wallet_pubkey: self.ddk.wallet().get_pubkey().to_string(),
+        }))
+    }
+
+    async fn new_address(
+        &self,
+        _request: Request<NewAddressRequest>,
+    ) -> Result<Response<NewAddressResponse>, Status> {
+        let address = self
+            .ddk
+            .wallet()
+            .new_external_address()
+            .map_err(wallet_status)?
+            .address
+            .to_string();
+        Ok(Response::new(NewAddressResponse { address }))
+    }
+
+    async fn get_balance(
+        &self,
+        _request: Request<GetBalanceRequest>,
+    ) -> Result<Response<GetBalanceResponse>, Status> {
+        let balance = self.ddk.wallet().get_balance().map_err(wallet_status)?;
+        Ok(Response::new(GetBalanceResponse {
+            confirmed: balance.confirmed.to_sat(),
+            unconfirmed: (balance.trusted_pending + balance.untrusted_pending).to_sat(),
+            immature: balance.immature.to_sat(),
+        }))
+    }
+
+    async fn send(&self, request: Request<SendRequest>) -> Result<Response<SendResponse>, Status> {
+        let SendRequest {
+            address,
+            amount,
+            fee_rate_sat_per_vb,
+            allow_dust,
+            no_broadcast,
+        } = request.into_inner();
+        let address = Address::from_str(&address)
+            .map_err(|e| Status::invalid_argument(format!("Invalid address. {}", e)))?
+            .require_network(self.ddk.network())
+            .map_err(|e| Status::invalid_argument(format!("Invalid address. {}", e)))?;
+        if amount == 0 {
+            return Err(Status::invalid_argument("Amount must be more than zero."));
+        }
+        let fee_rate = FeeRate::from_sat_per_vb(fee_rate_sat_per_vb)
+            .filter(|rate| *rate > FeeRate::ZERO)
+            .ok_or_else(|| Status::invalid_argument("Invalid fee rate."))?;
+
+        let wallet = self.ddk.wallet();
+        let sent = tokio::task::spawn_blocking(move || {
+            wallet.send_to_address(
+                address,
+                Amount::from_sat(amount),
+                fee_rate,
+                allow_dust,
+                !no_broadcast,
+            )
+        })
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?
+        .map_err(wallet_status)?;
+        Ok(Response::new(SendResponse {
+            txid: sent.txid.to_string(),
+            fee_paid_sats: sent.fee_paid.to_sat(),
+            vsize: sent.vsize,
+            raw_tx: sent.raw_tx,
+        }))
+    }
+
+    async fn list_contracts(
+        &self,
+        _request: Request<ListContractsRequest>,
+    ) -> Result<Response<ListContractsResponse>, Status> {
+        let contracts = self
+            .ddk
+            .list_contracts()
+            .map_err(status)?
+            .into_iter()
+            .map(Into::into)
+            .collect();
+        Ok(Response::new(ListContractsResponse { contracts }))
+    }
+
+    async fn get_contract(
+        &self,
+        request: Request<GetContractRequest>,
+    ) -> Result<Response<GetContractResponse>, Status> {
+        let contract_id = parse_contract_id(&request.into_inner().contract_id)?;
+        let contract = self.find_contract(&contract_id)?;
+        Ok(Response::new(contract.into()))
+    }
+
+    async fn send_offer(
+        &self,
+        request: Request<SendOfferRequest>,
+    ) -> Result<Response<SendOfferResponse>, Status> {
+        let SendOfferRequest {
+            contract_input,
+            counterparty,
+        } = request.into_inner();
+        let counterparty = parse_pubkey(&counterparty)?;
+        let contract_input: ContractInput = serde_json::from_slice(&contract_input)
+            .map_err(|e| Status::invalid_argument(format!("Invalid contract input. {}", e)))?;
+
+        let mut announcements = Vec::new();
+        for info in &contract_input.contract_infos {
+            for oracle in &info.oracles.public_keys {
+                let announcement = self
+                    .ddk
+                    .get_oracle_announcement(oracle, &info.oracles.event_id)
+                    .await
+                    .map_err(|e| Status::failed_precondition(e.to_string()))?;
+                announcements.push(announcement);
+            }
+        }
+
+        let offer = self
+            .ddk
+            .send_dlc_offer_async(&contract_input, counterparty, announcements)
+            .await
+            .map_err(status)?;
+        let offer_dlc = serde_json::to_vec(&offer).map_err(|e| Status::internal(e.to_string()))?;
+        Ok(Response::new(SendOfferResponse {
+            temporary_contract_id: hex::encode(offer.temporary_contract_id),
+            offer_dlc,
+        }))
+    }
+
+    async fn accept_offer(
+        &self,
+        request: Request<AcceptOfferRequest>,
+    ) -> Result<Response<AcceptOfferResponse>, Status> {
+        let contract_id = parse_contract_id(&request.into_inner().contract_id)?;
+        self.find_contract(&contract_id)?;
+
+        let (contract_id, counterparty, accept) = self
+            .ddk
+            .accept_dlc_offer_async(contract_id)
+            .await
+            .map_err(status)?;
+        let accept_dlc =
+            serde_json::to_vec(&accept).map_err(|e| Status::internal(e.to_string()))?;
+        Ok(Response::new(AcceptOfferResponse {
+            contract_id,
+            counterparty,
+            accept_dlc,
+        }))
+    }
+
+    async fn reject_offer(
+        &self,
+        request: Request<RejectOfferRequest>,
+    ) -> Result<Response<RejectOfferResponse>, Status> {
+        let contract_id = parse_contract_id(&request.into_inner().contract_id)?;
+        self.find_contract(&contract_id)?;
+
+        self.blocking(move |ddk| ddk.reject_dlc_offer(contract_id))
+            .await?;
+        Ok(Response::new(RejectOfferResponse {}))
+    }
+
+    async fn list_peers(
+        &self,
+        _request: Request<ListPeersRequest>,
+    ) -> Result<Response<ListPeersResponse>, Status> {
+        let peers = self
+            .ddk
+            .list_connected_peers()
+            .map_err(status)?
+            .into_iter()
+            .map(|peer| Peer {
+                pubkey: peer.pubkey,
+                host: peer.host,
+            })
+            .collect();
+        Ok(Response::new(ListPeersResponse { peers }))
+    }
+
+    async fn connect_peer(
+        &self,
+        request: Request<ConnectPeerRequest>,
+    ) -> Result<Response<ConnectPeerResponse>, Status> {
+        let ConnectPeerRequest { pubkey, host } = request.into_inner();
+        let pubkey = parse_pubkey(&pubkey)?;
+        if host.is_empty() {
+            return Err(Status::invalid_argument("Host is empty."));
+        }
+        self.ddk
+            .connect_peer(pubkey, &host)
+            .await
+            .map_err(|e| Status::unavailable(e.to_string()))?;
+        Ok(Response::new(ConnectPeerResponse {}))
+    }
+
+    type SubscribeStream = ReceiverStream<Result<Event, Status>>;
+
+    async fn subscribe(
+        &self,
+        _request: Request<SubscribeRequest>,
+    ) -> Result<Response<Self::SubscribeStream>, Status> {
+        let events = self.ddk.subscribe();
+        let (sender, receiver) = mpsc::channel(EVENT_BUFFER);
+        // The event bus is a blocking channel, so forward from a thread. It stops once the
+        // client drops the stream.
+        std::thread::spawn(move || loop {
+            match events.recv_timeout(SUBSCRIPTION_POLL) {
+                Ok(event) => {
+                    if sender.blocking_send(Ok(event.into())).is_err() {
+                        break;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) if sender.is_closed() => break,
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(receiver)))
+    }
+}
+
+/// Parse a hex encoded 32 byte contract id.
+pub fn parse_contract_id(contract_id: &str) -> Result<ContractId, Status> {
+    let bytes = hex::decode(contract_id)
+        .map_err(|e| Status::invalid_argument(format!("Contract id is not hex. {}", e)))?;
+    bytes.try_into().map_err(|bytes: Vec<u8>| {
+        Status::invalid_argument(format!(
+            "Contract id must be 32 bytes, got {}.",
+            bytes.len()
+        ))
+    })
+}
+
+/// Parse a hex encoded compressed public key.
+pub fn parse_pubkey(pubkey: &str) -> Result<PublicKey, Status> {
+    PublicKey::from_str(pubkey)
+        .map_err(|e| Status::invalid_argument(format!("Invalid public key. {}", e)))
+}
+
+/// Map an error from [DlcDevKit] onto the closest gRPC status.
+fn status(e: DdkError) -> Status {
+    match &e {
+        DdkError::InvalidContractInput(_) | DdkError::InvalidCollateralShare(_) => {
+            Status::invalid_argument(e.to_string())
+        }
+        DdkError::RiskLimit { .. }
+        | DdkError::InvalidState { .. }
+        | DdkError::InsufficientChannelReserve { .. }
+        | DdkError::InsufficientFunds { .. } => Status::failed_precondition(e.to_string()),
+        DdkError::ContractNotFound(_) => Status::not_found(e.to_string()),
+        DdkError::Maintenance => Status::unavailable(e.to_string()),
+        DdkError::ManagerUnresponsive { .. } => Status::deadline_exceeded(e.to_string()),
+        DdkError::Manager(ManagerError::InvalidParameters(_)) => {
+            Status::invalid_argument(e.to_string())
+        }
+        DdkError::Manager(ManagerError::InvalidState(_)) => {
+            Status::failed_precondition(e.to_string())
+        }
+        DdkError::Wallet(e) => wallet_status_ref(e),
+        e if e.is_retryable() => Status::unavailable(e.to_string()),
+        _ => Status::internal(e.to_string()),
+    }
+}
+
+fn wallet_status(e: WalletError) -> Status {
+    wallet_status_ref(&e)
+}
+
+fn wallet_status_ref(e: &WalletError) -> Status {
+    match e {
+        WalletError::InsufficientFunds { .. } => Status::failed_precondition(e.to_string()),
+        WalletError::BelowDustLimit { .. } => Status::invalid_argument(e.to_string()),
+        WalletError::Broadcast { .. } => Status::failed_precondition(e.to_string()),
+        _ => Status::internal(e.to_string()),
+    }
+}
+
+impl From<ContractSummary> for proto::ContractSummary {
+    fn from(summary: ContractSummary) -> proto::ContractSummary {
+        let held_for_maintenance = summary
+            .metadata
+            .as_ref()
+            .is_some_and(|metadata| metadata.held_for_maintenance);
+        proto::ContractSummary {
+            id: summary.id,
+            state: summary.state.to_string(),
+            counterparty: summary.counterparty.to_string(),
+            is_offer_party: summary.is_offer_party,
+            offer_collateral: summary.offer_collateral,
+            accept_collateral: summary.accept_collateral,
+            total_collateral: summary.total_collateral,
+            event_id: summary.event_id,
+            maturity: summary.maturity,
+            pnl: summary.pnl,
+            closing_txid: summary.closing_txid.map(|txid| txid.to_string()),
+            label: summary.metadata.and_then(|metadata| metadata.label),
+            confirmations: summary.confirmations,
+            required_confirmations: summary.required_confirmations,
+            fiat_currency: summary.fiat_currency,
+            fiat_collateral: summary.fiat_collateral,
+            fiat_pnl: summary.fiat_pnl,
+            held_for_maintenance,
+        }
+    }
+}
+
+impl From<ContractDetails> for GetContractResponse {
+    fn from(details: ContractDetails) -> GetContractResponse {
+        GetContractResponse {
+            summary: Some(details.summary.into()),
+            temporary_id: details.temporary_id,
+            fee_rate_per_vb: details.fee_rate_per_vb,
+            cet_locktime: details.cet_locktime,
+            refund_locktime: details.refund_locktime,
+            funding_txid: details.funding_txid.map(|txid| txid.to_string()),
+            error: details.error,
+        }
+    }
+}
+
+impl From<DdkEvent> for Event {
+    fn from(event: DdkEvent) -> Event {
+        use proto::event::Event as Kind;
+        let kind = match event {
+            DdkEvent::ContractOffered(id) => Kind::ContractOffered(hex::encode(id)),
+            DdkEvent::ContractAccepted(id) => Kind::ContractAccepted(hex::encode(id)),
+            DdkEvent::OfferAutoAccepted(id) => Kind::OfferAutoAccepted(hex::encode(id)),
+            DdkEvent::OfferRejected {
+                contract_id,
+                reason,
+            } => Kind::OfferRejected(OfferRejected {
+                contract_id: hex::encode(contract_id),
+                reason,
+            }),
+            DdkEvent::ContractSigned(id) => Kind::ContractSigned(hex::encode(id)),
+            DdkEvent::ContractConfirmed(id) => Kind::ContractConfirmed(hex::encode(id)),
+            DdkEvent::ContractClosed { contract_id, pnl } => Kind::ContractClosed(ContractClosed {
+                contract_id: hex::encode(contract_id),
+                pnl,
+            }),
+            DdkEvent::ContractRefunded(id) => Kind::ContractRefunded(hex::encode(id)),
+            DdkEvent::PeerConnected(pubkey) => Kind::PeerConnected(pubkey.to_string()),
+            DdkEvent::PeerDisconnected(pubkey) => Kind::PeerDisconnected(pubkey.to_string()),
+            DdkEvent::PeerConnectionFailed { pubkey, attempts } => {
+                Kind::PeerConnectionFailed(PeerConnectionFailed {
+                    pubkey: pubkey.to_string(),
+                    attempts,
+                })
+            }
+            DdkEvent::WalletSyncFailing { failing_for, error } => {
+                Kind::WalletSyncFailing(WalletSyncFailing {
+                    failing_for_secs: failing_for.as_secs(),
+                    error,
+                })
+            }
+            DdkEvent::WalletSyncRecovered => Kind::WalletSyncRecovered(WalletSyncRecovered {}),
+            DdkEvent::AwaitingSignature {
+                contract_id,
+                txid,
+                input_index,
+            } => Kind::AwaitingSignature(AwaitingSignature {
+                contract_id: hex::encode(contract_id),
+                txid: txid.to_string(),
+                input_index: input_index as u32,
+            }),
+            DdkEvent::PeerBanned {
+                pubkey,
+                until,
+                reason,
+            } => Kind::PeerBanned(PeerBanned {
+                pubkey: pubkey.to_string(),
+                until,
+                reason,
+            }),
+            DdkEvent::FundingConfirmed {
+                contract_id,
+                txid,
+                confirmations,
+            } => Kind::FundingConfirmed(FundingConfirmed {
+                contract_id: hex::encode(contract_id),
+                txid: txid.to_string(),
+                confirmations,
+            }),
+            DdkEvent::FundingReorged { contract_id, txid } => {
+                Kind::FundingReorged(FundingReorged {
+                    contract_id: hex::encode(contract_id),
+                    txid: txid.to_string(),
+                })
+            }
+            DdkEvent::FundingDoubleSpent {
+                contract_id,
+                txid,
+                conflicting_txid,
+            } => Kind::FundingDoubleSpent(FundingDoubleSpent {
+                contract_id: hex::encode(contract_id),
+                txid: txid.to_string(),
+                conflicting_txid: conflicting_txid.to_string(),
+            }),
+            DdkEvent::NegotiationTimedOut {
+                contract_id,
+                stalled_state,
+            } => Kind::NegotiationTimedOut(NegotiationTimedOut {
+                contract_id: hex::encode(contract_id),
+                stalled_state: stalled_state.to_string(),
+            }),
+            DdkEvent::FundingBroadcast {
+                contract_id,
+                txid,
+                by_us,
+            } => Kind::FundingBroadcast(FundingBroadcast {
+                contract_id: hex::encode(contract_id),
+                txid: txid.to_string(),
+                by_us,
+            }),
+            DdkEvent::NegotiationProgress {
+                contract_id,
+                progress,
+            } => Kind::NegotiationProgress(NegotiationProgress {
+                contract_id: hex::encode(contract_id),
+                phase: progress.phase.to_string(),
+                done: progress.done,
+                total: progress.total,
+            }),
+            DdkEvent::CetSeen { contract_id, txid } => Kind::CetSeen(CetSeen {
+                contract_id: hex::encode(contract_id),
+                txid: txid.to_string(),
+            }),
+            DdkEvent::RevokedChannelState {
+                channel_id,
+                punishment_txid,
+            } => Kind::RevokedChannelState(RevokedChannelState {
+                channel_id: hex::encode(channel_id),
+                punishment_txid: punishment_txid.to_string(),
+            }),
+            DdkEvent::InvalidAttestation {
+                contract_id,
+                oracle,
+                event_id,
+                reason,
+            } => Kind::InvalidAttestation(InvalidAttestation {
+                contract_id: hex::encode(contract_id),
+                oracle: oracle.to_string(),
+                event_id,
+                reason,
+            }),
+            DdkEvent::BroadcastRejected {
+                txid,
+                rebroadcasts,
+                reason,
+            } => Kind::BroadcastRejected(BroadcastRejected {
+                txid: txid.to_string(),
+                rebroadcasts,
+                reason,
+            }),
+            DdkEvent::ContractMaturing {
+                contract_id,
+                event_id,
+                maturity,
+            } => Kind::ContractMaturing(ContractMaturing {
+                contract_id: hex::encode(contract_id),
+                event_id,
+                maturity,
+            }),
+            DdkEvent::DeliveryFailed {
+                counterparty,
+                contract_id,
+                kind,
+                attempts,
+            } => Kind::DeliveryFailed(DeliveryFailed {
+                counterparty: counterparty.to_string(),
+                contract_id: hex::encode(contract_id),
+                kind,
+                attempts,
+            }),
+        };
+        Event { event: Some(kind) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::proto::ddk_service_client::DdkServiceClient;
+    use super::*;
+    use crate::transport::memory::harness::{contract_input, is_signed, wait_for, TwoNodes};
+    use tokio::net::TcpListener;
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tokio_stream::StreamExt;
+    use tonic::transport::Channel;
+    use tonic::Code;
+
+    /// Serve `ddk` on a local port and connect a client to it.
+    async fn client<T, S, O, B>(ddk: Arc<DlcDevKit<T, S, O, B>>) -> DdkServiceClient<Channel>
+    where
+        T: DdkTransport,
+        S: DdkStorage,
+        O: DdkOracle,
+        B: DdkBlockchain,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(DdkServer::new(ddk).into_service())
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+        DdkServiceClient::connect(format!("http://{addr}"))
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn malformed_requests_are_invalid_arguments() {
+        let nodes = TwoNodes::new("grpc_validation");
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let mut alice = client(nodes.alice.clone()).await;
+
+            let not_hex = alice
+                .get_contract(GetContractRequest {
+                    contract_id: "not hex".into(),
+                })
+                .await
+                .unwrap_err();
+            assert_eq!(not_hex.code(), Code::InvalidArgument);
+
+            let short = alice
+                .accept_offer(AcceptOfferRequest {
+                    contract_id: "0102".into(),
+                })
+                .await
+                .unwrap_err();
+            assert_eq!(short.code(), Code::InvalidArgument);
+
+            let unknown = alice
+                .reject_offer(RejectOfferRequest {
+                    contract_id: hex::encode([7u8; 32]),
+                })
+                .await
+                .unwrap_err();
+            assert_eq!(unknown.code(), Code::NotFound);
+
+            let bad_pubkey = alice
+                .send_offer(SendOfferRequest {
+                    contract_input: b"{}".to_vec(),
+                    counterparty: "02".into(),
+                })
+                .await
+                .unwrap_err();
+            assert_eq!(bad_pubkey.code(), Code::InvalidArgument);
+
+            let bad_address = alice
+                .send(SendRequest {
+                    address: "bc1notanaddress".into(),
+                    amount: 1_000,
+                    fee_rate_sat_per_vb: 1,
+                    allow_dust: false,
+                    no_broadcast: false,
+                })
+                .await
+                .unwrap_err();
+            assert_eq!(bad_address.code(), Code::InvalidArgument);
+        });
+    }
+
+    #[test]
+    fn offer_is_signed_through_the_rpc() {
+        let nodes = TwoNodes::new("grpc_offer");
+        nodes.start();
+        let alice_key = nodes.alice.transport().public_key();
+        let bob_key = nodes.bob.transport().public_key();
+        let input = serde_json::to_vec(&contract_input(&nodes.oracle.announcement)).unwrap();
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let mut alice_rpc = client(nodes.alice.clone()).await;
+            let mut bob_rpc = client(nodes.bob.clone()).await;
+            let mut bob_events = bob_rpc
+                .subscribe(SubscribeRequest {})
+                .await
+                .unwrap()
+                .into_inner();
+
+            let offer = alice_rpc
+                .send_offer(SendOfferRequest {
+                    contract_input: input,
+                    counterparty: bob_key.to_string(),
+                })
+                .await
+                .unwrap()
+                .into_inner();
+
+            let temporary_id = offer.temporary_contract_id;
+            let mut offered = None;
+            for _ in 0..600 {
+                let contracts = bob_rpc
+                    .list_contracts(ListContractsRequest {})
+                    .await
+                    .unwrap()
+                    .into_inner()
+                    .contracts;
+                offered = contracts.into_iter().find(|c| c.id == temporary_id);
+                if offered.is_some() {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+            let offered = offered.expect("Bob did not receive the offer.");
+            assert_eq!(offered.state, "Offered");
+            assert_eq!(offered.counterparty, alice_key.to_string());
+            assert_eq!(offered.is_offer_party, Some(false));
+
+            let accepted = bob_rpc
+                .accept_offer(AcceptOfferRequest {
+                    contract_id: temporary_id,
+                })
+                .await
+                .unwrap()
+                .into_inner();
+            assert_eq!(accepted.counterparty, alice_key.to_string());
+            let contract_id = accepted.contract_id;
+
+            let signed = loop {
+                let event = tokio::time::timeout(Duration::from_secs(60), bob_events.next())
+                    .await
+                    .expect("No signed event.")
+                    .unwrap()
+                    .unwrap();
+                if let Some(proto::event::Event::ContractSigned(id)) = event.event {
+                    break id;
+                }
+            };
+            assert_eq!(signed, contract_id);
+
+            let contract_id = parse_contract_id(&contract_id).unwrap();
+            wait_for("Alice to sign", || is_signed(&nodes.alice, &contract_id));
+            let contract = alice_rpc
+                .get_contract(GetContractRequest {
+                    contract_id: hex::encode(contract_id),
+                })
+                .await
+                .unwrap()
+                .into_inner();
+            let summary = contract.summary.unwrap();
+            assert_eq!(summary.state, "Signed");
+            assert_eq!(summary.counterparty, bob_key.to_string());
+            assert!(contract.funding_txid.is_some());
+        });
+    }
+}
@@ -0,0 +1,286 @@
+//! Seed file encrypted with a passphrase.
+//!
+//! Layout of `seed.ddk`:
+//!
+//! | bytes | field                                  |
+//! |-------|----------------------------------------|
+//! | 4     | magic `DDKS`                           |
+//! | 1     | format version                         |
+//! | 1     | scrypt log2(N)                         |
+//! | 4     | scrypt r, big endian                   |
+//! | 4     | scrypt p, big endian                   |
+//! | 16    | salt                                   |
+//! | 12    | nonce                                  |
+//! | 80    | ChaCha20-Poly1305 encrypted 64 byte seed |
+use super::key_storage::{write_seed_file, FileKeyStorage, KeyStorage};
+use crate::ConfigError;
+use bitcoin::key::rand::{thread_rng, Rng};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+const MAGIC: &[u8; 4] = b"DDKS";
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = 4 + 1 + 1 + 4 + 4;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const FILE_LEN: usize = HEADER_LEN + SALT_LEN + NONCE_LEN + 64 + 16;
+
+// The cost is stored in the file, so seeds written by tests still load with the real cost.
+#[cfg(not(test))]
+const SCRYPT_LOG_N: u8 = 15;
+#[cfg(test)]
+const SCRYPT_LOG_N: u8 = 4;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+// Limits on the cost read from a seed file, well above what is written.
+const MAX_SCRYPT_LOG_N: u8 = 20;
+const MAX_SCRYPT_R: u32 = 32;
+const MAX_SCRYPT_P: u32 = 16;
+const MAX_SCRYPT_MEMORY: u64 = 1 << 30;
+
+/// Supplies the passphrase for an encrypted seed. Ex. a prompt or an OS keyring.
+pub trait PassphraseProvider: std::fmt::Debug + Send + Sync + 'static {
+    fn passphrase(&self) -> anyhow::Result<String>;
+}
+
+impl PassphraseProvider for String {
+    fn passphrase(&self) -> anyhow::Result<String> {
+        Ok(self.clone())
+    }
+}
+
+/// Whether the seed file contents are in the encrypted format.
+pub(crate) fn is_encrypted_seed(contents: &[u8]) -> bool {
+    contents.starts_with(MAGIC)
+}
+
+/// Stores the seed in a `seed.ddk` file in a directory, encrypted with a passphrase.
+#[derive(Debug, Clone)]
+pub struct EncryptedFileKeyStorage {
+    dir: PathBuf,
+    passphrase_provider: Arc<dyn PassphraseProvider>,
+}
+
+impl EncryptedFileKeyStorage {
+    pub fn new<P: Into<PathBuf>>(
+        dir: P,
+        passphrase_provider: Arc<dyn PassphraseProvider>,
+    ) -> EncryptedFileKeyStorage {
+        EncryptedFileKeyStorage {
+            dir: dir.into(),
+            passphrase_provider,
+        }
+    }
+
+    pub fn seed_path(&self) -> PathBuf {
+        self.dir.join("seed.ddk")
+    }
+}
+
+impl KeyStorage for EncryptedFileKeyStorage {
+    fn load(&self) -> anyhow::Result<Option<[u8; 64]>> {
+        let path = self.seed_path();
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let contents = std::fs::read(path)?;
+        let passphrase = self.passphrase_provider.passphrase()?;
+        Ok(Some(decrypt_seed(&contents, &passphrase)?))
+    }
+
+    fn store(&self, seed: &[u8; 64]) -> anyhow::Result<()> {
+        let passphrase = self.passphrase_provider.passphrase()?;
+        write_seed_file(&self.dir, &encrypt_seed(seed, &passphrase)?)
+    }
+}
+
+/// Encrypt the plaintext `seed.ddk` in `dir` in place. Does nothing if it is already encrypted.
+pub fn encrypt_seed_file<P: Into<PathBuf>>(dir: P, passphrase: &str) -> anyhow::Result<()> {
+    let dir = dir.into();
+    let plaintext = FileKeyStorage::new(&dir);
+    if is_encrypted_seed(&std::fs::read(plaintext.seed_path())?) {
+        return Ok(());
+    }
+    let seed = plaintext
+        .load()?
+        .ok_or_else(|| anyhow::anyhow!("No seed file to encrypt."))?;
+
+    EncryptedFileKeyStorage::new(&dir, Arc::new(passphrase.to_string())).store(&seed)?;
+    tracing::info!(dir = dir.display().to_string(), "Encrypted seed file.");
+    Ok(())
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], log_n: u8, r: u32, p: u32) -> anyhow::Result<[u8; 32]> {
+    let params = scrypt::Params::new(log_n, r, p, 32)
+        .map_err(|e| anyhow::anyhow!("Invalid scrypt parameters. {}", e))?;
+    let mut key = [0u8; 32];
+    scrypt::scrypt(passphrase.as_bytes(), salt, &params, &mut key)
+        .map_err(|e| anyhow::anyhow!("Could not derive seed key. {}", e))?;
+    Ok(key)
+}
+
+fn encrypt_seed(seed: &[u8; 64], passphrase: &str) -> anyhow::Result<Vec<u8>> {
+    let salt: [u8; SALT_LEN] = thread_rng().gen();
+    let nonce: [u8; NONCE_LEN] = thread_rng().gen();
+    let key = derive_key(passphrase, &salt, SCRYPT_LOG_N, SCRYPT_R, SCRYPT_P)?;
+    let ciphertext = ChaCha20Poly1305::new(Key::from_slice(&key))
+        .encrypt(Nonce::from_slice(&nonce), seed.as_ref())
+        .map_err(|_| anyhow::anyhow!("Could not encrypt seed."))?;
+
+    let mut contents = Vec::with_capacity(FILE_LEN);
+    contents.extend_from_slice(MAGIC);
+    contents.push(VERSION);
+    contents.push(SCRYPT_LOG_N);
+    contents.extend_from_slice(&SCRYPT_R.to_be_bytes());
+    contents.extend_from_slice(&SCRYPT_P.to_be_bytes());
+    contents.extend_from_slice(&salt);
+    contents.extend_from_slice(&nonce);
+    contents.extend_from_slice(&ciphertext);
+    Ok(contents)
+}
+
+/// Refuse a cost read from the file that would take more memory or time than any seed this
+/// crate writes, so a corrupted or hostile file cannot exhaust the machine.
+fn check_scrypt_cost(log_n: u8, r: u32, p: u32) -> Result<(), ConfigError> {
+    let memory = (128 * r as u64).checked_shl(log_n as u32).unwrap_or(u64::MAX);
+    if !(1..=MAX_SCRYPT_LOG_N).contains(&log_n)
+        || !(1..=MAX_SCRYPT_R).contains(&r)
+        || !(1..=MAX_SCRYPT_P).contains(&p)
+        || memory > MAX_SCRYPT_MEMORY
+    {
+        return Err(ConfigError::InvalidSeedFile(format!(
+            "Unreasonable scrypt parameters log_n={} r={} p={}.",
+            log_n, r, p
+        )));
+    }
+    Ok(())
+}
+
+fn decrypt_seed(contents: &[u8], passphrase: &str) -> Result<[u8; 64], ConfigError> {
+    if !is_encrypted_seed(contents) {
+        return Err(ConfigError::SeedNotEncrypted);
+    }
+    if contents.len() != FILE_LEN {
+        return Err(ConfigError::InvalidSeedLength {
+            expected: FILE_LEN,
+            got: contents.len(),
+        });
+    }
+    if contents[4] != VERSION {
+        return Err(ConfigError::UnsupportedSeedVersion(contents[4]));
+    }
+
+    let log_n = contents[5];
+    let r = u32::from_be_bytes(contents[6..10].try_into().unwrap());
+    let p = u32::from_be_bytes(contents[10..14].try_into().unwrap());
+    check_scrypt_cost(log_n, r, p)?;
+    let (salt, rest) = contents[HEADER_LEN..].split_at(SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+
+    let key = derive_key(passphrase, salt, log_n, r, p)
+        .map_err(|e| ConfigError::InvalidSeedFile(e.to_string()))?;
+    let seed = ChaCha20Poly1305::new(Key::from_slice(&key))
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| ConfigError::WrongPassphrase)?;
+    Ok(seed.try_into().expect("ciphertext length is checked"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn storage(dir: &str, passphrase: &str) -> EncryptedFileKeyStorage {
+        EncryptedFileKeyStorage::new(dir, Arc::new(passphrase.to_string()))
+    }
+
+    #[test]
+    fn round_trip_and_wrong_passphrase() {
+        let dir = "tests/data/encrypted_key_storage";
+        std::fs::create_dir_all(dir).unwrap();
+        let encrypted = storage(dir, "correct horse");
+        assert!(encrypted.load().unwrap().is_none());
+
+        encrypted.store(&[5u8; 64]).unwrap();
+        let contents = std::fs::read(encrypted.seed_path()).unwrap();
+        assert_eq!(contents.len(), FILE_LEN);
+        assert!(!contents.windows(64).any(|w| w == [5u8; 64]));
+        assert_eq!(storage(dir, "correct horse").load().unwrap(), Some([5u8; 64]));
+
+        let err = storage(dir, "battery staple").load().unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ConfigError>(),
+            Some(ConfigError::WrongPassphrase)
+        ));
+        // Reading it as plaintext is refused instead of producing a garbage seed.
+        assert!(FileKeyStorage::new(dir).load().is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn unknown_version_is_rejected() {
+        let mut contents = encrypt_seed(&[5u8; 64], "pass").unwrap();
+        contents[4] = 2;
+        assert!(matches!(
+            decrypt_seed(&contents, "pass"),
+            Err(ConfigError::UnsupportedSeedVersion(2))
+        ));
+    }
+
+    #[test]
+    fn truncated_file_is_rejected() {
+        for len in [4, 5, FILE_LEN - 1] {
+            let contents = encrypt_seed(&[5u8; 64], "pass").unwrap();
+            assert!(matches!(
+                decrypt_seed(&contents[..len], "pass"),
+                Err(ConfigError::InvalidSeedLength { expected: FILE_LEN, got }) if got == len
+            ));
+        }
+    }
+
+    #[test]
+    fn unreasonable_scrypt_cost_is_rejected() {
+        let contents = encrypt_seed(&[5u8; 64], "pass").unwrap();
+        let with_cost = |log_n: u8, r: u32, p: u32| {
+            let mut contents = contents.clone();
+            contents[5] = log_n;
+            contents[6..10].copy_from_slice(&r.to_be_bytes());
+            contents[10..14].copy_from_slice(&p.to_be_bytes());
+            decrypt_seed(&contents, "pass")
+        };
+        for (log_n, r, p) in [
+            (0, 8, 1),
+            (63, 8, 1),
+            (15, u32::MAX, 1),
+            (15, 8, u32::MAX),
+            (20, 32, 1),
+        ] {
+            assert!(matches!(
+                with_cost(log_n, r, p),
+                Err(ConfigError::InvalidSeedFile(_))
+            ));
+        }
+        assert_eq!(
+            with_cost(SCRYPT_LOG_N, SCRYPT_R, SCRYPT_P).unwrap(),
+            [5u8; 64]
+        );
+    }
+
+    #[test]
+    fn migrate_plaintext_seed() {
+        let dir = "tests/data/encrypt_seed_file";
+        std::fs::create_dir_all(dir).unwrap();
+        FileKeyStorage::new(dir).store(&[6u8; 64]).unwrap();
+
+        encrypt_seed_file(dir, "pass").unwrap();
+        assert_eq!(storage(dir, "pass").load().unwrap(), Some([6u8; 64]));
+        // Running it again leaves the encrypted seed alone.
+        encrypt_seed_file(dir, "other").unwrap();
+        assert_eq!(storage(dir, "pass").load().unwrap(), Some([6u8; 64]));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,164 @@
+use super::encrypted::is_encrypted_seed;
+use crate::ConfigError;
+use bitcoin::key::rand;
+use rand::Fill;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Persistence for the 64 byte master seed.
+///
+/// Platforms can keep the seed in a secure enclave (iOS Keychain, Android Keystore)
+/// by implementing this trait.
+pub trait KeyStorage: std::fmt::Debug + std::marker::Send + std::marker::Sync + 'static {
+    /// Load the seed. Returns `None` if no seed has been stored yet.
+    fn load(&self) -> anyhow::Result<Option<[u8; 64]>>;
+    /// Store the seed.
+    fn store(&self, seed: &[u8; 64]) -> anyhow::Result<()>;
+}
+
+/// Load the seed from storage, generating and storing a new one on first boot.
+pub fn load_or_generate_seed(key_storage: &dyn KeyStorage) -> anyhow::Result<[u8; 64]> {
+    if let Some(seed) = key_storage.load()? {
+        return Ok(seed);
+    }
+
+    let mut entropy = [0u8; 64];
+    entropy.try_fill(&mut rand::thread_rng())?;
+    key_storage.store(&entropy)?;
+    tracing::info!("Generated new seed.");
+    Ok(entropy)
+}
+
+/// Stores the seed in a `seed.ddk` file in a directory.
+#[derive(Debug, Clone)]
+pub struct FileKeyStorage {
+    dir: PathBuf,
+}
+
+impl FileKeyStorage {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> FileKeyStorage {
+        FileKeyStorage { dir: dir.into() }
+    }
+
+    pub fn seed_path(&self) -> PathBuf {
+        self.dir.join("seed.ddk")
+    }
+}
+
+impl KeyStorage for FileKeyStorage {
+    fn load(&self) -> anyhow::Result<Option<[u8; 64]>> {
+        let path = self.seed_path();
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let seed = std::fs::read(path)?;
+        if is_encrypted_seed(&seed) {
+            return Err(anyhow::anyhow!(
+                "Seed file is encrypted. Use SeedConfig::EncryptedFile."
+            ));
+        }
+        let key: [u8; 64] = seed
+            .as_slice()
+            .try_into()
+            .map_err(|_| ConfigError::InvalidSeedLength {
+                expected: 64,
+                got: seed.len(),
+            })?;
+        Ok(Some(key))
+    }
+
+    fn store(&self, seed: &[u8; 64]) -> anyhow::Result<()> {
+        write_seed_file(&self.dir, seed)
+    }
+}
+
+/// Replace `seed.ddk` in `dir`, readable only by the owner.
+pub(crate) fn write_seed_file(dir: &Path, contents: &[u8]) -> anyhow::Result<()> {
+    // Write to a temporary file first so a crash never leaves a partial seed.
+    let tmp_path = dir.join("seed.ddk.tmp");
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file: File = options.open(&tmp_path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    std::fs::rename(tmp_path, dir.join("seed.ddk"))?;
+    Ok(())
+}
+
+/// Keeps the seed in memory. Useful for tests and ephemeral nodes.
+#[derive(Debug, Default)]
+pub struct MemoryKeyStorage {
+    seed: Mutex<Option<[u8; 64]>>,
+}
+
+impl MemoryKeyStorage {
+    pub fn new(seed: Option<[u8; 64]>) -> MemoryKeyStorage {
+        MemoryKeyStorage {
+            seed: Mutex::new(seed),
+        }
+    }
+}
+
+impl KeyStorage for MemoryKeyStorage {
+    fn load(&self) -> anyhow::Result<Option<[u8; 64]>> {
+        Ok(*self.seed.lock().unwrap())
+    }
+
+    fn store(&self, seed: &[u8; 64]) -> anyhow::Result<()> {
+        *self.seed.lock().unwrap() = Some(*seed);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Default)]
+    struct RecordingKeyStorage {
+        inner: MemoryKeyStorage,
+        stores: Mutex<usize>,
+    }
+
+    impl KeyStorage for RecordingKeyStorage {
+        fn load(&self) -> anyhow::Result<Option<[u8; 64]>> {
+            self.inner.load()
+        }
+
+        fn store(&self, seed: &[u8; 64]) -> anyhow::Result<()> {
+            *self.stores.lock().unwrap() += 1;
+            self.inner.store(seed)
+        }
+    }
+
+    #[test]
+    fn first_boot_generates_and_stores_once() {
+        let storage = RecordingKeyStorage::default();
+        let first = load_or_generate_seed(&storage).unwrap();
+        let second = load_or_generate_seed(&storage).unwrap();
+        assert_eq!(first, second);
+        assert_eq!(*storage.stores.lock().unwrap(), 1);
+    }
+
+    #[test]
+    fn file_storage_round_trip() {
+        let dir = "tests/data/file_key_storage";
+        std::fs::create_dir_all(dir).unwrap();
+        let storage = FileKeyStorage::new(dir);
+        assert!(storage.load().unwrap().is_none());
+
+        let seed = load_or_generate_seed(&storage).unwrap();
+        let reopened = FileKeyStorage::new(dir);
+        assert_eq!(reopened.load().unwrap(), Some(seed));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,9 +1,13 @@
+mod encrypted;
+mod key_storage;
+
 use bitcoin::bip32::Xpriv;
 use bitcoin::Network;
-use bitcoin::key::rand;
-use rand::Fill;
-use std::{fs::File, io::Write, path::Path};
 use crate::config::SeedConfig;
+use crate::ConfigError;
+
+pub use encrypted::{encrypt_seed_file, EncryptedFileKeyStorage, PassphraseProvider};
+pub use key_storage::{load_or_generate_seed, FileKeyStorage, KeyStorage, MemoryKeyStorage};
 
 pub fn xprv_from_config(
     seed_config: &SeedConfig,
@@ -11,24 +15,113 @@ pub fn xprv_from_config(
 ) -> anyhow::Result<Xpriv> {
     let seed = match seed_config {
         SeedConfig::Bytes(bytes) => Xpriv::new_master(network, bytes)?,
-        SeedConfig::File(file) => {
-            if Path::new(&format!("{file}/seed.ddk")).exists() {
-                let seed = std::fs::read(format!("{file}/seed.ddk"))?;
-                let mut key = [0; 64];
-                key.copy_from_slice(&seed);
-                let xprv = Xpriv::new_master(network, &seed)?;
-                xprv
-            } else {
-                let mut file = File::create(format!("{file}/seed.ddk"))?;
-                let mut entropy = [0u8; 64];
-                entropy.try_fill(&mut rand::thread_rng())?;
-                // let _mnemonic = Mnemonic::from_entropy(&entropy)?;
-                let xprv = Xpriv::new_master(network, &entropy)?;
-                file.write_all(&entropy)?;
-                xprv
-            }
+        SeedConfig::File(file) => xprv_from_key_storage(&FileKeyStorage::new(file), network)?,
+        SeedConfig::KeyStorage(key_storage) => {
+            xprv_from_key_storage(key_storage.as_ref(), network)?
+        }
+        SeedConfig::EncryptedFile { path, passphrase_provider } => xprv_from_key_storage(
+            &EncryptedFileKeyStorage::new(path, passphrase_provider.clone()),
+            network,
+        )?,
+        SeedConfig::Mnemonic { phrase, passphrase } => {
+            let mnemonic = bip39::Mnemonic::parse(phrase).map_err(ConfigError::from)?;
+            let seed = mnemonic.to_seed(passphrase.as_deref().unwrap_or(""));
+            Xpriv::new_master(network, &seed)?
         }
     };
 
     Ok(seed)
 }
+
+/// Master key from a [KeyStorage], generating the seed on first boot.
+pub fn xprv_from_key_storage(
+    key_storage: &dyn KeyStorage,
+    network: Network,
+) -> anyhow::Result<Xpriv> {
+    let seed = load_or_generate_seed(key_storage)?;
+    Ok(Xpriv::new_master(network, &seed)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    const PHRASE: &str = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+
+    #[test]
+    fn bytes_round_trip() {
+        let config = SeedConfig::Bytes([7u8; 64]);
+        let xprv = xprv_from_config(&config, Network::Regtest).unwrap();
+        assert_eq!(xprv, Xpriv::new_master(Network::Regtest, &[7u8; 64]).unwrap());
+    }
+
+    #[test]
+    fn file_round_trip() {
+        let dir = "tests/data/xprv_from_file";
+        std::fs::create_dir_all(dir).unwrap();
+        let config = SeedConfig::File(dir.to_string());
+        let first = xprv_from_config(&config, Network::Regtest).unwrap();
+        let second = xprv_from_config(&config, Network::Regtest).unwrap();
+        assert_eq!(first, second);
+
+        let seed = std::fs::read(FileKeyStorage::new(dir).seed_path()).unwrap();
+        assert_eq!(first, Xpriv::new_master(Network::Regtest, &seed).unwrap());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn truncated_seed_file_is_an_error() {
+        let dir = "tests/data/xprv_truncated_file";
+        std::fs::create_dir_all(dir).unwrap();
+        std::fs::write(FileKeyStorage::new(dir).seed_path(), [1u8; 32]).unwrap();
+
+        let err = xprv_from_config(&SeedConfig::File(dir.to_string()), Network::Regtest)
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ConfigError>(),
+            Some(ConfigError::InvalidSeedLength {
+                expected: 64,
+                got: 32
+            })
+        ));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn key_storage_round_trip() {
+        let config = SeedConfig::KeyStorage(Arc::new(MemoryKeyStorage::new(Some([9u8; 64]))));
+        let xprv = xprv_from_config(&config, Network::Regtest).unwrap();
+        assert_eq!(xprv, Xpriv::new_master(Network::Regtest, &[9u8; 64]).unwrap());
+    }
+
+    #[test]
+    fn mnemonic_round_trip() {
+        // BIP-39 test vector.
+        let seed = hex::decode("c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04").unwrap();
+        let config = SeedConfig::Mnemonic {
+            phrase: PHRASE.to_string(),
+            passphrase: Some("TREZOR".to_string()),
+        };
+        let xprv = xprv_from_config(&config, Network::Regtest).unwrap();
+        assert_eq!(xprv, Xpriv::new_master(Network::Regtest, &seed).unwrap());
+
+        let without_passphrase = SeedConfig::Mnemonic {
+            phrase: PHRASE.to_string(),
+            passphrase: None,
+        };
+        assert_ne!(xprv_from_config(&without_passphrase, Network::Regtest).unwrap(), xprv);
+
+        let invalid = SeedConfig::Mnemonic {
+            phrase: "abandon abandon".to_string(),
+            passphrase: None,
+        };
+        let err = xprv_from_config(&invalid, Network::Regtest).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ConfigError>(),
+            Some(ConfigError::InvalidMnemonic(_))
+        ));
+    }
+}
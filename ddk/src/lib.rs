@@ -2,32 +2,75 @@
 
 #![allow(dead_code)]
 // #![allow(unused_imports)]
-mod chain;
 // pub mod ddk;
 mod ddk;
+mod dispatch;
 mod error;
-mod io;
 mod signer;
+#[cfg(test)]
 mod test_util;
 
+/// Realized profit and loss of settled contracts.
+pub mod accounting;
+/// Audit trail of contract state changes.
+pub mod audit;
+/// Inspect a data directory before building.
+pub mod bootstrap;
 /// Build a DDK application.
 pub mod builder;
+/// Blockchain clients.
+pub mod chain;
+/// Channel summaries.
+pub mod channel;
 /// Configuration for a DDK application.
 pub mod config;
+/// Contract helpers.
+pub mod contract;
+/// Contract lifecycle events.
+pub mod events;
+/// gRPC service for controlling a node from other languages.
+#[cfg(feature = "grpc")]
+pub mod grpc;
 /// DLC utilities.
 pub mod util;
+/// Seed and key storage.
+pub mod io;
+/// Tracing spans and subscriber setup.
+pub mod logging;
+/// Prometheus metrics.
+pub mod metrics;
 /// Oracle clients.
 pub mod oracle;
+/// Exchange rates for fiat reporting.
+pub mod rates;
+/// Replay recorded negotiations for debugging.
+#[cfg(any(test, feature = "test-util"))]
+pub mod replay;
+/// Rebuild a node from its seed.
+pub mod recovery;
+/// Global risk limits.
+pub mod risk;
+/// Health report of a running node.
+pub mod status;
 /// Storage implementations.
 pub mod storage;
+/// Helpers for testing DDK applications.
+#[cfg(any(test, feature = "test-util"))]
+pub mod testkit;
 /// Transport services.
 pub mod transport;
+/// Checks of contract inputs before offering.
+pub mod validation;
 /// The internal [bdk::Wallet].
 pub mod wallet;
 /// DDK object with all services
 pub use ddk::DlcDevKit;
 /// Type alias for [dlc_manager::manager::Manager]
 pub use ddk::DlcDevKitDlcManager;
+/// Errors returned by [DlcDevKit].
+pub use error::{
+    ChainError, ConfigError, DdkError, OracleError, StorageError, TransportError, WalletError,
+};
 
 /// Re-exports
 pub use bitcoin;
@@ -45,16 +88,26 @@ pub const ESPLORA_HOST: &str = "http://localhost:30000";
 
 use async_trait::async_trait;
 use bitcoin::secp256k1::PublicKey;
-use dlc_messages::oracle_msgs::OracleAnnouncement;
+use dlc_messages::oracle_msgs::{OracleAnnouncement, OracleAttestation};
 use dlc_messages::Message;
 use signer::DeriveSigner;
-use transport::PeerInformation;
-use bdk_wallet::WalletPersister;
+use transport::{PeerInformation, PendingInbound, PendingOutbound, TransportEvent};
+use crossbeam::channel::Sender;
+use rates::ContractRates;
+use dlc_manager::contract::Contract;
+use dlc_manager::ContractId;
+use bdk_chain::spk_client::{FullScanRequest, FullScanResult};
+use bdk_wallet::{KeychainKind, WalletPersister};
 use bitcoin::key::XOnlyPublicKey;
+use bitcoin::{OutPoint, Transaction, Txid};
 
 /// Allows ddk to open a listening connection and send/receive dlc messages functionality.
 ///
-/// TODO: error handling and result types
+/// The node runs the transport with [DdkTransport::start] and learns about received messages
+/// and peer connections from the [TransportEvent]s it reports. Transports written against the
+/// polling methods, [DdkTransport::listen] and
+/// [DdkTransport::get_and_clear_received_messages], keep working: the default `start` polls
+/// them, see [transport::poll_events].
 #[async_trait]
 pub trait DdkTransport: std::marker::Send + std::marker::Sync + 'static {
     type PeerManager;
@@ -62,6 +115,27 @@ pub trait DdkTransport: std::marker::Send + std::marker::Sync + 'static {
 
     /// Name for the transport service.
     fn name(&self) -> String;
+    /// Run the transport, reporting received messages and connection changes on `event_sink`.
+    /// Returns when the transport stops.
+    async fn start(&self, event_sink: Sender<TransportEvent>) {
+        transport::poll_events(self, event_sink).await
+    }
+    /// Send a message to a specific counterparty. Fails when the transport cannot take the
+    /// message, ex. because the counterparty is not connected. Defaults to queueing the message
+    /// and processing the queue.
+    async fn send_message(
+        &self,
+        counterparty: PublicKey,
+        message: Message,
+    ) -> Result<(), TransportError> {
+        self.queue_message(counterparty, message);
+        self.process_messages();
+        Ok(())
+    }
+    /// Peers the transport is connected to. Empty for transports without connections.
+    fn connected_peers(&self) -> Vec<PublicKey> {
+        vec![]
+    }
     /// Open an incoming listener for DLC messages from peers.
     async fn listen(&self);
     /// Retrieve the message handler.
@@ -72,20 +146,243 @@ pub trait DdkTransport: std::marker::Send + std::marker::Sync + 'static {
     fn peer_manager(&self) -> Self::PeerManager;
     /// Process messages
     fn process_messages(&self);
-    /// Send a message to a specific counterparty.
-    fn send_message(&self, counterparty: PublicKey, message: Message);
+    /// Queue a message for a specific counterparty, sent when the queue is processed.
+    fn queue_message(&self, counterparty: PublicKey, message: Message);
     /// Get messages that have not been processed yet.
     fn get_and_clear_received_messages(&self) -> Vec<(PublicKey, Message)>;
     /// If their are messages that still need to be processed.
     fn has_pending_messages(&self) -> bool;
     /// Connect to another peer
     async fn connect_outbound(&self, pubkey: PublicKey, host: &str);
+    /// Connect to a stored peer. Connectionless transports do nothing.
+    async fn connect(&self, _peer: PeerInformation) -> anyhow::Result<()> {
+        Ok(())
+    }
+    /// Whether the counterparty is currently reachable. Transports without connections
+    /// always return true.
+    fn is_connected(&self, _counterparty: &PublicKey) -> bool {
+        true
+    }
+    /// How far the transport received, for transports that can receive again from a point in
+    /// time, like a relay subscription. The node persists it once received messages are
+    /// journaled. None by default.
+    fn inbound_cursor(&self) -> Option<u64> {
+        None
+    }
+    /// Receive from a cursor persisted by an earlier run. Called before the transport is
+    /// started.
+    fn resume_inbound(&self, _cursor: u64) {}
 }
 
 /// Storage for DLC contracts.
 pub trait DdkStorage: dlc_manager::Storage + DeriveSigner + std::marker::Send + std::marker::Sync + 'static + WalletPersister {
+    /// Open the storage from the DDK config when none is given to the builder.
+    fn from_config(_config: &config::DdkConfig) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        Err(builder::BuilderError::NoStorage.into())
+    }
     fn list_peers(&self) -> anyhow::Result<Vec<PeerInformation>>;
     fn save_peer(&self, peer: PeerInformation) -> anyhow::Result<()>;
+    /// Record an outbound message that has not been handed to the transport yet.
+    fn save_pending_outbound(&self, pending: PendingOutbound) -> anyhow::Result<()>;
+    /// Outbound messages that still need to be delivered.
+    fn list_pending_outbound(&self) -> anyhow::Result<Vec<PendingOutbound>>;
+    /// Clear an outbound message once the transport has taken ownership of it.
+    fn remove_pending_outbound(&self, id: &str) -> anyhow::Result<()>;
+    /// Journal a received message before the manager processes it. Returns false when a message
+    /// with the same [transport::message_id] was already acknowledged, so it is not processed
+    /// twice. Queueing a message that is still pending keeps the original entry.
+    fn queue_inbound_message(&self, counterparty: PublicKey, message: &Message) -> anyhow::Result<bool>;
+    /// Mark a journaled message as processed. Its id is remembered to drop duplicates.
+    fn ack_inbound_message(&self, id: &str) -> anyhow::Result<()>;
+    /// Journaled messages that were not acknowledged, oldest first.
+    fn pending_inbound_messages(&self) -> anyhow::Result<Vec<PendingInbound>>;
+    /// Count a failed attempt at processing a journaled message and return its attempts so
+    /// far. Returns 0 when the message is not pending.
+    fn record_inbound_failure(&self, id: &str) -> anyhow::Result<u32>;
+    /// Number of journaled messages that were not acknowledged.
+    fn pending_inbound_count(&self) -> anyhow::Result<usize> {
+        Ok(self.pending_inbound_messages()?.len())
+    }
+    /// Append an entry to the audit log and return the sequence number it was given. Contract
+    /// state changes are appended by the backend itself, in the same write as the contract.
+    fn append_audit_entry(&self, entry: audit::AuditEntry) -> anyhow::Result<u64>;
+    /// Audit entries with a sequence number of at least `from`, in sequence order.
+    fn audit_entries(&self, from: u64) -> anyhow::Result<Vec<audit::AuditEntry>>;
+    /// Exchange rates recorded for a contract.
+    fn get_contract_rates(&self, contract_id: &ContractId) -> anyhow::Result<Option<ContractRates>>;
+    fn save_contract_rates(&self, contract_id: &ContractId, rates: ContractRates) -> anyhow::Result<()>;
+    /// Remember which contract transactions belong to, so they stay labelled after close.
+    fn save_contract_transactions(&self, records: &[wallet::ContractTransaction]) -> anyhow::Result<()>;
+    fn list_contract_transactions(&self) -> anyhow::Result<Vec<wallet::ContractTransaction>>;
+    /// Record what a contract paid us when it settled, replacing an earlier record.
+    fn save_contract_settlement(&self, settlement: &accounting::ContractSettlement) -> anyhow::Result<()>;
+    fn list_contract_settlements(&self) -> anyhow::Result<Vec<accounting::ContractSettlement>>;
+    /// Cache an oracle announcement, replacing an earlier one for the same oracle and event.
+    fn save_announcement(&self, announcement: &OracleAnnouncement) -> anyhow::Result<()>;
+    fn get_announcement(&self, oracle: &XOnlyPublicKey, event_id: &str) -> anyhow::Result<Option<OracleAnnouncement>>;
+    /// Delete cached announcements of events that matured before `matured_before`, a unix
+    /// timestamp in seconds. Returns the number deleted.
+    fn evict_announcements(&self, matured_before: u64) -> anyhow::Result<usize>;
+    /// Cache the attestation of an event. Attestations are kept once fetched.
+    fn save_attestation(&self, event_id: &str, attestation: &OracleAttestation) -> anyhow::Result<()>;
+    fn get_attestation(&self, oracle: &XOnlyPublicKey, event_id: &str) -> anyhow::Result<Option<OracleAttestation>>;
+    /// Attach application metadata to a contract, replacing what was stored before. Metadata set
+    /// on the temporary id of an offer moves to the contract id once the offer is accepted.
+    fn set_contract_metadata(&self, contract_id: &ContractId, metadata: contract::metadata::ContractMetadata) -> anyhow::Result<()>;
+    fn get_contract_metadata(&self, contract_id: &ContractId) -> anyhow::Result<Option<contract::metadata::ContractMetadata>>;
+    /// Delete signer keys of closed contracts once they are older than the retention period.
+    fn vacuum_signers(&self, options: &storage::SignerVacuumOptions) -> anyhow::Result<storage::SignerVacuumReport>;
+    /// Whether the node is in maintenance mode and refuses new contracts.
+    fn maintenance(&self) -> anyhow::Result<bool>;
+    /// Persist the maintenance mode so it survives restarts.
+    fn set_maintenance(&self, enabled: bool) -> anyhow::Result<()>;
+    /// Where the transport named `transport` resumes receiving after a restart. See
+    /// [DdkTransport::inbound_cursor].
+    fn get_transport_cursor(&self, transport: &str) -> anyhow::Result<Option<u64>>;
+    /// Persist the cursor of the transport named `transport`.
+    fn save_transport_cursor(&self, transport: &str, cursor: u64) -> anyhow::Result<()>;
+    /// UTXOs locked for DLC funding so they are not selected twice.
+    fn list_reserved_utxos(&self) -> anyhow::Result<Vec<OutPoint>>;
+    /// Replace the reserved UTXOs.
+    fn save_reserved_utxos(&self, outpoints: &[OutPoint]) -> anyhow::Result<()>;
+    /// UTXOs the operator excluded from DLC funding.
+    fn list_frozen_utxos(&self) -> anyhow::Result<Vec<OutPoint>>;
+    /// Replace the frozen UTXOs.
+    fn save_frozen_utxos(&self, outpoints: &[OutPoint]) -> anyhow::Result<()>;
+    /// Peers banned for exceeding the [transport::rate_limit::PeerLimits], including expired
+    /// bans that were not cleaned up yet.
+    fn list_peer_bans(&self) -> anyhow::Result<Vec<transport::rate_limit::PeerBan>>;
+    /// Replace the banned peers.
+    fn save_peer_bans(&self, bans: &[transport::rate_limit::PeerBan]) -> anyhow::Result<()>;
+    /// Transactions the node broadcast that have not confirmed yet, tracked by the
+    /// [chain::rebroadcast::BroadcastTracker].
+    fn list_pending_broadcasts(&self) -> anyhow::Result<Vec<chain::rebroadcast::PendingBroadcast>>;
+    /// Replace the pending broadcasts.
+    fn save_pending_broadcasts(&self, pending: &[chain::rebroadcast::PendingBroadcast]) -> anyhow::Result<()>;
+    /// Fee rates the wallet last refreshed, restored when it starts.
+    fn get_fee_estimates(&self) -> anyhow::Result<Option<wallet::fees::StoredFees>>;
+    /// Replace the stored fee rates.
+    fn save_fee_estimates(&self, fees: &wallet::fees::StoredFees) -> anyhow::Result<()>;
+    /// A page of the contracts passing `filter`, oldest first. `get_contracts` stays for callers
+    /// that need everything. This implementation deserializes every contract and takes the
+    /// creation time from the contract metadata; backends with an index should override it.
+    fn get_contracts_paginated(
+        &self,
+        filter: storage::ContractFilter,
+        offset: usize,
+        limit: usize,
+    ) -> anyhow::Result<Vec<Contract>> {
+        let mut contracts = vec![];
+        for contract in self.get_contracts()? {
+            let created_at = self
+                .get_contract_metadata(&contract.get_id())?
+                .map(|metadata| metadata.created_at)
+                .unwrap_or_default();
+            if filter.matches(&contract, created_at) {
+                contracts.push((created_at, contract));
+            }
+        }
+        contracts.sort_by_key(|(created_at, contract)| (*created_at, contract.get_id()));
+        Ok(contracts
+            .into_iter()
+            .skip(offset)
+            .take(limit)
+            .map(|(_, contract)| contract)
+            .collect())
+    }
+    /// Move finished contracts created more than `older_than` ago out of the contract store
+    /// and return how many were moved. The creation time is the one `get_contracts_paginated`
+    /// filters on.
+    fn archive_closed_contracts(&self, older_than: std::time::Duration) -> anyhow::Result<usize>;
+    /// Contracts moved out by `archive_closed_contracts`.
+    fn get_archived_contracts(&self) -> anyhow::Result<Vec<Contract>>;
+    /// Permanently delete archived contracts, and their metadata, archived more than
+    /// `older_than` ago. Returns how many were deleted.
+    fn prune_archive(&self, older_than: std::time::Duration) -> anyhow::Result<usize>;
+    /// Check that contracts, channels, and the chain monitor can be read and that signed channels
+    /// reference stored contracts. This implementation reads them through [dlc_manager::Storage],
+    /// so a record that does not deserialize fails the check instead of being reported;
+    /// backends should override it to report each record.
+    fn verify_integrity(&self) -> anyhow::Result<storage::IntegrityReport> {
+        let mut report = storage::IntegrityReport {
+            contracts_checked: self.get_contracts()?.len(),
+            ..Default::default()
+        };
+        for channel in self.get_signed_channels(None)? {
+            report.channels_checked += 1;
+            let Some(contract_id) = channel.get_contract_id() else {
+                continue;
+            };
+            if self.get_contract(&contract_id)?.is_none() {
+                report.issues.push(storage::IntegrityIssue {
+                    tree: "channels".to_string(),
+                    key: hex::encode(channel.channel_id),
+                    prefix: None,
+                    kind: storage::IntegrityIssueKind::MissingContract(hex::encode(contract_id)),
+                });
+            }
+        }
+        self.get_chain_monitor()?;
+        Ok(report)
+    }
+    /// Sizes and counts of the stored data. Backends should avoid deserializing every record.
+    fn storage_stats(&self) -> anyhow::Result<storage::StorageStats> {
+        let mut stats = storage::StorageStats::default();
+        for contract in self.get_contracts()? {
+            *stats
+                .contracts_by_state
+                .entry(contract::ContractState::from(&contract))
+                .or_default() += 1;
+        }
+        Ok(stats)
+    }
+}
+
+/// Chain backend shared by the wallet and the [dlc_manager::manager::Manager].
+pub trait DdkBlockchain: dlc_manager::Blockchain + std::marker::Send + std::marker::Sync + 'static {
+    /// Look up a transaction in the mempool or chain. `None` if the backend has not seen it.
+    fn find_transaction(&self, txid: &Txid) -> Result<Option<Transaction>, dlc_manager::error::Error>;
+    /// The transaction spending `outpoint`, in the mempool or chain. Backends that cannot look
+    /// up spends return none, and counterparty CETs are then only noticed by the manager.
+    fn find_spending_transaction(
+        &self,
+        _outpoint: &OutPoint,
+    ) -> Result<Option<Transaction>, dlc_manager::error::Error> {
+        Ok(None)
+    }
+    /// Fee estimates in sats per vbyte keyed by confirmation target in blocks. Backends without
+    /// estimates return none and the wallet keeps its default fee rates.
+    fn fee_estimates(&self) -> Result<std::collections::HashMap<u16, f64>, dlc_manager::error::Error> {
+        Ok(std::collections::HashMap::new())
+    }
+    /// Scan the wallet's scripts until `stop_gap` unused scripts in a row, for recovering a
+    /// wallet from its seed.
+    fn full_scan(
+        &self,
+        _request: FullScanRequest<KeychainKind>,
+        _stop_gap: usize,
+    ) -> Result<FullScanResult<KeychainKind>, dlc_manager::error::Error> {
+        Err(dlc_manager::error::Error::BlockchainError(
+            "Chain backend cannot scan the wallet.".to_string(),
+        ))
+    }
+    /// Unix time in seconds that time locktimes, ex. the refund locktime, are checked against.
+    /// The wall clock by default.
+    fn unix_time(&self) -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs())
+    }
+    /// Create the backend from the DDK config when none is given to the builder.
+    fn from_config(_config: &config::DdkConfig) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        Err(builder::BuilderError::NoBlockchain.into())
+    }
 }
 
 /// Oracle client
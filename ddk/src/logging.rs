@@ -0,0 +1,254 @@
+//! Tracing spans that carry contract ids through the message pipeline, and a subscriber for
+//! applications that do not set up their own.
+//!
+//! Every received message is processed in a `dlc_message` span, offers and accepts we start in
+//! `send_offer` and `accept_offer` spans. The spans record the temporary contract id and, once
+//! it is known, the contract id, so the log lines of a contract can be found by either id.
+use bitcoin::secp256k1::PublicKey;
+use dlc_manager::ContractId;
+use dlc_messages::Message;
+use tracing::field::Empty;
+use tracing::level_filters::LevelFilter;
+use tracing::{Span, Subscriber};
+use tracing_subscriber::filter::{EnvFilter, ParseError};
+use tracing_subscriber::fmt::MakeWriter;
+
+/// Span of processing a message received from `counter_party`.
+pub(crate) fn message_span(counter_party: &PublicKey, message: &Message) -> Span {
+    let span = tracing::info_span!(
+        "dlc_message",
+        peer = %counter_party,
+        message_type = crate::transport::message_kind(message),
+        temporary_contract_id = Empty,
+        contract_id = Empty,
+    );
+    match message {
+        Message::Offer(offer) => record_temporary_id(&span, &offer.temporary_contract_id),
+        Message::Accept(accept) => record_temporary_id(&span, &accept.temporary_contract_id),
+        Message::Sign(sign) => record_contract_id(&span, &sign.contract_id),
+        _ => {}
+    }
+    span
+}
+
+/// Span of creating an offer for `counter_party`.
+pub(crate) fn offer_span(counter_party: &PublicKey) -> Span {
+    tracing::info_span!(
+        "send_offer",
+        peer = %counter_party,
+        temporary_contract_id = Empty,
+    )
+}
+
+/// Span of accepting the offer with `temporary_contract_id`.
+pub(crate) fn accept_span(temporary_contract_id: &ContractId) -> Span {
+    let span = tracing::info_span!(
+        "accept_offer",
+        temporary_contract_id = Empty,
+        contract_id = Empty,
+    );
+    record_temporary_id(&span, temporary_contract_id);
+    span
+}
+
+pub(crate) fn record_temporary_id(span: &Span, temporary_contract_id: &ContractId) {
+    span.record(
+        "temporary_contract_id",
+        hex::encode(temporary_contract_id).as_str(),
+    );
+}
+
+pub(crate) fn record_contract_id(span: &Span, contract_id: &ContractId) {
+    span.record("contract_id", hex::encode(contract_id).as_str());
+}
+
+/// Builds a [tracing_subscriber::fmt] subscriber that prints the contract spans with every
+/// line. JSON output puts one object per line, with the fields of every enclosing span, for
+/// log collectors.
+///
+/// ```no_run
+/// use ddk::logging::DdkSubscriberBuilder;
+/// use tracing::level_filters::LevelFilter;
+///
+/// DdkSubscriberBuilder::new()
+///     .set_level(LevelFilter::DEBUG)
+///     .set_json(true)
+///     .init()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct DdkSubscriberBuilder {
+    level: LevelFilter,
+    directives: Option<String>,
+    json: bool,
+    line_numbers: bool,
+}
+
+impl Default for DdkSubscriberBuilder {
+    fn default() -> Self {
+        Self {
+            level: LevelFilter::INFO,
+            directives: None,
+            json: false,
+            line_numbers: true,
+        }
+    }
+}
+
+impl DdkSubscriberBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Most verbose level logged. Defaults to info.
+    pub fn set_level(&mut self, level: LevelFilter) -> &mut Self {
+        self.level = level;
+        self
+    }
+
+    /// Filter directives like `RUST_LOG`, e.g. `ddk=debug,sled=warn`. Replaces the level.
+    pub fn set_directives(&mut self, directives: &str) -> &mut Self {
+        self.directives = Some(directives.to_string());
+        self
+    }
+
+    /// Print one JSON object per line instead of text.
+    pub fn set_json(&mut self, json: bool) -> &mut Self {
+        self.json = json;
+        self
+    }
+
+    /// Print the file line of every event. Defaults to true.
+    pub fn set_line_numbers(&mut self, line_numbers: bool) -> &mut Self {
+        self.line_numbers = line_numbers;
+        self
+    }
+
+    /// A subscriber writing to stdout. Fails when the directives cannot be parsed.
+    pub fn finish(&self) -> Result<Box<dyn Subscriber + Send + Sync>, ParseError> {
+        self.finish_with_writer(std::io::stdout)
+    }
+
+    /// Finish and install the subscriber as the global default.
+    pub fn init(&self) -> anyhow::Result<()> {
+        tracing::subscriber::set_global_default(self.finish()?)?;
+        Ok(())
+    }
+
+    fn finish_with_writer<W>(
+        &self,
+        writer: W,
+    ) -> Result<Box<dyn Subscriber + Send + Sync>, ParseError>
+    where
+        W: for<'writer> MakeWriter<'writer> + Send + Sync + 'static,
+    {
+        let filter = match &self.directives {
+            Some(directives) => EnvFilter::try_new(directives)?,
+            None => EnvFilter::default().add_directive(self.level.into()),
+        };
+        let builder = tracing_subscriber::fmt()
+            .with_env_filter(filter)
+            .with_line_number(self.line_numbers)
+            .with_writer(writer);
+        if self.json {
+            Ok(Box::new(
+                builder
+                    .json()
+                    .with_current_span(true)
+                    .with_span_list(true)
+                    .finish(),
+            ))
+        } else {
+            Ok(Box::new(builder.finish()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::secp256k1::{Secp256k1, SecretKey};
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for Buffer {
+        type Writer = Buffer;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    fn log_lines(builder: &DdkSubscriberBuilder, log: impl FnOnce()) -> Vec<String> {
+        let buffer = Buffer::default();
+        let subscriber = builder.finish_with_writer(buffer.clone()).unwrap();
+        tracing::subscriber::with_default(subscriber, log);
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        output.lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn json_lines_carry_the_contract_ids() {
+        let secp = Secp256k1::new();
+        let peer = SecretKey::from_slice(&[3u8; 32]).unwrap().public_key(&secp);
+        let lines = log_lines(DdkSubscriberBuilder::new().set_json(true), || {
+            let span = accept_span(&[1u8; 32]);
+            let _entered = span.enter();
+            tracing::info!("Accepting offer.");
+            record_contract_id(&span, &[2u8; 32]);
+            tracing::info!("Accepted offer.");
+            let _offer = offer_span(&peer).entered();
+            tracing::debug!("Not logged at info.");
+        });
+
+        assert_eq!(lines.len(), 2);
+        let first: serde_json::Value = serde_json::from_str(&lines[0]).unwrap();
+        assert_eq!(first["span"]["name"], "accept_offer");
+        assert_eq!(
+            first["span"]["temporary_contract_id"],
+            hex::encode([1u8; 32])
+        );
+        assert!(first["span"].get("contract_id").is_none());
+        let second: serde_json::Value = serde_json::from_str(&lines[1]).unwrap();
+        assert_eq!(second["span"]["contract_id"], hex::encode([2u8; 32]));
+        assert_eq!(second["fields"]["message"], "Accepted offer.");
+    }
+
+    #[test]
+    fn text_lines_carry_the_span_fields() {
+        let secp = Secp256k1::new();
+        let peer = SecretKey::from_slice(&[3u8; 32]).unwrap().public_key(&secp);
+        let mut builder = DdkSubscriberBuilder::new();
+        builder.set_directives("debug");
+        let lines = log_lines(&builder, || {
+            let span = offer_span(&peer);
+            let _entered = span.enter();
+            record_temporary_id(&span, &[4u8; 32]);
+            tracing::debug!("Created offer.");
+        });
+
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].contains("send_offer"));
+        assert!(lines[0].contains(&format!("peer={}", peer)));
+        assert!(lines[0].contains(&hex::encode([4u8; 32])));
+    }
+
+    #[test]
+    fn invalid_directives_fail() {
+        let mut builder = DdkSubscriberBuilder::new();
+        builder.set_directives("ddk=loud");
+        assert!(builder.finish().is_err());
+    }
+}
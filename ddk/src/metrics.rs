@@ -0,0 +1,365 @@
+//! Prometheus metrics of the manager, wallet, chain backend, and storage. Recording compiles to
+//! nothing without the `metrics` feature, and [gather] returns an empty string.
+//!
+//! The metrics are global to the process. Several nodes in one process share them.
+use crate::contract::ContractState;
+use dlc_manager::contract::Contract;
+#[cfg(feature = "metrics")]
+use std::time::Instant;
+
+/// What happened to an offer of ours or to one we received.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OfferOutcome {
+    Sent,
+    Accepted,
+    Rejected,
+}
+
+impl OfferOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OfferOutcome::Sent => "sent",
+            OfferOutcome::Accepted => "accepted",
+            OfferOutcome::Rejected => "rejected",
+        }
+    }
+}
+
+/// Measures the duration of an operation for one of the histograms.
+#[derive(Debug, Clone, Copy)]
+pub struct Timer {
+    #[cfg(feature = "metrics")]
+    start: Instant,
+}
+
+impl Timer {
+    pub fn start() -> Timer {
+        Timer {
+            #[cfg(feature = "metrics")]
+            start: Instant::now(),
+        }
+    }
+}
+
+/// Time `operation` on the storage backend. It runs in a `storage` span at debug level.
+pub fn time_storage<R>(operation: &'static str, f: impl FnOnce() -> R) -> R {
+    let _span = tracing::debug_span!("storage", operation).entered();
+    let timer = Timer::start();
+    let result = f();
+    storage_operation(operation, timer);
+    result
+}
+
+/// Record a state change of a stored contract. Updates in the same state are not counted.
+pub fn contract_updated(existing: Option<&Contract>, updated: &Contract) {
+    let Some(existing) = existing else {
+        return;
+    };
+    let (from, to) = (ContractState::from(existing), ContractState::from(updated));
+    if from != to {
+        contract_transition(from, to);
+    }
+}
+
+pub use imp::*;
+
+#[cfg(feature = "metrics")]
+mod imp {
+    use super::{OfferOutcome, Timer};
+    use crate::contract::ContractState;
+    use crate::storage::StorageStats;
+    use prometheus::{
+        Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts, Registry,
+        TextEncoder,
+    };
+    use std::sync::OnceLock;
+
+    struct Metrics {
+        registry: Registry,
+        messages: IntCounterVec,
+        offers: IntCounterVec,
+        transitions: IntCounterVec,
+        broadcasts: IntCounterVec,
+        wallet_sync: HistogramVec,
+        esplora: HistogramVec,
+        storage: HistogramVec,
+        storage_size: IntGaugeVec,
+        storage_entries: IntGaugeVec,
+        contracts: IntGaugeVec,
+        wallet: IntGaugeVec,
+    }
+
+    impl Metrics {
+        fn new() -> Metrics {
+            let registry = Registry::new();
+            let counter = |name: &str, help: &str, labels: &[&str]| {
+                let counter = IntCounterVec::new(Opts::new(name, help), labels).unwrap();
+                registry.register(Box::new(counter.clone())).unwrap();
+                counter
+            };
+            let messages = counter(
+                "ddk_dlc_messages_total",
+                "DLC messages processed by the manager.",
+                &["kind", "result"],
+            );
+            let offers = counter(
+                "ddk_offers_total",
+                "Offers sent, accepted, and rejected.",
+                &["outcome"],
+            );
+            let transitions = counter(
+                "ddk_contract_transitions_total",
+                "State changes of stored contracts.",
+                &["from", "to"],
+            );
+            let broadcasts = counter(
+                "ddk_broadcasts_total",
+                "Transactions broadcast and rebroadcast.",
+                &["result"],
+            );
+
+            let histogram = |name: &str, help: &str, labels: &[&str], buckets: Vec<f64>| {
+                let opts = HistogramOpts::new(name, help).buckets(buckets);
+                let histogram = HistogramVec::new(opts, labels).unwrap();
+                registry.register(Box::new(histogram.clone())).unwrap();
+                histogram
+            };
+            let wallet_sync = histogram(
+                "ddk_wallet_sync_duration_seconds",
+                "Duration of wallet syncs.",
+                &["result"],
+                vec![0.1, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0],
+            );
+            let esplora = histogram(
+                "ddk_esplora_request_duration_seconds",
+                "Duration of esplora requests, each retry counted on its own.",
+                &["operation", "result"],
+                prometheus::DEFAULT_BUCKETS.to_vec(),
+            );
+            let storage = histogram(
+                "ddk_storage_operation_duration_seconds",
+                "Duration of contract storage operations.",
+                &["operation"],
+                vec![0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0],
+            );
+
+            let gauge = |name: &str, help: &str, labels: &[&str]| {
+                let gauge = IntGaugeVec::new(Opts::new(name, help), labels).unwrap();
+                registry.register(Box::new(gauge.clone())).unwrap();
+                gauge
+            };
+            let storage_size = gauge(
+                "ddk_storage_size_bytes",
+                "Bytes the contract store uses on disk.",
+                &[],
+            );
+            let storage_entries = gauge(
+                "ddk_storage_entries",
+                "Entries in each storage tree or table.",
+                &["tree"],
+            );
+            let contracts = gauge("ddk_contracts", "Stored contracts by state.", &["state"]);
+            let wallet = gauge(
+                "ddk_wallet_entries",
+                "Wallet changesets pending compaction, revealed addresses, utxos, and transactions.",
+                &["kind"],
+            );
+
+            Metrics {
+                registry,
+                messages,
+                offers,
+                transitions,
+                broadcasts,
+                wallet_sync,
+                esplora,
+                storage,
+                storage_size,
+                storage_entries,
+                contracts,
+                wallet,
+            }
+        }
+    }
+
+    fn metrics() -> &'static Metrics {
+        static METRICS: OnceLock<Metrics> = OnceLock::new();
+        METRICS.get_or_init(Metrics::new)
+    }
+
+    fn result(ok: bool) -> &'static str {
+        if ok {
+            "ok"
+        } else {
+            "error"
+        }
+    }
+
+    fn seconds(timer: Timer) -> f64 {
+        timer.start.elapsed().as_secs_f64()
+    }
+
+    pub fn message_processed(kind: &str, ok: bool) {
+        metrics()
+            .messages
+            .with_label_values(&[kind, result(ok)])
+            .inc();
+    }
+
+    pub fn offer(outcome: OfferOutcome) {
+        metrics()
+            .offers
+            .with_label_values(&[outcome.as_str()])
+            .inc();
+    }
+
+    pub fn contract_transition(from: ContractState, to: ContractState) {
+        metrics()
+            .transitions
+            .with_label_values(&[&from.to_string(), &to.to_string()])
+            .inc();
+    }
+
+    pub fn broadcast(ok: bool) {
+        metrics().broadcasts.with_label_values(&[result(ok)]).inc();
+    }
+
+    pub fn wallet_sync(timer: Timer, ok: bool) {
+        metrics()
+            .wallet_sync
+            .with_label_values(&[result(ok)])
+            .observe(seconds(timer));
+    }
+
+    pub fn esplora_request(operation: &str, timer: Timer, ok: bool) {
+        metrics()
+            .esplora
+            .with_label_values(&[operation, result(ok)])
+            .observe(seconds(timer));
+    }
+
+    pub fn storage_operation(operation: &str, timer: Timer) {
+        metrics()
+            .storage
+            .with_label_values(&[operation])
+            .observe(seconds(timer));
+    }
+
+    pub fn storage_stats(stats: &StorageStats) {
+        let metrics = metrics();
+        if let Some(size) = stats.size_on_disk {
+            metrics.storage_size.with_label_values(&[]).set(size as i64);
+        }
+        for (tree, entries) in &stats.tree_sizes {
+            metrics
+                .storage_entries
+                .with_label_values(&[tree])
+                .set(*entries as i64);
+        }
+        for state in ContractState::ALL {
+            let count = stats.contracts_by_state.get(&state).copied().unwrap_or_default();
+            metrics
+                .contracts
+                .with_label_values(&[&state.to_string()])
+                .set(count as i64);
+        }
+        let wallet = [
+            ("changesets", stats.wallet_changesets as i64),
+            ("external_addresses", stats.wallet.revealed_external_addresses as i64),
+            ("internal_addresses", stats.wallet.revealed_internal_addresses as i64),
+            ("utxos", stats.wallet.utxos as i64),
+            ("transactions", stats.wallet.transactions as i64),
+        ];
+        for (kind, count) in wallet {
+            metrics.wallet.with_label_values(&[kind]).set(count);
+        }
+    }
+
+    /// Every metric in the Prometheus text exposition format.
+    pub fn gather() -> String {
+        let mut buffer = vec![];
+        if let Err(e) = TextEncoder::new().encode(&metrics().registry.gather(), &mut buffer) {
+            tracing::error!(error = e.to_string(), "Could not encode metrics.");
+        }
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+}
+
+#[cfg(not(feature = "metrics"))]
+mod imp {
+    use super::{OfferOutcome, Timer};
+    use crate::contract::ContractState;
+
+    #[inline(always)]
+    pub fn message_processed(_kind: &str, _ok: bool) {}
+
+    #[inline(always)]
+    pub fn offer(_outcome: OfferOutcome) {}
+
+    #[inline(always)]
+    pub fn contract_transition(_from: ContractState, _to: ContractState) {}
+
+    #[inline(always)]
+    pub fn broadcast(_ok: bool) {}
+
+    #[inline(always)]
+    pub fn wallet_sync(_timer: Timer, _ok: bool) {}
+
+    #[inline(always)]
+    pub fn esplora_request(_operation: &str, _timer: Timer, _ok: bool) {}
+
+    #[inline(always)]
+    pub fn storage_operation(_operation: &str, _timer: Timer) {}
+
+    #[inline(always)]
+    pub fn storage_stats(_stats: &crate::storage::StorageStats) {}
+
+    /// Empty without the `metrics` feature.
+    pub fn gather() -> String {
+        String::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::StorageStats;
+
+    #[test]
+    fn recorded_metrics_are_gathered() {
+        message_processed("offer", true);
+        offer(OfferOutcome::Accepted);
+        contract_transition(ContractState::Signed, ContractState::Confirmed);
+        broadcast(false);
+        wallet_sync(Timer::start(), true);
+        esplora_request("get_tx", Timer::start(), true);
+        assert_eq!(time_storage("get_contract", || 7), 7);
+        storage_stats(&StorageStats {
+            size_on_disk: Some(4_096),
+            wallet_changesets: 3,
+            contracts_by_state: [(ContractState::Closed, 2)].into(),
+            ..Default::default()
+        });
+
+        let text = gather();
+        if !cfg!(feature = "metrics") {
+            assert!(text.is_empty());
+            return;
+        }
+        for line in [
+            "ddk_dlc_messages_total{kind=\"offer\",result=\"ok\"}",
+            "ddk_offers_total{outcome=\"accepted\"}",
+            "ddk_contract_transitions_total{from=\"Signed\",to=\"Confirmed\"}",
+            "ddk_broadcasts_total{result=\"error\"}",
+            "ddk_wallet_sync_duration_seconds_count{result=\"ok\"}",
+            "ddk_esplora_request_duration_seconds_count{operation=\"get_tx\",result=\"ok\"}",
+            "ddk_storage_operation_duration_seconds_count{operation=\"get_contract\"}",
+            "ddk_storage_size_bytes 4096",
+            "ddk_contracts{state=\"Closed\"} 2",
+            "ddk_contracts{state=\"Offered\"} 0",
+            "ddk_wallet_entries{kind=\"changesets\"} 3",
+        ] {
+            assert!(text.contains(line), "{} is missing from\n{}", line, text);
+        }
+    }
+}
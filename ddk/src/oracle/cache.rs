@@ -0,0 +1,212 @@
+//! In-memory cache of oracle announcements.
+use bitcoin::key::XOnlyPublicKey;
+use dlc_manager::contract::Contract;
+use dlc_messages::oracle_msgs::OracleAnnouncement;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+/// Announcements are unique per oracle and event id.
+pub type AnnouncementKey = (XOnlyPublicKey, String);
+
+struct CachedAnnouncement {
+    announcement: OracleAnnouncement,
+    last_used: u64,
+}
+
+/// Size bounded, least recently used cache of oracle announcements.
+///
+/// Announcements referenced by contracts that are not closed are protected and never evicted.
+pub struct AnnouncementCache {
+    max_entries: usize,
+    max_age: Duration,
+    entries: HashMap<AnnouncementKey, CachedAnnouncement>,
+    protected: HashSet<AnnouncementKey>,
+    clock: u64,
+}
+
+impl AnnouncementCache {
+    /// `max_age` is how long past event maturity an announcement is kept.
+    pub fn new(max_entries: usize, max_age: Duration) -> AnnouncementCache {
+        AnnouncementCache {
+            max_entries,
+            max_age,
+            entries: HashMap::new(),
+            protected: HashSet::new(),
+            clock: 0,
+        }
+    }
+
+    pub fn key(announcement: &OracleAnnouncement) -> AnnouncementKey {
+        (
+            announcement.oracle_public_key,
+            announcement.oracle_event.event_id.clone(),
+        )
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Insert an announcement. A newer announcement for the same oracle and event replaces the old one.
+    pub fn insert(&mut self, announcement: OracleAnnouncement) {
+        let last_used = self.tick();
+        self.entries.insert(
+            Self::key(&announcement),
+            CachedAnnouncement {
+                announcement,
+                last_used,
+            },
+        );
+        self.evict_over_capacity();
+    }
+
+    pub fn get(&mut self, oracle: &XOnlyPublicKey, event_id: &str) -> Option<OracleAnnouncement> {
+        let last_used = self.tick();
+        let entry = self.entries.get_mut(&(*oracle, event_id.to_string()))?;
+        entry.last_used = last_used;
+        Some(entry.announcement.clone())
+    }
+
+    /// Replace the set of announcements that must never be evicted.
+    pub fn set_protected(&mut self, protected: HashSet<AnnouncementKey>) {
+        self.protected = protected;
+    }
+
+    fn evict_over_capacity(&mut self) {
+        while self.entries.len() > self.max_entries {
+            let lru = self
+                .entries
+                .iter()
+                .filter(|(key, _)| !self.protected.contains(*key))
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            match lru {
+                Some(key) => {
+                    self.entries.remove(&key);
+                }
+                // Everything left is protected.
+                None => break,
+            }
+        }
+    }
+
+    /// Evict announcements older than `max_age` past maturity and enforce the size cap.
+    /// `now` is a unix timestamp in seconds. Returns the number of evicted announcements.
+    pub fn vacuum(&mut self, now: u64) -> usize {
+        let before = self.entries.len();
+        let max_age = self.max_age.as_secs();
+        let protected = &self.protected;
+        self.entries.retain(|key, entry| {
+            let maturity = entry.announcement.oracle_event.event_maturity_epoch as u64;
+            protected.contains(key) || maturity.saturating_add(max_age) > now
+        });
+        self.evict_over_capacity();
+        before - self.entries.len()
+    }
+}
+
+/// Announcements referenced by contracts that are not closed, refunded, rejected, or failed.
+pub fn referenced_announcements(contracts: &[Contract]) -> HashSet<AnnouncementKey> {
+    contracts
+        .iter()
+        .filter_map(|contract| match contract {
+            Contract::Offered(o) => Some(o),
+            Contract::Accepted(a) => Some(&a.offered_contract),
+            Contract::Signed(s) | Contract::Confirmed(s) => Some(&s.accepted_contract.offered_contract),
+            Contract::PreClosed(p) => Some(&p.signed_contract.accepted_contract.offered_contract),
+            _ => None,
+        })
+        .flat_map(|offered| offered.contract_info.iter())
+        .flat_map(|info| info.oracle_announcements.iter())
+        .map(AnnouncementCache::key)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::key::Keypair;
+    use bitcoin::secp256k1::{schnorr::Signature, Secp256k1, SecretKey};
+    use dlc_messages::oracle_msgs::{EnumEventDescriptor, EventDescriptor, OracleEvent};
+
+    fn announcement(event_id: &str, maturity: u32, signature: u8) -> OracleAnnouncement {
+        let secp = Secp256k1::new();
+        let keypair = Keypair::from_secret_key(&secp, &SecretKey::from_slice(&[1u8; 32]).unwrap());
+        OracleAnnouncement {
+            announcement_signature: Signature::from_slice(&[signature; 64]).unwrap(),
+            oracle_public_key: keypair.x_only_public_key().0,
+            oracle_event: OracleEvent {
+                oracle_nonces: vec![],
+                event_maturity_epoch: maturity,
+                event_descriptor: EventDescriptor::EnumEvent(EnumEventDescriptor {
+                    outcomes: vec!["yes".into(), "no".into()],
+                }),
+                event_id: event_id.to_string(),
+            },
+        }
+    }
+
+    #[test]
+    fn deduplicates_with_newer_announcement() {
+        let mut cache = AnnouncementCache::new(10, Duration::from_secs(60));
+        let first = announcement("event", 100, 1);
+        let newer = announcement("event", 100, 2);
+        cache.insert(first.clone());
+        cache.insert(newer.clone());
+        assert_eq!(cache.len(), 1);
+        let cached = cache.get(&first.oracle_public_key, "event").unwrap();
+        assert_eq!(cached.announcement_signature, newer.announcement_signature);
+    }
+
+    #[test]
+    fn evicts_least_recently_used_over_capacity() {
+        let mut cache = AnnouncementCache::new(2, Duration::from_secs(60));
+        let a = announcement("a", 100, 1);
+        cache.insert(a.clone());
+        cache.insert(announcement("b", 100, 1));
+        cache.get(&a.oracle_public_key, "a");
+        cache.insert(announcement("c", 100, 1));
+        assert!(cache.get(&a.oracle_public_key, "a").is_some());
+        assert!(cache.get(&a.oracle_public_key, "b").is_none());
+    }
+
+    #[test]
+    fn protected_announcements_are_never_evicted() {
+        let mut cache = AnnouncementCache::new(3, Duration::from_secs(60));
+        let protected = (0..3)
+            .map(|i| announcement(&format!("protected-{i}"), 0, 1))
+            .collect::<Vec<_>>();
+        cache.set_protected(protected.iter().map(AnnouncementCache::key).collect());
+        for a in &protected {
+            cache.insert(a.clone());
+        }
+        for i in 0..10 {
+            cache.insert(announcement(&format!("other-{i}"), 0, 1));
+        }
+
+        // Long past maturity and over capacity.
+        cache.vacuum(1_000_000);
+        assert_eq!(cache.len(), 3);
+        for a in &protected {
+            assert!(cache.get(&a.oracle_public_key, &a.oracle_event.event_id).is_some());
+        }
+    }
+
+    #[test]
+    fn vacuum_evicts_stale_announcements() {
+        let mut cache = AnnouncementCache::new(10, Duration::from_secs(60));
+        cache.insert(announcement("stale", 100, 1));
+        cache.insert(announcement("fresh", 1_000, 1));
+        assert_eq!(cache.vacuum(500), 1);
+        assert_eq!(cache.len(), 1);
+    }
+}
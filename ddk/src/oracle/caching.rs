@@ -0,0 +1,425 @@
+//! An oracle client that keeps announcements and attestations in [DdkStorage].
+//!
+//! Offers and periodic checks ask the oracle for the same events over and over. Wrapping the
+//! client in a [CachingOracle] answers those from storage, and lets the node settle contracts
+//! while the oracle is down once the attestation was fetched. The builder wraps every oracle
+//! the manager uses in one, sharing the node's in-memory [AnnouncementCache].
+use super::cache::AnnouncementCache;
+use super::verify::verify_attestation;
+use crate::config::DEFAULT_ANNOUNCEMENT_MAX_AGE;
+use crate::{DdkOracle, DdkStorage};
+use bitcoin::key::XOnlyPublicKey;
+use dlc_manager::error::Error as ManagerError;
+use dlc_messages::oracle_msgs::{OracleAnnouncement, OracleAttestation};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Wraps an oracle client and consults its caches before asking it.
+///
+/// Announcements are looked up in memory, then in storage, and cached until their event
+/// matured longer than the grace period ago. Attestations never change once published and are
+/// kept, but only once they verify against the event's announcement. An attestation that does
+/// not verify is returned uncached, so the next lookup asks the oracle again.
+pub struct CachingOracle<O, S> {
+    oracle: Arc<O>,
+    storage: Arc<S>,
+    grace_period: Duration,
+    memory: Option<Arc<Mutex<AnnouncementCache>>>,
+}
+
+impl<O: DdkOracle, S: DdkStorage> CachingOracle<O, S> {
+    /// Cache with the default grace period of one week past event maturity.
+    pub fn new(oracle: Arc<O>, storage: Arc<S>) -> CachingOracle<O, S> {
+        CachingOracle::with_grace_period(oracle, storage, DEFAULT_ANNOUNCEMENT_MAX_AGE)
+    }
+
+    pub fn with_grace_period(
+        oracle: Arc<O>,
+        storage: Arc<S>,
+        grace_period: Duration,
+    ) -> CachingOracle<O, S> {
+        CachingOracle {
+            oracle,
+            storage,
+            grace_period,
+            memory: None,
+        }
+    }
+
+    /// Keep announcements in `cache` too, in front of storage. The node shares its own cache
+    /// so announcements fetched through the manager and through the node are fetched once.
+    pub fn with_announcement_cache(mut self, cache: Arc<Mutex<AnnouncementCache>>) -> Self {
+        self.memory = Some(cache);
+        self
+    }
+
+    /// The wrapped oracle client.
+    pub fn inner(&self) -> &Arc<O> {
+        &self.oracle
+    }
+
+    /// Delete cached announcements past maturity and the grace period. Returns the number
+    /// deleted.
+    pub fn evict_expired(&self) -> anyhow::Result<usize> {
+        let evicted = self.storage.evict_announcements(self.expired_before())?;
+        tracing::debug!(
+            oracle = self.oracle.name(),
+            evicted,
+            "Evicted expired oracle announcements."
+        );
+        Ok(evicted)
+    }
+
+    fn expired_before(&self) -> u64 {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        now.saturating_sub(self.grace_period.as_secs())
+    }
+
+    /// A cached announcement that has not expired. Expired announcements are deleted.
+    fn cached_announcement(&self, event_id: &str) -> Option<OracleAnnouncement> {
+        let public_key = self.oracle.get_public_key();
+        if let Some(memory) = &self.memory {
+            if let Some(announcement) = memory.lock().unwrap().get(&public_key, event_id) {
+                tracing::debug!(event_id, "Announcement memory cache hit.");
+                return Some(announcement);
+            }
+        }
+        let cached = match self.storage.get_announcement(&public_key, event_id) {
+            Ok(cached) => cached,
+            Err(e) => {
+                tracing::warn!(
+                    event_id,
+                    error = e.to_string(),
+                    "Could not read cached announcement."
+                );
+                None
+            }
+        };
+        match cached {
+            Some(announcement)
+                if (announcement.oracle_event.event_maturity_epoch as u64)
+                    >= self.expired_before() =>
+            {
+                tracing::debug!(event_id, "Announcement cache hit.");
+                if let Some(memory) = &self.memory {
+                    memory.lock().unwrap().insert(announcement.clone());
+                }
+                Some(announcement)
+            }
+            Some(_) => {
+                tracing::debug!(event_id, "Announcement cache entry expired.");
+                if let Err(e) = self.evict_expired() {
+                    tracing::warn!(error = e.to_string(), "Could not evict announcements.");
+                }
+                None
+            }
+            None => {
+                tracing::debug!(event_id, "Announcement cache miss.");
+                None
+            }
+        }
+    }
+
+    /// Whether `attestation` verifies against the announcement of `event_id`. False if the
+    /// announcement is not available.
+    fn verifies(&self, event_id: &str, attestation: &OracleAttestation) -> bool {
+        let announcement = match dlc_manager::Oracle::get_announcement(self, event_id) {
+            Ok(announcement) => announcement,
+            Err(e) => {
+                tracing::warn!(
+                    event_id,
+                    error = e.to_string(),
+                    "Could not verify attestation without its announcement."
+                );
+                return false;
+            }
+        };
+        match verify_attestation(&announcement, attestation) {
+            Ok(()) => true,
+            Err(e) => {
+                tracing::warn!(event_id, error = e.to_string(), "Not caching invalid attestation.");
+                false
+            }
+        }
+    }
+
+    fn cache_announcement(&self, announcement: &OracleAnnouncement) {
+        if let Some(memory) = &self.memory {
+            memory.lock().unwrap().insert(announcement.clone());
+        }
+        if let Err(e) = self.storage.save_announcement(announcement) {
+            tracing::warn!(
+                event_id = announcement.oracle_event.event_id,
+                error = e.to_string(),
+                "Could not cache announcement."
+            );
+        }
+    }
+}
+
+impl<O: DdkOracle, S: DdkStorage> dlc_manager::Oracle for CachingOracle<O, S> {
+    fn get_public_key(&self) -> XOnlyPublicKey {
+        self.oracle.get_public_key()
+    }
+
+    fn get_announcement(&self, event_id: &str) -> Result<OracleAnnouncement, ManagerError> {
+        if let Some(announcement) = self.cached_announcement(event_id) {
+            return Ok(announcement);
+        }
+        let announcement = self.oracle.get_announcement(event_id)?;
+        self.cache_announcement(&announcement);
+        Ok(announcement)
+    }
+
+    /// Cached attestations are verified again, so one cached before attestations were verified
+    /// is replaced by the oracle's. Invalid attestations are passed on uncached, which leaves
+    /// reporting them to the caller, ex. the [super::VerifyingOracle] of the manager.
+    fn get_attestation(&self, event_id: &str) -> Result<OracleAttestation, ManagerError> {
+        let public_key = self.oracle.get_public_key();
+        match self.storage.get_attestation(&public_key, event_id) {
+            Ok(Some(attestation)) if self.verifies(event_id, &attestation) => {
+                tracing::debug!(event_id, "Attestation cache hit.");
+                return Ok(attestation);
+            }
+            Ok(Some(_)) => tracing::warn!(event_id, "Dropping cached attestation that does not verify."),
+            Ok(None) => tracing::debug!(event_id, "Attestation cache miss."),
+            Err(e) => tracing::warn!(
+                event_id,
+                error = e.to_string(),
+                "Could not read cached attestation."
+            ),
+        }
+        let attestation = self.oracle.get_attestation(event_id)?;
+        if !self.verifies(event_id, &attestation) {
+            return Ok(attestation);
+        }
+        if let Err(e) = self.storage.save_attestation(event_id, &attestation) {
+            tracing::warn!(
+                event_id,
+                error = e.to_string(),
+                "Could not cache attestation."
+            );
+        }
+        Ok(attestation)
+    }
+}
+
+#[async_trait::async_trait]
+impl<O: DdkOracle, S: DdkStorage> DdkOracle for CachingOracle<O, S> {
+    fn name(&self) -> String {
+        self.oracle.name()
+    }
+
+    async fn get_announcement_async(
+        &self,
+        event_id: &str,
+    ) -> Result<OracleAnnouncement, ManagerError> {
+        if let Some(announcement) = self.cached_announcement(event_id) {
+            return Ok(announcement);
+        }
+        let announcement = self.oracle.get_announcement_async(event_id).await?;
+        self.cache_announcement(&announcement);
+        Ok(announcement)
+    }
+
+    async fn get_public_key_async(&self) -> Result<XOnlyPublicKey, ManagerError> {
+        self.oracle.get_public_key_async().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorageProvider;
+    use bitcoin::bip32::Xpriv;
+    use bitcoin::secp256k1::SecretKey;
+    use bitcoin::Network;
+    use dlc_manager::Oracle;
+    use kormir::storage::MemoryStorage;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    const EVENT_ID: &str = "coin-flip";
+
+    /// Serves one attested event, counts requests, and fails them once the oracle is down.
+    struct FlakyOracle {
+        announcement: OracleAnnouncement,
+        attestation: OracleAttestation,
+        requests: AtomicUsize,
+        down: AtomicBool,
+        /// Serve an attestation whose outcome does not match its signature.
+        tampered: AtomicBool,
+    }
+
+    impl FlakyOracle {
+        fn new() -> FlakyOracle {
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+            let signing_key = SecretKey::from_slice(&[5u8; 32]).unwrap();
+            let nonce_xpriv = Xpriv::new_master(Network::Regtest, &[6u8; 32]).unwrap();
+            let oracle = kormir::Oracle::new(MemoryStorage::default(), signing_key, nonce_xpriv);
+            let maturity = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs() as u32
+                + 86_400;
+            let announcement = runtime
+                .block_on(oracle.create_enum_event(
+                    EVENT_ID.to_string(),
+                    vec!["heads".into(), "tails".into()],
+                    maturity,
+                ))
+                .unwrap();
+            let attestation = runtime
+                .block_on(oracle.sign_enum_event(EVENT_ID.to_string(), "heads".into()))
+                .unwrap();
+            FlakyOracle {
+                announcement,
+                attestation,
+                requests: AtomicUsize::new(0),
+                down: Default::default(),
+                tampered: Default::default(),
+            }
+        }
+
+        fn request(&self, event_id: &str) -> Result<(), ManagerError> {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            if self.down.load(Ordering::SeqCst) {
+                return Err(ManagerError::OracleError("Oracle is down.".into()));
+            }
+            if event_id != EVENT_ID {
+                return Err(ManagerError::OracleError(format!("Unknown event {}.", event_id)));
+            }
+            Ok(())
+        }
+    }
+
+    impl dlc_manager::Oracle for FlakyOracle {
+        fn get_public_key(&self) -> XOnlyPublicKey {
+            self.announcement.oracle_public_key
+        }
+
+        fn get_announcement(&self, event_id: &str) -> Result<OracleAnnouncement, ManagerError> {
+            self.request(event_id)?;
+            Ok(self.announcement.clone())
+        }
+
+        fn get_attestation(&self, event_id: &str) -> Result<OracleAttestation, ManagerError> {
+            self.request(event_id)?;
+            let mut attestation = self.attestation.clone();
+            if self.tampered.load(Ordering::SeqCst) {
+                attestation.outcomes = vec!["tails".into()];
+            }
+            Ok(attestation)
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl DdkOracle for FlakyOracle {
+        fn name(&self) -> String {
+            "flaky".into()
+        }
+
+        async fn get_announcement_async(
+            &self,
+            event_id: &str,
+        ) -> Result<OracleAnnouncement, ManagerError> {
+            dlc_manager::Oracle::get_announcement(self, event_id)
+        }
+
+        async fn get_public_key_async(&self) -> Result<XOnlyPublicKey, ManagerError> {
+            Ok(self.get_public_key())
+        }
+    }
+
+    fn caching_oracle(
+        grace_period: Duration,
+    ) -> (
+        CachingOracle<FlakyOracle, MemoryStorageProvider>,
+        Arc<FlakyOracle>,
+    ) {
+        let flaky = Arc::new(FlakyOracle::new());
+        let storage = Arc::new(MemoryStorageProvider::new());
+        (
+            CachingOracle::with_grace_period(flaky.clone(), storage, grace_period),
+            flaky,
+        )
+    }
+
+    #[test]
+    fn cached_data_survives_the_oracle_going_down() {
+        let (oracle, flaky) = caching_oracle(DEFAULT_ANNOUNCEMENT_MAX_AGE);
+
+        let announcement = oracle.get_announcement(EVENT_ID).unwrap();
+        let attestation = oracle.get_attestation(EVENT_ID).unwrap();
+        assert_eq!(flaky.requests.load(Ordering::SeqCst), 2);
+
+        flaky.down.store(true, Ordering::SeqCst);
+        assert_eq!(oracle.get_announcement(EVENT_ID).unwrap(), announcement);
+        assert_eq!(oracle.get_attestation(EVENT_ID).unwrap(), attestation);
+        assert_eq!(flaky.requests.load(Ordering::SeqCst), 2);
+        assert!(oracle.get_attestation("unknown").is_err());
+    }
+
+    #[test]
+    fn invalid_attestations_are_not_cached() {
+        let (oracle, flaky) = caching_oracle(DEFAULT_ANNOUNCEMENT_MAX_AGE);
+        let public_key = flaky.get_public_key();
+        flaky.tampered.store(true, Ordering::SeqCst);
+
+        // Passed on for the caller to report, but never kept.
+        let tampered = oracle.get_attestation(EVENT_ID).unwrap();
+        assert_eq!(tampered.outcomes, vec!["tails".to_string()]);
+        assert!(oracle.storage.get_attestation(&public_key, EVENT_ID).unwrap().is_none());
+
+        // A tampered attestation cached before is dropped for the oracle's.
+        oracle.storage.save_attestation(EVENT_ID, &tampered).unwrap();
+        flaky.tampered.store(false, Ordering::SeqCst);
+        assert_eq!(oracle.get_attestation(EVENT_ID).unwrap(), flaky.attestation);
+        assert_eq!(
+            oracle.storage.get_attestation(&public_key, EVENT_ID).unwrap(),
+            Some(flaky.attestation.clone())
+        );
+    }
+
+    #[test]
+    fn announcements_are_shared_with_the_memory_cache() {
+        let (oracle, flaky) = caching_oracle(DEFAULT_ANNOUNCEMENT_MAX_AGE);
+        let memory = Arc::new(Mutex::new(AnnouncementCache::new(
+            10,
+            DEFAULT_ANNOUNCEMENT_MAX_AGE,
+        )));
+        let oracle = oracle.with_announcement_cache(memory.clone());
+        let public_key = flaky.get_public_key();
+
+        oracle.get_announcement(EVENT_ID).unwrap();
+        assert_eq!(
+            memory.lock().unwrap().get(&public_key, EVENT_ID),
+            Some(flaky.announcement.clone())
+        );
+
+        // Served from memory even when storage lost it.
+        let maturity = flaky.announcement.oracle_event.event_maturity_epoch as u64;
+        oracle.storage.evict_announcements(maturity + 1).unwrap();
+        oracle.get_announcement(EVENT_ID).unwrap();
+        assert_eq!(flaky.requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn announcements_expire_after_the_grace_period() {
+        // The event matures in the future, so it is cached even without a grace period until
+        // the clock passes its maturity.
+        let (oracle, flaky) = caching_oracle(Duration::ZERO);
+        oracle.get_announcement(EVENT_ID).unwrap();
+        oracle.get_announcement(EVENT_ID).unwrap();
+        assert_eq!(flaky.requests.load(Ordering::SeqCst), 1);
+        assert_eq!(oracle.evict_expired().unwrap(), 0);
+
+        let maturity = flaky.announcement.oracle_event.event_maturity_epoch as u64;
+        let storage = oracle.storage.clone();
+        assert_eq!(storage.evict_announcements(maturity + 1).unwrap(), 1);
+        oracle.get_announcement(EVENT_ID).unwrap();
+        assert_eq!(flaky.requests.load(Ordering::SeqCst), 2);
+    }
+}
@@ -1,4 +1,11 @@
+//! Client for a [kormir](https://github.com/bennyhodl/kormir) oracle server.
+//!
+//! Announcements and attestations are verified against the oracle's public key before they
+//! are returned. The client does not cache them: wrap it in a [super::CachingOracle], as the
+//! builder does, so repeated lookups, ex. on every periodic check, stay local.
+use super::verify::verify_attestation;
 use bitcoin::key::XOnlyPublicKey;
+use dlc::secp256k1_zkp::Secp256k1;
 use dlc_manager::error::Error;
 use dlc_messages::oracle_msgs::{OracleAnnouncement, OracleAttestation};
 use kormir::storage::OracleEventData;
@@ -6,14 +13,28 @@ use serde::Serialize;
 use std::str::FromStr;
 use uuid::Uuid;
 
-fn get<T>(host: &str, path: &str) -> anyhow::Result<T>
+fn get<T>(url: &str) -> Result<T, Error>
 where
     T: serde::de::DeserializeOwned,
 {
-    let url = format!("{}{}", host, path);
-    let request = reqwest::blocking::get(url)?.json::<T>()?;
+    reqwest::blocking::get(url)
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| Error::OracleError(format!("Request to {} failed. {}", url, e)))?
+        .json::<T>()
+        .map_err(|e| Error::OracleError(e.to_string()))
+}
 
-    Ok(request)
+async fn get_async<T>(url: &str) -> Result<T, Error>
+where
+    T: serde::de::DeserializeOwned,
+{
+    reqwest::get(url)
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| Error::OracleError(format!("Request to {} failed. {}", url, e)))?
+        .json::<T>()
+        .await
+        .map_err(|e| Error::OracleError(e.to_string()))
 }
 
 #[derive(Serialize)]
@@ -33,12 +54,17 @@ pub struct KormirOracleClient {
 impl KormirOracleClient {
     pub async fn new(host: &str) -> anyhow::Result<KormirOracleClient> {
         tracing::info!(host, "Connecting to Kormir oracle client.");
+        let host = host.trim_end_matches('/').to_string();
         let request: String = reqwest::get(format!("{host}/pubkey")).await?.json().await?;
         let pubkey = XOnlyPublicKey::from_str(&request)?;
         let client = reqwest::Client::new();
         tracing::info!(pubkey = pubkey.to_string(), "Connected to Kormir client.");
 
-        Ok(KormirOracleClient { pubkey, client, host: host.to_string() })
+        Ok(KormirOracleClient {
+            pubkey,
+            client,
+            host,
+        })
     }
 
     pub async fn get_pubkey(&self) -> anyhow::Result<XOnlyPublicKey> {
@@ -51,7 +77,6 @@ impl KormirOracleClient {
 
     pub async fn list_events(&self) -> anyhow::Result<Vec<OracleAnnouncement>> {
         let oracle_events: Vec<OracleEventData> = reqwest::get(format!("{}/list-events", self.host)).await?.json().await?;
-        println!("oracle_events: {:?}", oracle_events);
 
         Ok(oracle_events.iter().map(|event| event.announcement.clone()).collect::<Vec<OracleAnnouncement>>())
     }
@@ -72,6 +97,38 @@ impl KormirOracleClient {
 
         Ok(())
     }
+
+    fn announcement_url(&self, event_id: &str) -> String {
+        format!("{}/announcement/{}", self.host, event_id)
+    }
+
+    fn attestation_url(&self, event_id: &str) -> String {
+        format!("{}/attestation/{}", self.host, event_id)
+    }
+
+}
+
+/// Check that the announcement is for `event_id` and signed by `pubkey`.
+fn verify_announcement(
+    pubkey: &XOnlyPublicKey,
+    event_id: &str,
+    announcement: &OracleAnnouncement,
+) -> Result<(), Error> {
+    if announcement.oracle_public_key != *pubkey {
+        return Err(Error::OracleError(format!(
+            "Announcement for {} is from oracle {} instead of {}.",
+            event_id, announcement.oracle_public_key, pubkey
+        )));
+    }
+    if announcement.oracle_event.event_id != event_id {
+        return Err(Error::OracleError(format!(
+            "Requested announcement for {} but got {}.",
+            event_id, announcement.oracle_event.event_id
+        )));
+    }
+    announcement
+        .validate(&Secp256k1::verification_only())
+        .map_err(|e| Error::OracleError(format!("Invalid announcement signature. {}", e)))
 }
 
 impl dlc_manager::Oracle for KormirOracleClient {
@@ -81,19 +138,22 @@ impl dlc_manager::Oracle for KormirOracleClient {
 
     fn get_attestation(
         &self,
-        _event_id: &str,
+        event_id: &str,
     ) -> Result<dlc_messages::oracle_msgs::OracleAttestation, dlc_manager::error::Error> {
-        get::<OracleAttestation>(&self.host, "attestation")
-            .map_err(|_| dlc_manager::error::Error::OracleError("Could not get attestation".into()))
+        let announcement = self.get_announcement(event_id)?;
+        let attestation = get::<OracleAttestation>(&self.attestation_url(event_id))?;
+        verify_attestation(&announcement, &attestation)
+            .map_err(|e| Error::OracleError(format!("Invalid attestation. {}", e)))?;
+        Ok(attestation)
     }
 
     fn get_announcement(
         &self,
-        _event_id: &str,
+        event_id: &str,
     ) -> Result<dlc_messages::oracle_msgs::OracleAnnouncement, dlc_manager::error::Error> {
-        get::<OracleAnnouncement>(&self.host, "announcement").map_err(|_| {
-            dlc_manager::error::Error::OracleError("Could not get announcement".into())
-        })
+        let announcement = get::<OracleAnnouncement>(&self.announcement_url(event_id))?;
+        verify_announcement(&self.pubkey, event_id, &announcement)?;
+        Ok(announcement)
     }
 }
 
@@ -111,19 +171,145 @@ impl crate::DdkOracle for KormirOracleClient {
         &self,
         event_id: &str,
     ) -> Result<OracleAnnouncement, dlc_manager::error::Error> {
-        let announcements = reqwest::get(format!("{}/list-events", &self.host))
-            .await
-            .map_err(|_| Error::OracleError("Could not get announcements async.".into()))?
-            .json::<Vec<OracleEventData>>()
-            .await
-            .map_err(|_| Error::OracleError("Could not get announcements async.".into()))?;
-        
-        let event = announcements.iter().find(|event| event.announcement.oracle_event.event_id == event_id);
-
-        match event {
-            Some(event_data) => Ok(event_data.announcement.to_owned()),
-            None => return Err(Error::OracleError("No event found".to_string()))
+        let announcement = get_async::<OracleAnnouncement>(&self.announcement_url(event_id)).await?;
+        verify_announcement(&self.pubkey, event_id, &announcement)?;
+        Ok(announcement)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::bip32::Xpriv;
+    use bitcoin::secp256k1::SecretKey;
+    use bitcoin::Network;
+    use crate::oracle::CachingOracle;
+    use crate::storage::MemoryStorageProvider;
+    use dlc_manager::Oracle;
+    use kormir::storage::MemoryStorage;
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    /// Serve canned JSON bodies by path. Returns the host and the number of requests served.
+    fn serve(routes: HashMap<String, String>) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let host = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let served = requests.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut header = String::new();
+                while reader.read_line(&mut header).unwrap() > 2 {
+                    header.clear();
+                }
+                served.fetch_add(1, Ordering::SeqCst);
+
+                let path = request_line.split_whitespace().nth(1).unwrap_or_default();
+                let response = match routes.get(path) {
+                    Some(body) => format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    ),
+                    None => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+                };
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        (host, requests)
+    }
+
+    struct Event {
+        pubkey: XOnlyPublicKey,
+        announcement: OracleAnnouncement,
+        attestation: OracleAttestation,
+    }
+
+    fn signed_event(event_id: &str) -> Event {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let signing_key = SecretKey::from_slice(&[3u8; 32]).unwrap();
+        let nonce_xpriv = Xpriv::new_master(Network::Regtest, &[4u8; 32]).unwrap();
+        let oracle = kormir::Oracle::new(MemoryStorage::default(), signing_key, nonce_xpriv);
+        let announcement = runtime
+            .block_on(oracle.create_enum_event(
+                event_id.to_string(),
+                vec!["heads".into(), "tails".into()],
+                1_700_000_000,
+            ))
+            .unwrap();
+        let attestation = runtime
+            .block_on(oracle.sign_enum_event(event_id.to_string(), "heads".into()))
+            .unwrap();
+        Event {
+            pubkey: announcement.oracle_public_key,
+            announcement,
+            attestation,
         }
+    }
+
+    fn client(routes: HashMap<String, String>) -> (KormirOracleClient, Arc<AtomicUsize>) {
+        let (host, requests) = serve(routes);
+        let client = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(KormirOracleClient::new(&host))
+            .unwrap();
+        (client, requests)
+    }
+
+    #[test]
+    fn fetches_and_caches_announcement_and_attestation_when_wrapped() {
+        let event = signed_event("coin-flip");
+        let routes = HashMap::from([
+            ("/pubkey".to_string(), serde_json::to_string(&event.pubkey.to_string()).unwrap()),
+            ("/announcement/coin-flip".to_string(), serde_json::to_string(&event.announcement).unwrap()),
+            ("/attestation/coin-flip".to_string(), serde_json::to_string(&event.attestation).unwrap()),
+        ]);
+        let (client, requests) = client(routes);
+        // The event matured long ago, keep it cached regardless.
+        let client = CachingOracle::with_grace_period(
+            Arc::new(client),
+            Arc::new(MemoryStorageProvider::new()),
+            Duration::MAX,
+        );
+
+        assert_eq!(client.get_announcement("coin-flip").unwrap(), event.announcement);
+        assert_eq!(client.get_attestation("coin-flip").unwrap(), event.attestation);
+        let served = requests.load(Ordering::SeqCst);
+
+        // Repeat lookups are answered from the cache.
+        client.get_announcement("coin-flip").unwrap();
+        client.get_attestation("coin-flip").unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), served);
+    }
+
+    #[test]
+    fn rejects_invalid_signatures() {
+        let event = signed_event("coin-flip");
+        let pubkey = serde_json::to_string(&event.pubkey.to_string()).unwrap();
+
+        let mut tampered = event.announcement.clone();
+        tampered.oracle_event.event_maturity_epoch += 1;
+        let (client, _) = client(HashMap::from([
+            ("/pubkey".to_string(), pubkey.clone()),
+            ("/announcement/coin-flip".to_string(), serde_json::to_string(&tampered).unwrap()),
+        ]));
+        assert!(matches!(client.get_announcement("coin-flip"), Err(Error::OracleError(_))));
 
+        let mut wrong_outcome = event.attestation.clone();
+        wrong_outcome.outcomes = vec!["tails".into()];
+        let (client, _) = client(HashMap::from([
+            ("/pubkey".to_string(), pubkey),
+            ("/announcement/coin-flip".to_string(), serde_json::to_string(&event.announcement).unwrap()),
+            ("/attestation/coin-flip".to_string(), serde_json::to_string(&wrong_outcome).unwrap()),
+        ]));
+        assert!(matches!(client.get_attestation("coin-flip"), Err(Error::OracleError(_))));
     }
 }
@@ -1,5 +1,13 @@
+pub mod cache;
+pub mod caching;
 mod kormir;
 mod p2p_derivatives;
+pub mod set;
+pub mod verify;
 
 pub use kormir::KormirOracleClient;
 pub use p2p_derivatives::P2PDOracleClient;
+pub use cache::AnnouncementCache;
+pub use caching::CachingOracle;
+pub use set::OracleSet;
+pub use verify::{verify_attestation, AttestationError, VerifyingOracle};
@@ -0,0 +1,249 @@
+//! The oracles a node settles contracts with, and matching offer announcements to them.
+use crate::DdkOracle;
+use bitcoin::key::XOnlyPublicKey;
+use dlc_manager::contract::contract_input::ContractInput;
+use dlc_messages::oracle_msgs::OracleAnnouncement;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Oracles keyed by public key. The first oracle is the primary one, used when a lookup does
+/// not name an oracle.
+pub struct OracleSet<O> {
+    primary: XOnlyPublicKey,
+    oracles: HashMap<XOnlyPublicKey, Arc<O>>,
+}
+
+impl<O: DdkOracle> OracleSet<O> {
+    pub fn new(primary: Arc<O>) -> OracleSet<O> {
+        let public_key = primary.get_public_key();
+        OracleSet {
+            primary: public_key,
+            oracles: HashMap::from([(public_key, primary)]),
+        }
+    }
+
+    /// Add an oracle. Returns false if an oracle with the same public key is already set.
+    pub fn insert(&mut self, oracle: Arc<O>) -> bool {
+        let public_key = oracle.get_public_key();
+        if self.oracles.contains_key(&public_key) {
+            return false;
+        }
+        self.oracles.insert(public_key, oracle);
+        true
+    }
+
+    pub fn primary(&self) -> Arc<O> {
+        self.oracles[&self.primary].clone()
+    }
+
+    pub fn get(&self, public_key: &XOnlyPublicKey) -> Option<Arc<O>> {
+        self.oracles.get(public_key).cloned()
+    }
+
+    pub fn contains(&self, public_key: &XOnlyPublicKey) -> bool {
+        self.oracles.contains_key(public_key)
+    }
+
+    /// Every oracle, the primary first and the rest ordered by public key.
+    pub fn list(&self) -> Vec<Arc<O>> {
+        let mut public_keys = self.oracles.keys().copied().collect::<Vec<_>>();
+        public_keys.sort_by_key(|public_key| (*public_key != self.primary, *public_key));
+        public_keys
+            .iter()
+            .map(|public_key| self.oracles[public_key].clone())
+            .collect()
+    }
+
+    /// The map handed to the [dlc_manager::manager::Manager].
+    pub fn to_map(&self) -> HashMap<XOnlyPublicKey, Arc<O>> {
+        self.oracles.clone()
+    }
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum OracleInputError {
+    #[error("Oracle {0} is not one of the node's oracles, so the contract could not be settled.")]
+    UnknownOracle(XOnlyPublicKey),
+    #[error("No announcement of event {event_id} from oracle {public_key}.")]
+    MissingAnnouncement {
+        public_key: XOnlyPublicKey,
+        event_id: String,
+    },
+    #[error("Announcement of event {event_id} from oracle {public_key} is not used by the contract.")]
+    UnusedAnnouncement {
+        public_key: XOnlyPublicKey,
+        event_id: String,
+    },
+    #[error("Threshold {threshold} is not between one and the {oracles} oracles of the contract.")]
+    InvalidThreshold { threshold: u16, oracles: usize },
+}
+
+/// Check that `announcements` cover every oracle referenced by `contract_input` and group them
+/// per contract info, in the order of each info's oracle public keys.
+///
+/// Every referenced oracle must be known, otherwise the node could not fetch the attestation
+/// to settle.
+pub fn announcements_for_input(
+    contract_input: &ContractInput,
+    announcements: &[OracleAnnouncement],
+    is_known: impl Fn(&XOnlyPublicKey) -> bool,
+) -> Result<Vec<Vec<OracleAnnouncement>>, OracleInputError> {
+    let mut used = vec![false; announcements.len()];
+    let mut grouped = Vec::with_capacity(contract_input.contract_infos.len());
+
+    for info in &contract_input.contract_infos {
+        let oracles = &info.oracles;
+        if oracles.threshold == 0 || oracles.threshold as usize > oracles.public_keys.len() {
+            return Err(OracleInputError::InvalidThreshold {
+                threshold: oracles.threshold,
+                oracles: oracles.public_keys.len(),
+            });
+        }
+
+        let mut group = Vec::with_capacity(oracles.public_keys.len());
+        for public_key in &oracles.public_keys {
+            if !is_known(public_key) {
+                return Err(OracleInputError::UnknownOracle(*public_key));
+            }
+            let index = announcements
+                .iter()
+                .position(|a| {
+                    a.oracle_public_key == *public_key && a.oracle_event.event_id == oracles.event_id
+                })
+                .ok_or_else(|| OracleInputError::MissingAnnouncement {
+                    public_key: *public_key,
+                    event_id: oracles.event_id.clone(),
+                })?;
+            used[index] = true;
+            group.push(announcements[index].clone());
+        }
+        grouped.push(group);
+    }
+
+    if let Some(index) = used.iter().position(|used| !used) {
+        let unused = &announcements[index];
+        return Err(OracleInputError::UnusedAnnouncement {
+            public_key: unused.oracle_public_key,
+            event_id: unused.oracle_event.event_id.clone(),
+        });
+    }
+
+    Ok(grouped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::bip32::Xpriv;
+    use bitcoin::secp256k1::{Secp256k1, SecretKey};
+    use bitcoin::Network;
+    use dlc::{EnumerationPayout, Payout};
+    use dlc_manager::contract::contract_input::{ContractInputInfo, OracleInput};
+    use dlc_manager::contract::enum_descriptor::EnumDescriptor;
+    use dlc_manager::contract::ContractDescriptor;
+    use kormir::storage::MemoryStorage;
+
+    const EVENT_ID: &str = "multi-oracle";
+
+    fn announcement(seed: u8) -> OracleAnnouncement {
+        let signing_key = SecretKey::from_slice(&[seed; 32]).unwrap();
+        let nonce_xpriv = Xpriv::new_master(Network::Regtest, &[seed; 32]).unwrap();
+        let oracle = kormir::Oracle::new(MemoryStorage::default(), signing_key, nonce_xpriv);
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(oracle.create_enum_event(
+                EVENT_ID.to_string(),
+                vec!["yes".into(), "no".into()],
+                1_720_000_000,
+            ))
+            .unwrap()
+    }
+
+    fn two_of_three(public_keys: Vec<XOnlyPublicKey>) -> ContractInput {
+        let outcome_payouts = [("yes", 100_000), ("no", 0)]
+            .into_iter()
+            .map(|(outcome, offer)| EnumerationPayout {
+                outcome: outcome.to_string(),
+                payout: Payout {
+                    offer,
+                    accept: 100_000 - offer,
+                },
+            })
+            .collect();
+        ContractInput {
+            offer_collateral: 50_000,
+            accept_collateral: 50_000,
+            fee_rate: 2,
+            contract_infos: vec![ContractInputInfo {
+                contract_descriptor: ContractDescriptor::Enum(EnumDescriptor { outcome_payouts }),
+                oracles: OracleInput {
+                    public_keys,
+                    event_id: EVENT_ID.to_string(),
+                    threshold: 2,
+                },
+            }],
+        }
+    }
+
+    #[test]
+    fn two_of_three_offer_groups_announcements() {
+        let announcements = (1..=3).map(announcement).collect::<Vec<_>>();
+        let public_keys = announcements
+            .iter()
+            .map(|a| a.oracle_public_key)
+            .collect::<Vec<_>>();
+        let input = two_of_three(public_keys);
+        input.validate().unwrap();
+
+        // Announcements are ordered like the public keys of the input, not like the caller's.
+        let reversed = announcements.iter().rev().cloned().collect::<Vec<_>>();
+        let grouped = announcements_for_input(&input, &reversed, |_| true).unwrap();
+        assert_eq!(grouped, vec![announcements.clone()]);
+        for announcement in &grouped[0] {
+            announcement.validate(&Secp256k1::verification_only()).unwrap();
+        }
+    }
+
+    #[test]
+    fn every_oracle_needs_an_announcement() {
+        let announcements = (1..=3).map(announcement).collect::<Vec<_>>();
+        let public_keys = announcements
+            .iter()
+            .map(|a| a.oracle_public_key)
+            .collect::<Vec<_>>();
+        let input = two_of_three(public_keys.clone());
+
+        assert_eq!(
+            announcements_for_input(&input, &announcements[..2], |_| true),
+            Err(OracleInputError::MissingAnnouncement {
+                public_key: public_keys[2],
+                event_id: EVENT_ID.to_string(),
+            })
+        );
+        assert_eq!(
+            announcements_for_input(&input, &announcements, |key| *key != public_keys[1]),
+            Err(OracleInputError::UnknownOracle(public_keys[1]))
+        );
+
+        let extra = announcement(4);
+        let mut with_extra = announcements.clone();
+        with_extra.push(extra.clone());
+        assert_eq!(
+            announcements_for_input(&input, &with_extra, |_| true),
+            Err(OracleInputError::UnusedAnnouncement {
+                public_key: extra.oracle_public_key,
+                event_id: EVENT_ID.to_string(),
+            })
+        );
+
+        let mut too_high = input.clone();
+        too_high.contract_infos[0].oracles.threshold = 4;
+        assert_eq!(
+            announcements_for_input(&too_high, &announcements, |_| true),
+            Err(OracleInputError::InvalidThreshold {
+                threshold: 4,
+                oracles: 3,
+            })
+        );
+    }
+}
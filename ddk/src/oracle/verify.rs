@@ -0,0 +1,492 @@
+//! Check oracle attestations against the announcement a contract was built on before a CET is
+//! broadcast. A wrong or malicious oracle client could otherwise make the node settle on an
+//! outcome the oracle never signed, and the contract can still be refunded instead.
+use crate::events::{DdkEvent, EventBus};
+use crate::status::StatusTracker;
+use crate::DdkStorage;
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::key::XOnlyPublicKey;
+use bitcoin::secp256k1::{Message, Secp256k1};
+use dlc_manager::contract::signed_contract::SignedContract;
+use dlc_manager::contract::Contract;
+use dlc_manager::error::Error as ManagerError;
+use dlc_manager::ContractId;
+use dlc_messages::oracle_msgs::{EventDescriptor, OracleAnnouncement, OracleAttestation};
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum AttestationError {
+    #[error("Attestation is from oracle {attested} but the event was announced by {announced}.")]
+    WrongOracle {
+        announced: XOnlyPublicKey,
+        attested: XOnlyPublicKey,
+    },
+    #[error("Event has {expected} outcomes but the attestation has {outcomes}.")]
+    OutcomeCount { expected: usize, outcomes: usize },
+    #[error("Announcement has {nonces} nonces for {expected} outcomes.")]
+    NonceCount { expected: usize, nonces: usize },
+    #[error("Attestation has {signatures} signatures for {outcomes} outcomes.")]
+    SignatureCount { signatures: usize, outcomes: usize },
+    #[error("Outcome {0} is not one of the announced outcomes.")]
+    UnknownOutcome(String),
+    #[error("Sign of the outcome is {0} instead of + or -.")]
+    InvalidSign(String),
+    #[error("Digit {index} is {digit}, which is not a base {base} digit.")]
+    DigitOutOfRange {
+        index: usize,
+        digit: String,
+        base: u16,
+    },
+    #[error("Outcome {index} was not signed with the announced nonce.")]
+    NonceMismatch { index: usize },
+    #[error("Signature of outcome {index} is invalid.")]
+    InvalidSignature { index: usize },
+}
+
+/// Check that `attestation` signs outcomes of the announced event: one outcome per nonce, each
+/// a valid outcome of the event descriptor and signed by the oracle with the announced nonce.
+pub fn verify_attestation(
+    announcement: &OracleAnnouncement,
+    attestation: &OracleAttestation,
+) -> Result<(), AttestationError> {
+    if attestation.oracle_public_key != announcement.oracle_public_key {
+        return Err(AttestationError::WrongOracle {
+            announced: announcement.oracle_public_key,
+            attested: attestation.oracle_public_key,
+        });
+    }
+
+    let event = &announcement.oracle_event;
+    let expected = match &event.event_descriptor {
+        EventDescriptor::EnumEvent(_) => 1,
+        EventDescriptor::DigitDecompositionEvent(d) => d.nb_digits as usize + d.is_signed as usize,
+    };
+    if attestation.outcomes.len() != expected {
+        return Err(AttestationError::OutcomeCount {
+            expected,
+            outcomes: attestation.outcomes.len(),
+        });
+    }
+    if event.oracle_nonces.len() != expected {
+        return Err(AttestationError::NonceCount {
+            expected,
+            nonces: event.oracle_nonces.len(),
+        });
+    }
+    if attestation.signatures.len() != attestation.outcomes.len() {
+        return Err(AttestationError::SignatureCount {
+            signatures: attestation.signatures.len(),
+            outcomes: attestation.outcomes.len(),
+        });
+    }
+    verify_outcomes(&event.event_descriptor, &attestation.outcomes)?;
+
+    let secp = Secp256k1::verification_only();
+    for (index, ((signature, outcome), nonce)) in attestation
+        .signatures
+        .iter()
+        .zip(&attestation.outcomes)
+        .zip(&event.oracle_nonces)
+        .enumerate()
+    {
+        if signature.as_ref()[..32] != nonce.serialize() {
+            return Err(AttestationError::NonceMismatch { index });
+        }
+        let message = Message::from_digest(sha256::Hash::hash(outcome.as_bytes()).to_byte_array());
+        secp.verify_schnorr(signature, &message, &attestation.oracle_public_key)
+            .map_err(|_| AttestationError::InvalidSignature { index })?;
+    }
+    Ok(())
+}
+
+/// Enum outcomes must be announced. Numeric outcomes are an optional sign followed by digits
+/// of the announced base.
+fn verify_outcomes(
+    descriptor: &EventDescriptor,
+    outcomes: &[String],
+) -> Result<(), AttestationError> {
+    match descriptor {
+        EventDescriptor::EnumEvent(e) => match outcomes.iter().find(|o| !e.outcomes.contains(o)) {
+            Some(outcome) => Err(AttestationError::UnknownOutcome(outcome.clone())),
+            None => Ok(()),
+        },
+        EventDescriptor::DigitDecompositionEvent(d) => {
+            let digits = if d.is_signed {
+                if outcomes[0] != "+" && outcomes[0] != "-" {
+                    return Err(AttestationError::InvalidSign(outcomes[0].clone()));
+                }
+                &outcomes[1..]
+            } else {
+                outcomes
+            };
+            for (index, digit) in digits.iter().enumerate() {
+                if !matches!(digit.parse::<u16>(), Ok(value) if value < d.base) {
+                    return Err(AttestationError::DigitOutOfRange {
+                        index,
+                        digit: digit.clone(),
+                        base: d.base,
+                    });
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Announcements from `oracle` in the contract. An attestation does not name its event, so it
+/// is valid if it verifies against one of them.
+fn oracle_announcements<'a>(
+    contract: &'a SignedContract,
+    oracle: &'a XOnlyPublicKey,
+) -> impl Iterator<Item = &'a OracleAnnouncement> {
+    contract
+        .accepted_contract
+        .offered_contract
+        .contract_info
+        .iter()
+        .flat_map(|info| info.oracle_announcements.iter())
+        .filter(move |announcement| announcement.oracle_public_key == *oracle)
+}
+
+/// Verify attestations given to close `contract`. Returns the oracle, event id, and error of the
+/// first attestation that does not verify against any announcement of its oracle.
+pub(crate) fn verify_contract_attestations(
+    contract: &SignedContract,
+    attestations: &[OracleAttestation],
+) -> Result<(), (XOnlyPublicKey, String, AttestationError)> {
+    for attestation in attestations {
+        let mut first_error = None;
+        for announcement in oracle_announcements(contract, &attestation.oracle_public_key) {
+            match verify_attestation(announcement, attestation) {
+                Ok(()) => {
+                    first_error = None;
+                    break;
+                }
+                Err(e) if first_error.is_none() => {
+                    first_error = Some((
+                        attestation.oracle_public_key,
+                        announcement.oracle_event.event_id.clone(),
+                        e,
+                    ))
+                }
+                Err(_) => {}
+            }
+        }
+        if let Some(error) = first_error {
+            return Err(error);
+        }
+    }
+    Ok(())
+}
+
+/// Wraps the oracle clients handed to the [dlc_manager::manager::Manager] so the attestations
+/// it settles with are verified against the announcements of the confirmed contracts. Invalid
+/// attestations are reported as not available, so the contract stays open until it is attested
+/// correctly or refunded, and a [DdkEvent::InvalidAttestation] is emitted once per contract.
+pub struct VerifyingOracle<O, S> {
+    oracle: Arc<O>,
+    storage: Arc<S>,
+    events: Arc<EventBus>,
+    status: Arc<StatusTracker>,
+    reported: Mutex<HashSet<(ContractId, String)>>,
+}
+
+impl<O: dlc_manager::Oracle, S: DdkStorage> VerifyingOracle<O, S> {
+    pub fn new(
+        oracle: Arc<O>,
+        storage: Arc<S>,
+        events: Arc<EventBus>,
+        status: Arc<StatusTracker>,
+    ) -> VerifyingOracle<O, S> {
+        VerifyingOracle {
+            oracle,
+            storage,
+            events,
+            status,
+            reported: Mutex::new(HashSet::new()),
+        }
+    }
+
+    fn report(&self, contract_id: ContractId, event_id: &str, error: &AttestationError) {
+        tracing::error!(
+            contract_id = hex::encode(contract_id),
+            oracle = self.oracle.get_public_key().to_string(),
+            event_id,
+            error = error.to_string(),
+            "Refusing to settle contract with an invalid attestation."
+        );
+        let first = self
+            .reported
+            .lock()
+            .unwrap()
+            .insert((contract_id, event_id.to_string()));
+        if first {
+            self.events.emit(DdkEvent::InvalidAttestation {
+                contract_id,
+                oracle: self.oracle.get_public_key(),
+                event_id: event_id.to_string(),
+                reason: error.to_string(),
+            });
+        }
+    }
+}
+
+impl<O: dlc_manager::Oracle, S: DdkStorage> dlc_manager::Oracle for VerifyingOracle<O, S> {
+    fn get_public_key(&self) -> XOnlyPublicKey {
+        self.oracle.get_public_key()
+    }
+
+    fn get_announcement(&self, event_id: &str) -> Result<OracleAnnouncement, ManagerError> {
+        let announcement = self.oracle.get_announcement(event_id)?;
+        self.status.record_oracle_contact();
+        Ok(announcement)
+    }
+
+    fn get_attestation(&self, event_id: &str) -> Result<OracleAttestation, ManagerError> {
+        let attestation = self.oracle.get_attestation(event_id)?;
+        self.status.record_oracle_contact();
+        let public_key = self.oracle.get_public_key();
+        let contracts = self.storage.get_contracts()?;
+        let announced = contracts
+            .iter()
+            .filter_map(|contract| match contract {
+                Contract::Confirmed(signed) => Some((contract.get_id(), signed)),
+                _ => None,
+            })
+            .flat_map(|(contract_id, signed)| {
+                oracle_announcements(signed, &public_key)
+                    .filter(|a| a.oracle_event.event_id == event_id)
+                    .map(move |a| (contract_id, a))
+            })
+            .collect::<Vec<_>>();
+
+        let mut invalid = None;
+        for (contract_id, announcement) in &announced {
+            if let Err(e) = verify_attestation(announcement, &attestation) {
+                self.report(*contract_id, event_id, &e);
+                invalid = Some(e);
+            }
+        }
+        if announced.is_empty() {
+            invalid =
+                verify_attestation(&self.oracle.get_announcement(event_id)?, &attestation).err();
+        }
+        match invalid {
+            Some(e) => Err(ManagerError::OracleError(format!(
+                "Invalid attestation of event {}. {}",
+                event_id, e
+            ))),
+            None => Ok(attestation),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::key::Keypair;
+    use bitcoin::secp256k1::{schnorr::Signature, SecretKey};
+    use dlc::secp_utils::schnorrsig_sign_with_nonce;
+    use dlc_messages::oracle_msgs::{
+        DigitDecompositionEventDescriptor, EnumEventDescriptor, OracleEvent,
+    };
+
+    const ORACLE_KEY: [u8; 32] = [1u8; 32];
+
+    fn nonce(index: usize) -> [u8; 32] {
+        [index as u8 + 10; 32]
+    }
+
+    fn announcement(descriptor: EventDescriptor, nonces: usize) -> OracleAnnouncement {
+        let secp = Secp256k1::new();
+        let keypair = Keypair::from_seckey_slice(&secp, &ORACLE_KEY).unwrap();
+        let oracle_nonces = (0..nonces)
+            .map(|i| {
+                Keypair::from_seckey_slice(&secp, &nonce(i))
+                    .unwrap()
+                    .x_only_public_key()
+                    .0
+            })
+            .collect();
+        OracleAnnouncement {
+            announcement_signature: Signature::from_slice(&[1u8; 64]).unwrap(),
+            oracle_public_key: keypair.x_only_public_key().0,
+            oracle_event: OracleEvent {
+                oracle_nonces,
+                event_maturity_epoch: 1_700_000_000,
+                event_descriptor: descriptor,
+                event_id: "event".into(),
+            },
+        }
+    }
+
+    fn attest(outcomes: &[&str]) -> OracleAttestation {
+        let secp = Secp256k1::new();
+        let keypair = Keypair::from_seckey_slice(&secp, &ORACLE_KEY).unwrap();
+        let signatures = outcomes
+            .iter()
+            .enumerate()
+            .map(|(i, outcome)| {
+                let message =
+                    Message::from_digest(sha256::Hash::hash(outcome.as_bytes()).to_byte_array());
+                schnorrsig_sign_with_nonce(&secp, &message, &keypair, &nonce(i))
+            })
+            .collect();
+        OracleAttestation {
+            oracle_public_key: keypair.x_only_public_key().0,
+            signatures,
+            outcomes: outcomes.iter().map(|o| o.to_string()).collect(),
+        }
+    }
+
+    fn enum_announcement() -> OracleAnnouncement {
+        announcement(
+            EventDescriptor::EnumEvent(EnumEventDescriptor {
+                outcomes: vec!["heads".into(), "tails".into()],
+            }),
+            1,
+        )
+    }
+
+    fn numeric_announcement() -> OracleAnnouncement {
+        announcement(
+            EventDescriptor::DigitDecompositionEvent(DigitDecompositionEventDescriptor {
+                base: 2,
+                is_signed: true,
+                unit: "sats".into(),
+                precision: 0,
+                nb_digits: 3,
+            }),
+            4,
+        )
+    }
+
+    #[test]
+    fn valid_attestations_verify() {
+        assert_eq!(
+            verify_attestation(&enum_announcement(), &attest(&["tails"])),
+            Ok(())
+        );
+        assert_eq!(
+            verify_attestation(&numeric_announcement(), &attest(&["-", "1", "0", "1"])),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn attestation_from_another_oracle() {
+        let mut attestation = attest(&["heads"]);
+        let other = Keypair::from_seckey_slice(&Secp256k1::new(), &[2u8; 32]).unwrap();
+        attestation.oracle_public_key = other.x_only_public_key().0;
+        assert!(matches!(
+            verify_attestation(&enum_announcement(), &attestation),
+            Err(AttestationError::WrongOracle { .. })
+        ));
+    }
+
+    #[test]
+    fn outcome_count_must_match_the_descriptor() {
+        assert_eq!(
+            verify_attestation(&enum_announcement(), &attest(&["heads", "tails"])),
+            Err(AttestationError::OutcomeCount {
+                expected: 1,
+                outcomes: 2
+            })
+        );
+        assert_eq!(
+            verify_attestation(&numeric_announcement(), &attest(&["+", "1", "0"])),
+            Err(AttestationError::OutcomeCount {
+                expected: 4,
+                outcomes: 3
+            })
+        );
+
+        let mut announcement = numeric_announcement();
+        announcement.oracle_event.oracle_nonces.pop();
+        assert_eq!(
+            verify_attestation(&announcement, &attest(&["+", "1", "0", "1"])),
+            Err(AttestationError::NonceCount {
+                expected: 4,
+                nonces: 3
+            })
+        );
+
+        let mut attestation = attest(&["+", "1", "0", "1"]);
+        attestation.signatures.pop();
+        assert_eq!(
+            verify_attestation(&numeric_announcement(), &attestation),
+            Err(AttestationError::SignatureCount {
+                signatures: 3,
+                outcomes: 4
+            })
+        );
+    }
+
+    #[test]
+    fn outcomes_must_be_announced() {
+        assert_eq!(
+            verify_attestation(&enum_announcement(), &attest(&["edge"])),
+            Err(AttestationError::UnknownOutcome("edge".into()))
+        );
+        assert_eq!(
+            verify_attestation(&numeric_announcement(), &attest(&["1", "1", "0", "1"])),
+            Err(AttestationError::InvalidSign("1".into()))
+        );
+        assert_eq!(
+            verify_attestation(&numeric_announcement(), &attest(&["+", "1", "2", "1"])),
+            Err(AttestationError::DigitOutOfRange {
+                index: 1,
+                digit: "2".into(),
+                base: 2
+            })
+        );
+    }
+
+    #[test]
+    fn signatures_must_use_the_announced_nonces() {
+        let mut attestation = attest(&["+", "1", "0", "1"]);
+        attestation.signatures.swap(1, 2);
+        assert_eq!(
+            verify_attestation(&numeric_announcement(), &attestation),
+            Err(AttestationError::NonceMismatch { index: 1 })
+        );
+    }
+
+    #[test]
+    fn contract_attestations_are_verified_against_its_announcements() {
+        use dlc_manager::contract::ser::Serializable;
+        let mut cursor = ::lightning::io::Cursor::new(include_bytes!(
+            "../../tests/data/dlc_storage/sled/Confirmed"
+        ));
+        let contract = SignedContract::deserialize(&mut cursor).unwrap();
+        let announcement =
+            &contract.accepted_contract.offered_contract.contract_info[0].oracle_announcements[0];
+        let nonces = announcement.oracle_event.oracle_nonces.len();
+        let forged = OracleAttestation {
+            oracle_public_key: announcement.oracle_public_key,
+            signatures: vec![Signature::from_slice(&[1u8; 64]).unwrap(); nonces],
+            outcomes: vec!["0".into(); nonces],
+        };
+
+        let (oracle, event_id, _) = verify_contract_attestations(&contract, &[forged]).unwrap_err();
+        assert_eq!(oracle, announcement.oracle_public_key);
+        assert_eq!(event_id, announcement.oracle_event.event_id);
+        // Attestations from oracles the contract does not use are left to the manager.
+        assert_eq!(
+            verify_contract_attestations(&contract, &[attest(&["heads"])]),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn signatures_must_sign_the_outcome() {
+        // Signed heads, claims tails.
+        let mut attestation = attest(&["heads"]);
+        attestation.outcomes = vec!["tails".into()];
+        assert_eq!(
+            verify_attestation(&enum_announcement(), &attestation),
+            Err(AttestationError::InvalidSignature { index: 0 })
+        );
+    }
+}
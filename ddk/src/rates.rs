@@ -0,0 +1,278 @@
+//! Exchange rates for annotating contract amounts with fiat values.
+use crate::DdkStorage;
+use dlc_manager::ContractId;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::runtime::Handle;
+use tokio::task::JoinHandle;
+
+const SATS_PER_BTC: f64 = 100_000_000.0;
+
+/// Provides the price of one bitcoin in a fiat currency.
+///
+/// Rate lookups are best effort. A failure never blocks a contract flow, the rate is left empty.
+pub trait RateProvider: std::fmt::Debug + std::marker::Send + std::marker::Sync + 'static {
+    fn rate(&self, currency: &str) -> anyhow::Result<f64>;
+}
+
+/// Default provider that does not record any rates.
+#[derive(Debug, Clone, Default)]
+pub struct NoopRateProvider;
+
+impl RateProvider for NoopRateProvider {
+    fn rate(&self, _currency: &str) -> anyhow::Result<f64> {
+        Err(anyhow::anyhow!("No rate provider configured."))
+    }
+}
+
+#[derive(Deserialize)]
+struct RateResponse {
+    rate: f64,
+}
+
+/// Rate provider calling an HTTP endpoint. Requests `GET {host}/{currency}` and expects
+/// a JSON response of `{ "rate": <price of one bitcoin> }`.
+#[derive(Debug, Clone)]
+pub struct HttpRateProvider {
+    host: String,
+    client: reqwest::blocking::Client,
+}
+
+impl HttpRateProvider {
+    pub fn new(host: &str) -> anyhow::Result<HttpRateProvider> {
+        let client = reqwest::blocking::Client::builder()
+            .timeout(Duration::from_secs(5))
+            .build()?;
+        Ok(HttpRateProvider {
+            host: host.trim_end_matches('/').to_string(),
+            client,
+        })
+    }
+}
+
+impl RateProvider for HttpRateProvider {
+    fn rate(&self, currency: &str) -> anyhow::Result<f64> {
+        let response = self
+            .client
+            .get(format!("{}/{}", self.host, currency))
+            .send()?
+            .error_for_status()?
+            .json::<RateResponse>()?;
+        Ok(response.rate)
+    }
+}
+
+/// When a rate was captured for a contract.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RatePoint {
+    /// The offer was accepted.
+    Accept,
+    /// The contract was closed.
+    Close,
+}
+
+/// Exchange rates recorded for a contract.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ContractRates {
+    pub currency: String,
+    pub at_accept: Option<f64>,
+    pub at_close: Option<f64>,
+}
+
+impl ContractRates {
+    /// Fiat value of an amount in sats at the rate recorded when the offer was accepted.
+    pub fn fiat_at_accept(&self, sats: u64) -> Option<f64> {
+        self.at_accept.map(|rate| to_fiat(sats, rate))
+    }
+
+    /// Fiat value of an amount in sats at the rate recorded when the contract closed.
+    pub fn fiat_at_close(&self, sats: u64) -> Option<f64> {
+        self.at_close.map(|rate| to_fiat(sats, rate))
+    }
+
+    /// Fiat value of a profit or loss in sats at the rate recorded when the contract closed.
+    pub fn pnl_at_close(&self, pnl: i64) -> Option<f64> {
+        self.at_close.map(|rate| pnl as f64 / SATS_PER_BTC * rate)
+    }
+}
+
+/// Records exchange rates for contracts on the node's runtime, so a slow or failing provider
+/// never holds up the contract flow that asked for the rate.
+#[derive(Debug, Clone)]
+pub struct RateRecorder {
+    provider: Arc<dyn RateProvider>,
+    currency: String,
+    runtime: Handle,
+}
+
+impl RateRecorder {
+    pub fn new(provider: Arc<dyn RateProvider>, currency: String, runtime: Handle) -> RateRecorder {
+        RateRecorder {
+            provider,
+            currency,
+            runtime,
+        }
+    }
+
+    /// Record the rate for `contract_id` at `point` in the background. Failures are logged
+    /// and leave the rate empty.
+    pub fn record<S: DdkStorage>(&self, storage: Arc<S>, contract_id: ContractId, point: RatePoint) -> JoinHandle<()> {
+        let recorder = self.clone();
+        self.runtime
+            .spawn_blocking(move || recorder.capture(storage.as_ref(), contract_id, point))
+    }
+
+    /// Look up the rate and store it. A close rate is only recorded once, the first close
+    /// seen is the one the contract settled at.
+    fn capture<S: DdkStorage>(&self, storage: &S, contract_id: ContractId, point: RatePoint) {
+        let mut rates = match storage.get_contract_rates(&contract_id) {
+            Ok(rates) => rates.unwrap_or_default(),
+            Err(e) => {
+                tracing::warn!(error = e.to_string(), "Could not read contract rates.");
+                return;
+            }
+        };
+        if point == RatePoint::Close && rates.at_close.is_some() {
+            return;
+        }
+
+        let rate = match self.provider.rate(&self.currency) {
+            Ok(rate) => rate,
+            Err(e) => {
+                tracing::debug!(error = e.to_string(), "Could not get exchange rate.");
+                return;
+            }
+        };
+        rates.currency = self.currency.clone();
+        match point {
+            RatePoint::Accept => rates.at_accept = Some(rate),
+            RatePoint::Close => rates.at_close = Some(rate),
+        }
+
+        if let Err(e) = storage.save_contract_rates(&contract_id, rates) {
+            tracing::warn!(error = e.to_string(), "Could not save contract rates.");
+        }
+    }
+}
+
+/// Convert sats to fiat with the price of one bitcoin.
+pub fn to_fiat(sats: u64, rate: f64) -> f64 {
+    sats as f64 / SATS_PER_BTC * rate
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorageProvider;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc::{channel, Receiver};
+    use std::sync::Mutex;
+    use std::time::Instant;
+
+    /// Answers every lookup with the next of its rates.
+    #[derive(Debug)]
+    struct StubRateProvider {
+        rates: Vec<f64>,
+        lookups: AtomicUsize,
+    }
+
+    impl RateProvider for StubRateProvider {
+        fn rate(&self, _currency: &str) -> anyhow::Result<f64> {
+            let lookup = self.lookups.fetch_add(1, Ordering::SeqCst);
+            self.rates
+                .get(lookup)
+                .copied()
+                .ok_or_else(|| anyhow::anyhow!("No more rates."))
+        }
+    }
+
+    /// Fails every lookup once it is released.
+    #[derive(Debug)]
+    struct StalledRateProvider {
+        release: Mutex<Receiver<()>>,
+    }
+
+    impl RateProvider for StalledRateProvider {
+        fn rate(&self, _currency: &str) -> anyhow::Result<f64> {
+            let _ = self.release.lock().unwrap().recv();
+            Err(anyhow::anyhow!("Rate service unavailable."))
+        }
+    }
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn noop_provider_has_no_rate() {
+        assert!(NoopRateProvider.rate("USD").is_err())
+    }
+
+    #[test]
+    fn fiat_uses_recorded_rates() {
+        let rates = ContractRates {
+            currency: "USD".into(),
+            at_accept: Some(50_000.0),
+            at_close: None,
+        };
+        assert_eq!(rates.fiat_at_accept(100_000), Some(50.0));
+        assert_eq!(rates.fiat_at_close(100_000), None);
+        assert_eq!(rates.pnl_at_close(-100_000), None);
+    }
+
+    #[test]
+    fn accept_and_close_rates_are_recorded() {
+        let runtime = runtime();
+        let storage = Arc::new(MemoryStorageProvider::new());
+        let provider = Arc::new(StubRateProvider {
+            rates: vec![50_000.0, 60_000.0, 70_000.0],
+            lookups: AtomicUsize::new(0),
+        });
+        let recorder = RateRecorder::new(provider.clone(), "USD".into(), runtime.handle().clone());
+        let contract_id = [1u8; 32];
+
+        for point in [RatePoint::Accept, RatePoint::Close, RatePoint::Close] {
+            runtime
+                .block_on(recorder.record(storage.clone(), contract_id, point))
+                .unwrap();
+        }
+
+        let rates = storage.get_contract_rates(&contract_id).unwrap().unwrap();
+        assert_eq!(
+            rates,
+            ContractRates {
+                currency: "USD".into(),
+                at_accept: Some(50_000.0),
+                at_close: Some(60_000.0),
+            }
+        );
+        assert_eq!(rates.pnl_at_close(-100_000), Some(-60.0));
+        // The second close is not looked up.
+        assert_eq!(provider.lookups.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn failing_provider_does_not_block() {
+        let runtime = runtime();
+        let storage = Arc::new(MemoryStorageProvider::new());
+        let (release, released) = channel();
+        let provider = Arc::new(StalledRateProvider {
+            release: Mutex::new(released),
+        });
+        let recorder = RateRecorder::new(provider, "USD".into(), runtime.handle().clone());
+        let contract_id = [2u8; 32];
+
+        let started = Instant::now();
+        let recording = recorder.record(storage.clone(), contract_id, RatePoint::Close);
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert!(!recording.is_finished());
+
+        release.send(()).unwrap();
+        runtime.block_on(recording).unwrap();
+        assert_eq!(storage.get_contract_rates(&contract_id).unwrap(), None);
+    }
+}
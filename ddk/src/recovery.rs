@@ -0,0 +1,231 @@
+//! Recover a node from its seed after the data directory is lost.
+//!
+//! Contract state cannot be rebuilt from the chain. The report lists the wallet's UTXOs and
+//! the outputs of wallet funded transactions that look like DLC funding outputs, so the
+//! operator can find the contracts that need to be settled by hand.
+use bdk_chain::ChainPosition;
+use bdk_wallet::Wallet;
+use bitcoin::{Amount, OutPoint, Txid};
+use dlc_manager::ContractId;
+use serde::{Deserialize, Serialize};
+
+/// Default number of unused scripts in a row that ends a recovery scan.
+pub const DEFAULT_RECOVERY_STOP_GAP: usize = 50;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecoveredUtxo {
+    pub outpoint: OutPoint,
+    pub value: Amount,
+    pub confirmation_height: Option<u32>,
+}
+
+/// A P2WSH output the wallet does not own on a transaction our inputs paid into. DLC funding
+/// outputs are 2-of-2 P2WSH multisigs, so each one may be an open contract.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PotentialContract {
+    pub funding_outpoint: OutPoint,
+    pub value: Amount,
+    /// What our inputs paid into the funding transaction.
+    pub our_contribution: Amount,
+    pub confirmation_height: Option<u32>,
+    /// Transaction spending the output, when the wallet saw one. Outputs that are still
+    /// unspent may belong to contracts that are still open.
+    pub spent_by: Option<Txid>,
+    /// Contract the funding transaction was recorded for, when that record survived.
+    pub contract_id: Option<ContractId>,
+}
+
+/// What the chain scan found.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WalletRecovery {
+    pub utxos: Vec<RecoveredUtxo>,
+    pub potential_contracts: Vec<PotentialContract>,
+}
+
+/// Signer keys checked and re-derived from the seed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignerRecovery {
+    /// Stored signers that match the seed.
+    pub verified: usize,
+    /// Signers written back to storage, either missing or not matching the seed.
+    pub restored: usize,
+    /// Hex key ids that could not be re-derived within the searched indexes.
+    pub unrecovered: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecoveryReport {
+    /// Funding transactions confirmed before this height are not reported.
+    pub birthday_height: u32,
+    pub stop_gap: usize,
+    pub utxos: Vec<RecoveredUtxo>,
+    pub potential_contracts: Vec<PotentialContract>,
+    pub signers: SignerRecovery,
+}
+
+/// UTXOs and potential funding outputs of a scanned wallet.
+pub(crate) fn wallet_recovery(wallet: &Wallet, birthday_height: u32) -> WalletRecovery {
+    let utxos = wallet
+        .list_unspent()
+        .map(|utxo| RecoveredUtxo {
+            outpoint: utxo.outpoint,
+            value: utxo.txout.value,
+            confirmation_height: match utxo.chain_position {
+                ChainPosition::Confirmed(anchor) => Some(anchor.block_id.height),
+                ChainPosition::Unconfirmed(_) => None,
+            },
+        })
+        .collect();
+
+    let mut potential_contracts = vec![];
+    for canonical in wallet.transactions() {
+        let confirmation_height = match canonical.chain_position {
+            ChainPosition::Confirmed(anchor) => Some(anchor.block_id.height),
+            ChainPosition::Unconfirmed(_) => None,
+        };
+        if confirmation_height.is_some_and(|height| height < birthday_height) {
+            continue;
+        }
+        let tx = &canonical.tx_node.tx;
+        let (sent, _) = wallet.sent_and_received(tx);
+        if sent == Amount::ZERO {
+            continue;
+        }
+        let txid = canonical.tx_node.txid;
+        for (vout, output) in tx.output.iter().enumerate() {
+            if !output.script_pubkey.is_p2wsh() || wallet.is_mine(output.script_pubkey.clone()) {
+                continue;
+            }
+            let funding_outpoint = OutPoint::new(txid, vout as u32);
+            potential_contracts.push(PotentialContract {
+                funding_outpoint,
+                value: output.value,
+                our_contribution: sent,
+                confirmation_height,
+                spent_by: wallet.tx_graph().outspends(funding_outpoint).iter().next().copied(),
+                contract_id: None,
+            });
+        }
+    }
+
+    WalletRecovery {
+        utxos,
+        potential_contracts,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bdk_chain::TxGraph;
+    use bdk_wallet::template::Bip84;
+    use bdk_wallet::{KeychainKind, Update};
+    use bitcoin::absolute::LockTime;
+    use bitcoin::bip32::Xpriv;
+    use bitcoin::hashes::Hash;
+    use bitcoin::opcodes::all::{OP_CHECKMULTISIG, OP_PUSHNUM_2};
+    use bitcoin::script::Builder;
+    use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+    use bitcoin::transaction::Version;
+    use bitcoin::{Network, ScriptBuf, Transaction, TxIn, TxOut};
+
+    fn wallet() -> Wallet {
+        let xprv = Xpriv::new_master(Network::Regtest, &[6u8; 64]).unwrap();
+        Wallet::create(
+            Bip84(xprv, KeychainKind::External),
+            Bip84(xprv, KeychainKind::Internal),
+        )
+        .network(Network::Regtest)
+        .create_wallet_no_persist()
+        .unwrap()
+    }
+
+    fn apply(wallet: &mut Wallet, tx: &Transaction) {
+        let mut graph = TxGraph::default();
+        let _ = graph.insert_tx(tx.clone());
+        let _ = graph.insert_seen_at(tx.compute_txid(), 1);
+        wallet
+            .apply_update(Update {
+                graph,
+                ..Default::default()
+            })
+            .unwrap();
+    }
+
+    fn funding_script() -> ScriptBuf {
+        let secp = Secp256k1::new();
+        let ours = PublicKey::from_secret_key(&secp, &SecretKey::from_slice(&[1u8; 32]).unwrap());
+        let theirs = PublicKey::from_secret_key(&secp, &SecretKey::from_slice(&[2u8; 32]).unwrap());
+        let multisig = Builder::new()
+            .push_opcode(OP_PUSHNUM_2)
+            .push_slice(ours.serialize())
+            .push_slice(theirs.serialize())
+            .push_opcode(OP_PUSHNUM_2)
+            .push_opcode(OP_CHECKMULTISIG)
+            .into_script();
+        ScriptBuf::new_p2wsh(&multisig.wscript_hash())
+    }
+
+    #[test]
+    fn reports_plain_utxos_and_funding_outputs() {
+        let mut wallet = wallet();
+        let receive = wallet.next_unused_address(KeychainKind::External);
+        let deposit = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::from_byte_array([1u8; 32]), 0),
+                ..Default::default()
+            }],
+            output: vec![
+                TxOut {
+                    value: Amount::from_sat(50_000),
+                    script_pubkey: receive.script_pubkey(),
+                },
+                TxOut {
+                    value: Amount::from_sat(100_000),
+                    script_pubkey: receive.script_pubkey(),
+                },
+            ],
+        };
+        apply(&mut wallet, &deposit);
+
+        let change = wallet.next_unused_address(KeychainKind::Internal);
+        let funding = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(deposit.compute_txid(), 1),
+                ..Default::default()
+            }],
+            output: vec![
+                TxOut {
+                    value: Amount::from_sat(80_000),
+                    script_pubkey: funding_script(),
+                },
+                TxOut {
+                    value: Amount::from_sat(19_000),
+                    script_pubkey: change.script_pubkey(),
+                },
+            ],
+        };
+        apply(&mut wallet, &funding);
+
+        let recovery = wallet_recovery(&wallet, 0);
+        let mut values = recovery.utxos.iter().map(|utxo| utxo.value.to_sat()).collect::<Vec<_>>();
+        values.sort();
+        assert_eq!(values, vec![19_000, 50_000]);
+        assert_eq!(
+            recovery.potential_contracts,
+            vec![PotentialContract {
+                funding_outpoint: OutPoint::new(funding.compute_txid(), 0),
+                value: Amount::from_sat(80_000),
+                our_contribution: Amount::from_sat(100_000),
+                confirmation_height: None,
+                spent_by: None,
+                contract_id: None,
+            }]
+        );
+        assert!(serde_json::to_string(&recovery).is_ok());
+    }
+}
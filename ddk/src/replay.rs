@@ -0,0 +1,332 @@
+//! Replay a recorded contract negotiation into a fresh node for debugging.
+use crate::chain::MockBlockchain;
+use crate::contract::summary::{accepted_contract, offered_contract};
+use crate::contract::ContractState;
+use crate::storage::MemoryStorageProvider;
+use crate::testkit::oracle::MockOracle;
+use crate::transport::{message_contract_id, message_kind, JournalEntry, MessageDirection};
+use crate::wallet::DlcDevKitWallet;
+use crate::{DdkBlockchain, DdkOracle, DdkStorage, DdkTransport, DlcDevKit};
+use bitcoin::key::XOnlyPublicKey;
+use dlc_manager::contract::Contract;
+use dlc_manager::manager::Manager;
+use dlc_manager::{
+    CachedContractSignerProvider, ContractId, SimpleSigner, Storage, SystemTimeProvider,
+};
+use dlc_messages::Message;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Outcome of one journal entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplayOutcome {
+    /// The node responded. `matches_recorded` is whether the response is of the same kind as
+    /// the recorded response.
+    Responded {
+        kind: String,
+        matches_recorded: bool,
+    },
+    /// The node did not respond. `expected` is the kind of the recorded response.
+    NoResponse { expected: Option<String> },
+    /// The node's own offer or accept was restored from its store.
+    Restored,
+    /// Processing the message failed.
+    Error(String),
+}
+
+/// One journal entry fed through the node.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayStep {
+    /// Index of the entry in the journal.
+    pub index: usize,
+    pub kind: String,
+    pub outcome: ReplayOutcome,
+    /// State of the negotiated contract after the step.
+    pub state: Option<ContractState>,
+}
+
+/// Result of replaying a journal.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReplayReport {
+    pub steps: Vec<ReplayStep>,
+    /// Journal index of the first message that failed to process.
+    pub first_error: Option<usize>,
+    /// Journal index of the first message whose response differs from the recording.
+    pub first_divergence: Option<usize>,
+    /// State of the negotiated contract after the replay.
+    pub final_state: Option<ContractState>,
+}
+
+type ReplayManager<S, B> = Manager<
+    Arc<DlcDevKitWallet<S, B>>,
+    Arc<CachedContractSignerProvider<Arc<DlcDevKitWallet<S, B>>, SimpleSigner>>,
+    Arc<MockBlockchain>,
+    Arc<MemoryStorageProvider>,
+    Arc<MockOracle>,
+    Arc<SystemTimeProvider>,
+    Arc<DlcDevKitWallet<S, B>>,
+    SimpleSigner,
+>;
+
+impl<T: DdkTransport, S: DdkStorage, O: DdkOracle, B: DdkBlockchain> DlcDevKit<T, S, O, B> {
+    /// Feed a journal recorded by this node through a fresh manager, backed by memory storage
+    /// and a mock chain, in order.
+    ///
+    /// Signatures bind a negotiation to the keys and inputs of the original run, so the
+    /// offers and accepts this node sent are not created again. They are restored from this
+    /// node's store, and signed with its wallet. Responses are compared to the recording by
+    /// kind, as their bytes differ between runs.
+    pub fn replay_contract(&self, journal: Vec<JournalEntry>) -> ReplayReport {
+        let mut report = ReplayReport::default();
+        let manager = match self.replay_manager() {
+            Ok(manager) => manager,
+            Err(e) => {
+                report.first_error = Some(0);
+                report.steps.push(ReplayStep {
+                    index: 0,
+                    kind: "unknown".into(),
+                    outcome: ReplayOutcome::Error(e.to_string()),
+                    state: None,
+                });
+                return report;
+            }
+        };
+        let store = manager.get_store();
+        let mut contract_ids = Vec::new();
+
+        for (index, entry) in journal.iter().enumerate() {
+            let message = match entry.message() {
+                Ok(message) => message,
+                Err(e) => {
+                    report.first_error.get_or_insert(index);
+                    report.steps.push(ReplayStep {
+                        index,
+                        kind: "unknown".into(),
+                        outcome: ReplayOutcome::Error(e.to_string()),
+                        state: None,
+                    });
+                    continue;
+                }
+            };
+            contract_ids.extend(message_contract_id(&message));
+
+            let outcome = match entry.direction {
+                MessageDirection::Outbound => match self.restore_own(store, &message) {
+                    // Sent in response to an inbound message, compared on that step.
+                    Ok(false) => continue,
+                    Ok(true) => ReplayOutcome::Restored,
+                    Err(e) => ReplayOutcome::Error(e.to_string()),
+                },
+                MessageDirection::Inbound => {
+                    let expected = recorded_response(&journal[index + 1..]);
+                    match manager.on_dlc_message(&message, entry.counterparty) {
+                        Ok(Some(response)) => {
+                            contract_ids.extend(message_contract_id(&response));
+                            let kind = message_kind(&response);
+                            ReplayOutcome::Responded {
+                                kind: kind.to_string(),
+                                matches_recorded: expected.as_deref() == Some(kind),
+                            }
+                        }
+                        Ok(None) => ReplayOutcome::NoResponse { expected },
+                        Err(e) => ReplayOutcome::Error(e.to_string()),
+                    }
+                }
+            };
+
+            match &outcome {
+                ReplayOutcome::Error(_) => {
+                    report.first_error.get_or_insert(index);
+                }
+                ReplayOutcome::Responded {
+                    matches_recorded: false,
+                    ..
+                }
+                | ReplayOutcome::NoResponse { expected: Some(_) } => {
+                    report.first_divergence.get_or_insert(index);
+                }
+                _ => {}
+            }
+
+            report.steps.push(ReplayStep {
+                index,
+                kind: message_kind(&message).to_string(),
+                outcome,
+                state: contract_state(store, &contract_ids),
+            });
+        }
+
+        report.final_state = contract_state(store, &contract_ids);
+        report
+    }
+
+    /// A manager that shares this node's wallet, to sign as it did, and nothing else.
+    fn replay_manager(&self) -> Result<ReplayManager<S, B>, dlc_manager::error::Error> {
+        Manager::new(
+            self.wallet.clone(),
+            self.wallet.clone(),
+            Arc::new(MockBlockchain::new(self.network)),
+            Arc::new(MemoryStorageProvider::new()),
+            HashMap::<XOnlyPublicKey, Arc<MockOracle>>::new(),
+            Arc::new(SystemTimeProvider {}),
+            self.wallet.clone(),
+        )
+    }
+
+    /// Copy the contract this node offered or accepted with `message` into the replay store.
+    /// Returns false for messages that are responses.
+    fn restore_own(
+        &self,
+        store: &MemoryStorageProvider,
+        message: &Message,
+    ) -> anyhow::Result<bool> {
+        let (temporary_id, accepted) = match message {
+            Message::Offer(offer) => (offer.temporary_contract_id, false),
+            Message::Accept(accept) => (accept.temporary_contract_id, true),
+            _ => return Ok(false),
+        };
+        let own = self
+            .storage
+            .get_contracts()?
+            .into_iter()
+            .find(|contract| offered_contract(contract).is_some_and(|o| o.id == temporary_id))
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "No stored contract for temporary id {}.",
+                    hex::encode(temporary_id)
+                )
+            })?;
+        let offered = offered_contract(&own).expect("matched on the offer");
+        if store.get_contract(&temporary_id)?.is_none() {
+            store.create_contract(offered)?;
+        }
+        if accepted {
+            let accepted = accepted_contract(&own).ok_or_else(|| {
+                anyhow::anyhow!("Contract {} was never accepted.", hex::encode(temporary_id))
+            })?;
+            store.update_contract(&Contract::Accepted(accepted.clone()))?;
+        }
+        Ok(true)
+    }
+}
+
+/// Kind of the response recorded to an inbound message: the first outbound message before
+/// the next inbound one. Offers and accepts are sent on request, not in response.
+fn recorded_response(rest: &[JournalEntry]) -> Option<String> {
+    rest.iter()
+        .take_while(|e| e.direction == MessageDirection::Outbound)
+        .filter_map(|e| e.message().ok())
+        .find(|m| !matches!(m, Message::Offer(_) | Message::Accept(_)))
+        .map(|m| message_kind(&m).to_string())
+}
+
+fn contract_state(
+    store: &MemoryStorageProvider,
+    contract_ids: &[ContractId],
+) -> Option<ContractState> {
+    contract_ids.iter().rev().find_map(|id| {
+        store
+            .get_contract(id)
+            .ok()
+            .flatten()
+            .map(|c| ContractState::from(&c))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testkit::harness::{enum_contract_input, yes_no_payouts, TestHarness};
+    use crate::transport::encode_message;
+    use bitcoin::secp256k1::{Message as SecpMessage, Secp256k1, SecretKey};
+
+    /// Alice's journal of a negotiation with Bob, driven through the managers directly so
+    /// every message is at hand.
+    fn recorded_negotiation(harness: &TestHarness) -> (Vec<JournalEntry>, Message) {
+        let announcement = harness
+            .oracle
+            .create_enum_event("replay", &["yes", "no"], u32::MAX)
+            .unwrap();
+        let input = enum_contract_input(&announcement, yes_no_payouts(100_000));
+        let alice_key = harness.alice.transport().public_key();
+        let bob_key = harness.bob.transport().public_key();
+
+        let offer = harness
+            .alice
+            .manager()
+            .send_offer_with_announcements(&input, bob_key, vec![vec![announcement]])
+            .unwrap();
+        harness
+            .bob
+            .manager()
+            .on_dlc_message(&Message::Offer(offer.clone()), alice_key)
+            .unwrap();
+        let (_, _, accept) = harness
+            .bob
+            .manager()
+            .accept_contract_offer(&offer.temporary_contract_id)
+            .unwrap();
+        let sign = harness
+            .alice
+            .manager()
+            .on_dlc_message(&Message::Accept(accept.clone()), bob_key)
+            .unwrap()
+            .unwrap();
+
+        let entry = |direction, message: &Message| JournalEntry {
+            direction,
+            counterparty: bob_key,
+            message: encode_message(message),
+            timestamp: 0,
+        };
+        let journal = vec![
+            entry(MessageDirection::Outbound, &Message::Offer(offer)),
+            entry(MessageDirection::Inbound, &Message::Accept(accept.clone())),
+            entry(MessageDirection::Outbound, &sign),
+        ];
+        (journal, Message::Accept(accept))
+    }
+
+    #[test]
+    fn known_good_journal_replays_to_signed() {
+        let harness = TestHarness::new_pair();
+        let (journal, _) = recorded_negotiation(&harness);
+
+        let report = harness.alice.replay_contract(journal);
+
+        assert_eq!(report.first_error, None);
+        assert_eq!(report.first_divergence, None);
+        assert_eq!(report.steps.len(), 2);
+        assert_eq!(report.steps[0].outcome, ReplayOutcome::Restored);
+        assert_eq!(report.steps[0].state, Some(ContractState::Offered));
+        assert_eq!(
+            report.steps[1].outcome,
+            ReplayOutcome::Responded {
+                kind: "sign".into(),
+                matches_recorded: true
+            }
+        );
+        assert_eq!(report.final_state, Some(ContractState::Signed));
+    }
+
+    #[test]
+    fn corrupted_accept_is_reported() {
+        let harness = TestHarness::new_pair();
+        let (mut journal, accept) = recorded_negotiation(&harness);
+        let Message::Accept(mut accept) = accept else {
+            unreachable!()
+        };
+        let secp = Secp256k1::new();
+        accept.refund_signature = secp.sign_ecdsa(
+            &SecpMessage::from_digest([1u8; 32]),
+            &SecretKey::from_slice(&[3u8; 32]).unwrap(),
+        );
+        journal[1].message = encode_message(&Message::Accept(accept));
+
+        let report = harness.alice.replay_contract(journal);
+
+        assert_eq!(report.first_error, Some(1));
+        assert!(matches!(report.steps[1].outcome, ReplayOutcome::Error(_)));
+        assert_ne!(report.final_state, Some(ContractState::Signed));
+    }
+}
@@ -0,0 +1,183 @@
+//! Global risk policy for open contracts.
+use crate::error::DdkError;
+use bitcoin::secp256k1::PublicKey;
+use dlc_manager::contract::Contract;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Hard caps on contract exposure. A limit of zero means unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RiskLimits {
+    /// Maximum simultaneous open contracts.
+    pub max_open_contracts: u64,
+    /// Maximum total collateral, in sats, this node has at risk.
+    pub max_total_collateral: u64,
+    /// Maximum collateral, in sats, at risk with a single counterparty.
+    pub max_per_counterparty: u64,
+}
+
+/// The limit that was exceeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RiskLimitKind {
+    OpenContracts,
+    TotalCollateral,
+    PerCounterparty,
+}
+
+impl fmt::Display for RiskLimitKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OpenContracts => write!(f, "max_open_contracts"),
+            Self::TotalCollateral => write!(f, "max_total_collateral"),
+            Self::PerCounterparty => write!(f, "max_per_counterparty"),
+        }
+    }
+}
+
+/// Current exposure of the node computed from stored contracts.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RiskUtilization {
+    pub open_contracts: u64,
+    pub total_collateral: u64,
+    pub per_counterparty: HashMap<PublicKey, u64>,
+}
+
+impl RiskUtilization {
+    /// Sums our collateral in offers we sent and in accepted, signed, and confirmed contracts.
+    pub fn from_contracts(contracts: &[Contract]) -> RiskUtilization {
+        let mut utilization = RiskUtilization::default();
+        for contract in contracts {
+            let exposure = match contract {
+                Contract::Offered(o) if o.is_offer_party => {
+                    Some((o.counter_party, o.offer_params.collateral))
+                }
+                Contract::Accepted(a) => {
+                    let offered = &a.offered_contract;
+                    Some((offered.counter_party, our_collateral(offered, a.accept_params.collateral)))
+                }
+                Contract::Signed(s) | Contract::Confirmed(s) => {
+                    let offered = &s.accepted_contract.offered_contract;
+                    let accept_collateral = s.accepted_contract.accept_params.collateral;
+                    Some((offered.counter_party, our_collateral(offered, accept_collateral)))
+                }
+                _ => None,
+            };
+
+            if let Some((counterparty, collateral)) = exposure {
+                utilization.open_contracts += 1;
+                utilization.total_collateral += collateral;
+                *utilization.per_counterparty.entry(counterparty).or_default() += collateral;
+            }
+        }
+        utilization
+    }
+}
+
+fn our_collateral(
+    offered: &dlc_manager::contract::offered_contract::OfferedContract,
+    accept_collateral: u64,
+) -> u64 {
+    if offered.is_offer_party {
+        offered.offer_params.collateral
+    } else {
+        accept_collateral
+    }
+}
+
+impl RiskLimits {
+    /// Check that opening one more contract with `counterparty` and `collateral` stays within limits.
+    pub fn check(
+        &self,
+        utilization: &RiskUtilization,
+        counterparty: &PublicKey,
+        collateral: u64,
+    ) -> Result<(), DdkError> {
+        if self.max_open_contracts > 0 && utilization.open_contracts + 1 > self.max_open_contracts {
+            return Err(DdkError::RiskLimit {
+                which: RiskLimitKind::OpenContracts,
+                current: utilization.open_contracts,
+                limit: self.max_open_contracts,
+            });
+        }
+
+        if self.max_total_collateral > 0
+            && utilization.total_collateral + collateral > self.max_total_collateral
+        {
+            return Err(DdkError::RiskLimit {
+                which: RiskLimitKind::TotalCollateral,
+                current: utilization.total_collateral,
+                limit: self.max_total_collateral,
+            });
+        }
+
+        let counterparty_collateral = utilization
+            .per_counterparty
+            .get(counterparty)
+            .copied()
+            .unwrap_or_default();
+        if self.max_per_counterparty > 0
+            && counterparty_collateral + collateral > self.max_per_counterparty
+        {
+            return Err(DdkError::RiskLimit {
+                which: RiskLimitKind::PerCounterparty,
+                current: counterparty_collateral,
+                limit: self.max_per_counterparty,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::secp256k1::{Secp256k1, SecretKey};
+
+    fn pubkey(byte: u8) -> PublicKey {
+        PublicKey::from_secret_key(&Secp256k1::new(), &SecretKey::from_slice(&[byte; 32]).unwrap())
+    }
+
+    fn utilization() -> RiskUtilization {
+        let mut per_counterparty = HashMap::new();
+        per_counterparty.insert(pubkey(1), 90_000);
+        RiskUtilization {
+            open_contracts: 2,
+            total_collateral: 150_000,
+            per_counterparty,
+        }
+    }
+
+    fn tripped(result: Result<(), DdkError>) -> RiskLimitKind {
+        match result {
+            Err(DdkError::RiskLimit { which, .. }) => which,
+            _ => panic!("Expected a risk limit error."),
+        }
+    }
+
+    #[test]
+    fn zero_limits_are_unlimited() {
+        let limits = RiskLimits::default();
+        assert!(limits.check(&utilization(), &pubkey(1), u64::MAX / 2).is_ok());
+    }
+
+    #[test]
+    fn open_contracts_limit() {
+        let limits = RiskLimits { max_open_contracts: 2, ..Default::default() };
+        assert_eq!(tripped(limits.check(&utilization(), &pubkey(2), 1)), RiskLimitKind::OpenContracts);
+    }
+
+    #[test]
+    fn total_collateral_limit() {
+        let limits = RiskLimits { max_total_collateral: 200_000, ..Default::default() };
+        assert!(limits.check(&utilization(), &pubkey(2), 50_000).is_ok());
+        assert_eq!(tripped(limits.check(&utilization(), &pubkey(2), 50_001)), RiskLimitKind::TotalCollateral);
+    }
+
+    #[test]
+    fn per_counterparty_limit() {
+        let limits = RiskLimits { max_per_counterparty: 100_000, ..Default::default() };
+        assert_eq!(tripped(limits.check(&utilization(), &pubkey(1), 20_000)), RiskLimitKind::PerCounterparty);
+        assert!(limits.check(&utilization(), &pubkey(2), 20_000).is_ok());
+    }
+}
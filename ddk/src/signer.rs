@@ -8,6 +8,15 @@ pub struct SignerInformation {
     pub public_key: PublicKey,
 }
 
+/// Which negotiation a derived key was created for.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyUsage {
+    /// Temporary id of the contract or channel the key was derived for.
+    pub temporary_id: [u8; 32],
+    /// Unix timestamp the key was derived at.
+    pub created_at: u64,
+}
+
 /// Trait with contract specific information
 /// 1. Storing and retrieving private keys for DLC CETs.
 /// 2. Tracking contract specific addresses for counterparties.
@@ -22,4 +31,24 @@ pub trait DeriveSigner {
     ) -> Result<(), Self::Error>;
     fn get_secret_key(&self, public_key: &PublicKey) -> Result<SecretKey, Self::Error>;
     fn import_address_to_storage(&self, address: &bitcoin::Address) -> Result<(), Self::Error>;
+    /// Record which negotiation a key id was derived for so unused keys can be removed later.
+    fn record_key_usage(&self, _key_id: [u8; 32], _usage: KeyUsage) -> Result<(), Self::Error> {
+        Ok(())
+    }
+    /// Every stored signer with its key id.
+    fn list_signers(&self) -> Result<Vec<([u8; 32], SignerInformation)>, Self::Error> {
+        Ok(vec![])
+    }
+    /// Every recorded key usage with its key id.
+    fn list_key_usage(&self) -> Result<Vec<([u8; 32], KeyUsage)>, Self::Error> {
+        Ok(vec![])
+    }
+    /// Index of the next contract signer key. None until one was saved.
+    fn signer_index(&self) -> Result<Option<u32>, Self::Error> {
+        Ok(None)
+    }
+    /// Save the index of the next contract signer key. The wallet only ever raises it.
+    fn save_signer_index(&self, _index: u32) -> Result<(), Self::Error> {
+        Ok(())
+    }
 }
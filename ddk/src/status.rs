@@ -0,0 +1,164 @@
+//! Liveness of the node's background tasks, reported by [crate::DlcDevKit::status].
+use crate::contract::ContractState;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Stands for a value that was never recorded.
+const UNSET: u64 = u64::MAX;
+
+/// Updated by the background tasks as they run, so the status can be read without calling
+/// the chain backend, the oracles, or the peers.
+#[derive(Debug)]
+pub struct StatusTracker {
+    listening: AtomicBool,
+    chain_tip: AtomicU64,
+    last_oracle_contact: AtomicU64,
+}
+
+impl Default for StatusTracker {
+    fn default() -> Self {
+        Self {
+            listening: AtomicBool::new(false),
+            chain_tip: AtomicU64::new(UNSET),
+            last_oracle_contact: AtomicU64::new(UNSET),
+        }
+    }
+}
+
+impl StatusTracker {
+    /// Set while the transport listener task runs.
+    pub fn set_listening(&self, listening: bool) {
+        self.listening.store(listening, Ordering::Relaxed);
+    }
+
+    pub fn listening(&self) -> bool {
+        self.listening.load(Ordering::Relaxed)
+    }
+
+    /// Record the chain height returned by the chain backend.
+    pub fn record_chain_tip(&self, height: u64) {
+        self.chain_tip.store(height, Ordering::Relaxed);
+    }
+
+    pub fn chain_tip(&self) -> Option<u64> {
+        get(&self.chain_tip)
+    }
+
+    /// Record an announcement or attestation received from an oracle.
+    pub fn record_oracle_contact(&self) {
+        self.last_oracle_contact
+            .store(unix_time(SystemTime::now()), Ordering::Relaxed);
+    }
+
+    /// Unix timestamp in seconds of the last announcement or attestation received.
+    pub fn last_oracle_contact(&self) -> Option<u64> {
+        get(&self.last_oracle_contact)
+    }
+}
+
+fn get(value: &AtomicU64) -> Option<u64> {
+    Some(value.load(Ordering::Relaxed)).filter(|value| *value != UNSET)
+}
+
+/// Health of the contract store.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StorageHealth {
+    /// Bytes used on disk, if the backend can report it.
+    pub size_on_disk: Option<u64>,
+    /// Entries in each tree or table, see [crate::storage::StorageStats::tree_sizes].
+    pub tree_sizes: BTreeMap<String, usize>,
+    /// Wallet changesets waiting to be compacted.
+    pub wallet_changesets: usize,
+    /// The first error reading the status from storage. None when every read succeeded.
+    pub error: Option<String>,
+}
+
+/// Snapshot of the node for health checks. Timestamps are unix seconds.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DdkStatus {
+    /// Whether [crate::DlcDevKit::start] was called and the background tasks run.
+    pub running: bool,
+    pub last_wallet_sync: Option<u64>,
+    /// Wallet syncs failed since the last successful one.
+    pub wallet_sync_failures: u32,
+    /// Chain height at the last poll of the chain watcher.
+    pub chain_tip: Option<u64>,
+    /// Name of the transport.
+    pub transport: String,
+    /// Whether the transport task, [crate::DdkTransport::start], runs.
+    pub listening: bool,
+    /// Stored peers the transport is connected to.
+    pub connected_peers: usize,
+    /// Received messages the manager has not processed.
+    pub pending_inbound_messages: usize,
+    /// Messages not handed to the transport yet.
+    pub pending_outbound_messages: usize,
+    pub contracts_by_state: HashMap<ContractState, usize>,
+    /// Last announcement or attestation received from any oracle.
+    pub last_oracle_contact: Option<u64>,
+    /// Whether maintenance mode is on, see [crate::DlcDevKit::set_maintenance].
+    pub maintenance: bool,
+    /// Offers received in maintenance mode waiting for an answer.
+    pub held_offers: usize,
+    pub storage: StorageHealth,
+}
+
+/// Unix timestamp in seconds of a monotonic instant in the past.
+pub(crate) fn instant_to_unix(instant: Instant) -> u64 {
+    unix_time(SystemTime::now() - instant.elapsed())
+}
+
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracker_records_background_tasks() {
+        let tracker = StatusTracker::default();
+        assert!(!tracker.listening());
+        assert_eq!(tracker.chain_tip(), None);
+        assert_eq!(tracker.last_oracle_contact(), None);
+
+        tracker.set_listening(true);
+        tracker.record_chain_tip(0);
+        tracker.record_oracle_contact();
+        assert!(tracker.listening());
+        assert_eq!(tracker.chain_tip(), Some(0));
+        let now = unix_time(SystemTime::now());
+        assert!(tracker
+            .last_oracle_contact()
+            .is_some_and(|contact| contact <= now && contact + 5 > now));
+    }
+
+    #[test]
+    fn status_serializes() {
+        let status = DdkStatus {
+            running: true,
+            last_wallet_sync: Some(1_700_000_000),
+            chain_tip: Some(850_000),
+            transport: "nostr".to_string(),
+            contracts_by_state: HashMap::from([
+                (ContractState::Confirmed, 2),
+                (ContractState::Offered, 1),
+            ]),
+            storage: StorageHealth {
+                size_on_disk: Some(4096),
+                tree_sizes: BTreeMap::from([("contracts".to_string(), 3)]),
+                wallet_changesets: 12,
+                error: None,
+            },
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&status).unwrap();
+        assert!(json.contains("\"Confirmed\":2"));
+        assert_eq!(serde_json::from_str::<DdkStatus>(&json).unwrap(), status);
+    }
+}
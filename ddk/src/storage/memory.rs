@@ -0,0 +1,1042 @@
+//! Storage kept entirely in memory, for tests and throwaway nodes.
+//!
+//! Contracts and channels are held in their serialized form so they round trip exactly like
+//! the on-disk providers.
+use crate::accounting::ContractSettlement;
+use crate::audit::AuditEntry;
+use crate::chain::rebroadcast::PendingBroadcast;
+use crate::contract::metadata::ContractMetadata;
+use crate::contract::{validate_transition, ContractState};
+use crate::metrics;
+use crate::oracle::cache::{AnnouncementCache, AnnouncementKey};
+use crate::error::WalletError;
+use crate::rates::ContractRates;
+use crate::signer::{DeriveSigner, KeyUsage, SignerInformation};
+use crate::storage::sled::{append_to_archive, deserialize_channel, serialize_channel};
+use crate::storage::{
+    is_archivable, is_vacuumable, live_negotiation_ids, SignerVacuumOptions, SignerVacuumReport,
+    StorageStats,
+};
+use crate::transport::rate_limit::PeerBan;
+use crate::transport::{PeerInformation, PendingInbound, PendingOutbound};
+use crate::util::{deserialize_contract_bytes, serialize_contract};
+use crate::wallet::fees::StoredFees;
+use crate::wallet::ContractTransaction;
+use crate::DdkStorage;
+use bdk_chain::Merge;
+use bdk_wallet::{ChangeSet, WalletPersister};
+use bitcoin::key::XOnlyPublicKey;
+use bitcoin::secp256k1::{PublicKey, SecretKey};
+use bitcoin::{OutPoint, Txid};
+use dlc_manager::chain_monitor::ChainMonitor;
+use dlc_manager::channel::offered_channel::OfferedChannel;
+use dlc_manager::channel::signed_channel::{SignedChannel, SignedChannelStateType};
+use dlc_manager::channel::Channel;
+use dlc_manager::contract::offered_contract::OfferedContract;
+use dlc_manager::contract::ser::Serializable;
+use dlc_manager::contract::signed_contract::SignedContract;
+use dlc_manager::contract::{Contract, PreClosedContract};
+use dlc_manager::{error::Error, ChannelId, ContractId, Storage};
+use dlc_messages::oracle_msgs::{OracleAnnouncement, OracleAttestation};
+use dlc_messages::Message;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+#[derive(Debug, Default)]
+struct MemoryStore {
+    contracts: HashMap<ContractId, Vec<u8>>,
+    channels: HashMap<ChannelId, Vec<u8>>,
+    chain_monitor: Option<Vec<u8>>,
+    peers: Vec<PeerInformation>,
+    signers: HashMap<[u8; 32], SignerInformation>,
+    key_usage: HashMap<[u8; 32], KeyUsage>,
+    wallet: ChangeSet,
+    wallet_changesets: usize,
+    pending_outbound: HashMap<String, PendingOutbound>,
+    inbound_messages: HashMap<String, PendingInbound>,
+    processed_inbound: HashSet<String>,
+    audit_log: Vec<AuditEntry>,
+    contract_rates: HashMap<ContractId, ContractRates>,
+    contract_settlements: HashMap<ContractId, ContractSettlement>,
+    announcements: HashMap<AnnouncementKey, OracleAnnouncement>,
+    attestations: HashMap<AnnouncementKey, OracleAttestation>,
+    contract_transactions: HashMap<Txid, ContractTransaction>,
+    contract_metadata: HashMap<ContractId, ContractMetadata>,
+    /// Archive time and serialized contract of archived contracts.
+    archived_contracts: HashMap<ContractId, (u64, Vec<u8>)>,
+    maintenance: bool,
+    transport_cursors: HashMap<String, u64>,
+    reserved_utxos: Vec<OutPoint>,
+    frozen_utxos: Vec<OutPoint>,
+    peer_bans: Vec<PeerBan>,
+    pending_broadcasts: Vec<PendingBroadcast>,
+    fee_estimates: Option<StoredFees>,
+    signer_index: Option<u32>,
+}
+
+impl MemoryStore {
+    /// Store `contract` and audit its state change under the same lock.
+    fn insert_contract(&mut self, contract: &Contract) -> Result<(), Error> {
+        let serialized = serialize_contract(contract)?;
+        let existing = self
+            .contracts
+            .get(&contract.get_id())
+            .or_else(|| self.contracts.get(&contract.get_temporary_id()))
+            .map(deserialize_contract_bytes)
+            .transpose()?;
+        if let Some(entry) = AuditEntry::state_change(existing.as_ref(), contract) {
+            self.append_audit(entry);
+        }
+        if let Contract::Accepted(_) | Contract::Signed(_) = contract {
+            self.contracts.remove(&contract.get_temporary_id());
+            if let Some(metadata) = self.contract_metadata.remove(&contract.get_temporary_id()) {
+                self.contract_metadata.insert(contract.get_id(), metadata);
+            }
+        }
+        self.contracts.insert(contract.get_id(), serialized);
+        Ok(())
+    }
+
+    fn append_audit(&mut self, mut entry: AuditEntry) -> u64 {
+        entry.sequence = self.audit_log.len() as u64 + 1;
+        self.audit_log.push(entry);
+        self.audit_log.len() as u64
+    }
+
+    fn contracts(&self) -> Result<Vec<Contract>, Error> {
+        self.contracts
+            .values()
+            .map(deserialize_contract_bytes)
+            .collect()
+    }
+
+    fn channels(&self) -> Result<Vec<Channel>, Error> {
+        self.channels
+            .values()
+            .map(|data| deserialize_channel(data))
+            .collect()
+    }
+}
+
+/// Implementation of Storage interface that keeps everything in memory. Clones share the
+/// same data.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStorageProvider {
+    store: Arc<RwLock<MemoryStore>>,
+    strict_transitions: bool,
+}
+
+impl MemoryStorageProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reject contract updates that do not follow the contract state machine. By default
+    /// invalid transitions are only logged.
+    pub fn with_strict_transitions(mut self, strict_transitions: bool) -> Self {
+        self.strict_transitions = strict_transitions;
+        self
+    }
+
+    fn contracts_where<T>(&self, f: impl Fn(Contract) -> Option<T>) -> Result<Vec<T>, Error> {
+        Ok(self
+            .store
+            .read()
+            .unwrap()
+            .contracts()?
+            .into_iter()
+            .filter_map(f)
+            .collect())
+    }
+
+    /// Remove signers whose contract is closed or gone and that were derived more than the
+    /// retention period before `now`. Signers of open contracts and channels are always kept.
+    pub(crate) fn vacuum_signers_at(
+        &self,
+        now: u64,
+        options: &SignerVacuumOptions,
+    ) -> anyhow::Result<SignerVacuumReport> {
+        let mut store = self.store.write().unwrap();
+        let live = live_negotiation_ids(&store.contracts()?, &store.channels()?);
+
+        let mut report = SignerVacuumReport::default();
+        let mut removed = vec![];
+        for (key_id, signer) in &store.signers {
+            let Some(usage) = store.key_usage.get(key_id) else {
+                report.unattributed += 1;
+                continue;
+            };
+            if !is_vacuumable(usage, &live, now, options) {
+                report.kept += 1;
+                continue;
+            }
+            removed.push((hex::encode(key_id), *key_id, bincode::serialize(signer)?));
+        }
+
+        if removed.is_empty() {
+            return Ok(report);
+        }
+
+        if let Some(archive) = &options.export_before_delete {
+            let archived = removed
+                .iter()
+                .map(|(hex_id, _, signer)| (hex_id.clone(), signer.clone()))
+                .collect::<Vec<_>>();
+            append_to_archive(archive, &archived)?;
+        }
+
+        for (hex_id, key_id, _) in removed {
+            store.signers.remove(&key_id);
+            store.key_usage.remove(&key_id);
+            tracing::info!(key_id = hex_id, "Removed unused signer.");
+            report.removed.push(hex_id);
+        }
+        Ok(report)
+    }
+}
+
+impl Storage for MemoryStorageProvider {
+    fn get_contract(&self, contract_id: &ContractId) -> Result<Option<Contract>, Error> {
+        self.store
+            .read()
+            .unwrap()
+            .contracts
+            .get(contract_id)
+            .map(deserialize_contract_bytes)
+            .transpose()
+    }
+
+    fn get_contracts(&self) -> Result<Vec<Contract>, Error> {
+        self.store.read().unwrap().contracts()
+    }
+
+    fn create_contract(&self, contract: &OfferedContract) -> Result<(), Error> {
+        let contract = Contract::Offered(contract.clone());
+        let serialized = serialize_contract(&contract)?;
+        let mut store = self.store.write().unwrap();
+        store.contracts.insert(contract.get_id(), serialized);
+        if let Some(entry) = AuditEntry::state_change(None, &contract) {
+            store.append_audit(entry);
+        }
+        Ok(())
+    }
+
+    fn delete_contract(&self, contract_id: &ContractId) -> Result<(), Error> {
+        self.store.write().unwrap().contracts.remove(contract_id);
+        Ok(())
+    }
+
+    fn update_contract(&self, contract: &Contract) -> Result<(), Error> {
+        let existing = match self.get_contract(&contract.get_id())? {
+            Some(c) => Some(c),
+            None => self.get_contract(&contract.get_temporary_id())?,
+        };
+        if let Some(existing) = &existing {
+            if let Err(e) = validate_transition(existing, contract) {
+                if self.strict_transitions {
+                    return Err(Error::StorageError(e.to_string()));
+                }
+                tracing::warn!(
+                    contract_id = hex::encode(contract.get_id()),
+                    error = e.to_string(),
+                    "Updating contract with an invalid transition."
+                );
+            }
+        }
+
+        let pending = PendingOutbound::for_state_change(existing.as_ref(), contract);
+        let mut store = self.store.write().unwrap();
+        store.insert_contract(contract)?;
+        if let Some(pending) = pending {
+            store.pending_outbound.insert(pending.id.clone(), pending);
+        }
+        drop(store);
+        metrics::contract_updated(existing.as_ref(), contract);
+        Ok(())
+    }
+
+    fn get_contract_offers(&self) -> Result<Vec<OfferedContract>, Error> {
+        self.contracts_where(|c| match c {
+            Contract::Offered(o) => Some(o),
+            _ => None,
+        })
+    }
+
+    fn get_signed_contracts(&self) -> Result<Vec<SignedContract>, Error> {
+        self.contracts_where(|c| match c {
+            Contract::Signed(s) => Some(s),
+            _ => None,
+        })
+    }
+
+    fn get_confirmed_contracts(&self) -> Result<Vec<SignedContract>, Error> {
+        self.contracts_where(|c| match c {
+            Contract::Confirmed(s) => Some(s),
+            _ => None,
+        })
+    }
+
+    fn get_preclosed_contracts(&self) -> Result<Vec<PreClosedContract>, Error> {
+        self.contracts_where(|c| match c {
+            Contract::PreClosed(p) => Some(p),
+            _ => None,
+        })
+    }
+
+    fn upsert_channel(&self, channel: Channel, contract: Option<Contract>) -> Result<(), Error> {
+        let serialized = serialize_channel(&channel)?;
+        let mut store = self.store.write().unwrap();
+        // Serialize the contract before touching the channel so a failure changes nothing.
+        if let Some(c) = &contract {
+            serialize_contract(c)?;
+        }
+        if let Channel::Accepted(_) | Channel::Signed(_) = &channel {
+            store.channels.remove(&channel.get_temporary_id());
+        }
+        store.channels.insert(channel.get_id(), serialized);
+        if let Some(c) = &contract {
+            store.insert_contract(c)?;
+        }
+        Ok(())
+    }
+
+    fn delete_channel(&self, channel_id: &ChannelId) -> Result<(), Error> {
+        self.store.write().unwrap().channels.remove(channel_id);
+        Ok(())
+    }
+
+    fn get_channel(&self, channel_id: &ChannelId) -> Result<Option<Channel>, Error> {
+        self.store
+            .read()
+            .unwrap()
+            .channels
+            .get(channel_id)
+            .map(|data| deserialize_channel(data))
+            .transpose()
+    }
+
+    fn get_signed_channels(
+        &self,
+        channel_state: Option<SignedChannelStateType>,
+    ) -> Result<Vec<SignedChannel>, Error> {
+        Ok(self
+            .store
+            .read()
+            .unwrap()
+            .channels()?
+            .into_iter()
+            .filter_map(|c| match c {
+                Channel::Signed(s)
+                    if channel_state
+                        .as_ref()
+                        .map_or(true, |state| s.state.get_type() == *state) =>
+                {
+                    Some(s)
+                }
+                _ => None,
+            })
+            .collect())
+    }
+
+    fn get_offered_channels(&self) -> Result<Vec<OfferedChannel>, Error> {
+        Ok(self
+            .store
+            .read()
+            .unwrap()
+            .channels()?
+            .into_iter()
+            .filter_map(|c| match c {
+                Channel::Offered(o) => Some(o),
+                _ => None,
+            })
+            .collect())
+    }
+
+    fn persist_chain_monitor(&self, monitor: &ChainMonitor) -> Result<(), Error> {
+        self.store.write().unwrap().chain_monitor = Some(monitor.serialize()?);
+        Ok(())
+    }
+
+    fn get_chain_monitor(&self) -> Result<Option<ChainMonitor>, Error> {
+        self.store
+            .read()
+            .unwrap()
+            .chain_monitor
+            .as_ref()
+            .map(|s| {
+                ChainMonitor::deserialize(&mut ::lightning::io::Cursor::new(s))
+                    .map_err(|e| Error::StorageError(e.to_string()))
+            })
+            .transpose()
+    }
+}
+
+impl WalletPersister for MemoryStorageProvider {
+    type Error = WalletError;
+
+    fn persist(persister: &mut Self, changeset: &ChangeSet) -> Result<(), Self::Error> {
+        let mut store = persister.store.write().unwrap();
+        store.wallet.merge(changeset.clone());
+        store.wallet_changesets += 1;
+        Ok(())
+    }
+
+    fn initialize(persister: &mut Self) -> Result<ChangeSet, Self::Error> {
+        Ok(persister.store.read().unwrap().wallet.clone())
+    }
+}
+
+impl DeriveSigner for MemoryStorageProvider {
+    type Error = WalletError;
+
+    fn get_key_information(&self, key_id: [u8; 32]) -> Result<SignerInformation, Self::Error> {
+        let store = self.store.read().unwrap();
+        let info = store
+            .signers
+            .get(&key_id)
+            .ok_or_else(|| WalletError::SignerError("Could not find key id.".into()))?;
+        Ok(SignerInformation {
+            index: info.index,
+            secret_key: info.secret_key,
+            public_key: info.public_key,
+        })
+    }
+
+    fn store_derived_key_id(
+        &self,
+        key_id: [u8; 32],
+        signer_information: SignerInformation,
+    ) -> Result<(), Self::Error> {
+        self.store
+            .write()
+            .unwrap()
+            .signers
+            .insert(key_id, signer_information);
+        Ok(())
+    }
+
+    fn get_secret_key(&self, public_key: &PublicKey) -> Result<SecretKey, Self::Error> {
+        self.store
+            .read()
+            .unwrap()
+            .signers
+            .values()
+            .find(|info| info.public_key == *public_key)
+            .map(|info| info.secret_key)
+            .ok_or_else(|| WalletError::SignerError("Could not find secret key.".into()))
+    }
+
+    fn import_address_to_storage(&self, _address: &bitcoin::Address) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn record_key_usage(&self, key_id: [u8; 32], usage: KeyUsage) -> Result<(), Self::Error> {
+        self.store.write().unwrap().key_usage.insert(key_id, usage);
+        Ok(())
+    }
+
+    fn list_signers(&self) -> Result<Vec<([u8; 32], SignerInformation)>, Self::Error> {
+        Ok(self
+            .store
+            .read()
+            .unwrap()
+            .signers
+            .iter()
+            .map(|(key_id, info)| {
+                (
+                    *key_id,
+                    SignerInformation {
+                        index: info.index,
+                        secret_key: info.secret_key,
+                        public_key: info.public_key,
+                    },
+                )
+            })
+            .collect())
+    }
+
+    fn list_key_usage(&self) -> Result<Vec<([u8; 32], KeyUsage)>, Self::Error> {
+        Ok(self
+            .store
+            .read()
+            .unwrap()
+            .key_usage
+            .iter()
+            .map(|(key_id, usage)| (*key_id, *usage))
+            .collect())
+    }
+
+    fn signer_index(&self) -> Result<Option<u32>, Self::Error> {
+        Ok(self.store.read().unwrap().signer_index)
+    }
+
+    fn save_signer_index(&self, index: u32) -> Result<(), Self::Error> {
+        self.store.write().unwrap().signer_index = Some(index);
+        Ok(())
+    }
+}
+
+impl DdkStorage for MemoryStorageProvider {
+    fn from_config(_config: &crate::config::DdkConfig) -> anyhow::Result<Self> {
+        Ok(MemoryStorageProvider::new())
+    }
+
+    fn list_peers(&self) -> anyhow::Result<Vec<PeerInformation>> {
+        Ok(self.store.read().unwrap().peers.clone())
+    }
+
+    fn save_peer(&self, peer: PeerInformation) -> anyhow::Result<()> {
+        let mut store = self.store.write().unwrap();
+        if !store.peers.contains(&peer) {
+            store.peers.push(peer);
+        }
+        Ok(())
+    }
+
+    fn save_pending_outbound(&self, pending: PendingOutbound) -> anyhow::Result<()> {
+        self.store
+            .write()
+            .unwrap()
+            .pending_outbound
+            .insert(pending.id.clone(), pending);
+        Ok(())
+    }
+
+    fn list_pending_outbound(&self) -> anyhow::Result<Vec<PendingOutbound>> {
+        Ok(self
+            .store
+            .read()
+            .unwrap()
+            .pending_outbound
+            .values()
+            .cloned()
+            .collect())
+    }
+
+    fn remove_pending_outbound(&self, id: &str) -> anyhow::Result<()> {
+        self.store.write().unwrap().pending_outbound.remove(id);
+        Ok(())
+    }
+
+    fn queue_inbound_message(&self, counterparty: PublicKey, message: &Message) -> anyhow::Result<bool> {
+        let pending = PendingInbound::new(counterparty, message);
+        let mut store = self.store.write().unwrap();
+        if store.processed_inbound.contains(&pending.id) {
+            return Ok(false);
+        }
+        store
+            .inbound_messages
+            .entry(pending.id.clone())
+            .or_insert(pending);
+        Ok(true)
+    }
+
+    fn ack_inbound_message(&self, id: &str) -> anyhow::Result<()> {
+        let mut store = self.store.write().unwrap();
+        store.processed_inbound.insert(id.to_string());
+        store.inbound_messages.remove(id);
+        Ok(())
+    }
+
+    fn pending_inbound_messages(&self) -> anyhow::Result<Vec<PendingInbound>> {
+        let mut pending = self
+            .store
+            .read()
+            .unwrap()
+            .inbound_messages
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+        pending.sort_by_key(|p| p.received_at);
+        Ok(pending)
+    }
+
+    fn record_inbound_failure(&self, id: &str) -> anyhow::Result<u32> {
+        let mut store = self.store.write().unwrap();
+        Ok(store.inbound_messages.get_mut(id).map_or(0, |pending| {
+            pending.attempts += 1;
+            pending.attempts
+        }))
+    }
+
+    fn pending_inbound_count(&self) -> anyhow::Result<usize> {
+        Ok(self.store.read().unwrap().inbound_messages.len())
+    }
+
+    fn append_audit_entry(&self, entry: AuditEntry) -> anyhow::Result<u64> {
+        Ok(self.store.write().unwrap().append_audit(entry))
+    }
+
+    fn audit_entries(&self, from: u64) -> anyhow::Result<Vec<AuditEntry>> {
+        Ok(self
+            .store
+            .read()
+            .unwrap()
+            .audit_log
+            .iter()
+            .filter(|entry| entry.sequence >= from)
+            .cloned()
+            .collect())
+    }
+
+    fn get_contract_rates(&self, contract_id: &ContractId) -> anyhow::Result<Option<ContractRates>> {
+        Ok(self
+            .store
+            .read()
+            .unwrap()
+            .contract_rates
+            .get(contract_id)
+            .cloned())
+    }
+
+    fn save_contract_rates(&self, contract_id: &ContractId, rates: ContractRates) -> anyhow::Result<()> {
+        self.store
+            .write()
+            .unwrap()
+            .contract_rates
+            .insert(*contract_id, rates);
+        Ok(())
+    }
+
+    fn save_contract_settlement(&self, settlement: &ContractSettlement) -> anyhow::Result<()> {
+        self.store
+            .write()
+            .unwrap()
+            .contract_settlements
+            .insert(settlement.contract_id, settlement.clone());
+        Ok(())
+    }
+
+    fn list_contract_settlements(&self) -> anyhow::Result<Vec<ContractSettlement>> {
+        Ok(self
+            .store
+            .read()
+            .unwrap()
+            .contract_settlements
+            .values()
+            .cloned()
+            .collect())
+    }
+
+    fn save_announcement(&self, announcement: &OracleAnnouncement) -> anyhow::Result<()> {
+        self.store
+            .write()
+            .unwrap()
+            .announcements
+            .insert(AnnouncementCache::key(announcement), announcement.clone());
+        Ok(())
+    }
+
+    fn get_announcement(
+        &self,
+        oracle: &XOnlyPublicKey,
+        event_id: &str,
+    ) -> anyhow::Result<Option<OracleAnnouncement>> {
+        Ok(self
+            .store
+            .read()
+            .unwrap()
+            .announcements
+            .get(&(*oracle, event_id.to_string()))
+            .cloned())
+    }
+
+    fn evict_announcements(&self, matured_before: u64) -> anyhow::Result<usize> {
+        let mut store = self.store.write().unwrap();
+        let before = store.announcements.len();
+        store.announcements.retain(|_, announcement| {
+            announcement.oracle_event.event_maturity_epoch as u64 >= matured_before
+        });
+        Ok(before - store.announcements.len())
+    }
+
+    fn save_attestation(&self, event_id: &str, attestation: &OracleAttestation) -> anyhow::Result<()> {
+        self.store.write().unwrap().attestations.insert(
+            (attestation.oracle_public_key, event_id.to_string()),
+            attestation.clone(),
+        );
+        Ok(())
+    }
+
+    fn get_attestation(
+        &self,
+        oracle: &XOnlyPublicKey,
+        event_id: &str,
+    ) -> anyhow::Result<Option<OracleAttestation>> {
+        Ok(self
+            .store
+            .read()
+            .unwrap()
+            .attestations
+            .get(&(*oracle, event_id.to_string()))
+            .cloned())
+    }
+
+    fn save_contract_transactions(&self, records: &[ContractTransaction]) -> anyhow::Result<()> {
+        let mut store = self.store.write().unwrap();
+        for record in records {
+            store.contract_transactions.insert(record.txid, *record);
+        }
+        Ok(())
+    }
+
+    fn list_contract_transactions(&self) -> anyhow::Result<Vec<ContractTransaction>> {
+        Ok(self
+            .store
+            .read()
+            .unwrap()
+            .contract_transactions
+            .values()
+            .copied()
+            .collect())
+    }
+
+    fn set_contract_metadata(&self, contract_id: &ContractId, metadata: ContractMetadata) -> anyhow::Result<()> {
+        self.store
+            .write()
+            .unwrap()
+            .contract_metadata
+            .insert(*contract_id, metadata);
+        Ok(())
+    }
+
+    fn get_contract_metadata(&self, contract_id: &ContractId) -> anyhow::Result<Option<ContractMetadata>> {
+        Ok(self
+            .store
+            .read()
+            .unwrap()
+            .contract_metadata
+            .get(contract_id)
+            .cloned())
+    }
+
+    /// Contracts are as old as the `created_at` of their metadata. Without metadata they are
+    /// archived as soon as they are finished.
+    fn archive_closed_contracts(&self, older_than: Duration) -> anyhow::Result<usize> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+        let mut store = self.store.write().unwrap();
+        let mut archived = vec![];
+        for (id, data) in &store.contracts {
+            let created_at = store
+                .contract_metadata
+                .get(id)
+                .map(|metadata| metadata.created_at)
+                .unwrap_or_default();
+            if is_archivable(&deserialize_contract_bytes(data)?, created_at, now, older_than) {
+                archived.push(*id);
+            }
+        }
+        for id in &archived {
+            if let Some(data) = store.contracts.remove(id) {
+                store.archived_contracts.insert(*id, (now, data));
+            }
+        }
+        Ok(archived.len())
+    }
+
+    fn get_archived_contracts(&self) -> anyhow::Result<Vec<Contract>> {
+        let store = self.store.read().unwrap();
+        let mut contracts = vec![];
+        for (_, data) in store.archived_contracts.values() {
+            contracts.push(deserialize_contract_bytes(data)?);
+        }
+        Ok(contracts)
+    }
+
+    fn prune_archive(&self, older_than: Duration) -> anyhow::Result<usize> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+        let mut store = self.store.write().unwrap();
+        let expired: Vec<ContractId> = store
+            .archived_contracts
+            .iter()
+            .filter(|(_, (archived_at, _))| archived_at.saturating_add(older_than.as_secs()) <= now)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in &expired {
+            store.archived_contracts.remove(id);
+            store.contract_metadata.remove(id);
+        }
+        Ok(expired.len())
+    }
+
+    fn vacuum_signers(&self, options: &SignerVacuumOptions) -> anyhow::Result<SignerVacuumReport> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+        self.vacuum_signers_at(now, options)
+    }
+
+    fn maintenance(&self) -> anyhow::Result<bool> {
+        Ok(self.store.read().unwrap().maintenance)
+    }
+
+    fn set_maintenance(&self, enabled: bool) -> anyhow::Result<()> {
+        self.store.write().unwrap().maintenance = enabled;
+        Ok(())
+    }
+
+    fn get_transport_cursor(&self, transport: &str) -> anyhow::Result<Option<u64>> {
+        Ok(self.store.read().unwrap().transport_cursors.get(transport).copied())
+    }
+
+    fn save_transport_cursor(&self, transport: &str, cursor: u64) -> anyhow::Result<()> {
+        self.store
+            .write()
+            .unwrap()
+            .transport_cursors
+            .insert(transport.to_string(), cursor);
+        Ok(())
+    }
+
+    fn list_reserved_utxos(&self) -> anyhow::Result<Vec<OutPoint>> {
+        Ok(self.store.read().unwrap().reserved_utxos.clone())
+    }
+
+    fn save_reserved_utxos(&self, outpoints: &[OutPoint]) -> anyhow::Result<()> {
+        self.store.write().unwrap().reserved_utxos = outpoints.to_vec();
+        Ok(())
+    }
+
+    fn list_frozen_utxos(&self) -> anyhow::Result<Vec<OutPoint>> {
+        Ok(self.store.read().unwrap().frozen_utxos.clone())
+    }
+
+    fn save_frozen_utxos(&self, outpoints: &[OutPoint]) -> anyhow::Result<()> {
+        self.store.write().unwrap().frozen_utxos = outpoints.to_vec();
+        Ok(())
+    }
+
+    fn list_peer_bans(&self) -> anyhow::Result<Vec<PeerBan>> {
+        Ok(self.store.read().unwrap().peer_bans.clone())
+    }
+
+    fn save_peer_bans(&self, bans: &[PeerBan]) -> anyhow::Result<()> {
+        self.store.write().unwrap().peer_bans = bans.to_vec();
+        Ok(())
+    }
+
+    fn list_pending_broadcasts(&self) -> anyhow::Result<Vec<PendingBroadcast>> {
+        Ok(self.store.read().unwrap().pending_broadcasts.clone())
+    }
+
+    fn save_pending_broadcasts(&self, pending: &[PendingBroadcast]) -> anyhow::Result<()> {
+        self.store.write().unwrap().pending_broadcasts = pending.to_vec();
+        Ok(())
+    }
+
+    fn get_fee_estimates(&self) -> anyhow::Result<Option<StoredFees>> {
+        Ok(self.store.read().unwrap().fee_estimates.clone())
+    }
+
+    fn save_fee_estimates(&self, fees: &StoredFees) -> anyhow::Result<()> {
+        self.store.write().unwrap().fee_estimates = Some(fees.clone());
+        Ok(())
+    }
+
+    fn storage_stats(&self) -> anyhow::Result<StorageStats> {
+        let store = self.store.read().unwrap();
+        let mut contracts_by_state = HashMap::new();
+        for contract in store.contracts()? {
+            *contracts_by_state
+                .entry(ContractState::from(&contract))
+                .or_default() += 1;
+        }
+        let tree_sizes = BTreeMap::from([
+            ("contracts".to_string(), store.contracts.len()),
+            ("channels".to_string(), store.channels.len()),
+            ("signers".to_string(), store.signers.len()),
+            ("key_usage".to_string(), store.key_usage.len()),
+            ("pending_outbound".to_string(), store.pending_outbound.len()),
+            ("inbound_messages".to_string(), store.inbound_messages.len()),
+            ("processed_inbound".to_string(), store.processed_inbound.len()),
+            ("audit_log".to_string(), store.audit_log.len()),
+            ("contract_rates".to_string(), store.contract_rates.len()),
+            (
+                "contract_settlements".to_string(),
+                store.contract_settlements.len(),
+            ),
+            (
+                "oracle_announcements".to_string(),
+                store.announcements.len(),
+            ),
+            (
+                "oracle_attestations".to_string(),
+                store.attestations.len(),
+            ),
+            (
+                "contract_transactions".to_string(),
+                store.contract_transactions.len(),
+            ),
+            ("contract_metadata".to_string(), store.contract_metadata.len()),
+            (
+                "archived_contracts".to_string(),
+                store.archived_contracts.len(),
+            ),
+        ]);
+
+        Ok(StorageStats {
+            size_on_disk: None,
+            tree_sizes,
+            wallet_changesets: store.wallet_changesets,
+            contracts_by_state,
+            wallet: Default::default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deserialize_object<T: Serializable>(serialized: &[u8]) -> T {
+        let mut cursor = ::lightning::io::Cursor::new(&serialized);
+        T::deserialize(&mut cursor).unwrap()
+    }
+
+    fn insert_offered_signed_and_confirmed(storage: &MemoryStorageProvider) {
+        let offered_contract: OfferedContract =
+            deserialize_object(include_bytes!("../../tests/data/dlc_storage/sled/Offered"));
+        storage.create_contract(&offered_contract).unwrap();
+
+        let fixtures: [(&[u8], fn(SignedContract) -> Contract); 4] = [
+            (include_bytes!("../../tests/data/dlc_storage/sled/Signed"), Contract::Signed),
+            (include_bytes!("../../tests/data/dlc_storage/sled/Signed1"), Contract::Signed),
+            (include_bytes!("../../tests/data/dlc_storage/sled/Confirmed"), Contract::Confirmed),
+            (include_bytes!("../../tests/data/dlc_storage/sled/Confirmed1"), Contract::Confirmed),
+        ];
+        for (serialized, state) in fixtures {
+            storage
+                .update_contract(&state(deserialize_object(serialized)))
+                .unwrap();
+        }
+
+        let preclosed = Contract::PreClosed(deserialize_object(include_bytes!(
+            "../../tests/data/dlc_storage/sled/PreClosed"
+        )));
+        storage.update_contract(&preclosed).unwrap();
+    }
+
+    #[test]
+    fn update_contract_removes_temporary_id() {
+        let storage = MemoryStorageProvider::new();
+        let offered: OfferedContract =
+            deserialize_object(include_bytes!("../../tests/data/dlc_storage/sled/Offered"));
+        storage.create_contract(&offered).unwrap();
+
+        let accepted = Contract::Accepted(deserialize_object(include_bytes!(
+            "../../tests/data/dlc_storage/sled/Accepted"
+        )));
+        storage.update_contract(&accepted).unwrap();
+
+        assert!(matches!(
+            storage.get_contract(&accepted.get_id()).unwrap(),
+            Some(Contract::Accepted(_))
+        ));
+        assert!(storage.get_contract(&offered.id).unwrap().is_none());
+        assert_eq!(storage.get_contracts().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn contracts_by_state() {
+        let storage = MemoryStorageProvider::new();
+        insert_offered_signed_and_confirmed(&storage);
+
+        assert_eq!(storage.get_signed_contracts().unwrap().len(), 2);
+        assert_eq!(storage.get_confirmed_contracts().unwrap().len(), 2);
+        assert_eq!(storage.get_contract_offers().unwrap().len(), 1);
+        assert_eq!(storage.get_preclosed_contracts().unwrap().len(), 1);
+        assert_eq!(storage.get_contracts().unwrap().len(), 6);
+        assert_eq!(
+            storage
+                .storage_stats()
+                .unwrap()
+                .contracts_by_state
+                .values()
+                .sum::<usize>(),
+            6
+        );
+    }
+
+    #[test]
+    fn clones_share_data() {
+        let storage = MemoryStorageProvider::new();
+        let mut persister = storage.clone();
+        assert!(WalletPersister::initialize(&mut persister)
+            .unwrap()
+            .network
+            .is_none());
+
+        let changeset = ChangeSet {
+            network: Some(bitcoin::Network::Regtest),
+            ..Default::default()
+        };
+        WalletPersister::persist(&mut persister, &changeset).unwrap();
+        storage.set_maintenance(true).unwrap();
+
+        let mut reopened = storage.clone();
+        assert_eq!(
+            WalletPersister::initialize(&mut reopened).unwrap().network,
+            Some(bitcoin::Network::Regtest)
+        );
+        assert!(persister.maintenance().unwrap());
+    }
+
+    #[test]
+    fn inbound_messages_are_pending_until_acknowledged() {
+        let storage = MemoryStorageProvider::new();
+        let counterparty = PublicKey::from_secret_key(
+            &bitcoin::secp256k1::Secp256k1::new(),
+            &SecretKey::from_slice(&[1u8; 32]).unwrap(),
+        );
+        let offer: dlc_messages::OfferDlc =
+            serde_json::from_str(include_str!("../../tests/data/dlc/offer.json")).unwrap();
+        let mut second = offer.clone();
+        second.temporary_contract_id = [2u8; 32];
+        let (first, second) = (Message::Offer(offer), Message::Offer(second));
+
+        assert!(storage.queue_inbound_message(counterparty, &first).unwrap());
+        assert!(storage.queue_inbound_message(counterparty, &second).unwrap());
+        // A message that is still pending can be received again.
+        assert!(storage.queue_inbound_message(counterparty, &first).unwrap());
+        let pending = storage.pending_inbound_messages().unwrap();
+        assert_eq!(pending.len(), 2);
+        assert_eq!(pending[0].id, crate::transport::message_id(&first));
+        assert!(matches!(pending[0].message().unwrap(), Message::Offer(o) if o.temporary_contract_id != [2u8; 32]));
+
+        storage.ack_inbound_message(&pending[0].id).unwrap();
+        assert_eq!(storage.pending_inbound_messages().unwrap().len(), 1);
+        assert!(!storage.queue_inbound_message(counterparty, &first).unwrap());
+        assert_eq!(storage.pending_inbound_messages().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn chain_monitor_round_trip() {
+        let storage = MemoryStorageProvider::new();
+        assert!(storage.get_chain_monitor().unwrap().is_none());
+        let chain_monitor = ChainMonitor::new(123);
+        storage.persist_chain_monitor(&chain_monitor).unwrap();
+        assert_eq!(storage.get_chain_monitor().unwrap(), Some(chain_monitor));
+    }
+
+    #[test]
+    fn contract_transactions_are_kept_after_close() {
+        let storage = MemoryStorageProvider::new();
+        let signed: SignedContract =
+            deserialize_object(include_bytes!("../../tests/data/dlc_storage/sled/Signed"));
+        let records = crate::wallet::history::contract_transactions(&Contract::Signed(signed));
+        storage.save_contract_transactions(&records).unwrap();
+        // Saving again replaces the records instead of adding duplicates.
+        storage.save_contract_transactions(&records).unwrap();
+
+        let mut stored = storage.list_contract_transactions().unwrap();
+        stored.sort_by_key(|record| record.txid);
+        let mut expected = records.clone();
+        expected.sort_by_key(|record| record.txid);
+        assert_eq!(stored, expected);
+    }
+}
@@ -1,3 +1,251 @@
+mod memory;
+#[cfg(feature = "postgres")]
+mod postgres;
 mod sled;
+#[cfg(feature = "sqlite")]
+mod sqlite;
 
-pub use sled::SledStorageProvider;
+pub use memory::MemoryStorageProvider;
+#[cfg(feature = "postgres")]
+pub use postgres::PostgresStorageProvider;
+pub use sled::{
+    read_signer_archive, BackupSummary, SledStorageProvider, BACKUP_FORMAT_VERSION,
+    SLED_SCHEMA_VERSION,
+};
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteStorageProvider;
+
+use crate::contract::ContractState;
+use crate::signer::KeyUsage;
+use crate::wallet::WalletStats;
+use bitcoin::secp256k1::PublicKey;
+use dlc_manager::channel::Channel;
+use dlc_manager::contract::Contract;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Key ids are stored hex encoded. Entries with malformed keys are skipped.
+pub(crate) fn decode_key_id(key: &[u8]) -> Option<[u8; 32]> {
+    hex::decode(key).ok()?.try_into().ok()
+}
+
+/// Directory of the sled contract store inside the data directory.
+pub const SLED_DB_DIR: &str = "sled_db";
+
+/// Size and count information for capacity planning.
+#[derive(Debug, Clone, Default)]
+pub struct StorageStats {
+    /// Bytes used on disk, if the backend can report it.
+    pub size_on_disk: Option<u64>,
+    /// Number of entries in each tree or table.
+    pub tree_sizes: BTreeMap<String, usize>,
+    /// Wallet changesets waiting to be compacted.
+    pub wallet_changesets: usize,
+    /// Number of contracts in each state.
+    pub contracts_by_state: HashMap<ContractState, usize>,
+    /// Address, utxo, and transaction counts from the wallet.
+    pub wallet: WalletStats,
+}
+
+/// Result of [crate::DdkStorage::verify_integrity].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IntegrityReport {
+    /// Contracts that could be read.
+    pub contracts_checked: usize,
+    /// Channels that could be read.
+    pub channels_checked: usize,
+    pub issues: Vec<IntegrityIssue>,
+}
+
+impl IntegrityReport {
+    /// Whether no issue was found.
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// A stored record that failed the integrity check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IntegrityIssue {
+    /// Tree or table of the record, named like in [StorageStats::tree_sizes].
+    pub tree: String,
+    /// Hex encoded key of the record.
+    pub key: String,
+    /// First byte of the stored value, the state of contracts and channels. `None` when the
+    /// record is missing or could not be decrypted.
+    pub prefix: Option<u8>,
+    pub kind: IntegrityIssueKind,
+}
+
+/// What is wrong with a record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IntegrityIssueKind {
+    /// The record does not deserialize.
+    Unreadable(String),
+    /// A signed channel references a contract that is not stored. Holds the hex encoded
+    /// contract id.
+    MissingContract(String),
+    /// A record under the temporary id of a contract or channel that is also stored under its
+    /// final id, left by an update that did not finish.
+    TemporaryIdLeftover,
+    /// A secondary index entry that is missing, stale, or does not match its contract.
+    IndexMismatch,
+}
+
+/// What [SledStorageProvider::repair] changed, or would change on a dry run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RepairReport {
+    pub dry_run: bool,
+    /// Temporary id records that were deleted, or moved to the final id for metadata.
+    pub removed: Vec<IntegrityIssue>,
+    /// Index entries inserted or deleted to match the contracts.
+    pub index_entries_fixed: usize,
+}
+
+/// Which contracts [crate::DdkStorage::get_contracts_paginated] returns. Unset fields match
+/// every contract.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ContractFilter {
+    pub state: Option<ContractState>,
+    pub counterparty: Option<PublicKey>,
+    /// Unix timestamp the contract must be created at or after.
+    pub created_after: Option<u64>,
+    /// Unix timestamp the contract must be created before.
+    pub created_before: Option<u64>,
+}
+
+impl ContractFilter {
+    /// Whether a contract created at `created_at` passes the filter. Contracts stored before
+    /// their creation time was recorded count as created at 0.
+    pub fn matches(&self, contract: &Contract, created_at: u64) -> bool {
+        self.state.map_or(true, |state| state == ContractState::from(contract))
+            && self
+                .counterparty
+                .map_or(true, |counterparty| counterparty == contract.get_counter_party_id())
+            && self.matches_created_at(created_at)
+    }
+
+    pub(crate) fn matches_created_at(&self, created_at: u64) -> bool {
+        self.created_after.map_or(true, |after| created_at >= after)
+            && self.created_before.map_or(true, |before| created_at < before)
+    }
+}
+
+/// Default time between runs of the [ArchivePolicy] task.
+pub const DEFAULT_ARCHIVE_INTERVAL: Duration = Duration::from_secs(60 * 60 * 24);
+
+/// When finished contracts are moved out of the contract store and when they are deleted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchivePolicy {
+    /// Closed, refunded, rejected, and failed contracts created longer ago are archived.
+    pub archive_after: Duration,
+    /// Archived contracts are deleted this long after they were archived. `None` keeps them.
+    pub prune_after: Option<Duration>,
+    /// How often the background task applies the policy. Defaults to once a day.
+    pub interval: Duration,
+}
+
+impl ArchivePolicy {
+    pub fn new(archive_after: Duration) -> ArchivePolicy {
+        ArchivePolicy {
+            archive_after,
+            prune_after: None,
+            interval: DEFAULT_ARCHIVE_INTERVAL,
+        }
+    }
+}
+
+/// Contracts moved and deleted by [crate::DlcDevKit::archive].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ArchiveReport {
+    pub archived: usize,
+    pub pruned: usize,
+}
+
+/// Whether a contract created at `created_at` is finished and old enough to archive at `now`.
+pub(crate) fn is_archivable(contract: &Contract, created_at: u64, now: u64, older_than: Duration) -> bool {
+    ContractState::from(contract).is_final() && created_at.saturating_add(older_than.as_secs()) <= now
+}
+
+/// Default time a signer is kept after its contract is closed.
+pub const DEFAULT_SIGNER_RETENTION: Duration = Duration::from_secs(60 * 60 * 24 * 30);
+
+/// Which signer entries [crate::DdkStorage::vacuum_signers] removes.
+#[derive(Debug, Clone)]
+pub struct SignerVacuumOptions {
+    /// Signers derived more recently than this are kept even if their contract is closed.
+    pub retention: Duration,
+    /// Append removed signers to an encrypted archive before deleting them.
+    pub export_before_delete: Option<SignerArchive>,
+}
+
+impl Default for SignerVacuumOptions {
+    fn default() -> Self {
+        Self {
+            retention: DEFAULT_SIGNER_RETENTION,
+            export_before_delete: None,
+        }
+    }
+}
+
+/// Encrypted file removed signers are appended to.
+#[derive(Clone)]
+pub struct SignerArchive {
+    pub path: PathBuf,
+    /// ChaCha20-Poly1305 key for the archive.
+    pub key: [u8; 32],
+}
+
+impl std::fmt::Debug for SignerArchive {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SignerArchive")
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Result of [crate::DdkStorage::vacuum_signers].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SignerVacuumReport {
+    /// Hex encoded key ids that were deleted.
+    pub removed: Vec<String>,
+    /// Signers kept because a contract or channel still uses them, or they are within retention.
+    pub kept: usize,
+    /// Signers without a usage record. They predate usage tracking and are never removed.
+    pub unattributed: usize,
+}
+
+/// Temporary and final ids of contracts and channels that are still open. Their signers are
+/// never vacuumed.
+pub(crate) fn live_negotiation_ids(contracts: &[Contract], channels: &[Channel]) -> HashSet<[u8; 32]> {
+    let mut live = HashSet::new();
+    for contract in contracts {
+        if !ContractState::from(contract).is_final() {
+            live.insert(contract.get_temporary_id());
+            live.insert(contract.get_id());
+        }
+    }
+    for channel in channels {
+        let temporary_id = match channel {
+            Channel::Offered(o) => o.temporary_channel_id,
+            Channel::Accepted(a) => a.temporary_channel_id,
+            Channel::Signed(s) => s.temporary_channel_id,
+            Channel::Closing(c) => c.temporary_channel_id,
+            _ => continue,
+        };
+        live.insert(temporary_id);
+    }
+    live
+}
+
+/// Whether a signer with `usage` may be removed at `now`.
+pub(crate) fn is_vacuumable(
+    usage: &KeyUsage,
+    live: &HashSet<[u8; 32]>,
+    now: u64,
+    options: &SignerVacuumOptions,
+) -> bool {
+    let expired = usage.created_at.saturating_add(options.retention.as_secs()) <= now;
+    expired && !live.contains(&usage.temporary_id)
+}
@@ -0,0 +1,65 @@
+CREATE TYPE contract_state AS ENUM (
+    'Offered',
+    'Accepted',
+    'Signed',
+    'Confirmed',
+    'PreClosed',
+    'Closed',
+    'Refunded',
+    'FailedAccept',
+    'FailedSign',
+    'Rejected'
+);
+CREATE TABLE contracts (
+    id BYTEA PRIMARY KEY,
+    state contract_state NOT NULL,
+    data BYTEA NOT NULL
+);
+CREATE INDEX contracts_state ON contracts (state);
+CREATE TABLE channels (
+    id BYTEA PRIMARY KEY,
+    kind SMALLINT NOT NULL,
+    signed_state SMALLINT,
+    data BYTEA NOT NULL
+);
+CREATE INDEX channels_kind ON channels (kind, signed_state);
+CREATE TABLE chain_monitor (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    data BYTEA NOT NULL
+);
+CREATE TABLE peers (
+    id BIGSERIAL PRIMARY KEY,
+    pubkey TEXT NOT NULL,
+    host TEXT NOT NULL,
+    UNIQUE (pubkey, host)
+);
+CREATE TABLE signers (
+    key_id TEXT PRIMARY KEY,
+    public_key TEXT NOT NULL,
+    data BYTEA NOT NULL
+);
+CREATE INDEX signers_public_key ON signers (public_key);
+CREATE TABLE key_usage (
+    key_id TEXT PRIMARY KEY,
+    data BYTEA NOT NULL
+);
+CREATE TABLE wallet_changesets (
+    id BIGSERIAL PRIMARY KEY,
+    data BYTEA NOT NULL
+);
+CREATE TABLE pending_outbound (
+    id TEXT PRIMARY KEY,
+    data TEXT NOT NULL
+);
+CREATE TABLE contract_rates (
+    contract_id BYTEA PRIMARY KEY,
+    data TEXT NOT NULL
+);
+CREATE TABLE contract_transactions (
+    txid BYTEA PRIMARY KEY,
+    data TEXT NOT NULL
+);
+CREATE TABLE settings (
+    key TEXT PRIMARY KEY,
+    value BYTEA NOT NULL
+);
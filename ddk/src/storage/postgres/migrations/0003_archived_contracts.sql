@@ -0,0 +1,6 @@
+CREATE TABLE archived_contracts (
+    id BYTEA PRIMARY KEY,
+    archived_at BIGINT NOT NULL,
+    data BYTEA NOT NULL
+);
+CREATE INDEX archived_contracts_archived_at ON archived_contracts (archived_at);
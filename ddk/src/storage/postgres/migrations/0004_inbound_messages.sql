@@ -0,0 +1,8 @@
+CREATE TABLE inbound_messages (
+    id TEXT PRIMARY KEY,
+    received_at BIGINT NOT NULL,
+    data TEXT NOT NULL
+);
+CREATE TABLE processed_inbound (
+    id TEXT PRIMARY KEY
+);
@@ -0,0 +1,4 @@
+CREATE TABLE audit_log (
+    sequence BIGSERIAL PRIMARY KEY,
+    data TEXT NOT NULL
+);
@@ -0,0 +1,4 @@
+CREATE TABLE contract_settlements (
+    contract_id BYTEA PRIMARY KEY,
+    data TEXT NOT NULL
+);
@@ -0,0 +1,13 @@
+CREATE TABLE oracle_announcements (
+    oracle_public_key TEXT NOT NULL,
+    event_id TEXT NOT NULL,
+    maturity BIGINT NOT NULL,
+    data TEXT NOT NULL,
+    PRIMARY KEY (oracle_public_key, event_id)
+);
+CREATE TABLE oracle_attestations (
+    oracle_public_key TEXT NOT NULL,
+    event_id TEXT NOT NULL,
+    data TEXT NOT NULL,
+    PRIMARY KEY (oracle_public_key, event_id)
+);
@@ -0,0 +1,175 @@
+//! Finished contracts moved out of the contract tree, so prefix scans of the contract tree only
+//! walk contracts that can still change.
+//!
+//! Values are the archive time as a big endian unix timestamp followed by the serialized
+//! contract, keyed by contract id. Encrypted storage seals the whole value.
+use super::contract::ContractPrefix;
+use super::{index, SledStorageProvider};
+use crate::contract::ContractState;
+use crate::util::deserialize_contract_bytes;
+use dlc_manager::contract::Contract;
+use dlc_manager::ContractId;
+use sled::transaction::UnabortableTransactionError;
+use sled::Transactional;
+use std::time::Duration;
+
+fn archived_at(value: &[u8]) -> Option<u64> {
+    Some(u64::from_be_bytes(value.get(..8)?.try_into().ok()?))
+}
+
+impl SledStorageProvider {
+    /// Archive the finished contracts created more than `older_than` before `now`. The state is
+    /// read from the prefix byte and every contract moves in one transaction, so a crash leaves
+    /// each record either in the contract tree or in the archive.
+    pub(crate) fn archive_closed_contracts_at(
+        &self,
+        now: u64,
+        older_than: Duration,
+    ) -> anyhow::Result<usize> {
+        let final_prefixes: Vec<u8> = ContractState::ALL
+            .into_iter()
+            .filter(ContractState::is_final)
+            .map(|state| ContractPrefix::from(state).into())
+            .collect();
+        let contract_tree = self.contract_tree()?;
+        let index_tree = self.contract_index_tree()?;
+        let archive_tree = self.contract_archive_tree()?;
+
+        let mut candidates = vec![];
+        for entry in contract_tree.iter() {
+            let (id, stored) = entry?;
+            let value = self.open(stored.clone())?;
+            if !value.first().is_some_and(|prefix| final_prefixes.contains(prefix)) {
+                continue;
+            }
+            let created_at = index::created_at(&index_tree, &id)?.unwrap_or_default();
+            if created_at.saturating_add(older_than.as_secs()) <= now {
+                let archived = self.seal([&now.to_be_bytes()[..], &value].concat())?;
+                candidates.push((ContractId::try_from(id.as_ref())?, stored, archived));
+            }
+        }
+
+        let archived = (&contract_tree, &index_tree, &archive_tree)
+            .transaction::<_, _, UnabortableTransactionError>(|(contracts, index, archive)| {
+                let mut archived = 0;
+                for (id, stored, value) in &candidates {
+                    // Deleted since it was selected.
+                    let Some(current) = contracts.remove(id)? else {
+                        continue;
+                    };
+                    // Updated since it was selected.
+                    if current != *stored {
+                        contracts.insert(id, current)?;
+                        continue;
+                    }
+                    index::remove_entries(index, id)?;
+                    archive.insert(id, value.clone())?;
+                    archived += 1;
+                }
+                Ok(archived)
+            })
+            .map_err(|e| anyhow::anyhow!("Could not archive contracts. error={}", e))?;
+        archive_tree.flush()?;
+        Ok(archived)
+    }
+
+    pub(crate) fn archived_contracts(&self) -> anyhow::Result<Vec<Contract>> {
+        let mut contracts = vec![];
+        for value in self.contract_archive_tree()?.iter().values() {
+            let value = self.open(value?)?;
+            contracts.push(deserialize_contract_bytes(&value[8..].to_vec())?);
+        }
+        Ok(contracts)
+    }
+
+    /// Delete contracts archived more than `older_than` before `now`, with their metadata.
+    pub(crate) fn prune_archive_at(&self, now: u64, older_than: Duration) -> anyhow::Result<usize> {
+        let archive_tree = self.contract_archive_tree()?;
+        let metadata_tree = self.contract_metadata_tree()?;
+        let mut expired = vec![];
+        for entry in archive_tree.iter() {
+            let (id, value) = entry?;
+            let archived_at = archived_at(&self.open(value)?).unwrap_or_default();
+            if archived_at.saturating_add(older_than.as_secs()) <= now {
+                expired.push(id);
+            }
+        }
+
+        (&archive_tree, &metadata_tree)
+            .transaction::<_, _, UnabortableTransactionError>(|(archive, metadata)| {
+                for id in &expired {
+                    archive.remove(id)?;
+                    metadata.remove(id)?;
+                }
+                Ok(())
+            })
+            .map_err(|e| anyhow::anyhow!("Could not prune contract archive. error={}", e))?;
+        archive_tree.flush()?;
+        Ok(expired.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::metadata::ContractMetadata;
+    use crate::DdkStorage;
+    use dlc_manager::contract::ser::Serializable;
+    use dlc_manager::Storage;
+
+    fn deserialize_object<T: Serializable>(serialized: &[u8]) -> T {
+        T::deserialize(&mut lightning::io::Cursor::new(&serialized)).unwrap()
+    }
+
+    #[test]
+    fn finished_contracts_are_archived_then_pruned() {
+        let path = "tests/data/dlc_storage/sleddb/archive";
+        {
+            let storage = SledStorageProvider::new(path).unwrap();
+            let offered = deserialize_object(include_bytes!(
+                "../../../tests/data/dlc_storage/sled/Offered"
+            ));
+            storage.create_contract(&offered).unwrap();
+            let closed = Contract::Closed(deserialize_object(include_bytes!(
+                "../../../tests/data/dlc_storage/sled/Closed"
+            )));
+            storage.update_contract(&closed).unwrap();
+            storage
+                .set_contract_metadata(&closed.get_id(), ContractMetadata::new())
+                .unwrap();
+            let now = index::created_at(&storage.contract_index_tree().unwrap(), &closed.get_id())
+                .unwrap()
+                .unwrap();
+
+            let week = Duration::from_secs(60 * 60 * 24 * 7);
+            assert_eq!(storage.archive_closed_contracts_at(now, week).unwrap(), 0);
+
+            let later = now + week.as_secs();
+            assert_eq!(storage.archive_closed_contracts_at(later, week).unwrap(), 1);
+            assert!(storage.get_contract(&closed.get_id()).unwrap().is_none());
+            assert!(storage.get_contract(&offered.id).unwrap().is_some());
+            let archived = storage.get_archived_contracts().unwrap();
+            assert_eq!(archived.len(), 1);
+            assert_eq!(archived[0].get_id(), closed.get_id());
+            assert!(storage
+                .get_contracts_paginated(Default::default(), 0, 10)
+                .unwrap()
+                .iter()
+                .all(|c| c.get_id() != closed.get_id()));
+
+            assert_eq!(storage.prune_archive_at(later + 60, week).unwrap(), 0);
+            assert_eq!(
+                storage
+                    .prune_archive_at(later + week.as_secs(), week)
+                    .unwrap(),
+                1
+            );
+            assert!(storage.get_archived_contracts().unwrap().is_empty());
+            assert!(storage
+                .get_contract_metadata(&closed.get_id())
+                .unwrap()
+                .is_none());
+        }
+        std::fs::remove_dir_all(path).unwrap();
+    }
+}
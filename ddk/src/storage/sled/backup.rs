@@ -0,0 +1,392 @@
+//! Backups of the sled database as a single stream.
+//!
+//! The stream does not depend on sled's file format, so it survives sled upgrades and can be
+//! read by other backends. Layout, with integers big endian:
+//!
+//! ```text
+//! magic "DDKBACKUP" | format version u16 | schema version u32
+//! ( TREE  | name length u8 | name
+//! | ENTRY | key length u32 | key | value length u32 | value )*
+//! END | sha256 of everything before END
+//! ```
+//!
+//! Trees are named like in [crate::storage::StorageStats] and values are stored as the provider
+//! serializes them, decrypted when the storage is encrypted. The backup holds the signer keys
+//! unencrypted, so it must be stored as carefully as the database itself.
+use super::migration::SLED_SCHEMA_VERSION;
+use super::{
+    SledStorageProvider, CHAIN_MONITOR_TREE, CHANNEL_TREE, CONTRACT_ARCHIVE_TREE,
+    CONTRACT_INDEX_TREE, CONTRACT_METADATA_TREE, CONTRACT_RATES_TREE, CONTRACT_SETTLEMENTS_TREE,
+    CONTRACT_TRANSACTIONS_TREE, ORACLE_ANNOUNCEMENTS_TREE, ORACLE_ATTESTATIONS_TREE, FEES_TREE,
+    AUDIT_LOG_TREE, CONTRACT_TREE, INBOUND_MESSAGE_TREE, KEY_USAGE_TREE, PEER_TREE, PENDING_OUTBOUND_TREE,
+    PROCESSED_INBOUND_TREE, SETTINGS_TREE, SIGNER_TREE, WALLET_TREE,
+};
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+
+/// Version of the backup stream written by this build.
+pub const BACKUP_FORMAT_VERSION: u16 = 1;
+
+const MAGIC: &[u8; 9] = b"DDKBACKUP";
+const TREE: u8 = 1;
+const ENTRY: u8 = 2;
+const END: u8 = 0;
+/// Largest key or value accepted when reading, so a corrupted length fails instead of
+/// allocating.
+const MAX_LEN: usize = 64 * 1024 * 1024;
+
+/// Every tree except the meta tree, whose schema version is part of the header.
+const BACKUP_TREES: [u8; 21] = [
+    CONTRACT_TREE,
+    CHANNEL_TREE,
+    CHAIN_MONITOR_TREE,
+    PEER_TREE,
+    SIGNER_TREE,
+    WALLET_TREE,
+    PENDING_OUTBOUND_TREE,
+    CONTRACT_RATES_TREE,
+    KEY_USAGE_TREE,
+    SETTINGS_TREE,
+    CONTRACT_TRANSACTIONS_TREE,
+    CONTRACT_METADATA_TREE,
+    CONTRACT_INDEX_TREE,
+    CONTRACT_ARCHIVE_TREE,
+    INBOUND_MESSAGE_TREE,
+    PROCESSED_INBOUND_TREE,
+    AUDIT_LOG_TREE,
+    CONTRACT_SETTLEMENTS_TREE,
+    ORACLE_ANNOUNCEMENTS_TREE,
+    ORACLE_ATTESTATIONS_TREE,
+    FEES_TREE,
+];
+
+/// Entries written or restored per tree.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BackupSummary {
+    pub schema_version: u32,
+    pub entries: BTreeMap<String, usize>,
+}
+
+struct HashingWriter<W> {
+    inner: W,
+    engine: sha256::HashEngine,
+}
+
+impl<W: Write> HashingWriter<W> {
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        self.engine.input(bytes);
+        self.inner.write_all(bytes)
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        self.write(&(bytes.len() as u32).to_be_bytes())?;
+        self.write(bytes)
+    }
+}
+
+struct HashingReader<R> {
+    inner: R,
+    engine: sha256::HashEngine,
+}
+
+impl<R: Read> HashingReader<R> {
+    fn read<const N: usize>(&mut self) -> anyhow::Result<[u8; N]> {
+        let mut buf = [0u8; N];
+        self.inner
+            .read_exact(&mut buf)
+            .map_err(|e| anyhow::anyhow!("Backup stream ended early. error={}", e))?;
+        self.engine.input(&buf);
+        Ok(buf)
+    }
+
+    fn read_vec(&mut self, len: usize) -> anyhow::Result<Vec<u8>> {
+        if len > MAX_LEN {
+            return Err(anyhow::anyhow!("Backup record of {} bytes is too large.", len));
+        }
+        let mut buf = vec![0u8; len];
+        self.inner
+            .read_exact(&mut buf)
+            .map_err(|e| anyhow::anyhow!("Backup stream ended early. error={}", e))?;
+        self.engine.input(&buf);
+        Ok(buf)
+    }
+
+    fn read_bytes(&mut self) -> anyhow::Result<Vec<u8>> {
+        let len = u32::from_be_bytes(self.read()?) as usize;
+        self.read_vec(len)
+    }
+}
+
+fn tree_id(name: &str) -> Option<u8> {
+    BACKUP_TREES
+        .into_iter()
+        .find(|id| SledStorageProvider::tree_name(&[*id]) == name)
+}
+
+impl SledStorageProvider {
+    /// Write every tree to `writer`. Writes made while the backup runs may or may not be
+    /// included, stop the node for a consistent snapshot.
+    pub fn export_backup(&self, writer: impl Write) -> anyhow::Result<BackupSummary> {
+        let schema_version = self.schema_version()?.unwrap_or(0);
+        let mut writer = HashingWriter {
+            inner: writer,
+            engine: sha256::HashEngine::default(),
+        };
+        writer.write(MAGIC)?;
+        writer.write(&BACKUP_FORMAT_VERSION.to_be_bytes())?;
+        writer.write(&schema_version.to_be_bytes())?;
+
+        let mut summary = BackupSummary {
+            schema_version,
+            entries: BTreeMap::new(),
+        };
+        for id in BACKUP_TREES {
+            let name = Self::tree_name(&[id]);
+            writer.write(&[TREE, name.len() as u8])?;
+            writer.write(name.as_bytes())?;
+            let mut count = 0;
+            for entry in self.db.open_tree([id])?.iter() {
+                let (key, mut value) = entry?;
+                if self.is_encrypted_tree(id) {
+                    value = self.open(value)?;
+                }
+                writer.write(&[ENTRY])?;
+                writer.write_bytes(&key)?;
+                writer.write_bytes(&value)?;
+                count += 1;
+            }
+            summary.entries.insert(name, count);
+        }
+
+        writer.write(&[END])?;
+        let checksum = sha256::Hash::from_engine(writer.engine);
+        writer.inner.write_all(checksum.as_byte_array())?;
+        writer.inner.flush()?;
+        Ok(summary)
+    }
+
+    /// Restore a backup written by [SledStorageProvider::export_backup]. The whole stream is
+    /// read and its checksum verified before anything is written, so a corrupted backup leaves
+    /// the database untouched. A database with data is only replaced when `force` is set.
+    /// Backups of an older layout are migrated after the restore, which encrypted storage does
+    /// not support: restore those to a plaintext database and encrypt it in place.
+    pub fn import_backup(&self, reader: impl Read, force: bool) -> anyhow::Result<BackupSummary> {
+        let mut reader = HashingReader {
+            inner: reader,
+            engine: sha256::HashEngine::default(),
+        };
+        if &reader.read::<9>()? != MAGIC {
+            return Err(anyhow::anyhow!("Not a ddk backup."));
+        }
+        let format_version = u16::from_be_bytes(reader.read()?);
+        if format_version != BACKUP_FORMAT_VERSION {
+            return Err(anyhow::anyhow!(
+                "Unsupported backup format version {}.",
+                format_version
+            ));
+        }
+        let schema_version = u32::from_be_bytes(reader.read()?);
+        if schema_version > SLED_SCHEMA_VERSION {
+            return Err(anyhow::anyhow!(
+                "Backup schema version {} is newer than this build supports ({}).",
+                schema_version,
+                SLED_SCHEMA_VERSION
+            ));
+        }
+        if schema_version < SLED_SCHEMA_VERSION && self.cipher.is_some() {
+            return Err(anyhow::anyhow!(
+                "Backup schema version {} must be restored to unencrypted storage and migrated first.",
+                schema_version
+            ));
+        }
+
+        // Tree id, name, entries, and entry count of every tree in the stream.
+        let mut trees: Vec<(u8, String, sled::Batch, usize)> = vec![];
+        loop {
+            match reader.read::<1>()?[0] {
+                TREE => {
+                    let len = reader.read::<1>()?[0] as usize;
+                    let name = String::from_utf8(reader.read_vec(len)?)?;
+                    let id = tree_id(&name)
+                        .ok_or_else(|| anyhow::anyhow!("Unknown tree {} in backup.", name))?;
+                    trees.push((id, name, sled::Batch::default(), 0));
+                }
+                ENTRY => {
+                    let key = reader.read_bytes()?;
+                    let value = reader.read_bytes()?;
+                    let (id, _, batch, count) = trees
+                        .last_mut()
+                        .ok_or_else(|| anyhow::anyhow!("Backup entry before any tree."))?;
+                    if self.is_encrypted_tree(*id) {
+                        batch.insert(key, self.seal(value)?);
+                    } else {
+                        batch.insert(key, value);
+                    }
+                    *count += 1;
+                }
+                END => break,
+                tag => return Err(anyhow::anyhow!("Unknown backup record {}.", tag)),
+            }
+        }
+        let checksum = sha256::Hash::from_engine(reader.engine);
+        let mut expected = [0u8; 32];
+        reader
+            .inner
+            .read_exact(&mut expected)
+            .map_err(|e| anyhow::anyhow!("Backup stream ended early. error={}", e))?;
+        if checksum.to_byte_array() != expected {
+            return Err(anyhow::anyhow!("Backup checksum does not match."));
+        }
+
+        let mut has_data = false;
+        for id in BACKUP_TREES {
+            has_data |= !self.db.open_tree([id])?.is_empty();
+        }
+        if has_data && !force {
+            return Err(anyhow::anyhow!(
+                "Storage is not empty. Pass force to replace it with the backup."
+            ));
+        }
+
+        for id in BACKUP_TREES {
+            self.db.open_tree([id])?.clear()?;
+        }
+        let mut summary = BackupSummary {
+            schema_version,
+            entries: BTreeMap::new(),
+        };
+        for (id, name, batch, count) in trees {
+            self.db.open_tree([id])?.apply_batch(batch)?;
+            summary.entries.insert(name, count);
+        }
+        self.set_schema_version(schema_version)?;
+        self.db.flush()?;
+        self.migrate()?;
+        Ok(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::metadata::ContractMetadata;
+    use crate::transport::PeerInformation;
+    use crate::DdkStorage;
+    use dlc_manager::contract::ser::Serializable;
+    use dlc_manager::contract::Contract;
+    use dlc_manager::Storage;
+
+    fn deserialize_object<T: Serializable>(serialized: &[u8]) -> T {
+        T::deserialize(&mut lightning::io::Cursor::new(&serialized)).unwrap()
+    }
+
+    fn populated(path: &str) -> SledStorageProvider {
+        let storage = SledStorageProvider::new(path).unwrap();
+        let offered = deserialize_object(include_bytes!(
+            "../../../tests/data/dlc_storage/sled/Offered"
+        ));
+        storage.create_contract(&offered).unwrap();
+        storage
+            .set_contract_metadata(&offered.id, ContractMetadata::new().with_label("backup"))
+            .unwrap();
+        let signed = Contract::Signed(deserialize_object(include_bytes!(
+            "../../../tests/data/dlc_storage/sled/Signed"
+        )));
+        storage.update_contract(&signed).unwrap();
+        let channel = deserialize_object(include_bytes!(
+            "../../../tests/data/dlc_storage/sled/OfferedChannel"
+        ));
+        storage
+            .upsert_channel(dlc_manager::channel::Channel::Offered(channel), None)
+            .unwrap();
+        storage
+            .save_peer(PeerInformation {
+                pubkey: "pubkey".into(),
+                host: "127.0.0.1:9000".into(),
+            })
+            .unwrap();
+        storage.set_maintenance(true).unwrap();
+        storage
+    }
+
+    fn contents(storage: &SledStorageProvider) -> Vec<Vec<(sled::IVec, sled::IVec)>> {
+        BACKUP_TREES
+            .into_iter()
+            .map(|id| {
+                storage
+                    .db
+                    .open_tree([id])
+                    .unwrap()
+                    .iter()
+                    .collect::<Result<Vec<_>, _>>()
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn backup_round_trips() {
+        let source_path = "tests/data/dlc_storage/sleddb/backup_source";
+        let target_path = "tests/data/dlc_storage/sleddb/backup_target";
+        {
+            let source = populated(source_path);
+            let mut backup = vec![];
+            let exported = source.export_backup(&mut backup).unwrap();
+            assert_eq!(exported.schema_version, SLED_SCHEMA_VERSION);
+            assert_eq!(exported.entries["contracts"], 1);
+
+            let target = SledStorageProvider::new(target_path).unwrap();
+            let imported = target.import_backup(backup.as_slice(), false).unwrap();
+            assert_eq!(imported, exported);
+            assert_eq!(contents(&target), contents(&source));
+            assert!(target.maintenance().unwrap());
+        }
+        std::fs::remove_dir_all(source_path).unwrap();
+        std::fs::remove_dir_all(target_path).unwrap();
+    }
+
+    #[test]
+    fn non_empty_storage_needs_force() {
+        let path = "tests/data/dlc_storage/sleddb/backup_force";
+        {
+            let storage = populated(path);
+            let mut backup = vec![];
+            storage.export_backup(&mut backup).unwrap();
+            storage.set_maintenance(false).unwrap();
+
+            let error = storage.import_backup(backup.as_slice(), false).unwrap_err();
+            assert!(error.to_string().contains("not empty"));
+            assert!(!storage.maintenance().unwrap());
+
+            storage.import_backup(backup.as_slice(), true).unwrap();
+            assert!(storage.maintenance().unwrap());
+        }
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn corrupted_backup_is_rejected() {
+        let source_path = "tests/data/dlc_storage/sleddb/backup_corrupt_source";
+        let target_path = "tests/data/dlc_storage/sleddb/backup_corrupt_target";
+        {
+            let mut backup = vec![];
+            populated(source_path).export_backup(&mut backup).unwrap();
+            let target = SledStorageProvider::new(target_path).unwrap();
+
+            let mut flipped = backup.clone();
+            let middle = flipped.len() / 2;
+            flipped[middle] ^= 0xff;
+            assert!(target.import_backup(flipped.as_slice(), false).is_err());
+
+            let truncated = &backup[..backup.len() - 40];
+            assert!(target.import_backup(truncated, false).is_err());
+
+            assert!(target.import_backup(&b"not a backup"[..], false).is_err());
+
+            assert!(contents(&target).iter().all(|tree| tree.is_empty()));
+        }
+        std::fs::remove_dir_all(source_path).unwrap();
+        std::fs::remove_dir_all(target_path).unwrap();
+    }
+}
@@ -1,4 +1,4 @@
-use super::{SledStorageProvider, CHAIN_MONITOR_KEY, CHAIN_MONITOR_TREE};
+use super::{index, SledStorageProvider, CHAIN_MONITOR_KEY, CHAIN_MONITOR_TREE};
 use bitcoin::consensus::ReadExt;
 use dlc_manager::chain_monitor::ChainMonitor;
 use dlc_manager::channel::accepted_channel::AcceptedChannel;
@@ -12,9 +12,16 @@ use dlc_manager::contract::ser::Serializable;
 use dlc_manager::contract::signed_contract::SignedContract;
 use dlc_manager::contract::{Contract, PreClosedContract};
 use dlc_manager::{error::Error, ContractId, Storage};
-use sled::transaction::{ConflictableTransactionResult, UnabortableTransactionError};
+use sled::transaction::{
+    ConflictableTransactionResult, TransactionalTree, UnabortableTransactionError,
+};
 use sled::Transactional;
 use std::convert::TryInto;
+use crate::audit::AuditEntry;
+use crate::contract::{validate_transition, ContractState};
+use crate::metrics;
+use crate::transport::PendingOutbound;
+use std::collections::HashMap;
 use crate::util::{serialize_contract, deserialize_contract};
 
 macro_rules! convertible_enum {
@@ -23,7 +30,7 @@ macro_rules! convertible_enum {
         $($tname:ident $(= $tval:expr)?,)*
     }, $input:ident) => {
         #[derive(Debug)]
-        enum $name {
+        pub(crate) enum $name {
             $($vname $(= $val)?,)*
             $($tname $(= $tval)?,)*
         }
@@ -47,7 +54,7 @@ macro_rules! convertible_enum {
         }
 
         impl $name {
-            fn get_prefix(input: &$input) -> u8 {
+            pub(crate) fn get_prefix(input: &$input) -> u8 {
                 let prefix = match input {
                     $($input::$vname(_) => $name::$vname,)*
                     $($input::$tname{..} => $name::$tname,)*
@@ -109,6 +116,23 @@ convertible_enum!(
     SignedChannelStateType
 );
 
+impl From<ContractState> for ContractPrefix {
+    fn from(state: ContractState) -> ContractPrefix {
+        match state {
+            ContractState::Offered => ContractPrefix::Offered,
+            ContractState::Accepted => ContractPrefix::Accepted,
+            ContractState::Signed => ContractPrefix::Signed,
+            ContractState::Confirmed => ContractPrefix::Confirmed,
+            ContractState::PreClosed => ContractPrefix::PreClosed,
+            ContractState::Closed => ContractPrefix::Closed,
+            ContractState::FailedAccept => ContractPrefix::FailedAccept,
+            ContractState::FailedSign => ContractPrefix::FailedSign,
+            ContractState::Refunded => ContractPrefix::Refunded,
+            ContractState::Rejected => ContractPrefix::Rejected,
+        }
+    }
+}
+
 fn to_storage_error<T>(e: T) -> Error
 where
     T: std::fmt::Display,
@@ -118,208 +142,394 @@ where
 
 impl Storage for SledStorageProvider {
     fn get_contract(&self, contract_id: &ContractId) -> Result<Option<Contract>, Error> {
-        match self
-            .contract_tree()?
-            .get(contract_id)
-            .map_err(to_storage_error)?
-        {
-            Some(res) => Ok(Some(deserialize_contract(&res)?)),
-            None => Ok(None),
-        }
+        metrics::time_storage("get_contract", || {
+            match self
+                .contract_tree()?
+                .get(contract_id)
+                .map_err(to_storage_error)?
+            {
+                Some(res) => Ok(Some(deserialize_contract(
+                    &self.open(res).map_err(to_storage_error)?,
+                )?)),
+                None => Ok(None),
+            }
+        })
     }
 
     fn get_contracts(&self) -> Result<Vec<Contract>, Error> {
-        self.contract_tree()?
-            .iter()
-            .values()
-            .map(|x| deserialize_contract(&x.unwrap()))
-            .collect::<Result<Vec<Contract>, Error>>()
+        metrics::time_storage("get_contracts", || {
+            self.contract_tree()?
+                .iter()
+                .values()
+                .map(|x| {
+                    let value = self.open(x.unwrap()).map_err(to_storage_error)?;
+                    deserialize_contract(&value)
+                })
+                .collect::<Result<Vec<Contract>, Error>>()
+        })
     }
 
     fn create_contract(&self, contract: &OfferedContract) -> Result<(), Error> {
-        let serialized = serialize_contract(&Contract::Offered(contract.clone()))?;
-        self.contract_tree()?
-            .insert(contract.id, serialized)
-            .map_err(to_storage_error)?;
-        Ok(())
+        metrics::time_storage("create_contract", || {
+            let contract = Contract::Offered(contract.clone());
+            let serialized = self
+                .seal(serialize_contract(&contract)?)
+                .map_err(to_storage_error)?;
+            let audit_entry = self.serialize_state_change(None, &contract)?;
+            let contract_tree = self.contract_tree()?;
+            let index_tree = self.contract_index_tree().map_err(to_storage_error)?;
+            let audit_tree = self.audit_log_tree().map_err(to_storage_error)?;
+            let now = unix_now();
+            (&contract_tree, &index_tree, &audit_tree)
+                .transaction::<_, _, UnabortableTransactionError>(|(db, index, audit)| {
+                    db.insert(&contract.get_id(), serialized.clone())?;
+                    index::index_contract(index, &contract, now)?;
+                    append_audit_entry(audit, audit_entry.as_deref())?;
+                    Ok(())
+                })
+                .map_err(to_storage_error)?;
+            Ok(())
+        })
     }
 
     fn delete_contract(&self, contract_id: &ContractId) -> Result<(), Error> {
-        self.contract_tree()?
-            .remove(contract_id)
-            .map_err(to_storage_error)?;
-        Ok(())
+        metrics::time_storage("delete_contract", || {
+            let contract_tree = self.contract_tree()?;
+            let index_tree = self.contract_index_tree().map_err(to_storage_error)?;
+            (&contract_tree, &index_tree)
+                .transaction::<_, _, UnabortableTransactionError>(|(db, index)| {
+                    db.remove(contract_id)?;
+                    index::remove_entries(index, contract_id)?;
+                    Ok(())
+                })
+                .map_err(to_storage_error)?;
+            Ok(())
+        })
     }
 
     fn update_contract(&self, contract: &Contract) -> Result<(), Error> {
-        let serialized = serialize_contract(contract)?;
-        self.contract_tree()?
-            .transaction::<_, _, UnabortableTransactionError>(|db| {
-                match contract {
-                    a @ Contract::Accepted(_) | a @ Contract::Signed(_) => {
-                        db.remove(&a.get_temporary_id())?;
+        metrics::time_storage("update_contract", || {
+            let existing = match self.get_contract(&contract.get_id())? {
+                Some(c) => Some(c),
+                None => self.get_contract(&contract.get_temporary_id())?,
+            };
+            if let Some(existing) = &existing {
+                if let Err(e) = validate_transition(existing, contract) {
+                    if self.strict_transitions {
+                        return Err(Error::StorageError(e.to_string()));
                     }
-                    _ => {}
-                };
+                    tracing::warn!(
+                        contract_id = hex::encode(contract.get_id()),
+                        error = e.to_string(),
+                        "Updating contract with an invalid transition."
+                    );
+                }
+            }
 
-                db.insert(&contract.get_id(), serialized.clone())?;
-                Ok(())
-            })
-            .map_err(to_storage_error)?;
-        Ok(())
+            let serialized = self
+                .seal(serialize_contract(contract)?)
+                .map_err(to_storage_error)?;
+            let audit_entry = self.serialize_state_change(existing.as_ref(), contract)?;
+            let pending = PendingOutbound::for_state_change(existing.as_ref(), contract)
+                .map(|pending| serde_json::to_vec(&pending).map(|data| (pending.id, data)))
+                .transpose()
+                .map_err(to_storage_error)?;
+            let contract_tree = self.contract_tree()?;
+            let metadata_tree = self.contract_metadata_tree().map_err(to_storage_error)?;
+            let index_tree = self.contract_index_tree().map_err(to_storage_error)?;
+            let audit_tree = self.audit_log_tree().map_err(to_storage_error)?;
+            let pending_tree = self.pending_outbound_tree().map_err(to_storage_error)?;
+            let now = unix_now();
+            (&contract_tree, &metadata_tree, &index_tree, &audit_tree, &pending_tree)
+                .transaction::<_, _, UnabortableTransactionError>(|(db, metadata, index, audit, outbound)| {
+                    insert_contract(db, metadata, index, serialized.clone(), contract, now)?;
+                    append_audit_entry(audit, audit_entry.as_deref())?;
+                    // The message the new state commits us to send.
+                    if let Some((id, data)) = &pending {
+                        outbound.insert(id.as_bytes(), data.clone())?;
+                    }
+                    Ok(())
+                })
+                .map_err(to_storage_error)?;
+            metrics::contract_updated(existing.as_ref(), contract);
+            Ok(())
+        })
     }
 
     fn get_contract_offers(&self) -> Result<Vec<OfferedContract>, Error> {
-        self.get_data_with_prefix(
-            &self.contract_tree()?,
-            &[ContractPrefix::Offered.into()],
-            None,
-        )
+        metrics::time_storage("get_contract_offers", || {
+            self.get_data_with_prefix(
+                &self.contract_tree()?,
+                &[ContractPrefix::Offered.into()],
+                None,
+            )
+        })
     }
 
     fn get_signed_contracts(&self) -> Result<Vec<SignedContract>, Error> {
-        self.get_data_with_prefix(
-            &self.contract_tree()?,
-            &[ContractPrefix::Signed.into()],
-            None,
-        )
+        metrics::time_storage("get_signed_contracts", || {
+            self.get_data_with_prefix(
+                &self.contract_tree()?,
+                &[ContractPrefix::Signed.into()],
+                None,
+            )
+        })
     }
 
     fn get_confirmed_contracts(&self) -> Result<Vec<SignedContract>, Error> {
-        self.get_data_with_prefix(
-            &self.contract_tree()?,
-            &[ContractPrefix::Confirmed.into()],
-            None,
-        )
+        metrics::time_storage("get_confirmed_contracts", || {
+            self.get_data_with_prefix(
+                &self.contract_tree()?,
+                &[ContractPrefix::Confirmed.into()],
+                None,
+            )
+        })
     }
 
     fn get_preclosed_contracts(&self) -> Result<Vec<PreClosedContract>, Error> {
-        self.get_data_with_prefix(
-            &self.contract_tree()?,
-            &[ContractPrefix::PreClosed.into()],
-            None,
-        )
+        metrics::time_storage("get_preclosed_contracts", || {
+            self.get_data_with_prefix(
+                &self.contract_tree()?,
+                &[ContractPrefix::PreClosed.into()],
+                None,
+            )
+        })
     }
 
     fn upsert_channel(&self, channel: Channel, contract: Option<Contract>) -> Result<(), Error> {
-        let serialized = serialize_channel(&channel)?;
-        let serialized_contract = match contract.as_ref() {
-            Some(c) => Some(serialize_contract(c)?),
-            None => None,
-        };
-        let channel_tree = self.channel_tree()?;
-        let contract_tree = self.contract_tree()?;
-        (&channel_tree, &contract_tree)
-            .transaction::<_, ()>(
-                |(channel_db, contract_db)| -> ConflictableTransactionResult<(), UnabortableTransactionError> {
-                    match &channel {
-                        a @ Channel::Accepted(_) | a @ Channel::Signed(_) => {
-                            channel_db.remove(&a.get_temporary_id())?;
-                        }
-                        _ => {}
+        metrics::time_storage("upsert_channel", || {
+            let serialized = self
+                .seal(serialize_channel(&channel)?)
+                .map_err(to_storage_error)?;
+            let serialized_contract = match contract.as_ref() {
+                Some(c) => Some(self.seal(serialize_contract(c)?).map_err(to_storage_error)?),
+                None => None,
+            };
+            let audit_entry = match contract.as_ref() {
+                Some(c) => {
+                    let existing = match self.get_contract(&c.get_id())? {
+                        Some(existing) => Some(existing),
+                        None => self.get_contract(&c.get_temporary_id())?,
                     };
-
-                    channel_db.insert(&channel.get_id(), serialized.clone())?;
-
-                    if let Some(c) = contract.as_ref() {
-                        insert_contract(
-                            contract_db,
-                            serialized_contract
-                                .clone()
-                                .expect("to have the serialized version"),
-                            c,
-                        )?;
-                    }
-                    Ok(())
-                },
-            )
-        .map_err(to_storage_error)?;
-        Ok(())
+                    self.serialize_state_change(existing.as_ref(), c)?
+                }
+                None => None,
+            };
+            let channel_tree = self.channel_tree()?;
+            let contract_tree = self.contract_tree()?;
+            let metadata_tree = self.contract_metadata_tree().map_err(to_storage_error)?;
+            let index_tree = self.contract_index_tree().map_err(to_storage_error)?;
+            let audit_tree = self.audit_log_tree().map_err(to_storage_error)?;
+            let now = unix_now();
+            (&channel_tree, &contract_tree, &metadata_tree, &index_tree, &audit_tree)
+                .transaction::<_, ()>(
+                    |(channel_db, contract_db, metadata_db, index_db, audit_db)| -> ConflictableTransactionResult<(), UnabortableTransactionError> {
+                        match &channel {
+                            a @ Channel::Accepted(_) | a @ Channel::Signed(_) => {
+                                channel_db.remove(&a.get_temporary_id())?;
+                            }
+                            _ => {}
+                        };
+
+                        channel_db.insert(&channel.get_id(), serialized.clone())?;
+
+                        if let Some(c) = contract.as_ref() {
+                            insert_contract(
+                                contract_db,
+                                metadata_db,
+                                index_db,
+                                serialized_contract
+                                    .clone()
+                                    .expect("to have the serialized version"),
+                                c,
+                                now,
+                            )?;
+                        }
+                        append_audit_entry(audit_db, audit_entry.as_deref())?;
+                        Ok(())
+                    },
+                )
+            .map_err(to_storage_error)?;
+            Ok(())
+        })
     }
 
     fn delete_channel(&self, channel_id: &dlc_manager::ChannelId) -> Result<(), Error> {
-        self.channel_tree()?
-            .remove(channel_id)
-            .map_err(to_storage_error)?;
-        Ok(())
+        metrics::time_storage("delete_channel", || {
+            self.channel_tree()?
+                .remove(channel_id)
+                .map_err(to_storage_error)?;
+            Ok(())
+        })
     }
 
     fn get_channel(&self, channel_id: &dlc_manager::ChannelId) -> Result<Option<Channel>, Error> {
-        match self
-            .channel_tree()?
-            .get(channel_id)
-            .map_err(to_storage_error)?
-        {
-            Some(res) => Ok(Some(deserialize_channel(&res)?)),
-            None => Ok(None),
-        }
+        metrics::time_storage("get_channel", || {
+            match self
+                .channel_tree()?
+                .get(channel_id)
+                .map_err(to_storage_error)?
+            {
+                Some(res) => Ok(Some(deserialize_channel(
+                    &self.open(res).map_err(to_storage_error)?,
+                )?)),
+                None => Ok(None),
+            }
+        })
     }
 
     fn get_signed_channels(
         &self,
         channel_state: Option<SignedChannelStateType>,
     ) -> Result<Vec<SignedChannel>, Error> {
-        let (prefix, consume) = if let Some(state) = &channel_state {
-            (
-                vec![
-                    ChannelPrefix::Signed.into(),
-                    SignedChannelPrefix::get_prefix(state),
-                ],
-                None,
-            )
-        } else {
-            (vec![ChannelPrefix::Signed.into()], Some(1))
-        };
+        metrics::time_storage("get_signed_channels", || {
+            let (prefix, consume) = if let Some(state) = &channel_state {
+                (
+                    vec![
+                        ChannelPrefix::Signed.into(),
+                        SignedChannelPrefix::get_prefix(state),
+                    ],
+                    None,
+                )
+            } else {
+                (vec![ChannelPrefix::Signed.into()], Some(1))
+            };
 
-        self.get_data_with_prefix(&self.channel_tree()?, &prefix, consume)
+            self.get_data_with_prefix(&self.channel_tree()?, &prefix, consume)
+        })
     }
 
     fn get_offered_channels(&self) -> Result<Vec<OfferedChannel>, Error> {
-        self.get_data_with_prefix(
-            &self.channel_tree()?,
-            &[ChannelPrefix::Offered.into()],
-            None,
-        )
+        metrics::time_storage("get_offered_channels", || {
+            self.get_data_with_prefix(
+                &self.channel_tree()?,
+                &[ChannelPrefix::Offered.into()],
+                None,
+            )
+        })
     }
 
     fn persist_chain_monitor(&self, monitor: &ChainMonitor) -> Result<(), Error> {
-        self.open_tree(&[CHAIN_MONITOR_TREE])?
-            .insert([CHAIN_MONITOR_KEY], monitor.serialize()?)
-            .map_err(|e| Error::StorageError(format!("Error writing chain monitor: {}", e)))?;
-        Ok(())
+        metrics::time_storage("persist_chain_monitor", || {
+            self.open_tree(&[CHAIN_MONITOR_TREE])?
+                .insert([CHAIN_MONITOR_KEY], monitor.serialize()?)
+                .map_err(|e| Error::StorageError(format!("Error writing chain monitor: {}", e)))?;
+            Ok(())
+        })
     }
     fn get_chain_monitor(&self) -> Result<Option<ChainMonitor>, dlc_manager::error::Error> {
-        let serialized = self
-            .open_tree(&[CHAIN_MONITOR_TREE])?
-            .get([CHAIN_MONITOR_KEY])
-            .map_err(|e| Error::StorageError(format!("Error reading chain monitor: {}", e)))?;
-        let deserialized = match serialized {
-            Some(s) => Some(
-                ChainMonitor::deserialize(&mut ::lightning::io::Cursor::new(s))
-                    .map_err(to_storage_error)?,
-            ),
-            None => None,
-        };
-        Ok(deserialized)
+        metrics::time_storage("get_chain_monitor", || {
+            let serialized = self
+                .open_tree(&[CHAIN_MONITOR_TREE])?
+                .get([CHAIN_MONITOR_KEY])
+                .map_err(|e| Error::StorageError(format!("Error reading chain monitor: {}", e)))?;
+            let deserialized = match serialized {
+                Some(s) => Some(
+                    ChainMonitor::deserialize(&mut ::lightning::io::Cursor::new(s))
+                        .map_err(to_storage_error)?,
+                ),
+                None => None,
+            };
+            Ok(deserialized)
+        })
+    }
+}
+
+impl SledStorageProvider {
+    /// Count contracts by state from the stored prefix byte without deserializing them.
+    pub(crate) fn contract_state_counts(&self) -> Result<HashMap<ContractState, usize>, Error> {
+        let mut counts = HashMap::new();
+        for value in self.contract_tree()?.iter().values() {
+            let value = self
+                .open(value.map_err(to_storage_error)?)
+                .map_err(to_storage_error)?;
+            let Some(prefix) = value.first() else {
+                continue;
+            };
+            let state = match ContractPrefix::try_from(*prefix)? {
+                ContractPrefix::Offered => ContractState::Offered,
+                ContractPrefix::Accepted => ContractState::Accepted,
+                ContractPrefix::Signed => ContractState::Signed,
+                ContractPrefix::Confirmed => ContractState::Confirmed,
+                ContractPrefix::PreClosed => ContractState::PreClosed,
+                ContractPrefix::Closed => ContractState::Closed,
+                ContractPrefix::FailedAccept => ContractState::FailedAccept,
+                ContractPrefix::FailedSign => ContractState::FailedSign,
+                ContractPrefix::Refunded => ContractState::Refunded,
+                ContractPrefix::Rejected => ContractState::Rejected,
+            };
+            *counts.entry(state).or_default() += 1;
+        }
+        Ok(counts)
     }
 }
 
+/// Write a contract under its id. Metadata and index entries of the temporary id follow the
+/// contract once it is accepted, and a contract seen for the first time is indexed at `now`.
 fn insert_contract(
-    db: &sled::transaction::TransactionalTree,
+    db: &TransactionalTree,
+    metadata: &TransactionalTree,
+    index: &TransactionalTree,
     serialized: Vec<u8>,
     contract: &Contract,
-) -> Result<Option<sled::IVec>, UnabortableTransactionError> {
+    now: u64,
+) -> Result<(), UnabortableTransactionError> {
     match contract {
         a @ Contract::Accepted(_) | a @ Contract::Signed(_) => {
             db.remove(&a.get_temporary_id())?;
+            if let Some(value) = metadata.remove(&a.get_temporary_id())? {
+                metadata.insert(&a.get_id(), value)?;
+            }
         }
         _ => {}
     };
 
-    db.insert(&contract.get_id(), serialized)
+    db.insert(&contract.get_id(), serialized)?;
+    index::index_contract(index, contract, now)
+}
+
+/// Key of the next audit sequence number. It sorts before every entry key.
+const AUDIT_SEQUENCE_KEY: &[u8] = b"";
+
+/// Append a serialized [AuditEntry] under the next sequence number, starting at 1. The counter
+/// is kept in the audit tree so a restored backup continues the sequence.
+pub(super) fn append_audit_entry(
+    audit: &TransactionalTree,
+    entry: Option<&[u8]>,
+) -> Result<Option<u64>, UnabortableTransactionError> {
+    let Some(entry) = entry else {
+        return Ok(None);
+    };
+    let sequence = match audit.get(AUDIT_SEQUENCE_KEY)? {
+        Some(next) => u64::from_be_bytes(next.as_ref().try_into().unwrap_or_default()),
+        None => 1,
+    };
+    audit.insert(AUDIT_SEQUENCE_KEY, &(sequence + 1).to_be_bytes())?;
+    audit.insert(&sequence.to_be_bytes(), entry)?;
+    Ok(Some(sequence))
+}
+
+impl SledStorageProvider {
+    /// The serialized audit entry for storing `new` over `existing`, if its state changes.
+    fn serialize_state_change(
+        &self,
+        existing: Option<&Contract>,
+        new: &Contract,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        AuditEntry::state_change(existing, new)
+            .map(|entry| serde_json::to_vec(&entry).map_err(to_storage_error))
+            .transpose()
+    }
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
 }
 
-fn serialize_channel(channel: &Channel) -> Result<Vec<u8>, ::lightning::io::Error> {
+pub(crate) fn serialize_channel(channel: &Channel) -> Result<Vec<u8>, ::lightning::io::Error> {
     let serialized = match channel {
         Channel::Offered(o) => o.serialize(),
         Channel::Accepted(a) => a.serialize(),
@@ -343,7 +553,7 @@ fn serialize_channel(channel: &Channel) -> Result<Vec<u8>, ::lightning::io::Erro
     Ok(res)
 }
 
-fn deserialize_channel(buff: &sled::IVec) -> Result<Channel, Error> {
+pub(crate) fn deserialize_channel(buff: &[u8]) -> Result<Channel, Error> {
     let mut cursor = lightning::io::Cursor::new(buff);
     let mut prefix = [0u8; 1];
     cursor.read_slice(&mut prefix)?;
@@ -391,6 +601,8 @@ fn deserialize_channel(buff: &sled::IVec) -> Result<Channel, Error> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::DdkStorage;
+    use std::time::{Duration, Instant};
 
     macro_rules! sled_test {
         ($name: ident, $body: expr) => {
@@ -468,6 +680,44 @@ mod tests {
         }
     );
 
+    sled_test!(
+        metadata_follows_accepted_contract,
+        |storage: SledStorageProvider| {
+            use crate::contract::metadata::ContractMetadata;
+            use crate::DdkStorage;
+
+            let serialized = include_bytes!("../../../tests/data/dlc_storage/sled/Offered");
+            let offered_contract: OfferedContract = deserialize_object(serialized);
+            let serialized = include_bytes!("../../../tests/data/dlc_storage/sled/Accepted");
+            let accepted_contract = Contract::Accepted(deserialize_object(serialized));
+
+            storage
+                .create_contract(&offered_contract)
+                .expect("Error creating contract");
+            let metadata = ContractMetadata::new()
+                .with_label("btc-usd weekly")
+                .with_tag("order", serde_json::json!(42));
+            storage
+                .set_contract_metadata(&offered_contract.id, metadata.clone())
+                .unwrap();
+
+            storage
+                .update_contract(&accepted_contract)
+                .expect("Error updating contract.");
+
+            assert_eq!(
+                storage
+                    .get_contract_metadata(&accepted_contract.get_id())
+                    .unwrap(),
+                Some(metadata)
+            );
+            assert!(storage
+                .get_contract_metadata(&offered_contract.id)
+                .unwrap()
+                .is_none());
+        }
+    );
+
     sled_test!(
         delete_contract_is_deleted,
         |storage: SledStorageProvider| {
@@ -613,6 +863,23 @@ mod tests {
         }
     );
 
+    sled_test!(
+        contract_state_counts_from_prefix,
+        |mut storage: SledStorageProvider| {
+            insert_offered_signed_and_confirmed(&mut storage);
+
+            let counts = storage
+                .contract_state_counts()
+                .expect("Error counting contracts");
+
+            assert_eq!(counts.get(&ContractState::Offered), Some(&1));
+            assert_eq!(counts.get(&ContractState::Signed), Some(&2));
+            assert_eq!(counts.get(&ContractState::Confirmed), Some(&2));
+            assert_eq!(counts.get(&ContractState::PreClosed), Some(&1));
+            assert_eq!(counts.values().sum::<usize>(), 6);
+        }
+    );
+
     sled_test!(
         persist_chain_monitor_test,
         |storage: SledStorageProvider| {
@@ -630,4 +897,30 @@ mod tests {
             assert_eq!(chain_monitor, retrieved);
         }
     );
+
+    sled_test!(
+        storage_stats_of_a_large_store_take_under_a_second,
+        |storage: SledStorageProvider| {
+            // Contracts and wallet changesets of a node with a long history, written as raw
+            // records since only their prefix is read.
+            let contracts = storage.contract_tree().unwrap();
+            let wallet = storage.wallet_tree().unwrap();
+            let record = vec![0u8; 4_096];
+            for i in 0..25_000u32 {
+                let mut contract = vec![u8::from(ContractPrefix::Closed)];
+                contract.extend_from_slice(&record);
+                contracts.insert(i.to_be_bytes(), contract).unwrap();
+                wallet.insert(i.to_be_bytes(), record.as_slice()).unwrap();
+            }
+            storage.db.flush().unwrap();
+
+            let start = Instant::now();
+            let stats = storage.storage_stats().unwrap();
+            let elapsed = start.elapsed();
+            assert!(elapsed < Duration::from_secs(1), "Storage stats took {:?}.", elapsed);
+            assert_eq!(stats.contracts_by_state.get(&ContractState::Closed), Some(&25_000));
+            assert_eq!(stats.wallet_changesets, 25_000);
+            assert!(stats.size_on_disk.unwrap() > 100_000_000);
+        }
+    );
 }
@@ -0,0 +1,300 @@
+//! Encryption of sled values at rest.
+//!
+//! Values of the signer, contract, channel, contract archive, and wallet trees are stored as a
+//! random 12 byte nonce followed by their ChaCha20-Poly1305 ciphertext. Keys stay in the clear
+//! so lookups and ordered iteration keep working, which leaves contract ids, key ids, and the
+//! metadata of the other trees readable.
+//!
+//! An encrypted database stores an encrypted check value in the meta tree. Opening it without a
+//! key, or with the wrong key, fails instead of reading garbage.
+use super::{
+    SledStorageProvider, CHANNEL_TREE, CONTRACT_ARCHIVE_TREE, CONTRACT_TREE, META_TREE,
+    SIGNER_TREE, WALLET_TREE,
+};
+use bitcoin::key::rand::{thread_rng, Rng};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use sled::transaction::{ConflictableTransactionError, TransactionError};
+use sled::{IVec, Transactional};
+use std::sync::Arc;
+
+/// Trees whose values are encrypted.
+const ENCRYPTED_TREES: [u8; 5] = [
+    SIGNER_TREE,
+    CONTRACT_TREE,
+    CHANNEL_TREE,
+    CONTRACT_ARCHIVE_TREE,
+    WALLET_TREE,
+];
+
+const ENCRYPTION_CHECK_KEY: &[u8] = b"encryption_check";
+const ENCRYPTION_CHECK: &[u8] = b"ddk encrypted storage";
+const NONCE_LEN: usize = 12;
+
+/// Cipher of an encrypted database.
+#[derive(Clone)]
+pub(crate) struct ValueCipher(Arc<ChaCha20Poly1305>);
+
+impl std::fmt::Debug for ValueCipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ValueCipher").finish_non_exhaustive()
+    }
+}
+
+impl ValueCipher {
+    fn new(key: &[u8; 32]) -> ValueCipher {
+        ValueCipher(Arc::new(ChaCha20Poly1305::new(Key::from_slice(key))))
+    }
+
+    fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, sled::Error> {
+        let nonce: [u8; NONCE_LEN] = thread_rng().gen();
+        let ciphertext = self
+            .0
+            .encrypt(Nonce::from_slice(&nonce), plaintext)
+            .map_err(|_| encryption_error("Could not encrypt value."))?;
+        Ok([&nonce[..], &ciphertext].concat())
+    }
+
+    fn decrypt(&self, value: &[u8]) -> Result<Vec<u8>, sled::Error> {
+        if value.len() < NONCE_LEN {
+            return Err(encryption_error("Encrypted value is truncated."));
+        }
+        let (nonce, ciphertext) = value.split_at(NONCE_LEN);
+        self.0
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| {
+                encryption_error("Could not decrypt value, the key is wrong or the value corrupt.")
+            })
+    }
+}
+
+fn encryption_error(message: &str) -> sled::Error {
+    sled::Error::Unsupported(message.to_string())
+}
+
+impl SledStorageProvider {
+    /// Open a database whose values are encrypted with `key`, upgrading it to the current
+    /// layout. A new database is encrypted from the start. Fails if the database is not
+    /// encrypted or was encrypted with another key; plaintext databases are converted with
+    /// [SledStorageProvider::encrypt_in_place].
+    pub fn new_encrypted(path: &str, key: [u8; 32]) -> Result<Self, sled::Error> {
+        let mut storage = Self::open_unmigrated(path)?;
+        storage.migrate()?;
+        let cipher = ValueCipher::new(&key);
+        let meta = storage.db.open_tree([META_TREE])?;
+        match meta.get(ENCRYPTION_CHECK_KEY)? {
+            Some(check) => {
+                if cipher.decrypt(&check).ok().as_deref() != Some(ENCRYPTION_CHECK) {
+                    return Err(encryption_error("Wrong encryption key for storage."));
+                }
+            }
+            None => {
+                if storage.has_encryptable_data()? {
+                    return Err(encryption_error(
+                        "Storage is not encrypted. Convert it with encrypt_in_place first.",
+                    ));
+                }
+                meta.insert(ENCRYPTION_CHECK_KEY, cipher.encrypt(ENCRYPTION_CHECK)?)?;
+                meta.flush()?;
+            }
+        }
+        storage.cipher = Some(cipher);
+        Ok(storage)
+    }
+
+    /// Encrypt the values of a plaintext database with `key` and open it. Every value is
+    /// rewritten in one transaction, so an interrupted conversion leaves the database in
+    /// plaintext. The values are held in memory while converting.
+    pub fn encrypt_in_place(path: &str, key: [u8; 32]) -> Result<Self, sled::Error> {
+        let mut storage = Self::new(path)?;
+        let cipher = ValueCipher::new(&key);
+
+        let trees = ENCRYPTED_TREES
+            .into_iter()
+            .map(|id| storage.db.open_tree([id]))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut encrypted = vec![];
+        for tree in &trees {
+            let mut values = vec![];
+            for entry in tree.iter() {
+                let (key, value) = entry?;
+                values.push((key, cipher.encrypt(&value)?));
+            }
+            encrypted.push(values);
+        }
+        let meta = storage.db.open_tree([META_TREE])?;
+        let check = cipher.encrypt(ENCRYPTION_CHECK)?;
+
+        (&trees[0], &trees[1], &trees[2], &trees[3], &trees[4], &meta)
+            .transaction(|(signers, contracts, channels, archive, wallet, meta)| {
+                for (tree, values) in [signers, contracts, channels, archive, wallet]
+                    .into_iter()
+                    .zip(&encrypted)
+                {
+                    for (key, value) in values {
+                        tree.insert(key.clone(), value.clone())?;
+                    }
+                }
+                meta.insert(ENCRYPTION_CHECK_KEY, check.clone())?;
+                Ok::<_, ConflictableTransactionError<()>>(())
+            })
+            .map_err(|e| match e {
+                TransactionError::Storage(e) => e,
+                TransactionError::Abort(()) => encryption_error("Could not encrypt storage."),
+            })?;
+        storage.db.flush()?;
+        tracing::info!("Encrypted sled storage.");
+        storage.cipher = Some(cipher);
+        Ok(storage)
+    }
+
+    /// Fail when a database is opened without a key but its values are encrypted.
+    pub(super) fn check_not_encrypted(&self) -> Result<(), sled::Error> {
+        if self
+            .db
+            .open_tree([META_TREE])?
+            .contains_key(ENCRYPTION_CHECK_KEY)?
+        {
+            return Err(encryption_error(
+                "Storage is encrypted. Open it with new_encrypted and its key.",
+            ));
+        }
+        Ok(())
+    }
+
+    fn has_encryptable_data(&self) -> Result<bool, sled::Error> {
+        for id in ENCRYPTED_TREES {
+            if !self.db.open_tree([id])?.is_empty() {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Encrypt a value before writing it to an encrypted tree. Without a key it is returned as
+    /// is.
+    pub(super) fn seal(&self, value: Vec<u8>) -> Result<Vec<u8>, sled::Error> {
+        match &self.cipher {
+            Some(cipher) => cipher.encrypt(&value),
+            None => Ok(value),
+        }
+    }
+
+    /// Decrypt a value read from an encrypted tree.
+    pub(super) fn open(&self, value: IVec) -> Result<IVec, sled::Error> {
+        match &self.cipher {
+            Some(cipher) => Ok(cipher.decrypt(&value)?.into()),
+            None => Ok(value),
+        }
+    }
+
+    /// Whether values of `tree_id` go through [SledStorageProvider::seal] and
+    /// [SledStorageProvider::open].
+    pub(super) fn is_encrypted_tree(&self, tree_id: u8) -> bool {
+        self.cipher.is_some() && ENCRYPTED_TREES.contains(&tree_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signer::{DeriveSigner, SignerInformation};
+    use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+    use dlc_manager::contract::offered_contract::OfferedContract;
+    use dlc_manager::contract::ser::Serializable;
+    use dlc_manager::Storage;
+
+    const KEY: [u8; 32] = [7u8; 32];
+
+    fn offered() -> OfferedContract {
+        let serialized = include_bytes!("../../../tests/data/dlc_storage/sled/Offered");
+        OfferedContract::deserialize(&mut lightning::io::Cursor::new(&serialized)).unwrap()
+    }
+
+    fn signer() -> SignerInformation {
+        let secret_key = SecretKey::from_slice(&[3u8; 32]).unwrap();
+        SignerInformation {
+            index: 1,
+            secret_key,
+            public_key: PublicKey::from_secret_key(&Secp256k1::new(), &secret_key),
+        }
+    }
+
+    fn populate(storage: &SledStorageProvider) {
+        storage.create_contract(&offered()).unwrap();
+        storage.store_derived_key_id([1u8; 32], signer()).unwrap();
+    }
+
+    fn assert_readable(storage: &SledStorageProvider) {
+        let offer = offered();
+        assert!(storage.get_contract(&offer.id).unwrap().is_some());
+        assert_eq!(storage.get_contract_offers().unwrap().len(), 1);
+        assert_eq!(
+            storage.get_key_information([1u8; 32]).unwrap().public_key,
+            signer().public_key
+        );
+        assert_eq!(
+            storage.get_secret_key(&signer().public_key).unwrap(),
+            signer().secret_key
+        );
+    }
+
+    #[test]
+    fn values_are_encrypted_and_read_back() {
+        let path = "tests/data/dlc_storage/sleddb/encrypted";
+        {
+            let storage = SledStorageProvider::new_encrypted(path, KEY).unwrap();
+            populate(&storage);
+            assert_readable(&storage);
+
+            let raw = storage
+                .signer_tree()
+                .unwrap()
+                .get(hex::encode([1u8; 32]))
+                .unwrap()
+                .unwrap();
+            assert!(bincode::deserialize::<SignerInformation>(&raw).is_err());
+            assert_ne!(raw.as_ref(), bincode::serialize(&signer()).unwrap());
+        }
+        {
+            let storage = SledStorageProvider::new_encrypted(path, KEY).unwrap();
+            assert_readable(&storage);
+        }
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn key_must_match_the_database() {
+        let path = "tests/data/dlc_storage/sleddb/encrypted_wrong_key";
+        {
+            let storage = SledStorageProvider::new_encrypted(path, KEY).unwrap();
+            populate(&storage);
+        }
+        let error = SledStorageProvider::new_encrypted(path, [8u8; 32]).unwrap_err();
+        assert!(error.to_string().contains("Wrong encryption key"));
+        let error = SledStorageProvider::new(path).unwrap_err();
+        assert!(error.to_string().contains("Storage is encrypted"));
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn plaintext_database_is_encrypted_in_place() {
+        let path = "tests/data/dlc_storage/sleddb/encrypt_in_place";
+        {
+            let storage = SledStorageProvider::new(path).unwrap();
+            populate(&storage);
+        }
+        let error = SledStorageProvider::new_encrypted(path, KEY).unwrap_err();
+        assert!(error.to_string().contains("encrypt_in_place"));
+        {
+            let storage = SledStorageProvider::encrypt_in_place(path, KEY).unwrap();
+            assert_readable(&storage);
+        }
+        {
+            let storage = SledStorageProvider::new_encrypted(path, KEY).unwrap();
+            assert_readable(&storage);
+        }
+        assert!(SledStorageProvider::new(path).is_err());
+        std::fs::remove_dir_all(path).unwrap();
+    }
+}
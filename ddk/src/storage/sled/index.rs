@@ -0,0 +1,400 @@
+//! Secondary index of the contract tree by creation time and counterparty.
+//!
+//! Every contract has three entries in the index tree, all ending in its id so a key is
+//! never shared:
+//!
+//! * `ENTRY | id` with the counterparty and creation time as value, to find the other two.
+//! * `BY_TIME | created_at | id`
+//! * `BY_COUNTERPARTY | counterparty | created_at | id`
+//!
+//! Timestamps are big endian so iteration follows creation order. The entries move from the
+//! temporary id to the contract id with the contract, keeping the creation time of the offer.
+use super::contract::ContractPrefix;
+use super::SledStorageProvider;
+use crate::storage::ContractFilter;
+use crate::util::deserialize_contract;
+use dlc_manager::contract::Contract;
+use dlc_manager::error::Error;
+use dlc_manager::ContractId;
+use sled::transaction::{TransactionalTree, UnabortableTransactionError};
+use std::collections::BTreeMap;
+
+const ENTRY: u8 = 0;
+const BY_TIME: u8 = 1;
+const BY_COUNTERPARTY: u8 = 2;
+
+const COUNTERPARTY_LEN: usize = 33;
+
+fn entry_key(id: &[u8]) -> Vec<u8> {
+    [&[ENTRY][..], id].concat()
+}
+
+fn time_key(created_at: u64, id: &ContractId) -> Vec<u8> {
+    [&[BY_TIME][..], &created_at.to_be_bytes(), id].concat()
+}
+
+fn counterparty_key(counterparty: &[u8], created_at: u64, id: &ContractId) -> Vec<u8> {
+    [&[BY_COUNTERPARTY][..], counterparty, &created_at.to_be_bytes(), id].concat()
+}
+
+/// The three entries of a contract indexed under `id`.
+fn entries(id: &ContractId, counterparty: &[u8], created_at: u64) -> [(Vec<u8>, Vec<u8>); 3] {
+    [
+        (
+            entry_key(id),
+            [counterparty, &created_at.to_be_bytes()].concat(),
+        ),
+        (time_key(created_at, id), Vec::new()),
+        (counterparty_key(counterparty, created_at, id), Vec::new()),
+    ]
+}
+
+/// Counterparty and creation time stored in an `ENTRY` value.
+fn read_entry(value: &[u8]) -> Option<(&[u8], u64)> {
+    if value.len() != COUNTERPARTY_LEN + 8 {
+        return None;
+    }
+    let (counterparty, created_at) = value.split_at(COUNTERPARTY_LEN);
+    Some((counterparty, u64::from_be_bytes(created_at.try_into().ok()?)))
+}
+
+/// Creation time of an indexed contract.
+pub(super) fn created_at(index: &sled::Tree, id: &[u8]) -> Result<Option<u64>, sled::Error> {
+    Ok(index
+        .get(entry_key(id))?
+        .and_then(|entry| read_entry(&entry).map(|(_, created_at)| created_at)))
+}
+
+/// Index a contract under its current id. A contract that is already indexed keeps its entries,
+/// one still indexed under its temporary id is moved, and anything else is indexed as created
+/// at `created_at`.
+pub(super) fn index_contract(
+    index: &TransactionalTree,
+    contract: &Contract,
+    created_at: u64,
+) -> Result<(), UnabortableTransactionError> {
+    let id = contract.get_id();
+    if index.get(entry_key(&id))?.is_some() {
+        return Ok(());
+    }
+    let created_at = remove_entries(index, &contract.get_temporary_id())?.unwrap_or(created_at);
+
+    let counterparty = contract.get_counter_party_id().serialize();
+    for (key, value) in entries(&id, &counterparty, created_at) {
+        index.insert(key, value)?;
+    }
+    Ok(())
+}
+
+/// Drop the entries of a contract, returning its creation time if it was indexed.
+pub(super) fn remove_entries(
+    index: &TransactionalTree,
+    id: &ContractId,
+) -> Result<Option<u64>, UnabortableTransactionError> {
+    let Some(entry) = index.remove(entry_key(id))? else {
+        return Ok(None);
+    };
+    let Some((counterparty, created_at)) = read_entry(&entry) else {
+        return Ok(None);
+    };
+    index.remove(time_key(created_at, id))?;
+    index.remove(counterparty_key(counterparty, created_at, id))?;
+    Ok(Some(created_at))
+}
+
+/// Entries the index should hold for `contracts`, each stored under its id. A contract keeps
+/// the creation time of its current entries, under its id or still under its temporary id,
+/// and falls back to `metadata_created_at`, then 0. `unreadable` contracts keep the entries
+/// they have.
+pub(super) fn expected_entries(
+    index: &sled::Tree,
+    contracts: &[&Contract],
+    unreadable: &[ContractId],
+    metadata_created_at: impl Fn(&Contract) -> Option<u64>,
+) -> Result<BTreeMap<Vec<u8>, Vec<u8>>, sled::Error> {
+    let mut expected = BTreeMap::new();
+    for contract in contracts {
+        let id = contract.get_id();
+        let created_at = match created_at(index, &id)? {
+            Some(created_at) => Some(created_at),
+            None => created_at(index, &contract.get_temporary_id())?,
+        }
+        .or_else(|| metadata_created_at(contract))
+        .unwrap_or_default();
+        let counterparty = contract.get_counter_party_id().serialize();
+        expected.extend(entries(&id, &counterparty, created_at));
+    }
+    for id in unreadable {
+        if let Some(entry) = index.get(entry_key(id))? {
+            if let Some((counterparty, created_at)) = read_entry(&entry) {
+                expected.extend(entries(id, counterparty, created_at));
+            }
+        }
+    }
+    Ok(expected)
+}
+
+/// Changes that make the index hold exactly the expected entries.
+#[derive(Debug, Default)]
+pub(super) struct IndexDiff {
+    /// Keys without an expected entry.
+    pub stale: Vec<Vec<u8>>,
+    /// Expected entries that are missing or hold another value.
+    pub missing: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl IndexDiff {
+    pub fn len(&self) -> usize {
+        self.stale.len() + self.missing.len()
+    }
+}
+
+pub(super) fn diff(
+    index: &sled::Tree,
+    expected: &BTreeMap<Vec<u8>, Vec<u8>>,
+) -> Result<IndexDiff, sled::Error> {
+    let mut diff = IndexDiff::default();
+    for entry in index.iter() {
+        let (key, _) = entry?;
+        if !expected.contains_key(key.as_ref()) {
+            diff.stale.push(key.to_vec());
+        }
+    }
+    for (key, value) in expected {
+        if index.get(key)?.as_deref() != Some(value.as_slice()) {
+            diff.missing.push((key.clone(), value.clone()));
+        }
+    }
+    Ok(diff)
+}
+
+impl SledStorageProvider {
+    /// Walk the index range selected by the counterparty and time bounds and only deserialize
+    /// the contracts of the requested page. The state is checked on the prefix byte, after
+    /// decrypting the value of encrypted storage.
+    pub(crate) fn contracts_page(
+        &self,
+        filter: &ContractFilter,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<Contract>, Error> {
+        let to_storage_error = |e: sled::Error| Error::StorageError(e.to_string());
+        let index = self.contract_index_tree().map_err(to_storage_error)?;
+        let contracts = self.contract_tree()?;
+
+        let prefix = match &filter.counterparty {
+            Some(counterparty) => [&[BY_COUNTERPARTY][..], &counterparty.serialize()].concat(),
+            None => vec![BY_TIME],
+        };
+        let start = [&prefix[..], &filter.created_after.unwrap_or(0).to_be_bytes()].concat();
+        let state = filter.state.map(|state| u8::from(ContractPrefix::from(state)));
+
+        let mut skipped = 0;
+        let mut page = vec![];
+        for entry in index.range(start..) {
+            if page.len() >= limit {
+                break;
+            }
+            let (key, _) = entry.map_err(to_storage_error)?;
+            if !key.starts_with(&prefix) {
+                break;
+            }
+            let suffix = &key[prefix.len()..];
+            let created_at = u64::from_be_bytes(suffix[..8].try_into().expect("8 bytes"));
+            if !filter.matches_created_at(created_at) {
+                break;
+            }
+            let Some(value) = contracts.get(&suffix[8..]).map_err(to_storage_error)? else {
+                continue;
+            };
+            let value = self.open(value).map_err(to_storage_error)?;
+            if state.is_some_and(|state| value.first() != Some(&state)) {
+                continue;
+            }
+            if skipped < offset {
+                skipped += 1;
+                continue;
+            }
+            page.push(deserialize_contract(&value)?);
+        }
+        Ok(page)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::serialize_contract;
+    use crate::DdkStorage;
+    use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+    use dlc_manager::contract::offered_contract::OfferedContract;
+    use dlc_manager::contract::ser::Serializable;
+    use dlc_manager::Storage;
+
+    fn offered() -> OfferedContract {
+        let serialized = include_bytes!("../../../tests/data/dlc_storage/sled/Offered");
+        OfferedContract::deserialize(&mut lightning::io::Cursor::new(&serialized)).unwrap()
+    }
+
+    fn pubkey(byte: u8) -> PublicKey {
+        PublicKey::from_secret_key(&Secp256k1::new(), &SecretKey::from_slice(&[byte; 32]).unwrap())
+    }
+
+    fn id(n: u32) -> ContractId {
+        let mut id = [0u8; 32];
+        id[..4].copy_from_slice(&n.to_be_bytes());
+        id
+    }
+
+    /// Store offers with the given counterparties and creation times in one batch.
+    fn store(storage: &SledStorageProvider, offers: &[(ContractId, PublicKey, u64)]) {
+        let template = offered();
+        let contracts: Vec<(Contract, u64)> = offers
+            .iter()
+            .map(|(id, counterparty, created_at)| {
+                let mut offer = template.clone();
+                offer.id = *id;
+                offer.counter_party = *counterparty;
+                (Contract::Offered(offer), *created_at)
+            })
+            .collect();
+        let tree = storage.contract_tree().unwrap();
+        for (contract, _) in &contracts {
+            tree.insert(contract.get_id(), serialize_contract(contract).unwrap())
+                .unwrap();
+        }
+        storage
+            .contract_index_tree()
+            .unwrap()
+            .transaction(|index| {
+                for (contract, created_at) in &contracts {
+                    index_contract(index, contract, *created_at)?;
+                }
+                Ok::<_, sled::transaction::ConflictableTransactionError<()>>(())
+            })
+            .unwrap();
+    }
+
+    fn ids(contracts: &[Contract]) -> Vec<ContractId> {
+        contracts.iter().map(|c| c.get_id()).collect()
+    }
+
+    #[test]
+    fn pages_follow_creation_time_and_filters() {
+        let path = "tests/data/dlc_storage/sleddb/index_filters";
+        {
+            let storage = SledStorageProvider::new(path).unwrap();
+            let (alice, bob) = (pubkey(1), pubkey(2));
+            store(
+                &storage,
+                &[
+                    (id(0), alice, 300),
+                    (id(1), bob, 100),
+                    (id(2), alice, 200),
+                    (id(3), bob, 400),
+                ],
+            );
+
+            let all = storage
+                .get_contracts_paginated(ContractFilter::default(), 0, 10)
+                .unwrap();
+            assert_eq!(ids(&all), vec![id(1), id(2), id(0), id(3)]);
+
+            let page = storage
+                .get_contracts_paginated(ContractFilter::default(), 1, 2)
+                .unwrap();
+            assert_eq!(ids(&page), vec![id(2), id(0)]);
+
+            let alice_only = ContractFilter {
+                counterparty: Some(alice),
+                ..Default::default()
+            };
+            let page = storage.get_contracts_paginated(alice_only, 0, 10).unwrap();
+            assert_eq!(ids(&page), vec![id(2), id(0)]);
+
+            let window = ContractFilter {
+                created_after: Some(200),
+                created_before: Some(400),
+                ..Default::default()
+            };
+            let page = storage.get_contracts_paginated(window, 0, 10).unwrap();
+            assert_eq!(ids(&page), vec![id(2), id(0)]);
+
+            let closed = ContractFilter {
+                state: Some(crate::contract::ContractState::Closed),
+                ..Default::default()
+            };
+            assert!(storage
+                .get_contracts_paginated(closed, 0, 10)
+                .unwrap()
+                .is_empty());
+
+            storage.delete_contract(&id(2)).unwrap();
+            let page = storage
+                .get_contracts_paginated(ContractFilter::default(), 0, 10)
+                .unwrap();
+            assert_eq!(ids(&page), vec![id(1), id(0), id(3)]);
+        }
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn accepted_contract_keeps_creation_time() {
+        let path = "tests/data/dlc_storage/sleddb/index_accepted";
+        {
+            let storage = SledStorageProvider::new(path).unwrap();
+            let offer = offered();
+            store(&storage, &[(offer.id, offer.counter_party, 100)]);
+
+            let serialized = include_bytes!("../../../tests/data/dlc_storage/sled/Accepted");
+            let accepted = Contract::Accepted(
+                Serializable::deserialize(&mut lightning::io::Cursor::new(&serialized)).unwrap(),
+            );
+            storage.update_contract(&accepted).unwrap();
+
+            let filter = ContractFilter {
+                created_before: Some(101),
+                ..Default::default()
+            };
+            let page = storage.get_contracts_paginated(filter, 0, 10).unwrap();
+            assert_eq!(ids(&page), vec![accepted.get_id()]);
+            assert_eq!(storage.contract_index_tree().unwrap().len(), 3);
+        }
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn only_the_page_is_deserialized() {
+        let path = "tests/data/dlc_storage/sleddb/index_ten_thousand";
+        {
+            let storage = SledStorageProvider::new(path).unwrap();
+            let (alice, bob) = (pubkey(1), pubkey(2));
+            let offers: Vec<_> = (0..10_000)
+                .map(|n| (id(n), if n % 2 == 0 { alice } else { bob }, n as u64))
+                .collect();
+            store(&storage, &offers);
+
+            // Keep the prefix byte but drop the body of every contract outside the page, so
+            // reading any of them fails.
+            let page_ids: Vec<_> = (5000..5010).filter(|n| n % 2 == 1).map(id).collect();
+            let tree = storage.contract_tree().unwrap();
+            let mut batch = sled::Batch::default();
+            for (id, _, _) in &offers {
+                if !page_ids.contains(id) {
+                    batch.insert(id.to_vec(), vec![u8::from(ContractPrefix::Offered)]);
+                }
+            }
+            tree.apply_batch(batch).unwrap();
+            assert!(storage.get_contracts().is_err());
+
+            let filter = ContractFilter {
+                state: Some(crate::contract::ContractState::Offered),
+                counterparty: Some(bob),
+                ..Default::default()
+            };
+            let page = storage.get_contracts_paginated(filter, 2500, 5).unwrap();
+            assert_eq!(ids(&page), page_ids);
+        }
+        std::fs::remove_dir_all(path).unwrap();
+    }
+}
@@ -0,0 +1,360 @@
+//! Integrity check and repair of the contract and channel trees.
+//!
+//! Contract updates move a contract from its temporary id to its final id in one transaction,
+//! but databases written by older builds or damaged by an unclean shutdown can still hold the
+//! record under both. The check reads every contract and channel record, so one that no
+//! longer deserializes is found before the manager trips over it.
+use super::contract::deserialize_channel;
+use super::index::{self, IndexDiff};
+use super::{
+    SledStorageProvider, CHAIN_MONITOR_KEY, CHAIN_MONITOR_TREE, CHANNEL_TREE, CONTRACT_INDEX_TREE,
+    CONTRACT_METADATA_TREE, CONTRACT_TREE,
+};
+use crate::contract::metadata::ContractMetadata;
+use crate::storage::{IntegrityIssue, IntegrityIssueKind, IntegrityReport, RepairReport};
+use crate::util::deserialize_contract;
+use dlc_manager::chain_monitor::ChainMonitor;
+use dlc_manager::channel::Channel;
+use dlc_manager::contract::ser::Serializable;
+use dlc_manager::contract::Contract;
+use dlc_manager::error::Error;
+use dlc_manager::ContractId;
+use sled::transaction::UnabortableTransactionError;
+use sled::Transactional;
+
+fn issue(tree: u8, key: &[u8], value: Option<&[u8]>, kind: IntegrityIssueKind) -> IntegrityIssue {
+    IntegrityIssue {
+        tree: SledStorageProvider::tree_name(&[tree]),
+        key: hex::encode(key),
+        prefix: value.and_then(|value| value.first().copied()),
+        kind,
+    }
+}
+
+/// Temporary id records of a contract stored under its final id.
+struct ContractLeftover {
+    temporary_id: ContractId,
+    id: ContractId,
+    /// The contract tree still holds a record under the temporary id.
+    record: bool,
+    /// The metadata tree still holds the metadata under the temporary id.
+    metadata: bool,
+}
+
+/// What the check found, with what repair needs to fix it.
+struct Inspection {
+    report: IntegrityReport,
+    contract_leftovers: Vec<ContractLeftover>,
+    channel_leftovers: Vec<[u8; 32]>,
+    index_diff: IndexDiff,
+}
+
+impl SledStorageProvider {
+    /// Decrypt and deserialize every record of `tree_id`, reporting the ones that fail.
+    fn read_records<T>(
+        &self,
+        tree_id: u8,
+        deserialize: impl Fn(&[u8]) -> Result<T, Error>,
+        issues: &mut Vec<IntegrityIssue>,
+    ) -> anyhow::Result<Vec<(Vec<u8>, T)>> {
+        let mut records = vec![];
+        for entry in self.db.open_tree([tree_id])?.iter() {
+            let (key, value) = entry?;
+            let value = match self.open(value) {
+                Ok(value) => value,
+                Err(e) => {
+                    let kind = IntegrityIssueKind::Unreadable(e.to_string());
+                    issues.push(issue(tree_id, &key, None, kind));
+                    continue;
+                }
+            };
+            match deserialize(&value) {
+                Ok(record) => records.push((key.to_vec(), record)),
+                Err(e) => {
+                    let kind = IntegrityIssueKind::Unreadable(e.to_string());
+                    issues.push(issue(tree_id, &key, Some(&value[..]), kind));
+                }
+            }
+        }
+        Ok(records)
+    }
+
+    fn inspect(&self) -> anyhow::Result<Inspection> {
+        let mut issues = vec![];
+        let contract_tree = self.contract_tree()?;
+        let channel_tree = self.channel_tree()?;
+        let metadata_tree = self.contract_metadata_tree()?;
+        let index_tree = self.contract_index_tree()?;
+
+        let contracts = self.read_records(CONTRACT_TREE, deserialize_contract, &mut issues)?;
+        let unreadable: Vec<ContractId> = issues
+            .iter()
+            .filter_map(|issue| hex::decode(&issue.key).ok()?.try_into().ok())
+            .collect();
+        let channels = self.read_records(CHANNEL_TREE, deserialize_channel, &mut issues)?;
+
+        let mut contract_leftovers = vec![];
+        for (_, contract) in &contracts {
+            let (id, temporary_id) = (contract.get_id(), contract.get_temporary_id());
+            if id == temporary_id {
+                continue;
+            }
+            let leftover = ContractLeftover {
+                temporary_id,
+                id,
+                record: contract_tree.contains_key(temporary_id)?,
+                metadata: metadata_tree.contains_key(temporary_id)?,
+            };
+            if leftover.record {
+                let value = contract_tree.get(temporary_id)?;
+                let value = value
+                    .map(|value| self.open(value))
+                    .transpose()
+                    .ok()
+                    .flatten();
+                let kind = IntegrityIssueKind::TemporaryIdLeftover;
+                issues.push(issue(CONTRACT_TREE, &temporary_id, value.as_deref(), kind));
+            }
+            if leftover.metadata {
+                let kind = IntegrityIssueKind::TemporaryIdLeftover;
+                issues.push(issue(CONTRACT_METADATA_TREE, &temporary_id, None, kind));
+            }
+            if leftover.record || leftover.metadata {
+                contract_leftovers.push(leftover);
+            }
+        }
+
+        let mut channel_leftovers = vec![];
+        for (key, channel) in &channels {
+            let temporary_id = channel.get_temporary_id();
+            if channel.get_id() != temporary_id && channel_tree.contains_key(temporary_id)? {
+                let value = channel_tree.get(temporary_id)?;
+                let value = value
+                    .map(|value| self.open(value))
+                    .transpose()
+                    .ok()
+                    .flatten();
+                let kind = IntegrityIssueKind::TemporaryIdLeftover;
+                issues.push(issue(CHANNEL_TREE, &temporary_id, value.as_deref(), kind));
+                channel_leftovers.push(temporary_id);
+            }
+            let Channel::Signed(signed) = channel else {
+                continue;
+            };
+            if let Some(contract_id) = signed.get_contract_id() {
+                if !contract_tree.contains_key(contract_id)? {
+                    let value = channel_tree
+                        .get(key)?
+                        .map(|value| self.open(value))
+                        .transpose()?;
+                    let kind = IntegrityIssueKind::MissingContract(hex::encode(contract_id));
+                    issues.push(issue(CHANNEL_TREE, key, value.as_deref(), kind));
+                }
+            }
+        }
+
+        if let Some(monitor) = self
+            .db
+            .open_tree([CHAIN_MONITOR_TREE])?
+            .get([CHAIN_MONITOR_KEY])?
+        {
+            if let Err(e) = ChainMonitor::deserialize(&mut lightning::io::Cursor::new(&monitor)) {
+                let kind = IntegrityIssueKind::Unreadable(e.to_string());
+                issues.push(issue(
+                    CHAIN_MONITOR_TREE,
+                    &[CHAIN_MONITOR_KEY],
+                    Some(&monitor[..]),
+                    kind,
+                ));
+            }
+        }
+
+        // Leftover records are not indexed, repair deletes them.
+        let indexed: Vec<&Contract> = contracts
+            .iter()
+            .filter(|(key, contract)| key.as_slice() == contract.get_id())
+            .filter(|(key, _)| {
+                !contract_leftovers
+                    .iter()
+                    .any(|l| l.record && l.temporary_id == key.as_slice())
+            })
+            .map(|(_, contract)| contract)
+            .collect();
+        let metadata_created_at = |contract: &Contract| {
+            [contract.get_id(), contract.get_temporary_id()]
+                .into_iter()
+                .find_map(|id| metadata_tree.get(id).ok().flatten())
+                .and_then(|value| serde_json::from_slice::<ContractMetadata>(&value).ok())
+                .map(|metadata| metadata.created_at)
+        };
+        let expected =
+            index::expected_entries(&index_tree, &indexed, &unreadable, metadata_created_at)?;
+        let index_diff = index::diff(&index_tree, &expected)?;
+        for key in index_diff
+            .stale
+            .iter()
+            .chain(index_diff.missing.iter().map(|(key, _)| key))
+        {
+            issues.push(issue(
+                CONTRACT_INDEX_TREE,
+                key,
+                None,
+                IntegrityIssueKind::IndexMismatch,
+            ));
+        }
+
+        Ok(Inspection {
+            report: IntegrityReport {
+                contracts_checked: contracts.len(),
+                channels_checked: channels.len(),
+                issues,
+            },
+            contract_leftovers,
+            channel_leftovers,
+            index_diff,
+        })
+    }
+
+    /// Read every contract and channel record and the chain monitor, and check that signed
+    /// channels reference stored contracts, that no record is left under a temporary id, and
+    /// that the contract index matches the contracts.
+    pub(crate) fn integrity_report(&self) -> anyhow::Result<IntegrityReport> {
+        Ok(self.inspect()?.report)
+    }
+
+    /// Delete records left under temporary ids and rebuild the contract index. Metadata left
+    /// under a temporary id moves to the final id unless the contract already has metadata
+    /// there. Records that do not deserialize are never deleted, restore them from a backup.
+    /// With `dry_run` nothing is written and the report lists what would change.
+    pub fn repair(&self, dry_run: bool) -> anyhow::Result<RepairReport> {
+        let inspection = self.inspect()?;
+        let removed = inspection
+            .report
+            .issues
+            .into_iter()
+            .filter(|issue| issue.kind == IntegrityIssueKind::TemporaryIdLeftover)
+            .collect();
+        let report = RepairReport {
+            dry_run,
+            removed,
+            index_entries_fixed: inspection.index_diff.len(),
+        };
+        if dry_run {
+            return Ok(report);
+        }
+
+        let contract_tree = self.contract_tree()?;
+        let channel_tree = self.channel_tree()?;
+        let metadata_tree = self.contract_metadata_tree()?;
+        let index_tree = self.contract_index_tree()?;
+        (&contract_tree, &channel_tree, &metadata_tree, &index_tree)
+            .transaction::<_, _, UnabortableTransactionError>(
+                |(contracts, channels, metadata, index)| {
+                    for leftover in &inspection.contract_leftovers {
+                        if leftover.record {
+                            contracts.remove(&leftover.temporary_id)?;
+                        }
+                        if let Some(value) = metadata.remove(&leftover.temporary_id)? {
+                            if metadata.get(leftover.id)?.is_none() {
+                                metadata.insert(&leftover.id, value)?;
+                            }
+                        }
+                    }
+                    for temporary_id in &inspection.channel_leftovers {
+                        channels.remove(temporary_id)?;
+                    }
+                    for key in &inspection.index_diff.stale {
+                        index.remove(key.as_slice())?;
+                    }
+                    for (key, value) in &inspection.index_diff.missing {
+                        index.insert(key.as_slice(), value.as_slice())?;
+                    }
+                    Ok(())
+                },
+            )
+            .map_err(|e| anyhow::anyhow!("Could not repair storage. {}", e))?;
+        self.db.flush()?;
+        tracing::info!(
+            removed = report.removed.len(),
+            index_entries_fixed = report.index_entries_fixed,
+            "Repaired storage."
+        );
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::serialize_contract;
+    use crate::DdkStorage;
+    use dlc_manager::contract::offered_contract::OfferedContract;
+    use dlc_manager::Storage;
+
+    fn deserialize_object<T: Serializable>(serialized: &[u8]) -> T {
+        T::deserialize(&mut lightning::io::Cursor::new(&serialized)).unwrap()
+    }
+
+    fn kinds(report: &IntegrityReport) -> Vec<(String, IntegrityIssueKind)> {
+        report
+            .issues
+            .iter()
+            .map(|issue| (issue.tree.clone(), issue.kind.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn leftovers_are_repaired_and_unreadable_records_kept() {
+        let path = "tests/data/dlc_storage/sleddb/integrity";
+        {
+            let storage = SledStorageProvider::new(path).unwrap();
+            let offered: OfferedContract = deserialize_object(include_bytes!(
+                "../../../tests/data/dlc_storage/sled/Offered"
+            ));
+            let accepted = Contract::Accepted(deserialize_object(include_bytes!(
+                "../../../tests/data/dlc_storage/sled/Accepted"
+            )));
+            storage.create_contract(&offered).unwrap();
+            storage.update_contract(&accepted).unwrap();
+            let report = storage.verify_integrity().unwrap();
+            assert!(report.is_ok(), "{:?}", report.issues);
+            assert_eq!(report.contracts_checked, 1);
+
+            // What an interrupted update leaves behind, a corrupted contract, and a lost index
+            // entry.
+            let contracts = storage.contract_tree().unwrap();
+            let offer = serialize_contract(&Contract::Offered(offered.clone())).unwrap();
+            contracts.insert(offered.id, offer).unwrap();
+            contracts.insert([9u8; 32], vec![3, 0, 0]).unwrap();
+            let index = storage.contract_index_tree().unwrap();
+            let (time_key, _) = index.scan_prefix([1]).next().unwrap().unwrap();
+            index.remove(time_key).unwrap();
+
+            let report = storage.verify_integrity().unwrap();
+            let found = kinds(&report);
+            assert_eq!(found.len(), 3, "{:?}", found);
+            assert!(found.contains(&("contracts".into(), IntegrityIssueKind::TemporaryIdLeftover)));
+            assert!(found.contains(&("contract_index".into(), IntegrityIssueKind::IndexMismatch)));
+            let corrupt = report
+                .issues
+                .iter()
+                .find(|issue| matches!(issue.kind, IntegrityIssueKind::Unreadable(_)))
+                .unwrap();
+            assert_eq!(corrupt.key, hex::encode([9u8; 32]));
+            assert_eq!(corrupt.prefix, Some(3));
+
+            let dry_run = storage.repair(true).unwrap();
+            assert_eq!(dry_run.removed.len(), 1);
+            assert_eq!(dry_run.index_entries_fixed, 1);
+            assert_eq!(storage.verify_integrity().unwrap(), report);
+
+            let repaired = storage.repair(false).unwrap();
+            assert_eq!(repaired.removed, dry_run.removed);
+            let report = storage.verify_integrity().unwrap();
+            assert_eq!(report.issues, vec![corrupt.clone()]);
+            assert!(storage.get_contract(&accepted.get_id()).unwrap().is_some());
+            assert!(storage.get_contract(&offered.id).unwrap().is_none());
+        }
+        std::fs::remove_dir_all(path).unwrap();
+    }
+}
@@ -0,0 +1,327 @@
+//! Versioning of the sled database layout.
+//!
+//! The version lives in the meta tree. Opening a database runs every migration newer than the
+//! stored version in order, recording the version after each one so an interrupted upgrade
+//! resumes where it stopped. Databases written before versioning have no version record and
+//! are upgraded from version 0.
+use super::index::index_contract;
+use super::{
+    SledStorageProvider, CONTRACT_INDEX_TREE, CONTRACT_METADATA_TREE, CONTRACT_TREE, META_TREE,
+    PEER_TREE, WALLET_TREE,
+};
+use crate::contract::metadata::ContractMetadata;
+use crate::transport::PeerInformation;
+use crate::util::deserialize_contract;
+use bdk_chain::Merge;
+use bdk_wallet::ChangeSet;
+use sled::Db;
+
+/// Version of the layout written by this build.
+pub const SLED_SCHEMA_VERSION: u32 = 3;
+
+const SCHEMA_VERSION_KEY: &[u8] = b"schema_version";
+/// Key of the JSON peer list in the default tree before version 1.
+const LEGACY_PEERS_KEY: &[u8] = b"peers";
+
+type Migration = fn(&Db) -> Result<(), sled::Error>;
+
+/// `MIGRATIONS[n]` upgrades a database from version `n` to `n + 1`.
+const MIGRATIONS: [Migration; SLED_SCHEMA_VERSION as usize] =
+    [peers_to_tree, wallet_changesets_to_sequence, contracts_to_index];
+
+impl SledStorageProvider {
+    /// Layout version of the database. `None` when it was written before versioning.
+    pub fn schema_version(&self) -> Result<Option<u32>, sled::Error> {
+        let Some(value) = self.db.open_tree([META_TREE])?.get(SCHEMA_VERSION_KEY)? else {
+            return Ok(None);
+        };
+        let bytes: [u8; 4] = value
+            .as_ref()
+            .try_into()
+            .map_err(|_| unsupported("Malformed storage schema version."))?;
+        Ok(Some(u32::from_be_bytes(bytes)))
+    }
+
+    /// Upgrade the database to [SLED_SCHEMA_VERSION] and return the version. Databases written
+    /// by a newer build are refused instead of being read with the wrong layout.
+    pub fn migrate(&self) -> Result<u32, sled::Error> {
+        let mut version = self.schema_version()?.unwrap_or(0);
+        if version > SLED_SCHEMA_VERSION {
+            return Err(unsupported(format!(
+                "Storage schema version {} is newer than this build supports ({}). Upgrade ddk to open it.",
+                version, SLED_SCHEMA_VERSION
+            )));
+        }
+
+        for migration in &MIGRATIONS[version as usize..] {
+            migration(&self.db)?;
+            version += 1;
+            self.set_schema_version(version)?;
+            tracing::info!(version, "Migrated sled storage.");
+        }
+        Ok(version)
+    }
+}
+
+impl SledStorageProvider {
+    pub(super) fn set_schema_version(&self, version: u32) -> Result<(), sled::Error> {
+        let meta = self.db.open_tree([META_TREE])?;
+        meta.insert(SCHEMA_VERSION_KEY, version.to_be_bytes().to_vec())?;
+        meta.flush()?;
+        Ok(())
+    }
+}
+
+fn unsupported(message: impl ToString) -> sled::Error {
+    sled::Error::Unsupported(message.to_string())
+}
+
+/// Version 1 moves the peers from a JSON array in the default tree to a tree keyed by pubkey.
+/// When a pubkey was stored with several hosts the last one is kept.
+fn peers_to_tree(db: &Db) -> Result<(), sled::Error> {
+    let Some(bytes) = db.get(LEGACY_PEERS_KEY)? else {
+        return Ok(());
+    };
+    let peers: Vec<PeerInformation> = serde_json::from_slice(&bytes)
+        .map_err(|e| unsupported(format!("Could not read stored peers. error={}", e)))?;
+
+    let tree = db.open_tree([PEER_TREE])?;
+    for peer in peers {
+        let value = serde_json::to_vec(&peer).map_err(|e| unsupported(e.to_string()))?;
+        tree.insert(peer.pubkey.as_bytes(), value)?;
+    }
+    tree.flush()?;
+    db.remove(LEGACY_PEERS_KEY)?;
+    db.flush()?;
+    Ok(())
+}
+
+/// Version 2 merges the wallet changesets stored under random keys into one record under
+/// sequence number 0, the first key of the sequence [WalletPersister](bdk_wallet::WalletPersister)
+/// now appends to. Their write order is lost, but it only matters for chain data which the
+/// wallet did not persist before.
+fn wallet_changesets_to_sequence(db: &Db) -> Result<(), sled::Error> {
+    let tree = db.open_tree([WALLET_TREE])?;
+    let mut aggregate = ChangeSet::default();
+    let mut legacy = vec![];
+    for entry in tree.iter() {
+        let (key, value) = entry?;
+        if key.len() == 8 {
+            continue;
+        }
+        let changeset: ChangeSet = bincode::deserialize(&value)
+            .map_err(|e| unsupported(format!("Could not read wallet changeset. error={}", e)))?;
+        aggregate.merge(changeset);
+        legacy.push(key);
+    }
+    if legacy.is_empty() {
+        return Ok(());
+    }
+
+    let aggregate = bincode::serialize(&aggregate).map_err(|e| unsupported(e.to_string()))?;
+    tree.insert(0u64.to_be_bytes(), aggregate)?;
+    tree.flush()?;
+    for key in legacy {
+        tree.remove(key)?;
+    }
+    tree.flush()?;
+    Ok(())
+}
+
+/// Version 3 builds the creation time and counterparty index of the contracts. Their creation
+/// time was not recorded, so it is taken from their metadata or set to 0.
+fn contracts_to_index(db: &Db) -> Result<(), sled::Error> {
+    let contracts = db.open_tree([CONTRACT_TREE])?;
+    let metadata = db.open_tree([CONTRACT_METADATA_TREE])?;
+    let index = db.open_tree([CONTRACT_INDEX_TREE])?;
+    for entry in contracts.iter() {
+        let (id, value) = entry?;
+        let contract = deserialize_contract(&value)
+            .map_err(|e| unsupported(format!("Could not read stored contract. error={}", e)))?;
+        let created_at = match metadata.get(&id)? {
+            Some(bytes) => serde_json::from_slice::<ContractMetadata>(&bytes)
+                .map(|m| m.created_at)
+                .unwrap_or_default(),
+            None => 0,
+        };
+        index
+            .transaction(|index| {
+                index_contract(index, &contract, created_at)?;
+                Ok::<_, sled::transaction::ConflictableTransactionError<sled::Error>>(())
+            })
+            .map_err(|e| unsupported(e.to_string()))?;
+    }
+    index.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DdkStorage;
+
+    fn peer(pubkey: &str, host: &str) -> PeerInformation {
+        PeerInformation {
+            pubkey: pubkey.into(),
+            host: host.into(),
+        }
+    }
+
+    #[test]
+    fn fresh_db_is_current() {
+        let path = "tests/data/dlc_storage/sleddb/migration_fresh";
+        {
+            let storage = SledStorageProvider::new(path).unwrap();
+            assert_eq!(storage.schema_version().unwrap(), Some(SLED_SCHEMA_VERSION));
+            assert!(storage.list_peers().unwrap().is_empty());
+        }
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn migrated_db_is_left_alone() {
+        let path = "tests/data/dlc_storage/sleddb/migration_current";
+        {
+            let storage = SledStorageProvider::new(path).unwrap();
+            storage.save_peer(peer("a", "127.0.0.1:9000")).unwrap();
+        }
+        {
+            let storage = SledStorageProvider::new(path).unwrap();
+            assert_eq!(storage.migrate().unwrap(), SLED_SCHEMA_VERSION);
+            assert_eq!(
+                storage.list_peers().unwrap(),
+                vec![peer("a", "127.0.0.1:9000")]
+            );
+        }
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn legacy_peers_are_moved() {
+        let path = "tests/data/dlc_storage/sleddb/migration_legacy";
+        {
+            let db = sled::open(path).unwrap();
+            let legacy = vec![
+                peer("b", "127.0.0.1:9001"),
+                peer("a", "127.0.0.1:9000"),
+                peer("b", "127.0.0.1:9002"),
+            ];
+            db.insert(LEGACY_PEERS_KEY, serde_json::to_vec(&legacy).unwrap())
+                .unwrap();
+            db.flush().unwrap();
+        }
+        {
+            let storage = SledStorageProvider::new(path).unwrap();
+            assert_eq!(storage.schema_version().unwrap(), Some(SLED_SCHEMA_VERSION));
+            assert_eq!(
+                storage.list_peers().unwrap(),
+                vec![peer("a", "127.0.0.1:9000"), peer("b", "127.0.0.1:9002")]
+            );
+            assert!(storage.db.get(LEGACY_PEERS_KEY).unwrap().is_none());
+        }
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn random_keyed_changesets_are_merged() {
+        use bdk_wallet::template::Bip84;
+        use bdk_wallet::{KeychainKind, Wallet};
+        use bitcoin::bip32::Xpriv;
+        use bitcoin::key::rand::{thread_rng, Rng};
+        use bitcoin::Network;
+
+        let path = "tests/data/dlc_storage/sleddb/migration_wallet";
+        {
+            let xprv = Xpriv::new_master(Network::Regtest, &[8u8; 32]).unwrap();
+            let mut wallet = Wallet::create(
+                Bip84(xprv, KeychainKind::External),
+                Bip84(xprv, KeychainKind::Internal),
+            )
+            .network(Network::Regtest)
+            .create_wallet_no_persist()
+            .unwrap();
+            let db = sled::open(path).unwrap();
+            let tree = db.open_tree([WALLET_TREE]).unwrap();
+            let write = |changeset: ChangeSet| {
+                let key: [u8; 32] = thread_rng().gen();
+                tree.insert(key, bincode::serialize(&changeset).unwrap())
+                    .unwrap();
+            };
+            write(wallet.take_staged().unwrap());
+            let _ = wallet.reveal_addresses_to(KeychainKind::External, 4);
+            write(wallet.take_staged().unwrap());
+            db.flush().unwrap();
+        }
+        {
+            let storage = SledStorageProvider::new(path).unwrap();
+            let tree = storage.wallet_tree().unwrap();
+            assert_eq!(tree.len(), 1);
+            assert_eq!(
+                tree.first().unwrap().unwrap().0.to_vec(),
+                0u64.to_be_bytes().to_vec()
+            );
+            let changeset = storage.stored_changeset().unwrap();
+            assert_eq!(changeset.network, Some(Network::Regtest));
+            assert_eq!(changeset.indexer.last_revealed.values().max(), Some(&4));
+        }
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn stored_contracts_are_indexed() {
+        use crate::storage::ContractFilter;
+        use dlc_manager::contract::ser::Serializable;
+        use dlc_manager::contract::Contract;
+
+        let path = "tests/data/dlc_storage/sleddb/migration_index";
+        let serialized = include_bytes!("../../../tests/data/dlc_storage/sled/Offered");
+        let offer: dlc_manager::contract::offered_contract::OfferedContract =
+            Serializable::deserialize(&mut lightning::io::Cursor::new(&serialized)).unwrap();
+        {
+            let db = sled::open(path).unwrap();
+            db.open_tree([META_TREE])
+                .unwrap()
+                .insert(SCHEMA_VERSION_KEY, 2u32.to_be_bytes().to_vec())
+                .unwrap();
+            let contract = crate::util::serialize_contract(&Contract::Offered(offer.clone()));
+            db.open_tree([CONTRACT_TREE])
+                .unwrap()
+                .insert(offer.id, contract.unwrap())
+                .unwrap();
+            db.flush().unwrap();
+        }
+        {
+            let storage = SledStorageProvider::new(path).unwrap();
+            let filter = ContractFilter {
+                counterparty: Some(offer.counter_party),
+                created_before: Some(1),
+                ..Default::default()
+            };
+            let page = storage.get_contracts_paginated(filter, 0, 10).unwrap();
+            assert_eq!(page.len(), 1);
+            assert_eq!(page[0].get_id(), offer.id);
+        }
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn newer_db_is_refused() {
+        let path = "tests/data/dlc_storage/sleddb/migration_newer";
+        {
+            let db = sled::open(path).unwrap();
+            db.open_tree([META_TREE])
+                .unwrap()
+                .insert(
+                    SCHEMA_VERSION_KEY,
+                    (SLED_SCHEMA_VERSION + 1).to_be_bytes().to_vec(),
+                )
+                .unwrap();
+            db.flush().unwrap();
+        }
+        let result = SledStorageProvider::new(path);
+        assert!(
+            matches!(result, Err(sled::Error::Unsupported(message)) if message.contains("newer"))
+        );
+        std::fs::remove_dir_all(path).unwrap();
+    }
+}
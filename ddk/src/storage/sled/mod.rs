@@ -2,39 +2,130 @@
 //! # dlc-sled-storage-provider
 //! Storage provider for dlc-manager using sled as underlying storage.
 
+mod archive;
+mod backup;
 mod contract;
+mod encryption;
+mod index;
+mod integrity;
+mod migration;
+mod signer;
 mod wallet;
 
+pub use backup::{BackupSummary, BACKUP_FORMAT_VERSION};
+pub use migration::SLED_SCHEMA_VERSION;
+pub use signer::read_signer_archive;
+pub(crate) use contract::{deserialize_channel, serialize_channel, ChannelPrefix, SignedChannelPrefix};
+pub(crate) use signer::append_to_archive;
+pub use wallet::DEFAULT_WALLET_COMPACTION_THRESHOLD;
+
+use bitcoin::hashes::Hash;
+use bitcoin::key::XOnlyPublicKey;
+use bitcoin::secp256k1::PublicKey;
+use std::time::Duration;
 use dlc_manager::contract::ser::Serializable;
+use dlc_manager::contract::Contract;
 use dlc_manager::error::Error;
+use dlc_manager::ContractId;
+use dlc_messages::oracle_msgs::{OracleAnnouncement, OracleAttestation};
+use dlc_messages::Message;
+use sled::transaction::UnabortableTransactionError;
 use sled::{Db, Tree};
 use lightning::io::{Cursor, Read};
 
-use crate::transport::PeerInformation;
+use crate::accounting::ContractSettlement;
+use crate::audit::AuditEntry;
+use crate::chain::rebroadcast::PendingBroadcast;
+use crate::contract::metadata::ContractMetadata;
+use crate::rates::ContractRates;
+use crate::storage::{
+    ContractFilter, IntegrityReport, SignerVacuumOptions, SignerVacuumReport, StorageStats,
+};
+use crate::transport::rate_limit::PeerBan;
+use crate::transport::{PeerInformation, PendingInbound, PendingOutbound};
+use crate::wallet::fees::StoredFees;
+use crate::wallet::ContractTransaction;
 use crate::DdkStorage;
 
 const CONTRACT_TREE: u8 = 1;
 const CHANNEL_TREE: u8 = 2;
 pub const CHAIN_MONITOR_TREE: u8 = 3;
 pub const CHAIN_MONITOR_KEY: u8 = 4;
-const PEER_KEY: u8 = 5;
+const PEER_TREE: u8 = 5;
 const SIGNER_TREE: u8 = 6;
 const WALLET_TREE: u8 = 7;
+const PENDING_OUTBOUND_TREE: u8 = 8;
+const CONTRACT_RATES_TREE: u8 = 9;
+const KEY_USAGE_TREE: u8 = 10;
+const SETTINGS_TREE: u8 = 11;
+const CONTRACT_TRANSACTIONS_TREE: u8 = 12;
+const META_TREE: u8 = 13;
+const CONTRACT_METADATA_TREE: u8 = 14;
+const CONTRACT_INDEX_TREE: u8 = 15;
+const CONTRACT_ARCHIVE_TREE: u8 = 16;
+const INBOUND_MESSAGE_TREE: u8 = 17;
+const PROCESSED_INBOUND_TREE: u8 = 18;
+const AUDIT_LOG_TREE: u8 = 19;
+const CONTRACT_SETTLEMENTS_TREE: u8 = 20;
+const ORACLE_ANNOUNCEMENTS_TREE: u8 = 21;
+const ORACLE_ATTESTATIONS_TREE: u8 = 22;
+const FEES_TREE: u8 = 23;
+
+const MAINTENANCE_KEY: &[u8] = b"maintenance";
+const RESERVED_UTXOS_KEY: &[u8] = b"reserved_utxos";
+const FROZEN_UTXOS_KEY: &[u8] = b"frozen_utxos";
+const PEER_BANS_KEY: &[u8] = b"peer_bans";
+const PENDING_BROADCASTS_KEY: &[u8] = b"pending_broadcasts";
+const SIGNER_INDEX_KEY: &[u8] = b"signer_index";
+
+fn transport_cursor_key(transport: &str) -> Vec<u8> {
+    format!("transport_cursor/{}", transport).into_bytes()
+}
 
 /// Implementation of Storage interface using the sled DB backend.
 #[derive(Debug, Clone)]
 pub struct SledStorageProvider {
     db: Db,
+    strict_transitions: bool,
+    wallet_compaction_threshold: Option<usize>,
+    cipher: Option<encryption::ValueCipher>,
 }
 
 impl SledStorageProvider {
-    /// Creates a new instance of a SledStorageProvider.
+    /// Creates a new instance of a SledStorageProvider, upgrading the database to the current
+    /// layout. Fails on a database encrypted with [SledStorageProvider::new_encrypted].
     pub fn new(path: &str) -> Result<Self, sled::Error> {
+        let storage = Self::open_unmigrated(path)?;
+        storage.check_not_encrypted()?;
+        storage.migrate()?;
+        Ok(storage)
+    }
+
+    /// Open without upgrading, to inspect a database that may have been written by another
+    /// build.
+    pub(crate) fn open_unmigrated(path: &str) -> Result<Self, sled::Error> {
         Ok(SledStorageProvider {
             db: sled::open(path)?,
+            strict_transitions: false,
+            wallet_compaction_threshold: Some(DEFAULT_WALLET_COMPACTION_THRESHOLD),
+            cipher: None,
         })
     }
 
+    /// Reject contract updates that do not follow the contract state machine. By default
+    /// invalid transitions are only logged.
+    pub fn with_strict_transitions(mut self, strict_transitions: bool) -> Self {
+        self.strict_transitions = strict_transitions;
+        self
+    }
+
+    /// Merge the wallet changesets into one record once more than `threshold` are stored.
+    /// `None` never compacts.
+    pub fn with_wallet_compaction(mut self, threshold: Option<usize>) -> Self {
+        self.wallet_compaction_threshold = threshold;
+        self
+    }
+
     fn get_data_with_prefix<T: Serializable>(
         &self,
         tree: &Tree,
@@ -44,7 +135,10 @@ impl SledStorageProvider {
         let iter = tree.iter();
         iter.values()
             .filter_map(|res| {
-                let value = res.unwrap();
+                let value = match self.open(res.unwrap()) {
+                    Ok(value) => value,
+                    Err(e) => return Some(Err(Error::StorageError(e.to_string()))),
+                };
                 let mut cursor = Cursor::new(&value);
                 let mut pref = vec![0u8; prefix.len()];
                 cursor.read_exact(&mut pref).expect("Error reading prefix");
@@ -81,30 +175,665 @@ impl SledStorageProvider {
     pub fn wallet_tree(&self) -> Result<Tree, sled::Error> {
         self.db.open_tree(&[WALLET_TREE])
     }
+
+    fn pending_outbound_tree(&self) -> Result<Tree, sled::Error> {
+        self.db.open_tree(&[PENDING_OUTBOUND_TREE])
+    }
+
+    fn inbound_message_tree(&self) -> Result<Tree, sled::Error> {
+        self.db.open_tree(&[INBOUND_MESSAGE_TREE])
+    }
+
+    fn processed_inbound_tree(&self) -> Result<Tree, sled::Error> {
+        self.db.open_tree(&[PROCESSED_INBOUND_TREE])
+    }
+
+    fn audit_log_tree(&self) -> Result<Tree, sled::Error> {
+        self.db.open_tree(&[AUDIT_LOG_TREE])
+    }
+
+    fn contract_rates_tree(&self) -> Result<Tree, sled::Error> {
+        self.db.open_tree(&[CONTRACT_RATES_TREE])
+    }
+
+    fn contract_settlements_tree(&self) -> Result<Tree, sled::Error> {
+        self.db.open_tree(&[CONTRACT_SETTLEMENTS_TREE])
+    }
+
+    fn oracle_announcements_tree(&self) -> Result<Tree, sled::Error> {
+        self.db.open_tree(&[ORACLE_ANNOUNCEMENTS_TREE])
+    }
+
+    fn oracle_attestations_tree(&self) -> Result<Tree, sled::Error> {
+        self.db.open_tree(&[ORACLE_ATTESTATIONS_TREE])
+    }
+
+    fn fees_tree(&self) -> Result<Tree, sled::Error> {
+        self.db.open_tree(&[FEES_TREE])
+    }
+
+    fn key_usage_tree(&self) -> Result<Tree, sled::Error> {
+        self.db.open_tree(&[KEY_USAGE_TREE])
+    }
+
+    fn settings_tree(&self) -> Result<Tree, sled::Error> {
+        self.db.open_tree(&[SETTINGS_TREE])
+    }
+
+    fn contract_transactions_tree(&self) -> Result<Tree, sled::Error> {
+        self.db.open_tree(&[CONTRACT_TRANSACTIONS_TREE])
+    }
+
+    fn peer_tree(&self) -> Result<Tree, sled::Error> {
+        self.db.open_tree(&[PEER_TREE])
+    }
+
+    fn contract_metadata_tree(&self) -> Result<Tree, sled::Error> {
+        self.db.open_tree(&[CONTRACT_METADATA_TREE])
+    }
+
+    fn contract_index_tree(&self) -> Result<Tree, sled::Error> {
+        self.db.open_tree(&[CONTRACT_INDEX_TREE])
+    }
+
+    fn contract_archive_tree(&self) -> Result<Tree, sled::Error> {
+        self.db.open_tree(&[CONTRACT_ARCHIVE_TREE])
+    }
+
+    fn tree_name(tree_id: &[u8]) -> String {
+        match tree_id {
+            [CONTRACT_TREE] => "contracts".into(),
+            [CHANNEL_TREE] => "channels".into(),
+            [CHAIN_MONITOR_TREE] => "chain_monitor".into(),
+            [SIGNER_TREE] => "signers".into(),
+            [WALLET_TREE] => "wallet".into(),
+            [PENDING_OUTBOUND_TREE] => "pending_outbound".into(),
+            [CONTRACT_RATES_TREE] => "contract_rates".into(),
+            [KEY_USAGE_TREE] => "key_usage".into(),
+            [SETTINGS_TREE] => "settings".into(),
+            [CONTRACT_TRANSACTIONS_TREE] => "contract_transactions".into(),
+            [PEER_TREE] => "peers".into(),
+            [META_TREE] => "meta".into(),
+            [CONTRACT_METADATA_TREE] => "contract_metadata".into(),
+            [CONTRACT_INDEX_TREE] => "contract_index".into(),
+            [CONTRACT_ARCHIVE_TREE] => "contract_archive".into(),
+            [INBOUND_MESSAGE_TREE] => "inbound_messages".into(),
+            [PROCESSED_INBOUND_TREE] => "processed_inbound".into(),
+            [AUDIT_LOG_TREE] => "audit_log".into(),
+            [CONTRACT_SETTLEMENTS_TREE] => "contract_settlements".into(),
+            [ORACLE_ANNOUNCEMENTS_TREE] => "oracle_announcements".into(),
+            [ORACLE_ATTESTATIONS_TREE] => "oracle_attestations".into(),
+            [FEES_TREE] => "fees".into(),
+            name => String::from_utf8_lossy(name).to_string(),
+        }
+    }
+}
+
+/// Oracle data is keyed by the oracle public key followed by the event id.
+fn oracle_event_key(oracle: &XOnlyPublicKey, event_id: &str) -> Vec<u8> {
+    let mut key = oracle.serialize().to_vec();
+    key.extend_from_slice(event_id.as_bytes());
+    key
 }
 
 impl DdkStorage for SledStorageProvider {
+    fn from_config(config: &crate::config::DdkConfig) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(&config.storage_path)?;
+        let path = config.storage_path.join(crate::storage::SLED_DB_DIR);
+        Ok(SledStorageProvider::new(&path.to_string_lossy())?)
+    }
+
     fn list_peers(&self) -> anyhow::Result<Vec<PeerInformation>> {
-        if let Some(bytes) = self.db.get("peers")? {
-            let peers: Vec<PeerInformation> = serde_json::from_slice(&bytes)?;
-            Ok(peers)
-        } else {
-            Ok(vec![])
+        let mut peers = vec![];
+        for entry in self.peer_tree()?.iter() {
+            let (_, value) = entry?;
+            peers.push(serde_json::from_slice(&value)?);
         }
+        Ok(peers)
     }
 
+    /// Peers are keyed by pubkey, saving a known peer with a new host replaces its host.
     fn save_peer(&self, peer: PeerInformation) -> anyhow::Result<()> {
-        let mut known_peers = self.list_peers()?;
+        let tree = self.peer_tree()?;
+        tree.insert(peer.pubkey.as_bytes(), serde_json::to_vec(&peer)?)?;
+        tree.flush()?;
+        Ok(())
+    }
+
+    fn save_pending_outbound(&self, pending: PendingOutbound) -> anyhow::Result<()> {
+        let tree = self.pending_outbound_tree()?;
+        tree.insert(pending.id.as_bytes(), serde_json::to_vec(&pending)?)?;
+        tree.flush()?;
+        Ok(())
+    }
+
+    fn list_pending_outbound(&self) -> anyhow::Result<Vec<PendingOutbound>> {
+        let mut pending = vec![];
+        for entry in self.pending_outbound_tree()?.iter() {
+            let (_, value) = entry?;
+            pending.push(serde_json::from_slice(&value)?);
+        }
+        Ok(pending)
+    }
+
+    fn remove_pending_outbound(&self, id: &str) -> anyhow::Result<()> {
+        self.pending_outbound_tree()?.remove(id.as_bytes())?;
+        Ok(())
+    }
+
+    fn queue_inbound_message(&self, counterparty: PublicKey, message: &Message) -> anyhow::Result<bool> {
+        let pending = PendingInbound::new(counterparty, message);
+        if self.processed_inbound_tree()?.contains_key(pending.id.as_bytes())? {
+            return Ok(false);
+        }
+        let tree = self.inbound_message_tree()?;
+        if !tree.contains_key(pending.id.as_bytes())? {
+            tree.insert(pending.id.as_bytes(), serde_json::to_vec(&pending)?)?;
+            tree.flush()?;
+        }
+        Ok(true)
+    }
+
+    /// The id is recorded as processed before the message is removed, so a crash in between
+    /// cannot replay it.
+    fn ack_inbound_message(&self, id: &str) -> anyhow::Result<()> {
+        let processed = self.processed_inbound_tree()?;
+        processed.insert(id.as_bytes(), &[])?;
+        processed.flush()?;
+        let tree = self.inbound_message_tree()?;
+        tree.remove(id.as_bytes())?;
+        tree.flush()?;
+        Ok(())
+    }
+
+    fn pending_inbound_messages(&self) -> anyhow::Result<Vec<PendingInbound>> {
+        let processed = self.processed_inbound_tree()?;
+        let mut pending = vec![];
+        for entry in self.inbound_message_tree()?.iter() {
+            let (id, value) = entry?;
+            if !processed.contains_key(&id)? {
+                pending.push(serde_json::from_slice::<PendingInbound>(&value)?);
+            }
+        }
+        pending.sort_by_key(|p| p.received_at);
+        Ok(pending)
+    }
+
+    fn record_inbound_failure(&self, id: &str) -> anyhow::Result<u32> {
+        let tree = self.inbound_message_tree()?;
+        let Some(value) = tree.get(id.as_bytes())? else {
+            return Ok(0);
+        };
+        let mut pending = serde_json::from_slice::<PendingInbound>(&value)?;
+        pending.attempts += 1;
+        tree.insert(id.as_bytes(), serde_json::to_vec(&pending)?)?;
+        tree.flush()?;
+        Ok(pending.attempts)
+    }
+
+    fn pending_inbound_count(&self) -> anyhow::Result<usize> {
+        Ok(self.inbound_message_tree()?.len())
+    }
+
+    fn append_audit_entry(&self, entry: AuditEntry) -> anyhow::Result<u64> {
+        let serialized = serde_json::to_vec(&entry)?;
+        let tree = self.audit_log_tree()?;
+        let sequence = tree
+            .transaction::<_, _, UnabortableTransactionError>(|audit| {
+                Ok(contract::append_audit_entry(audit, Some(&serialized))?)
+            })
+            .map_err(|e| anyhow::anyhow!("Could not append audit entry. {}", e))?
+            .unwrap_or_default();
+        tree.flush()?;
+        Ok(sequence)
+    }
+
+    /// The sequence number lives in the key. The stored entry is serialized before it is given
+    /// one.
+    fn audit_entries(&self, from: u64) -> anyhow::Result<Vec<AuditEntry>> {
+        let mut entries = vec![];
+        for record in self.audit_log_tree()?.range(from.max(1).to_be_bytes()..) {
+            let (key, value) = record?;
+            let mut entry = serde_json::from_slice::<AuditEntry>(&value)?;
+            entry.sequence = u64::from_be_bytes(key.as_ref().try_into()?);
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    fn get_contract_rates(&self, contract_id: &ContractId) -> anyhow::Result<Option<ContractRates>> {
+        match self.contract_rates_tree()?.get(contract_id)? {
+            Some(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn save_contract_rates(&self, contract_id: &ContractId, rates: ContractRates) -> anyhow::Result<()> {
+        self.contract_rates_tree()?
+            .insert(contract_id, serde_json::to_vec(&rates)?)?;
+        Ok(())
+    }
+
+    fn save_contract_settlement(&self, settlement: &ContractSettlement) -> anyhow::Result<()> {
+        let tree = self.contract_settlements_tree()?;
+        tree.insert(settlement.contract_id, serde_json::to_vec(settlement)?)?;
+        tree.flush()?;
+        Ok(())
+    }
+
+    fn list_contract_settlements(&self) -> anyhow::Result<Vec<ContractSettlement>> {
+        let mut settlements = vec![];
+        for entry in self.contract_settlements_tree()?.iter() {
+            let (_, value) = entry?;
+            settlements.push(serde_json::from_slice(&value)?);
+        }
+        Ok(settlements)
+    }
+
+    fn save_announcement(&self, announcement: &OracleAnnouncement) -> anyhow::Result<()> {
+        let key = oracle_event_key(
+            &announcement.oracle_public_key,
+            &announcement.oracle_event.event_id,
+        );
+        self.oracle_announcements_tree()?
+            .insert(key, serde_json::to_vec(announcement)?)?;
+        Ok(())
+    }
+
+    fn get_announcement(
+        &self,
+        oracle: &XOnlyPublicKey,
+        event_id: &str,
+    ) -> anyhow::Result<Option<OracleAnnouncement>> {
+        match self
+            .oracle_announcements_tree()?
+            .get(oracle_event_key(oracle, event_id))?
+        {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn evict_announcements(&self, matured_before: u64) -> anyhow::Result<usize> {
+        let tree = self.oracle_announcements_tree()?;
+        let mut evicted = 0;
+        for entry in tree.iter() {
+            let (key, value) = entry?;
+            let announcement: OracleAnnouncement = serde_json::from_slice(&value)?;
+            if (announcement.oracle_event.event_maturity_epoch as u64) < matured_before {
+                tree.remove(key)?;
+                evicted += 1;
+            }
+        }
+        Ok(evicted)
+    }
+
+    fn save_attestation(&self, event_id: &str, attestation: &OracleAttestation) -> anyhow::Result<()> {
+        let tree = self.oracle_attestations_tree()?;
+        tree.insert(
+            oracle_event_key(&attestation.oracle_public_key, event_id),
+            serde_json::to_vec(attestation)?,
+        )?;
+        tree.flush()?;
+        Ok(())
+    }
+
+    fn get_attestation(
+        &self,
+        oracle: &XOnlyPublicKey,
+        event_id: &str,
+    ) -> anyhow::Result<Option<OracleAttestation>> {
+        match self
+            .oracle_attestations_tree()?
+            .get(oracle_event_key(oracle, event_id))?
+        {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn save_contract_transactions(&self, records: &[ContractTransaction]) -> anyhow::Result<()> {
+        let tree = self.contract_transactions_tree()?;
+        for record in records {
+            tree.insert(record.txid.as_byte_array(), serde_json::to_vec(record)?)?;
+        }
+        Ok(())
+    }
+
+    fn list_contract_transactions(&self) -> anyhow::Result<Vec<ContractTransaction>> {
+        let mut records = vec![];
+        for entry in self.contract_transactions_tree()?.iter() {
+            let (_, value) = entry?;
+            records.push(serde_json::from_slice(&value)?);
+        }
+        Ok(records)
+    }
+
+    fn set_contract_metadata(&self, contract_id: &ContractId, metadata: ContractMetadata) -> anyhow::Result<()> {
+        let tree = self.contract_metadata_tree()?;
+        tree.insert(contract_id, serde_json::to_vec(&metadata)?)?;
+        tree.flush()?;
+        Ok(())
+    }
+
+    fn get_contract_metadata(&self, contract_id: &ContractId) -> anyhow::Result<Option<ContractMetadata>> {
+        match self.contract_metadata_tree()?.get(contract_id)? {
+            Some(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Uses the creation time and counterparty index, so only the returned page is deserialized.
+    fn get_contracts_paginated(
+        &self,
+        filter: ContractFilter,
+        offset: usize,
+        limit: usize,
+    ) -> anyhow::Result<Vec<Contract>> {
+        Ok(self.contracts_page(&filter, offset, limit)?)
+    }
+
+    fn archive_closed_contracts(&self, older_than: Duration) -> anyhow::Result<usize> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+        self.archive_closed_contracts_at(now, older_than)
+    }
+
+    fn get_archived_contracts(&self) -> anyhow::Result<Vec<Contract>> {
+        self.archived_contracts()
+    }
+
+    fn prune_archive(&self, older_than: Duration) -> anyhow::Result<usize> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+        self.prune_archive_at(now, older_than)
+    }
+
+    fn vacuum_signers(&self, options: &SignerVacuumOptions) -> anyhow::Result<SignerVacuumReport> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+        self.vacuum_signers_at(now, options)
+    }
+
+    fn maintenance(&self) -> anyhow::Result<bool> {
+        Ok(self
+            .settings_tree()?
+            .get(MAINTENANCE_KEY)?
+            .is_some_and(|value| value.as_ref() == [1]))
+    }
+
+    fn set_maintenance(&self, enabled: bool) -> anyhow::Result<()> {
+        let tree = self.settings_tree()?;
+        tree.insert(MAINTENANCE_KEY, &[enabled as u8])?;
+        tree.flush()?;
+        Ok(())
+    }
+
+    fn get_transport_cursor(&self, transport: &str) -> anyhow::Result<Option<u64>> {
+        match self.settings_tree()?.get(transport_cursor_key(transport))? {
+            Some(value) => Ok(Some(bincode::deserialize(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn save_transport_cursor(&self, transport: &str, cursor: u64) -> anyhow::Result<()> {
+        self.settings_tree()?
+            .insert(transport_cursor_key(transport), bincode::serialize(&cursor)?)?;
+        Ok(())
+    }
+
+    fn list_reserved_utxos(&self) -> anyhow::Result<Vec<bitcoin::OutPoint>> {
+        match self.settings_tree()?.get(RESERVED_UTXOS_KEY)? {
+            Some(value) => Ok(bincode::deserialize(&value)?),
+            None => Ok(vec![]),
+        }
+    }
+
+    fn save_reserved_utxos(&self, outpoints: &[bitcoin::OutPoint]) -> anyhow::Result<()> {
+        let tree = self.settings_tree()?;
+        tree.insert(RESERVED_UTXOS_KEY, bincode::serialize(outpoints)?)?;
+        tree.flush()?;
+        Ok(())
+    }
 
-        if known_peers.contains(&peer) {
-            return Ok(());
+    fn list_frozen_utxos(&self) -> anyhow::Result<Vec<bitcoin::OutPoint>> {
+        match self.settings_tree()?.get(FROZEN_UTXOS_KEY)? {
+            Some(value) => Ok(bincode::deserialize(&value)?),
+            None => Ok(vec![]),
         }
+    }
 
-        known_peers.push(peer);
-        let peer_vec = serde_json::to_vec(&known_peers)?;
+    fn save_frozen_utxos(&self, outpoints: &[bitcoin::OutPoint]) -> anyhow::Result<()> {
+        let tree = self.settings_tree()?;
+        tree.insert(FROZEN_UTXOS_KEY, bincode::serialize(outpoints)?)?;
+        tree.flush()?;
+        Ok(())
+    }
 
-        self.db.insert("peers", peer_vec)?;
+    fn list_peer_bans(&self) -> anyhow::Result<Vec<PeerBan>> {
+        match self.settings_tree()?.get(PEER_BANS_KEY)? {
+            Some(value) => Ok(bincode::deserialize(&value)?),
+            None => Ok(vec![]),
+        }
+    }
 
+    fn save_peer_bans(&self, bans: &[PeerBan]) -> anyhow::Result<()> {
+        let tree = self.settings_tree()?;
+        tree.insert(PEER_BANS_KEY, bincode::serialize(bans)?)?;
+        tree.flush()?;
         Ok(())
     }
+
+    fn list_pending_broadcasts(&self) -> anyhow::Result<Vec<PendingBroadcast>> {
+        match self.settings_tree()?.get(PENDING_BROADCASTS_KEY)? {
+            Some(value) => Ok(bincode::deserialize(&value)?),
+            None => Ok(vec![]),
+        }
+    }
+
+    fn save_pending_broadcasts(&self, pending: &[PendingBroadcast]) -> anyhow::Result<()> {
+        let tree = self.settings_tree()?;
+        tree.insert(PENDING_BROADCASTS_KEY, bincode::serialize(pending)?)?;
+        tree.flush()?;
+        Ok(())
+    }
+
+    /// Fee rates are keyed by [crate::wallet::fees::target_key]. Each value is the refresh
+    /// time as a big endian u64 followed by the rate as a big endian u32.
+    fn get_fee_estimates(&self) -> anyhow::Result<Option<StoredFees>> {
+        let mut stored: Option<StoredFees> = None;
+        for entry in self.fees_tree()?.iter() {
+            let (key, value) = entry?;
+            if value.len() != 12 {
+                return Err(anyhow::anyhow!(
+                    "Stored fee rate of {} is {} bytes.",
+                    String::from_utf8_lossy(&key),
+                    value.len()
+                ));
+            }
+            let fetched_at = u64::from_be_bytes(value[..8].try_into()?);
+            let fee = u32::from_be_bytes(value[8..].try_into()?);
+            let stored = stored.get_or_insert_with(|| StoredFees {
+                fetched_at,
+                fees: Default::default(),
+            });
+            stored.fetched_at = stored.fetched_at.min(fetched_at);
+            stored
+                .fees
+                .insert(String::from_utf8_lossy(&key).to_string(), fee);
+        }
+        Ok(stored)
+    }
+
+    fn save_fee_estimates(&self, fees: &StoredFees) -> anyhow::Result<()> {
+        let tree = self.fees_tree()?;
+        let mut batch = sled::Batch::default();
+        for key in tree.iter().keys() {
+            batch.remove(key?);
+        }
+        for (target, fee) in &fees.fees {
+            let mut value = fees.fetched_at.to_be_bytes().to_vec();
+            value.extend_from_slice(&fee.to_be_bytes());
+            batch.insert(target.as_bytes(), value);
+        }
+        tree.apply_batch(batch)?;
+        tree.flush()?;
+        Ok(())
+    }
+
+    /// Reads every record on its own, so each one that fails is reported with its key.
+    fn verify_integrity(&self) -> anyhow::Result<IntegrityReport> {
+        self.integrity_report()
+    }
+
+    fn storage_stats(&self) -> anyhow::Result<StorageStats> {
+        let mut tree_sizes = std::collections::BTreeMap::new();
+        for name in self.db.tree_names() {
+            let tree = self.db.open_tree(&name)?;
+            tree_sizes.insert(Self::tree_name(&name), tree.len());
+        }
+
+        Ok(StorageStats {
+            size_on_disk: Some(self.db.size_on_disk()?),
+            tree_sizes,
+            wallet_changesets: self.wallet_tree()?.len(),
+            contracts_by_state: self.contract_state_counts()?,
+            wallet: Default::default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maintenance_persists_across_restart() {
+        let path = "tests/data/dlc_storage/sleddb/maintenance";
+        {
+            let storage = SledStorageProvider::new(path).unwrap();
+            assert!(!storage.maintenance().unwrap());
+            storage.set_maintenance(true).unwrap();
+            assert!(storage.maintenance().unwrap());
+        }
+        {
+            let storage = SledStorageProvider::new(path).unwrap();
+            assert!(storage.maintenance().unwrap());
+            storage.set_maintenance(false).unwrap();
+            assert!(!storage.maintenance().unwrap());
+        }
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn transport_cursor_persists_across_restart() {
+        let path = "tests/data/dlc_storage/sleddb/transport_cursor";
+        {
+            let storage = SledStorageProvider::new(path).unwrap();
+            assert_eq!(storage.get_transport_cursor("nostr").unwrap(), None);
+            storage.save_transport_cursor("nostr", 1_700_000_000).unwrap();
+        }
+        {
+            let storage = SledStorageProvider::new(path).unwrap();
+            assert_eq!(storage.get_transport_cursor("nostr").unwrap(), Some(1_700_000_000));
+            assert_eq!(storage.get_transport_cursor("tcp").unwrap(), None);
+        }
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn fee_estimates_persist_across_restart() {
+        let path = "tests/data/dlc_storage/sleddb/fee_estimates";
+        let fees = crate::wallet::fees::default_fees(bitcoin::Network::Bitcoin);
+        let stored = StoredFees::new(&fees, 1_700_000_000);
+        {
+            let storage = SledStorageProvider::new(path).unwrap();
+            assert_eq!(storage.get_fee_estimates().unwrap(), None);
+            storage.save_fee_estimates(&stored).unwrap();
+        }
+        {
+            let storage = SledStorageProvider::new(path).unwrap();
+            assert_eq!(storage.get_fee_estimates().unwrap(), Some(stored.clone()));
+            let newer = StoredFees {
+                fetched_at: 1_700_000_600,
+                fees: [("urgent_on_chain_sweep".to_string(), 8_000)].into(),
+            };
+            storage.save_fee_estimates(&newer).unwrap();
+            assert_eq!(storage.get_fee_estimates().unwrap(), Some(newer));
+        }
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn acknowledged_messages_stay_processed_across_restart() {
+        let path = "tests/data/dlc_storage/sleddb/inbound_messages";
+        let counterparty = PublicKey::from_secret_key(
+            &bitcoin::secp256k1::Secp256k1::new(),
+            &bitcoin::secp256k1::SecretKey::from_slice(&[1u8; 32]).unwrap(),
+        );
+        let offer: dlc_messages::OfferDlc =
+            serde_json::from_str(include_str!("../../../tests/data/dlc/offer.json")).unwrap();
+        let message = Message::Offer(offer);
+        {
+            let storage = SledStorageProvider::new(path).unwrap();
+            assert!(storage.queue_inbound_message(counterparty, &message).unwrap());
+        }
+        {
+            let storage = SledStorageProvider::new(path).unwrap();
+            let pending = storage.pending_inbound_messages().unwrap();
+            assert_eq!(pending.len(), 1);
+            assert_eq!(pending[0].counterparty, counterparty);
+            storage.ack_inbound_message(&pending[0].id).unwrap();
+        }
+        {
+            let storage = SledStorageProvider::new(path).unwrap();
+            assert!(storage.pending_inbound_messages().unwrap().is_empty());
+            assert!(!storage.queue_inbound_message(counterparty, &message).unwrap());
+        }
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn state_changes_are_audited_with_the_contract() {
+        use crate::audit::AuditEventType;
+        use dlc_manager::contract::offered_contract::OfferedContract;
+        use dlc_manager::contract::signed_contract::SignedContract;
+        use dlc_manager::Storage;
+
+        let path = "tests/data/dlc_storage/sleddb/audit_log";
+        let offered = OfferedContract::deserialize(&mut Cursor::new(include_bytes!(
+            "../../../tests/data/dlc_storage/sled/Offered"
+        )))
+        .unwrap();
+        let signed = SignedContract::deserialize(&mut Cursor::new(include_bytes!(
+            "../../../tests/data/dlc_storage/sled/Signed"
+        )))
+        .unwrap();
+        {
+            let storage = SledStorageProvider::new(path).unwrap();
+            storage.create_contract(&offered).unwrap();
+            storage.update_contract(&Contract::Offered(offered.clone())).unwrap();
+            let txid = signed.accepted_contract.dlc_transactions.fund.compute_txid();
+            let broadcast = AuditEntry::broadcast(&[1u8; 32], txid, "funding");
+            assert_eq!(storage.append_audit_entry(broadcast).unwrap(), 2);
+        }
+        {
+            let storage = SledStorageProvider::new(path).unwrap();
+            storage.update_contract(&Contract::Signed(signed)).unwrap();
+            let entries = storage.audit_entries(0).unwrap();
+            assert_eq!(
+                entries.iter().map(|e| e.sequence).collect::<Vec<_>>(),
+                vec![1, 2, 3]
+            );
+            assert_eq!(entries[0].event_type, AuditEventType::StateChanged);
+            assert_eq!(entries[0].details["to"], "Offered");
+            assert_eq!(entries[1].event_type, AuditEventType::TransactionBroadcast);
+            assert_eq!(entries[2].details["to"], "Signed");
+            assert_eq!(storage.audit_entries(3).unwrap().len(), 1);
+        }
+        std::fs::remove_dir_all(path).unwrap();
+    }
 }
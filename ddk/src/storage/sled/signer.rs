@@ -0,0 +1,213 @@
+use super::contract::deserialize_channel;
+use super::SledStorageProvider;
+use crate::signer::{KeyUsage, SignerInformation};
+use crate::storage::{
+    is_vacuumable, live_negotiation_ids, SignerArchive, SignerVacuumOptions, SignerVacuumReport,
+};
+use bitcoin::key::rand::{thread_rng, Rng};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use dlc_manager::Storage;
+use sled::transaction::ConflictableTransactionError;
+use sled::Transactional;
+use std::fs::OpenOptions;
+use std::io::{Read, Write};
+use std::path::Path;
+
+impl SledStorageProvider {
+    /// Remove signers whose contract is closed or gone and that were derived more than the
+    /// retention period before `now`. Signers of open contracts and channels are always kept.
+    pub(crate) fn vacuum_signers_at(
+        &self,
+        now: u64,
+        options: &SignerVacuumOptions,
+    ) -> anyhow::Result<SignerVacuumReport> {
+        let mut channels = vec![];
+        for value in self.channel_tree()?.iter().values() {
+            channels.push(deserialize_channel(&self.open(value?)?)?);
+        }
+        let live = live_negotiation_ids(&self.get_contracts()?, &channels);
+
+        let signer_tree = self.signer_tree()?;
+        let usage_tree = self.key_usage_tree()?;
+        let mut report = SignerVacuumReport::default();
+        let mut removed = vec![];
+        for entry in signer_tree.iter() {
+            let (key_id, signer) = entry?;
+            let Some(usage) = usage_tree.get(&key_id)? else {
+                report.unattributed += 1;
+                continue;
+            };
+            let usage: KeyUsage = bincode::deserialize(&usage)?;
+            if !is_vacuumable(&usage, &live, now, options) {
+                report.kept += 1;
+                continue;
+            }
+            removed.push((String::from_utf8(key_id.to_vec())?, self.open(signer)?.to_vec()));
+        }
+
+        if removed.is_empty() {
+            return Ok(report);
+        }
+
+        if let Some(archive) = &options.export_before_delete {
+            append_to_archive(archive, &removed)?;
+        }
+
+        (&signer_tree, &usage_tree)
+            .transaction(|(signers, usages)| {
+                for (key_id, _) in &removed {
+                    signers.remove(key_id.as_bytes())?;
+                    usages.remove(key_id.as_bytes())?;
+                }
+                Ok::<(), ConflictableTransactionError<()>>(())
+            })
+            .map_err(|e| anyhow::anyhow!("Could not remove signers: {:?}", e))?;
+
+        for (key_id, _) in removed {
+            tracing::info!(key_id, "Removed unused signer.");
+            report.removed.push(key_id);
+        }
+        Ok(report)
+    }
+}
+
+/// Each record is a big endian u32 length followed by a nonce and the encrypted signers.
+pub(crate) fn append_to_archive(archive: &SignerArchive, signers: &[(String, Vec<u8>)]) -> anyhow::Result<()> {
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(&archive.key));
+    let nonce: [u8; 12] = thread_rng().gen();
+    let plaintext = bincode::serialize(signers)?;
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), plaintext.as_ref())
+        .map_err(|_| anyhow::anyhow!("Could not encrypt signer archive."))?;
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&archive.path)?;
+    file.write_all(&((nonce.len() + ciphertext.len()) as u32).to_be_bytes())?;
+    file.write_all(&nonce)?;
+    file.write_all(&ciphertext)?;
+    file.sync_all()?;
+    Ok(())
+}
+
+/// Decrypt every signer appended to a signer archive.
+pub fn read_signer_archive<P: AsRef<Path>>(
+    path: P,
+    key: &[u8; 32],
+) -> anyhow::Result<Vec<(String, SignerInformation)>> {
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key));
+    let mut bytes = vec![];
+    std::fs::File::open(path)?.read_to_end(&mut bytes)?;
+
+    let mut signers = vec![];
+    let mut rest = bytes.as_slice();
+    while !rest.is_empty() {
+        if rest.len() < 4 {
+            return Err(anyhow::anyhow!("Truncated signer archive."));
+        }
+        let (len, tail) = rest.split_at(4);
+        let len = u32::from_be_bytes(len.try_into()?) as usize;
+        if tail.len() < len || len < 12 {
+            return Err(anyhow::anyhow!("Truncated signer archive."));
+        }
+        let (record, tail) = tail.split_at(len);
+        let (nonce, ciphertext) = record.split_at(12);
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow::anyhow!("Could not decrypt signer archive."))?;
+        let records: Vec<(String, Vec<u8>)> = bincode::deserialize(&plaintext)?;
+        for (key_id, signer) in records {
+            signers.push((key_id, bincode::deserialize(&signer)?));
+        }
+        rest = tail;
+    }
+    Ok(signers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signer::DeriveSigner;
+    use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+    use dlc_manager::contract::ser::Serializable;
+    use dlc_manager::contract::Contract;
+    use std::time::Duration;
+
+    const DAY: u64 = 60 * 60 * 24;
+    const NOW: u64 = 100 * DAY;
+
+    fn deserialize_object<T: Serializable>(serialized: &[u8]) -> T {
+        let mut cursor = ::lightning::io::Cursor::new(&serialized);
+        T::deserialize(&mut cursor).unwrap()
+    }
+
+    fn store_signer(storage: &SledStorageProvider, key_id: u8, temporary_id: [u8; 32], created_at: u64) {
+        let secp = Secp256k1::new();
+        let secret_key = SecretKey::from_slice(&[key_id; 32]).unwrap();
+        storage
+            .store_derived_key_id(
+                [key_id; 32],
+                SignerInformation {
+                    index: key_id as u32,
+                    secret_key,
+                    public_key: PublicKey::from_secret_key(&secp, &secret_key),
+                },
+            )
+            .unwrap();
+        storage
+            .record_key_usage(
+                [key_id; 32],
+                KeyUsage {
+                    temporary_id,
+                    created_at,
+                },
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn removes_only_old_signers_of_closed_contracts() {
+        let path = "tests/data/dlc_storage/sleddb/vacuum_signers";
+        let archive_path = "tests/data/dlc_storage/vacuum_signers.archive";
+        {
+            let storage = SledStorageProvider::new(path).unwrap();
+            let mut offered: dlc_manager::contract::offered_contract::OfferedContract =
+                deserialize_object(include_bytes!("../../../tests/data/dlc_storage/sled/Offered"));
+            offered.id = [7u8; 32];
+            storage.create_contract(&offered).unwrap();
+            let mut closed: dlc_manager::contract::ClosedContract =
+                deserialize_object(include_bytes!("../../../tests/data/dlc_storage/sled/Closed"));
+            closed.temporary_contract_id = [8u8; 32];
+            storage.update_contract(&Contract::Closed(closed)).unwrap();
+
+            // Live contract, closed and recent, closed and old.
+            store_signer(&storage, 1, [7u8; 32], 0);
+            store_signer(&storage, 2, [8u8; 32], NOW - DAY);
+            store_signer(&storage, 3, [8u8; 32], 0);
+
+            let archive = SignerArchive {
+                path: archive_path.into(),
+                key: [9u8; 32],
+            };
+            let options = SignerVacuumOptions {
+                retention: Duration::from_secs(30 * DAY),
+                export_before_delete: Some(archive.clone()),
+            };
+            let report = storage.vacuum_signers_at(NOW, &options).unwrap();
+            assert_eq!(report.removed, vec![hex::encode([3u8; 32])]);
+            assert_eq!(report.kept, 2);
+            assert!(storage.get_key_information([1u8; 32]).is_ok());
+            assert!(storage.get_key_information([2u8; 32]).is_ok());
+            assert!(storage.signer_tree().unwrap().get(hex::encode([3u8; 32])).unwrap().is_none());
+
+            let archived = read_signer_archive(archive_path, &archive.key).unwrap();
+            assert_eq!(archived.len(), 1);
+            assert_eq!(archived[0].1.index, 3);
+            assert!(read_signer_archive(archive_path, &[0u8; 32]).is_err());
+        }
+        std::fs::remove_dir_all(path).unwrap();
+        std::fs::remove_file(archive_path).unwrap();
+    }
+}
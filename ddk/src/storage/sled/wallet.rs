@@ -1,31 +1,133 @@
-use super::SledStorageProvider;
+use super::{SledStorageProvider, SIGNER_INDEX_KEY};
 use crate::error::WalletError;
-use crate::signer::{DeriveSigner, SignerInformation};
+use crate::signer::{DeriveSigner, KeyUsage, SignerInformation};
+use crate::storage::decode_key_id;
+use bdk_chain::Merge;
+use bdk_file_store::Store;
 use bdk_wallet::ChangeSet;
 use bdk_wallet::WalletPersister;
-use bitcoin::{
-    key::rand::{thread_rng, Rng},
-    secp256k1::{PublicKey, SecretKey},
-};
+use bitcoin::secp256k1::{PublicKey, SecretKey};
+use sled::Tree;
+use std::path::Path;
 
+/// Wallet changesets kept before they are merged into a single record.
+pub const DEFAULT_WALLET_COMPACTION_THRESHOLD: usize = 100;
+
+/// Changesets are appended under big endian sequence numbers so the tree iterates them in the
+/// order they were written.
 impl WalletPersister for SledStorageProvider {
     type Error = WalletError;
 
     fn persist(persister: &mut Self, changeset: &ChangeSet) -> Result<(), Self::Error> {
+        if changeset.is_empty() {
+            return Ok(());
+        }
         let wallet_tree = persister.wallet_tree()?;
-        let rand_key: [u8; 32] = thread_rng().gen();
-        let new_changeset = bincode::serialize(changeset).map_err(|_| {
-            WalletError::StorageError(sled::Error::Io(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "Serialization error",
-            )))
-        })?;
-        wallet_tree.insert(rand_key, new_changeset)?;
+        let sequence = next_sequence(&wallet_tree)?;
+        wallet_tree.insert(
+            sequence.to_be_bytes(),
+            persister.seal(bincode::serialize(changeset)?)?,
+        )?;
+        wallet_tree.flush()?;
+
+        if persister
+            .wallet_compaction_threshold
+            .is_some_and(|threshold| wallet_tree.len() > threshold)
+        {
+            persister.compact_wallet_changesets()?;
+        }
         Ok(())
     }
 
-    fn initialize(_persister: &mut Self) -> Result<ChangeSet, Self::Error> {
-        Ok(ChangeSet::default())
+    fn initialize(persister: &mut Self) -> Result<ChangeSet, Self::Error> {
+        persister.stored_changeset()
+    }
+}
+
+fn next_sequence(tree: &Tree) -> Result<u64, WalletError> {
+    match tree.last()? {
+        Some((key, _)) => Ok(changeset_sequence(&key)? + 1),
+        None => Ok(0),
+    }
+}
+
+fn changeset_sequence(key: &[u8]) -> Result<u64, WalletError> {
+    let bytes: [u8; 8] = key.try_into().map_err(|_| {
+        WalletError::StorageError(sled::Error::Unsupported(
+            "Wallet changeset key is not a sequence number.".into(),
+        ))
+    })?;
+    Ok(u64::from_be_bytes(bytes))
+}
+
+/// Magic bytes of the legacy [bdk_file_store::Store] wallet.
+pub const LEGACY_WALLET_MAGIC: &[u8] = b"ddk-wallet";
+/// File name of the legacy [bdk_file_store::Store] wallet in the data directory.
+pub const LEGACY_WALLET_FILE: &str = "wallet_db";
+
+impl SledStorageProvider {
+    /// Merge every persisted wallet changeset, in the order they were written.
+    pub fn stored_changeset(&self) -> Result<ChangeSet, WalletError> {
+        let mut aggregate = ChangeSet::default();
+        for entry in self.wallet_tree()?.iter() {
+            let (_, value) = entry?;
+            aggregate.merge(bincode::deserialize::<ChangeSet>(&self.open(value)?)?);
+        }
+        Ok(aggregate)
+    }
+
+    /// Merge the persisted wallet changesets into a single record. Returns the number of
+    /// records removed.
+    ///
+    /// The aggregate replaces the newest record before older ones are removed. Merging is
+    /// idempotent, so a crash in between loads the same wallet.
+    pub fn compact_wallet_changesets(&self) -> Result<usize, WalletError> {
+        let wallet_tree = self.wallet_tree()?;
+        let Some((last, _)) = wallet_tree.last()? else {
+            return Ok(0);
+        };
+        let aggregate = self.stored_changeset()?;
+        wallet_tree.insert(&last, self.seal(bincode::serialize(&aggregate)?)?)?;
+        wallet_tree.flush()?;
+
+        let mut removed = 0;
+        for key in wallet_tree.range(..last).keys() {
+            wallet_tree.remove(key?)?;
+            removed += 1;
+        }
+        wallet_tree.flush()?;
+        tracing::debug!(removed, "Compacted wallet changesets.");
+        Ok(removed)
+    }
+
+    /// Move the wallet history of a legacy `wallet_db` file store in `data_dir` into sled.
+    ///
+    /// Only runs when the sled wallet tree is empty. The legacy changesets are aggregated and
+    /// written as a single record, and the old file is renamed to `wallet_db.migrated`. A corrupt
+    /// legacy store leaves sled untouched. Returns whether a migration happened.
+    pub fn migrate_legacy_wallet<P: AsRef<Path>>(&self, data_dir: P) -> Result<bool, WalletError> {
+        let legacy_path = data_dir.as_ref().join(LEGACY_WALLET_FILE);
+        if !legacy_path.exists() || !self.wallet_tree()?.is_empty() {
+            return Ok(false);
+        }
+
+        let mut store = Store::<ChangeSet>::open(LEGACY_WALLET_MAGIC, &legacy_path)
+            .map_err(|e| WalletError::Migration(e.to_string()))?;
+        let changeset = store
+            .aggregate_changesets()
+            .map_err(|e| WalletError::Migration(e.to_string()))?;
+        drop(store);
+
+        if let Some(changeset) = changeset {
+            let mut persister = self.clone();
+            Self::persist(&mut persister, &changeset)?;
+            self.db.flush()?;
+        }
+
+        std::fs::rename(&legacy_path, legacy_path.with_extension("migrated"))
+            .map_err(|e| WalletError::Migration(e.to_string()))?;
+        tracing::info!(path = ?legacy_path, "Migrated legacy wallet store into sled.");
+        Ok(true)
     }
 }
 
@@ -34,8 +136,11 @@ impl DeriveSigner for SledStorageProvider {
 
     fn get_key_information(&self, key_id: [u8; 32]) -> Result<SignerInformation, Self::Error> {
         let key = hex::encode(key_id);
-        let info = self.signer_tree()?.get(key)?.unwrap();
-        Ok(bincode::deserialize::<SignerInformation>(&info)?)
+        let info = self
+            .signer_tree()?
+            .get(key)?
+            .ok_or_else(|| WalletError::SignerError("Could not find key id.".into()))?;
+        Ok(bincode::deserialize::<SignerInformation>(&self.open(info)?)?)
     }
 
     /// Store the secret and public with the givem key id
@@ -54,7 +159,8 @@ impl DeriveSigner for SledStorageProvider {
         // Store the key id string instead of bytes.
         let key_id = hex::encode(key_id);
 
-        self.signer_tree()?.insert(key_id, serialized_signer_info)?;
+        self.signer_tree()?
+            .insert(key_id, self.seal(serialized_signer_info)?)?;
         Ok(())
     }
 
@@ -62,8 +168,8 @@ impl DeriveSigner for SledStorageProvider {
     fn get_secret_key(&self, public_key: &PublicKey) -> Result<SecretKey, WalletError> {
         let tree = self.signer_tree()?;
         for result in tree.iter() {
-            if let Ok(value) = result {
-                let info: SignerInformation = bincode::deserialize(&value.1).map_err(|_| {
+            if let Ok((_, value)) = result {
+                let info: SignerInformation = bincode::deserialize(&self.open(value)?).map_err(|_| {
                     WalletError::StorageError(sled::Error::Io(std::io::Error::new(
                         std::io::ErrorKind::Other,
                         "Deserialization error aggregating changset.",
@@ -83,4 +189,183 @@ impl DeriveSigner for SledStorageProvider {
     fn import_address_to_storage(&self, _address: &bitcoin::Address) -> Result<(), WalletError> {
         Ok(())
     }
+
+    fn record_key_usage(&self, key_id: [u8; 32], usage: KeyUsage) -> Result<(), WalletError> {
+        self.key_usage_tree()?
+            .insert(hex::encode(key_id), bincode::serialize(&usage)?)?;
+        Ok(())
+    }
+
+    fn list_signers(&self) -> Result<Vec<([u8; 32], SignerInformation)>, WalletError> {
+        let mut signers = vec![];
+        for entry in self.signer_tree()?.iter() {
+            let (key, value) = entry?;
+            if let Some(key_id) = decode_key_id(&key) {
+                signers.push((key_id, bincode::deserialize(&self.open(value)?)?));
+            }
+        }
+        Ok(signers)
+    }
+
+    fn list_key_usage(&self) -> Result<Vec<([u8; 32], KeyUsage)>, WalletError> {
+        let mut usages = vec![];
+        for entry in self.key_usage_tree()?.iter() {
+            let (key, value) = entry?;
+            if let Some(key_id) = decode_key_id(&key) {
+                usages.push((key_id, bincode::deserialize(&value)?));
+            }
+        }
+        Ok(usages)
+    }
+
+    fn signer_index(&self) -> Result<Option<u32>, WalletError> {
+        match self.settings_tree()?.get(SIGNER_INDEX_KEY)? {
+            Some(value) => Ok(Some(bincode::deserialize(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn save_signer_index(&self, index: u32) -> Result<(), WalletError> {
+        let tree = self.settings_tree()?;
+        tree.insert(SIGNER_INDEX_KEY, bincode::serialize(&index)?)?;
+        tree.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bdk_wallet::template::Bip84;
+    use bdk_wallet::{KeychainKind, Wallet};
+    use bitcoin::bip32::Xpriv;
+    use bitcoin::Network;
+
+    fn legacy_wallet(dir: &Path) -> u32 {
+        let xprv = Xpriv::new_master(Network::Regtest, &[7u8; 32]).unwrap();
+        let mut store =
+            Store::<ChangeSet>::open_or_create_new(LEGACY_WALLET_MAGIC, dir.join(LEGACY_WALLET_FILE))
+                .unwrap();
+        let mut wallet = Wallet::create(
+            Bip84(xprv, KeychainKind::External),
+            Bip84(xprv, KeychainKind::Internal),
+        )
+        .network(Network::Regtest)
+        .create_wallet(&mut store)
+        .unwrap();
+        let revealed = wallet.reveal_addresses_to(KeychainKind::External, 5).count();
+        wallet.persist(&mut store).unwrap();
+        revealed as u32
+    }
+
+    fn new_wallet(
+        storage: &mut SledStorageProvider,
+    ) -> bdk_wallet::PersistedWallet<SledStorageProvider> {
+        let xprv = Xpriv::new_master(Network::Regtest, &[9u8; 32]).unwrap();
+        Wallet::create(
+            Bip84(xprv, KeychainKind::External),
+            Bip84(xprv, KeychainKind::Internal),
+        )
+        .network(Network::Regtest)
+        .create_wallet(storage)
+        .unwrap()
+    }
+
+    #[test]
+    fn changesets_survive_reopen() {
+        let path = "tests/data/dlc_storage/sleddb/wallet_round_trip";
+        {
+            let mut storage = SledStorageProvider::new(path).unwrap();
+            let mut wallet = new_wallet(&mut storage);
+            let _ = wallet.reveal_addresses_to(KeychainKind::External, 3);
+            wallet.persist(&mut storage).unwrap();
+            let _ = wallet.reveal_addresses_to(KeychainKind::Internal, 2);
+            wallet.persist(&mut storage).unwrap();
+            assert_eq!(storage.wallet_tree().unwrap().len(), 3);
+        }
+        {
+            let mut storage = SledStorageProvider::new(path).unwrap();
+            let wallet = Wallet::load()
+                .check_network(Network::Regtest)
+                .load_wallet(&mut storage)
+                .unwrap()
+                .expect("wallet was persisted");
+            assert_eq!(wallet.derivation_index(KeychainKind::External), Some(3));
+            assert_eq!(wallet.derivation_index(KeychainKind::Internal), Some(2));
+        }
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn changesets_are_compacted() {
+        let path = "tests/data/dlc_storage/sleddb/wallet_compaction";
+        {
+            let mut storage = SledStorageProvider::new(path)
+                .unwrap()
+                .with_wallet_compaction(Some(2));
+            let mut wallet = new_wallet(&mut storage);
+            for index in 1..=4 {
+                let _ = wallet.reveal_addresses_to(KeychainKind::External, index);
+                wallet.persist(&mut storage).unwrap();
+            }
+            assert!(storage.wallet_tree().unwrap().len() <= 2);
+            // Appends continue after the compacted record.
+            let _ = wallet.reveal_addresses_to(KeychainKind::Internal, 1);
+            wallet.persist(&mut storage).unwrap();
+        }
+        {
+            let mut storage = SledStorageProvider::new(path).unwrap();
+            let wallet = Wallet::load()
+                .load_wallet(&mut storage)
+                .unwrap()
+                .expect("wallet was persisted");
+            assert_eq!(wallet.derivation_index(KeychainKind::External), Some(4));
+            assert_eq!(wallet.derivation_index(KeychainKind::Internal), Some(1));
+        }
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn migrates_legacy_store_once() {
+        let dir = Path::new("tests/data/legacy_wallet_migration");
+        std::fs::create_dir_all(dir).unwrap();
+        let revealed = legacy_wallet(dir);
+
+        let storage = SledStorageProvider::new(dir.join("wallet-db").to_str().unwrap()).unwrap();
+        assert!(storage.migrate_legacy_wallet(dir).unwrap());
+        assert!(!dir.join(LEGACY_WALLET_FILE).exists());
+        assert!(dir.join("wallet_db.migrated").exists());
+
+        let changeset = storage.stored_changeset().unwrap();
+        let last_revealed = changeset
+            .indexer
+            .last_revealed
+            .values()
+            .max()
+            .copied()
+            .unwrap();
+        assert_eq!(last_revealed + 1, revealed);
+
+        // Idempotent.
+        assert!(!storage.migrate_legacy_wallet(dir).unwrap());
+        assert_eq!(storage.wallet_tree().unwrap().len(), 1);
+
+        drop(storage);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn corrupt_legacy_store_does_not_write() {
+        let dir = Path::new("tests/data/corrupt_wallet_migration");
+        std::fs::create_dir_all(dir).unwrap();
+        std::fs::write(dir.join(LEGACY_WALLET_FILE), b"not a wallet").unwrap();
+
+        let storage = SledStorageProvider::new(dir.join("wallet-db").to_str().unwrap()).unwrap();
+        assert!(storage.migrate_legacy_wallet(dir).is_err());
+        assert!(storage.wallet_tree().unwrap().is_empty());
+        assert!(dir.join(LEGACY_WALLET_FILE).exists());
+
+        drop(storage);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 }
@@ -0,0 +1,1542 @@
+//! Storage provider for dlc-manager using SQLite.
+//!
+//! Contracts and channels are stored in the rust-dlc [Serializable] format, the same bytes
+//! the sled provider writes. Their state is kept in a separate indexed column so state
+//! filtered queries do not deserialize every row.
+use crate::accounting::ContractSettlement;
+use crate::audit::AuditEntry;
+use crate::chain::rebroadcast::PendingBroadcast;
+use crate::contract::metadata::ContractMetadata;
+use crate::contract::{validate_transition, ContractState};
+use crate::metrics;
+use crate::error::WalletError;
+use crate::rates::ContractRates;
+use crate::signer::{DeriveSigner, KeyUsage, SignerInformation};
+use crate::storage::sled::{
+    append_to_archive, deserialize_channel, serialize_channel, ChannelPrefix, SignedChannelPrefix,
+};
+use crate::storage::{
+    decode_key_id, is_archivable, is_vacuumable, live_negotiation_ids, SignerVacuumOptions,
+    SignerVacuumReport, StorageStats,
+};
+use crate::transport::rate_limit::PeerBan;
+use crate::transport::{PeerInformation, PendingInbound, PendingOutbound};
+use crate::util::{deserialize_contract_bytes, serialize_contract};
+use crate::wallet::fees::StoredFees;
+use crate::wallet::ContractTransaction;
+use crate::DdkStorage;
+use bdk_chain::Merge;
+use bdk_wallet::{ChangeSet, WalletPersister};
+use bitcoin::secp256k1::{PublicKey, SecretKey};
+use bitcoin::hashes::Hash;
+use bitcoin::key::XOnlyPublicKey;
+use bitcoin::OutPoint;
+use dlc_manager::chain_monitor::ChainMonitor;
+use dlc_manager::channel::offered_channel::OfferedChannel;
+use dlc_manager::channel::signed_channel::{SignedChannel, SignedChannelStateType};
+use dlc_manager::channel::Channel;
+use dlc_manager::contract::offered_contract::OfferedContract;
+use dlc_manager::contract::ser::Serializable;
+use dlc_manager::contract::signed_contract::SignedContract;
+use dlc_manager::contract::{Contract, PreClosedContract};
+use dlc_manager::{error::Error, ChannelId, ContractId, Storage};
+use dlc_messages::oracle_msgs::{OracleAnnouncement, OracleAttestation};
+use dlc_messages::Message;
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS contracts (
+    id BLOB PRIMARY KEY,
+    state TEXT NOT NULL,
+    data BLOB NOT NULL
+);
+CREATE INDEX IF NOT EXISTS contracts_state ON contracts (state);
+CREATE TABLE IF NOT EXISTS channels (
+    id BLOB PRIMARY KEY,
+    kind INTEGER NOT NULL,
+    signed_state INTEGER,
+    data BLOB NOT NULL
+);
+CREATE INDEX IF NOT EXISTS channels_kind ON channels (kind, signed_state);
+CREATE TABLE IF NOT EXISTS chain_monitor (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    data BLOB NOT NULL
+);
+CREATE TABLE IF NOT EXISTS peers (
+    pubkey TEXT NOT NULL,
+    host TEXT NOT NULL,
+    PRIMARY KEY (pubkey, host)
+);
+CREATE TABLE IF NOT EXISTS signers (
+    key_id TEXT PRIMARY KEY,
+    public_key TEXT NOT NULL,
+    data BLOB NOT NULL
+);
+CREATE INDEX IF NOT EXISTS signers_public_key ON signers (public_key);
+CREATE TABLE IF NOT EXISTS key_usage (
+    key_id TEXT PRIMARY KEY,
+    data BLOB NOT NULL
+);
+CREATE TABLE IF NOT EXISTS wallet_changesets (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    data BLOB NOT NULL
+);
+CREATE TABLE IF NOT EXISTS pending_outbound (
+    id TEXT PRIMARY KEY,
+    data TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS inbound_messages (
+    id TEXT PRIMARY KEY,
+    received_at INTEGER NOT NULL,
+    data TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS processed_inbound (
+    id TEXT PRIMARY KEY
+);
+CREATE TABLE IF NOT EXISTS contract_rates (
+    contract_id BLOB PRIMARY KEY,
+    data TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS contract_settlements (
+    contract_id BLOB PRIMARY KEY,
+    data TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS oracle_announcements (
+    oracle_public_key TEXT NOT NULL,
+    event_id TEXT NOT NULL,
+    maturity INTEGER NOT NULL,
+    data TEXT NOT NULL,
+    PRIMARY KEY (oracle_public_key, event_id)
+);
+CREATE TABLE IF NOT EXISTS oracle_attestations (
+    oracle_public_key TEXT NOT NULL,
+    event_id TEXT NOT NULL,
+    data TEXT NOT NULL,
+    PRIMARY KEY (oracle_public_key, event_id)
+);
+CREATE TABLE IF NOT EXISTS contract_transactions (
+    txid BLOB PRIMARY KEY,
+    data TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS contract_metadata (
+    contract_id BLOB PRIMARY KEY,
+    data TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS archived_contracts (
+    id BLOB PRIMARY KEY,
+    archived_at INTEGER NOT NULL,
+    data BLOB NOT NULL
+);
+CREATE TABLE IF NOT EXISTS settings (
+    key TEXT PRIMARY KEY,
+    value BLOB NOT NULL
+);
+CREATE TABLE IF NOT EXISTS audit_log (
+    sequence INTEGER PRIMARY KEY AUTOINCREMENT,
+    data TEXT NOT NULL
+);
+";
+
+const TABLES: [&str; 19] = [
+    "contracts",
+    "channels",
+    "chain_monitor",
+    "peers",
+    "signers",
+    "key_usage",
+    "wallet_changesets",
+    "pending_outbound",
+    "inbound_messages",
+    "processed_inbound",
+    "contract_rates",
+    "contract_settlements",
+    "oracle_announcements",
+    "oracle_attestations",
+    "contract_transactions",
+    "contract_metadata",
+    "archived_contracts",
+    "settings",
+    "audit_log",
+];
+
+const MAINTENANCE_KEY: &str = "maintenance";
+const RESERVED_UTXOS_KEY: &str = "reserved_utxos";
+const FROZEN_UTXOS_KEY: &str = "frozen_utxos";
+const PEER_BANS_KEY: &str = "peer_bans";
+const PENDING_BROADCASTS_KEY: &str = "pending_broadcasts";
+const FEE_ESTIMATES_KEY: &str = "fee_estimates";
+const SIGNER_INDEX_KEY: &str = "signer_index";
+
+const UPSERT_PENDING_OUTBOUND: &str = "INSERT OR REPLACE INTO pending_outbound (id, data) VALUES (?1, ?2)";
+
+fn transport_cursor_key(transport: &str) -> String {
+    format!("transport_cursor/{}", transport)
+}
+
+/// Implementation of Storage interface using SQLite.
+#[derive(Debug, Clone)]
+pub struct SqliteStorageProvider {
+    conn: Arc<Mutex<Connection>>,
+    strict_transitions: bool,
+}
+
+impl SqliteStorageProvider {
+    /// Open or create the database at `path`. Use `:memory:` for a database that is dropped
+    /// with the provider.
+    pub fn new(path: &str) -> Result<Self, rusqlite::Error> {
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    /// Use an existing connection, for apps that already manage their SQLite database.
+    pub fn from_connection(conn: Connection) -> Result<Self, rusqlite::Error> {
+        conn.execute_batch(SCHEMA)?;
+        Ok(SqliteStorageProvider {
+            conn: Arc::new(Mutex::new(conn)),
+            strict_transitions: false,
+        })
+    }
+
+    /// Reject contract updates that do not follow the contract state machine. By default
+    /// invalid transitions are only logged.
+    pub fn with_strict_transitions(mut self, strict_transitions: bool) -> Self {
+        self.strict_transitions = strict_transitions;
+        self
+    }
+
+    fn conn(&self) -> MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap()
+    }
+
+    fn contracts_in_state<T: Serializable>(&self, state: ContractState) -> Result<Vec<T>, Error> {
+        let conn = self.conn();
+        let mut stmt = conn
+            .prepare("SELECT data FROM contracts WHERE state = ?1")
+            .map_err(to_storage_error)?;
+        let rows = stmt
+            .query_map(params![state.to_string()], |row| row.get::<_, Vec<u8>>(0))
+            .map_err(to_storage_error)?;
+        rows.map(|data| {
+            let data = data.map_err(to_storage_error)?;
+            // Skip the prefix byte of the serialized contract.
+            T::deserialize(&mut ::lightning::io::Cursor::new(&data[1..])).map_err(to_storage_error)
+        })
+        .collect()
+    }
+
+    fn channels_of_kind<T: Serializable>(
+        &self,
+        kind: u8,
+        signed_state: Option<u8>,
+    ) -> Result<Vec<T>, Error> {
+        let conn = self.conn();
+        let mut stmt = conn
+            .prepare("SELECT data FROM channels WHERE kind = ?1 AND (?2 IS NULL OR signed_state = ?2)")
+            .map_err(to_storage_error)?;
+        let rows = stmt
+            .query_map(params![kind, signed_state], |row| row.get::<_, Vec<u8>>(0))
+            .map_err(to_storage_error)?;
+        rows.map(|data| {
+            let data = data.map_err(to_storage_error)?;
+            // Skip the channel prefix, and the signed state prefix of signed channels.
+            let skip = if kind == u8::from(ChannelPrefix::Signed) { 2 } else { 1 };
+            T::deserialize(&mut ::lightning::io::Cursor::new(&data[skip..]))
+                .map_err(to_storage_error)
+        })
+        .collect()
+    }
+
+    fn all_channels(&self) -> Result<Vec<Channel>, Error> {
+        let conn = self.conn();
+        let mut stmt = conn
+            .prepare("SELECT data FROM channels")
+            .map_err(to_storage_error)?;
+        let rows = stmt
+            .query_map([], |row| row.get::<_, Vec<u8>>(0))
+            .map_err(to_storage_error)?;
+        rows.map(|data| deserialize_channel(&data.map_err(to_storage_error)?))
+            .collect()
+    }
+
+    /// Count contracts by state from the state column without deserializing them.
+    pub(crate) fn contract_state_counts(&self) -> Result<HashMap<ContractState, usize>, Error> {
+        let conn = self.conn();
+        let mut stmt = conn
+            .prepare("SELECT state, COUNT(*) FROM contracts GROUP BY state")
+            .map_err(to_storage_error)?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?)))
+            .map_err(to_storage_error)?;
+        let mut counts = HashMap::new();
+        for row in rows {
+            let (state, count) = row.map_err(to_storage_error)?;
+            let state = ContractState::from_str(&state).map_err(to_storage_error)?;
+            counts.insert(state, count as usize);
+        }
+        Ok(counts)
+    }
+
+    /// Remove signers whose contract is closed or gone and that were derived more than the
+    /// retention period before `now`. Signers of open contracts and channels are always kept.
+    pub(crate) fn vacuum_signers_at(
+        &self,
+        now: u64,
+        options: &SignerVacuumOptions,
+    ) -> anyhow::Result<SignerVacuumReport> {
+        let live = live_negotiation_ids(&self.get_contracts()?, &self.all_channels()?);
+
+        let mut conn = self.conn();
+        let mut report = SignerVacuumReport::default();
+        let mut removed = vec![];
+        {
+            let mut stmt = conn.prepare(
+                "SELECT signers.key_id, signers.data, key_usage.data FROM signers
+                 LEFT JOIN key_usage ON key_usage.key_id = signers.key_id",
+            )?;
+            let rows = stmt.query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, Vec<u8>>(1)?,
+                    row.get::<_, Option<Vec<u8>>>(2)?,
+                ))
+            })?;
+            for row in rows {
+                let (key_id, signer, usage) = row?;
+                let Some(usage) = usage else {
+                    report.unattributed += 1;
+                    continue;
+                };
+                let usage: KeyUsage = bincode::deserialize(&usage)?;
+                if !is_vacuumable(&usage, &live, now, options) {
+                    report.kept += 1;
+                    continue;
+                }
+                removed.push((key_id, signer));
+            }
+        }
+
+        if removed.is_empty() {
+            return Ok(report);
+        }
+
+        if let Some(archive) = &options.export_before_delete {
+            append_to_archive(archive, &removed)?;
+        }
+
+        let tx = conn.transaction()?;
+        for (key_id, _) in &removed {
+            tx.execute("DELETE FROM signers WHERE key_id = ?1", params![key_id])?;
+            tx.execute("DELETE FROM key_usage WHERE key_id = ?1", params![key_id])?;
+        }
+        tx.commit()?;
+
+        for (key_id, _) in removed {
+            tracing::info!(key_id, "Removed unused signer.");
+            report.removed.push(key_id);
+        }
+        Ok(report)
+    }
+}
+
+fn to_storage_error<T>(e: T) -> Error
+where
+    T: std::fmt::Display,
+{
+    Error::StorageError(e.to_string())
+}
+
+/// Append an audit entry and return its sequence number. AUTOINCREMENT keeps sequence numbers
+/// from being reused.
+fn append_audit_entry(conn: &Connection, entry: &AuditEntry) -> Result<u64, Error> {
+    conn.execute(
+        "INSERT INTO audit_log (data) VALUES (?1)",
+        params![serde_json::to_string(entry).map_err(to_storage_error)?],
+    )
+    .map_err(to_storage_error)?;
+    Ok(conn.last_insert_rowid() as u64)
+}
+
+/// Store `contract` and audit its state change in the same transaction.
+fn insert_contract(tx: &Transaction, contract: &Contract) -> Result<(), Error> {
+    let serialized = serialize_contract(contract)?;
+    let existing = tx
+        .query_row(
+            "SELECT data FROM contracts WHERE id IN (?1, ?2) ORDER BY id = ?1 DESC LIMIT 1",
+            params![
+                contract.get_id().as_slice(),
+                contract.get_temporary_id().as_slice()
+            ],
+            |row| row.get::<_, Vec<u8>>(0),
+        )
+        .optional()
+        .map_err(to_storage_error)?
+        .map(|data| deserialize_contract_bytes(&data))
+        .transpose()?;
+    if let Some(entry) = AuditEntry::state_change(existing.as_ref(), contract) {
+        append_audit_entry(tx, &entry)?;
+    }
+    if let Contract::Accepted(_) | Contract::Signed(_) = contract {
+        tx.execute(
+            "DELETE FROM contracts WHERE id = ?1",
+            params![contract.get_temporary_id().as_slice()],
+        )
+        .map_err(to_storage_error)?;
+        tx.execute(
+            "UPDATE OR REPLACE contract_metadata SET contract_id = ?1 WHERE contract_id = ?2",
+            params![
+                contract.get_id().as_slice(),
+                contract.get_temporary_id().as_slice()
+            ],
+        )
+        .map_err(to_storage_error)?;
+    }
+    tx.execute(
+        "INSERT OR REPLACE INTO contracts (id, state, data) VALUES (?1, ?2, ?3)",
+        params![
+            contract.get_id().as_slice(),
+            ContractState::from(contract).to_string(),
+            serialized
+        ],
+    )
+    .map_err(to_storage_error)?;
+    Ok(())
+}
+
+impl Storage for SqliteStorageProvider {
+    fn get_contract(&self, contract_id: &ContractId) -> Result<Option<Contract>, Error> {
+        let data = self
+            .conn()
+            .query_row(
+                "SELECT data FROM contracts WHERE id = ?1",
+                params![contract_id.as_slice()],
+                |row| row.get::<_, Vec<u8>>(0),
+            )
+            .optional()
+            .map_err(to_storage_error)?;
+        data.map(|data| deserialize_contract_bytes(&data))
+            .transpose()
+    }
+
+    fn get_contracts(&self) -> Result<Vec<Contract>, Error> {
+        let conn = self.conn();
+        let mut stmt = conn
+            .prepare("SELECT data FROM contracts")
+            .map_err(to_storage_error)?;
+        let rows = stmt
+            .query_map([], |row| row.get::<_, Vec<u8>>(0))
+            .map_err(to_storage_error)?;
+        rows.map(|data| deserialize_contract_bytes(&data.map_err(to_storage_error)?))
+            .collect()
+    }
+
+    fn create_contract(&self, contract: &OfferedContract) -> Result<(), Error> {
+        let mut conn = self.conn();
+        let tx = conn.transaction().map_err(to_storage_error)?;
+        insert_contract(&tx, &Contract::Offered(contract.clone()))?;
+        tx.commit().map_err(to_storage_error)
+    }
+
+    fn delete_contract(&self, contract_id: &ContractId) -> Result<(), Error> {
+        self.conn()
+            .execute(
+                "DELETE FROM contracts WHERE id = ?1",
+                params![contract_id.as_slice()],
+            )
+            .map_err(to_storage_error)?;
+        Ok(())
+    }
+
+    fn update_contract(&self, contract: &Contract) -> Result<(), Error> {
+        let existing = match self.get_contract(&contract.get_id())? {
+            Some(c) => Some(c),
+            None => self.get_contract(&contract.get_temporary_id())?,
+        };
+        if let Some(existing) = &existing {
+            if let Err(e) = validate_transition(existing, contract) {
+                if self.strict_transitions {
+                    return Err(Error::StorageError(e.to_string()));
+                }
+                tracing::warn!(
+                    contract_id = hex::encode(contract.get_id()),
+                    error = e.to_string(),
+                    "Updating contract with an invalid transition."
+                );
+            }
+        }
+
+        let mut conn = self.conn();
+        let tx = conn.transaction().map_err(to_storage_error)?;
+        insert_contract(&tx, contract)?;
+        if let Some(pending) = PendingOutbound::for_state_change(existing.as_ref(), contract) {
+            tx.execute(
+                UPSERT_PENDING_OUTBOUND,
+                params![
+                    pending.id,
+                    serde_json::to_string(&pending).map_err(to_storage_error)?
+                ],
+            )
+            .map_err(to_storage_error)?;
+        }
+        tx.commit().map_err(to_storage_error)?;
+        metrics::contract_updated(existing.as_ref(), contract);
+        Ok(())
+    }
+
+    fn get_contract_offers(&self) -> Result<Vec<OfferedContract>, Error> {
+        self.contracts_in_state(ContractState::Offered)
+    }
+
+    fn get_signed_contracts(&self) -> Result<Vec<SignedContract>, Error> {
+        self.contracts_in_state(ContractState::Signed)
+    }
+
+    fn get_confirmed_contracts(&self) -> Result<Vec<SignedContract>, Error> {
+        self.contracts_in_state(ContractState::Confirmed)
+    }
+
+    fn get_preclosed_contracts(&self) -> Result<Vec<PreClosedContract>, Error> {
+        self.contracts_in_state(ContractState::PreClosed)
+    }
+
+    fn upsert_channel(&self, channel: Channel, contract: Option<Contract>) -> Result<(), Error> {
+        let serialized = serialize_channel(&channel)?;
+        let kind = ChannelPrefix::get_prefix(&channel);
+        let signed_state = match &channel {
+            Channel::Signed(s) => Some(SignedChannelPrefix::get_prefix(&s.state.get_type())),
+            _ => None,
+        };
+
+        let mut conn = self.conn();
+        let tx = conn.transaction().map_err(to_storage_error)?;
+        if let Channel::Accepted(_) | Channel::Signed(_) = &channel {
+            tx.execute(
+                "DELETE FROM channels WHERE id = ?1",
+                params![channel.get_temporary_id().as_slice()],
+            )
+            .map_err(to_storage_error)?;
+        }
+        tx.execute(
+            "INSERT OR REPLACE INTO channels (id, kind, signed_state, data) VALUES (?1, ?2, ?3, ?4)",
+            params![channel.get_id().as_slice(), kind, signed_state, serialized],
+        )
+        .map_err(to_storage_error)?;
+        if let Some(contract) = &contract {
+            insert_contract(&tx, contract)?;
+        }
+        tx.commit().map_err(to_storage_error)
+    }
+
+    fn delete_channel(&self, channel_id: &ChannelId) -> Result<(), Error> {
+        self.conn()
+            .execute(
+                "DELETE FROM channels WHERE id = ?1",
+                params![channel_id.as_slice()],
+            )
+            .map_err(to_storage_error)?;
+        Ok(())
+    }
+
+    fn get_channel(&self, channel_id: &ChannelId) -> Result<Option<Channel>, Error> {
+        let data = self
+            .conn()
+            .query_row(
+                "SELECT data FROM channels WHERE id = ?1",
+                params![channel_id.as_slice()],
+                |row| row.get::<_, Vec<u8>>(0),
+            )
+            .optional()
+            .map_err(to_storage_error)?;
+        data.map(|data| deserialize_channel(&data)).transpose()
+    }
+
+    fn get_signed_channels(
+        &self,
+        channel_state: Option<SignedChannelStateType>,
+    ) -> Result<Vec<SignedChannel>, Error> {
+        let signed_state = channel_state
+            .as_ref()
+            .map(SignedChannelPrefix::get_prefix);
+        self.channels_of_kind(ChannelPrefix::Signed.into(), signed_state)
+    }
+
+    fn get_offered_channels(&self) -> Result<Vec<OfferedChannel>, Error> {
+        self.channels_of_kind(ChannelPrefix::Offered.into(), None)
+    }
+
+    fn persist_chain_monitor(&self, monitor: &ChainMonitor) -> Result<(), Error> {
+        self.conn()
+            .execute(
+                "INSERT OR REPLACE INTO chain_monitor (id, data) VALUES (1, ?1)",
+                params![monitor.serialize()?],
+            )
+            .map_err(|e| Error::StorageError(format!("Error writing chain monitor: {}", e)))?;
+        Ok(())
+    }
+
+    fn get_chain_monitor(&self) -> Result<Option<ChainMonitor>, Error> {
+        let serialized = self
+            .conn()
+            .query_row("SELECT data FROM chain_monitor WHERE id = 1", [], |row| {
+                row.get::<_, Vec<u8>>(0)
+            })
+            .optional()
+            .map_err(|e| Error::StorageError(format!("Error reading chain monitor: {}", e)))?;
+        serialized
+            .map(|s| {
+                ChainMonitor::deserialize(&mut ::lightning::io::Cursor::new(s))
+                    .map_err(to_storage_error)
+            })
+            .transpose()
+    }
+}
+
+impl WalletPersister for SqliteStorageProvider {
+    type Error = WalletError;
+
+    fn persist(persister: &mut Self, changeset: &ChangeSet) -> Result<(), Self::Error> {
+        persister.conn().execute(
+            "INSERT INTO wallet_changesets (data) VALUES (?1)",
+            params![bincode::serialize(changeset)?],
+        )?;
+        Ok(())
+    }
+
+    fn initialize(persister: &mut Self) -> Result<ChangeSet, Self::Error> {
+        let conn = persister.conn();
+        let mut stmt = conn.prepare("SELECT data FROM wallet_changesets ORDER BY id")?;
+        let rows = stmt.query_map([], |row| row.get::<_, Vec<u8>>(0))?;
+        let mut aggregate = ChangeSet::default();
+        for data in rows {
+            aggregate.merge(bincode::deserialize::<ChangeSet>(&data?)?);
+        }
+        Ok(aggregate)
+    }
+}
+
+impl DeriveSigner for SqliteStorageProvider {
+    type Error = WalletError;
+
+    fn get_key_information(&self, key_id: [u8; 32]) -> Result<SignerInformation, Self::Error> {
+        let info = self
+            .conn()
+            .query_row(
+                "SELECT data FROM signers WHERE key_id = ?1",
+                params![hex::encode(key_id)],
+                |row| row.get::<_, Vec<u8>>(0),
+            )
+            .optional()?
+            .ok_or_else(|| WalletError::SignerError("Could not find key id.".into()))?;
+        Ok(bincode::deserialize::<SignerInformation>(&info)?)
+    }
+
+    fn store_derived_key_id(
+        &self,
+        key_id: [u8; 32],
+        signer_information: SignerInformation,
+    ) -> Result<(), Self::Error> {
+        self.conn().execute(
+            "INSERT OR REPLACE INTO signers (key_id, public_key, data) VALUES (?1, ?2, ?3)",
+            params![
+                hex::encode(key_id),
+                signer_information.public_key.to_string(),
+                bincode::serialize(&signer_information)?
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn get_secret_key(&self, public_key: &PublicKey) -> Result<SecretKey, Self::Error> {
+        let info = self
+            .conn()
+            .query_row(
+                "SELECT data FROM signers WHERE public_key = ?1",
+                params![public_key.to_string()],
+                |row| row.get::<_, Vec<u8>>(0),
+            )
+            .optional()?
+            .ok_or_else(|| WalletError::SignerError("Could not find secret key.".into()))?;
+        Ok(bincode::deserialize::<SignerInformation>(&info)?.secret_key)
+    }
+
+    fn import_address_to_storage(&self, _address: &bitcoin::Address) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn record_key_usage(&self, key_id: [u8; 32], usage: KeyUsage) -> Result<(), Self::Error> {
+        self.conn().execute(
+            "INSERT OR REPLACE INTO key_usage (key_id, data) VALUES (?1, ?2)",
+            params![hex::encode(key_id), bincode::serialize(&usage)?],
+        )?;
+        Ok(())
+    }
+
+    fn list_signers(&self) -> Result<Vec<([u8; 32], SignerInformation)>, Self::Error> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare("SELECT key_id, data FROM signers")?;
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, Vec<u8>>(1)?)))?;
+        let mut signers = vec![];
+        for row in rows {
+            let (key, data) = row?;
+            if let Some(key_id) = decode_key_id(key.as_bytes()) {
+                signers.push((key_id, bincode::deserialize(&data)?));
+            }
+        }
+        Ok(signers)
+    }
+
+    fn list_key_usage(&self) -> Result<Vec<([u8; 32], KeyUsage)>, Self::Error> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare("SELECT key_id, data FROM key_usage")?;
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, Vec<u8>>(1)?)))?;
+        let mut usages = vec![];
+        for row in rows {
+            let (key, data) = row?;
+            if let Some(key_id) = decode_key_id(key.as_bytes()) {
+                usages.push((key_id, bincode::deserialize(&data)?));
+            }
+        }
+        Ok(usages)
+    }
+
+    fn signer_index(&self) -> Result<Option<u32>, Self::Error> {
+        let value = self
+            .conn()
+            .query_row(
+                "SELECT value FROM settings WHERE key = ?1",
+                params![SIGNER_INDEX_KEY],
+                |row| row.get::<_, Vec<u8>>(0),
+            )
+            .optional()?;
+        match value {
+            Some(value) => Ok(Some(bincode::deserialize(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn save_signer_index(&self, index: u32) -> Result<(), Self::Error> {
+        self.conn().execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES (?1, ?2)",
+            params![SIGNER_INDEX_KEY, bincode::serialize(&index)?],
+        )?;
+        Ok(())
+    }
+}
+
+impl DdkStorage for SqliteStorageProvider {
+    fn list_peers(&self) -> anyhow::Result<Vec<PeerInformation>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare("SELECT pubkey, host FROM peers ORDER BY rowid")?;
+        let rows = stmt.query_map([], |row| {
+            Ok(PeerInformation {
+                pubkey: row.get(0)?,
+                host: row.get(1)?,
+            })
+        })?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    fn save_peer(&self, peer: PeerInformation) -> anyhow::Result<()> {
+        self.conn().execute(
+            "INSERT OR IGNORE INTO peers (pubkey, host) VALUES (?1, ?2)",
+            params![peer.pubkey, peer.host],
+        )?;
+        Ok(())
+    }
+
+    fn save_pending_outbound(&self, pending: PendingOutbound) -> anyhow::Result<()> {
+        self.conn().execute(
+            UPSERT_PENDING_OUTBOUND,
+            params![pending.id, serde_json::to_string(&pending)?],
+        )?;
+        Ok(())
+    }
+
+    fn list_pending_outbound(&self) -> anyhow::Result<Vec<PendingOutbound>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare("SELECT data FROM pending_outbound")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        let mut pending = vec![];
+        for data in rows {
+            pending.push(serde_json::from_str(&data?)?);
+        }
+        Ok(pending)
+    }
+
+    fn remove_pending_outbound(&self, id: &str) -> anyhow::Result<()> {
+        self.conn()
+            .execute("DELETE FROM pending_outbound WHERE id = ?1", params![id])?;
+        Ok(())
+    }
+
+    fn queue_inbound_message(&self, counterparty: PublicKey, message: &Message) -> anyhow::Result<bool> {
+        let pending = PendingInbound::new(counterparty, message);
+        let conn = self.conn();
+        let processed = conn
+            .query_row(
+                "SELECT 1 FROM processed_inbound WHERE id = ?1",
+                params![pending.id],
+                |_| Ok(()),
+            )
+            .optional()?
+            .is_some();
+        if processed {
+            return Ok(false);
+        }
+        conn.execute(
+            "INSERT OR IGNORE INTO inbound_messages (id, received_at, data) VALUES (?1, ?2, ?3)",
+            params![pending.id, pending.received_at as i64, serde_json::to_string(&pending)?],
+        )?;
+        Ok(true)
+    }
+
+    fn ack_inbound_message(&self, id: &str) -> anyhow::Result<()> {
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT OR IGNORE INTO processed_inbound (id) VALUES (?1)",
+            params![id],
+        )?;
+        tx.execute("DELETE FROM inbound_messages WHERE id = ?1", params![id])?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn pending_inbound_messages(&self) -> anyhow::Result<Vec<PendingInbound>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare("SELECT data FROM inbound_messages ORDER BY received_at")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        let mut pending = vec![];
+        for data in rows {
+            pending.push(serde_json::from_str(&data?)?);
+        }
+        Ok(pending)
+    }
+
+    fn record_inbound_failure(&self, id: &str) -> anyhow::Result<u32> {
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        let data = tx
+            .query_row(
+                "SELECT data FROM inbound_messages WHERE id = ?1",
+                params![id],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()?;
+        let Some(data) = data else {
+            return Ok(0);
+        };
+        let mut pending: PendingInbound = serde_json::from_str(&data)?;
+        pending.attempts += 1;
+        tx.execute(
+            "UPDATE inbound_messages SET data = ?2 WHERE id = ?1",
+            params![id, serde_json::to_string(&pending)?],
+        )?;
+        tx.commit()?;
+        Ok(pending.attempts)
+    }
+
+    fn pending_inbound_count(&self) -> anyhow::Result<usize> {
+        let count = self
+            .conn()
+            .query_row("SELECT COUNT(*) FROM inbound_messages", [], |row| row.get::<_, i64>(0))?;
+        Ok(count as usize)
+    }
+
+    fn append_audit_entry(&self, entry: AuditEntry) -> anyhow::Result<u64> {
+        Ok(append_audit_entry(&self.conn(), &entry)?)
+    }
+
+    fn audit_entries(&self, from: u64) -> anyhow::Result<Vec<AuditEntry>> {
+        let conn = self.conn();
+        let mut stmt =
+            conn.prepare("SELECT sequence, data FROM audit_log WHERE sequence >= ?1 ORDER BY sequence")?;
+        let rows = stmt.query_map(params![from as i64], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+        })?;
+        let mut entries = vec![];
+        for row in rows {
+            let (sequence, data) = row?;
+            let mut entry: AuditEntry = serde_json::from_str(&data)?;
+            entry.sequence = sequence as u64;
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    fn get_contract_rates(&self, contract_id: &ContractId) -> anyhow::Result<Option<ContractRates>> {
+        let data = self
+            .conn()
+            .query_row(
+                "SELECT data FROM contract_rates WHERE contract_id = ?1",
+                params![contract_id.as_slice()],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()?;
+        Ok(data.map(|data| serde_json::from_str(&data)).transpose()?)
+    }
+
+    fn save_contract_rates(&self, contract_id: &ContractId, rates: ContractRates) -> anyhow::Result<()> {
+        self.conn().execute(
+            "INSERT OR REPLACE INTO contract_rates (contract_id, data) VALUES (?1, ?2)",
+            params![contract_id.as_slice(), serde_json::to_string(&rates)?],
+        )?;
+        Ok(())
+    }
+
+    fn save_contract_settlement(&self, settlement: &ContractSettlement) -> anyhow::Result<()> {
+        self.conn().execute(
+            "INSERT OR REPLACE INTO contract_settlements (contract_id, data) VALUES (?1, ?2)",
+            params![
+                settlement.contract_id.as_slice(),
+                serde_json::to_string(settlement)?
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn list_contract_settlements(&self) -> anyhow::Result<Vec<ContractSettlement>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare("SELECT data FROM contract_settlements")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        let mut settlements = vec![];
+        for data in rows {
+            settlements.push(serde_json::from_str(&data?)?);
+        }
+        Ok(settlements)
+    }
+
+    fn save_announcement(&self, announcement: &OracleAnnouncement) -> anyhow::Result<()> {
+        self.conn().execute(
+            "INSERT OR REPLACE INTO oracle_announcements (oracle_public_key, event_id, maturity, data)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![
+                announcement.oracle_public_key.to_string(),
+                announcement.oracle_event.event_id,
+                announcement.oracle_event.event_maturity_epoch,
+                serde_json::to_string(announcement)?
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn get_announcement(
+        &self,
+        oracle: &XOnlyPublicKey,
+        event_id: &str,
+    ) -> anyhow::Result<Option<OracleAnnouncement>> {
+        let data = self
+            .conn()
+            .query_row(
+                "SELECT data FROM oracle_announcements WHERE oracle_public_key = ?1 AND event_id = ?2",
+                params![oracle.to_string(), event_id],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()?;
+        Ok(data.map(|data| serde_json::from_str(&data)).transpose()?)
+    }
+
+    fn evict_announcements(&self, matured_before: u64) -> anyhow::Result<usize> {
+        Ok(self.conn().execute(
+            "DELETE FROM oracle_announcements WHERE maturity < ?1",
+            params![matured_before as i64],
+        )?)
+    }
+
+    fn save_attestation(&self, event_id: &str, attestation: &OracleAttestation) -> anyhow::Result<()> {
+        self.conn().execute(
+            "INSERT OR REPLACE INTO oracle_attestations (oracle_public_key, event_id, data)
+             VALUES (?1, ?2, ?3)",
+            params![
+                attestation.oracle_public_key.to_string(),
+                event_id,
+                serde_json::to_string(attestation)?
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn get_attestation(
+        &self,
+        oracle: &XOnlyPublicKey,
+        event_id: &str,
+    ) -> anyhow::Result<Option<OracleAttestation>> {
+        let data = self
+            .conn()
+            .query_row(
+                "SELECT data FROM oracle_attestations WHERE oracle_public_key = ?1 AND event_id = ?2",
+                params![oracle.to_string(), event_id],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()?;
+        Ok(data.map(|data| serde_json::from_str(&data)).transpose()?)
+    }
+
+    fn save_contract_transactions(&self, records: &[ContractTransaction]) -> anyhow::Result<()> {
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        for record in records {
+            tx.execute(
+                "INSERT OR REPLACE INTO contract_transactions (txid, data) VALUES (?1, ?2)",
+                params![record.txid.as_byte_array(), serde_json::to_string(record)?],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn list_contract_transactions(&self) -> anyhow::Result<Vec<ContractTransaction>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare("SELECT data FROM contract_transactions")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        let mut records = vec![];
+        for data in rows {
+            records.push(serde_json::from_str(&data?)?);
+        }
+        Ok(records)
+    }
+
+    fn set_contract_metadata(&self, contract_id: &ContractId, metadata: ContractMetadata) -> anyhow::Result<()> {
+        self.conn().execute(
+            "INSERT OR REPLACE INTO contract_metadata (contract_id, data) VALUES (?1, ?2)",
+            params![contract_id.as_slice(), serde_json::to_string(&metadata)?],
+        )?;
+        Ok(())
+    }
+
+    fn get_contract_metadata(&self, contract_id: &ContractId) -> anyhow::Result<Option<ContractMetadata>> {
+        let data = self
+            .conn()
+            .query_row(
+                "SELECT data FROM contract_metadata WHERE contract_id = ?1",
+                params![contract_id.as_slice()],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()?;
+        Ok(data.map(|data| serde_json::from_str(&data)).transpose()?)
+    }
+
+    /// Contracts are as old as the `created_at` of their metadata. Without metadata they are
+    /// archived as soon as they are finished.
+    fn archive_closed_contracts(&self, older_than: Duration) -> anyhow::Result<usize> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+        let finished = ContractState::ALL
+            .into_iter()
+            .filter(ContractState::is_final)
+            .map(|state| format!("'{}'", state))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        let candidates = {
+            let mut stmt = tx.prepare(&format!(
+                "SELECT c.id, c.data, m.data FROM contracts c
+                 LEFT JOIN contract_metadata m ON m.contract_id = c.id
+                 WHERE c.state IN ({})",
+                finished
+            ))?;
+            let rows = stmt.query_map([], |row| {
+                Ok((
+                    row.get::<_, Vec<u8>>(0)?,
+                    row.get::<_, Vec<u8>>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                ))
+            })?;
+            rows.collect::<Result<Vec<_>, _>>()?
+        };
+
+        let mut archived = 0;
+        for (id, data, metadata) in candidates {
+            let created_at = match metadata {
+                Some(metadata) => serde_json::from_str::<ContractMetadata>(&metadata)?.created_at,
+                None => 0,
+            };
+            if !is_archivable(&deserialize_contract_bytes(&data)?, created_at, now, older_than) {
+                continue;
+            }
+            tx.execute(
+                "INSERT OR REPLACE INTO archived_contracts (id, archived_at, data) VALUES (?1, ?2, ?3)",
+                params![id, now as i64, data],
+            )?;
+            tx.execute("DELETE FROM contracts WHERE id = ?1", params![id])?;
+            archived += 1;
+        }
+        tx.commit()?;
+        Ok(archived)
+    }
+
+    fn get_archived_contracts(&self) -> anyhow::Result<Vec<Contract>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare("SELECT data FROM archived_contracts")?;
+        let rows = stmt.query_map([], |row| row.get::<_, Vec<u8>>(0))?;
+        let mut contracts = vec![];
+        for data in rows {
+            contracts.push(deserialize_contract_bytes(&data?)?);
+        }
+        Ok(contracts)
+    }
+
+    fn prune_archive(&self, older_than: Duration) -> anyhow::Result<usize> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+        let cutoff = now.saturating_sub(older_than.as_secs()) as i64;
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "DELETE FROM contract_metadata WHERE contract_id IN
+             (SELECT id FROM archived_contracts WHERE archived_at <= ?1)",
+            params![cutoff],
+        )?;
+        let pruned = tx.execute(
+            "DELETE FROM archived_contracts WHERE archived_at <= ?1",
+            params![cutoff],
+        )?;
+        tx.commit()?;
+        Ok(pruned)
+    }
+
+    fn vacuum_signers(&self, options: &SignerVacuumOptions) -> anyhow::Result<SignerVacuumReport> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+        self.vacuum_signers_at(now, options)
+    }
+
+    fn maintenance(&self) -> anyhow::Result<bool> {
+        let value = self
+            .conn()
+            .query_row(
+                "SELECT value FROM settings WHERE key = ?1",
+                params![MAINTENANCE_KEY],
+                |row| row.get::<_, Vec<u8>>(0),
+            )
+            .optional()?;
+        Ok(value.is_some_and(|value| value == [1]))
+    }
+
+    fn set_maintenance(&self, enabled: bool) -> anyhow::Result<()> {
+        self.conn().execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES (?1, ?2)",
+            params![MAINTENANCE_KEY, vec![enabled as u8]],
+        )?;
+        Ok(())
+    }
+
+    fn get_transport_cursor(&self, transport: &str) -> anyhow::Result<Option<u64>> {
+        let value = self
+            .conn()
+            .query_row(
+                "SELECT value FROM settings WHERE key = ?1",
+                params![transport_cursor_key(transport)],
+                |row| row.get::<_, Vec<u8>>(0),
+            )
+            .optional()?;
+        match value {
+            Some(value) => Ok(Some(bincode::deserialize(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn save_transport_cursor(&self, transport: &str, cursor: u64) -> anyhow::Result<()> {
+        self.conn().execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES (?1, ?2)",
+            params![transport_cursor_key(transport), bincode::serialize(&cursor)?],
+        )?;
+        Ok(())
+    }
+
+    fn list_reserved_utxos(&self) -> anyhow::Result<Vec<OutPoint>> {
+        let value = self
+            .conn()
+            .query_row(
+                "SELECT value FROM settings WHERE key = ?1",
+                params![RESERVED_UTXOS_KEY],
+                |row| row.get::<_, Vec<u8>>(0),
+            )
+            .optional()?;
+        match value {
+            Some(value) => Ok(bincode::deserialize(&value)?),
+            None => Ok(vec![]),
+        }
+    }
+
+    fn save_reserved_utxos(&self, outpoints: &[OutPoint]) -> anyhow::Result<()> {
+        self.conn().execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES (?1, ?2)",
+            params![RESERVED_UTXOS_KEY, bincode::serialize(outpoints)?],
+        )?;
+        Ok(())
+    }
+
+    fn list_frozen_utxos(&self) -> anyhow::Result<Vec<OutPoint>> {
+        let value = self
+            .conn()
+            .query_row(
+                "SELECT value FROM settings WHERE key = ?1",
+                params![FROZEN_UTXOS_KEY],
+                |row| row.get::<_, Vec<u8>>(0),
+            )
+            .optional()?;
+        match value {
+            Some(value) => Ok(bincode::deserialize(&value)?),
+            None => Ok(vec![]),
+        }
+    }
+
+    fn save_frozen_utxos(&self, outpoints: &[OutPoint]) -> anyhow::Result<()> {
+        self.conn().execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES (?1, ?2)",
+            params![FROZEN_UTXOS_KEY, bincode::serialize(outpoints)?],
+        )?;
+        Ok(())
+    }
+
+    fn list_peer_bans(&self) -> anyhow::Result<Vec<PeerBan>> {
+        let value = self
+            .conn()
+            .query_row(
+                "SELECT value FROM settings WHERE key = ?1",
+                params![PEER_BANS_KEY],
+                |row| row.get::<_, Vec<u8>>(0),
+            )
+            .optional()?;
+        match value {
+            Some(value) => Ok(bincode::deserialize(&value)?),
+            None => Ok(vec![]),
+        }
+    }
+
+    fn save_peer_bans(&self, bans: &[PeerBan]) -> anyhow::Result<()> {
+        self.conn().execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES (?1, ?2)",
+            params![PEER_BANS_KEY, bincode::serialize(bans)?],
+        )?;
+        Ok(())
+    }
+
+    fn list_pending_broadcasts(&self) -> anyhow::Result<Vec<PendingBroadcast>> {
+        let value = self
+            .conn()
+            .query_row(
+                "SELECT value FROM settings WHERE key = ?1",
+                params![PENDING_BROADCASTS_KEY],
+                |row| row.get::<_, Vec<u8>>(0),
+            )
+            .optional()?;
+        match value {
+            Some(value) => Ok(bincode::deserialize(&value)?),
+            None => Ok(vec![]),
+        }
+    }
+
+    fn save_pending_broadcasts(&self, pending: &[PendingBroadcast]) -> anyhow::Result<()> {
+        self.conn().execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES (?1, ?2)",
+            params![PENDING_BROADCASTS_KEY, bincode::serialize(pending)?],
+        )?;
+        Ok(())
+    }
+
+    fn get_fee_estimates(&self) -> anyhow::Result<Option<StoredFees>> {
+        let value = self
+            .conn()
+            .query_row(
+                "SELECT value FROM settings WHERE key = ?1",
+                params![FEE_ESTIMATES_KEY],
+                |row| row.get::<_, Vec<u8>>(0),
+            )
+            .optional()?;
+        match value {
+            Some(value) => Ok(Some(bincode::deserialize(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn save_fee_estimates(&self, fees: &StoredFees) -> anyhow::Result<()> {
+        self.conn().execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES (?1, ?2)",
+            params![FEE_ESTIMATES_KEY, bincode::serialize(fees)?],
+        )?;
+        Ok(())
+    }
+
+    fn storage_stats(&self) -> anyhow::Result<StorageStats> {
+        let contracts_by_state = self.contract_state_counts()?;
+        let conn = self.conn();
+        let mut tree_sizes = BTreeMap::new();
+        for table in TABLES {
+            let count: i64 =
+                conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| row.get(0))?;
+            tree_sizes.insert(table.to_string(), count as usize);
+        }
+        let page_count: i64 = conn.query_row("PRAGMA page_count", [], |row| row.get(0))?;
+        let page_size: i64 = conn.query_row("PRAGMA page_size", [], |row| row.get(0))?;
+
+        Ok(StorageStats {
+            size_on_disk: Some((page_count * page_size) as u64),
+            wallet_changesets: tree_sizes["wallet_changesets"],
+            tree_sizes,
+            contracts_by_state,
+            wallet: Default::default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    macro_rules! sqlite_test {
+        ($name: ident, $body: expr) => {
+            #[test]
+            fn $name() {
+                let storage = SqliteStorageProvider::new(":memory:").expect("Error opening sqlite");
+                #[allow(clippy::redundant_closure_call)]
+                $body(storage);
+            }
+        };
+    }
+
+    fn deserialize_object<T>(serialized: &[u8]) -> T
+    where
+        T: Serializable,
+    {
+        let mut cursor = ::lightning::io::Cursor::new(&serialized);
+        T::deserialize(&mut cursor).unwrap()
+    }
+
+    sqlite_test!(
+        create_contract_can_be_retrieved,
+        |storage: SqliteStorageProvider| {
+            let serialized = include_bytes!("../../tests/data/dlc_storage/sled/Offered");
+            let contract = deserialize_object(serialized);
+
+            storage
+                .create_contract(&contract)
+                .expect("Error creating contract");
+
+            let retrieved = storage
+                .get_contract(&contract.id)
+                .expect("Error retrieving contract.");
+
+            if let Some(Contract::Offered(retrieved_offer)) = retrieved {
+                assert_eq!(serialized[..], retrieved_offer.serialize().unwrap()[..]);
+            } else {
+                unreachable!();
+            }
+        }
+    );
+
+    sqlite_test!(
+        update_contract_is_updated,
+        |storage: SqliteStorageProvider| {
+            let serialized = include_bytes!("../../tests/data/dlc_storage/sled/Offered");
+            let offered_contract: OfferedContract = deserialize_object(serialized);
+            let serialized = include_bytes!("../../tests/data/dlc_storage/sled/Accepted");
+            let accepted_contract = Contract::Accepted(deserialize_object(serialized));
+
+            storage
+                .create_contract(&offered_contract)
+                .expect("Error creating contract");
+
+            storage
+                .update_contract(&accepted_contract)
+                .expect("Error updating contract.");
+            let retrieved = storage
+                .get_contract(&accepted_contract.get_id())
+                .expect("Error retrieving contract.");
+
+            assert!(matches!(retrieved, Some(Contract::Accepted(_))));
+            assert_eq!(storage.get_contracts().unwrap().len(), 1);
+        }
+    );
+
+    sqlite_test!(
+        delete_contract_is_deleted,
+        |storage: SqliteStorageProvider| {
+            let serialized = include_bytes!("../../tests/data/dlc_storage/sled/Offered");
+            let contract = deserialize_object(serialized);
+            storage
+                .create_contract(&contract)
+                .expect("Error creating contract");
+
+            storage
+                .delete_contract(&contract.id)
+                .expect("Error deleting contract");
+
+            assert!(storage
+                .get_contract(&contract.id)
+                .expect("Error querying contract")
+                .is_none());
+        }
+    );
+
+    fn insert_offered_signed_and_confirmed(storage: &SqliteStorageProvider) {
+        let serialized = include_bytes!("../../tests/data/dlc_storage/sled/Offered");
+        let offered_contract = deserialize_object(serialized);
+        storage
+            .create_contract(&offered_contract)
+            .expect("Error creating contract");
+
+        let fixtures: [(&[u8], fn(SignedContract) -> Contract); 4] = [
+            (include_bytes!("../../tests/data/dlc_storage/sled/Signed"), Contract::Signed),
+            (include_bytes!("../../tests/data/dlc_storage/sled/Signed1"), Contract::Signed),
+            (include_bytes!("../../tests/data/dlc_storage/sled/Confirmed"), Contract::Confirmed),
+            (include_bytes!("../../tests/data/dlc_storage/sled/Confirmed1"), Contract::Confirmed),
+        ];
+        for (serialized, state) in fixtures {
+            storage
+                .update_contract(&state(deserialize_object(serialized)))
+                .expect("Error creating contract");
+        }
+
+        let serialized = include_bytes!("../../tests/data/dlc_storage/sled/PreClosed");
+        let preclosed_contract = Contract::PreClosed(deserialize_object(serialized));
+        storage
+            .update_contract(&preclosed_contract)
+            .expect("Error creating contract");
+    }
+
+    fn insert_offered_and_signed_channels(storage: &SqliteStorageProvider) {
+        let serialized = include_bytes!("../../tests/data/dlc_storage/sled/Offered");
+        let offered_contract = deserialize_object(serialized);
+        let serialized = include_bytes!("../../tests/data/dlc_storage/sled/OfferedChannel");
+        let offered_channel = deserialize_object(serialized);
+        storage
+            .upsert_channel(
+                Channel::Offered(offered_channel),
+                Some(Contract::Offered(offered_contract)),
+            )
+            .expect("Error creating contract");
+
+        let serialized =
+            include_bytes!("../../tests/data/dlc_storage/sled/SignedChannelEstablished");
+        let signed_channel = Channel::Signed(deserialize_object(serialized));
+        storage
+            .upsert_channel(signed_channel, None)
+            .expect("Error creating contract");
+
+        let serialized =
+            include_bytes!("../../tests/data/dlc_storage/sled/SignedChannelSettled");
+        let signed_channel = Channel::Signed(deserialize_object(serialized));
+        storage
+            .upsert_channel(signed_channel, None)
+            .expect("Error creating contract");
+    }
+
+    sqlite_test!(
+        get_contracts_by_state,
+        |storage: SqliteStorageProvider| {
+            insert_offered_signed_and_confirmed(&storage);
+
+            assert_eq!(storage.get_signed_contracts().unwrap().len(), 2);
+            assert_eq!(storage.get_confirmed_contracts().unwrap().len(), 2);
+            assert_eq!(storage.get_contract_offers().unwrap().len(), 1);
+            assert_eq!(storage.get_preclosed_contracts().unwrap().len(), 1);
+            assert_eq!(storage.get_contracts().unwrap().len(), 6);
+
+            let counts = storage.contract_state_counts().unwrap();
+            assert_eq!(counts.get(&ContractState::Signed), Some(&2));
+            assert_eq!(counts.values().sum::<usize>(), 6);
+        }
+    );
+
+    sqlite_test!(
+        channels_by_state,
+        |storage: SqliteStorageProvider| {
+            insert_offered_and_signed_channels(&storage);
+
+            assert_eq!(storage.get_offered_channels().unwrap().len(), 1);
+            assert_eq!(
+                storage
+                    .get_signed_channels(Some(SignedChannelStateType::Established))
+                    .unwrap()
+                    .len(),
+                1
+            );
+            assert_eq!(
+                storage
+                    .get_signed_channels(Some(SignedChannelStateType::Settled))
+                    .unwrap()
+                    .len(),
+                1
+            );
+            assert_eq!(storage.get_signed_channels(None).unwrap().len(), 2);
+            assert_eq!(storage.get_contract_offers().unwrap().len(), 1);
+        }
+    );
+
+    sqlite_test!(
+        persist_chain_monitor_test,
+        |storage: SqliteStorageProvider| {
+            let chain_monitor = ChainMonitor::new(123);
+
+            storage
+                .persist_chain_monitor(&chain_monitor)
+                .expect("to be able to persist the chain monistor.");
+
+            let retrieved = storage
+                .get_chain_monitor()
+                .expect("to be able to retrieve the chain monitor.")
+                .expect("to have a persisted chain monitor.");
+
+            assert_eq!(chain_monitor, retrieved);
+        }
+    );
+
+    sqlite_test!(peers_are_deduplicated, |storage: SqliteStorageProvider| {
+        let peer = PeerInformation {
+            pubkey: "pubkey".into(),
+            host: "127.0.0.1:9000".into(),
+        };
+        storage.save_peer(peer.clone()).unwrap();
+        storage.save_peer(peer.clone()).unwrap();
+        assert_eq!(storage.list_peers().unwrap(), vec![peer]);
+    });
+
+    #[test]
+    fn maintenance_persists_across_restart() {
+        let path = "tests/data/dlc_storage/sqlite_maintenance.db";
+        {
+            let storage = SqliteStorageProvider::new(path).unwrap();
+            assert!(!storage.maintenance().unwrap());
+            storage.set_maintenance(true).unwrap();
+        }
+        {
+            let storage = SqliteStorageProvider::new(path).unwrap();
+            assert!(storage.maintenance().unwrap());
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    sqlite_test!(
+        state_changes_are_audited_with_the_contract,
+        |storage: SqliteStorageProvider| {
+            let offered: OfferedContract =
+                deserialize_object(include_bytes!("../../tests/data/dlc_storage/sled/Offered"));
+            let signed: SignedContract =
+                deserialize_object(include_bytes!("../../tests/data/dlc_storage/sled/Signed"));
+            storage.create_contract(&offered).unwrap();
+            storage
+                .update_contract(&Contract::Offered(offered.clone()))
+                .unwrap();
+            storage.update_contract(&Contract::Signed(signed)).unwrap();
+
+            let entries = storage.audit_entries(0).unwrap();
+            assert_eq!(
+                entries.iter().map(|e| e.sequence).collect::<Vec<_>>(),
+                vec![1, 2]
+            );
+            assert_eq!(entries[0].details["to"], "Offered");
+            assert_eq!(entries[1].details["from"], "Offered");
+            assert_eq!(entries[1].details["to"], "Signed");
+            assert!(storage.audit_entries(3).unwrap().is_empty());
+        }
+    );
+}
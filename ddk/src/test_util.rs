@@ -1,32 +1,26 @@
-use bitcoin::{bip32::Xpriv, key::rand::Fill, Network};
+use bdk_wallet::{template::Bip84, KeychainKind, SignOptions, Wallet};
+use bitcoin::{bip32::Xpriv, key::rand::Fill, psbt::Psbt, Network};
 use dlc_manager::{manager::Manager, SystemTimeProvider};
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 
 use crate::{
-    chain::EsploraClient, oracle::P2PDOracleClient, storage::SledStorageProvider,
-    wallet::DlcDevKitWallet,
+    chain::{rebroadcast::BroadcastTracker, MockBlockchain},
+    oracle::P2PDOracleClient,
+    storage::SledStorageProvider,
+    wallet::{DlcDevKitWallet, ExternalSigner},
+    DlcDevKitDlcManager,
 };
 
-type TestManager = Arc<
-    Manager<
-        Arc<DlcDevKitWallet<SledStorageProvider>>,
-        Arc<
-            dlc_manager::CachedContractSignerProvider<
-                Arc<DlcDevKitWallet<SledStorageProvider>>,
-                dlc_manager::SimpleSigner,
-            >,
-        >,
-        Arc<EsploraClient>,
-        Arc<SledStorageProvider>,
-        Arc<P2PDOracleClient>,
-        Arc<SystemTimeProvider>,
-        Arc<DlcDevKitWallet<SledStorageProvider>>,
-        dlc_manager::SimpleSigner,
-    >,
->;
+type TestWalletInner = DlcDevKitWallet<SledStorageProvider, MockBlockchain>;
+
+/// The node's manager type, so tests can hand it to the node's message handlers.
+pub type TestManager = Arc<DlcDevKitDlcManager<SledStorageProvider, P2PDOracleClient, MockBlockchain>>;
 
 pub struct TestWallet {
-    pub wallet: DlcDevKitWallet<SledStorageProvider>,
+    pub wallet: Arc<TestWalletInner>,
+    pub blockchain: Arc<MockBlockchain>,
+    pub storage: Arc<SledStorageProvider>,
     pub path: String,
 }
 
@@ -39,16 +33,38 @@ impl TestWallet {
             .try_fill(&mut bitcoin::key::rand::thread_rng())
             .unwrap();
         let xpriv = Xpriv::new_master(Network::Regtest, &entropy).unwrap();
+        let blockchain = Arc::new(MockBlockchain::new(Network::Regtest));
         let wallet = DlcDevKitWallet::new(
             "test".into(),
             xpriv,
-            "http://localhost:30000",
+            blockchain.clone(),
             Network::Regtest,
             &path,
             storage.clone(),
         )
         .unwrap();
-        TestWallet { wallet, path }
+        TestWallet {
+            wallet: Arc::new(wallet),
+            blockchain,
+            storage,
+            path,
+        }
+    }
+
+    /// A manager over this wallet and its store, without any oracles.
+    pub fn manager(&self) -> TestManager {
+        Arc::new(
+            Manager::new(
+                self.wallet.clone(),
+                self.wallet.clone(),
+                Arc::new(BroadcastTracker::new(self.blockchain.clone(), self.storage.clone())),
+                self.storage.clone(),
+                HashMap::new(),
+                Arc::new(SystemTimeProvider {}),
+                self.wallet.clone(),
+            )
+            .unwrap(),
+        )
     }
 }
 
@@ -57,3 +73,32 @@ impl Drop for TestWallet {
         std::fs::remove_dir_all(&self.path).expect("Couldn't remove wallet dir");
     }
 }
+
+/// External signer holding the keys of a wallet in memory, standing in for a hardware wallet.
+pub struct InMemorySigner(Mutex<Wallet>);
+
+impl InMemorySigner {
+    pub fn new(xprv: Xpriv) -> InMemorySigner {
+        let wallet = Wallet::create(
+            Bip84(xprv, KeychainKind::External),
+            Bip84(xprv, KeychainKind::Internal),
+        )
+        .network(Network::Regtest)
+        .create_wallet_no_persist()
+        .unwrap();
+        InMemorySigner(Mutex::new(wallet))
+    }
+}
+
+impl ExternalSigner for InMemorySigner {
+    fn sign_input(&self, mut psbt: Psbt, _input_index: usize) -> anyhow::Result<Psbt> {
+        // The signer never saw the funding transactions.
+        let options = SignOptions {
+            trust_witness_utxo: true,
+            try_finalize: false,
+            ..Default::default()
+        };
+        self.0.lock().unwrap().sign(&mut psbt, options)?;
+        Ok(psbt)
+    }
+}
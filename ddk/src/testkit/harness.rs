@@ -0,0 +1,681 @@
+//! Two nodes wired to each other in one process, for end-to-end tests of DDK applications.
+//!
+//! The nodes talk over a [MemoryTransport], keep contracts in [MemoryStorageProvider], settle
+//! on a shared [MockOracle], and see the same regtest [MockBlockchain], where blocks are mined
+//! on demand.
+use crate::builder::DdkBuilder;
+use crate::chain::MockBlockchain;
+use crate::channel::ChannelState;
+use crate::config::{DdkConfig, SeedConfig};
+use crate::contract::confirmations::AcceptanceParams;
+use crate::contract::progress::AcceptOptions;
+use crate::contract::ContractState;
+use crate::storage::MemoryStorageProvider;
+use crate::testkit::oracle::MockOracle;
+use crate::transport::memory::MemoryTransport;
+use crate::DlcDevKit;
+use anyhow::anyhow;
+use bitcoin::absolute::LockTime;
+use bitcoin::hashes::Hash;
+use bitcoin::transaction::Version;
+use bitcoin::{Amount, Network, OutPoint, Transaction, TxIn, TxOut, Txid};
+use dlc::{EnumerationPayout, Payout};
+use dlc_manager::contract::contract_input::{ContractInput, ContractInputInfo, OracleInput};
+use dlc_manager::contract::enum_descriptor::EnumDescriptor;
+use dlc_manager::contract::ContractDescriptor;
+use dlc_manager::{Blockchain, ChannelId, ContractId, Storage};
+use dlc_messages::oracle_msgs::{OracleAnnouncement, OracleAttestation};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// A node of the [TestHarness].
+pub type TestNode = DlcDevKit<MemoryTransport, MemoryStorageProvider, MockOracle, MockBlockchain>;
+
+/// Sats each node's wallet is funded with.
+pub const DEFAULT_FUNDING: u64 = 200_000;
+
+/// Two started and funded nodes linked to each other.
+pub struct TestHarness {
+    pub alice: Arc<TestNode>,
+    pub bob: Arc<TestNode>,
+    pub oracle: Arc<MockOracle>,
+    pub blockchain: Arc<MockBlockchain>,
+    data_dir: PathBuf,
+}
+
+impl TestHarness {
+    /// Two nodes with the default config. Panics if a node cannot be built or started.
+    pub fn new_pair() -> TestHarness {
+        TestHarness::with_config(DdkConfig::default())
+    }
+
+    /// Like [TestHarness::new_pair], with both nodes built from `config`. The network, storage
+    /// path and seeds are set by the harness.
+    pub fn with_config(config: DdkConfig) -> TestHarness {
+        let data_dir = std::env::temp_dir().join(format!("ddk-harness-{}", uuid::Uuid::new_v4()));
+        let (alice_transport, bob_transport) = MemoryTransport::pair();
+        let oracle = Arc::new(MockOracle::new());
+        let blockchain = Arc::new(MockBlockchain::new(Network::Regtest));
+        let node = |name: &str, seed: u8, transport: MemoryTransport| {
+            let config = DdkConfig {
+                network: Network::Regtest,
+                storage_path: data_dir.join(name),
+                seed_config: SeedConfig::Bytes([seed; 64]),
+                ..config.clone()
+            };
+            let mut builder = DdkBuilder::new();
+            builder
+                .set_config(config)
+                .set_name(name)
+                .set_transport(Arc::new(transport))
+                .set_storage(Arc::new(MemoryStorageProvider::new()))
+                .set_oracle(oracle.clone())
+                .set_blockchain(blockchain.clone());
+            let node = builder.finish().expect("test node");
+            node.start().expect("test node start");
+            Arc::new(node)
+        };
+        let harness = TestHarness {
+            alice: node("alice", 1, alice_transport),
+            bob: node("bob", 2, bob_transport),
+            oracle,
+            blockchain,
+            data_dir,
+        };
+        harness.fund_wallet(&harness.alice, DEFAULT_FUNDING);
+        harness.fund_wallet(&harness.bob, DEFAULT_FUNDING);
+        harness
+    }
+
+    /// Pay `sats` to the node's wallet in an unconfirmed transaction.
+    pub fn fund_wallet(&self, node: &TestNode, sats: u64) -> OutPoint {
+        let address = node
+            .wallet()
+            .new_external_address()
+            .expect("address")
+            .address;
+        let deposit = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                // A distinct input per deposit so deposits do not conflict.
+                previous_output: OutPoint::new(Txid::hash(address.script_pubkey().as_bytes()), 0),
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(sats),
+                script_pubkey: address.script_pubkey(),
+            }],
+        };
+        self.blockchain
+            .send_transaction(&deposit)
+            .expect("deposit broadcast");
+        node.wallet().scan_for_recovery(20, 0).expect("wallet scan");
+        OutPoint::new(deposit.compute_txid(), 0)
+    }
+
+    /// Mine `blocks` blocks, confirming every broadcast transaction in the first one.
+    pub fn mine_blocks(&self, blocks: u64) {
+        self.blockchain.mine(blocks);
+    }
+
+    /// Attest `outcome` of an event of the oracle.
+    pub fn attest(&self, event_id: &str, outcome: &str) -> anyhow::Result<OracleAttestation> {
+        self.oracle.attest(event_id, outcome)
+    }
+
+    /// Alice offers `contract_input` on the oracle's announced events and Bob accepts it.
+    /// Returns the contract id once both nodes signed the contract.
+    pub fn offer_and_accept(&self, contract_input: &ContractInput) -> anyhow::Result<ContractId> {
+        self.negotiate(contract_input, None)
+    }
+
+    /// Like [TestHarness::offer_and_accept], with both nodes confirming the contract by
+    /// `params`.
+    pub fn offer_and_accept_with_params(
+        &self,
+        contract_input: &ContractInput,
+        params: AcceptanceParams,
+    ) -> anyhow::Result<ContractId> {
+        self.negotiate(contract_input, Some(params))
+    }
+
+    fn negotiate(
+        &self,
+        contract_input: &ContractInput,
+        params: Option<AcceptanceParams>,
+    ) -> anyhow::Result<ContractId> {
+        let announcements = contract_input
+            .contract_infos
+            .iter()
+            .map(|info| {
+                dlc_manager::Oracle::get_announcement(&*self.oracle, &info.oracles.event_id)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let bob_key = self.bob.transport().public_key();
+        let offer = match params {
+            Some(params) => self.alice.send_dlc_offer_with_params(
+                contract_input,
+                bob_key,
+                announcements,
+                params,
+            )?,
+            None => self
+                .alice
+                .send_dlc_offer(contract_input, bob_key, announcements)?,
+        };
+        let temporary_id = offer.temporary_contract_id;
+        wait_for_state(&self.bob, temporary_id, ContractState::Offered, WAIT)?;
+
+        let options = AcceptOptions {
+            acceptance: params,
+            ..Default::default()
+        };
+        let (contract_id, _, _) = self
+            .bob
+            .accept_dlc_offer_with_options(temporary_id, options)?;
+        let contract_id: ContractId = hex::decode(contract_id)?
+            .try_into()
+            .map_err(|_| anyhow!("Contract id is not 32 bytes."))?;
+        wait_for_state(&self.alice, contract_id, ContractState::Signed, WAIT)?;
+        wait_for_state(&self.bob, contract_id, ContractState::Signed, WAIT)?;
+        Ok(contract_id)
+    }
+}
+
+impl Drop for TestHarness {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.data_dir);
+    }
+}
+
+/// How long [TestHarness::offer_and_accept] waits for each step.
+const WAIT: Duration = Duration::from_secs(60);
+
+/// Wait until the node stores the contract in `state`. Fails with the last seen state after
+/// `timeout`.
+pub fn wait_for_state(
+    node: &TestNode,
+    contract_id: ContractId,
+    state: ContractState,
+    timeout: Duration,
+) -> anyhow::Result<()> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let current = node
+            .storage()
+            .get_contract(&contract_id)?
+            .map(|contract| ContractState::from(&contract));
+        if current == Some(state) {
+            return Ok(());
+        }
+        if Instant::now() > deadline {
+            return Err(anyhow!(
+                "Contract {} is {:?} after {}s, expected {}.",
+                hex::encode(contract_id),
+                current,
+                timeout.as_secs(),
+                state
+            ));
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+/// Wait until the node stores the channel in `state`. Fails with the last seen state after
+/// `timeout`.
+pub fn wait_for_channel_state(
+    node: &TestNode,
+    channel_id: ChannelId,
+    state: ChannelState,
+    timeout: Duration,
+) -> anyhow::Result<()> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let current = node
+            .storage()
+            .get_channel(&channel_id)?
+            .map(|channel| ChannelState::from(&channel));
+        if current == Some(state) {
+            return Ok(());
+        }
+        if Instant::now() > deadline {
+            return Err(anyhow!(
+                "Channel {} is {:?} after {}s, expected {}.",
+                hex::encode(channel_id),
+                current,
+                timeout.as_secs(),
+                state
+            ));
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+/// Payouts of a yes/no event: all of `total` to the offer party on yes, to the accept party
+/// on no.
+pub fn yes_no_payouts(total: u64) -> Vec<EnumerationPayout> {
+    [("yes", total), ("no", 0)]
+        .into_iter()
+        .map(|(outcome, offer)| EnumerationPayout {
+            outcome: outcome.to_string(),
+            payout: Payout {
+                offer,
+                accept: total - offer,
+            },
+        })
+        .collect()
+}
+
+/// A contract on one oracle event paying `outcome_payouts`, with both parties putting up
+/// half of the total payout.
+pub fn enum_contract_input(
+    announcement: &OracleAnnouncement,
+    outcome_payouts: Vec<EnumerationPayout>,
+) -> ContractInput {
+    let total = outcome_payouts
+        .iter()
+        .map(|payout| payout.payout.offer + payout.payout.accept)
+        .max()
+        .unwrap_or_default();
+    ContractInput {
+        offer_collateral: total / 2,
+        accept_collateral: total - total / 2,
+        fee_rate: 2,
+        contract_infos: vec![ContractInputInfo {
+            contract_descriptor: ContractDescriptor::Enum(EnumDescriptor { outcome_payouts }),
+            oracles: OracleInput {
+                public_keys: vec![announcement.oracle_public_key],
+                event_id: announcement.oracle_event.event_id.clone(),
+                threshold: 1,
+            },
+        }],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::close::CloseError;
+    use crate::contract::locktimes::DEFAULT_REFUND_DELAY;
+    use crate::contract::progress::{AcceptProgress, NegotiationPhase, ProgressCallback};
+    use crate::contract::refund::RefundError;
+    use crate::events::DdkEvent;
+    use crate::DdkError;
+    use crossbeam::channel::Receiver;
+    use ddk_payouts::curve::linear_payout;
+    use dlc_manager::contract::Contract;
+    use dlc_messages::oracle_msgs::{DigitDecompositionEventDescriptor, EventDescriptor};
+    use std::sync::Mutex;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    /// A contract on a new yes/no event maturing in a day. The offer party gets everything on
+    /// yes, the accept party on no.
+    fn coin_flip(harness: &TestHarness, event_id: &str) -> ContractInput {
+        let maturity = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as u32
+            + 86_400;
+        let announcement = harness
+            .oracle
+            .create_enum_event(event_id, &["yes", "no"], maturity)
+            .unwrap();
+        enum_contract_input(&announcement, yes_no_payouts(100_000))
+    }
+
+    /// Mine enough blocks to confirm the contract and wait until `node` sees it confirmed.
+    fn confirm(harness: &TestHarness, node: &TestNode, contract_id: ContractId) {
+        harness.mine_blocks(6);
+        node.force_check().unwrap();
+        wait_for_state(node, contract_id, ContractState::Confirmed, WAIT).unwrap();
+    }
+
+    fn recorder() -> (ProgressCallback, Arc<Mutex<Vec<AcceptProgress>>>) {
+        let progress = Arc::new(Mutex::new(vec![]));
+        let sink = progress.clone();
+        let callback: ProgressCallback = Arc::new(move |p| sink.lock().unwrap().push(p));
+        (callback, progress)
+    }
+
+    fn finished(progress: &[AcceptProgress]) -> bool {
+        progress
+            .last()
+            .is_some_and(|p| p.phase == NegotiationPhase::Signing && p.done == p.total)
+    }
+
+    /// Progress reported through the callback once the last phase finished.
+    fn wait_for_callback(progress: &Mutex<Vec<AcceptProgress>>) -> Vec<AcceptProgress> {
+        let deadline = Instant::now() + WAIT;
+        while !finished(&progress.lock().unwrap()) && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        progress.lock().unwrap().clone()
+    }
+
+    /// Progress events of `contract_id` until the last phase finished.
+    fn wait_for_events(events: &Receiver<DdkEvent>, contract_id: ContractId) -> Vec<AcceptProgress> {
+        let mut progress = vec![];
+        while !finished(&progress) {
+            match events.recv_timeout(WAIT) {
+                Ok(DdkEvent::NegotiationProgress {
+                    contract_id: id,
+                    progress: p,
+                }) if id == contract_id => progress.push(p),
+                Ok(_) => {}
+                Err(_) => break,
+            }
+        }
+        progress
+    }
+
+    /// Phases in order, `done` never going back within one, and every phase finished.
+    fn assert_monotonic(progress: &[AcceptProgress]) {
+        let rank = |p: &AcceptProgress| (p.phase as u8, p.done);
+        assert!(
+            progress.windows(2).all(|w| rank(&w[0]) <= rank(&w[1])),
+            "{:?}",
+            progress
+        );
+        assert!(progress.iter().all(|p| p.done <= p.total));
+        assert!(finished(progress), "{:?}", progress);
+        for phase in [NegotiationPhase::VerifyingCets, NegotiationPhase::BuildingFunding] {
+            let last = progress.iter().filter(|p| p.phase == phase).last().unwrap();
+            assert_eq!(last.done, last.total);
+        }
+    }
+
+    #[test]
+    fn large_numeric_contract_reports_monotonic_progress() {
+        let harness = TestHarness::new_pair();
+        let maturity = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as u32
+            + 86_400;
+        let descriptor = EventDescriptor::DigitDecompositionEvent(DigitDecompositionEventDescriptor {
+            base: 2,
+            is_signed: false,
+            unit: "usd".into(),
+            precision: 0,
+            nb_digits: 20,
+        });
+        let announcement = harness
+            .oracle
+            .create_event("progress", descriptor, maturity)
+            .unwrap();
+        let contract_input = linear_payout(20_000, 80_000, 50_000, 50_000)
+            .unwrap()
+            .rounding(0, 500)
+            .contract_input(&announcement, 2)
+            .unwrap();
+
+        let alice_events = harness.alice.subscribe();
+        let bob_events = harness.bob.subscribe();
+        let (sign_callback, sign_progress) = recorder();
+        harness.alice.set_sign_progress(Some(sign_callback));
+        let (accept_callback, accept_progress) = recorder();
+
+        let offer = harness
+            .alice
+            .send_dlc_offer(&contract_input, harness.bob.transport().public_key(), vec![announcement])
+            .unwrap();
+        let temporary_id = offer.temporary_contract_id;
+        wait_for_state(&harness.bob, temporary_id, ContractState::Offered, WAIT).unwrap();
+        let options = AcceptOptions {
+            progress: Some(accept_callback),
+            ..Default::default()
+        };
+        harness
+            .bob
+            .accept_dlc_offer_with_options(temporary_id, options)
+            .unwrap();
+
+        let accept_progress = wait_for_callback(&accept_progress);
+        let sign_progress = wait_for_callback(&sign_progress);
+        assert_monotonic(&accept_progress);
+        assert_monotonic(&sign_progress);
+        // Hundreds of payout ranges, counted the same on both sides.
+        let cets = accept_progress[0].total;
+        assert!(cets > 100, "{} CETs", cets);
+        assert_eq!(sign_progress[0].total, cets);
+
+        // The event stream carries only the start and end of each phase.
+        for events in [&alice_events, &bob_events] {
+            let progress = wait_for_events(events, temporary_id);
+            assert_monotonic(&progress);
+            assert_eq!(progress.len(), 6);
+            assert_eq!(progress[0].total, cets);
+        }
+    }
+
+    #[test]
+    fn offer_accept_sign_and_close() {
+        let harness = TestHarness::new_pair();
+        let maturity = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as u32
+            + 86_400;
+        let announcement = harness
+            .oracle
+            .create_enum_event("harness", &["yes", "no"], maturity)
+            .unwrap();
+        let contract_id = harness
+            .offer_and_accept(&enum_contract_input(&announcement, yes_no_payouts(100_000)))
+            .unwrap();
+
+        let Some(dlc_manager::contract::Contract::Signed(signed)) =
+            harness.alice.storage().get_contract(&contract_id).unwrap()
+        else {
+            panic!("Contract is not signed.");
+        };
+        let fund_txid = signed
+            .accepted_contract
+            .dlc_transactions
+            .fund
+            .compute_txid();
+        assert!(harness.blockchain.broadcasts().contains(&fund_txid));
+
+        harness.mine_blocks(6);
+        harness.alice.force_check().unwrap();
+        wait_for_state(&harness.alice, contract_id, ContractState::Confirmed, WAIT).unwrap();
+
+        harness.attest("harness", "yes").unwrap();
+        let cet_txid = harness.alice.close_contract(contract_id, vec![]).unwrap();
+        wait_for_state(&harness.alice, contract_id, ContractState::PreClosed, WAIT).unwrap();
+        assert!(harness.blockchain.broadcasts().contains(&cet_txid));
+    }
+
+    #[test]
+    fn close_contract_broadcasts_the_cet_of_the_attested_outcome() {
+        let harness = TestHarness::new_pair();
+        let contract_id = harness
+            .offer_and_accept(&coin_flip(&harness, "close"))
+            .unwrap();
+        confirm(&harness, &harness.bob, contract_id);
+
+        assert!(matches!(
+            harness.bob.close_contract(contract_id, vec![]),
+            Err(DdkError::Close(CloseError::NotAttested { .. }))
+        ));
+
+        // Bob closes alone with the attestation he was handed, while Alice does nothing.
+        let attestation = harness.attest("close", "no").unwrap();
+        let cet_txid = harness
+            .bob
+            .close_contract(contract_id, vec![attestation])
+            .unwrap();
+        wait_for_state(&harness.bob, contract_id, ContractState::PreClosed, WAIT).unwrap();
+        assert!(matches!(
+            harness.bob.close_contract(contract_id, vec![]),
+            Err(DdkError::Close(CloseError::NotConfirmed(ContractState::PreClosed)))
+        ));
+
+        harness.mine_blocks(6);
+        let confirmations = harness
+            .blockchain
+            .get_transaction_confirmations(&cet_txid)
+            .unwrap();
+        assert!(confirmations >= 1, "CET {} is not on chain", cet_txid);
+        harness.bob.force_check().unwrap();
+        wait_for_state(&harness.bob, contract_id, ContractState::Closed, WAIT).unwrap();
+    }
+
+    #[test]
+    fn offers_received_in_maintenance_are_held() {
+        let harness = TestHarness::new_pair();
+        let contract_input = coin_flip(&harness, "maintenance");
+        let announcement =
+            dlc_manager::Oracle::get_announcement(&*harness.oracle, "maintenance").unwrap();
+        harness.alice.set_maintenance(true).unwrap();
+
+        assert!(matches!(
+            harness.alice.send_dlc_offer(
+                &contract_input,
+                harness.bob.transport().public_key(),
+                vec![announcement.clone()]
+            ),
+            Err(DdkError::Maintenance)
+        ));
+
+        let offer = harness
+            .bob
+            .send_dlc_offer(&contract_input, harness.alice.transport().public_key(), vec![announcement])
+            .unwrap();
+        let temporary_id = offer.temporary_contract_id;
+        let deadline = Instant::now() + WAIT;
+        while harness.alice.held_offers().unwrap().is_empty() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        let held = harness.alice.held_offers().unwrap();
+        assert_eq!(held.len(), 1);
+        assert_eq!(held[0].id, hex::encode(temporary_id));
+        assert_eq!(held[0].state, ContractState::Offered);
+        let status = harness.alice.status();
+        assert!(status.maintenance);
+        assert_eq!(status.held_offers, 1);
+
+        // Held, not rejected: it can be accepted once maintenance is over.
+        harness.alice.set_maintenance(false).unwrap();
+        assert!(!harness.alice.status().maintenance);
+        let (contract_id, _, _) = harness.alice.accept_dlc_offer(temporary_id).unwrap();
+        let contract_id: ContractId = hex::decode(contract_id).unwrap().try_into().unwrap();
+        wait_for_state(&harness.bob, contract_id, ContractState::Signed, WAIT).unwrap();
+        assert!(harness.alice.held_offers().unwrap().is_empty());
+    }
+
+    #[test]
+    fn refund_returns_collateral_to_both_parties() {
+        let harness = TestHarness::new_pair();
+        let contract_id = harness
+            .offer_and_accept(&coin_flip(&harness, "refund"))
+            .unwrap();
+        confirm(&harness, &harness.alice, contract_id);
+
+        assert!(matches!(
+            harness.alice.refund_contract(contract_id),
+            Err(DdkError::Refund(RefundError::NotYetRefundable { .. }))
+        ));
+
+        // Past maturity and the refund delay, with the event never attested.
+        harness
+            .blockchain
+            .advance_time(Duration::from_secs(86_400) + DEFAULT_REFUND_DELAY + Duration::from_secs(60));
+        let refund_txid = harness.alice.refund_contract(contract_id).unwrap();
+        wait_for_state(&harness.alice, contract_id, ContractState::Refunded, WAIT).unwrap();
+        harness.mine_blocks(1);
+        assert_eq!(
+            harness
+                .blockchain
+                .get_transaction_confirmations(&refund_txid)
+                .unwrap(),
+            1
+        );
+
+        let Some(Contract::Refunded(refunded)) =
+            harness.alice.storage().get_contract(&contract_id).unwrap()
+        else {
+            panic!("Contract is not refunded.");
+        };
+        let offered = &refunded.accepted_contract.offered_contract;
+        let accept_params = &refunded.accepted_contract.accept_params;
+        let refund = harness.blockchain.get_transaction(&refund_txid).unwrap();
+        for (script_pubkey, collateral) in [
+            (&offered.offer_params.payout_script_pubkey, offered.offer_params.collateral),
+            (&accept_params.payout_script_pubkey, accept_params.collateral),
+        ] {
+            assert!(refund
+                .output
+                .iter()
+                .any(|o| &o.script_pubkey == script_pubkey && o.value.to_sat() == collateral));
+        }
+    }
+
+    #[test]
+    fn channel_offer_settle_renew_and_close() {
+        let harness = TestHarness::new_pair();
+        let alice: &TestNode = &harness.alice;
+        let bob: &TestNode = &harness.bob;
+        let input = coin_flip(&harness, "channel");
+        let announcement =
+            dlc_manager::Oracle::get_announcement(&*harness.oracle, "channel").unwrap();
+
+        let (temporary_id, _) = alice
+            .offer_channel(&input, bob.transport().public_key(), vec![announcement])
+            .unwrap();
+        wait_for_channel_state(bob, temporary_id, ChannelState::Offered, WAIT).unwrap();
+        let (channel_id, _) = bob.accept_channel(temporary_id).unwrap();
+        for node in [alice, bob] {
+            wait_for_channel_state(node, channel_id, ChannelState::Established, WAIT).unwrap();
+        }
+        harness.mine_blocks(6);
+
+        let settle = |node: &TestNode, counterparty: &TestNode| {
+            node.settle_channel(channel_id, 40_000).unwrap();
+            wait_for_channel_state(counterparty, channel_id, ChannelState::SettledReceived, WAIT)
+                .unwrap();
+            counterparty.accept_channel_update(channel_id).unwrap();
+            for node in [node, counterparty] {
+                wait_for_channel_state(node, channel_id, ChannelState::Settled, WAIT).unwrap();
+            }
+        };
+        settle(alice, bob);
+
+        let renewal = coin_flip(&harness, "channel-renewal");
+        alice.renew_channel(channel_id, &renewal).unwrap();
+        wait_for_channel_state(bob, channel_id, ChannelState::RenewOffered, WAIT).unwrap();
+        bob.accept_channel_update(channel_id).unwrap();
+        for node in [alice, bob] {
+            wait_for_channel_state(node, channel_id, ChannelState::Established, WAIT).unwrap();
+        }
+        let summary = alice
+            .list_channels()
+            .unwrap()
+            .into_iter()
+            .find(|c| c.id == hex::encode(channel_id))
+            .unwrap();
+        assert_eq!(summary.state, ChannelState::Established);
+        assert!(summary.contract_id.is_some());
+
+        // Only settled channels are closed collaboratively.
+        settle(bob, alice);
+        alice.collaborative_close_channel(channel_id).unwrap();
+        wait_for_channel_state(bob, channel_id, ChannelState::CollaborativeCloseOffered, WAIT)
+            .unwrap();
+        bob.accept_channel_update(channel_id).unwrap();
+        wait_for_channel_state(bob, channel_id, ChannelState::CollaborativelyClosed, WAIT)
+            .unwrap();
+        assert!(bob
+            .list_channels()
+            .unwrap()
+            .iter()
+            .all(|c| c.id != hex::encode(channel_id)));
+
+        harness.mine_blocks(6);
+        alice.force_check().unwrap();
+        wait_for_channel_state(alice, channel_id, ChannelState::CollaborativelyClosed, WAIT)
+            .unwrap();
+    }
+}
@@ -0,0 +1,85 @@
+//! Helpers for testing DDK applications.
+pub mod harness;
+pub mod oracle;
+pub mod strategies;
+
+use crate::chain::network::MUTINYNET_FAUCET;
+use crate::wallet::DlcDevKitWallet;
+use crate::{DdkBlockchain, DdkStorage};
+use anyhow::anyhow;
+use bitcoin::{Address, Amount, OutPoint, Txid};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// A signet faucet that pays to an address over HTTP.
+#[derive(Debug, Clone)]
+pub struct SignetFaucet {
+    /// Endpoint taking a JSON body of `{"sats", "address"}` and returning `{"txid"}`.
+    pub url: String,
+    /// How many wallet syncs to wait for the payment.
+    pub attempts: u32,
+    /// Time between syncs.
+    pub interval: Duration,
+}
+
+impl Default for SignetFaucet {
+    fn default() -> Self {
+        Self {
+            url: MUTINYNET_FAUCET.to_string(),
+            attempts: 30,
+            interval: Duration::from_secs(10),
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct FaucetResponse {
+    txid: String,
+}
+
+impl SignetFaucet {
+    /// Request `amount` to `address` and sync `wallet` until the payment shows up.
+    pub fn fund<S: DdkStorage, B: DdkBlockchain>(
+        &self,
+        wallet: &DlcDevKitWallet<S, B>,
+        address: &Address,
+        amount: Amount,
+    ) -> anyhow::Result<OutPoint> {
+        let body = serde_json::json!({
+            "sats": amount.to_sat(),
+            "address": address.to_string(),
+        });
+        let response = reqwest::blocking::Client::new()
+            .post(&self.url)
+            .header("Content-Type", "application/json")
+            .body(body.to_string())
+            .send()?
+            .error_for_status()?
+            .text()?;
+        let response: FaucetResponse = serde_json::from_str(&response)?;
+        let txid = Txid::from_str(&response.txid)?;
+        tracing::info!(txid = txid.to_string(), "Faucet sent funds.");
+
+        for _ in 0..self.attempts {
+            wallet.sync()?;
+            if let Some(utxo) = wallet
+                .list_utxos()?
+                .into_iter()
+                .find(|utxo| utxo.outpoint.txid == txid && utxo.address.as_ref() == Some(address))
+            {
+                return Ok(utxo.outpoint);
+            }
+            std::thread::sleep(self.interval);
+        }
+        Err(anyhow!("Faucet transaction {} did not reach the wallet.", txid))
+    }
+}
+
+/// Fund a wallet address from the mutinynet faucet.
+pub fn signet_faucet_fund<S: DdkStorage, B: DdkBlockchain>(
+    wallet: &DlcDevKitWallet<S, B>,
+    address: &Address,
+    amount: Amount,
+) -> anyhow::Result<OutPoint> {
+    SignetFaucet::default().fund(wallet, address, amount)
+}
@@ -0,0 +1,432 @@
+//! An oracle living in the test process. Announcements and attestations are signed with real
+//! keys and nonces, so contracts on its events verify and settle like on a live oracle.
+//!
+//! Keys and nonces are derived from a seed. An oracle built again from the same seed and
+//! storage attests the events announced before, so tests can restart it along with the nodes.
+use crate::storage::MemoryStorageProvider;
+use crate::{DdkOracle, DdkStorage};
+use anyhow::anyhow;
+use async_trait::async_trait;
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::key::{Keypair, XOnlyPublicKey};
+use bitcoin::secp256k1::{All, Message, Secp256k1};
+use dlc::secp_utils::schnorrsig_sign_with_nonce;
+use dlc_manager::error::Error as ManagerError;
+use dlc_messages::oracle_msgs::{
+    DigitDecompositionEventDescriptor, EnumEventDescriptor, EventDescriptor, OracleAnnouncement,
+    OracleAttestation, OracleEvent,
+};
+use lightning::util::ser::Writeable;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Seed of [MockOracle::new].
+pub const DEFAULT_ORACLE_SEED: [u8; 32] = [1u8; 32];
+
+/// Oracle signing announcements and attestations of events created by the test.
+///
+/// Events are kept in memory, and in `S` when built [MockOracle::with_storage].
+pub struct MockOracle<S = MemoryStorageProvider> {
+    secp: Secp256k1<All>,
+    seed: [u8; 32],
+    keypair: Keypair,
+    storage: Option<Arc<S>>,
+    announcements: Mutex<HashMap<String, OracleAnnouncement>>,
+    attestations: Mutex<HashMap<String, OracleAttestation>>,
+}
+
+impl<S> std::fmt::Debug for MockOracle<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MockOracle")
+            .field("public_key", &self.keypair.x_only_public_key().0)
+            .field("persisted", &self.storage.is_some())
+            .finish()
+    }
+}
+
+impl Default for MockOracle {
+    fn default() -> Self {
+        MockOracle::new()
+    }
+}
+
+impl MockOracle {
+    /// An oracle with the same keys in every test.
+    pub fn new() -> MockOracle {
+        MockOracle::from_seed(DEFAULT_ORACLE_SEED)
+    }
+
+    /// An oracle keeping its events in memory only.
+    pub fn from_seed(seed: [u8; 32]) -> MockOracle {
+        MockOracle::build(seed, None)
+    }
+}
+
+impl<S: DdkStorage> MockOracle<S> {
+    /// An oracle that also saves its announcements and attestations to `storage` and looks up
+    /// events there it does not know, ex. after the test restarted it.
+    pub fn with_storage(seed: [u8; 32], storage: Arc<S>) -> MockOracle<S> {
+        MockOracle::build(seed, Some(storage))
+    }
+
+    fn build(seed: [u8; 32], storage: Option<Arc<S>>) -> MockOracle<S> {
+        let secp = Secp256k1::new();
+        let keypair = Keypair::from_seckey_slice(&secp, &derive(&seed, b"signing-key", &[]))
+            .expect("valid key");
+        MockOracle {
+            secp,
+            seed,
+            keypair,
+            storage,
+            announcements: Mutex::new(HashMap::new()),
+            attestations: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn public_key(&self) -> XOnlyPublicKey {
+        self.keypair.x_only_public_key().0
+    }
+
+    /// Announce an event maturing at the unix time `maturity`. Numeric events get one nonce
+    /// per digit, and one for the sign if they are signed.
+    pub fn create_event(
+        &self,
+        event_id: &str,
+        descriptor: EventDescriptor,
+        maturity: u32,
+    ) -> anyhow::Result<OracleAnnouncement> {
+        if self.announcement(event_id)?.is_some() {
+            return Err(anyhow!("Event {} is already announced.", event_id));
+        }
+        let nonces = match &descriptor {
+            EventDescriptor::EnumEvent(e) if e.outcomes.is_empty() => {
+                return Err(anyhow!("Event {} has no outcomes.", event_id))
+            }
+            EventDescriptor::EnumEvent(_) => 1,
+            EventDescriptor::DigitDecompositionEvent(d) if d.base < 2 || d.nb_digits == 0 => {
+                return Err(anyhow!(
+                    "Event {} needs a base of at least 2 and at least one digit.",
+                    event_id
+                ))
+            }
+            EventDescriptor::DigitDecompositionEvent(d) => {
+                d.nb_digits as usize + d.is_signed as usize
+            }
+        };
+        let oracle_event = OracleEvent {
+            oracle_nonces: (0..nonces)
+                .map(|index| {
+                    Keypair::from_seckey_slice(&self.secp, &self.nonce(event_id, index))
+                        .expect("valid nonce")
+                        .x_only_public_key()
+                        .0
+                })
+                .collect(),
+            event_maturity_epoch: maturity,
+            event_descriptor: descriptor,
+            event_id: event_id.to_string(),
+        };
+        let message =
+            Message::from_digest(sha256::Hash::hash(&oracle_event.encode()).to_byte_array());
+        let announcement = OracleAnnouncement {
+            announcement_signature: self.secp.sign_schnorr_no_aux_rand(&message, &self.keypair),
+            oracle_public_key: self.public_key(),
+            oracle_event,
+        };
+        if let Some(storage) = &self.storage {
+            storage.save_announcement(&announcement)?;
+        }
+        self.announcements
+            .lock()
+            .unwrap()
+            .insert(event_id.to_string(), announcement.clone());
+        Ok(announcement)
+    }
+
+    /// Announce an event with `outcomes` maturing at the unix time `maturity`.
+    pub fn create_enum_event(
+        &self,
+        event_id: &str,
+        outcomes: &[&str],
+        maturity: u32,
+    ) -> anyhow::Result<OracleAnnouncement> {
+        let descriptor = EventDescriptor::EnumEvent(EnumEventDescriptor {
+            outcomes: outcomes.iter().map(|outcome| outcome.to_string()).collect(),
+        });
+        self.create_event(event_id, descriptor, maturity)
+    }
+
+    /// Attest `outcome` of an announced event. Contracts on the event can be closed after.
+    ///
+    /// The outcome of a numeric event is an integer in the unit of the event. It is split into
+    /// digits of the event's base, and values out of range attest the closest bound.
+    pub fn attest(&self, event_id: &str, outcome: &str) -> anyhow::Result<OracleAttestation> {
+        let announcement = self
+            .announcement(event_id)?
+            .ok_or_else(|| anyhow!("Event {} is not announced.", event_id))?;
+        let outcomes = match &announcement.oracle_event.event_descriptor {
+            EventDescriptor::EnumEvent(e) if !e.outcomes.iter().any(|o| o == outcome) => {
+                return Err(anyhow!(
+                    "{} is not an outcome of event {}.",
+                    outcome,
+                    event_id
+                ))
+            }
+            EventDescriptor::EnumEvent(_) => vec![outcome.to_string()],
+            EventDescriptor::DigitDecompositionEvent(d) => {
+                let value = outcome.parse::<i64>().map_err(|_| {
+                    anyhow!(
+                        "{} is not an outcome of numeric event {}.",
+                        outcome,
+                        event_id
+                    )
+                })?;
+                decompose(d, value)
+            }
+        };
+        // Signing other outcomes with the same nonces would reveal the oracle key.
+        if let Some(attestation) = self.attestation(event_id)? {
+            if attestation.outcomes != outcomes {
+                return Err(anyhow!("Event {} is already attested.", event_id));
+            }
+            return Ok(attestation);
+        }
+        let signatures = outcomes
+            .iter()
+            .enumerate()
+            .map(|(index, outcome)| {
+                let message =
+                    Message::from_digest(sha256::Hash::hash(outcome.as_bytes()).to_byte_array());
+                schnorrsig_sign_with_nonce(
+                    &self.secp,
+                    &message,
+                    &self.keypair,
+                    &self.nonce(event_id, index),
+                )
+            })
+            .collect();
+        let attestation = OracleAttestation {
+            oracle_public_key: self.public_key(),
+            signatures,
+            outcomes,
+        };
+        if let Some(storage) = &self.storage {
+            storage.save_attestation(event_id, &attestation)?;
+        }
+        self.attestations
+            .lock()
+            .unwrap()
+            .insert(event_id.to_string(), attestation.clone());
+        Ok(attestation)
+    }
+
+    fn announcement(&self, event_id: &str) -> anyhow::Result<Option<OracleAnnouncement>> {
+        if let Some(announcement) = self.announcements.lock().unwrap().get(event_id) {
+            return Ok(Some(announcement.clone()));
+        }
+        let Some(storage) = &self.storage else {
+            return Ok(None);
+        };
+        let announcement = storage.get_announcement(&self.public_key(), event_id)?;
+        if let Some(announcement) = &announcement {
+            self.announcements
+                .lock()
+                .unwrap()
+                .insert(event_id.to_string(), announcement.clone());
+        }
+        Ok(announcement)
+    }
+
+    fn attestation(&self, event_id: &str) -> anyhow::Result<Option<OracleAttestation>> {
+        if let Some(attestation) = self.attestations.lock().unwrap().get(event_id) {
+            return Ok(Some(attestation.clone()));
+        }
+        let Some(storage) = &self.storage else {
+            return Ok(None);
+        };
+        let attestation = storage.get_attestation(&self.public_key(), event_id)?;
+        if let Some(attestation) = &attestation {
+            self.attestations
+                .lock()
+                .unwrap()
+                .insert(event_id.to_string(), attestation.clone());
+        }
+        Ok(attestation)
+    }
+
+    /// Secret nonce of outcome `index` of the event.
+    fn nonce(&self, event_id: &str, index: usize) -> [u8; 32] {
+        let mut data = event_id.as_bytes().to_vec();
+        data.extend_from_slice(&(index as u32).to_be_bytes());
+        derive(&self.seed, b"nonce", &data)
+    }
+}
+
+/// A secret key from the seed for `purpose`.
+fn derive(seed: &[u8; 32], purpose: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut engine = sha256::Hash::engine();
+    engine.input(seed);
+    engine.input(purpose);
+    engine.input(data);
+    sha256::Hash::from_engine(engine).to_byte_array()
+}
+
+/// The outcomes attesting `value`: the sign if the event is signed, then the digits, most
+/// significant first.
+fn decompose(descriptor: &DigitDecompositionEventDescriptor, value: i64) -> Vec<String> {
+    let base = descriptor.base as u128;
+    let max = base
+        .checked_pow(descriptor.nb_digits as u32)
+        .map_or(u128::MAX, |values| values - 1);
+    let magnitude = if descriptor.is_signed || value > 0 {
+        (value.unsigned_abs() as u128).min(max)
+    } else {
+        0
+    };
+    let mut outcomes = Vec::with_capacity(descriptor.nb_digits as usize + 1);
+    if descriptor.is_signed {
+        outcomes.push(if value < 0 { "-" } else { "+" }.to_string());
+    }
+    outcomes.extend((0..descriptor.nb_digits as u32).rev().map(|position| {
+        let digit = base
+            .checked_pow(position)
+            .map_or(0, |weight| magnitude / weight % base);
+        digit.to_string()
+    }));
+    outcomes
+}
+
+impl<S: DdkStorage> dlc_manager::Oracle for MockOracle<S> {
+    fn get_public_key(&self) -> XOnlyPublicKey {
+        self.public_key()
+    }
+
+    fn get_announcement(&self, event_id: &str) -> Result<OracleAnnouncement, ManagerError> {
+        self.announcement(event_id)
+            .map_err(|e| ManagerError::OracleError(e.to_string()))?
+            .ok_or_else(|| ManagerError::OracleError(format!("Unknown event {}.", event_id)))
+    }
+
+    fn get_attestation(&self, event_id: &str) -> Result<OracleAttestation, ManagerError> {
+        self.attestation(event_id)
+            .map_err(|e| ManagerError::OracleError(e.to_string()))?
+            .ok_or_else(|| {
+                ManagerError::OracleError(format!("Event {} is not attested.", event_id))
+            })
+    }
+}
+
+#[async_trait]
+impl<S: DdkStorage> DdkOracle for MockOracle<S> {
+    fn name(&self) -> String {
+        "mock".into()
+    }
+
+    async fn get_announcement_async(
+        &self,
+        event_id: &str,
+    ) -> Result<OracleAnnouncement, ManagerError> {
+        dlc_manager::Oracle::get_announcement(self, event_id)
+    }
+
+    async fn get_public_key_async(&self) -> Result<XOnlyPublicKey, ManagerError> {
+        Ok(self.public_key())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::oracle::verify::verify_attestation;
+    use dlc_manager::Oracle;
+
+    fn numeric(is_signed: bool) -> EventDescriptor {
+        EventDescriptor::DigitDecompositionEvent(DigitDecompositionEventDescriptor {
+            base: 2,
+            is_signed,
+            unit: "sats".to_string(),
+            precision: 0,
+            nb_digits: 4,
+        })
+    }
+
+    #[test]
+    fn attestations_verify_against_the_announcement() {
+        let oracle = MockOracle::new();
+        let announcement = oracle
+            .create_enum_event("coin-flip", &["heads", "tails"], 1_700_000_000)
+            .unwrap();
+        announcement
+            .validate(&Secp256k1::verification_only())
+            .unwrap();
+        assert_eq!(announcement.oracle_public_key, oracle.get_public_key());
+        assert!(oracle.get_attestation("coin-flip").is_err());
+        assert!(oracle.attest("coin-flip", "edge").is_err());
+
+        let attestation = oracle.attest("coin-flip", "heads").unwrap();
+        assert_eq!(oracle.get_attestation("coin-flip").unwrap(), attestation);
+        assert_eq!(attestation.outcomes, vec!["heads".to_string()]);
+        verify_attestation(&announcement, &attestation).unwrap();
+        assert!(oracle.attest("unknown", "heads").is_err());
+    }
+
+    #[test]
+    fn numeric_outcomes_are_decomposed() {
+        let oracle = MockOracle::from_seed([7u8; 32]);
+        let announcement = oracle
+            .create_event("price", numeric(true), 1_700_000_000)
+            .unwrap();
+        announcement
+            .validate(&Secp256k1::verification_only())
+            .unwrap();
+        assert_eq!(announcement.oracle_event.oracle_nonces.len(), 5);
+
+        let attestation = oracle.attest("price", "-5").unwrap();
+        assert_eq!(attestation.outcomes, ["-", "0", "1", "0", "1"]);
+        verify_attestation(&announcement, &attestation).unwrap();
+        assert!(oracle.attest("price", "five").is_err());
+
+        let descriptor = |d: EventDescriptor| match d {
+            EventDescriptor::DigitDecompositionEvent(d) => d,
+            _ => unreachable!(),
+        };
+        assert_eq!(
+            decompose(&descriptor(numeric(true)), 40),
+            ["+", "1", "1", "1", "1"]
+        );
+        assert_eq!(
+            decompose(&descriptor(numeric(false)), -3),
+            ["0", "0", "0", "0"]
+        );
+        assert_eq!(
+            decompose(&descriptor(numeric(false)), 6),
+            ["0", "1", "1", "0"]
+        );
+    }
+
+    #[test]
+    fn events_survive_a_restart_with_storage() {
+        let storage = Arc::new(MemoryStorageProvider::new());
+        let oracle = MockOracle::with_storage([3u8; 32], storage.clone());
+        let announcement = oracle
+            .create_enum_event("restart", &["up", "down"], 1_700_000_000)
+            .unwrap();
+        drop(oracle);
+
+        let restarted = MockOracle::with_storage([3u8; 32], storage.clone());
+        assert_eq!(restarted.get_announcement("restart").unwrap(), announcement);
+        assert!(restarted
+            .create_enum_event("restart", &["up"], 1_700_000_000)
+            .is_err());
+        let attestation = restarted.attest("restart", "down").unwrap();
+        verify_attestation(&announcement, &attestation).unwrap();
+        assert_eq!(restarted.attest("restart", "down").unwrap(), attestation);
+        assert!(restarted.attest("restart", "up").is_err());
+
+        let again = MockOracle::with_storage([3u8; 32], storage);
+        assert_eq!(again.get_attestation("restart").unwrap(), attestation);
+        assert!(MockOracle::from_seed([3u8; 32])
+            .get_announcement("restart")
+            .is_err());
+    }
+}
@@ -0,0 +1,492 @@
+//! Proptest strategies for contracts, contract inputs, peers and storage operations.
+//!
+//! Generated values are valid but lean on the extremes: boundary collateral, locktimes at the
+//! height/time threshold and large payout curves. Contracts are built on the stored fixtures
+//! with their economic fields replaced, so every state of the lifecycle can be generated.
+use crate::contract::locktimes::LOCKTIME_THRESHOLD;
+use crate::contract::ContractState;
+use crate::transport::PeerInformation;
+use bitcoin::key::Keypair;
+use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey, XOnlyPublicKey};
+use bitcoin::Amount;
+use dlc::{EnumerationPayout, Payout};
+use dlc_manager::contract::accepted_contract::AcceptedContract;
+use dlc_manager::contract::contract_input::{ContractInput, ContractInputInfo, OracleInput};
+use dlc_manager::contract::enum_descriptor::EnumDescriptor;
+use dlc_manager::contract::numerical_descriptor::NumericalDescriptor;
+use dlc_manager::contract::offered_contract::OfferedContract;
+use dlc_manager::contract::ser::Serializable;
+use dlc_manager::contract::signed_contract::SignedContract;
+use dlc_manager::contract::{
+    ClosedContract, Contract, ContractDescriptor, PreClosedContract,
+};
+use dlc_manager::payout_curve::{
+    PayoutFunction, PayoutFunctionPiece, PayoutPoint, PolynomialPayoutCurvePiece,
+    RoundingInterval, RoundingIntervals,
+};
+use dlc_trie::OracleNumericInfo;
+use proptest::collection::{btree_set, vec};
+use proptest::prelude::*;
+
+/// Largest number of outcomes in a generated enum contract.
+pub const MAX_ENUM_OUTCOMES: usize = 128;
+/// Largest number of points on a generated payout curve.
+pub const MAX_PAYOUT_POINTS: usize = 256;
+/// Digits of the generated numeric oracle events, in base 2.
+pub const NUMERIC_DIGITS: usize = 20;
+
+const OFFERED: &[u8] = include_bytes!("../../tests/data/dlc_storage/sled/Offered");
+const ACCEPTED: &[u8] = include_bytes!("../../tests/data/dlc_storage/sled/Accepted");
+const SIGNED: &[u8] = include_bytes!("../../tests/data/dlc_storage/sled/Signed");
+const PRE_CLOSED: &[u8] = include_bytes!("../../tests/data/dlc_storage/sled/PreClosed");
+const CLOSED: &[u8] = include_bytes!("../../tests/data/dlc_storage/sled/Closed");
+
+fn fixture<T: Serializable>(bytes: &[u8]) -> T {
+    let mut cursor = ::lightning::io::Cursor::new(bytes);
+    T::deserialize(&mut cursor).expect("fixture deserializes")
+}
+
+pub fn secret_key() -> impl Strategy<Value = SecretKey> {
+    any::<[u8; 32]>().prop_filter_map("not a valid secret key", |bytes| {
+        SecretKey::from_slice(&bytes).ok()
+    })
+}
+
+pub fn public_key() -> impl Strategy<Value = PublicKey> {
+    secret_key().prop_map(|secret_key| secret_key.public_key(&Secp256k1::signing_only()))
+}
+
+pub fn xonly_public_key() -> impl Strategy<Value = XOnlyPublicKey> {
+    secret_key().prop_map(|secret_key| {
+        Keypair::from_secret_key(&Secp256k1::signing_only(), &secret_key)
+            .x_only_public_key()
+            .0
+    })
+}
+
+/// Total collateral from one sat to the whole money supply, weighted to the bounds.
+pub fn total_collateral() -> impl Strategy<Value = u64> {
+    let max = Amount::MAX_MONEY.to_sat();
+    prop_oneof![Just(1u64), Just(max), 1..=max]
+}
+
+/// A total collateral and the offer party's share of it.
+pub fn collateral_split() -> impl Strategy<Value = (u64, u64)> {
+    total_collateral().prop_flat_map(|total| {
+        let offer = prop_oneof![Just(0u64), Just(total), 0..=total];
+        (Just(total), offer)
+    })
+}
+
+/// Locktimes on both sides of the height/time threshold.
+pub fn locktime() -> impl Strategy<Value = u32> {
+    prop_oneof![
+        Just(0u32),
+        Just(LOCKTIME_THRESHOLD - 1),
+        Just(LOCKTIME_THRESHOLD),
+        Just(u32::MAX),
+        any::<u32>(),
+    ]
+}
+
+/// Fee rates in sats per vbyte.
+pub fn fee_rate() -> impl Strategy<Value = u64> {
+    prop_oneof![Just(1u64), Just(10_000u64), 1..10_000u64]
+}
+
+pub fn peer_information() -> impl Strategy<Value = PeerInformation> {
+    let host = prop_oneof![
+        (any::<[u8; 4]>(), any::<u16>())
+            .prop_map(|(ip, port)| format!("{}.{}.{}.{}:{}", ip[0], ip[1], ip[2], ip[3], port)),
+        ("[a-z0-9-]{1,253}", any::<u16>()).prop_map(|(name, port)| format!("{}:{}", name, port)),
+    ];
+    (public_key(), host).prop_map(|(pubkey, host)| PeerInformation {
+        pubkey: pubkey.to_string(),
+        host,
+    })
+}
+
+fn oracle_input() -> impl Strategy<Value = OracleInput> {
+    (xonly_public_key(), "[a-zA-Z0-9_-]{1,64}").prop_map(|(public_key, event_id)| OracleInput {
+        public_keys: vec![public_key],
+        event_id,
+        threshold: 1,
+    })
+}
+
+/// Enum contract inputs with up to [MAX_ENUM_OUTCOMES] outcomes that each pay out the total.
+pub fn enum_contract_input() -> impl Strategy<Value = ContractInput> {
+    collateral_split().prop_flat_map(|(total, offer_collateral)| {
+        let outcomes = btree_set("[a-z0-9]{1,16}", 1..=MAX_ENUM_OUTCOMES);
+        let payouts = vec(0..=total, MAX_ENUM_OUTCOMES);
+        (outcomes, payouts, fee_rate(), oracle_input()).prop_map(
+            move |(outcomes, payouts, fee_rate, oracles)| {
+                let outcome_payouts = outcomes
+                    .into_iter()
+                    .zip(payouts)
+                    .map(|(outcome, offer)| EnumerationPayout {
+                        outcome,
+                        payout: Payout {
+                            offer,
+                            accept: total - offer,
+                        },
+                    })
+                    .collect();
+                ContractInput {
+                    offer_collateral,
+                    accept_collateral: total - offer_collateral,
+                    fee_rate,
+                    contract_infos: vec![ContractInputInfo {
+                        contract_descriptor: ContractDescriptor::Enum(EnumDescriptor {
+                            outcome_payouts,
+                        }),
+                        oracles,
+                    }],
+                }
+            },
+        )
+    })
+}
+
+/// A piecewise linear payout curve over the whole outcome range with up to
+/// [MAX_PAYOUT_POINTS] points, each paying between zero and the total collateral.
+pub fn payout_function(total_collateral: u64) -> impl Strategy<Value = PayoutFunction> {
+    let max_outcome = (1u64 << NUMERIC_DIGITS) - 1;
+    let inner = btree_set(1..max_outcome, 0..=MAX_PAYOUT_POINTS - 2);
+    (inner, vec(0..=total_collateral, MAX_PAYOUT_POINTS)).prop_map(move |(inner, payouts)| {
+        let outcomes = std::iter::once(0)
+            .chain(inner)
+            .chain(std::iter::once(max_outcome));
+        let points: Vec<PayoutPoint> = outcomes
+            .zip(payouts)
+            .map(|(event_outcome, outcome_payout)| PayoutPoint {
+                event_outcome,
+                outcome_payout,
+                extra_precision: 0,
+            })
+            .collect();
+        let pieces = points
+            .windows(2)
+            .map(|pair| {
+                PayoutFunctionPiece::PolynomialPayoutCurvePiece(
+                    PolynomialPayoutCurvePiece::new(pair.to_vec())
+                        .expect("two increasing points"),
+                )
+            })
+            .collect();
+        PayoutFunction::new(pieces).expect("continuous pieces")
+    })
+}
+
+/// Numerical contract inputs over a [NUMERIC_DIGITS] digit event.
+pub fn numerical_contract_input() -> impl Strategy<Value = ContractInput> {
+    collateral_split().prop_flat_map(|(total, offer_collateral)| {
+        (payout_function(total), fee_rate(), oracle_input()).prop_map(
+            move |(payout_function, fee_rate, oracles)| ContractInput {
+                offer_collateral,
+                accept_collateral: total - offer_collateral,
+                fee_rate,
+                contract_infos: vec![ContractInputInfo {
+                    contract_descriptor: ContractDescriptor::Numerical(NumericalDescriptor {
+                        payout_function,
+                        rounding_intervals: RoundingIntervals {
+                            intervals: vec![RoundingInterval {
+                                begin_interval: 0,
+                                rounding_mod: 1,
+                            }],
+                        },
+                        difference_params: None,
+                        oracle_numeric_infos: OracleNumericInfo {
+                            base: 2,
+                            nb_digits: vec![NUMERIC_DIGITS],
+                        },
+                    }),
+                    oracles,
+                }],
+            },
+        )
+    })
+}
+
+pub fn contract_input() -> impl Strategy<Value = ContractInput> {
+    prop_oneof![enum_contract_input(), numerical_contract_input()]
+}
+
+/// Offered contracts with random ids, counterparties, collateral, locktimes and fees.
+pub fn offered_contract() -> impl Strategy<Value = OfferedContract> {
+    (
+        any::<[u8; 32]>(),
+        any::<bool>(),
+        public_key(),
+        collateral_split(),
+        locktime(),
+        locktime(),
+        fee_rate(),
+        any::<u64>(),
+    )
+        .prop_map(
+            |(
+                id,
+                is_offer_party,
+                counter_party,
+                (total_collateral, offer_collateral),
+                cet_locktime,
+                refund_locktime,
+                fee_rate_per_vb,
+                fund_output_serial_id,
+            )| {
+                let mut offered: OfferedContract = fixture(OFFERED);
+                offered.id = id;
+                offered.is_offer_party = is_offer_party;
+                offered.counter_party = counter_party;
+                offered.total_collateral = total_collateral;
+                offered.offer_params.collateral = offer_collateral;
+                offered.cet_locktime = cet_locktime;
+                offered.refund_locktime = refund_locktime;
+                offered.fee_rate_per_vb = fee_rate_per_vb;
+                offered.fund_output_serial_id = fund_output_serial_id;
+                offered
+            },
+        )
+}
+
+/// Every state a contract passed through, oldest first.
+#[derive(Clone)]
+pub struct Lifecycle(pub Vec<Contract>);
+
+impl Lifecycle {
+    pub fn current(&self) -> &Contract {
+        self.0.last().expect("a lifecycle starts with an offer")
+    }
+}
+
+impl std::fmt::Debug for Lifecycle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.0.iter().map(|c| {
+                format!("{}({})", ContractState::from(c), hex::encode(c.get_id()))
+            }))
+            .finish()
+    }
+}
+
+/// Contracts from the offer up to a random point of the signing and closing path.
+pub fn lifecycle() -> impl Strategy<Value = Lifecycle> {
+    (offered_contract(), 0..6usize).prop_map(|(offered, steps)| {
+        let mut states = vec![Contract::Offered(offered)];
+        for _ in 0..steps {
+            match next_state(states.last().unwrap()) {
+                Some(next) => states.push(next),
+                None => break,
+            }
+        }
+        Lifecycle(states)
+    })
+}
+
+fn offered_of(contract: &Contract) -> Option<&OfferedContract> {
+    match contract {
+        Contract::Offered(o) | Contract::Rejected(o) => Some(o),
+        Contract::Accepted(a) => Some(&a.offered_contract),
+        Contract::Signed(s) | Contract::Confirmed(s) | Contract::Refunded(s) => {
+            Some(&s.accepted_contract.offered_contract)
+        }
+        Contract::PreClosed(p) => Some(&p.signed_contract.accepted_contract.offered_contract),
+        _ => None,
+    }
+}
+
+fn accepted_from(offered: &OfferedContract) -> AcceptedContract {
+    let mut accepted: AcceptedContract = fixture(ACCEPTED);
+    accepted.offered_contract = offered.clone();
+    accepted
+}
+
+fn signed_from(accepted: AcceptedContract) -> SignedContract {
+    let mut signed: SignedContract = fixture(SIGNED);
+    signed.accepted_contract = accepted;
+    signed
+}
+
+/// The contract after the next step of a successful negotiation and close. The offering
+/// party skips accepted. `None` once the contract is closed or failed.
+pub fn next_state(contract: &Contract) -> Option<Contract> {
+    let next = match contract {
+        Contract::Offered(o) if o.is_offer_party => {
+            Contract::Signed(signed_from(accepted_from(o)))
+        }
+        Contract::Offered(o) => Contract::Accepted(accepted_from(o)),
+        Contract::Accepted(a) => Contract::Signed(signed_from(a.clone())),
+        Contract::Signed(s) => Contract::Confirmed(s.clone()),
+        Contract::Confirmed(s) => {
+            let mut pre_closed: PreClosedContract = fixture(PRE_CLOSED);
+            pre_closed.signed_contract = s.clone();
+            Contract::PreClosed(pre_closed)
+        }
+        Contract::PreClosed(p) => {
+            let mut closed: ClosedContract = fixture(CLOSED);
+            closed.contract_id = p.signed_contract.accepted_contract.get_contract_id();
+            closed.temporary_contract_id = p.signed_contract.accepted_contract.offered_contract.id;
+            Contract::Closed(closed)
+        }
+        _ => return None,
+    };
+    Some(next)
+}
+
+/// Rejects an offer. `None` for any other state.
+pub fn rejected(contract: &Contract) -> Option<Contract> {
+    match contract {
+        Contract::Offered(o) => Some(Contract::Rejected(o.clone())),
+        _ => None,
+    }
+}
+
+/// The temporary id a contract was created with, in every state.
+pub fn temporary_id(contract: &Contract) -> [u8; 32] {
+    match (contract, offered_of(contract)) {
+        (_, Some(offered)) => offered.id,
+        (Contract::Closed(c), None) => c.temporary_contract_id,
+        (contract, None) => contract.get_temporary_id(),
+    }
+}
+
+/// A storage operation. Indexes refer to previously created contracts, modulo their count.
+#[derive(Debug, Clone)]
+pub enum StorageOp {
+    Create(OfferedContract),
+    Advance(usize),
+    Reject(usize),
+    SavePeer(PeerInformation),
+}
+
+pub fn storage_op() -> impl Strategy<Value = StorageOp> {
+    prop_oneof![
+        2 => offered_contract().prop_map(StorageOp::Create),
+        4 => any::<usize>().prop_map(StorageOp::Advance),
+        1 => any::<usize>().prop_map(StorageOp::Reject),
+        1 => peer_information().prop_map(StorageOp::SavePeer),
+    ]
+}
+
+/// Interleavings of up to `max_len` storage operations.
+pub fn storage_ops(max_len: usize) -> impl Strategy<Value = Vec<StorageOp>> {
+    vec(storage_op(), 1..=max_len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::validate_transition;
+    use crate::storage::SledStorageProvider;
+    use crate::util::{deserialize_contract_bytes, serialize_contract};
+    use crate::DdkStorage;
+    use dlc_manager::Storage;
+    use std::collections::{BTreeMap, HashSet};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static DB: AtomicUsize = AtomicUsize::new(0);
+
+    fn with_storage(name: &str, test: impl FnOnce(&SledStorageProvider)) {
+        let path = format!(
+            "tests/data/dlc_storage/sleddb/{}_{}",
+            name,
+            DB.fetch_add(1, Ordering::Relaxed)
+        );
+        {
+            let storage = SledStorageProvider::new(&path)
+                .unwrap()
+                .with_strict_transitions(true);
+            test(&storage);
+        }
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    /// Move a contract along `transition` if the transition applies to its state.
+    fn apply(
+        storage: &SledStorageProvider,
+        contracts: &mut [Contract],
+        i: usize,
+        transition: fn(&Contract) -> Option<Contract>,
+    ) {
+        if contracts.is_empty() {
+            return;
+        }
+        let i = i % contracts.len();
+        if let Some(next) = transition(&contracts[i]) {
+            assert!(validate_transition(&contracts[i], &next).is_ok());
+            storage.update_contract(&next).unwrap();
+            contracts[i] = next;
+        }
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn contracts_round_trip(lifecycle in lifecycle()) {
+            let states = &lifecycle.0;
+            let contract = lifecycle.current();
+            let serialized = serialize_contract(contract).unwrap();
+            let deserialized = deserialize_contract_bytes(&serialized).unwrap();
+            prop_assert_eq!(&serialized, &serialize_contract(&deserialized).unwrap());
+
+            with_storage("round_trip", |storage| {
+                let Contract::Offered(offered) = &states[0] else { unreachable!() };
+                storage.create_contract(offered).unwrap();
+                for state in &states[1..] {
+                    storage.update_contract(state).unwrap();
+                }
+                let stored = storage.get_contract(&contract.get_id()).unwrap().unwrap();
+                assert_eq!(serialized, serialize_contract(&stored).unwrap());
+            });
+        }
+
+        #[test]
+        fn contract_inputs_round_trip(input in contract_input()) {
+            let json = serde_json::to_value(&input).unwrap();
+            let parsed: ContractInput = serde_json::from_value(json.clone()).unwrap();
+            prop_assert_eq!(json, serde_json::to_value(&parsed).unwrap());
+            prop_assert!(input.offer_collateral + input.accept_collateral <= Amount::MAX_MONEY.to_sat());
+        }
+
+        #[test]
+        fn legal_operations_keep_storage_consistent(ops in storage_ops(24)) {
+            with_storage("operations", |storage| {
+                let mut contracts: Vec<Contract> = vec![];
+                let mut peers = BTreeMap::new();
+                for op in ops {
+                    match op {
+                        StorageOp::Create(mut offered) => {
+                            // Unique temporary ids, a real node never reuses one.
+                            offered.id = [contracts.len() as u8 + 1; 32];
+                            storage.create_contract(&offered).unwrap();
+                            contracts.push(Contract::Offered(offered));
+                        }
+                        StorageOp::Advance(i) => apply(storage, &mut contracts, i, next_state),
+                        StorageOp::Reject(i) => apply(storage, &mut contracts, i, rejected),
+                        StorageOp::SavePeer(peer) => {
+                            storage.save_peer(peer.clone()).unwrap();
+                            peers.insert(peer.pubkey.clone(), peer);
+                        }
+                    }
+
+                    // One stored entry per contract, under its current id.
+                    let stored = storage.get_contracts().unwrap();
+                    assert_eq!(stored.len(), contracts.len());
+                    let stored_ids: HashSet<_> = stored.iter().map(temporary_id).collect();
+                    let expected_ids: HashSet<_> = contracts.iter().map(temporary_id).collect();
+                    assert_eq!(stored_ids, expected_ids);
+                    for contract in &contracts {
+                        assert!(storage.get_contract(&contract.get_id()).unwrap().is_some());
+                    }
+                }
+                // Peers are keyed by pubkey and listed in pubkey order.
+                assert_eq!(
+                    storage.list_peers().unwrap(),
+                    peers.into_values().collect::<Vec<_>>()
+                );
+            });
+        }
+    }
+}
@@ -0,0 +1,312 @@
+//! Admit, journal, and acknowledge the messages peers send. Received messages are journaled
+//! before the manager processes them and acknowledged once it did, so a restart replays what
+//! was received but never processed.
+use super::rate_limit::{PeerBan, PeerRateLimiter};
+use super::{message_id, message_kind, unix_now, MAX_INBOUND_ATTEMPTS, MAX_PENDING_INBOUND};
+use crate::audit::{self, AuditEntry, AuditEventType};
+use crate::dispatch::RecentMessages;
+use crate::events::{DdkEvent, EventBus};
+use crate::{DdkStorage, DdkTransport};
+use bitcoin::secp256k1::PublicKey;
+use dlc_messages::Message;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Whether the manager failed for a reason that can pass, like an unreachable chain backend,
+/// oracle, or store, so processing the message again may succeed.
+pub(crate) fn is_transient_error(e: &dlc_manager::error::Error) -> bool {
+    use dlc_manager::error::Error;
+    matches!(
+        e,
+        Error::IOError(_)
+            | Error::StorageError(_)
+            | Error::BlockchainError(_)
+            | Error::WalletError(_)
+            | Error::OracleError(_)
+    )
+}
+
+/// Drop messages from banned peers and ban peers that exceed the rate limit or send more
+/// offers than may wait for an answer. Runs before journaling, so dropped messages are
+/// never stored.
+pub(crate) fn limit_inbound<S: DdkStorage>(
+    storage: &S,
+    limiter: &mut PeerRateLimiter,
+    events: &EventBus,
+    messages: Vec<(PublicKey, Message)>,
+) -> Vec<(PublicKey, Message)> {
+    if messages.is_empty() {
+        return messages;
+    }
+    let now = unix_now().as_secs();
+    let mut bans = match storage.list_peer_bans() {
+        Ok(bans) => bans,
+        Err(e) => {
+            tracing::error!(error = e.to_string(), "Could not list peer bans.");
+            Vec::new()
+        }
+    };
+    let stored_bans = bans.len();
+    bans.retain(|ban| ban.is_active(now));
+    let mut changed = bans.len() != stored_bans;
+
+    let limits = *limiter.limits();
+    let started = Instant::now();
+    let mut pending_offers: HashMap<PublicKey, usize> = HashMap::new();
+    let mut admitted = Vec::with_capacity(messages.len());
+    for (counter_party, message) in messages {
+        if bans.iter().any(|ban| ban.pubkey == counter_party) {
+            tracing::warn!(
+                counter_party = counter_party.to_string(),
+                kind = message_kind(&message),
+                "Dropping message from banned peer."
+            );
+            continue;
+        }
+        let violation = if !limiter.allow(&counter_party, started) {
+            Some(format!(
+                "More than {} messages per second.",
+                limits.messages_per_second
+            ))
+        } else if let Message::Offer(_) = &message {
+            let pending = pending_offers
+                .entry(counter_party)
+                .or_insert_with(|| pending_offers_from(storage, &counter_party));
+            *pending += 1;
+            (*pending > limits.max_pending_offers)
+                .then(|| format!("More than {} unanswered offers.", limits.max_pending_offers))
+        } else {
+            None
+        };
+        let Some(reason) = violation else {
+            admitted.push((counter_party, message));
+            continue;
+        };
+
+        let until = now + limits.ban_duration.as_secs();
+        tracing::warn!(
+            counter_party = counter_party.to_string(),
+            kind = message_kind(&message),
+            until,
+            reason,
+            "Banning peer that exceeded its limits."
+        );
+        limiter.forget(&counter_party);
+        bans.push(PeerBan {
+            pubkey: counter_party,
+            until,
+            reason: reason.clone(),
+        });
+        changed = true;
+        events.emit(DdkEvent::PeerBanned {
+            pubkey: counter_party,
+            until,
+            reason,
+        });
+    }
+
+    if changed {
+        if let Err(e) = storage.save_peer_bans(&bans) {
+            tracing::error!(error = e.to_string(), "Could not save peer bans.");
+        }
+    }
+    admitted
+}
+
+/// Offers received from `counter_party` that were not accepted or rejected yet.
+fn pending_offers_from<S: DdkStorage>(storage: &S, counter_party: &PublicKey) -> usize {
+    match storage.get_contract_offers() {
+        Ok(offers) => offers
+            .iter()
+            .filter(|offer| !offer.is_offer_party && offer.counter_party == *counter_party)
+            .count(),
+        Err(e) => {
+            tracing::error!(error = e.to_string(), "Could not count pending offers.");
+            0
+        }
+    }
+}
+
+/// Journal received messages before they are processed. Messages that were already
+/// processed, or repeated in the batch, are dropped.
+pub(crate) fn journal_inbound<S: DdkStorage>(
+    storage: &S,
+    recent: &Mutex<RecentMessages>,
+    messages: Vec<(PublicKey, Message)>,
+) -> Vec<(PublicKey, Message)> {
+    let mut seen = HashSet::new();
+    let mut pending = match storage.pending_inbound_count() {
+        Ok(count) => count,
+        Err(e) => {
+            tracing::error!(
+                error = e.to_string(),
+                "Could not count pending inbound messages."
+            );
+            0
+        }
+    };
+    messages
+        .into_iter()
+        .filter(|(counter_party, message)| {
+            let id = message_id(message);
+            if recent.lock().unwrap().seen(counter_party, &id) {
+                tracing::debug!(
+                    counter_party = counter_party.to_string(),
+                    kind = message_kind(message),
+                    "Dropping duplicate message."
+                );
+                return false;
+            }
+            if !seen.insert(id) {
+                return false;
+            }
+            if pending >= MAX_PENDING_INBOUND {
+                tracing::warn!(
+                    counter_party = counter_party.to_string(),
+                    kind = message_kind(message),
+                    pending,
+                    "Inbound journal is full. Dropping message."
+                );
+                return false;
+            }
+            match storage.queue_inbound_message(*counter_party, message) {
+                Ok(true) => {
+                    pending += 1;
+                    audit::append(
+                        storage,
+                        AuditEntry::message(
+                            AuditEventType::MessageReceived,
+                            *counter_party,
+                            message,
+                        ),
+                    );
+                    true
+                }
+                Ok(false) => {
+                    tracing::info!(
+                        counter_party = counter_party.to_string(),
+                        kind = message_kind(message),
+                        "Skipping message that was already processed."
+                    );
+                    false
+                }
+                Err(e) => {
+                    tracing::error!(error = e.to_string(), "Could not journal inbound message.");
+                    true
+                }
+            }
+        })
+        .collect()
+}
+
+/// Persist how far the transport received, once what it received is journaled.
+pub(crate) fn save_inbound_cursor<S: DdkStorage, T: DdkTransport>(storage: &S, transport: &T) {
+    let Some(cursor) = transport.inbound_cursor() else {
+        return;
+    };
+    if let Err(e) = storage.save_transport_cursor(&transport.name(), cursor) {
+        tracing::error!(error = e.to_string(), "Could not save transport cursor.");
+    }
+}
+
+/// Record that the manager failed on an inbound message. Messages that failed for a
+/// reason that can pass stay pending and are replayed on the next start, until
+/// [MAX_INBOUND_ATTEMPTS]. Other failures, and messages out of attempts, are
+/// dead-lettered: acknowledged so they are never replayed, and kept in the audit log with
+/// the error.
+pub(crate) fn fail_inbound<S: DdkStorage>(
+    storage: &S,
+    recent: &Mutex<RecentMessages>,
+    counter_party: PublicKey,
+    message: &Message,
+    error: &dlc_manager::error::Error,
+) {
+    let attempts = if is_transient_error(error) {
+        match storage.record_inbound_failure(&message_id(message)) {
+            Ok(attempts) if attempts < MAX_INBOUND_ATTEMPTS => {
+                tracing::warn!(
+                    counter_party = counter_party.to_string(),
+                    kind = message_kind(message),
+                    error = error.to_string(),
+                    attempts,
+                    "Could not process message. Retrying on the next start."
+                );
+                return;
+            }
+            Ok(attempts) => attempts,
+            Err(e) => {
+                tracing::error!(
+                    error = e.to_string(),
+                    "Could not record failed inbound message."
+                );
+                return;
+            }
+        }
+    } else {
+        1
+    };
+    tracing::error!(
+        counter_party = counter_party.to_string(),
+        kind = message_kind(message),
+        error = error.to_string(),
+        attempts,
+        "Could not process message. Dead-lettering it."
+    );
+    audit::append(
+        storage,
+        AuditEntry::dead_letter(counter_party, message, &error.to_string(), attempts),
+    );
+    ack_inbound(storage, recent, counter_party, message);
+}
+
+/// Journaled messages that were never acknowledged.
+pub(crate) fn pending_inbound<S: DdkStorage>(storage: &S) -> Vec<(PublicKey, Message)> {
+    let pending = match storage.pending_inbound_messages() {
+        Ok(pending) => pending,
+        Err(e) => {
+            tracing::error!(
+                error = e.to_string(),
+                "Could not list pending inbound messages."
+            );
+            return vec![];
+        }
+    };
+    pending
+        .into_iter()
+        .filter_map(|inbound| match inbound.message() {
+            Ok(message) => {
+                tracing::info!(
+                    kind = inbound.kind,
+                    counter_party = inbound.counterparty.to_string(),
+                    "Replaying pending inbound message."
+                );
+                Some((inbound.counterparty, message))
+            }
+            Err(e) => {
+                tracing::error!(
+                    error = e.to_string(),
+                    "Could not decode pending inbound message."
+                );
+                None
+            }
+        })
+        .collect()
+}
+
+/// Mark an inbound message as processed. See [fail_inbound] for messages the manager fails on.
+pub(crate) fn ack_inbound<S: DdkStorage>(
+    storage: &S,
+    recent: &Mutex<RecentMessages>,
+    counter_party: PublicKey,
+    message: &Message,
+) {
+    let id = message_id(message);
+    if let Err(e) = storage.ack_inbound_message(&id) {
+        tracing::error!(
+            error = e.to_string(),
+            "Could not acknowledge inbound message."
+        );
+    }
+    recent.lock().unwrap().insert(counter_party, id);
+}
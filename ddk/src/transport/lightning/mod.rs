@@ -1,15 +1,77 @@
 use std::sync::Arc;
 
-use crate::DdkTransport;
+use crate::transport::PeerInformation;
+use crate::{DdkTransport, TransportError};
+use anyhow::anyhow;
 use async_trait::async_trait;
 use bitcoin::secp256k1::PublicKey;
 use dlc_messages::Message;
 use lightning_net_tokio::{connect_outbound, setup_inbound};
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::time::Duration;
 
 pub(crate) mod peer_manager;
 pub use peer_manager::LightningTransport;
 use tokio::net::TcpListener;
 
+/// How often peers are pinged. LDK disconnects peers that do not answer by the next tick.
+pub const PING_INTERVAL: Duration = Duration::from_secs(10);
+
+impl LightningTransport {
+    /// Accept peers on an already bound listener and keep connections alive with pings.
+    /// Runs until the listener fails.
+    pub async fn serve(&self, listener: TcpListener) {
+        let peer_manager = self.ln_peer_manager();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(PING_INTERVAL);
+            loop {
+                interval.tick().await;
+                peer_manager.timer_tick_occurred();
+            }
+        });
+
+        loop {
+            let (tcp_stream, socket) = match listener.accept().await {
+                Ok(connection) => connection,
+                Err(e) => {
+                    tracing::warn!(error = e.to_string(), "Could not accept connection.");
+                    continue;
+                }
+            };
+            let tcp_stream = match tcp_stream.into_std() {
+                Ok(tcp_stream) => tcp_stream,
+                Err(e) => {
+                    tracing::warn!(error = e.to_string(), "Could not accept connection.");
+                    continue;
+                }
+            };
+            let peer_mgr = self.ln_peer_manager();
+            tokio::spawn(async move {
+                tracing::info!(connection = socket.to_string(), "Received connection.");
+                setup_inbound(peer_mgr, tcp_stream).await;
+            });
+        }
+    }
+
+    async fn connect_to(
+        &self,
+        pubkey: PublicKey,
+        addresses: Vec<SocketAddr>,
+    ) -> anyhow::Result<()> {
+        for address in addresses {
+            if connect_outbound(self.ln_peer_manager(), pubkey, address)
+                .await
+                .is_some()
+            {
+                return Ok(());
+            }
+            tracing::debug!(%pubkey, %address, "Could not connect to address.");
+        }
+        Err(anyhow!("Could not connect to {}.", pubkey))
+    }
+}
+
 #[async_trait]
 impl DdkTransport for LightningTransport {
     type PeerManager = Arc<super::lightning::peer_manager::LnPeerManager>;
@@ -20,20 +82,18 @@ impl DdkTransport for LightningTransport {
     }
 
     async fn listen(&self) {
-        let peer_manager_connection_handler = self.peer_manager();
-
-        let listener = TcpListener::bind(format!("0.0.0.0:{}", self.listening_port))
-            .await
-            .expect("Coldn't get port.");
-
-        loop {
-            let peer_mgr = peer_manager_connection_handler.clone();
-            let (tcp_stream, socket) = listener.accept().await.unwrap();
-            tokio::spawn(async move {
-                tracing::info!(connection = socket.to_string(), "Received connection.");
-                setup_inbound(peer_mgr.clone(), tcp_stream.into_std().unwrap()).await;
-            });
-        }
+        let listener = match TcpListener::bind(format!("0.0.0.0:{}", self.listening_port)).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                tracing::error!(
+                    port = self.listening_port,
+                    error = e.to_string(),
+                    "Could not listen for lightning peers."
+                );
+                return;
+            }
+        };
+        self.serve(listener).await
     }
 
     fn message_handler(&self) -> Self::MessageHandler {
@@ -45,12 +105,39 @@ impl DdkTransport for LightningTransport {
     }
 
     fn process_messages(&self) {
-        tracing::info!("Processing lightning messages.");
+        tracing::trace!("Processing lightning messages.");
         self.ln_peer_manager().process_events()
     }
 
-    fn send_message(&self, counterparty: PublicKey, message: dlc_messages::Message) {
-        self.message_handler().send_message(counterparty, message)
+    /// Queues the message with the DLC message handler and flushes it to the peer. Fails when
+    /// the peer is not connected instead of leaving the message with the handler.
+    async fn send_message(
+        &self,
+        counterparty: PublicKey,
+        message: Message,
+    ) -> Result<(), TransportError> {
+        if !self.is_connected(&counterparty) {
+            return Err(TransportError(anyhow!(
+                "Peer {} is not connected.",
+                counterparty
+            )));
+        }
+        self.queue_message(counterparty, message);
+        Ok(())
+    }
+
+    fn connected_peers(&self) -> Vec<PublicKey> {
+        self.ln_peer_manager()
+            .list_peers()
+            .iter()
+            .map(|peer| peer.counterparty_node_id)
+            .collect()
+    }
+
+    /// Queues the message with the DLC message handler and flushes it to the peer.
+    fn queue_message(&self, counterparty: PublicKey, message: dlc_messages::Message) {
+        self.message_handler().send_message(counterparty, message);
+        self.ln_peer_manager().process_events()
     }
 
     fn get_and_clear_received_messages(&self) -> Vec<(PublicKey, Message)> {
@@ -62,6 +149,94 @@ impl DdkTransport for LightningTransport {
     }
 
     async fn connect_outbound(&self, pubkey: PublicKey, host: &str) {
-        connect_outbound(self.peer_manager(), pubkey, host.parse().unwrap()).await;
+        let peer = PeerInformation {
+            pubkey: pubkey.to_string(),
+            host: host.to_string(),
+        };
+        let connected = match peer.addresses() {
+            Ok(addresses) => self.connect_to(pubkey, addresses).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = connected {
+            tracing::warn!(%peer, error = e.to_string(), "Could not connect to peer.");
+        }
+    }
+
+    async fn connect(&self, peer: PeerInformation) -> anyhow::Result<()> {
+        let pubkey = PublicKey::from_str(&peer.pubkey)?;
+        self.connect_to(pubkey, peer.addresses()?).await
+    }
+
+    fn is_connected(&self, counterparty: &PublicKey) -> bool {
+        self.ln_peer_manager()
+            .list_peers()
+            .iter()
+            .any(|peer| peer.counterparty_node_id == *counterparty)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SeedConfig;
+    use bitcoin::Network;
+    use dlc_messages::OfferDlc;
+    use std::time::Instant;
+
+    fn transport(seed: u8) -> Arc<LightningTransport> {
+        Arc::new(
+            LightningTransport::new(&SeedConfig::Bytes([seed; 64]), 0, Network::Regtest).unwrap(),
+        )
+    }
+
+    async fn eventually<F: Fn() -> bool>(what: &str, condition: F) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !condition() {
+            if Instant::now() > deadline {
+                panic!("Timed out waiting for {what}.");
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn two_nodes_exchange_an_offer() {
+        let alice = transport(1);
+        let bob = transport(2);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let peer = PeerInformation::from_str(&format!(
+            "{}@{}",
+            bob.node_id,
+            listener.local_addr().unwrap()
+        ))
+        .unwrap();
+        let server = bob.clone();
+        tokio::spawn(async move { server.serve(listener).await });
+
+        alice.connect(peer).await.unwrap();
+        eventually("the handshake", || {
+            alice.is_connected(&bob.node_id) && bob.is_connected(&alice.node_id)
+        })
+        .await;
+
+        let offer: OfferDlc =
+            serde_json::from_str(include_str!("../../../tests/data/dlc/offer.json")).unwrap();
+        alice.queue_message(bob.node_id, Message::Offer(offer.clone()));
+
+        let received = std::sync::Mutex::new(Vec::new());
+        eventually("the offer", || {
+            bob.process_messages();
+            let mut received = received.lock().unwrap();
+            received.extend(bob.get_and_clear_received_messages());
+            !received.is_empty()
+        })
+        .await;
+        let received = received.into_inner().unwrap();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].0, alice.node_id);
+        match &received[0].1 {
+            Message::Offer(received) => assert_eq!(received, &offer),
+            _ => panic!("Received the wrong message type."),
+        }
     }
 }
@@ -0,0 +1,997 @@
+//! [DdkTransport] linking two nodes in the same process, for integration tests without a
+//! network. Create both ends with [MemoryTransport::pair].
+//!
+//! Queued messages wait in an outbound queue until [DdkTransport::process_messages] pushes them
+//! into the peer's receive queue, like the other transports hand them to a connection.
+//! [DdkTransport::send_message] pushes right away.
+//! [LinkFaults] adds latency, drops, and reordering on the way.
+use crate::{DdkTransport, TransportError};
+use async_trait::async_trait;
+use bitcoin::key::rand::{thread_rng, Rng};
+use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+use dlc_messages::Message;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Faults applied to messages on their way to the peer.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LinkFaults {
+    /// Time before the peer receives a message.
+    pub latency: Duration,
+    /// Probability, from 0 to 1, that a message is lost.
+    pub drop_probability: f64,
+    /// Probability, from 0 to 1, that a message is delivered twice.
+    pub duplicate_probability: f64,
+    /// Deliver messages in a random order.
+    pub reorder: bool,
+}
+
+struct Delivery {
+    from: PublicKey,
+    message: Message,
+    deliver_at: Instant,
+}
+
+type Inbox = Arc<Mutex<VecDeque<Delivery>>>;
+
+/// One end of an in-memory link. Messages can only be sent to the other end.
+pub struct MemoryTransport {
+    public_key: PublicKey,
+    peer: PublicKey,
+    outbound: Mutex<VecDeque<Message>>,
+    inbox: Inbox,
+    peer_inbox: Inbox,
+    faults: Mutex<LinkFaults>,
+}
+
+impl MemoryTransport {
+    /// Two linked transports with random node ids.
+    pub fn pair() -> (MemoryTransport, MemoryTransport) {
+        let secp = Secp256k1::signing_only();
+        let key = || PublicKey::from_secret_key(&secp, &SecretKey::new(&mut thread_rng()));
+        MemoryTransport::pair_with_keys(key(), key())
+    }
+
+    /// Two linked transports identified by `first` and `second`.
+    pub fn pair_with_keys(
+        first: PublicKey,
+        second: PublicKey,
+    ) -> (MemoryTransport, MemoryTransport) {
+        let first_inbox = Inbox::default();
+        let second_inbox = Inbox::default();
+        let end = |public_key, peer, inbox: &Inbox, peer_inbox: &Inbox| MemoryTransport {
+            public_key,
+            peer,
+            outbound: Mutex::new(VecDeque::new()),
+            inbox: inbox.clone(),
+            peer_inbox: peer_inbox.clone(),
+            faults: Mutex::new(LinkFaults::default()),
+        };
+        (
+            end(first, second, &first_inbox, &second_inbox),
+            end(second, first, &second_inbox, &first_inbox),
+        )
+    }
+
+    /// Node id the peer receives our messages from.
+    pub fn public_key(&self) -> PublicKey {
+        self.public_key
+    }
+
+    /// Node id of the other end.
+    pub fn peer(&self) -> PublicKey {
+        self.peer
+    }
+
+    /// Faults for messages sent from this end. The other end keeps its own.
+    pub fn set_faults(&self, faults: LinkFaults) {
+        *self.faults.lock().unwrap() = faults;
+    }
+}
+
+#[async_trait]
+impl DdkTransport for MemoryTransport {
+    type PeerManager = ();
+    type MessageHandler = ();
+
+    fn name(&self) -> String {
+        "memory".into()
+    }
+
+    /// Nothing to listen on. Messages are exchanged in [DdkTransport::process_messages].
+    async fn listen(&self) {}
+
+    fn message_handler(&self) -> Self::MessageHandler {}
+
+    fn peer_manager(&self) -> Self::PeerManager {}
+
+    /// Push queued messages into the peer's receive queue, applying the link faults.
+    fn process_messages(&self) {
+        let faults = *self.faults.lock().unwrap();
+        let outbound = std::mem::take(&mut *self.outbound.lock().unwrap());
+        let mut peer_inbox = self.peer_inbox.lock().unwrap();
+        let mut rng = thread_rng();
+        for message in outbound {
+            if faults.drop_probability > 0.0 && rng.gen_bool(faults.drop_probability.min(1.0)) {
+                tracing::debug!(
+                    peer = self.peer.to_string(),
+                    "Dropped message on memory link."
+                );
+                continue;
+            }
+            let copies = if faults.duplicate_probability > 0.0
+                && rng.gen_bool(faults.duplicate_probability.min(1.0))
+            {
+                2
+            } else {
+                1
+            };
+            for _ in 0..copies {
+                let delivery = Delivery {
+                    from: self.public_key,
+                    message: message.clone(),
+                    deliver_at: Instant::now() + faults.latency,
+                };
+                if faults.reorder {
+                    let position = rng.gen_range(0..=peer_inbox.len());
+                    peer_inbox.insert(position, delivery);
+                } else {
+                    peer_inbox.push_back(delivery);
+                }
+            }
+        }
+    }
+
+    /// Queue the message and push it to the peer right away. Fails for anyone but the peer.
+    async fn send_message(
+        &self,
+        counterparty: PublicKey,
+        message: Message,
+    ) -> Result<(), TransportError> {
+        if counterparty != self.peer {
+            return Err(TransportError(anyhow::anyhow!(
+                "Memory transport can only reach its peer."
+            )));
+        }
+        self.queue_message(counterparty, message);
+        self.process_messages();
+        Ok(())
+    }
+
+    /// The other end, always connected.
+    fn connected_peers(&self) -> Vec<PublicKey> {
+        vec![self.peer]
+    }
+
+    fn queue_message(&self, counterparty: PublicKey, message: Message) {
+        if counterparty != self.peer {
+            tracing::warn!(
+                counterparty = counterparty.to_string(),
+                "Memory transport can only reach its peer. Dropping message."
+            );
+            return;
+        }
+        self.outbound.lock().unwrap().push_back(message);
+    }
+
+    /// Messages whose latency has passed.
+    fn get_and_clear_received_messages(&self) -> Vec<(PublicKey, Message)> {
+        let now = Instant::now();
+        let mut inbox = self.inbox.lock().unwrap();
+        let (ready, waiting): (Vec<_>, Vec<_>) = inbox
+            .drain(..)
+            .partition(|delivery| delivery.deliver_at <= now);
+        *inbox = waiting.into();
+        ready
+            .into_iter()
+            .map(|delivery| (delivery.from, delivery.message))
+            .collect()
+    }
+
+    /// True while messages are queued and not yet pushed to the peer.
+    fn has_pending_messages(&self) -> bool {
+        !self.outbound.lock().unwrap().is_empty()
+    }
+
+    async fn connect_outbound(&self, _pubkey: PublicKey, _host: &str) {}
+
+    fn is_connected(&self, counterparty: &PublicKey) -> bool {
+        *counterparty == self.peer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::harness::{contract_input, fund, fund_account, is_signed, wait_for, TwoNodes};
+    use super::*;
+    use crate::chain::watch::ChainWatchOptions;
+    use crate::config::DdkConfig;
+    use crate::contract::confirmations::ConfirmationPolicy;
+    use crate::events::DdkEvent;
+    use crate::transport::rate_limit::PeerLimits;
+    use crate::transport::retry::OutboundRetryOptions;
+    use crate::wallet::Account;
+    use bitcoin::{Amount, Transaction};
+    use dlc_manager::contract::Contract;
+    use dlc_manager::Storage;
+    use dlc_messages::OfferDlc;
+
+    fn offer(id: u8) -> Message {
+        let mut offer: OfferDlc =
+            serde_json::from_str(include_str!("../../tests/data/dlc/offer.json")).unwrap();
+        offer.temporary_contract_id = [id; 32];
+        Message::Offer(offer)
+    }
+
+    fn offer_id(message: &Message) -> u8 {
+        match message {
+            Message::Offer(offer) => offer.temporary_contract_id[0],
+            _ => panic!("Received the wrong message type."),
+        }
+    }
+
+    #[test]
+    fn messages_reach_the_peer_once_processed() {
+        let (alice, bob) = MemoryTransport::pair();
+        alice.queue_message(bob.public_key(), offer(1));
+        assert!(alice.has_pending_messages());
+        assert!(bob.get_and_clear_received_messages().is_empty());
+
+        alice.process_messages();
+        assert!(!alice.has_pending_messages());
+        let received = bob.get_and_clear_received_messages();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].0, alice.public_key());
+        assert_eq!(offer_id(&received[0].1), 1);
+        assert!(bob.get_and_clear_received_messages().is_empty());
+        assert!(alice.get_and_clear_received_messages().is_empty());
+    }
+
+    #[test]
+    fn messages_to_other_nodes_are_not_sent() {
+        let (alice, bob) = MemoryTransport::pair();
+        alice.queue_message(alice.public_key(), offer(1));
+        assert!(!alice.has_pending_messages());
+        assert!(!alice.is_connected(&alice.public_key()));
+
+        let sent = futures::executor::block_on(alice.send_message(alice.public_key(), offer(2)));
+        assert!(sent.is_err());
+        futures::executor::block_on(alice.send_message(bob.public_key(), offer(3))).unwrap();
+        assert_eq!(offer_id(&bob.get_and_clear_received_messages()[0].1), 3);
+    }
+
+    #[test]
+    fn latency_delays_delivery() {
+        let (alice, bob) = MemoryTransport::pair();
+        alice.set_faults(LinkFaults {
+            latency: Duration::from_millis(50),
+            ..Default::default()
+        });
+        alice.queue_message(bob.public_key(), offer(1));
+        alice.process_messages();
+        assert!(bob.get_and_clear_received_messages().is_empty());
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(bob.get_and_clear_received_messages().len(), 1);
+    }
+
+    #[test]
+    fn dropped_messages_never_arrive() {
+        let (alice, bob) = MemoryTransport::pair();
+        alice.set_faults(LinkFaults {
+            drop_probability: 1.0,
+            ..Default::default()
+        });
+        alice.queue_message(bob.public_key(), offer(1));
+        alice.process_messages();
+        assert!(!alice.has_pending_messages());
+        assert!(bob.get_and_clear_received_messages().is_empty());
+    }
+
+    #[test]
+    fn duplicated_messages_arrive_twice() {
+        let (alice, bob) = MemoryTransport::pair();
+        alice.set_faults(LinkFaults {
+            duplicate_probability: 1.0,
+            ..Default::default()
+        });
+        alice.queue_message(bob.public_key(), offer(1));
+        alice.process_messages();
+        let received = bob.get_and_clear_received_messages();
+        assert_eq!(received.len(), 2);
+        assert!(received.iter().all(|(_, message)| offer_id(message) == 1));
+    }
+
+    #[test]
+    fn reordered_messages_all_arrive() {
+        let (alice, bob) = MemoryTransport::pair();
+        alice.set_faults(LinkFaults {
+            reorder: true,
+            ..Default::default()
+        });
+        for id in 0..20 {
+            alice.queue_message(bob.public_key(), offer(id));
+        }
+        alice.process_messages();
+
+        let mut received = bob
+            .get_and_clear_received_messages()
+            .iter()
+            .map(|(_, message)| offer_id(message))
+            .collect::<Vec<_>>();
+        assert_ne!(received, (0..20).collect::<Vec<_>>());
+        received.sort();
+        assert_eq!(received, (0..20).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn duplicated_accept_signs_once() {
+        let nodes = TwoNodes::new("memory_duplicate");
+        // Every message from Bob, including the accept, reaches Alice twice.
+        nodes.bob.transport().set_faults(LinkFaults {
+            duplicate_probability: 1.0,
+            ..Default::default()
+        });
+        let events = nodes.alice.subscribe();
+        nodes.start();
+        let contract_id = nodes.negotiate();
+
+        assert_eq!(
+            nodes.alice.storage().get_signed_contracts().unwrap().len(),
+            1
+        );
+        let signed_events = events
+            .try_iter()
+            .filter(|event| matches!(event, DdkEvent::ContractSigned(id) if *id == contract_id))
+            .count();
+        assert_eq!(signed_events, 1);
+
+        // Alice keeps processing messages after the duplicate.
+        fund(&nodes.alice, &nodes.blockchain, 200_000);
+        fund(&nodes.bob, &nodes.blockchain, 200_000);
+        let second = nodes.negotiate();
+        assert_ne!(second, contract_id);
+        assert_eq!(
+            nodes.alice.storage().get_signed_contracts().unwrap().len(),
+            2
+        );
+    }
+
+    #[test]
+    fn counterparty_cet_moves_the_contract_to_pre_closed() {
+        use dlc_manager::Blockchain;
+        let nodes = TwoNodes::with_config(
+            "memory_counterparty_cet",
+            DdkConfig {
+                chain_watch: ChainWatchOptions {
+                    interval: Duration::from_millis(100),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        );
+        let events = nodes.alice.subscribe();
+        nodes.start();
+        let contract_id = nodes.negotiate();
+
+        // The manager waits for six confirmations, so mark the contract confirmed directly.
+        let Ok(Some(Contract::Signed(signed))) = nodes.alice.storage().get_contract(&contract_id)
+        else {
+            panic!("Contract is not signed.");
+        };
+        nodes
+            .alice
+            .storage()
+            .update_contract(&Contract::Confirmed(signed.clone()))
+            .unwrap();
+
+        // Bob closes with a CET without telling Alice.
+        let cet = signed.accepted_contract.dlc_transactions.cets[0].clone();
+        let txid = cet.compute_txid();
+        nodes.blockchain.send_transaction(&cet).unwrap();
+
+        wait_for("the contract to be pre-closed", || {
+            matches!(
+                nodes.alice.storage().get_contract(&contract_id),
+                Ok(Some(Contract::PreClosed(pre_closed))) if pre_closed.signed_cet.compute_txid() == txid
+            )
+        });
+        wait_for("the CET event", || {
+            events
+                .try_iter()
+                .any(|event| event == DdkEvent::CetSeen { contract_id, txid })
+        });
+    }
+
+    #[test]
+    fn reorged_funding_moves_the_contract_back() {
+        let nodes = TwoNodes::with_config(
+            "memory_funding_reorg",
+            DdkConfig {
+                confirmation_policy: ConfirmationPolicy::new(2),
+                ..Default::default()
+            },
+        );
+        let events = nodes.alice.subscribe();
+        nodes.start();
+        let contract_id = nodes.negotiate();
+        let Ok(Some(Contract::Signed(signed))) = nodes.alice.storage().get_contract(&contract_id)
+        else {
+            panic!("Contract is not signed.");
+        };
+        let txid = signed
+            .accepted_contract
+            .dlc_transactions
+            .fund
+            .compute_txid();
+        let confirmations = || {
+            let summary = nodes
+                .alice
+                .list_contracts()
+                .unwrap()
+                .into_iter()
+                .find(|summary| summary.id == hex::encode(contract_id))
+                .unwrap();
+            (summary.confirmations, summary.required_confirmations)
+        };
+        let is_confirmed = || {
+            matches!(
+                nodes.alice.storage().get_contract(&contract_id),
+                Ok(Some(Contract::Confirmed(_)))
+            )
+        };
+
+        nodes.blockchain.mine(1);
+        nodes.alice.force_check().unwrap();
+        assert!(is_signed(&nodes.alice, &contract_id));
+        assert_eq!(confirmations(), (Some(1), Some(2)));
+
+        nodes.blockchain.mine(1);
+        nodes.alice.force_check().unwrap();
+        assert!(is_confirmed());
+
+        nodes.blockchain.reorg(2);
+        nodes.alice.force_check().unwrap();
+        assert!(is_signed(&nodes.alice, &contract_id));
+        assert_eq!(confirmations(), (Some(0), Some(2)));
+        wait_for("the reorg event", || {
+            events
+                .try_iter()
+                .any(|event| event == DdkEvent::FundingReorged { contract_id, txid })
+        });
+
+        nodes.blockchain.mine(2);
+        nodes.alice.force_check().unwrap();
+        assert!(is_confirmed());
+    }
+
+    #[test]
+    fn policy_holds_contracts_the_manager_would_confirm() {
+        let nodes = TwoNodes::with_config(
+            "memory_confirmation_policy",
+            DdkConfig {
+                confirmation_policy: ConfirmationPolicy::new(1).with_threshold(100_000, 10),
+                ..Default::default()
+            },
+        );
+        nodes.start();
+        let contract_id = nodes.negotiate();
+
+        nodes.blockchain.mine(6);
+        nodes.alice.force_check().unwrap();
+        assert!(is_signed(&nodes.alice, &contract_id));
+
+        nodes.blockchain.mine(4);
+        nodes.alice.force_check().unwrap();
+        assert!(matches!(
+            nodes.alice.storage().get_contract(&contract_id),
+            Ok(Some(Contract::Confirmed(_)))
+        ));
+    }
+
+    #[test]
+    fn lost_offer_is_resent() {
+        let nodes = TwoNodes::with_config(
+            "memory_lost_offer",
+            DdkConfig {
+                outbound_retry: OutboundRetryOptions {
+                    interval: Duration::from_secs(1),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        );
+        nodes.alice.transport().set_faults(LinkFaults {
+            drop_probability: 1.0,
+            ..Default::default()
+        });
+        nodes.start();
+        let bob_key = nodes.bob.transport().public_key();
+        let announcement = nodes.oracle.announcement.clone();
+        let offer = nodes
+            .alice
+            .send_dlc_offer(&contract_input(&announcement), bob_key, vec![announcement])
+            .unwrap();
+        wait_for("the offer to be sent", || {
+            nodes
+                .alice
+                .pending_outbound()
+                .unwrap()
+                .iter()
+                .any(|pending| pending.kind == "offer" && pending.attempts > 0)
+        });
+        assert!(nodes
+            .bob
+            .storage()
+            .get_contract_offers()
+            .unwrap()
+            .is_empty());
+
+        nodes.alice.transport().set_faults(LinkFaults::default());
+        wait_for("the resent offer", || {
+            !nodes
+                .bob
+                .storage()
+                .get_contract_offers()
+                .unwrap()
+                .is_empty()
+        });
+        nodes
+            .bob
+            .accept_dlc_offer(offer.temporary_contract_id)
+            .unwrap();
+        wait_for("the offer to be answered", || {
+            nodes
+                .alice
+                .pending_outbound()
+                .unwrap()
+                .iter()
+                .all(|pending| pending.kind != "offer")
+        });
+    }
+
+    #[test]
+    fn concurrent_offers_to_one_peer_are_signed() {
+        let nodes = TwoNodes::new("memory_concurrent_offers");
+        for _ in 0..10 {
+            fund(&nodes.alice, &nodes.blockchain, 60_000);
+            fund(&nodes.bob, &nodes.blockchain, 60_000);
+        }
+        nodes.start();
+        let bob_key = nodes.bob.transport().public_key();
+
+        let offers = (0..10)
+            .map(|_| {
+                let alice = nodes.alice.clone();
+                let announcement = nodes.oracle.announcement.clone();
+                std::thread::spawn(move || {
+                    alice
+                        .send_dlc_offer(&contract_input(&announcement), bob_key, vec![announcement])
+                        .unwrap()
+                        .temporary_contract_id
+                })
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|offer| offer.join().unwrap())
+            .collect::<Vec<_>>();
+        wait_for("the offers", || {
+            nodes.bob.storage().get_contract_offers().unwrap().len() == 10
+        });
+
+        let contract_ids = offers
+            .into_iter()
+            .map(|temporary_id| {
+                let bob = nodes.bob.clone();
+                std::thread::spawn(move || bob.accept_dlc_offer(temporary_id).unwrap().0)
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|accept| {
+                let contract_id = accept.join().unwrap();
+                <[u8; 32]>::try_from(hex::decode(contract_id).unwrap()).unwrap()
+            })
+            .collect::<std::collections::HashSet<_>>();
+        assert_eq!(contract_ids.len(), 10);
+        wait_for("all contracts to be signed", || {
+            contract_ids
+                .iter()
+                .all(|id| is_signed(&nodes.alice, id) && is_signed(&nodes.bob, id))
+        });
+    }
+
+    #[test]
+    fn offers_are_funded_from_the_dlc_account() {
+        let nodes = TwoNodes::new("memory_funding_account");
+        let spending = fund_account(
+            &nodes.alice,
+            &nodes.blockchain,
+            Account::Spending,
+            1_000_000,
+        );
+        nodes.start();
+        let balances = nodes.alice.wallet().get_balance_detailed().unwrap();
+        assert_eq!(balances.dlc.total(), Amount::from_sat(200_000));
+        assert_eq!(balances.spending.total(), Amount::from_sat(1_000_000));
+
+        let bob_key = nodes.bob.transport().public_key();
+        let announcement = nodes.oracle.announcement.clone();
+        let offer = nodes
+            .alice
+            .send_dlc_offer(&contract_input(&announcement), bob_key, vec![announcement])
+            .unwrap();
+        assert!(!offer.funding_inputs.is_empty());
+        for input in &offer.funding_inputs {
+            let prev_tx: Transaction = bitcoin::consensus::deserialize(&input.prev_tx).unwrap();
+            assert_ne!(prev_tx.compute_txid(), spending);
+        }
+    }
+
+    #[test]
+    fn offer_flood_bans_the_peer() {
+        let nodes = TwoNodes::with_config(
+            "memory_offer_flood",
+            DdkConfig {
+                peer_limits: PeerLimits {
+                    burst: 1_000,
+                    max_pending_offers: 3,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        );
+        let events = nodes.bob.subscribe();
+        nodes.start();
+        let alice_key = nodes.alice.transport().public_key();
+        let bob_key = nodes.bob.transport().public_key();
+        let announcement = nodes.oracle.announcement.clone();
+        let offer = nodes
+            .alice
+            .send_dlc_offer(&contract_input(&announcement), bob_key, vec![announcement])
+            .unwrap();
+        wait_for("the offer", || {
+            !nodes
+                .bob
+                .storage()
+                .get_contract_offers()
+                .unwrap()
+                .is_empty()
+        });
+
+        // Copies of the offer under new ids, more than Bob lets wait for an answer.
+        for id in 0..9 {
+            let mut copy = offer.clone();
+            copy.temporary_contract_id = [id; 32];
+            nodes
+                .alice
+                .transport()
+                .queue_message(bob_key, Message::Offer(copy));
+        }
+        nodes.alice.transport().process_messages();
+        wait_for("the ban", || {
+            events
+                .try_iter()
+                .any(|event| matches!(event, DdkEvent::PeerBanned { pubkey, .. } if *pubkey == alice_key))
+        });
+
+        let stored = nodes.bob.storage().get_contract_offers().unwrap();
+        assert!(stored.len() <= 3);
+        let bans = nodes.bob.banned_peers().unwrap();
+        assert_eq!(bans.len(), 1);
+        assert_eq!(bans[0].pubkey, alice_key);
+
+        assert!(nodes.bob.unban_peer(&alice_key).unwrap());
+        assert!(nodes.bob.banned_peers().unwrap().is_empty());
+    }
+}
+
+/// Two nodes linked by a [MemoryTransport], shared by the integration tests of other modules.
+#[cfg(test)]
+pub(crate) mod harness {
+    use super::*;
+    use crate::builder::DdkBuilder;
+    use crate::chain::MockBlockchain;
+    use crate::config::{DdkConfig, SeedConfig};
+    use crate::storage::MemoryStorageProvider;
+    use crate::wallet::Account;
+    use crate::{DdkOracle, DlcDevKit};
+    use bitcoin::absolute::LockTime;
+    use bitcoin::bip32::Xpriv;
+    use bitcoin::hashes::Hash;
+    use bitcoin::key::XOnlyPublicKey;
+    use bitcoin::transaction::Version;
+    use bitcoin::{Amount, Network, OutPoint, Transaction, TxIn, TxOut, Txid};
+    use dlc::{EnumerationPayout, Payout};
+    use dlc_manager::contract::contract_input::{ContractInput, ContractInputInfo, OracleInput};
+    use dlc_manager::contract::enum_descriptor::EnumDescriptor;
+    use dlc_manager::contract::{Contract, ContractDescriptor};
+    use dlc_manager::error::Error as ManagerError;
+    use dlc_manager::{Blockchain, Storage};
+    use dlc_messages::oracle_msgs::{OracleAnnouncement, OracleAttestation};
+    use kormir::storage::MemoryStorage;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    pub(crate) const EVENT_ID: &str = "memory-transport";
+
+    /// Oracle serving one announcement created locally.
+    #[derive(Debug)]
+    pub(crate) struct LocalOracle {
+        pub(crate) announcement: OracleAnnouncement,
+    }
+
+    impl dlc_manager::Oracle for LocalOracle {
+        fn get_public_key(&self) -> XOnlyPublicKey {
+            self.announcement.oracle_public_key
+        }
+
+        fn get_announcement(&self, event_id: &str) -> Result<OracleAnnouncement, ManagerError> {
+            if event_id != self.announcement.oracle_event.event_id {
+                return Err(ManagerError::OracleError(format!(
+                    "Unknown event {}.",
+                    event_id
+                )));
+            }
+            Ok(self.announcement.clone())
+        }
+
+        fn get_attestation(&self, event_id: &str) -> Result<OracleAttestation, ManagerError> {
+            Err(ManagerError::OracleError(format!(
+                "Event {} is not attested.",
+                event_id
+            )))
+        }
+    }
+
+    #[async_trait]
+    impl DdkOracle for LocalOracle {
+        fn name(&self) -> String {
+            "local".into()
+        }
+
+        async fn get_announcement_async(
+            &self,
+            event_id: &str,
+        ) -> Result<OracleAnnouncement, ManagerError> {
+            dlc_manager::Oracle::get_announcement(self, event_id)
+        }
+
+        async fn get_public_key_async(&self) -> Result<XOnlyPublicKey, ManagerError> {
+            Ok(self.announcement.oracle_public_key)
+        }
+    }
+
+    pub(crate) type TestNode =
+        DlcDevKit<MemoryTransport, MemoryStorageProvider, LocalOracle, MockBlockchain>;
+
+    pub(crate) fn local_oracle() -> LocalOracle {
+        let signing_key = SecretKey::from_slice(&[1u8; 32]).unwrap();
+        let nonce_xpriv = Xpriv::new_master(Network::Regtest, &[1u8; 32]).unwrap();
+        let oracle = kormir::Oracle::new(MemoryStorage::default(), signing_key, nonce_xpriv);
+        let maturity = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as u32
+            + 86_400;
+        let announcement = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(oracle.create_enum_event(
+                EVENT_ID.to_string(),
+                vec!["yes".into(), "no".into()],
+                maturity,
+            ))
+            .unwrap();
+        LocalOracle { announcement }
+    }
+
+    pub(crate) fn node(
+        name: &str,
+        seed: u8,
+        transport: MemoryTransport,
+        oracle: Arc<LocalOracle>,
+        blockchain: Arc<MockBlockchain>,
+    ) -> TestNode {
+        node_with_config(
+            name,
+            seed,
+            DdkConfig::default(),
+            transport,
+            oracle,
+            blockchain,
+        )
+    }
+
+    /// A node built from `config`, with the network, storage path, and seed of the harness.
+    pub(crate) fn node_with_config(
+        name: &str,
+        seed: u8,
+        config: DdkConfig,
+        transport: MemoryTransport,
+        oracle: Arc<LocalOracle>,
+        blockchain: Arc<MockBlockchain>,
+    ) -> TestNode {
+        let config = DdkConfig {
+            network: Network::Regtest,
+            storage_path: format!("tests/data/{name}").into(),
+            seed_config: SeedConfig::Bytes([seed; 64]),
+            ..config
+        };
+        let mut builder = DdkBuilder::new();
+        builder
+            .set_config(config)
+            .set_name(name)
+            .set_transport(Arc::new(transport))
+            .set_storage(Arc::new(MemoryStorageProvider::new()))
+            .set_oracle(oracle)
+            .set_blockchain(blockchain);
+        builder.finish().unwrap()
+    }
+
+    /// Pay `sats` to the node's wallet in an unconfirmed transaction.
+    pub(crate) fn fund(node: &TestNode, blockchain: &MockBlockchain, sats: u64) {
+        fund_account(node, blockchain, Account::Dlc, sats);
+    }
+
+    /// Pay `sats` to `account` of the node's wallet in an unconfirmed transaction.
+    pub(crate) fn fund_account(
+        node: &TestNode,
+        blockchain: &MockBlockchain,
+        account: Account,
+        sats: u64,
+    ) -> Txid {
+        let address = node
+            .wallet()
+            .new_external_address_for(account)
+            .unwrap()
+            .address;
+        let deposit = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                // A distinct input per deposit so deposits do not conflict.
+                previous_output: OutPoint::new(Txid::hash(address.script_pubkey().as_bytes()), 0),
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(sats),
+                script_pubkey: address.script_pubkey(),
+            }],
+        };
+        blockchain.send_transaction(&deposit).unwrap();
+        node.wallet().sync().unwrap();
+        deposit.compute_txid()
+    }
+
+    pub(crate) fn contract_input(announcement: &OracleAnnouncement) -> ContractInput {
+        let outcome_payouts = [("yes", 100_000), ("no", 0)]
+            .into_iter()
+            .map(|(outcome, offer)| EnumerationPayout {
+                outcome: outcome.to_string(),
+                payout: Payout {
+                    offer,
+                    accept: 100_000 - offer,
+                },
+            })
+            .collect();
+        ContractInput {
+            offer_collateral: 50_000,
+            accept_collateral: 50_000,
+            fee_rate: 2,
+            contract_infos: vec![ContractInputInfo {
+                contract_descriptor: ContractDescriptor::Enum(EnumDescriptor { outcome_payouts }),
+                oracles: OracleInput {
+                    public_keys: vec![announcement.oracle_public_key],
+                    event_id: EVENT_ID.to_string(),
+                    threshold: 1,
+                },
+            }],
+        }
+    }
+
+    pub(crate) fn wait_for<F: Fn() -> bool>(what: &str, condition: F) {
+        for _ in 0..600 {
+            if condition() {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        panic!("Timed out waiting for {what}.");
+    }
+
+    pub(crate) fn is_signed(node: &TestNode, contract_id: &[u8; 32]) -> bool {
+        matches!(
+            node.storage().get_contract(contract_id),
+            Ok(Some(Contract::Signed(_)))
+        )
+    }
+
+    pub(crate) struct TwoNodes {
+        name: String,
+        pub(crate) alice: Arc<TestNode>,
+        pub(crate) bob: Arc<TestNode>,
+        pub(crate) oracle: Arc<LocalOracle>,
+        pub(crate) blockchain: Arc<MockBlockchain>,
+    }
+
+    impl TwoNodes {
+        /// Two funded nodes linked by a memory transport. Call [TwoNodes::start] once the
+        /// links are configured.
+        pub(crate) fn new(name: &str) -> TwoNodes {
+            TwoNodes::with_config(name, DdkConfig::default())
+        }
+
+        /// Like [TwoNodes::new], with both nodes built from `config`.
+        pub(crate) fn with_config(name: &str, config: DdkConfig) -> TwoNodes {
+            let (alice_transport, bob_transport) = MemoryTransport::pair();
+            let oracle = Arc::new(local_oracle());
+            let blockchain = Arc::new(MockBlockchain::new(Network::Regtest));
+            let alice = node_with_config(
+                &format!("{name}_alice"),
+                1,
+                config.clone(),
+                alice_transport,
+                oracle.clone(),
+                blockchain.clone(),
+            );
+            let bob = node_with_config(
+                &format!("{name}_bob"),
+                2,
+                config,
+                bob_transport,
+                oracle.clone(),
+                blockchain.clone(),
+            );
+            fund(&alice, &blockchain, 200_000);
+            fund(&bob, &blockchain, 200_000);
+            TwoNodes {
+                name: name.to_string(),
+                alice: Arc::new(alice),
+                bob: Arc::new(bob),
+                oracle,
+                blockchain,
+            }
+        }
+
+        pub(crate) fn start(&self) {
+            self.alice.start().unwrap();
+            self.bob.start().unwrap();
+        }
+
+        /// Alice offers, Bob accepts, and both wait until the contract is signed.
+        pub(crate) fn negotiate(&self) -> [u8; 32] {
+            let alice_key = self.alice.transport().public_key();
+            let bob_key = self.bob.transport().public_key();
+            let announcement = self.oracle.announcement.clone();
+            let offer = self
+                .alice
+                .send_dlc_offer(&contract_input(&announcement), bob_key, vec![announcement])
+                .unwrap();
+            wait_for("the offer", || {
+                self.bob
+                    .storage()
+                    .get_contract_offers()
+                    .unwrap()
+                    .iter()
+                    .any(|o| o.id == offer.temporary_contract_id && o.counter_party == alice_key)
+            });
+
+            let (contract_id, counter_party, _) = self
+                .bob
+                .accept_dlc_offer(offer.temporary_contract_id)
+                .unwrap();
+            assert_eq!(counter_party, alice_key.to_string());
+            let contract_id: [u8; 32] = hex::decode(contract_id).unwrap().try_into().unwrap();
+            wait_for("both nodes to sign", || {
+                is_signed(&self.alice, &contract_id) && is_signed(&self.bob, &contract_id)
+            });
+            contract_id
+        }
+    }
+
+    impl Drop for TwoNodes {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(format!("tests/data/{}_alice", self.name));
+            let _ = std::fs::remove_dir_all(format!("tests/data/{}_bob", self.name));
+        }
+    }
+}
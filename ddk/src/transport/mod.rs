@@ -1,9 +1,467 @@
+pub(crate) mod inbound;
 pub mod lightning;
+pub mod memory;
 #[cfg(feature = "nostr")]
 pub mod nostr;
+pub(crate) mod outbox;
+pub(crate) mod reconnect;
+pub mod rate_limit;
+pub mod retry;
+pub mod tcp;
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+use crate::DdkTransport;
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::secp256k1::PublicKey;
+use crossbeam::channel::Sender;
+use dlc::secp256k1_zkp::EcdsaAdaptorSignature;
+use dlc_manager::contract::accepted_contract::AcceptedContract;
+use dlc_manager::contract::signed_contract::SignedContract;
+use dlc_manager::contract::Contract;
+use dlc_manager::ContractId;
+use dlc_messages::message_handler::read_dlc_message;
+use dlc_messages::{
+    AcceptDlc, CetAdaptorSignature, CetAdaptorSignatures, Message, SignDlc, WireMessage,
+};
+use ::lightning::ln::wire::Type;
+use ::lightning::util::ser::{Readable, Writeable};
+use std::collections::HashSet;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How often the default [DdkTransport::start] polls the transport.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// What a transport reports to the node while it runs.
+#[derive(Debug, Clone)]
+pub enum TransportEvent {
+    MessageReceived(PublicKey, Message),
+    PeerConnected(PublicKey),
+    PeerDisconnected(PublicKey),
+}
+
+/// Listen while polling the transport every [POLL_INTERVAL]. Queued messages are processed,
+/// received messages reported, and connections found by diffing
+/// [DdkTransport::connected_peers]. Polling stops once the receiver of `event_sink` is dropped.
+pub async fn poll_events<T>(transport: &T, event_sink: Sender<TransportEvent>)
+where
+    T: DdkTransport + ?Sized,
+{
+    let poll = async {
+        let mut timer = tokio::time::interval(POLL_INTERVAL);
+        let mut connected = HashSet::new();
+        loop {
+            timer.tick().await;
+            transport.process_messages();
+
+            let now = transport.connected_peers().into_iter().collect::<HashSet<_>>();
+            let mut events = now
+                .difference(&connected)
+                .map(|peer| TransportEvent::PeerConnected(*peer))
+                .collect::<Vec<_>>();
+            events.extend(
+                connected
+                    .difference(&now)
+                    .map(|peer| TransportEvent::PeerDisconnected(*peer)),
+            );
+            connected = now;
+            for (counterparty, message) in transport.get_and_clear_received_messages() {
+                events.push(TransportEvent::MessageReceived(counterparty, message));
+            }
+
+            for event in events {
+                if event_sink.send(event).is_err() {
+                    return;
+                }
+            }
+        }
+    };
+    tokio::join!(transport.listen(), poll);
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
 pub struct PeerInformation {
     pub pubkey: String,
     pub host: String,
 }
+
+impl PeerInformation {
+    /// Socket addresses the host resolves to.
+    pub fn addresses(&self) -> anyhow::Result<Vec<SocketAddr>> {
+        Ok(self.host.to_socket_addrs()?.collect())
+    }
+}
+
+/// Parses a `pubkey@host:port` connection string.
+impl FromStr for PeerInformation {
+    type Err = anyhow::Error;
+
+    fn from_str(connection: &str) -> Result<Self, Self::Err> {
+        let (pubkey, host) = connection
+            .split_once('@')
+            .ok_or_else(|| anyhow::anyhow!("Connection string must be pubkey@host:port."))?;
+        PublicKey::from_str(pubkey)?;
+        if !host.contains(':') {
+            return Err(anyhow::anyhow!("Connection string is missing a port."));
+        }
+        Ok(PeerInformation {
+            pubkey: pubkey.to_string(),
+            host: host.to_string(),
+        })
+    }
+}
+
+impl std::fmt::Display for PeerInformation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}@{}", self.pubkey, self.host)
+    }
+}
+
+/// A DLC message that was produced by the manager and not yet delivered. Persisted so the
+/// message can be re-enqueued if the node stops before delivery. Contract messages stay until
+/// the counterparty acts on them, see [retry].
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct PendingOutbound {
+    /// Hash of the counterparty, contract id, and kind for contract messages, so a newer message
+    /// replaces an older one. Hash of the serialized message otherwise.
+    pub id: String,
+    /// The type of DLC message. Ex. `accept` or `sign`.
+    pub kind: String,
+    pub counterparty: PublicKey,
+    /// The wire encoded message, prefixed with the message type.
+    pub message: Vec<u8>,
+    /// Contract whose state tells if the counterparty acted on the message. None for messages
+    /// that are sent once, like channel messages.
+    #[serde(default)]
+    pub contract_id: Option<ContractId>,
+    /// Times the transport took the message.
+    #[serde(default)]
+    pub attempts: u32,
+    /// Unix timestamp in seconds.
+    #[serde(default)]
+    pub created_at: u64,
+    /// Unix timestamp in seconds the transport last took the message. Zero before it did.
+    #[serde(default)]
+    pub last_attempt: u64,
+}
+
+impl PendingOutbound {
+    pub fn new(counterparty: PublicKey, message: &Message) -> PendingOutbound {
+        let bytes = encode_message(message);
+        let kind = message_kind(message).to_string();
+        let contract_id = message_contract_id(message);
+
+        PendingOutbound {
+            id: outbound_id(&counterparty, contract_id.as_ref(), &kind, &bytes),
+            kind,
+            counterparty,
+            message: bytes,
+            contract_id,
+            attempts: 0,
+            created_at: unix_now().as_secs(),
+            last_attempt: 0,
+        }
+    }
+
+    /// The message storing `new` over `existing` commits the node to send: the Accept of an
+    /// offer it accepted, or the Sign of its offer that was accepted. Storage writes the
+    /// marker in the same transaction as the contract, so a crash before the message reaches
+    /// the outbox does not lose it. Contracts of channels are negotiated with channel messages
+    /// and are not passed here.
+    pub fn for_state_change(existing: Option<&Contract>, new: &Contract) -> Option<PendingOutbound> {
+        match new {
+            Contract::Accepted(accepted) if !matches!(existing, Some(Contract::Accepted(_))) => {
+                let message = Message::Accept(accept_message(accepted));
+                Some(
+                    PendingOutbound::new(accepted.offered_contract.counter_party, &message)
+                        .for_contract(accepted.get_contract_id()),
+                )
+            }
+            Contract::Signed(signed)
+                if signed.accepted_contract.offered_contract.is_offer_party
+                    && matches!(existing, Some(Contract::Offered(_))) =>
+            {
+                let message = Message::Sign(sign_message(signed));
+                Some(PendingOutbound::new(
+                    signed.accepted_contract.offered_contract.counter_party,
+                    &message,
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    /// Track the message by `contract_id` instead of the id it carries. Accepts carry the
+    /// temporary id while the contract is stored under its final id.
+    pub fn for_contract(mut self, contract_id: ContractId) -> PendingOutbound {
+        self.id = outbound_id(&self.counterparty, Some(&contract_id), &self.kind, &self.message);
+        self.contract_id = Some(contract_id);
+        self
+    }
+
+    /// Count a send the transport took at `now`, in unix seconds.
+    pub fn record_attempt(&mut self, now: u64) {
+        self.attempts += 1;
+        self.last_attempt = now;
+    }
+
+    /// Decode the stored DLC message.
+    pub fn message(&self) -> anyhow::Result<Message> {
+        decode_message(&self.message)
+    }
+}
+
+/// A DLC message received from a peer and journaled before the manager processes it. It is
+/// replayed on startup until acknowledged.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct PendingInbound {
+    /// Hash of the serialized message. See [message_id].
+    pub id: String,
+    /// The type of DLC message. Ex. `accept` or `sign`.
+    pub kind: String,
+    pub counterparty: PublicKey,
+    /// The wire encoded message, prefixed with the message type.
+    pub message: Vec<u8>,
+    /// Unix timestamp in nanoseconds. Messages are replayed in this order.
+    pub received_at: u64,
+    /// Times processing failed for a reason that can pass, like an unreachable chain backend.
+    #[serde(default)]
+    pub attempts: u32,
+}
+
+/// The Accept the manager sent for `accepted`.
+fn accept_message(accepted: &AcceptedContract) -> AcceptDlc {
+    let params = &accepted.accept_params;
+    AcceptDlc {
+        protocol_version: PROTOCOL_VERSION,
+        temporary_contract_id: accepted.offered_contract.id,
+        accept_collateral: params.collateral,
+        funding_pubkey: params.fund_pubkey,
+        payout_spk: params.payout_script_pubkey.clone(),
+        payout_serial_id: params.payout_serial_id,
+        funding_inputs: accepted.funding_inputs.clone(),
+        change_spk: params.change_script_pubkey.clone(),
+        change_serial_id: params.change_serial_id,
+        cet_adaptor_signatures: cet_adaptor_signatures(accepted.adaptor_signatures.as_deref()),
+        refund_signature: accepted.accept_refund_signature,
+        negotiation_fields: None,
+    }
+}
+
+/// The Sign the manager sent for `signed`, our offer.
+fn sign_message(signed: &SignedContract) -> SignDlc {
+    SignDlc {
+        protocol_version: PROTOCOL_VERSION,
+        contract_id: signed.accepted_contract.get_contract_id(),
+        cet_adaptor_signatures: cet_adaptor_signatures(signed.adaptor_signatures.as_deref()),
+        refund_signature: signed.offer_refund_signature,
+        funding_signatures: signed.funding_signatures.clone(),
+    }
+}
+
+fn cet_adaptor_signatures(signatures: Option<&[EcdsaAdaptorSignature]>) -> CetAdaptorSignatures {
+    CetAdaptorSignatures {
+        ecdsa_adaptor_signatures: signatures
+            .unwrap_or_default()
+            .iter()
+            .map(|signature| CetAdaptorSignature {
+                signature: *signature,
+            })
+            .collect(),
+    }
+}
+
+/// Version of the DLC protocol the manager writes into its messages.
+const PROTOCOL_VERSION: u32 = 1;
+
+/// Failed attempts after which a journaled message is dead-lettered instead of replayed.
+pub const MAX_INBOUND_ATTEMPTS: u32 = 5;
+/// Journaled messages that may wait to be processed. Messages received beyond it are dropped
+/// before they are journaled.
+pub const MAX_PENDING_INBOUND: usize = 10_000;
+
+impl PendingInbound {
+    pub fn new(counterparty: PublicKey, message: &Message) -> PendingInbound {
+        let bytes = encode_message(message);
+        let received_at = unix_now().as_nanos() as u64;
+
+        PendingInbound {
+            id: bytes_id(&bytes),
+            kind: message_kind(message).to_string(),
+            counterparty,
+            message: bytes,
+            received_at,
+            attempts: 0,
+        }
+    }
+
+    /// Decode the journaled DLC message.
+    pub fn message(&self) -> anyhow::Result<Message> {
+        decode_message(&self.message)
+    }
+}
+
+/// Whether a journaled message was received or sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum MessageDirection {
+    Inbound,
+    Outbound,
+}
+
+/// A DLC message recorded with its wire bytes.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct JournalEntry {
+    pub direction: MessageDirection,
+    pub counterparty: PublicKey,
+    /// The wire encoded message, prefixed with the message type.
+    pub message: Vec<u8>,
+    /// Unix timestamp in seconds.
+    pub timestamp: u64,
+}
+
+impl JournalEntry {
+    /// Decode the recorded DLC message.
+    pub fn message(&self) -> anyhow::Result<Message> {
+        decode_message(&self.message)
+    }
+}
+
+/// Short name of a DLC message type.
+pub fn message_kind(message: &Message) -> &'static str {
+    match message {
+        Message::Offer(_) => "offer",
+        Message::Accept(_) => "accept",
+        Message::Sign(_) => "sign",
+        Message::Channel(_) => "channel",
+        #[allow(unreachable_patterns)]
+        _ => "other",
+    }
+}
+
+/// Contract the message is about. Offers and accepts carry the temporary contract id.
+pub fn message_contract_id(message: &Message) -> Option<ContractId> {
+    match message {
+        Message::Offer(offer) => Some(offer.temporary_contract_id),
+        Message::Accept(accept) => Some(accept.temporary_contract_id),
+        Message::Sign(sign) => Some(sign.contract_id),
+        _ => None,
+    }
+}
+
+/// Id of a DLC message, the hash of its wire bytes. The same message sent twice has the same id.
+pub fn message_id(message: &Message) -> String {
+    bytes_id(&encode_message(message))
+}
+
+fn bytes_id(bytes: &[u8]) -> String {
+    sha256::Hash::hash(bytes).to_string()
+}
+
+fn outbound_id(
+    counterparty: &PublicKey,
+    contract_id: Option<&ContractId>,
+    kind: &str,
+    bytes: &[u8],
+) -> String {
+    let Some(contract_id) = contract_id else {
+        return bytes_id(bytes);
+    };
+    let mut engine = sha256::Hash::engine();
+    engine.input(&counterparty.serialize());
+    engine.input(contract_id);
+    engine.input(kind.as_bytes());
+    sha256::Hash::from_engine(engine).to_string()
+}
+
+fn unix_now() -> Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+/// Encode a DLC message to wire bytes prefixed with the message type.
+pub fn encode_message(message: &Message) -> Vec<u8> {
+    let mut bytes = message.type_id().encode();
+    bytes.extend(message.encode());
+    bytes
+}
+
+/// Decode a DLC message from wire bytes prefixed with the message type.
+pub fn decode_message(bytes: &[u8]) -> anyhow::Result<Message> {
+    let mut cursor = ::lightning::io::Cursor::new(bytes);
+    let msg_type: u16 = Readable::read(&mut cursor)
+        .map_err(|_| anyhow::anyhow!("Could not read message type."))?;
+
+    match read_dlc_message(msg_type, &mut cursor)
+        .map_err(|_| anyhow::anyhow!("Could not read DLC message."))?
+    {
+        Some(WireMessage::Message(msg)) => Ok(msg),
+        _ => Err(anyhow::anyhow!("Not a DLC message.")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::secp256k1::{Secp256k1, SecretKey};
+    use dlc_messages::OfferDlc;
+
+    #[test]
+    fn pending_outbound_round_trip() {
+        let offer: OfferDlc =
+            serde_json::from_str(include_str!("../../tests/data/dlc/offer.json")).unwrap();
+        let secp = Secp256k1::new();
+        let counterparty =
+            PublicKey::from_secret_key(&secp, &SecretKey::from_slice(&[1u8; 32]).unwrap());
+
+        let pending = PendingOutbound::new(counterparty, &Message::Offer(offer.clone()));
+        assert_eq!(pending.kind, "offer");
+        assert_eq!(pending.contract_id, Some(offer.temporary_contract_id));
+
+        let json = serde_json::to_vec(&pending).unwrap();
+        let decoded: PendingOutbound = serde_json::from_slice(&json).unwrap();
+        assert_eq!(decoded, pending);
+        match decoded.message().unwrap() {
+            Message::Offer(decoded) => assert_eq!(decoded, offer),
+            _ => panic!("Decoded the wrong message type."),
+        }
+
+        let tracked = pending.clone().for_contract([9u8; 32]);
+        assert_eq!(tracked.contract_id, Some([9u8; 32]));
+        assert_ne!(tracked.id, pending.id);
+
+        // Markers stored before retries were tracked.
+        let mut json = serde_json::to_value(&pending).unwrap();
+        for field in ["contract_id", "attempts", "created_at", "last_attempt"] {
+            json.as_object_mut().unwrap().remove(field);
+        }
+        let old: PendingOutbound = serde_json::from_value(json).unwrap();
+        assert_eq!(old.contract_id, None);
+        assert_eq!(old.attempts, 0);
+    }
+
+    #[test]
+    fn connection_string_round_trip() {
+        let secp = Secp256k1::new();
+        let pubkey =
+            PublicKey::from_secret_key(&secp, &SecretKey::from_slice(&[1u8; 32]).unwrap())
+                .to_string();
+        let peer = PeerInformation::from_str(&format!("{pubkey}@127.0.0.1:9735")).unwrap();
+        assert_eq!(peer.pubkey, pubkey);
+        assert_eq!(peer.host, "127.0.0.1:9735");
+        assert_eq!(peer.to_string(), format!("{pubkey}@127.0.0.1:9735"));
+        assert_eq!(peer.addresses().unwrap(), vec!["127.0.0.1:9735".parse().unwrap()]);
+
+        assert!(PeerInformation::from_str("127.0.0.1:9735").is_err());
+        assert!(PeerInformation::from_str(&format!("{pubkey}@127.0.0.1")).is_err());
+        assert!(PeerInformation::from_str("not-a-key@127.0.0.1:9735").is_err());
+    }
+
+    #[test]
+    fn corrupted_message_does_not_decode() {
+        let offer: OfferDlc =
+            serde_json::from_str(include_str!("../../tests/data/dlc/offer.json")).unwrap();
+        let bytes = encode_message(&Message::Offer(offer));
+        assert!(decode_message(&bytes[..bytes.len() / 2]).is_err());
+    }
+}
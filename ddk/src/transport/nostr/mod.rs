@@ -1,7 +1,9 @@
 pub mod dlc_handler;
 pub mod relay_handler;
+mod transport;
 
 pub use dlc_handler::NostrDlcHandler;
+pub use transport::{counterparty_from_nostr, nostr_from_counterparty, NostrTransport};
 pub use nostr;
 pub use nostr_relay_pool::RelayPoolNotification;
 pub use nostr_sdk;
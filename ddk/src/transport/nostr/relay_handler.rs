@@ -1,11 +1,8 @@
 use crate::config::SeedConfig;
 use crate::{io, RELAY_HOST};
 use bitcoin::Network;
-use dlc_messages::{message_handler::read_dlc_message, Message, WireMessage};
-use lightning::{
-    ln::wire::Type,
-    util::ser::{Readable, Writeable},
-};
+use crate::transport::{decode_message, encode_message};
+use dlc_messages::Message;
 use nostr::{
     nips::nip04::{decrypt, encrypt},
     secp256k1::Secp256k1,
@@ -31,8 +28,7 @@ impl NostrDlcRelayHandler {
     ) -> anyhow::Result<NostrDlcRelayHandler> {
         let secp = Secp256k1::new();
         let seed = io::xprv_from_config(seed_config, network)?;
-        // TODO: Seed to bytes is 78 not 64?
-        let secret_key = SecretKey::from_slice(&seed.encode())?;
+        let secret_key = SecretKey::from_slice(&seed.private_key.secret_bytes())?;
         let keys = Keys::new_with_ctx(&secp, secret_key.into());
 
         let relay_url = relay_host.parse()?;
@@ -68,8 +64,7 @@ impl NostrDlcRelayHandler {
         event_id: Option<EventId>,
         msg: Message,
     ) -> anyhow::Result<Event> {
-        let mut bytes = msg.type_id().encode();
-        bytes.extend(msg.encode());
+        let bytes = encode_message(&msg);
 
         let content = encrypt(
             &self.keys.secret_key()?.clone(),
@@ -77,8 +72,9 @@ impl NostrDlcRelayHandler {
             base64::encode(&bytes),
         )?;
 
+        // Recipients filter on the p tag.
         let p_tags = Tag::PublicKey {
-            public_key: self.public_key(),
+            public_key: to,
             relay_url: None,
             alias: None,
             uppercase: false,
@@ -101,28 +97,9 @@ impl NostrDlcRelayHandler {
     }
 
     pub fn parse_dlc_msg_event(&self, event: &Event) -> anyhow::Result<Message> {
-        let decrypt = decrypt(
-            &self.keys.secret_key().unwrap(),
-            &event.pubkey,
-            &event.content,
-        )?;
-
+        let decrypt = decrypt(self.keys.secret_key()?, &event.pubkey, &event.content)?;
         let bytes = base64::decode(decrypt)?;
-
-        let mut cursor = lightning::io::Cursor::new(bytes);
-
-        let msg_type: u16 = Readable::read(&mut cursor).unwrap();
-
-        let Some(wire) = read_dlc_message(msg_type, &mut cursor).unwrap() else {
-            return Err(anyhow::anyhow!("Couldn't read DLC message."));
-        };
-
-        match wire {
-            WireMessage::Message(msg) => Ok(msg),
-            WireMessage::SegmentStart(_) | WireMessage::SegmentChunk(_) => {
-                Err(anyhow::anyhow!("Blah blah, something with a wire"))
-            }
-        }
+        decode_message(&bytes)
     }
 
     pub fn handle_dlc_msg_event(&self, event: Event) {
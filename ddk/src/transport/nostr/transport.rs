@@ -0,0 +1,376 @@
+//! [DdkTransport] over nostr. DLC messages are sent as NIP-04 encrypted events of
+//! [DLC_MESSAGE_KIND] tagged with the recipient.
+//!
+//! Nostr keys are x-only, so counterparties are identified by the even parity
+//! [bitcoin::secp256k1::PublicKey] of their nostr key. See [counterparty_from_nostr].
+use super::relay_handler::{NostrDlcRelayHandler, DLC_MESSAGE_KIND};
+use crate::config::SeedConfig;
+use crate::{DdkTransport, TransportError};
+use async_trait::async_trait;
+use bitcoin::key::Parity;
+use bitcoin::secp256k1::{PublicKey, XOnlyPublicKey};
+use bitcoin::Network;
+use dlc_messages::Message;
+use nostr::{Event, EventId, Timestamp, Url};
+use nostr_relay_pool::RelayPoolNotification;
+use nostr_sdk::Client;
+use std::collections::{HashSet, VecDeque};
+use std::str::FromStr;
+use std::sync::Mutex;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+/// Event ids remembered to drop events relays replay after a reconnect.
+const SEEN_EVENTS_CAPACITY: usize = 10_000;
+/// How far before the last event seen to subscribe from, for events relays stored late or
+/// that were created by a skewed clock. Replayed events are dropped.
+const RESUBSCRIBE_LOOKBACK_SECS: u64 = 60;
+
+pub struct NostrTransport {
+    handler: NostrDlcRelayHandler,
+    relays: Vec<Url>,
+    received: Mutex<Vec<(PublicKey, Message)>>,
+    /// Events created by `queue_message` and not yet handed to the relay task.
+    outbound: Mutex<Vec<Event>>,
+    publish: UnboundedSender<Event>,
+    publish_receiver: Mutex<Option<UnboundedReceiver<Event>>>,
+    seen: Mutex<SeenEvents>,
+    /// Creation time of the newest event received, or of the first subscription. Persisted
+    /// by the node as the [DdkTransport::inbound_cursor].
+    last_seen: Mutex<Option<Timestamp>>,
+}
+
+impl NostrTransport {
+    pub fn new(
+        seed_config: &SeedConfig,
+        relays: &[&str],
+        network: Network,
+    ) -> anyhow::Result<NostrTransport> {
+        let first = relays
+            .first()
+            .ok_or_else(|| anyhow::anyhow!("Nostr transport needs at least one relay."))?;
+        let handler = NostrDlcRelayHandler::new(seed_config, first, network)?;
+        let relays = relays
+            .iter()
+            .map(|relay| Url::parse(relay))
+            .collect::<Result<Vec<_>, _>>()?;
+        let (publish, publish_receiver) = unbounded_channel();
+
+        Ok(NostrTransport {
+            handler,
+            relays,
+            received: Mutex::new(vec![]),
+            outbound: Mutex::new(vec![]),
+            publish,
+            publish_receiver: Mutex::new(Some(publish_receiver)),
+            seen: Mutex::new(SeenEvents::new(SEEN_EVENTS_CAPACITY)),
+            last_seen: Mutex::new(None),
+        })
+    }
+
+    /// Our identity as a DLC counterparty.
+    pub fn node_id(&self) -> anyhow::Result<PublicKey> {
+        counterparty_from_nostr(&self.handler.public_key())
+    }
+
+    fn receive_event(&self, event: &Event) {
+        if event.kind != DLC_MESSAGE_KIND || !self.seen.lock().unwrap().insert(event.id) {
+            return;
+        }
+        {
+            let mut last_seen = self.last_seen.lock().unwrap();
+            if last_seen.map_or(true, |seen| event.created_at > seen) {
+                *last_seen = Some(event.created_at);
+            }
+        }
+
+        let counterparty = match counterparty_from_nostr(&event.pubkey) {
+            Ok(counterparty) => counterparty,
+            Err(e) => {
+                tracing::warn!(error = e.to_string(), "Ignoring event with invalid pubkey.");
+                return;
+            }
+        };
+        match self.handler.parse_dlc_msg_event(event) {
+            Ok(message) => self.received.lock().unwrap().push((counterparty, message)),
+            Err(e) => tracing::warn!(
+                counterparty = counterparty.to_string(),
+                error = e.to_string(),
+                "Could not read DLC message event."
+            ),
+        }
+    }
+
+    /// Where to subscribe from: shortly before the last event seen, so nothing sent while we
+    /// were away is missed, or now on the first start.
+    fn subscription_start(&self) -> Timestamp {
+        let mut last_seen = self.last_seen.lock().unwrap();
+        match *last_seen {
+            Some(seen) => Timestamp::from(seen.as_u64().saturating_sub(RESUBSCRIBE_LOOKBACK_SECS)),
+            None => *last_seen.insert(Timestamp::now()),
+        }
+    }
+
+    async fn subscribe(&self, since: Timestamp) {
+        let filter = self.handler.create_dlc_message_filter(since);
+        self.handler.client.subscribe(vec![filter], None).await;
+    }
+}
+
+#[async_trait]
+impl DdkTransport for NostrTransport {
+    type PeerManager = Client;
+    type MessageHandler = ();
+
+    fn name(&self) -> String {
+        "nostr".into()
+    }
+
+    /// Subscribes from the last event seen, restored with [DdkTransport::resume_inbound] after
+    /// a restart. Relays reconnect on their own. When notifications are missed the
+    /// subscription is renewed from the last event seen, and replayed events are dropped.
+    async fn listen(&self) {
+        let Some(mut publish_receiver) = self.publish_receiver.lock().unwrap().take() else {
+            tracing::warn!("Nostr transport is already listening.");
+            return;
+        };
+
+        let client = &self.handler.client;
+        for relay in &self.relays {
+            if let Err(e) = client.add_relay(relay.as_str()).await {
+                tracing::error!(relay = relay.to_string(), error = e.to_string(), "Could not add relay.");
+            }
+        }
+        client.connect().await;
+        self.subscribe(self.subscription_start()).await;
+
+        let mut notifications = client.notifications();
+        loop {
+            tokio::select! {
+                notification = notifications.recv() => match notification {
+                    Ok(RelayPoolNotification::Event { event, .. }) => self.receive_event(&event),
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(missed)) => {
+                        tracing::warn!(missed, "Missed relay notifications. Resubscribing.");
+                        self.subscribe(self.subscription_start()).await;
+                    }
+                    Err(RecvError::Closed) => {
+                        tracing::error!("Relay pool stopped.");
+                        return;
+                    }
+                },
+                Some(event) = publish_receiver.recv() => {
+                    if let Err(e) = client.send_event(event).await {
+                        tracing::error!(error = e.to_string(), "Could not publish DLC message.");
+                    }
+                }
+            }
+        }
+    }
+
+    fn message_handler(&self) -> Self::MessageHandler {}
+
+    fn peer_manager(&self) -> Self::PeerManager {
+        self.handler.client.clone()
+    }
+
+    fn process_messages(&self) {
+        for event in self.outbound.lock().unwrap().drain(..) {
+            if self.publish.send(event).is_err() {
+                tracing::error!("Nostr relay task stopped. Dropping outbound message.");
+            }
+        }
+    }
+
+    /// Hand the message to the relay task. Relays keep it for the counterparty, so there is no
+    /// connection to wait for.
+    async fn send_message(
+        &self,
+        counterparty: PublicKey,
+        message: Message,
+    ) -> Result<(), TransportError> {
+        let to = nostr_from_counterparty(&counterparty).map_err(TransportError)?;
+        let event = self
+            .handler
+            .create_dlc_msg_event(to, None, message)
+            .map_err(TransportError)?;
+        self.publish
+            .send(event)
+            .map_err(|_| TransportError(anyhow::anyhow!("Nostr relay task stopped.")))
+    }
+
+    fn queue_message(&self, counterparty: PublicKey, message: Message) {
+        let to = match nostr_from_counterparty(&counterparty) {
+            Ok(to) => to,
+            Err(e) => {
+                tracing::error!(error = e.to_string(), "Invalid nostr counterparty.");
+                return;
+            }
+        };
+        match self.handler.create_dlc_msg_event(to, None, message) {
+            Ok(event) => self.outbound.lock().unwrap().push(event),
+            Err(e) => tracing::error!(
+                counterparty = counterparty.to_string(),
+                error = e.to_string(),
+                "Could not create DLC message event."
+            ),
+        }
+    }
+
+    fn get_and_clear_received_messages(&self) -> Vec<(PublicKey, Message)> {
+        std::mem::take(&mut *self.received.lock().unwrap())
+    }
+
+    fn has_pending_messages(&self) -> bool {
+        !self.outbound.lock().unwrap().is_empty()
+    }
+
+    fn inbound_cursor(&self) -> Option<u64> {
+        self.last_seen.lock().unwrap().map(|seen| seen.as_u64())
+    }
+
+    fn resume_inbound(&self, cursor: u64) {
+        let mut last_seen = self.last_seen.lock().unwrap();
+        if last_seen.map_or(true, |seen| seen.as_u64() < cursor) {
+            *last_seen = Some(Timestamp::from(cursor));
+        }
+    }
+
+    /// Add the relay at `host` that the counterparty reads from.
+    async fn connect_outbound(&self, _pubkey: PublicKey, host: &str) {
+        if let Err(e) = self.handler.client.add_relay(host).await {
+            tracing::error!(relay = host, error = e.to_string(), "Could not add relay.");
+            return;
+        }
+        self.handler.client.connect().await;
+    }
+}
+
+/// The counterparty key a nostr pubkey is known by, its even parity full key.
+pub fn counterparty_from_nostr(pubkey: &nostr::PublicKey) -> anyhow::Result<PublicKey> {
+    let x_only = XOnlyPublicKey::from_str(&pubkey.to_hex())?;
+    Ok(PublicKey::from_x_only_public_key(x_only, Parity::Even))
+}
+
+/// The nostr pubkey of a counterparty. Parity is dropped.
+pub fn nostr_from_counterparty(counterparty: &PublicKey) -> anyhow::Result<nostr::PublicKey> {
+    Ok(nostr::PublicKey::from_slice(
+        &counterparty.x_only_public_key().0.serialize(),
+    )?)
+}
+
+/// Bounded set of event ids. The oldest ids are forgotten first.
+struct SeenEvents {
+    ids: HashSet<EventId>,
+    order: VecDeque<EventId>,
+    capacity: usize,
+}
+
+impl SeenEvents {
+    fn new(capacity: usize) -> SeenEvents {
+        SeenEvents {
+            ids: HashSet::new(),
+            order: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    /// Returns false if the id was already seen.
+    fn insert(&mut self, id: EventId) -> bool {
+        if !self.ids.insert(id) {
+            return false;
+        }
+        self.order.push_back(id);
+        if self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.ids.remove(&oldest);
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dlc_messages::OfferDlc;
+
+    fn transport(seed: u8) -> NostrTransport {
+        NostrTransport::new(
+            &SeedConfig::Bytes([seed; 64]),
+            &["ws://localhost:8081"],
+            Network::Regtest,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn message_round_trip_and_replay() {
+        let alice = transport(1);
+        let bob = transport(2);
+        let offer: OfferDlc =
+            serde_json::from_str(include_str!("../../../tests/data/dlc/offer.json")).unwrap();
+
+        alice.queue_message(bob.node_id().unwrap(), Message::Offer(offer.clone()));
+        assert!(alice.has_pending_messages());
+        let event = alice.outbound.lock().unwrap()[0].clone();
+
+        bob.receive_event(&event);
+        // A relay replaying the event after a reconnect.
+        bob.receive_event(&event);
+
+        let received = bob.get_and_clear_received_messages();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].0, alice.node_id().unwrap());
+        assert!(matches!(&received[0].1, Message::Offer(o) if *o == offer));
+        assert!(bob.get_and_clear_received_messages().is_empty());
+
+        alice.process_messages();
+        assert!(!alice.has_pending_messages());
+    }
+
+    #[test]
+    fn counterparty_key_mapping() {
+        let alice = transport(1);
+        let nostr_key = alice.handler.public_key();
+        let counterparty = counterparty_from_nostr(&nostr_key).unwrap();
+        assert_eq!(nostr_from_counterparty(&counterparty).unwrap(), nostr_key);
+    }
+
+    #[test]
+    fn subscription_resumes_from_the_persisted_cursor() {
+        let fresh = transport(1);
+        assert_eq!(fresh.inbound_cursor(), None);
+        let start = fresh.subscription_start();
+        // The first subscription point is persisted, so a restart does not skip ahead.
+        assert_eq!(fresh.inbound_cursor(), Some(start.as_u64()));
+
+        let restarted = transport(2);
+        restarted.resume_inbound(1_000);
+        assert_eq!(
+            restarted.subscription_start().as_u64(),
+            1_000 - RESUBSCRIBE_LOOKBACK_SECS
+        );
+        // Events move the cursor forward, never back.
+        let offer: OfferDlc =
+            serde_json::from_str(include_str!("../../../tests/data/dlc/offer.json")).unwrap();
+        fresh.queue_message(restarted.node_id().unwrap(), Message::Offer(offer));
+        let event = fresh.outbound.lock().unwrap()[0].clone();
+        restarted.receive_event(&event);
+        assert_eq!(restarted.inbound_cursor(), Some(event.created_at.as_u64()));
+        restarted.resume_inbound(1_000);
+        assert_eq!(restarted.inbound_cursor(), Some(event.created_at.as_u64()));
+    }
+
+    #[test]
+    fn seen_events_are_bounded() {
+        let mut seen = SeenEvents::new(2);
+        let ids = [[1u8; 32], [2u8; 32], [3u8; 32]].map(|id| EventId::from_slice(&id).unwrap());
+        assert!(seen.insert(ids[0]));
+        assert!(!seen.insert(ids[0]));
+        assert!(seen.insert(ids[1]));
+        assert!(seen.insert(ids[2]));
+        // The oldest id was forgotten.
+        assert!(seen.insert(ids[0]));
+    }
+}
@@ -0,0 +1,181 @@
+//! Hands outbound messages to the transport in the order they were produced, from the manager
+//! thread and from async callers alike.
+use super::{message_kind, unix_now, PendingOutbound};
+use crate::audit::{self, AuditEntry, AuditEventType};
+use crate::{DdkStorage, DdkTransport};
+use bitcoin::secp256k1::PublicKey;
+use dlc_messages::Message;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+/// A message waiting for the transport.
+#[derive(Debug)]
+pub(crate) struct Outbound {
+    pub counterparty: PublicKey,
+    pub message: Message,
+    /// The stored marker. Contract messages keep it with the attempt recorded once the
+    /// transport took the message, others have it removed.
+    pub pending: Option<PendingOutbound>,
+}
+
+/// Queue of outbound messages, delivered by [deliver] once the node runs.
+#[derive(Debug)]
+pub(crate) struct Outbox {
+    sender: UnboundedSender<Outbound>,
+    queue: Mutex<Option<UnboundedReceiver<Outbound>>>,
+}
+
+impl Outbox {
+    pub fn new() -> Outbox {
+        let (sender, queue) = unbounded_channel();
+        Outbox {
+            sender,
+            queue: Mutex::new(Some(queue)),
+        }
+    }
+
+    pub fn send(
+        &self,
+        counterparty: PublicKey,
+        message: Message,
+        pending: Option<PendingOutbound>,
+    ) {
+        let outbound = Outbound {
+            counterparty,
+            message,
+            pending,
+        };
+        if self.sender.send(outbound).is_err() {
+            tracing::error!("Outbox stopped. Dropping outbound message.");
+        }
+    }
+
+    /// The queue for [deliver]. None once taken.
+    pub fn take_queue(&self) -> Option<UnboundedReceiver<Outbound>> {
+        self.queue.lock().unwrap().take()
+    }
+}
+
+/// Send queued messages one at a time. A message the transport could not take keeps its
+/// marker, and is sent again when the peer reconnects, on the next retry, or when the node
+/// restarts.
+pub(crate) async fn deliver<T: DdkTransport, S: DdkStorage>(
+    transport: Arc<T>,
+    storage: Arc<S>,
+    mut queue: UnboundedReceiver<Outbound>,
+) {
+    while let Some(outbound) = queue.recv().await {
+        let kind = message_kind(&outbound.message);
+        match transport
+            .send_message(outbound.counterparty, outbound.message)
+            .await
+        {
+            Ok(()) => {
+                let Some(mut pending) = outbound.pending else {
+                    continue;
+                };
+                let stored = if pending.contract_id.is_some() {
+                    pending.record_attempt(unix_now().as_secs());
+                    storage.save_pending_outbound(pending)
+                } else {
+                    storage.remove_pending_outbound(&pending.id)
+                };
+                if let Err(e) = stored {
+                    tracing::error!(
+                        error = e.to_string(),
+                        "Could not update pending outbound message."
+                    );
+                }
+            }
+            Err(e) => tracing::warn!(
+                counterparty = outbound.counterparty.to_string(),
+                kind,
+                error = e.to_string(),
+                "Could not send message. Sending again when the peer reconnects."
+            ),
+        }
+    }
+}
+
+/// Persists an outbound marker before handing the message to the outbox. The marker is
+/// cleared once the transport took the message, or for contract messages once the
+/// counterparty acted on it. Storage already wrote the marker of an Accept or Sign with
+/// the contract state, see [PendingOutbound::for_state_change]; this overwrites it.
+pub(crate) fn send_pending<S: DdkStorage>(
+    storage: &S,
+    outbox: &Outbox,
+    counter_party: PublicKey,
+    message: Message,
+) {
+    let pending = PendingOutbound::new(counter_party, &message);
+    send_outbound(storage, outbox, pending, message);
+}
+
+/// [send_pending] with the marker built by the caller.
+pub(crate) fn send_outbound<S: DdkStorage>(
+    storage: &S,
+    outbox: &Outbox,
+    pending: PendingOutbound,
+    message: Message,
+) {
+    let counter_party = pending.counterparty;
+    if let Err(e) = storage.save_pending_outbound(pending.clone()) {
+        tracing::error!(
+            error = e.to_string(),
+            "Could not persist pending outbound message."
+        );
+    }
+    audit::append(
+        storage,
+        AuditEntry::message(AuditEventType::MessageSent, counter_party, &message),
+    );
+    outbox.send(counter_party, message, Some(pending));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorageProvider;
+    use crate::transport::memory::MemoryTransport;
+    use crate::transport::PendingOutbound;
+    use bitcoin::secp256k1::{Secp256k1, SecretKey};
+    use dlc_messages::OfferDlc;
+
+    #[tokio::test]
+    async fn delivered_messages_record_the_attempt() {
+        let (alice, bob) = MemoryTransport::pair();
+        let stranger = SecretKey::from_slice(&[5u8; 32])
+            .unwrap()
+            .public_key(&Secp256k1::new());
+        let offer: OfferDlc =
+            serde_json::from_str(include_str!("../../tests/data/dlc/offer.json")).unwrap();
+        let storage = Arc::new(MemoryStorageProvider::new());
+        let outbox = Outbox::new();
+        for counterparty in [bob.public_key(), stranger] {
+            let message = Message::Offer(offer.clone());
+            let pending = PendingOutbound::new(counterparty, &message);
+            storage.save_pending_outbound(pending.clone()).unwrap();
+            outbox.send(counterparty, message, Some(pending));
+        }
+
+        let queue = outbox.take_queue().unwrap();
+        assert!(outbox.take_queue().is_none());
+        drop(outbox);
+        deliver(Arc::new(alice), storage.clone(), queue).await;
+
+        assert_eq!(bob.get_and_clear_received_messages().len(), 1);
+        // The offer is kept until Bob answers it, the one for the stranger was never sent.
+        let mut pending = storage.list_pending_outbound().unwrap();
+        pending.sort_by_key(|pending| pending.attempts);
+        assert_eq!(pending.len(), 2);
+        assert_eq!(
+            (pending[0].counterparty, pending[0].attempts),
+            (stranger, 0)
+        );
+        assert_eq!(
+            (pending[1].counterparty, pending[1].attempts),
+            (bob.public_key(), 1)
+        );
+        assert!(pending[1].last_attempt > 0);
+    }
+}
@@ -0,0 +1,232 @@
+//! Per-peer limits on inbound DLC messages, so a flooding or buggy peer cannot fill storage
+//! with offers. Peers that exceed a limit are banned for a while and their messages dropped
+//! before they are journaled.
+use bitcoin::secp256k1::PublicKey;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Default average messages per second a peer may send.
+pub const DEFAULT_MESSAGES_PER_SECOND: f64 = 5.0;
+/// Default messages a peer may send at once.
+pub const DEFAULT_MESSAGE_BURST: u32 = 50;
+/// Default offers from one peer that may wait for an answer at the same time.
+pub const DEFAULT_MAX_PENDING_OFFERS: usize = 20;
+/// Default time a peer that exceeded a limit is banned for.
+pub const DEFAULT_BAN_DURATION: Duration = Duration::from_secs(60 * 60);
+
+/// Limits on the messages a single peer may send.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PeerLimits {
+    /// Average messages per second. Defaults to 5.
+    pub messages_per_second: f64,
+    /// Messages that may arrive at once before the average applies. Defaults to 50.
+    pub burst: u32,
+    /// Offers received from the peer and stored unanswered. Defaults to 20.
+    pub max_pending_offers: usize,
+    /// How long a peer that exceeded a limit is banned. Defaults to one hour.
+    pub ban_duration: Duration,
+}
+
+impl Default for PeerLimits {
+    fn default() -> Self {
+        Self {
+            messages_per_second: DEFAULT_MESSAGES_PER_SECOND,
+            burst: DEFAULT_MESSAGE_BURST,
+            max_pending_offers: DEFAULT_MAX_PENDING_OFFERS,
+            ban_duration: DEFAULT_BAN_DURATION,
+        }
+    }
+}
+
+/// A peer whose messages are dropped until `until`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerBan {
+    pub pubkey: PublicKey,
+    /// Unix timestamp in seconds the ban ends at.
+    pub until: u64,
+    /// The limit the peer exceeded.
+    pub reason: String,
+}
+
+impl PeerBan {
+    pub fn is_active(&self, now: u64) -> bool {
+        self.until > now
+    }
+}
+
+/// Refills `rate` tokens per second up to `capacity`. Every message takes one token.
+#[derive(Debug, Clone)]
+pub(crate) struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    rate: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    /// A full bucket.
+    pub(crate) fn new(rate: f64, capacity: u32, now: Instant) -> TokenBucket {
+        TokenBucket {
+            capacity: capacity as f64,
+            tokens: capacity as f64,
+            rate,
+            refilled_at: now,
+        }
+    }
+
+    /// Take a token at `now`. False when the bucket is empty.
+    pub(crate) fn try_take(&mut self, now: Instant) -> bool {
+        let elapsed = now
+            .saturating_duration_since(self.refilled_at)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.refilled_at = now;
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+
+    /// Whether the bucket refilled to its capacity by `now`, so it is as good as a new one.
+    pub(crate) fn is_full(&self, now: Instant) -> bool {
+        let elapsed = now
+            .saturating_duration_since(self.refilled_at)
+            .as_secs_f64();
+        self.tokens + elapsed * self.rate >= self.capacity
+    }
+}
+
+/// A [TokenBucket] per peer, created full on the first message. Buckets that refilled are
+/// dropped, so only peers heard from within about two refill periods are kept.
+#[derive(Debug)]
+pub(crate) struct PeerRateLimiter {
+    limits: PeerLimits,
+    buckets: HashMap<PublicKey, TokenBucket>,
+    swept_at: Instant,
+}
+
+impl PeerRateLimiter {
+    pub(crate) fn new(limits: PeerLimits) -> PeerRateLimiter {
+        PeerRateLimiter {
+            limits,
+            buckets: HashMap::new(),
+            swept_at: Instant::now(),
+        }
+    }
+
+    pub(crate) fn limits(&self) -> &PeerLimits {
+        &self.limits
+    }
+
+    /// Whether a message from `peer` at `now` is within its rate.
+    pub(crate) fn allow(&mut self, peer: &PublicKey, now: Instant) -> bool {
+        self.sweep(now);
+        let limits = self.limits;
+        self.buckets
+            .entry(*peer)
+            .or_insert_with(|| TokenBucket::new(limits.messages_per_second, limits.burst, now))
+            .try_take(now)
+    }
+
+    /// Drop the buckets that refilled, at most once per refill period. A full bucket behaves
+    /// like the one created on the next message, so no limit is lost.
+    fn sweep(&mut self, now: Instant) {
+        if now.saturating_duration_since(self.swept_at) < self.refill_period() {
+            return;
+        }
+        self.buckets.retain(|_, bucket| !bucket.is_full(now));
+        self.swept_at = now;
+    }
+
+    /// Time an empty bucket takes to refill.
+    fn refill_period(&self) -> Duration {
+        let limits = &self.limits;
+        if limits.messages_per_second <= 0.0 {
+            return Duration::MAX;
+        }
+        Duration::try_from_secs_f64(limits.burst as f64 / limits.messages_per_second)
+            .unwrap_or(Duration::MAX)
+    }
+
+    /// Drop the bucket of a banned peer. It starts full once the ban ends.
+    pub(crate) fn forget(&mut self, peer: &PublicKey) {
+        self.buckets.remove(peer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::secp256k1::{Secp256k1, SecretKey};
+
+    #[test]
+    fn bucket_allows_a_burst_then_the_rate() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(2.0, 3, start);
+        assert!((0..3).all(|_| bucket.try_take(start)));
+        assert!(!bucket.try_take(start));
+
+        // Half a second refills one token at two per second.
+        let later = start + Duration::from_millis(500);
+        assert!(bucket.try_take(later));
+        assert!(!bucket.try_take(later));
+
+        // Idle time refills up to the burst, not beyond.
+        let idle = later + Duration::from_secs(60);
+        assert!((0..3).all(|_| bucket.try_take(idle)));
+        assert!(!bucket.try_take(idle));
+    }
+
+    #[test]
+    fn peers_have_separate_buckets() {
+        let secp = Secp256k1::new();
+        let peer =
+            |byte| PublicKey::from_secret_key(&secp, &SecretKey::from_slice(&[byte; 32]).unwrap());
+        let (alice, bob) = (peer(1), peer(2));
+        let mut limiter = PeerRateLimiter::new(PeerLimits {
+            messages_per_second: 1.0,
+            burst: 1,
+            ..Default::default()
+        });
+        let now = Instant::now();
+        assert!(limiter.allow(&alice, now));
+        assert!(!limiter.allow(&alice, now));
+        assert!(limiter.allow(&bob, now));
+
+        limiter.forget(&alice);
+        assert!(limiter.allow(&alice, now));
+    }
+
+    #[test]
+    fn idle_peers_are_evicted() {
+        let secp = Secp256k1::new();
+        let peer = |index: u32| {
+            let mut secret = [1u8; 32];
+            secret[..4].copy_from_slice(&index.to_be_bytes());
+            PublicKey::from_secret_key(&secp, &SecretKey::from_slice(&secret).unwrap())
+        };
+        let mut limiter = PeerRateLimiter::new(PeerLimits {
+            messages_per_second: 1.0,
+            burst: 2,
+            ..Default::default()
+        });
+        let start = Instant::now();
+        for index in 0..1_000 {
+            assert!(limiter.allow(&peer(index), start));
+        }
+        assert_eq!(limiter.buckets.len(), 1_000);
+
+        // A peer that used up its burst stays limited across a sweep.
+        let flooding = peer(1_000);
+        let later = start + Duration::from_secs(2);
+        assert!(limiter.allow(&flooding, later));
+        assert!(limiter.allow(&flooding, later));
+        assert!(!limiter.allow(&flooding, later));
+        assert_eq!(limiter.buckets.len(), 1);
+
+        let later = later + Duration::from_millis(500);
+        assert!(!limiter.allow(&flooding, later));
+    }
+}
@@ -0,0 +1,136 @@
+//! Connecting to stored peers on start, retrying the ones that are unreachable.
+use super::PeerInformation;
+use crate::events::{DdkEvent, EventBus};
+use crate::DdkTransport;
+use bitcoin::secp256k1::PublicKey;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(5);
+pub const MAX_RETRY_DELAY: Duration = Duration::from_secs(10 * 60);
+
+/// Connect to `peer`, doubling the delay between failed attempts up to `max_delay`. Returns
+/// once connected.
+pub async fn connect_with_backoff<T: DdkTransport>(
+    transport: Arc<T>,
+    events: Arc<EventBus>,
+    peer: PeerInformation,
+    initial_delay: Duration,
+    max_delay: Duration,
+) {
+    let pubkey = match PublicKey::from_str(&peer.pubkey) {
+        Ok(pubkey) => pubkey,
+        Err(e) => {
+            tracing::error!(pubkey = peer.pubkey, error = e.to_string(), "Stored peer has an invalid pubkey.");
+            return;
+        }
+    };
+
+    let mut delay = initial_delay;
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        match transport.connect(peer.clone()).await {
+            Ok(()) => {
+                tracing::info!(pubkey = peer.pubkey, host = peer.host, "Connected to stored peer.");
+                events.emit(DdkEvent::PeerConnected(pubkey));
+                return;
+            }
+            Err(e) => {
+                tracing::warn!(
+                    pubkey = peer.pubkey,
+                    host = peer.host,
+                    attempts,
+                    retry_in_secs = delay.as_secs(),
+                    error = e.to_string(),
+                    "Could not connect to stored peer."
+                );
+                events.emit(DdkEvent::PeerConnectionFailed { pubkey, attempts });
+            }
+        }
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(max_delay);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use dlc_messages::Message;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// Refuses the first `failures` connection attempts.
+    struct FlakyTransport {
+        failures: u32,
+        attempts: AtomicU32,
+    }
+
+    #[async_trait]
+    impl DdkTransport for FlakyTransport {
+        type PeerManager = ();
+        type MessageHandler = ();
+
+        fn name(&self) -> String {
+            "flaky".into()
+        }
+        async fn listen(&self) {}
+        fn message_handler(&self) -> Self::MessageHandler {}
+        fn peer_manager(&self) -> Self::PeerManager {}
+        fn process_messages(&self) {}
+        fn queue_message(&self, _counterparty: PublicKey, _message: Message) {}
+        fn get_and_clear_received_messages(&self) -> Vec<(PublicKey, Message)> {
+            vec![]
+        }
+        fn has_pending_messages(&self) -> bool {
+            false
+        }
+        async fn connect_outbound(&self, _pubkey: PublicKey, _host: &str) {}
+
+        async fn connect(&self, _peer: PeerInformation) -> anyhow::Result<()> {
+            if self.attempts.fetch_add(1, Ordering::SeqCst) < self.failures {
+                return Err(anyhow::anyhow!("Connection refused."));
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_until_connected() {
+        let transport = Arc::new(FlakyTransport {
+            failures: 2,
+            attempts: AtomicU32::new(0),
+        });
+        let events = Arc::new(EventBus::default());
+        let subscriber = events.subscribe();
+        let pubkey = PublicKey::from_str(
+            "02e6642fd69bd211f93f7f1f36ca51a26a5290eb2dd1b0d8279a87bb0d480c8443",
+        )
+        .unwrap();
+        let peer = PeerInformation {
+            pubkey: pubkey.to_string(),
+            host: "127.0.0.1:9000".into(),
+        };
+
+        connect_with_backoff(
+            transport.clone(),
+            events,
+            peer,
+            Duration::from_millis(1),
+            Duration::from_millis(2),
+        )
+        .await;
+
+        assert_eq!(transport.attempts.load(Ordering::SeqCst), 3);
+        let received = subscriber.try_iter().collect::<Vec<_>>();
+        assert_eq!(
+            received,
+            vec![
+                DdkEvent::PeerConnectionFailed { pubkey, attempts: 1 },
+                DdkEvent::PeerConnectionFailed { pubkey, attempts: 2 },
+                DdkEvent::PeerConnected(pubkey),
+            ]
+        );
+    }
+}
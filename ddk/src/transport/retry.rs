@@ -0,0 +1,266 @@
+//! Resend contract messages until the counterparty acts on them. Transports only tell whether
+//! they took a message, not whether the peer received it, so a contract that stays in the state
+//! we left it in means the message may have been lost.
+use super::outbox::Outbox;
+use super::{unix_now, PendingOutbound};
+use crate::events::{DdkEvent, EventBus};
+use crate::DdkStorage;
+use bitcoin::secp256k1::PublicKey;
+use dlc_manager::contract::Contract;
+use std::time::Duration;
+
+/// Default time between resends of a contract message.
+pub const DEFAULT_OUTBOUND_RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often unanswered contract messages are resent and when they are given up on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutboundRetryOptions {
+    /// Time between resends. Defaults to 60 seconds.
+    pub interval: Duration,
+    /// Sends the transport took before the message is reported with
+    /// [crate::events::DdkEvent::DeliveryFailed]. Defaults to 30.
+    pub max_attempts: u32,
+    /// Time after the message was created that it is given up on. Defaults to 24 hours.
+    pub expiry: Duration,
+}
+
+impl Default for OutboundRetryOptions {
+    fn default() -> Self {
+        Self {
+            interval: DEFAULT_OUTBOUND_RETRY_INTERVAL,
+            max_attempts: 30,
+            expiry: Duration::from_secs(60 * 60 * 24),
+        }
+    }
+}
+
+/// What to do with a pending contract message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryDecision {
+    /// The contract moved on, so the counterparty acted on the message.
+    Delivered,
+    /// The attempts or the time ran out.
+    Failed,
+    /// Send the message again.
+    Resend,
+    /// Sent too recently.
+    Wait,
+}
+
+/// Whether `contract` is still in the state we left it in when we sent a message of `kind`.
+pub fn awaits_delivery(kind: &str, contract: Option<&Contract>) -> bool {
+    matches!(
+        (kind, contract),
+        ("offer", Some(Contract::Offered(_)))
+            | ("accept", Some(Contract::Accepted(_)))
+            | ("sign", Some(Contract::Signed(_)))
+    )
+}
+
+/// Decide on a message for a contract, given the stored contract and `now` in unix seconds.
+pub fn decide(
+    pending: &PendingOutbound,
+    contract: Option<&Contract>,
+    now: u64,
+    options: &OutboundRetryOptions,
+) -> RetryDecision {
+    if !awaits_delivery(&pending.kind, contract) {
+        return RetryDecision::Delivered;
+    }
+    if pending.attempts >= options.max_attempts
+        || now >= pending.created_at.saturating_add(options.expiry.as_secs())
+    {
+        return RetryDecision::Failed;
+    }
+    let next_attempt = pending
+        .last_attempt
+        .saturating_add(options.interval.as_secs());
+    if now < next_attempt {
+        return RetryDecision::Wait;
+    }
+    RetryDecision::Resend
+}
+
+/// Hand the stored outbound messages to the outbox again, only those for `counter_party`
+/// when given.
+pub(crate) fn resend_pending<S: DdkStorage>(
+    storage: &S,
+    outbox: &Outbox,
+    counter_party: Option<PublicKey>,
+) {
+    let pending = match storage.list_pending_outbound() {
+        Ok(pending) => pending,
+        Err(e) => {
+            tracing::error!(
+                error = e.to_string(),
+                "Could not list pending outbound messages."
+            );
+            return;
+        }
+    };
+    for outbound in pending {
+        if counter_party.is_some_and(|counter_party| counter_party != outbound.counterparty) {
+            continue;
+        }
+        match outbound.message() {
+            Ok(message) => {
+                tracing::info!(
+                    kind = outbound.kind,
+                    counter_party = outbound.counterparty.to_string(),
+                    "Re-enqueueing pending outbound message."
+                );
+                outbox.send(outbound.counterparty, message, Some(outbound));
+            }
+            Err(e) => tracing::error!(
+                error = e.to_string(),
+                "Could not decode pending outbound message."
+            ),
+        }
+    }
+}
+
+/// Resend contract messages the counterparty has not acted on, and give up on the ones past
+/// [OutboundRetryOptions::max_attempts] or the expiry.
+pub(crate) fn retry_outbound<S: DdkStorage>(
+    storage: &S,
+    outbox: &Outbox,
+    events: &EventBus,
+    options: &OutboundRetryOptions,
+) {
+    let pending = match storage.list_pending_outbound() {
+        Ok(pending) => pending,
+        Err(e) => {
+            tracing::error!(
+                error = e.to_string(),
+                "Could not list pending outbound messages."
+            );
+            return;
+        }
+    };
+    let now = unix_now().as_secs();
+    for outbound in pending {
+        // Messages without a contract are sent again when the peer reconnects.
+        let Some(contract_id) = outbound.contract_id else {
+            continue;
+        };
+        let contract = match storage.get_contract(&contract_id) {
+            Ok(contract) => contract,
+            Err(e) => {
+                tracing::error!(
+                    error = e.to_string(),
+                    "Could not get contract of pending outbound message."
+                );
+                continue;
+            }
+        };
+        match decide(&outbound, contract.as_ref(), now, options) {
+            RetryDecision::Delivered => {
+                tracing::debug!(
+                    contract_id = hex::encode(contract_id),
+                    kind = outbound.kind,
+                    "Counterparty acted on message."
+                );
+                remove_outbound(storage, &outbound.id);
+            }
+            RetryDecision::Failed => {
+                tracing::warn!(
+                    counter_party = outbound.counterparty.to_string(),
+                    contract_id = hex::encode(contract_id),
+                    kind = outbound.kind,
+                    attempts = outbound.attempts,
+                    "Giving up on delivering message."
+                );
+                remove_outbound(storage, &outbound.id);
+                events.emit(DdkEvent::DeliveryFailed {
+                    counterparty: outbound.counterparty,
+                    contract_id,
+                    kind: outbound.kind,
+                    attempts: outbound.attempts,
+                });
+            }
+            RetryDecision::Resend => match outbound.message() {
+                Ok(message) => {
+                    tracing::info!(
+                        counter_party = outbound.counterparty.to_string(),
+                        contract_id = hex::encode(contract_id),
+                        kind = outbound.kind,
+                        attempts = outbound.attempts,
+                        "Resending message the counterparty has not acted on."
+                    );
+                    outbox.send(outbound.counterparty, message, Some(outbound));
+                }
+                Err(e) => tracing::error!(
+                    error = e.to_string(),
+                    "Could not decode pending outbound message."
+                ),
+            },
+            RetryDecision::Wait => {}
+        }
+    }
+}
+
+fn remove_outbound<S: DdkStorage>(storage: &S, id: &str) {
+    if let Err(e) = storage.remove_pending_outbound(id) {
+        tracing::error!(
+            error = e.to_string(),
+            "Could not clear pending outbound message."
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+    use dlc_manager::contract::ser::Serializable;
+    use dlc_manager::contract::signed_contract::SignedContract;
+    use dlc_messages::{Message, OfferDlc};
+
+    fn pending(message: Message) -> PendingOutbound {
+        let counterparty = PublicKey::from_secret_key(
+            &Secp256k1::new(),
+            &SecretKey::from_slice(&[1u8; 32]).unwrap(),
+        );
+        PendingOutbound::new(counterparty, &message)
+    }
+
+    fn offer() -> Message {
+        let offer: OfferDlc =
+            serde_json::from_str(include_str!("../../tests/data/dlc/offer.json")).unwrap();
+        Message::Offer(offer)
+    }
+
+    fn signed() -> Contract {
+        let mut cursor = ::lightning::io::Cursor::new(include_bytes!(
+            "../../tests/data/dlc_storage/sled/Signed"
+        ));
+        Contract::Signed(SignedContract::deserialize(&mut cursor).unwrap())
+    }
+
+    #[test]
+    fn messages_are_resent_until_the_contract_moves_on() {
+        let options = OutboundRetryOptions {
+            interval: Duration::from_secs(10),
+            max_attempts: 3,
+            expiry: Duration::from_secs(100),
+        };
+        let mut sign = pending(offer());
+        sign.kind = "sign".to_string();
+        let now = sign.created_at;
+        let contract = signed();
+        let check = |pending: &PendingOutbound, at| decide(pending, Some(&contract), at, &options);
+
+        assert_eq!(check(&sign, now), RetryDecision::Resend);
+        sign.record_attempt(now);
+        assert_eq!(check(&sign, now + 9), RetryDecision::Wait);
+        assert_eq!(check(&sign, now + 10), RetryDecision::Resend);
+
+        // The offer of a signed contract was answered, as was a message for a deleted contract.
+        assert_eq!(check(&pending(offer()), now), RetryDecision::Delivered);
+        assert_eq!(decide(&sign, None, now, &options), RetryDecision::Delivered);
+
+        assert_eq!(check(&sign, now + 100), RetryDecision::Failed);
+        sign.attempts = 3;
+        assert_eq!(check(&sign, now + 10), RetryDecision::Failed);
+    }
+}
@@ -0,0 +1,172 @@
+//! Length prefixed frames and the node key handshake.
+//!
+//! Every frame is a 4 byte big endian length followed by the payload. DLC messages are sent
+//! as [encode_message](crate::transport::encode_message) payloads, the message type followed
+//! by the message.
+//!
+//! Both sides open with a hello, their node id and a random nonce, then sign the other
+//! side's nonce with their node key. A peer is only registered once it proved it holds the
+//! key it claims. Handshake messages have a fixed size and no length prefix, so a peer that
+//! has not authenticated cannot make us allocate a large frame.
+//!
+//! The handshake only authenticates the connection. Without TLS, frames after it are neither
+//! encrypted nor integrity protected, and anyone on the path can alter or inject them. Use
+//! TLS between hosts that do not share a trusted network.
+use anyhow::anyhow;
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::key::rand::{thread_rng, Rng};
+use bitcoin::secp256k1::ecdsa::Signature;
+use bitcoin::secp256k1::{Message, PublicKey, Secp256k1, SecretKey};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Large enough for offers and accepts of contracts with many CETs.
+pub const MAX_FRAME_LEN: u32 = 32 * 1024 * 1024;
+
+const HANDSHAKE_TAG: &[u8] = b"ddk-tcp-handshake";
+const NONCE_LEN: usize = 32;
+/// Compressed node id followed by the nonce.
+const HELLO_LEN: usize = 33 + NONCE_LEN;
+/// Compact ECDSA signature.
+const SIGNATURE_LEN: usize = 64;
+
+pub async fn write_frame<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    payload: &[u8],
+) -> std::io::Result<()> {
+    let len = u32::try_from(payload.len())
+        .ok()
+        .filter(|len| *len <= MAX_FRAME_LEN)
+        .ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, "Frame is too large.")
+        })?;
+    writer.write_u32(len).await?;
+    writer.write_all(payload).await?;
+    writer.flush().await
+}
+
+pub async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> std::io::Result<Vec<u8>> {
+    let len = reader.read_u32().await?;
+    if len > MAX_FRAME_LEN {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Frame of {} bytes is too large.", len),
+        ));
+    }
+    let mut payload = vec![0u8; len as usize];
+    reader.read_exact(&mut payload).await?;
+    Ok(payload)
+}
+
+/// Authenticate both ends of a fresh connection. Returns the counterparty's node id. When
+/// dialing, `expected` is the node we meant to reach.
+pub async fn handshake<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    secret_key: &SecretKey,
+    expected: Option<PublicKey>,
+) -> anyhow::Result<PublicKey> {
+    let secp = Secp256k1::new();
+    let node_id = secret_key.public_key(&secp);
+    let nonce: [u8; NONCE_LEN] = thread_rng().gen();
+
+    let mut hello = [0u8; HELLO_LEN];
+    hello[..33].copy_from_slice(&node_id.serialize());
+    hello[33..].copy_from_slice(&nonce);
+    stream.write_all(&hello).await?;
+    stream.flush().await?;
+
+    let mut their_hello = [0u8; HELLO_LEN];
+    stream.read_exact(&mut their_hello).await?;
+    let counterparty = PublicKey::from_slice(&their_hello[..33])
+        .map_err(|_| anyhow!("Invalid handshake from peer."))?;
+    if let Some(expected) = expected {
+        if expected != counterparty {
+            return Err(anyhow!(
+                "Expected node {} but connected to {}.",
+                expected,
+                counterparty
+            ));
+        }
+    }
+
+    let signature = secp.sign_ecdsa(&challenge(&their_hello[33..], &node_id), secret_key);
+    stream.write_all(&signature.serialize_compact()).await?;
+    stream.flush().await?;
+
+    let mut their_signature = [0u8; SIGNATURE_LEN];
+    stream.read_exact(&mut their_signature).await?;
+    let their_signature = Signature::from_compact(&their_signature)?;
+    secp.verify_ecdsa(
+        &challenge(&nonce, &counterparty),
+        &their_signature,
+        &counterparty,
+    )
+    .map_err(|_| anyhow!("Peer {} did not prove its node key.", counterparty))?;
+
+    Ok(counterparty)
+}
+
+/// What `signer` signs to answer `nonce`. Committing to the signer keeps a signature from
+/// being reflected back as proof of another key.
+fn challenge(nonce: &[u8], signer: &PublicKey) -> Message {
+    let mut engine = sha256::Hash::engine();
+    engine.input(HANDSHAKE_TAG);
+    engine.input(nonce);
+    engine.input(&signer.serialize());
+    Message::from_digest(sha256::Hash::from_engine(engine).to_byte_array())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(byte: u8) -> SecretKey {
+        SecretKey::from_slice(&[byte; 32]).unwrap()
+    }
+
+    #[tokio::test]
+    async fn frames_round_trip_and_reject_oversized() {
+        let (mut a, mut b) = tokio::io::duplex(1024);
+        write_frame(&mut a, b"dlc").await.unwrap();
+        assert_eq!(read_frame(&mut b).await.unwrap(), b"dlc");
+
+        a.write_u32(MAX_FRAME_LEN + 1).await.unwrap();
+        assert!(read_frame(&mut b).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn handshake_authenticates_both_sides() {
+        let secp = Secp256k1::new();
+        let (mut a, mut b) = tokio::io::duplex(1024);
+        let (alice, bob) = (key(1), key(2));
+
+        let (dialer, listener) = tokio::join!(
+            handshake(&mut a, &alice, Some(bob.public_key(&secp))),
+            handshake(&mut b, &bob, None)
+        );
+        assert_eq!(dialer.unwrap(), bob.public_key(&secp));
+        assert_eq!(listener.unwrap(), alice.public_key(&secp));
+    }
+
+    #[tokio::test]
+    async fn handshake_reads_fixed_size_messages() {
+        let (mut a, mut b) = tokio::io::duplex(1024);
+        // A length prefix announcing the largest frame, then garbage.
+        b.write_u32(MAX_FRAME_LEN).await.unwrap();
+        b.write_all(&[0xff; HELLO_LEN - 4]).await.unwrap();
+
+        let err = handshake(&mut a, &key(1), None).await.unwrap_err();
+        assert_eq!(err.to_string(), "Invalid handshake from peer.");
+    }
+
+    #[tokio::test]
+    async fn dialing_the_wrong_node_fails() {
+        let secp = Secp256k1::new();
+        let (mut a, mut b) = tokio::io::duplex(1024);
+
+        let (dialer, _) = tokio::join!(
+            handshake(&mut a, &key(1), Some(key(3).public_key(&secp))),
+            tokio::time::timeout(std::time::Duration::from_secs(1), handshake(&mut b, &key(2), None))
+        );
+        assert!(dialer.is_err());
+    }
+}
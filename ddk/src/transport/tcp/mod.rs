@@ -0,0 +1,509 @@
+//! [DdkTransport] over plain TCP, optionally wrapped in TLS, for servers that already know
+//! each other. See [framing] for the wire format.
+//!
+//! Messages are queued per peer and only handed to a connection while it is up, so messages
+//! for a peer that is down stay pending until it reconnects. Peers added with
+//! [TcpTransport::add_peers] or [DdkTransport::connect_outbound] are redialed with
+//! exponential backoff.
+mod framing;
+#[cfg(feature = "tls")]
+mod tls;
+
+#[cfg(feature = "tls")]
+pub use tls::TlsConfig;
+
+use crate::config::SeedConfig;
+use crate::transport::{decode_message, encode_message, message_kind, PeerInformation};
+use crate::{DdkTransport, TransportError};
+use async_trait::async_trait;
+use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+use bitcoin::Network;
+use dlc_messages::Message;
+use framing::{handshake, read_frame, write_frame};
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const INITIAL_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(5 * 60);
+
+trait AsyncStream: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> AsyncStream for T {}
+
+pub struct TcpTransport {
+    inner: Arc<Inner>,
+    pub node_id: PublicKey,
+    pub bind_address: SocketAddr,
+}
+
+struct Inner {
+    secret_key: SecretKey,
+    #[cfg(feature = "tls")]
+    tls: Option<TlsConfig>,
+    received: Mutex<Vec<(PublicKey, Message)>>,
+    queues: Mutex<HashMap<PublicKey, VecDeque<Message>>>,
+    connections: Mutex<HashMap<PublicKey, Connection>>,
+    dialers: Mutex<HashMap<PublicKey, Dialer>>,
+    next_connection_id: AtomicU64,
+}
+
+struct Connection {
+    id: u64,
+    sender: UnboundedSender<Message>,
+}
+
+/// A peer we keep a connection to.
+struct Dialer {
+    host: String,
+    delay: Duration,
+    next_attempt: Instant,
+    dialing: bool,
+}
+
+impl TcpTransport {
+    /// Listen and dial without TLS. Peers are authenticated by their node key, but messages
+    /// are sent in the clear and without integrity protection.
+    pub fn new(
+        seed_config: &SeedConfig,
+        bind_address: SocketAddr,
+        network: Network,
+    ) -> anyhow::Result<TcpTransport> {
+        let inner = Inner::new(seed_config, network)?;
+        Ok(TcpTransport::from_inner(inner, bind_address))
+    }
+
+    /// Listen and dial with TLS. Every peer must use TLS as well.
+    #[cfg(feature = "tls")]
+    pub fn new_with_tls(
+        seed_config: &SeedConfig,
+        bind_address: SocketAddr,
+        tls: TlsConfig,
+        network: Network,
+    ) -> anyhow::Result<TcpTransport> {
+        let mut inner = Inner::new(seed_config, network)?;
+        inner.tls = Some(tls);
+        Ok(TcpTransport::from_inner(inner, bind_address))
+    }
+
+    fn from_inner(inner: Inner, bind_address: SocketAddr) -> TcpTransport {
+        let node_id = inner.secret_key.public_key(&Secp256k1::new());
+        TcpTransport {
+            inner: Arc::new(inner),
+            node_id,
+            bind_address,
+        }
+    }
+
+    /// Keep connections to peers, ex. the ones in [crate::DdkStorage::list_peers]. They are
+    /// dialed once the transport is listening.
+    pub fn add_peers(&self, peers: Vec<PeerInformation>) -> anyhow::Result<()> {
+        for peer in peers {
+            let pubkey = PublicKey::from_str(&peer.pubkey)?;
+            self.inner.add_dialer(pubkey, peer.host);
+        }
+        Ok(())
+    }
+}
+
+impl Inner {
+    fn new(seed_config: &SeedConfig, network: Network) -> anyhow::Result<Inner> {
+        let secret_key = crate::io::xprv_from_config(seed_config, network)?.private_key;
+        Ok(Inner {
+            secret_key,
+            #[cfg(feature = "tls")]
+            tls: None,
+            received: Mutex::new(vec![]),
+            queues: Mutex::new(HashMap::new()),
+            connections: Mutex::new(HashMap::new()),
+            dialers: Mutex::new(HashMap::new()),
+            next_connection_id: AtomicU64::new(0),
+        })
+    }
+
+    fn add_dialer(&self, pubkey: PublicKey, host: String) {
+        self.dialers.lock().unwrap().insert(
+            pubkey,
+            Dialer {
+                host,
+                delay: INITIAL_RECONNECT_DELAY,
+                next_attempt: Instant::now(),
+                dialing: false,
+            },
+        );
+    }
+
+    async fn accept(self: Arc<Self>, tcp: TcpStream, socket: SocketAddr) {
+        let authenticated = async {
+            let mut stream = self.server_stream(tcp).await?;
+            let counterparty = handshake(&mut stream, &self.secret_key, None).await?;
+            Ok::<_, anyhow::Error>((counterparty, stream))
+        };
+
+        match tokio::time::timeout(CONNECT_TIMEOUT, authenticated).await {
+            Ok(Ok((counterparty, stream))) => self.register(counterparty, stream),
+            Ok(Err(e)) => tracing::warn!(
+                connection = socket.to_string(),
+                error = e.to_string(),
+                "Refused inbound connection."
+            ),
+            Err(_) => tracing::warn!(
+                connection = socket.to_string(),
+                "Inbound connection timed out during the handshake."
+            ),
+        }
+    }
+
+    async fn dial(self: &Arc<Self>, pubkey: PublicKey, host: &str) -> anyhow::Result<()> {
+        let authenticated = async {
+            let tcp = TcpStream::connect(host).await?;
+            let mut stream = self.client_stream(tcp).await?;
+            let counterparty = handshake(&mut stream, &self.secret_key, Some(pubkey)).await?;
+            Ok::<_, anyhow::Error>((counterparty, stream))
+        };
+
+        let (counterparty, stream) = tokio::time::timeout(CONNECT_TIMEOUT, authenticated)
+            .await
+            .map_err(|_| anyhow::anyhow!("Connecting to {} timed out.", host))??;
+        self.register(counterparty, stream);
+        Ok(())
+    }
+
+    async fn server_stream(&self, tcp: TcpStream) -> anyhow::Result<Box<dyn AsyncStream>> {
+        #[cfg(feature = "tls")]
+        if let Some(tls) = &self.tls {
+            return Ok(Box::new(tls.acceptor()?.accept(tcp).await?));
+        }
+        Ok(Box::new(tcp))
+    }
+
+    async fn client_stream(&self, tcp: TcpStream) -> anyhow::Result<Box<dyn AsyncStream>> {
+        #[cfg(feature = "tls")]
+        if let Some(tls) = &self.tls {
+            let name = tokio_rustls::rustls::pki_types::ServerName::try_from(tls::SERVER_NAME)?;
+            return Ok(Box::new(tls.connector()?.connect(name, tcp).await?));
+        }
+        Ok(Box::new(tcp))
+    }
+
+    /// Start reading from and writing to an authenticated connection. Replaces an older
+    /// connection to the same peer.
+    fn register(self: &Arc<Self>, counterparty: PublicKey, stream: Box<dyn AsyncStream>) {
+        let id = self.next_connection_id.fetch_add(1, Ordering::Relaxed);
+        let (mut reader, mut writer) = tokio::io::split(stream);
+        let (sender, mut outbound) = unbounded_channel::<Message>();
+        self.connections
+            .lock()
+            .unwrap()
+            .insert(counterparty, Connection { id, sender });
+        if let Some(dialer) = self.dialers.lock().unwrap().get_mut(&counterparty) {
+            dialer.delay = INITIAL_RECONNECT_DELAY;
+        }
+        tracing::info!(counterparty = counterparty.to_string(), "Connected to peer.");
+
+        let inner = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let frame = match read_frame(&mut reader).await {
+                    Ok(frame) => frame,
+                    Err(e) => {
+                        tracing::debug!(error = e.to_string(), "Connection read failed.");
+                        break;
+                    }
+                };
+                match decode_message(&frame) {
+                    Ok(message) => inner.received.lock().unwrap().push((counterparty, message)),
+                    Err(e) => tracing::warn!(
+                        counterparty = counterparty.to_string(),
+                        error = e.to_string(),
+                        "Could not decode DLC message."
+                    ),
+                }
+            }
+            inner.disconnected(counterparty, id);
+        });
+
+        let inner = self.clone();
+        tokio::spawn(async move {
+            while let Some(message) = outbound.recv().await {
+                if let Err(e) = write_frame(&mut writer, &encode_message(&message)).await {
+                    tracing::warn!(
+                        counterparty = counterparty.to_string(),
+                        kind = message_kind(&message),
+                        error = e.to_string(),
+                        "Could not send DLC message. Queued for retry."
+                    );
+                    inner.requeue(counterparty, message, &mut outbound);
+                    inner.disconnected(counterparty, id);
+                    return;
+                }
+            }
+        });
+    }
+
+    /// Put a message that failed to send, and everything handed over after it, back in front
+    /// of the peer's queue.
+    fn requeue(
+        &self,
+        counterparty: PublicKey,
+        failed: Message,
+        outbound: &mut tokio::sync::mpsc::UnboundedReceiver<Message>,
+    ) {
+        let mut unsent = vec![failed];
+        while let Ok(message) = outbound.try_recv() {
+            unsent.push(message);
+        }
+        let mut queues = self.queues.lock().unwrap();
+        let queue = queues.entry(counterparty).or_default();
+        for message in unsent.into_iter().rev() {
+            queue.push_front(message);
+        }
+    }
+
+    fn disconnected(&self, counterparty: PublicKey, id: u64) {
+        let mut connections = self.connections.lock().unwrap();
+        if connections.get(&counterparty).map(|c| c.id) == Some(id) {
+            connections.remove(&counterparty);
+            tracing::info!(counterparty = counterparty.to_string(), "Disconnected from peer.");
+        }
+    }
+
+    /// Dial peers that are down and due for another attempt.
+    async fn reconnect(self: Arc<Self>) {
+        let mut timer = tokio::time::interval(INITIAL_RECONNECT_DELAY);
+        loop {
+            timer.tick().await;
+            let now = Instant::now();
+            let due = {
+                let connections = self.connections.lock().unwrap();
+                let mut dialers = self.dialers.lock().unwrap();
+                dialers
+                    .iter_mut()
+                    .filter(|(pubkey, dialer)| {
+                        !dialer.dialing
+                            && dialer.next_attempt <= now
+                            && !connections.contains_key(pubkey)
+                    })
+                    .map(|(pubkey, dialer)| {
+                        dialer.dialing = true;
+                        (*pubkey, dialer.host.clone())
+                    })
+                    .collect::<Vec<_>>()
+            };
+
+            for (pubkey, host) in due {
+                let inner = self.clone();
+                tokio::spawn(async move {
+                    let result = inner.dial(pubkey, &host).await;
+                    let mut dialers = inner.dialers.lock().unwrap();
+                    let Some(dialer) = dialers.get_mut(&pubkey) else {
+                        return;
+                    };
+                    dialer.dialing = false;
+                    if let Err(e) = result {
+                        dialer.next_attempt = Instant::now() + dialer.delay;
+                        dialer.delay = (dialer.delay * 2).min(MAX_RECONNECT_DELAY);
+                        tracing::warn!(
+                            counterparty = pubkey.to_string(),
+                            host,
+                            retry_in_secs = dialer.next_attempt.saturating_duration_since(Instant::now()).as_secs(),
+                            error = e.to_string(),
+                            "Could not connect to peer."
+                        );
+                    }
+                });
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl DdkTransport for TcpTransport {
+    type PeerManager = ();
+    type MessageHandler = ();
+
+    fn name(&self) -> String {
+        "tcp".into()
+    }
+
+    async fn listen(&self) {
+        let listener = match TcpListener::bind(self.bind_address).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                tracing::error!(
+                    address = self.bind_address.to_string(),
+                    error = e.to_string(),
+                    "Could not bind TCP transport."
+                );
+                return;
+            }
+        };
+        tokio::spawn(self.inner.clone().reconnect());
+
+        loop {
+            match listener.accept().await {
+                Ok((tcp, socket)) => {
+                    tracing::info!(connection = socket.to_string(), "Received connection.");
+                    tokio::spawn(self.inner.clone().accept(tcp, socket));
+                }
+                Err(e) => tracing::warn!(error = e.to_string(), "Could not accept connection."),
+            }
+        }
+    }
+
+    fn message_handler(&self) -> Self::MessageHandler {}
+
+    fn peer_manager(&self) -> Self::PeerManager {}
+
+    /// Hand queued messages to the connections that are up.
+    fn process_messages(&self) {
+        let connections = self.inner.connections.lock().unwrap();
+        let mut queues = self.inner.queues.lock().unwrap();
+        for (counterparty, queue) in queues.iter_mut() {
+            let Some(connection) = connections.get(counterparty) else {
+                continue;
+            };
+            while let Some(message) = queue.pop_front() {
+                if let Err(unsent) = connection.sender.send(message) {
+                    queue.push_front(unsent.0);
+                    break;
+                }
+            }
+        }
+        queues.retain(|_, queue| !queue.is_empty());
+    }
+
+    /// Hand the message to the connection. Fails when the peer is not connected, instead of
+    /// holding the message until it reconnects.
+    async fn send_message(
+        &self,
+        counterparty: PublicKey,
+        message: Message,
+    ) -> Result<(), TransportError> {
+        if !self.is_connected(&counterparty) {
+            return Err(TransportError(anyhow::anyhow!(
+                "Peer {} is not connected.",
+                counterparty
+            )));
+        }
+        self.queue_message(counterparty, message);
+        self.process_messages();
+        Ok(())
+    }
+
+    /// Peers with an open connection.
+    fn connected_peers(&self) -> Vec<PublicKey> {
+        self.inner.connections.lock().unwrap().keys().copied().collect()
+    }
+
+    fn queue_message(&self, counterparty: PublicKey, message: Message) {
+        self.inner
+            .queues
+            .lock()
+            .unwrap()
+            .entry(counterparty)
+            .or_default()
+            .push_back(message);
+    }
+
+    fn get_and_clear_received_messages(&self) -> Vec<(PublicKey, Message)> {
+        std::mem::take(&mut *self.inner.received.lock().unwrap())
+    }
+
+    /// True while any peer has queued messages, including peers that are down.
+    fn has_pending_messages(&self) -> bool {
+        self.inner
+            .queues
+            .lock()
+            .unwrap()
+            .values()
+            .any(|queue| !queue.is_empty())
+    }
+
+    /// Dial the peer now and keep redialing it when the connection drops.
+    async fn connect_outbound(&self, pubkey: PublicKey, host: &str) {
+        self.inner.add_dialer(pubkey, host.to_string());
+        if let Err(e) = self.inner.dial(pubkey, host).await {
+            tracing::warn!(
+                counterparty = pubkey.to_string(),
+                host,
+                error = e.to_string(),
+                "Could not connect to peer. Will retry."
+            );
+        }
+    }
+
+    async fn connect(&self, peer: PeerInformation) -> anyhow::Result<()> {
+        let pubkey = PublicKey::from_str(&peer.pubkey)?;
+        self.inner.add_dialer(pubkey, peer.host.clone());
+        self.inner.dial(pubkey, &peer.host).await
+    }
+
+    fn is_connected(&self, counterparty: &PublicKey) -> bool {
+        self.inner
+            .connections
+            .lock()
+            .unwrap()
+            .contains_key(counterparty)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dlc_messages::OfferDlc;
+
+    fn transport(seed: u8, port: u16) -> Arc<TcpTransport> {
+        let address = SocketAddr::from(([127, 0, 0, 1], port));
+        Arc::new(TcpTransport::new(&SeedConfig::Bytes([seed; 64]), address, Network::Regtest).unwrap())
+    }
+
+    async fn wait_for<F: Fn() -> bool>(condition: F) {
+        for _ in 0..100 {
+            if condition() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("Condition was not met in time.");
+    }
+
+    #[tokio::test]
+    async fn queued_messages_are_delivered_once_connected() {
+        let alice = transport(1, 19_711);
+        let bob = transport(2, 19_712);
+        let offer: OfferDlc =
+            serde_json::from_str(include_str!("../../../tests/data/dlc/offer.json")).unwrap();
+
+        // Bob is not listening yet, the message waits in the queue.
+        alice.queue_message(bob.node_id, Message::Offer(offer.clone()));
+        alice.process_messages();
+        assert!(alice.has_pending_messages());
+
+        let listener = bob.clone();
+        tokio::spawn(async move { listener.listen().await });
+        let dialer = alice.clone();
+        tokio::spawn(async move { dialer.listen().await });
+        alice
+            .add_peers(vec![PeerInformation {
+                pubkey: bob.node_id.to_string(),
+                host: bob.bind_address.to_string(),
+            }])
+            .unwrap();
+
+        wait_for(|| alice.is_connected(&bob.node_id) && bob.is_connected(&alice.node_id)).await;
+        alice.process_messages();
+        assert!(!alice.has_pending_messages());
+
+        wait_for(|| !bob.inner.received.lock().unwrap().is_empty()).await;
+        let received = bob.get_and_clear_received_messages();
+        assert_eq!(received[0].0, alice.node_id);
+        assert!(matches!(&received[0].1, Message::Offer(o) if *o == offer));
+    }
+}
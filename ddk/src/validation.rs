@@ -0,0 +1,595 @@
+//! Checks of a [ContractInput] against the announcements it settles on, run before an offer is
+//! sent so bad inputs fail with the field at fault instead of deep inside the manager.
+use bitcoin::key::XOnlyPublicKey;
+use bitcoin::Amount;
+use dlc_manager::contract::contract_input::{ContractInput, ContractInputInfo};
+use dlc_manager::contract::ContractDescriptor;
+use dlc_manager::payout_curve::{PayoutFunction, PayoutPoint};
+use dlc_messages::oracle_msgs::{EventDescriptor, OracleAnnouncement};
+
+/// A constraint a contract input violates. `contract_info` is the index in
+/// [ContractInput::contract_infos].
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    #[error("Total collateral must not be zero.")]
+    ZeroCollateral,
+    #[error("Total collateral {total} sats is more than the bitcoin supply.")]
+    CollateralTooLarge { total: u128 },
+    #[error("Fee rate must not be zero.")]
+    ZeroFeeRate,
+    #[error("Contract input has no contract info.")]
+    NoContractInfo,
+    #[error("Contract info {contract_info}: threshold {threshold} is not between one and the {oracles} oracles.")]
+    InvalidThreshold {
+        contract_info: usize,
+        threshold: u16,
+        oracles: usize,
+    },
+    #[error("Contract info {contract_info}: no announcement from oracle {oracle}.")]
+    MissingAnnouncement {
+        contract_info: usize,
+        oracle: XOnlyPublicKey,
+    },
+    #[error("Contract info {contract_info}: oracle {oracle} announced event {announced}, the input uses {expected}.")]
+    EventIdMismatch {
+        contract_info: usize,
+        oracle: XOnlyPublicKey,
+        expected: String,
+        announced: String,
+    },
+    #[error("Contract info {contract_info}: event {event_id} matured at {maturity}.")]
+    MaturityPassed {
+        contract_info: usize,
+        event_id: String,
+        maturity: u32,
+    },
+    #[error("Contract info {contract_info}: the descriptor does not match the event type of oracle {oracle}.")]
+    EventTypeMismatch {
+        contract_info: usize,
+        oracle: XOnlyPublicKey,
+    },
+    #[error("Contract info {contract_info}: enum descriptor has no outcomes.")]
+    NoOutcomes { contract_info: usize },
+    #[error(
+        "Contract info {contract_info}: outcome {outcome} is not announced by oracle {oracle}."
+    )]
+    UnknownOutcome {
+        contract_info: usize,
+        oracle: XOnlyPublicKey,
+        outcome: String,
+    },
+    #[error("Contract info {contract_info}: outcome {outcome} pays {payout} sats, the total collateral is {total_collateral}.")]
+    PayoutMismatch {
+        contract_info: usize,
+        outcome: String,
+        payout: u64,
+        total_collateral: u64,
+    },
+    #[error("Contract info {contract_info}: outcome {event_outcome} pays {payout} sats, more than the total collateral {total_collateral}.")]
+    PayoutExceedsCollateral {
+        contract_info: usize,
+        event_outcome: u64,
+        payout: u64,
+        total_collateral: u64,
+    },
+    #[error("Contract info {contract_info}: payout curve covers {start} to {end}, the outcomes range from 0 to {max_outcome}.")]
+    PayoutCurveDomain {
+        contract_info: usize,
+        start: u64,
+        end: u64,
+        max_outcome: u64,
+    },
+    #[error("Contract info {contract_info}: descriptor uses {descriptor} digits, oracle {oracle} announced {announcement}.")]
+    DigitCountMismatch {
+        contract_info: usize,
+        oracle: XOnlyPublicKey,
+        descriptor: usize,
+        announcement: usize,
+    },
+    #[error("Contract info {contract_info}: descriptor uses base {descriptor}, oracle {oracle} announced base {announcement}.")]
+    BaseMismatch {
+        contract_info: usize,
+        oracle: XOnlyPublicKey,
+        descriptor: usize,
+        announcement: usize,
+    },
+}
+
+/// Validate `contract_input` and the announcements of its oracles, returning every violated
+/// constraint. Signatures of the announcements are checked by the manager when the offer is
+/// created.
+pub fn validate_contract_input(
+    contract_input: &ContractInput,
+    announcements: &[OracleAnnouncement],
+) -> Result<(), Vec<ValidationError>> {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    validate_contract_input_at(contract_input, announcements, now)
+}
+
+pub(crate) fn validate_contract_input_at(
+    contract_input: &ContractInput,
+    announcements: &[OracleAnnouncement],
+    now: u64,
+) -> Result<(), Vec<ValidationError>> {
+    let mut errors = vec![];
+    let total = contract_input.offer_collateral as u128 + contract_input.accept_collateral as u128;
+    if total == 0 {
+        errors.push(ValidationError::ZeroCollateral);
+    } else if total > Amount::MAX_MONEY.to_sat() as u128 {
+        errors.push(ValidationError::CollateralTooLarge { total });
+    }
+    if contract_input.fee_rate == 0 {
+        errors.push(ValidationError::ZeroFeeRate);
+    }
+    if contract_input.contract_infos.is_empty() {
+        errors.push(ValidationError::NoContractInfo);
+    }
+
+    // Payout checks only make sense against a representable total.
+    let total_collateral = u64::try_from(total).ok();
+    for (index, info) in contract_input.contract_infos.iter().enumerate() {
+        validate_info(
+            index,
+            info,
+            total_collateral,
+            announcements,
+            now,
+            &mut errors,
+        );
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+fn validate_info(
+    contract_info: usize,
+    info: &ContractInputInfo,
+    total_collateral: Option<u64>,
+    announcements: &[OracleAnnouncement],
+    now: u64,
+    errors: &mut Vec<ValidationError>,
+) {
+    let oracles = &info.oracles;
+    if oracles.threshold == 0 || oracles.threshold as usize > oracles.public_keys.len() {
+        errors.push(ValidationError::InvalidThreshold {
+            contract_info,
+            threshold: oracles.threshold,
+            oracles: oracles.public_keys.len(),
+        });
+    }
+
+    let mut announced = vec![];
+    for oracle in &oracles.public_keys {
+        let from_oracle = || {
+            announcements
+                .iter()
+                .filter(|a| a.oracle_public_key == *oracle)
+        };
+        match from_oracle().find(|a| a.oracle_event.event_id == oracles.event_id) {
+            Some(announcement) => announced.push(announcement),
+            None => match from_oracle().next() {
+                Some(other) => errors.push(ValidationError::EventIdMismatch {
+                    contract_info,
+                    oracle: *oracle,
+                    expected: oracles.event_id.clone(),
+                    announced: other.oracle_event.event_id.clone(),
+                }),
+                None => errors.push(ValidationError::MissingAnnouncement {
+                    contract_info,
+                    oracle: *oracle,
+                }),
+            },
+        }
+    }
+
+    if let Some(maturity) = announced
+        .iter()
+        .map(|a| a.oracle_event.event_maturity_epoch)
+        .min()
+    {
+        if maturity as u64 <= now {
+            errors.push(ValidationError::MaturityPassed {
+                contract_info,
+                event_id: oracles.event_id.clone(),
+                maturity,
+            });
+        }
+    }
+
+    match &info.contract_descriptor {
+        ContractDescriptor::Enum(descriptor) => {
+            if descriptor.outcome_payouts.is_empty() {
+                errors.push(ValidationError::NoOutcomes { contract_info });
+            }
+            if let Some(total_collateral) = total_collateral {
+                for outcome in &descriptor.outcome_payouts {
+                    let payout = outcome.payout.offer.saturating_add(outcome.payout.accept);
+                    if payout != total_collateral {
+                        errors.push(ValidationError::PayoutMismatch {
+                            contract_info,
+                            outcome: outcome.outcome.clone(),
+                            payout,
+                            total_collateral,
+                        });
+                    }
+                }
+            }
+            for announcement in &announced {
+                let oracle = announcement.oracle_public_key;
+                let EventDescriptor::EnumEvent(event) = &announcement.oracle_event.event_descriptor
+                else {
+                    errors.push(ValidationError::EventTypeMismatch {
+                        contract_info,
+                        oracle,
+                    });
+                    continue;
+                };
+                for outcome in &descriptor.outcome_payouts {
+                    if !event.outcomes.contains(&outcome.outcome) {
+                        errors.push(ValidationError::UnknownOutcome {
+                            contract_info,
+                            oracle,
+                            outcome: outcome.outcome.clone(),
+                        });
+                    }
+                }
+            }
+        }
+        ContractDescriptor::Numerical(descriptor) => {
+            let info = &descriptor.oracle_numeric_infos;
+            for (position, announcement) in announced.iter().enumerate() {
+                let oracle = announcement.oracle_public_key;
+                let EventDescriptor::DigitDecompositionEvent(event) =
+                    &announcement.oracle_event.event_descriptor
+                else {
+                    errors.push(ValidationError::EventTypeMismatch {
+                        contract_info,
+                        oracle,
+                    });
+                    continue;
+                };
+                if event.base as usize != info.base {
+                    errors.push(ValidationError::BaseMismatch {
+                        contract_info,
+                        oracle,
+                        descriptor: info.base,
+                        announcement: event.base as usize,
+                    });
+                }
+                let digits = info.nb_digits.get(position).or(info.nb_digits.first());
+                if let Some(&digits) = digits {
+                    if digits != event.nb_digits as usize {
+                        errors.push(ValidationError::DigitCountMismatch {
+                            contract_info,
+                            oracle,
+                            descriptor: digits,
+                            announcement: event.nb_digits as usize,
+                        });
+                    }
+                }
+            }
+
+            let points = curve_points(&descriptor.payout_function);
+            if let Some(total_collateral) = total_collateral {
+                for point in &points {
+                    if point.outcome_payout > total_collateral {
+                        errors.push(ValidationError::PayoutExceedsCollateral {
+                            contract_info,
+                            event_outcome: point.event_outcome,
+                            payout: point.outcome_payout,
+                            total_collateral,
+                        });
+                    }
+                }
+            }
+            // Outcomes above the smallest oracle's range can never be attested.
+            let max_outcome = info.nb_digits.iter().min().map(|&digits| {
+                u32::try_from(digits)
+                    .ok()
+                    .and_then(|digits| (info.base as u64).checked_pow(digits))
+                    .map_or(u64::MAX, |outcomes| outcomes - 1)
+            });
+            if let (Some(first), Some(last), Some(max_outcome)) =
+                (points.first(), points.last(), max_outcome)
+            {
+                if first.event_outcome != 0 || last.event_outcome < max_outcome {
+                    errors.push(ValidationError::PayoutCurveDomain {
+                        contract_info,
+                        start: first.event_outcome,
+                        end: last.event_outcome,
+                        max_outcome,
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Points of a payout curve in order. The pieces keep their points private, so they are read
+/// back from the serialized function.
+fn curve_points(payout_function: &PayoutFunction) -> Vec<PayoutPoint> {
+    let Ok(value) = serde_json::to_value(payout_function) else {
+        return vec![];
+    };
+    let pieces = value["payoutFunctionPieces"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default();
+    let mut points = vec![];
+    for piece in pieces {
+        // Pieces are tagged with their curve type.
+        let Some(piece) = piece.as_object().and_then(|tagged| tagged.values().next()) else {
+            continue;
+        };
+        let piece_points = match piece.get("payoutPoints") {
+            Some(payout_points) => payout_points.as_array().cloned().unwrap_or_default(),
+            None => ["leftEndPoint", "rightEndPoint"]
+                .iter()
+                .filter_map(|end| piece.get(*end).cloned())
+                .collect(),
+        };
+        points.extend(
+            piece_points
+                .into_iter()
+                .filter_map(|point| serde_json::from_value::<PayoutPoint>(point).ok()),
+        );
+    }
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::bip32::Xpriv;
+    use bitcoin::secp256k1::SecretKey;
+    use bitcoin::Network;
+    use dlc::{EnumerationPayout, Payout};
+    use dlc_manager::contract::contract_input::OracleInput;
+    use dlc_manager::contract::enum_descriptor::EnumDescriptor;
+    use dlc_manager::contract::numerical_descriptor::NumericalDescriptor;
+    use dlc_manager::payout_curve::{
+        PayoutFunctionPiece, PolynomialPayoutCurvePiece, RoundingInterval, RoundingIntervals,
+    };
+    use dlc_messages::oracle_msgs::DigitDecompositionEventDescriptor;
+    use dlc_trie::OracleNumericInfo;
+    use kormir::storage::MemoryStorage;
+
+    const EVENT_ID: &str = "validation";
+    const MATURITY: u32 = 1_720_000_000;
+    const BEFORE_MATURITY: u64 = MATURITY as u64 - 60;
+
+    fn announcement() -> OracleAnnouncement {
+        let signing_key = SecretKey::from_slice(&[1u8; 32]).unwrap();
+        let nonce_xpriv = Xpriv::new_master(Network::Regtest, &[1u8; 32]).unwrap();
+        let oracle = kormir::Oracle::new(MemoryStorage::default(), signing_key, nonce_xpriv);
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(oracle.create_enum_event(
+                EVENT_ID.to_string(),
+                vec!["yes".into(), "no".into()],
+                MATURITY,
+            ))
+            .unwrap()
+    }
+
+    /// The enum announcement turned into a numeric event. Its signature no longer verifies,
+    /// which validation does not check.
+    fn numeric_announcement(nb_digits: u16) -> OracleAnnouncement {
+        let mut announcement = announcement();
+        announcement.oracle_event.event_descriptor =
+            EventDescriptor::DigitDecompositionEvent(DigitDecompositionEventDescriptor {
+                base: 2,
+                is_signed: false,
+                unit: "sats/sec".to_string(),
+                precision: 0,
+                nb_digits,
+            });
+        announcement
+    }
+
+    fn oracles(announcement: &OracleAnnouncement) -> OracleInput {
+        OracleInput {
+            public_keys: vec![announcement.oracle_public_key],
+            event_id: EVENT_ID.to_string(),
+            threshold: 1,
+        }
+    }
+
+    fn enum_input(announcement: &OracleAnnouncement) -> ContractInput {
+        let outcome_payouts = [("yes", 100_000), ("no", 0)]
+            .into_iter()
+            .map(|(outcome, offer)| EnumerationPayout {
+                outcome: outcome.to_string(),
+                payout: Payout {
+                    offer,
+                    accept: 100_000 - offer,
+                },
+            })
+            .collect();
+        ContractInput {
+            offer_collateral: 50_000,
+            accept_collateral: 50_000,
+            fee_rate: 2,
+            contract_infos: vec![ContractInputInfo {
+                contract_descriptor: ContractDescriptor::Enum(EnumDescriptor { outcome_payouts }),
+                oracles: oracles(announcement),
+            }],
+        }
+    }
+
+    fn numeric_input(announcement: &OracleAnnouncement, nb_digits: usize) -> ContractInput {
+        let max_outcome = (1u64 << nb_digits) - 1;
+        let points = vec![
+            PayoutPoint {
+                event_outcome: 0,
+                outcome_payout: 0,
+                extra_precision: 0,
+            },
+            PayoutPoint {
+                event_outcome: max_outcome,
+                outcome_payout: 100_000,
+                extra_precision: 0,
+            },
+        ];
+        let payout_function =
+            PayoutFunction::new(vec![PayoutFunctionPiece::PolynomialPayoutCurvePiece(
+                PolynomialPayoutCurvePiece::new(points).unwrap(),
+            )])
+            .unwrap();
+        ContractInput {
+            offer_collateral: 50_000,
+            accept_collateral: 50_000,
+            fee_rate: 2,
+            contract_infos: vec![ContractInputInfo {
+                contract_descriptor: ContractDescriptor::Numerical(NumericalDescriptor {
+                    payout_function,
+                    rounding_intervals: RoundingIntervals {
+                        intervals: vec![RoundingInterval {
+                            begin_interval: 0,
+                            rounding_mod: 1,
+                        }],
+                    },
+                    difference_params: None,
+                    oracle_numeric_infos: OracleNumericInfo {
+                        base: 2,
+                        nb_digits: vec![nb_digits],
+                    },
+                }),
+                oracles: oracles(announcement),
+            }],
+        }
+    }
+
+    #[test]
+    fn valid_inputs_pass() {
+        let announcement = announcement();
+        let input = enum_input(&announcement);
+        assert_eq!(
+            validate_contract_input_at(&input, &[announcement], BEFORE_MATURITY),
+            Ok(())
+        );
+
+        let announcement = numeric_announcement(20);
+        let input = numeric_input(&announcement, 20);
+        assert_eq!(
+            validate_contract_input_at(&input, &[announcement], BEFORE_MATURITY),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn total_collateral_must_match_payouts() {
+        let announcement = announcement();
+        let mut input = enum_input(&announcement);
+        input.accept_collateral = 60_000;
+        let errors =
+            validate_contract_input_at(&input, &[announcement], BEFORE_MATURITY).unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert!(errors.contains(&ValidationError::PayoutMismatch {
+            contract_info: 0,
+            outcome: "yes".to_string(),
+            payout: 100_000,
+            total_collateral: 110_000,
+        }));
+
+        let mut input = enum_input(&announcement());
+        input.offer_collateral = 0;
+        input.accept_collateral = 0;
+        input.fee_rate = 0;
+        let errors =
+            validate_contract_input_at(&input, &[announcement()], BEFORE_MATURITY).unwrap_err();
+        assert!(errors.contains(&ValidationError::ZeroCollateral));
+        assert!(errors.contains(&ValidationError::ZeroFeeRate));
+    }
+
+    #[test]
+    fn announcement_must_be_of_the_input_event() {
+        let announcement = announcement();
+        let mut input = enum_input(&announcement);
+        input.contract_infos[0].oracles.event_id = "other".to_string();
+        assert_eq!(
+            validate_contract_input_at(&input, &[announcement.clone()], BEFORE_MATURITY),
+            Err(vec![ValidationError::EventIdMismatch {
+                contract_info: 0,
+                oracle: announcement.oracle_public_key,
+                expected: "other".to_string(),
+                announced: EVENT_ID.to_string(),
+            }])
+        );
+        assert_eq!(
+            validate_contract_input_at(&enum_input(&announcement), &[], BEFORE_MATURITY),
+            Err(vec![ValidationError::MissingAnnouncement {
+                contract_info: 0,
+                oracle: announcement.oracle_public_key,
+            }])
+        );
+    }
+
+    #[test]
+    fn matured_event_is_rejected() {
+        let announcement = announcement();
+        let input = enum_input(&announcement);
+        assert_eq!(
+            validate_contract_input_at(&input, &[announcement], MATURITY as u64),
+            Err(vec![ValidationError::MaturityPassed {
+                contract_info: 0,
+                event_id: EVENT_ID.to_string(),
+                maturity: MATURITY,
+            }])
+        );
+    }
+
+    #[test]
+    fn numeric_descriptor_must_match_announced_digits() {
+        let announcement = numeric_announcement(18);
+        let input = numeric_input(&announcement, 20);
+        assert_eq!(
+            validate_contract_input_at(&input, &[announcement.clone()], BEFORE_MATURITY),
+            Err(vec![ValidationError::DigitCountMismatch {
+                contract_info: 0,
+                oracle: announcement.oracle_public_key,
+                descriptor: 20,
+                announcement: 18,
+            }])
+        );
+
+        // A curve over 18 digits does not cover a 20 digit event.
+        let announcement = numeric_announcement(20);
+        let mut input = numeric_input(&announcement, 18);
+        let ContractDescriptor::Numerical(descriptor) =
+            &mut input.contract_infos[0].contract_descriptor
+        else {
+            unreachable!()
+        };
+        descriptor.oracle_numeric_infos.nb_digits = vec![20];
+        assert_eq!(
+            validate_contract_input_at(&input, &[announcement], BEFORE_MATURITY),
+            Err(vec![ValidationError::PayoutCurveDomain {
+                contract_info: 0,
+                start: 0,
+                end: (1 << 18) - 1,
+                max_outcome: (1 << 20) - 1,
+            }])
+        );
+    }
+
+    #[test]
+    fn enum_event_cannot_settle_numeric_descriptor() {
+        let announcement = announcement();
+        let input = numeric_input(&announcement, 20);
+        assert_eq!(
+            validate_contract_input_at(&input, &[announcement.clone()], BEFORE_MATURITY),
+            Err(vec![ValidationError::EventTypeMismatch {
+                contract_info: 0,
+                oracle: announcement.oracle_public_key,
+            }])
+        );
+    }
+}
@@ -0,0 +1,102 @@
+//! Separate accounts for DLC collateral and everyday spending. Each account is a BIP-84
+//! keychain pair under its own account index, kept in its own BDK wallet so coins of one are
+//! never selected for the other.
+use bdk_chain::Balance;
+use bitcoin::bip32::Xpriv;
+use bitcoin::Network;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Directory of the spending account's wallet store inside the data directory.
+pub const SPENDING_WALLET_DB_DIR: &str = "wallet-db-spending";
+
+/// An account of the wallet.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Account {
+    /// Collateral float for contracts. Account index 0, the keychains the wallet always had,
+    /// so funds received before accounts existed stay here.
+    #[default]
+    Dlc,
+    /// Funds for ordinary sends. Account index 1.
+    Spending,
+}
+
+impl Account {
+    /// BIP-84 account index of the keychains.
+    pub fn index(&self) -> u32 {
+        match self {
+            Account::Dlc => 0,
+            Account::Spending => 1,
+        }
+    }
+}
+
+impl fmt::Display for Account {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Account::Dlc => write!(f, "dlc"),
+            Account::Spending => write!(f, "spending"),
+        }
+    }
+}
+
+/// Which accounts the wallet spends from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WalletAccounts {
+    /// Accounts whose UTXOs fund contracts. Defaults to the DLC account.
+    pub funding: Vec<Account>,
+    /// Account that pays ordinary sends, payments, and PSBTs built for external signing.
+    /// Defaults to the DLC account, where funds received before accounts existed are. Set it
+    /// to [Account::Spending] to keep sends from consuming collateral.
+    pub sends: Account,
+}
+
+impl Default for WalletAccounts {
+    fn default() -> Self {
+        Self {
+            funding: vec![Account::Dlc],
+            sends: Account::Dlc,
+        }
+    }
+}
+
+/// Balance of each account.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AccountBalances {
+    pub dlc: Balance,
+    /// Zero for a watch-only wallet, which only watches the DLC account.
+    pub spending: Balance,
+}
+
+impl AccountBalances {
+    pub fn get(&self, account: Account) -> &Balance {
+        match account {
+            Account::Dlc => &self.dlc,
+            Account::Spending => &self.spending,
+        }
+    }
+
+    /// Balance of both accounts together.
+    pub fn total(&self) -> Balance {
+        self.dlc.clone() + self.spending.clone()
+    }
+}
+
+/// External and internal descriptors of `account`.
+pub(crate) fn account_descriptors(
+    xprv: &Xpriv,
+    network: Network,
+    account: Account,
+) -> (String, String) {
+    let coin_type = if network == Network::Bitcoin { 0 } else { 1 };
+    let descriptor = |change| {
+        format!(
+            "wpkh({}/84'/{}'/{}'/{}/*)",
+            xprv,
+            coin_type,
+            account.index(),
+            change
+        )
+    };
+    (descriptor(0), descriptor(1))
+}
@@ -0,0 +1,247 @@
+//! Proof of address ownership with BIP-322 simple signatures.
+//!
+//! Only P2WPKH addresses are supported, which is every address the wallet creates.
+use bitcoin::absolute::LockTime;
+use bitcoin::blockdata::opcodes::all::OP_RETURN;
+use bitcoin::blockdata::opcodes::OP_0;
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::script::Builder;
+use bitcoin::secp256k1::{Message, Secp256k1, SecretKey, Signing, Verification};
+use bitcoin::sighash::{EcdsaSighashType, SighashCache};
+use bitcoin::transaction::Version;
+use bitcoin::{
+    ecdsa, Address, Amount, CompressedPublicKey, OutPoint, Script, ScriptBuf, Sequence,
+    Transaction, TxIn, TxOut, Txid, Witness,
+};
+use std::fmt;
+
+use crate::error::WalletError;
+
+const BIP322_TAG: &[u8] = b"BIP0322-signed-message";
+
+/// A BIP-322 simple signature proving control of an address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddressProof {
+    pub witness: Witness,
+}
+
+impl AddressProof {
+    /// Base64 of the consensus encoded witness, as specified by BIP-322.
+    pub fn to_base64(&self) -> String {
+        base64::encode(bitcoin::consensus::serialize(&self.witness))
+    }
+
+    pub fn from_base64(proof: &str) -> Result<AddressProof, WalletError> {
+        let bytes = base64::decode(proof).map_err(|e| WalletError::AddressProof(e.to_string()))?;
+        let witness = bitcoin::consensus::deserialize(&bytes)
+            .map_err(|e| WalletError::AddressProof(e.to_string()))?;
+        Ok(AddressProof { witness })
+    }
+}
+
+impl fmt::Display for AddressProof {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_base64())
+    }
+}
+
+/// Tagged hash of the message committed to by the virtual `to_spend` transaction.
+pub fn message_hash(message: &[u8]) -> [u8; 32] {
+    let tag = sha256::Hash::hash(BIP322_TAG);
+    let mut engine = sha256::Hash::engine();
+    engine.input(tag.as_ref());
+    engine.input(tag.as_ref());
+    engine.input(message);
+    sha256::Hash::from_engine(engine).to_byte_array()
+}
+
+fn to_spend(script_pubkey: &Script, message: &[u8]) -> Transaction {
+    Transaction {
+        version: Version(0),
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(Txid::all_zeros(), 0xFFFFFFFF),
+            script_sig: Builder::new()
+                .push_opcode(OP_0)
+                .push_slice(message_hash(message))
+                .into_script(),
+            sequence: Sequence::ZERO,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value: Amount::ZERO,
+            script_pubkey: script_pubkey.to_owned(),
+        }],
+    }
+}
+
+fn to_sign(to_spend: &Transaction, witness: Witness) -> Transaction {
+    Transaction {
+        version: Version(0),
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(to_spend.compute_txid(), 0),
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ZERO,
+            witness,
+        }],
+        output: vec![TxOut {
+            value: Amount::ZERO,
+            script_pubkey: Builder::new().push_opcode(OP_RETURN).into_script(),
+        }],
+    }
+}
+
+fn sighash_message(script_pubkey: &Script, message: &[u8]) -> Result<Message, WalletError> {
+    let to_sign = to_sign(&to_spend(script_pubkey, message), Witness::new());
+    let sighash = SighashCache::new(&to_sign)
+        .p2wpkh_signature_hash(0, script_pubkey, Amount::ZERO, EcdsaSighashType::All)
+        .map_err(|e| WalletError::AddressProof(e.to_string()))?;
+    Ok(Message::from_digest(sighash.to_byte_array()))
+}
+
+/// Sign `message` with the key of a P2WPKH `address`.
+pub fn sign_address_proof<C: Signing>(
+    secp: &Secp256k1<C>,
+    secret_key: &SecretKey,
+    address: &Address,
+    message: &[u8],
+) -> Result<AddressProof, WalletError> {
+    let public_key = secret_key.public_key(secp);
+    let script_pubkey = address.script_pubkey();
+    if script_pubkey != ScriptBuf::new_p2wpkh(&CompressedPublicKey(public_key).wpubkey_hash()) {
+        return Err(WalletError::AddressProof(
+            "Key does not match the address.".into(),
+        ));
+    }
+
+    let sighash = sighash_message(&script_pubkey, message)?;
+    let signature = ecdsa::Signature::sighash_all(secp.sign_ecdsa(&sighash, secret_key));
+    Ok(AddressProof {
+        witness: Witness::p2wpkh(&signature, &public_key),
+    })
+}
+
+/// Verify a BIP-322 simple signature of `message` by a P2WPKH `address`.
+pub fn verify_address_proof<C: Verification>(
+    secp: &Secp256k1<C>,
+    address: &Address,
+    message: &[u8],
+    proof: &AddressProof,
+) -> bool {
+    let script_pubkey = address.script_pubkey();
+    if !script_pubkey.is_p2wpkh() || proof.witness.len() != 2 {
+        return false;
+    }
+    let (Some(signature), Some(public_key)) = (proof.witness.nth(0), proof.witness.nth(1)) else {
+        return false;
+    };
+    let Ok(signature) = ecdsa::Signature::from_slice(signature) else {
+        return false;
+    };
+    let Ok(public_key) = CompressedPublicKey::from_slice(public_key) else {
+        return false;
+    };
+    if signature.sighash_type != EcdsaSighashType::All
+        || ScriptBuf::new_p2wpkh(&public_key.wpubkey_hash()) != script_pubkey
+    {
+        return false;
+    }
+
+    match sighash_message(&script_pubkey, message) {
+        Ok(sighash) => secp
+            .verify_ecdsa(&sighash, &signature.signature, &public_key.0)
+            .is_ok(),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TestWallet;
+    use bitcoin::{Network, PrivateKey};
+    use std::str::FromStr;
+
+    const VECTOR_ADDRESS: &str = "bc1q9vza2e8x573nczrlzms0wvx3gsqjx7vavgkx0l";
+    const VECTOR_KEY: &str = "L3VFeEujGtevx9w18HD1fhRbCH67Az2dpCymeRE1SoPK6XQtaN2k";
+
+    fn vector_address() -> Address {
+        Address::from_str(VECTOR_ADDRESS)
+            .unwrap()
+            .require_network(Network::Bitcoin)
+            .unwrap()
+    }
+
+    #[test]
+    fn bip322_message_hashes() {
+        assert_eq!(
+            hex::encode(message_hash(b"")),
+            "c90c269c4f8fcbe6880f72a721ddfbf1914268a794cbb21cfafee13770ae19f1"
+        );
+        assert_eq!(
+            hex::encode(message_hash(b"Hello World")),
+            "f0eb03b1a75ac6d9847f55c624a99169b5dccba2a31f5b23bea77ba270de0a7a"
+        );
+    }
+
+    #[test]
+    fn bip322_vector_verifies() {
+        let secp = Secp256k1::new();
+        let proof = AddressProof::from_base64("AkcwRAIgZRfIY3p7/DoVTty6YZbWS71bc5Vct9p9Fia83eRmw2QCICK/ENGfwLtptFluMGs2KsqoNSk89pO7F29zJLUx9a/sASECx/EgAxlkQpQ9hYjgGu6EBCPMVPwVIVJqO4XCsMvViHI=").unwrap();
+        assert!(verify_address_proof(&secp, &vector_address(), b"Hello World", &proof));
+        assert!(!verify_address_proof(&secp, &vector_address(), b"Hello World!", &proof));
+    }
+
+    #[test]
+    fn sign_and_verify_round_trip() {
+        let secp = Secp256k1::new();
+        let key = PrivateKey::from_wif(VECTOR_KEY).unwrap();
+        let proof = sign_address_proof(&secp, &key.inner, &vector_address(), b"challenge").unwrap();
+        let proof = AddressProof::from_base64(&proof.to_base64()).unwrap();
+        assert!(verify_address_proof(&secp, &vector_address(), b"challenge", &proof));
+    }
+
+    #[test]
+    fn owned_address_proof() {
+        let test = TestWallet::create_wallet("address_proof_owned");
+        let address = test.wallet.new_external_address().unwrap().address;
+        assert!(test.wallet.is_mine(&address).unwrap());
+
+        let proof = test.wallet.sign_address_proof(&address, b"challenge").unwrap();
+        assert!(test.wallet.verify_address_proof(&address, b"challenge", &proof));
+    }
+
+    #[test]
+    fn tampered_proof_fails() {
+        let test = TestWallet::create_wallet("address_proof_tampered");
+        let address = test.wallet.new_external_address().unwrap().address;
+        let proof = test.wallet.sign_address_proof(&address, b"challenge").unwrap();
+        assert!(!test.wallet.verify_address_proof(&address, b"other challenge", &proof));
+
+        let mut signature = proof.witness.nth(0).unwrap().to_vec();
+        signature[10] ^= 1;
+        let tampered = AddressProof {
+            witness: Witness::from_slice(&[signature, proof.witness.nth(1).unwrap().to_vec()]),
+        };
+        assert!(!test.wallet.verify_address_proof(&address, b"challenge", &tampered));
+    }
+
+    #[test]
+    fn refuses_unowned_address() {
+        let test = TestWallet::create_wallet("address_proof_unowned");
+        let other = TestWallet::create_wallet("address_proof_unowned_other");
+        let address = other.wallet.new_external_address().unwrap().address;
+        assert!(!test.wallet.is_mine(&address).unwrap());
+        assert!(test.wallet.sign_address_proof(&address, b"challenge").is_err());
+    }
+
+    #[test]
+    fn refuses_wrong_network() {
+        let test = TestWallet::create_wallet("address_proof_network");
+        let address = test.wallet.new_external_address().unwrap().address;
+        let mainnet = Address::from_script(&address.script_pubkey(), Network::Bitcoin).unwrap();
+        assert!(!test.wallet.is_mine(&mainnet).unwrap());
+        assert!(test.wallet.sign_address_proof(&mainnet, b"challenge").is_err());
+    }
+}
@@ -0,0 +1,440 @@
+//! Coin selection shared by DLC funding and regular sends.
+use bdk_chain::ChainPosition;
+use bdk_wallet::LocalOutput;
+use bitcoin::key::rand::{rngs::OsRng, seq::SliceRandom};
+use bitcoin::{Amount, FeeRate, OutPoint};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use crate::error::WalletError;
+
+/// Virtual size of a P2WPKH input.
+pub const P2WPKH_INPUT_VBYTES: u64 = 68;
+/// Virtual size of a P2WPKH output.
+pub const P2WPKH_OUTPUT_VBYTES: u64 = 31;
+/// Smallest P2WPKH change output that is relayed.
+const DUST_LIMIT_SATS: i64 = 294;
+/// Maximum branches explored by branch and bound before falling back to largest first.
+const BNB_MAX_TRIES: usize = 100_000;
+
+/// How the wallet picks UTXOs to fund a transaction.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CoinSelectionStrategy {
+    /// Look for an input set that avoids a change output. Falls back to `LargestFirst`.
+    #[default]
+    BranchAndBound,
+    /// Fewest inputs, lowest fees.
+    LargestFirst,
+    /// Spend the oldest confirmed UTXOs first to consolidate.
+    OldestFirst,
+    /// Random order so UTXOs are not linked by a predictable pattern.
+    Random,
+}
+
+/// Value of a UTXO after paying for its own input at `fee_rate`.
+fn effective_value(utxo: &LocalOutput, fee_rate: FeeRate) -> i64 {
+    let input_fee = fee_rate.fee_vb(P2WPKH_INPUT_VBYTES).unwrap_or(Amount::MAX_MONEY);
+    utxo.txout.value.to_sat() as i64 - input_fee.to_sat() as i64
+}
+
+fn confirmation_height(utxo: &LocalOutput) -> u32 {
+    match &utxo.chain_position {
+        ChainPosition::Confirmed(anchor) => anchor.block_id.height,
+        ChainPosition::Unconfirmed(_) => u32::MAX,
+    }
+}
+
+/// Total value that can be spent at `fee_rate`, ignoring UTXOs that cost more to spend than
+/// they are worth.
+pub fn max_spendable(
+    candidates: &[LocalOutput],
+    fee_rate: FeeRate,
+    exclude: &HashSet<OutPoint>,
+) -> Amount {
+    let total = candidates
+        .iter()
+        .filter(|utxo| !exclude.contains(&utxo.outpoint))
+        .map(|utxo| effective_value(utxo, fee_rate))
+        .filter(|value| *value > 0)
+        .sum::<i64>();
+    Amount::from_sat(total as u64)
+}
+
+/// What the DLC manager asks the wallet to select when we fund `collateral` at `fee_rate`
+/// sats/vbyte: the collateral and our half of the fees both parties share.
+pub fn dlc_funding_target(collateral: u64, fee_rate: u64) -> Result<Amount, WalletError> {
+    let common_fee =
+        dlc::util::get_common_fee(fee_rate).map_err(|e| WalletError::Fee(e.to_string()))?;
+    Ok(Amount::from_sat(collateral) + Amount::from_sat(common_fee.div_ceil(2)))
+}
+
+/// Select UTXOs whose value after input fees covers `target`. UTXOs in `exclude` are never
+/// selected.
+pub fn select_coins(
+    strategy: CoinSelectionStrategy,
+    candidates: Vec<LocalOutput>,
+    target: Amount,
+    fee_rate: FeeRate,
+    exclude: &HashSet<OutPoint>,
+) -> Result<Vec<LocalOutput>, WalletError> {
+    let mut candidates = candidates
+        .into_iter()
+        .filter(|utxo| !exclude.contains(&utxo.outpoint) && effective_value(utxo, fee_rate) > 0)
+        .collect::<Vec<_>>();
+
+    let available = max_spendable(&candidates, fee_rate, exclude);
+    if available < target {
+        return Err(WalletError::InsufficientFunds {
+            needed: target,
+            available,
+        });
+    }
+
+    match strategy {
+        CoinSelectionStrategy::BranchAndBound => {
+            if let Some(selected) = branch_and_bound(&candidates, target, fee_rate) {
+                return Ok(selected);
+            }
+            candidates.sort_by_key(|utxo| std::cmp::Reverse(utxo.txout.value));
+        }
+        CoinSelectionStrategy::LargestFirst => {
+            candidates.sort_by_key(|utxo| std::cmp::Reverse(utxo.txout.value));
+        }
+        CoinSelectionStrategy::OldestFirst => {
+            candidates.sort_by_key(confirmation_height);
+        }
+        CoinSelectionStrategy::Random => {
+            candidates.shuffle(&mut OsRng);
+        }
+    }
+
+    Ok(accumulate(candidates, target, fee_rate))
+}
+
+/// Add candidates in order until `target` is covered. Keeps adding while the change would be
+/// dust, when there are candidates left.
+fn accumulate(candidates: Vec<LocalOutput>, target: Amount, fee_rate: FeeRate) -> Vec<LocalOutput> {
+    let target = target.to_sat() as i64;
+    let change_fee = fee_rate
+        .fee_vb(P2WPKH_OUTPUT_VBYTES)
+        .unwrap_or(Amount::MAX_MONEY)
+        .to_sat() as i64;
+    let mut selected_value = 0;
+    let mut selected = vec![];
+    for utxo in candidates {
+        let change = selected_value - target - change_fee;
+        if selected_value >= target && !(0..DUST_LIMIT_SATS).contains(&change) {
+            break;
+        }
+        selected_value += effective_value(&utxo, fee_rate);
+        selected.push(utxo);
+    }
+    selected
+}
+
+/// Depth first search for an input set within the cost of a change output above `target`.
+fn branch_and_bound(
+    candidates: &[LocalOutput],
+    target: Amount,
+    fee_rate: FeeRate,
+) -> Option<Vec<LocalOutput>> {
+    let mut sorted = candidates
+        .iter()
+        .map(|utxo| (effective_value(utxo, fee_rate), utxo))
+        .collect::<Vec<_>>();
+    sorted.sort_by_key(|(value, _)| std::cmp::Reverse(*value));
+
+    let cost_of_change = fee_rate
+        .fee_vb(P2WPKH_INPUT_VBYTES + P2WPKH_OUTPUT_VBYTES)?
+        .to_sat() as i64;
+    let target = target.to_sat() as i64;
+    let upper_bound = target + cost_of_change;
+
+    // remaining[i] is the value of sorted[i..].
+    let mut remaining = vec![0i64; sorted.len() + 1];
+    for i in (0..sorted.len()).rev() {
+        remaining[i] = remaining[i + 1] + sorted[i].0;
+    }
+
+    let mut tries = 0;
+    let mut selection = vec![];
+    let found = bnb_search(
+        &sorted,
+        &remaining,
+        0,
+        0,
+        target,
+        upper_bound,
+        &mut selection,
+        &mut tries,
+    );
+
+    found.then(|| selection.iter().map(|i| sorted[*i].1.clone()).collect())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn bnb_search(
+    sorted: &[(i64, &LocalOutput)],
+    remaining: &[i64],
+    index: usize,
+    value: i64,
+    target: i64,
+    upper_bound: i64,
+    selection: &mut Vec<usize>,
+    tries: &mut usize,
+) -> bool {
+    *tries += 1;
+    if *tries > BNB_MAX_TRIES || value > upper_bound || value + remaining[index] < target {
+        return false;
+    }
+    if value >= target {
+        return true;
+    }
+    if index == sorted.len() {
+        return false;
+    }
+
+    selection.push(index);
+    if bnb_search(
+        sorted,
+        remaining,
+        index + 1,
+        value + sorted[index].0,
+        target,
+        upper_bound,
+        selection,
+        tries,
+    ) {
+        return true;
+    }
+    selection.pop();
+
+    bnb_search(
+        sorted,
+        remaining,
+        index + 1,
+        value,
+        target,
+        upper_bound,
+        selection,
+        tries,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bdk_chain::{BlockId, ConfirmationBlockTime};
+    use bdk_wallet::KeychainKind;
+    use bitcoin::hashes::Hash;
+    use bitcoin::{BlockHash, ScriptBuf, TxOut, Txid};
+    use proptest::prelude::*;
+
+    const STRATEGIES: [CoinSelectionStrategy; 4] = [
+        CoinSelectionStrategy::BranchAndBound,
+        CoinSelectionStrategy::LargestFirst,
+        CoinSelectionStrategy::OldestFirst,
+        CoinSelectionStrategy::Random,
+    ];
+
+    fn utxo(vout: u32, value: u64, height: u32) -> LocalOutput {
+        LocalOutput {
+            outpoint: OutPoint::new(Txid::all_zeros(), vout),
+            txout: TxOut {
+                value: Amount::from_sat(value),
+                script_pubkey: ScriptBuf::new(),
+            },
+            keychain: KeychainKind::External,
+            is_spent: false,
+            derivation_index: vout,
+            chain_position: ChainPosition::Confirmed(ConfirmationBlockTime {
+                block_id: BlockId {
+                    height,
+                    hash: BlockHash::all_zeros(),
+                },
+                confirmation_time: height as u64,
+            }),
+        }
+    }
+
+    fn utxos(values: &[(u64, u32)]) -> Vec<LocalOutput> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(vout, (value, height))| utxo(vout as u32, *value, *height))
+            .collect()
+    }
+
+    proptest! {
+        #[test]
+        fn every_strategy_covers_target(
+            values in prop::collection::vec((1_000u64..1_000_000, 0u32..1_000), 1..30),
+            target in 1_000u64..5_000_000,
+            sat_per_vb in 1u64..50,
+            excluded in prop::collection::hash_set(0u32..30, 0..5),
+        ) {
+            let fee_rate = FeeRate::from_sat_per_vb_unchecked(sat_per_vb);
+            let candidates = utxos(&values);
+            let exclude = excluded
+                .iter()
+                .map(|vout| OutPoint::new(Txid::all_zeros(), *vout))
+                .collect::<HashSet<_>>();
+            let target = Amount::from_sat(target);
+            let available = max_spendable(&candidates, fee_rate, &exclude);
+
+            for strategy in STRATEGIES {
+                let result = select_coins(strategy, candidates.clone(), target, fee_rate, &exclude);
+                if available < target {
+                    prop_assert!(result.is_err());
+                    continue;
+                }
+                let selected = result.unwrap();
+                let value = selected.iter().map(|u| effective_value(u, fee_rate)).sum::<i64>();
+                prop_assert!(value >= target.to_sat() as i64);
+                prop_assert!(selected.iter().all(|u| !exclude.contains(&u.outpoint)));
+            }
+        }
+    }
+
+    #[test]
+    fn dlc_funding_pays_for_its_inputs() {
+        let fee_rate = 10;
+        let target = dlc_funding_target(50_000, fee_rate).unwrap();
+        assert!(target > Amount::from_sat(50_000));
+        let input_fee = P2WPKH_INPUT_VBYTES * fee_rate;
+        let select = |value: u64| {
+            select_coins(
+                CoinSelectionStrategy::BranchAndBound,
+                utxos(&[(value, 1)]),
+                target,
+                FeeRate::from_sat_per_vb_unchecked(fee_rate),
+                &HashSet::new(),
+            )
+        };
+
+        // Exactly the collateral, our fees and the fee of the input.
+        assert_eq!(select(target.to_sat() + input_fee).unwrap().len(), 1);
+        // Enough for the collateral and shared fees, but not for spending the input.
+        let Err(WalletError::InsufficientFunds { needed, available }) =
+            select(target.to_sat() + input_fee - 1)
+        else {
+            panic!("Selection covered the target.");
+        };
+        assert_eq!(needed, target);
+        assert_eq!(available, target - Amount::from_sat(1));
+    }
+
+    #[test]
+    fn largest_first_uses_fewest_inputs() {
+        let candidates = utxos(&[(10_000, 1), (50_000, 2), (20_000, 3)]);
+        let selected = select_coins(
+            CoinSelectionStrategy::LargestFirst,
+            candidates,
+            Amount::from_sat(40_000),
+            FeeRate::from_sat_per_vb_unchecked(1),
+            &HashSet::new(),
+        )
+        .unwrap();
+        assert_eq!(selected.len(), 1);
+        assert_eq!(selected[0].txout.value, Amount::from_sat(50_000));
+    }
+
+    #[test]
+    fn small_amount_does_not_consume_wallet() {
+        let candidates = utxos(&(0..10).map(|i| (100_000, i)).collect::<Vec<_>>());
+        for strategy in STRATEGIES {
+            let selected = select_coins(
+                strategy,
+                candidates.clone(),
+                Amount::from_sat(50_000),
+                FeeRate::from_sat_per_vb_unchecked(1),
+                &HashSet::new(),
+            )
+            .unwrap();
+            assert_eq!(selected.len(), 1, "{:?}", strategy);
+        }
+    }
+
+    #[test]
+    fn fee_rate_changes_selection_of_small_inputs() {
+        let candidates = utxos(&(0..5).map(|i| (1_000, i)).collect::<Vec<_>>());
+        let select = |sat_per_vb| {
+            select_coins(
+                CoinSelectionStrategy::LargestFirst,
+                candidates.clone(),
+                Amount::from_sat(2_000),
+                FeeRate::from_sat_per_vb_unchecked(sat_per_vb),
+                &HashSet::new(),
+            )
+            .unwrap()
+            .len()
+        };
+        assert_eq!(select(1), 3);
+        assert_eq!(select(5), 4);
+    }
+
+    #[test]
+    fn avoids_dust_change() {
+        let candidates = utxos(&[(50_100, 1), (10_000, 2)]);
+        let selected = select_coins(
+            CoinSelectionStrategy::LargestFirst,
+            candidates,
+            Amount::from_sat(50_000),
+            FeeRate::from_sat_per_vb_unchecked(1),
+            &HashSet::new(),
+        )
+        .unwrap();
+        assert_eq!(selected.len(), 2);
+    }
+
+    #[test]
+    fn oldest_first_spends_oldest() {
+        let candidates = utxos(&[(50_000, 30), (50_000, 10), (50_000, 20)]);
+        let selected = select_coins(
+            CoinSelectionStrategy::OldestFirst,
+            candidates,
+            Amount::from_sat(40_000),
+            FeeRate::from_sat_per_vb_unchecked(1),
+            &HashSet::new(),
+        )
+        .unwrap();
+        assert_eq!(confirmation_height(&selected[0]), 10);
+    }
+
+    #[test]
+    fn branch_and_bound_avoids_change() {
+        let fee_rate = FeeRate::from_sat_per_vb_unchecked(1);
+        let candidates = utxos(&[(100_000, 1), (30_068, 2), (20_068, 3)]);
+        let selected = select_coins(
+            CoinSelectionStrategy::BranchAndBound,
+            candidates,
+            Amount::from_sat(50_000),
+            fee_rate,
+            &HashSet::new(),
+        )
+        .unwrap();
+        let value = selected.iter().map(|u| effective_value(u, fee_rate)).sum::<i64>();
+        assert_eq!(value, 50_000);
+    }
+
+    #[test]
+    fn random_varies_across_runs() {
+        let candidates = utxos(&(0..20).map(|i| (10_000, i)).collect::<Vec<_>>());
+        let selections = (0..20)
+            .map(|_| {
+                select_coins(
+                    CoinSelectionStrategy::Random,
+                    candidates.clone(),
+                    Amount::from_sat(20_000),
+                    FeeRate::from_sat_per_vb_unchecked(1),
+                    &HashSet::new(),
+                )
+                .unwrap()
+                .iter()
+                .map(|u| u.outpoint)
+                .collect::<Vec<_>>()
+            })
+            .collect::<HashSet<_>>();
+        assert!(selections.len() > 1);
+    }
+}
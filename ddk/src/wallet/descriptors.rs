@@ -0,0 +1,47 @@
+//! Public descriptors of the wallet, for watching it from another wallet such as Sparrow or
+//! Specter.
+use crate::error::WalletError;
+use bdk_chain::ChainPosition;
+use bdk_wallet::descriptor::{Descriptor, DescriptorPublicKey};
+use bdk_wallet::{KeychainKind, Wallet};
+use bitcoin::secp256k1::PublicKey;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// External and internal descriptors without private keys. Both carry their checksum.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WalletDescriptors {
+    pub external: String,
+    pub internal: String,
+    /// Height of the first confirmed wallet transaction. Watching wallets can start their
+    /// rescan there. None when nothing confirmed yet.
+    pub birthday_height: Option<u32>,
+}
+
+pub(crate) fn wallet_descriptors(wallet: &Wallet) -> WalletDescriptors {
+    let birthday_height = wallet
+        .transactions()
+        .filter_map(|tx| match tx.chain_position {
+            ChainPosition::Confirmed(anchor) => Some(anchor.block_id.height),
+            ChainPosition::Unconfirmed(_) => None,
+        })
+        .min();
+    WalletDescriptors {
+        external: wallet.public_descriptor(KeychainKind::External).to_string(),
+        internal: wallet.public_descriptor(KeychainKind::Internal).to_string(),
+        birthday_height,
+    }
+}
+
+/// Public key of the account the descriptor derives from. Descriptors with private keys are
+/// rejected so secrets are never loaded into a watch-only wallet.
+pub(crate) fn account_pubkey(descriptor: &str) -> Result<PublicKey, WalletError> {
+    let descriptor = Descriptor::<DescriptorPublicKey>::from_str(descriptor)
+        .map_err(|e| WalletError::InvalidDescriptor(e.to_string()))?;
+    match descriptor.iter_pk().next() {
+        Some(DescriptorPublicKey::XPub(xpub)) => Ok(xpub.xkey.public_key),
+        _ => Err(WalletError::InvalidDescriptor(
+            "Descriptor must derive from an extended public key.".into(),
+        )),
+    }
+}
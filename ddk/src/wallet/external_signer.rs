@@ -0,0 +1,211 @@
+//! Signing wallet inputs outside the node, for cold storage setups where the node only holds
+//! public descriptors.
+//!
+//! The DLC manager signs funding inputs synchronously while it processes a message. An
+//! external signer may take minutes, so the first attempt hands the PSBT to the signer on its
+//! own thread and fails with [WalletError::AwaitingSignature]. The message is retried on the
+//! next processing round and picks up the signature once the signer answered.
+use crate::error::WalletError;
+use bitcoin::psbt::{Input, Psbt};
+use bitcoin::Txid;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Default time an external signer has to answer a signature request.
+pub const DEFAULT_EXTERNAL_SIGNER_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// Signs PSBT inputs with keys the node does not hold, e.g. on a hardware wallet.
+pub trait ExternalSigner: Send + Sync + 'static {
+    /// Sign input `input_index` of `psbt` and return the signed PSBT. The input may be
+    /// finalized or carry partial signatures, the wallet finalizes it when needed.
+    fn sign_input(&self, psbt: Psbt, input_index: usize) -> anyhow::Result<Psbt>;
+}
+
+/// Who signs the wallet's inputs of DLC funding transactions.
+#[derive(Clone, Default)]
+pub enum SignerBackend {
+    /// The wallet signs with the keys derived from its seed.
+    #[default]
+    Local,
+    External(Arc<dyn ExternalSigner>),
+}
+
+impl fmt::Debug for SignerBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignerBackend::Local => write!(f, "Local"),
+            SignerBackend::External(_) => write!(f, "External"),
+        }
+    }
+}
+
+enum SignatureRequest {
+    Pending(Instant),
+    Signed(Input),
+    Failed(String),
+}
+
+/// Signature requests handed to an [ExternalSigner], keyed by the unsigned txid and input.
+pub(crate) struct ExternalSignatures {
+    signer: Arc<dyn ExternalSigner>,
+    timeout: Duration,
+    requests: Arc<Mutex<HashMap<(Txid, usize), SignatureRequest>>>,
+}
+
+impl ExternalSignatures {
+    pub(crate) fn new(signer: Arc<dyn ExternalSigner>, timeout: Duration) -> ExternalSignatures {
+        ExternalSignatures {
+            signer,
+            timeout,
+            requests: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Copy the signed input into `psbt` once the signer answered. Until then the request is
+    /// sent to the signer once and [WalletError::AwaitingSignature] is returned.
+    pub(crate) fn sign_input(
+        &self,
+        psbt: &mut Psbt,
+        input_index: usize,
+    ) -> Result<(), WalletError> {
+        if input_index >= psbt.inputs.len() {
+            return Err(WalletError::ExternalSigner(format!(
+                "PSBT has no input {}.",
+                input_index
+            )));
+        }
+        let txid = psbt.unsigned_tx.compute_txid();
+        let key = (txid, input_index);
+        let awaiting = WalletError::AwaitingSignature { txid, input_index };
+
+        let mut requests = self.requests.lock().unwrap();
+        match requests.remove(&key) {
+            Some(SignatureRequest::Signed(input)) => {
+                psbt.inputs[input_index] = input;
+                Ok(())
+            }
+            Some(SignatureRequest::Failed(e)) => Err(WalletError::ExternalSigner(e)),
+            Some(SignatureRequest::Pending(since)) if since.elapsed() >= self.timeout => {
+                Err(WalletError::ExternalSigner(format!(
+                    "No signature after {}s.",
+                    self.timeout.as_secs()
+                )))
+            }
+            Some(pending @ SignatureRequest::Pending(_)) => {
+                requests.insert(key, pending);
+                Err(awaiting)
+            }
+            None => {
+                requests.insert(key, SignatureRequest::Pending(Instant::now()));
+                drop(requests);
+                self.request(psbt.clone(), key);
+                Err(awaiting)
+            }
+        }
+    }
+
+    fn request(&self, psbt: Psbt, key: (Txid, usize)) {
+        tracing::info!(
+            txid = key.0.to_string(),
+            input_index = key.1,
+            "Requesting signature from external signer."
+        );
+        let signer = self.signer.clone();
+        let requests = self.requests.clone();
+        std::thread::spawn(move || {
+            let answer = match signer.sign_input(psbt, key.1) {
+                Ok(signed) => match signed.inputs.get(key.1) {
+                    Some(input) if signed.unsigned_tx.compute_txid() == key.0 => {
+                        SignatureRequest::Signed(input.clone())
+                    }
+                    _ => SignatureRequest::Failed("Signer returned a different PSBT.".into()),
+                },
+                Err(e) => SignatureRequest::Failed(e.to_string()),
+            };
+            let mut requests = requests.lock().unwrap();
+            // Requests that timed out were removed and are not answered.
+            if let Some(SignatureRequest::Pending(_)) = requests.get(&key) {
+                requests.insert(key, answer);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::absolute::LockTime;
+    use bitcoin::transaction::Version;
+    use bitcoin::{Transaction, TxIn, Witness};
+
+    struct StaticSigner(Option<Duration>);
+
+    impl ExternalSigner for StaticSigner {
+        fn sign_input(&self, mut psbt: Psbt, input_index: usize) -> anyhow::Result<Psbt> {
+            match self.0 {
+                Some(delay) => std::thread::sleep(delay),
+                None => anyhow::bail!("Device rejected the request."),
+            }
+            psbt.inputs[input_index].final_script_witness = Some(Witness::from_slice(&[[1u8; 72]]));
+            Ok(psbt)
+        }
+    }
+
+    fn psbt() -> Psbt {
+        Psbt::from_unsigned_tx(Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn::default()],
+            output: vec![],
+        })
+        .unwrap()
+    }
+
+    fn wait_for_answer(
+        signatures: &ExternalSignatures,
+        psbt: &mut Psbt,
+    ) -> Result<(), WalletError> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            match signatures.sign_input(psbt, 0) {
+                Err(WalletError::AwaitingSignature { .. }) if Instant::now() < deadline => {
+                    std::thread::sleep(Duration::from_millis(10))
+                }
+                result => return result,
+            }
+        }
+    }
+
+    #[test]
+    fn signatures_are_picked_up_on_retry() {
+        let signatures = ExternalSignatures::new(
+            Arc::new(StaticSigner(Some(Duration::ZERO))),
+            Duration::from_secs(60),
+        );
+        let mut psbt = psbt();
+        assert!(matches!(
+            signatures.sign_input(&mut psbt, 0),
+            Err(WalletError::AwaitingSignature { input_index: 0, .. })
+        ));
+        wait_for_answer(&signatures, &mut psbt).unwrap();
+        assert!(psbt.inputs[0].final_script_witness.is_some());
+        assert!(signatures.sign_input(&mut psbt, 1).is_err());
+    }
+
+    #[test]
+    fn rejections_and_timeouts_fail_the_request() {
+        let rejecting =
+            ExternalSignatures::new(Arc::new(StaticSigner(None)), Duration::from_secs(60));
+        let err = wait_for_answer(&rejecting, &mut psbt()).unwrap_err();
+        assert!(err.to_string().contains("rejected"));
+
+        let slow = ExternalSignatures::new(
+            Arc::new(StaticSigner(Some(Duration::from_secs(60)))),
+            Duration::from_millis(50),
+        );
+        let err = wait_for_answer(&slow, &mut psbt()).unwrap_err();
+        assert!(matches!(err, WalletError::ExternalSigner(_)));
+    }
+}
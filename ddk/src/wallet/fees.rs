@@ -0,0 +1,196 @@
+//! Fee rates for each [ConfirmationTarget], refreshed from the chain backend's estimates.
+//! The last refreshed rates are stored, so a restart starts from them rather than the defaults.
+use bitcoin::Network;
+use lightning::chain::chaininterface::ConfirmationTarget;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+/// Relay floor in sats per 1000 weight units.
+pub const MIN_FEERATE: u32 = 253;
+
+/// Blocks each target should confirm within.
+const TARGET_BLOCKS: [(ConfirmationTarget, u16); 8] = [
+    (ConfirmationTarget::MaximumFeeEstimate, 1),
+    (ConfirmationTarget::UrgentOnChainSweep, 6),
+    (ConfirmationTarget::MinAllowedAnchorChannelRemoteFee, 1008),
+    (ConfirmationTarget::MinAllowedNonAnchorChannelRemoteFee, 144),
+    (ConfirmationTarget::AnchorChannelFee, 1008),
+    (ConfirmationTarget::NonAnchorChannelFee, 12),
+    (ConfirmationTarget::ChannelCloseMinimum, 144),
+    (ConfirmationTarget::OutputSpendingFee, 12),
+];
+
+/// Fee rates used until the first estimates are fetched.
+pub fn default_fees(network: Network) -> HashMap<ConfirmationTarget, AtomicU32> {
+    // Signet blocks are near empty, so every target pays the relay floor there.
+    let signet = network == Network::Signet;
+    TARGET_BLOCKS
+        .iter()
+        .map(|(target, _)| {
+            let fee = match target {
+                _ if signet => MIN_FEERATE,
+                ConfirmationTarget::MaximumFeeEstimate | ConfirmationTarget::UrgentOnChainSweep => 5000,
+                ConfirmationTarget::NonAnchorChannelFee | ConfirmationTarget::OutputSpendingFee => 2000,
+                _ => MIN_FEERATE,
+            };
+            (*target, AtomicU32::new(fee))
+        })
+        .collect()
+}
+
+/// Fee rates as last refreshed, kept in storage across restarts.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredFees {
+    /// Unix timestamp in seconds of the refresh.
+    pub fetched_at: u64,
+    /// Sats per 1000 weight units keyed by [target_key].
+    pub fees: BTreeMap<String, u32>,
+}
+
+impl StoredFees {
+    /// The current rates of `fees`, refreshed at `fetched_at`.
+    pub fn new(fees: &HashMap<ConfirmationTarget, AtomicU32>, fetched_at: u64) -> StoredFees {
+        StoredFees {
+            fetched_at,
+            fees: fees
+                .iter()
+                .map(|(target, fee)| (target_key(*target).to_string(), fee.load(Ordering::Acquire)))
+                .collect(),
+        }
+    }
+
+    /// Whether the rates are older than `max_age` at `now`, a unix timestamp in seconds.
+    pub fn is_stale(&self, max_age: Duration, now: u64) -> bool {
+        now.saturating_sub(self.fetched_at) > max_age.as_secs()
+    }
+}
+
+/// Name a target's fee rate is stored under.
+pub fn target_key(target: ConfirmationTarget) -> &'static str {
+    match target {
+        ConfirmationTarget::MaximumFeeEstimate => "maximum_fee_estimate",
+        ConfirmationTarget::UrgentOnChainSweep => "urgent_on_chain_sweep",
+        ConfirmationTarget::MinAllowedAnchorChannelRemoteFee => "min_allowed_anchor_channel_remote_fee",
+        ConfirmationTarget::MinAllowedNonAnchorChannelRemoteFee => "min_allowed_non_anchor_channel_remote_fee",
+        ConfirmationTarget::AnchorChannelFee => "anchor_channel_fee",
+        ConfirmationTarget::NonAnchorChannelFee => "non_anchor_channel_fee",
+        ConfirmationTarget::ChannelCloseMinimum => "channel_close_minimum",
+        ConfirmationTarget::OutputSpendingFee => "output_spending_fee",
+    }
+}
+
+/// The default fee rates with the `stored` ones applied over them, unless they are older than
+/// `max_age` at `now`.
+pub fn restored_fees(
+    network: Network,
+    stored: Option<&StoredFees>,
+    max_age: Duration,
+    now: u64,
+) -> HashMap<ConfirmationTarget, AtomicU32> {
+    let fees = default_fees(network);
+    let Some(stored) = stored else {
+        return fees;
+    };
+    if stored.is_stale(max_age, now) {
+        tracing::info!(
+            fetched_at = stored.fetched_at,
+            "Stored fee estimates are stale. Starting from the default fee rates."
+        );
+        return fees;
+    }
+    for (target, fee) in &fees {
+        if let Some(stored) = stored.fees.get(target_key(*target)) {
+            fee.store((*stored).max(MIN_FEERATE), Ordering::Release);
+        }
+    }
+    fees
+}
+
+/// Update `fees` from esplora style estimates, sats per vbyte keyed by confirmation blocks.
+/// Targets without an estimate keep their fee rate.
+pub fn apply_fee_estimates(
+    fees: &HashMap<ConfirmationTarget, AtomicU32>,
+    estimates: &HashMap<u16, f64>,
+) {
+    for (target, blocks) in TARGET_BLOCKS {
+        let Some(sat_per_vb) = estimate_for(estimates, blocks) else {
+            continue;
+        };
+        let sat_per_kw = ((sat_per_vb * 250.0).round() as u32).max(MIN_FEERATE);
+        if let Some(fee) = fees.get(&target) {
+            fee.store(sat_per_kw, Ordering::Release);
+        }
+    }
+}
+
+/// Estimate of the largest block target within `blocks`, or the fastest one if none is.
+fn estimate_for(estimates: &HashMap<u16, f64>, blocks: u16) -> Option<f64> {
+    estimates
+        .iter()
+        .filter(|(target, _)| **target <= blocks)
+        .max_by_key(|(target, _)| **target)
+        .or_else(|| estimates.iter().min_by_key(|(target, _)| **target))
+        .map(|(_, fee)| *fee)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fee(fees: &HashMap<ConfirmationTarget, AtomicU32>, target: ConfirmationTarget) -> u32 {
+        fees.get(&target).unwrap().load(Ordering::Acquire)
+    }
+
+    #[test]
+    fn every_target_has_a_fee() {
+        let fees = default_fees(Network::Bitcoin);
+        for (target, _) in TARGET_BLOCKS {
+            assert!(fee(&fees, target) >= MIN_FEERATE);
+        }
+        assert_eq!(fees.len(), TARGET_BLOCKS.len());
+    }
+
+    #[test]
+    fn estimates_update_fees_with_floor() {
+        let fees = default_fees(Network::Bitcoin);
+        // Shape of esplora's fee-estimates response.
+        let estimates: HashMap<u16, f64> = serde_json::from_str(
+            r#"{"1": 40.5, "6": 20.0, "12": 10.0, "144": 2.0, "1008": 0.5}"#,
+        )
+        .unwrap();
+        apply_fee_estimates(&fees, &estimates);
+
+        assert_eq!(fee(&fees, ConfirmationTarget::MaximumFeeEstimate), 10_125);
+        assert_eq!(fee(&fees, ConfirmationTarget::UrgentOnChainSweep), 5_000);
+        assert_eq!(fee(&fees, ConfirmationTarget::NonAnchorChannelFee), 2_500);
+        assert_eq!(fee(&fees, ConfirmationTarget::ChannelCloseMinimum), 500);
+        // 0.5 sat/vb is below the relay floor.
+        assert_eq!(fee(&fees, ConfirmationTarget::AnchorChannelFee), MIN_FEERATE);
+    }
+
+    #[test]
+    fn stored_fees_are_restored_until_stale() {
+        let fees = default_fees(Network::Bitcoin);
+        fees.get(&ConfirmationTarget::UrgentOnChainSweep)
+            .unwrap()
+            .store(8_000, Ordering::Release);
+        let stored = StoredFees::new(&fees, 1_000);
+        let max_age = Duration::from_secs(3600);
+
+        let restored = restored_fees(Network::Bitcoin, Some(&stored), max_age, 4_600);
+        assert_eq!(fee(&restored, ConfirmationTarget::UrgentOnChainSweep), 8_000);
+        let restored = restored_fees(Network::Bitcoin, Some(&stored), max_age, 4_601);
+        assert_eq!(fee(&restored, ConfirmationTarget::UrgentOnChainSweep), 5_000);
+        let restored = restored_fees(Network::Bitcoin, None, max_age, 4_600);
+        assert_eq!(fee(&restored, ConfirmationTarget::UrgentOnChainSweep), 5_000);
+    }
+
+    #[test]
+    fn missing_estimates_keep_fees() {
+        let fees = default_fees(Network::Bitcoin);
+        apply_fee_estimates(&fees, &HashMap::new());
+        assert_eq!(fee(&fees, ConfirmationTarget::UrgentOnChainSweep), 5000);
+    }
+}
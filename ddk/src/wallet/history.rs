@@ -0,0 +1,199 @@
+//! Wallet transaction history, labelled with the contracts transactions belong to.
+use bdk_chain::{ChainPosition, ConfirmationBlockTime};
+use bdk_wallet::Wallet;
+use bitcoin::{Amount, Transaction, Txid};
+use dlc_manager::contract::Contract;
+use dlc_manager::ContractId;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// The role a transaction plays in a contract.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ContractTransactionKind {
+    Funding,
+    Cet,
+    Refund,
+}
+
+/// A transaction of a contract. Recorded so transactions stay labelled after the contract is
+/// closed and no longer stores its funding transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContractTransaction {
+    pub txid: Txid,
+    pub contract_id: ContractId,
+    pub kind: ContractTransactionKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TransactionLabel {
+    DlcFunding(ContractId),
+    DlcCet(ContractId),
+    DlcRefund(ContractId),
+    Plain,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransactionDetails {
+    pub txid: Txid,
+    /// Sum of our inputs.
+    pub sent: Amount,
+    /// Sum of our outputs.
+    pub received: Amount,
+    /// `None` when the wallet does not know every input of the transaction.
+    pub fee: Option<Amount>,
+    pub confirmation_height: Option<u32>,
+    /// Block time of the confirmation, as a unix timestamp.
+    pub confirmation_time: Option<u64>,
+    pub label: TransactionLabel,
+}
+
+/// Transactions of a contract that are known from its current state. CETs are only known once
+/// one is broadcast.
+pub fn contract_transactions(contract: &Contract) -> Vec<ContractTransaction> {
+    let record = |txid, contract_id, kind| ContractTransaction {
+        txid,
+        contract_id,
+        kind,
+    };
+    match contract {
+        Contract::Signed(s) | Contract::Confirmed(s) | Contract::Refunded(s) => {
+            let id = s.accepted_contract.get_contract_id();
+            let transactions = &s.accepted_contract.dlc_transactions;
+            vec![
+                record(
+                    transactions.fund.compute_txid(),
+                    id,
+                    ContractTransactionKind::Funding,
+                ),
+                record(
+                    transactions.refund.compute_txid(),
+                    id,
+                    ContractTransactionKind::Refund,
+                ),
+            ]
+        }
+        Contract::PreClosed(p) => {
+            let id = p.signed_contract.accepted_contract.get_contract_id();
+            let transactions = &p.signed_contract.accepted_contract.dlc_transactions;
+            vec![
+                record(
+                    transactions.fund.compute_txid(),
+                    id,
+                    ContractTransactionKind::Funding,
+                ),
+                record(
+                    transactions.refund.compute_txid(),
+                    id,
+                    ContractTransactionKind::Refund,
+                ),
+                record(
+                    p.signed_cet.compute_txid(),
+                    id,
+                    ContractTransactionKind::Cet,
+                ),
+            ]
+        }
+        Contract::Closed(c) => c
+            .signed_cet
+            .iter()
+            .map(|cet| {
+                record(
+                    cet.compute_txid(),
+                    c.contract_id,
+                    ContractTransactionKind::Cet,
+                )
+            })
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// Label of a transaction given the recorded contract transactions.
+pub fn label(
+    txid: &Txid,
+    contract_transactions: &HashMap<Txid, ContractTransaction>,
+) -> TransactionLabel {
+    match contract_transactions.get(txid) {
+        Some(record) => match record.kind {
+            ContractTransactionKind::Funding => TransactionLabel::DlcFunding(record.contract_id),
+            ContractTransactionKind::Cet => TransactionLabel::DlcCet(record.contract_id),
+            ContractTransactionKind::Refund => TransactionLabel::DlcRefund(record.contract_id),
+        },
+        None => TransactionLabel::Plain,
+    }
+}
+
+/// Details of a wallet transaction, before it is labelled.
+pub(crate) fn transaction_details(
+    wallet: &Wallet,
+    tx: &Transaction,
+    position: &ChainPosition<&ConfirmationBlockTime>,
+) -> TransactionDetails {
+    let (sent, received) = wallet.sent_and_received(tx);
+    let (confirmation_height, confirmation_time) = match position {
+        ChainPosition::Confirmed(anchor) => {
+            (Some(anchor.block_id.height), Some(anchor.confirmation_time))
+        }
+        ChainPosition::Unconfirmed(_) => (None, None),
+    };
+    TransactionDetails {
+        txid: tx.compute_txid(),
+        sent,
+        received,
+        fee: wallet.calculate_fee(tx).ok(),
+        confirmation_height,
+        confirmation_time,
+        label: TransactionLabel::Plain,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::Hash;
+    use dlc_manager::contract::ser::Serializable;
+    use dlc_manager::contract::signed_contract::SignedContract;
+    use dlc_manager::contract::ClosedContract;
+
+    fn deserialize_object<T: Serializable>(serialized: &[u8]) -> T {
+        let mut cursor = ::lightning::io::Cursor::new(&serialized);
+        T::deserialize(&mut cursor).unwrap()
+    }
+
+    #[test]
+    fn contract_transactions_are_labelled() {
+        let signed: SignedContract =
+            deserialize_object(include_bytes!("../../tests/data/dlc_storage/sled/Signed"));
+        let closed: ClosedContract =
+            deserialize_object(include_bytes!("../../tests/data/dlc_storage/sled/Closed"));
+        let records = [
+            Contract::Signed(signed.clone()),
+            Contract::Closed(closed.clone()),
+        ]
+        .iter()
+        .flat_map(contract_transactions)
+        .map(|record| (record.txid, record))
+        .collect::<HashMap<_, _>>();
+
+        let id = signed.accepted_contract.get_contract_id();
+        let transactions = &signed.accepted_contract.dlc_transactions;
+        assert_eq!(
+            label(&transactions.fund.compute_txid(), &records),
+            TransactionLabel::DlcFunding(id)
+        );
+        assert_eq!(
+            label(&transactions.refund.compute_txid(), &records),
+            TransactionLabel::DlcRefund(id)
+        );
+        if let Some(cet) = &closed.signed_cet {
+            assert_eq!(
+                label(&cet.compute_txid(), &records),
+                TransactionLabel::DlcCet(closed.contract_id)
+            );
+        }
+        assert_eq!(
+            label(&Txid::from_byte_array([7u8; 32]), &records),
+            TransactionLabel::Plain
+        );
+    }
+}
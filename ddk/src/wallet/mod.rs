@@ -0,0 +1,2177 @@
+pub mod accounts;
+pub mod address_proof;
+pub mod coin_selection;
+pub mod descriptors;
+pub mod external_signer;
+pub mod fees;
+pub mod history;
+pub mod payment;
+pub mod reservation;
+pub mod send;
+pub mod sync;
+pub mod utxos;
+
+pub use accounts::{Account, AccountBalances, WalletAccounts};
+pub use address_proof::AddressProof;
+pub use coin_selection::CoinSelectionStrategy;
+pub use descriptors::WalletDescriptors;
+pub use external_signer::{ExternalSigner, SignerBackend, DEFAULT_EXTERNAL_SIGNER_TIMEOUT};
+pub use history::{ContractTransaction, TransactionDetails, TransactionLabel};
+pub use payment::PaymentRequest;
+pub use reservation::UtxoReservations;
+pub use send::SendResult;
+pub use sync::{SyncStatus, SyncTracker};
+pub use utxos::UtxoInfo;
+
+use crate::{
+    chain::EsploraClient,
+    metrics,
+    recovery::{self, SignerRecovery, WalletRecovery},
+    signer::{KeyUsage, SignerInformation},
+    storage::SledStorageProvider,
+    DdkBlockchain, DdkStorage,
+};
+use bdk_chain::spk_client::{FullScanRequest, FullScanResult};
+use bdk_chain::Balance;
+use bdk_wallet::{
+    bitcoin::{
+        bip32::{DerivationPath, Xpriv},
+        secp256k1::{All, PublicKey, Secp256k1},
+        Address, Network, Txid,
+    }, template::Bip84, AddressInfo, KeychainKind, LocalOutput, PersistedWallet, SignOptions, Wallet
+};
+use bitcoin::{hashes::{sha256::HashEngine, Hash}, psbt::Psbt, secp256k1::SecretKey, Amount, FeeRate, OutPoint, ScriptBuf, Transaction, Weight};
+use bitcoin::hashes::sha256::Hash as Sha256Hash;
+use crossbeam::channel::{unbounded, Receiver, Sender};
+use dlc_manager::{contract::{accepted_contract::AcceptedContract, Contract}, error::Error as ManagerError, Blockchain, SimpleSigner, Storage};
+use lightning::chain::chaininterface::{ConfirmationTarget, FeeEstimator};
+use std::{io::Write, sync::{atomic::Ordering, Arc, Mutex}};
+use std::{collections::{HashMap, HashSet}, path::Path};
+use std::{str::FromStr, sync::atomic::AtomicU32, time::Duration};
+use crate::error::{BroadcastErrorKind, WalletError};
+use bitcoin::consensus::encode::serialize_hex;
+use external_signer::ExternalSignatures;
+
+/// Internal [bdk::Wallet] for ddk.
+/// Uses eplora blocking for the [ddk::DlcDevKit] being sync only
+/// Persists to sled. Wallets from the file-based [bdk_file_store::Store] are migrated on startup.
+pub struct DlcDevKitWallet<S, B = EsploraClient> {
+    pub blockchain: Arc<B>,
+    pub sender: Sender<WalletOperation>,
+    pub network: Network,
+    /// Master key of the wallet. None for a watch-only wallet.
+    pub xprv: Option<Xpriv>,
+    pub name: String,
+    pub fees: Arc<HashMap<ConfirmationTarget, AtomicU32>>,
+    pub coin_selection: CoinSelectionStrategy,
+    /// Accounts funding contracts and paying sends.
+    pub accounts: WalletAccounts,
+    /// Whether the wallet thread holds the spending account. Watch-only wallets do not.
+    has_spending: bool,
+    derive_signer: Arc<S>,
+    /// Signature requests for DLC funding inputs. None when the wallet signs locally.
+    external_signatures: Option<ExternalSignatures>,
+    reservations: Arc<UtxoReservations<S>>,
+    /// Index of the next contract signer key. Loaded on first use.
+    signer_index: Mutex<Option<u32>>,
+    sync_tracker: Arc<SyncTracker>,
+    pubkey: PublicKey,
+    secp: Secp256k1<All>,
+}
+
+/// Messages that can be sent to the internal wallet.
+pub enum WalletOperation {
+    // Get a request to scan the chain for the wallet's scripts. The scan itself runs outside
+    // the wallet thread.
+    StartFullScan(Sender<FullScanRequest<KeychainKind>>),
+    // Apply the result of a full scan.
+    ApplyScan(FullScanResult<KeychainKind>, Sender<Result<(), WalletError>>),
+    // Retrieve wallet balance.
+    Balance(Sender<Balance>),
+    // Get a new, unused address for external use.
+    NewExternalAddress(Sender<AddressInfo>),
+    // Get a new, unused change address.
+    NewChangeAddress(Sender<AddressInfo>),
+    // Send an amount to an address.
+    SendToAddress(Address, Amount, FeeRate, CoinSelectionStrategy, bool, bool, Sender<Result<SendResult, WalletError>>),
+    // Send every spendable UTXO to an address.
+    SendAllToAddress(Address, FeeRate, bool, Sender<Result<Txid, WalletError>>),
+    // Replace an unconfirmed transaction with one paying a higher fee rate.
+    BumpFee(Txid, FeeRate, Sender<Result<Txid, WalletError>>),
+    // Apply a full scan, then report UTXOs and potential funding outputs.
+    Recover(FullScanResult<KeychainKind>, u32, Sender<Result<WalletRecovery, WalletError>>),
+    // Get all Transactions in the wallet.
+    GetTransactions(Sender<Vec<TransactionDetails>>),
+    // Get a wallet transaction by txid.
+    GetTransaction(Txid, Sender<Option<TransactionDetails>>),
+    // Get all UTXO's owned by the wallet.
+    ListUtxos(Sender<Vec<LocalOutput>>),
+    // Get the height of the last block the wallet synced.
+    ChainTip(Sender<u32>),
+    // Sign an input.
+    SignPsbtInput(Psbt, usize, Sender<Result<Psbt, WalletError>>),
+    // Finalize the signed inputs of a PSBT.
+    FinalizePsbt(Psbt, Sender<Result<Psbt, WalletError>>),
+    // Build an unsigned payment to several recipients.
+    BuildPsbt(Vec<(Address, Amount)>, FeeRate, Sender<Result<Psbt, WalletError>>),
+    // Finalize, extract, and broadcast a PSBT signed outside the wallet.
+    BroadcastPsbt(Psbt, Sender<Result<Txid, WalletError>>),
+    // Get the next unused derivation path.
+    NextDerivationIndex(Sender<u32>),
+    // Get address, utxo, and transaction counts.
+    Stats(Sender<WalletStats>),
+    // Get the keychain and derivation index of a script owned by the wallet.
+    DerivationOfSpk(ScriptBuf, Sender<Option<(KeychainKind, u32)>>),
+    // Bump the fee of a broadcast parent transaction with a child spending its wallet outputs.
+    // Returns the broadcast child.
+    Cpfp(Transaction, Amount, FeeRate, Sender<Result<Transaction, WalletError>>),
+    // Get the public descriptors and birthday of the wallet.
+    Descriptors(Sender<WalletDescriptors>),
+    // Run an operation on the wallet of an account other than the DLC account.
+    Account(Account, Box<WalletOperation>),
+}
+
+/// Address, utxo, and transaction counts of the wallet.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WalletStats {
+    pub revealed_external_addresses: u32,
+    pub revealed_internal_addresses: u32,
+    pub utxos: usize,
+    pub transactions: usize,
+}
+
+/// Estimated size of a child transaction spending one wallet output to a change address.
+const CPFP_CHILD_VBYTES: u64 = 110;
+/// Directory of the wallet store inside the data directory.
+pub const WALLET_DB_DIR: &str = "wallet-db";
+/// Unused scripts in a row that end a wallet sync.
+const SYNC_STOP_GAP: usize = 20;
+/// Age up to which stored fee estimates are used when the wallet starts.
+pub const DEFAULT_FEE_ESTIMATES_MAX_AGE: Duration = Duration::from_secs(60 * 60);
+
+impl<S: DdkStorage, B: DdkBlockchain> DlcDevKitWallet<S, B> {
+    pub fn new<P>(
+        name: &str,
+        xprv: Xpriv,
+        blockchain: Arc<B>,
+        network: Network,
+        wallet_storage_path: P,
+        derive_signer: Arc<S>,
+    ) -> anyhow::Result<DlcDevKitWallet<S, B>>
+    where
+        P: AsRef<Path>,
+    {
+        let secp = Secp256k1::new();
+        let data_dir = wallet_storage_path.as_ref().to_path_buf();
+        let wallet_storage_path = data_dir.join(WALLET_DB_DIR);
+
+        let external_descriptor = Bip84(xprv, KeychainKind::External);
+        let internal_descriptor = Bip84(xprv, KeychainKind::Internal);
+        let mut storage = SledStorageProvider::new(wallet_storage_path.to_str().unwrap())?;
+        storage.migrate_legacy_wallet(&data_dir)?;
+
+        let load_wallet = Wallet::load()
+            .descriptor(KeychainKind::External, Some(external_descriptor.clone()))
+            .descriptor(KeychainKind::Internal, Some(internal_descriptor.clone()))
+            .extract_keys()
+            .check_network(network)
+            .load_wallet(&mut storage)?;
+
+        let wallet = match load_wallet {
+            Some(w) => w,
+            None => Wallet::create(external_descriptor, internal_descriptor)
+                .network(network)
+                .create_wallet(&mut storage)?
+        };
+
+        // The descriptors stored with the wallet pin the account index to the account.
+        let (spending_external, spending_internal) =
+            accounts::account_descriptors(&xprv, network, Account::Spending);
+        let spending_storage_path = data_dir.join(accounts::SPENDING_WALLET_DB_DIR);
+        let mut spending_storage = SledStorageProvider::new(spending_storage_path.to_str().unwrap())?;
+        let load_spending = Wallet::load()
+            .descriptor(KeychainKind::External, Some(spending_external.clone()))
+            .descriptor(KeychainKind::Internal, Some(spending_internal.clone()))
+            .extract_keys()
+            .check_network(network)
+            .load_wallet(&mut spending_storage)?;
+        let spending = match load_spending {
+            Some(w) => w,
+            None => Wallet::create(spending_external, spending_internal)
+                .network(network)
+                .create_wallet(&mut spending_storage)?,
+        };
+
+        let pubkey = PublicKey::from_secret_key(&secp, &xprv.private_key);
+        Self::start(
+            name,
+            Some(xprv),
+            pubkey,
+            wallet,
+            storage,
+            Some((spending, spending_storage)),
+            blockchain,
+            network,
+            derive_signer,
+        )
+    }
+
+    /// A wallet that watches `descriptors` exported from another wallet with
+    /// [DlcDevKitWallet::export_descriptors]. It syncs and lists balances and UTXOs, but every
+    /// signing path fails with [WalletError::WatchOnly].
+    pub fn new_watch_only<P>(
+        name: &str,
+        descriptors: &WalletDescriptors,
+        blockchain: Arc<B>,
+        network: Network,
+        wallet_storage_path: P,
+        derive_signer: Arc<S>,
+    ) -> anyhow::Result<DlcDevKitWallet<S, B>>
+    where
+        P: AsRef<Path>,
+    {
+        let pubkey = descriptors::account_pubkey(&descriptors.external)?;
+        descriptors::account_pubkey(&descriptors.internal)?;
+        let wallet_storage_path = wallet_storage_path.as_ref().join(WALLET_DB_DIR);
+        let mut storage = SledStorageProvider::new(wallet_storage_path.to_str().unwrap())?;
+
+        let load_wallet = Wallet::load()
+            .descriptor(KeychainKind::External, Some(descriptors.external.clone()))
+            .descriptor(KeychainKind::Internal, Some(descriptors.internal.clone()))
+            .check_network(network)
+            .load_wallet(&mut storage)?;
+
+        let wallet = match load_wallet {
+            Some(w) => w,
+            None => Wallet::create(descriptors.external.clone(), descriptors.internal.clone())
+                .network(network)
+                .create_wallet(&mut storage)?
+        };
+
+        Self::start(name, None, pubkey, wallet, storage, None, blockchain, network, derive_signer)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn start(
+        name: &str,
+        xprv: Option<Xpriv>,
+        pubkey: PublicKey,
+        mut wallet: PersistedWallet<SledStorageProvider>,
+        mut storage: SledStorageProvider,
+        mut spending: Option<(PersistedWallet<SledStorageProvider>, SledStorageProvider)>,
+        blockchain: Arc<B>,
+        network: Network,
+        derive_signer: Arc<S>,
+    ) -> anyhow::Result<DlcDevKitWallet<S, B>> {
+        let fees = Arc::new(Self::load_fees(&derive_signer, network, DEFAULT_FEE_ESTIMATES_MAX_AGE));
+
+        let (sender, receiver) = unbounded::<WalletOperation>();
+
+        let reservations = Arc::new(UtxoReservations::load(derive_signer.clone())?);
+
+        let esplora = blockchain.clone();
+        let run_reservations = reservations.clone();
+        let has_spending = spending.is_some();
+        std::thread::spawn(move || {
+            Self::run(
+                &mut wallet,
+                &mut storage,
+                spending.as_mut().map(|(wallet, storage)| (wallet, storage)),
+                receiver,
+                esplora,
+                run_reservations,
+            )
+        });
+
+        Ok(DlcDevKitWallet {
+            blockchain,
+            sender,
+            network,
+            xprv,
+            fees,
+            coin_selection: CoinSelectionStrategy::default(),
+            accounts: WalletAccounts::default(),
+            has_spending,
+            derive_signer,
+            external_signatures: None,
+            reservations,
+            signer_index: Mutex::new(None),
+            sync_tracker: Arc::new(SyncTracker::default()),
+            pubkey,
+            secp: Secp256k1::new(),
+            name: name.to_string(),
+        })
+    }
+
+    /// The master key, or [WalletError::WatchOnly] for a watch-only wallet.
+    fn master_key(&self) -> Result<&Xpriv, WalletError> {
+        self.xprv.as_ref().ok_or(WalletError::WatchOnly)
+    }
+
+    /// Accounts the wallet holds. A watch-only wallet only watches the DLC account.
+    pub fn available_accounts(&self) -> Vec<Account> {
+        if self.has_spending {
+            vec![Account::Dlc, Account::Spending]
+        } else {
+            vec![Account::Dlc]
+        }
+    }
+
+    /// Hand `op` to the wallet thread for the wallet of `account`.
+    fn send_operation(&self, account: Account, op: WalletOperation) -> Result<(), WalletError> {
+        let op = match account {
+            Account::Dlc => op,
+            Account::Spending if self.has_spending => WalletOperation::Account(account, Box::new(op)),
+            Account::Spending => return Err(WalletError::AccountUnavailable(account)),
+        };
+        self.sender
+            .send(op)
+            .map_err(|e| WalletError::SendMessage(e.to_string()))
+    }
+
+    /// Whether the wallet was created from descriptors without private keys.
+    pub fn is_watch_only(&self) -> bool {
+        self.xprv.is_none()
+    }
+
+    /// Public BIP-84 descriptors of the wallet with checksums, and its birthday, for importing
+    /// into a watch-only wallet.
+    pub fn export_descriptors(&self) -> Result<WalletDescriptors, WalletError> {
+        let (sender, receiver) = unbounded();
+        self.sender
+            .send(WalletOperation::Descriptors(sender))
+            .map_err(|e| WalletError::SendMessage(e.to_string()))?;
+        Ok(receiver.recv()?)
+    }
+
+    /// Set the [CoinSelectionStrategy] used to fund DLCs and sends.
+    pub fn with_coin_selection(mut self, coin_selection: CoinSelectionStrategy) -> Self {
+        self.coin_selection = coin_selection;
+        self
+    }
+
+    /// Start from stored fee estimates only when they are at most `max_age` old, instead of
+    /// [DEFAULT_FEE_ESTIMATES_MAX_AGE].
+    pub fn with_fee_estimates_max_age(mut self, max_age: Duration) -> Self {
+        self.fees = Arc::new(Self::load_fees(&self.derive_signer, self.network, max_age));
+        self
+    }
+
+    /// The stored fee estimates over the defaults, unless they are older than `max_age`.
+    fn load_fees(storage: &S, network: Network, max_age: Duration) -> HashMap<ConfirmationTarget, AtomicU32> {
+        let stored = match storage.get_fee_estimates() {
+            Ok(stored) => stored,
+            Err(e) => {
+                tracing::warn!(error = e.to_string(), "Could not load stored fee estimates.");
+                None
+            }
+        };
+        fees::restored_fees(network, stored.as_ref(), max_age, unix_now())
+    }
+
+    /// Set the accounts that fund contracts and pay sends.
+    pub fn with_accounts(mut self, accounts: WalletAccounts) -> Self {
+        self.accounts = accounts;
+        self
+    }
+
+    /// Set who signs the wallet's inputs of DLC funding transactions. An external signer has
+    /// `timeout` to answer before the contract fails.
+    pub fn with_signer_backend(mut self, backend: SignerBackend, timeout: Duration) -> Self {
+        self.external_signatures = match backend {
+            SignerBackend::Local => None,
+            SignerBackend::External(signer) => Some(ExternalSignatures::new(signer, timeout)),
+        };
+        self
+    }
+
+    fn sign_and_broadcast(wallet: &mut Wallet, mut psbt: Psbt, blockchain: &B) -> Result<Txid, WalletError> {
+        wallet.sign(&mut psbt, SignOptions::default())?;
+        Self::broadcast(wallet, psbt.extract_tx()?, blockchain)
+    }
+
+    /// Build, sign, and broadcast a child of `parent` so the package of both pays `fee_rate`.
+    /// The child spends the parent's wallet outputs, plus wallet UTXOs when those do not cover
+    /// the fee, and drains to a change address.
+    fn cpfp(
+        wallet: &mut Wallet,
+        blockchain: &B,
+        parent: &Transaction,
+        parent_fee: Amount,
+        fee_rate: FeeRate,
+    ) -> Result<Transaction, WalletError> {
+        let parent_txid = parent.compute_txid();
+        let outpoints = parent
+            .output
+            .iter()
+            .enumerate()
+            .filter(|(_, output)| wallet.is_mine(output.script_pubkey.clone()))
+            .map(|(vout, _)| OutPoint::new(parent_txid, vout as u32))
+            .collect::<Vec<OutPoint>>();
+
+        if outpoints.is_empty() {
+            return Err(WalletError::Cpfp(format!(
+                "No output of {} is spendable by the wallet.",
+                parent_txid
+            )));
+        }
+
+        // The parent is already broadcast but may not be synced yet.
+        wallet.insert_tx(parent.clone());
+
+        // The child pays for the whole package at the target rate. Its size is known once coin
+        // selection added the wallet inputs, so it is rebuilt when the estimate fell short.
+        let mut child_weight = Weight::from_vb_unchecked(CPFP_CHILD_VBYTES);
+        for _ in 0..3 {
+            let package_fee = fee_rate
+                .fee_wu(parent.weight() + child_weight)
+                .ok_or_else(|| WalletError::Cpfp("Package fee overflow.".into()))?;
+            let child_fee = package_fee
+                .checked_sub(parent_fee)
+                .ok_or_else(|| WalletError::Cpfp("Parent already pays the target fee rate.".into()))?;
+
+            let drain_address = wallet.next_unused_address(KeychainKind::Internal);
+            let mut txn_builder = wallet.build_tx();
+            txn_builder
+                .add_utxos(&outpoints)?
+                .drain_to(drain_address.script_pubkey())
+                .fee_absolute(child_fee);
+
+            let mut psbt = txn_builder.finish()?;
+            wallet.sign(&mut psbt, SignOptions::default())?;
+            let child = psbt.extract_tx()?;
+            if child.weight() <= child_weight {
+                return Self::broadcast(wallet, child.clone(), blockchain).map(|_| child);
+            }
+            wallet.cancel_tx(&child);
+            child_weight = child.weight();
+        }
+        Err(WalletError::Cpfp("Child transaction keeps outgrowing its fee.".into()))
+    }
+
+    fn broadcast(wallet: &mut Wallet, tx: Transaction, blockchain: &B) -> Result<Txid, WalletError> {
+        blockchain.send_transaction(&tx).map_err(broadcast_error)?;
+        let txid = tx.compute_txid();
+        // Known to the wallet before the next sync so it can be fee bumped.
+        wallet.insert_tx(tx);
+        Ok(txid)
+    }
+
+    /// Answer operations on the DLC account wallet and, wrapped in [WalletOperation::Account],
+    /// on the spending account wallet.
+    pub fn run(
+        wallet: &mut PersistedWallet<SledStorageProvider>,
+        persister: &mut SledStorageProvider,
+        mut spending: Option<(&mut PersistedWallet<SledStorageProvider>, &mut SledStorageProvider)>,
+        receiver: Receiver<WalletOperation>,
+        blockchain: Arc<B>,
+        reservations: Arc<UtxoReservations<S>>,
+    ) {
+        while let Ok(op) = receiver.recv() {
+            let (wallet, persister, op) = match op {
+                WalletOperation::Account(Account::Spending, op) => match spending.as_mut() {
+                    Some((wallet, persister)) => (&mut **wallet, &mut **persister, *op),
+                    // Callers check for the account first. The responder of the dropped
+                    // operation fails.
+                    None => {
+                        tracing::error!("Wallet has no spending account.");
+                        continue;
+                    }
+                },
+                WalletOperation::Account(Account::Dlc, op) => (&mut *wallet, &mut *persister, *op),
+                op => (&mut *wallet, &mut *persister, op),
+            };
+            Self::handle_operation(wallet, op, &blockchain, &reservations);
+            // Revealed addresses and seen transactions survive a restart.
+            if let Err(e) = wallet.persist(persister) {
+                tracing::error!(error = e.to_string(), "Could not persist wallet changes.");
+            }
+        }
+    }
+
+    fn handle_operation(
+        wallet: &mut PersistedWallet<SledStorageProvider>,
+        op: WalletOperation,
+        blockchain: &Arc<B>,
+        reservations: &Arc<UtxoReservations<S>>,
+    ) {
+        match op {
+            WalletOperation::StartFullScan(responder) => {
+                if let Err(e) = responder.send(wallet.start_full_scan()) {
+                    tracing::error!(message=?e, "Could not send message in full scan message")
+                }
+            }
+            WalletOperation::ApplyScan(scan, responder) => {
+                let result = wallet.apply_update(scan).map_err(WalletError::from);
+                if let Err(e) = responder.send(result) {
+                    tracing::error!(message=?e, "Could not send message in sync message")
+                }
+            }
+            WalletOperation::Balance(responder) => {
+                let balance = wallet.balance();
+                if let Err(e) = responder.send(balance) {
+                    tracing::error!(message=?e, "Could not send message in balance message")
+                }
+            }
+            WalletOperation::NewExternalAddress(responder) => {
+                let address = wallet.next_unused_address(KeychainKind::External);
+                if let Err(e) = responder.send(address) {
+                    tracing::error!(message=?e, "Could not send message in balance message")
+                }
+            }
+            WalletOperation::NewChangeAddress(responder) => {
+                let address = wallet.next_unused_address(KeychainKind::Internal);
+                if let Err(e) = responder.send(address) {
+                    tracing::error!(message=?e, "Could not send message in balance message")
+                }
+            }
+            WalletOperation::SendToAddress(address, amount, fee_rate, strategy, allow_dust, broadcast, responder) => {
+                let send = |wallet: &mut PersistedWallet<SledStorageProvider>| -> Result<SendResult, WalletError> {
+                    let mut psbt = send::build_send(
+                        wallet,
+                        &address,
+                        amount,
+                        fee_rate,
+                        strategy,
+                        allow_dust,
+                        &reservations.reserved(),
+                    )?;
+                    wallet.sign(&mut psbt, SignOptions::default())?;
+                    let fee_paid = psbt.fee().map_err(|e| WalletError::Fee(e.to_string()))?;
+                    let tx = psbt.extract_tx()?;
+                    let vsize = tx.vsize() as u64;
+                    if !broadcast {
+                        return Ok(SendResult {
+                            txid: tx.compute_txid(),
+                            fee_paid,
+                            vsize,
+                            raw_tx: Some(serialize_hex(&tx)),
+                        });
+                    }
+                    let txid = Self::broadcast(wallet, tx, &blockchain)?;
+                    Ok(SendResult {
+                        txid,
+                        fee_paid,
+                        vsize,
+                        raw_tx: None,
+                    })
+                };
+                let sent = send(wallet);
+                if let Err(e) = responder.send(sent) {
+                    tracing::error!(message=?e, "Could not send message to broadcast transaction.")
+                }
+            }
+            WalletOperation::SendAllToAddress(address, fee_rate, allow_dust, responder) => {
+                let send_all = |wallet: &mut PersistedWallet<SledStorageProvider>| -> Result<Txid, WalletError> {
+                    let psbt = send::build_send_all(
+                        wallet,
+                        &address,
+                        fee_rate,
+                        allow_dust,
+                        &reservations.reserved(),
+                    )?;
+                    Self::sign_and_broadcast(wallet, psbt, &blockchain)
+                };
+                let txid = send_all(wallet);
+                if let Err(e) = responder.send(txid) {
+                    tracing::error!(message=?e, "Could not send message to broadcast transaction.")
+                }
+            }
+            WalletOperation::BumpFee(txid, fee_rate, responder) => {
+                let bump = |wallet: &mut PersistedWallet<SledStorageProvider>| -> Result<Txid, WalletError> {
+                    let psbt = send::build_fee_bump(wallet, txid, fee_rate, &reservations.reserved())?;
+                    Self::sign_and_broadcast(wallet, psbt, &blockchain)
+                };
+                let replacement = bump(wallet);
+                if let Err(e) = responder.send(replacement) {
+                    tracing::error!(message=?e, "Could not send message to bump fee.")
+                }
+            }
+            WalletOperation::Recover(scan, birthday_height, responder) => {
+                let recovered = wallet
+                    .apply_update(scan)
+                    .map(|_| recovery::wallet_recovery(wallet, birthday_height))
+                    .map_err(WalletError::from);
+                if let Err(e) = responder.send(recovered) {
+                    tracing::error!(message=?e, "Could not send message to recover wallet.")
+                }
+            }
+            WalletOperation::GetTransactions(responder) => {
+                let transactions: Vec<TransactionDetails> = wallet
+                    .transactions()
+                    .map(|t| history::transaction_details(wallet, &t.tx_node.tx, &t.chain_position))
+                    .collect();
+                if let Err(e) = responder.send(transactions) {
+                    tracing::error!(message=?e, "Could not send message to get transactions.")
+                }
+            }
+            WalletOperation::GetTransaction(txid, responder) => {
+                let transaction = wallet
+                    .get_tx(txid)
+                    .map(|t| history::transaction_details(wallet, &t.tx_node.tx, &t.chain_position));
+                if let Err(e) = responder.send(transaction) {
+                    tracing::error!(message=?e, "Could not send message to get transaction.")
+                }
+            }
+            WalletOperation::ListUtxos(responder) => {
+                let utxos: Vec<LocalOutput> = wallet
+                    .list_unspent()
+                    .into_iter()
+                    .map(|utxo| utxo.to_owned())
+                    .collect();
+                if let Err(e) = responder.send(utxos) {
+                    tracing::error!(message=?e, "Could not send message to get utxos.")
+                }
+            }
+            WalletOperation::ChainTip(responder) => {
+                let height = wallet.latest_checkpoint().height();
+                if let Err(e) = responder.send(height) {
+                    tracing::error!(message=?e, "Could not send message to get chain tip.")
+                }
+            }
+            WalletOperation::NextDerivationIndex(responder) => {
+                let next_index = wallet.next_derivation_index(KeychainKind::External);
+                if let Err(e) = responder.send(next_index) {
+                    tracing::error!(message=?e, "Could not send message to get utxos.")
+                }
+            }
+            WalletOperation::Stats(responder) => {
+                let revealed = |keychain| {
+                    wallet
+                        .spk_index()
+                        .last_revealed_index(keychain)
+                        .map_or(0, |index| index + 1)
+                };
+                let stats = WalletStats {
+                    revealed_external_addresses: revealed(KeychainKind::External),
+                    revealed_internal_addresses: revealed(KeychainKind::Internal),
+                    utxos: wallet.list_unspent().count(),
+                    transactions: wallet.transactions().count(),
+                };
+                if let Err(e) = responder.send(stats) {
+                    tracing::error!(message=?e, "Could not send message to get wallet stats.")
+                }
+            }
+            WalletOperation::DerivationOfSpk(script, responder) => {
+                let derivation = wallet.derivation_of_spk(script);
+                if let Err(e) = responder.send(derivation) {
+                    tracing::error!(message=?e, "Could not send message to get derivation.")
+                }
+            }
+            WalletOperation::Cpfp(parent, parent_fee, fee_rate, responder) => {
+                let child = Self::cpfp(wallet, &blockchain, &parent, parent_fee, fee_rate);
+                if let Err(e) = responder.send(child) {
+                    tracing::error!(message=?e, "Could not send message to bump fee.")
+                }
+            }
+            WalletOperation::Descriptors(responder) => {
+                if let Err(e) = responder.send(descriptors::wallet_descriptors(wallet)) {
+                    tracing::error!(message=?e, "Could not send message to get descriptors.")
+                }
+            }
+            WalletOperation::Account(account, _) => {
+                tracing::error!(%account, "Account operations cannot be nested.")
+            }
+            WalletOperation::SignPsbtInput(psbt, _input_index, responder) => {
+                let sign = |mut psbt: Psbt, wallet: &mut PersistedWallet<SledStorageProvider>, | -> Result<Psbt, WalletError> {
+                    wallet.sign(&mut psbt, SignOptions::default())?;
+                    Ok(psbt)
+                };
+                let sign_txn = sign(psbt, wallet);
+                if let Err(e) = responder.send(sign_txn) {
+                    tracing::error!(message=?e, "Could not send message to get utxos.")
+                }
+            }
+            WalletOperation::FinalizePsbt(mut psbt, responder) => {
+                let finalized = wallet
+                    .finalize_psbt(&mut psbt, SignOptions::default())
+                    .map(|_| psbt)
+                    .map_err(WalletError::from);
+                if let Err(e) = responder.send(finalized) {
+                    tracing::error!(message=?e, "Could not send message to finalize psbt.")
+                }
+            }
+            WalletOperation::BuildPsbt(recipients, fee_rate, responder) => {
+                let psbt = send::build_payment(wallet, &recipients, fee_rate, &reservations.reserved());
+                if let Err(e) = responder.send(psbt) {
+                    tracing::error!(message=?e, "Could not send message to build psbt.")
+                }
+            }
+            WalletOperation::BroadcastPsbt(mut psbt, responder) => {
+                let broadcast = |wallet: &mut PersistedWallet<SledStorageProvider>| -> Result<Txid, WalletError> {
+                    if !wallet.finalize_psbt(&mut psbt, SignOptions::default())? {
+                        return Err(WalletError::ExternalSigner(
+                            "PSBT is missing signatures.".into(),
+                        ));
+                    }
+                    Self::broadcast(wallet, psbt.extract_tx()?, &blockchain)
+                };
+                let txid = broadcast(wallet);
+                if let Err(e) = responder.send(txid) {
+                    tracing::error!(message=?e, "Could not send message to broadcast psbt.")
+                }
+            }
+        }
+    }
+
+    /// Scan the chain for the wallet's scripts and apply what was found.
+    pub fn sync(&self) -> Result<(), WalletError> {
+        let timer = metrics::Timer::start();
+        let result = self.available_accounts().into_iter().try_for_each(|account| {
+            let scan = self.full_scan(account, SYNC_STOP_GAP)?;
+            let (sender, receiver) = unbounded();
+            self.send_operation(account, WalletOperation::ApplyScan(scan, sender))?;
+            receiver.recv()?
+        });
+        metrics::wallet_sync(timer, result.is_ok());
+        self.sync_tracker.record(&result);
+        result
+    }
+
+    /// Scan the chain until `stop_gap` unused scripts in a row. The scan runs on the calling
+    /// thread, so the wallet keeps answering other operations while the chain backend is
+    /// queried. Only applying the result waits for the wallet thread.
+    fn full_scan(
+        &self,
+        account: Account,
+        stop_gap: usize,
+    ) -> Result<FullScanResult<KeychainKind>, WalletError> {
+        let (sender, receiver) = unbounded();
+        self.send_operation(account, WalletOperation::StartFullScan(sender))?;
+        self.blockchain
+            .full_scan(receiver.recv()?, stop_gap)
+            .map_err(|e| WalletError::Blockchain(e.to_string()))
+    }
+
+    /// When the wallet last synced and whether recent syncs failed.
+    pub fn sync_status(&self) -> SyncStatus {
+        self.sync_tracker.status()
+    }
+
+    pub(crate) fn sync_tracker(&self) -> Arc<SyncTracker> {
+        self.sync_tracker.clone()
+    }
+
+    /// Public key of the master key. For a watch-only wallet, the public key of the account
+    /// in its descriptors.
+    pub fn get_pubkey(&self) -> PublicKey {
+        tracing::info!("Getting wallet public key.");
+        self.pubkey
+    }
+
+    /// Balance of every account together.
+    pub fn get_balance(&self) -> Result<Balance, WalletError> {
+        Ok(self.get_balance_detailed()?.total())
+    }
+
+    /// Balance of each account.
+    pub fn get_balance_detailed(&self) -> Result<AccountBalances, WalletError> {
+        let mut balances = AccountBalances::default();
+        for account in self.available_accounts() {
+            let (sender, receiver) = unbounded();
+            self.send_operation(account, WalletOperation::Balance(sender))?;
+            match account {
+                Account::Dlc => balances.dlc = receiver.recv()?,
+                Account::Spending => balances.spending = receiver.recv()?,
+            }
+        }
+        Ok(balances)
+    }
+
+    /// A new receive address of the DLC account.
+    pub fn new_external_address(&self) -> Result<AddressInfo, WalletError> {
+        self.new_external_address_for(Account::Dlc)
+    }
+
+    /// A new receive address of `account`.
+    pub fn new_external_address_for(&self, account: Account) -> Result<AddressInfo, WalletError> {
+        let (sender, receiver) = unbounded();
+        self.send_operation(account, WalletOperation::NewExternalAddress(sender))?;
+        Ok(receiver.recv()?)
+    }
+
+    pub fn new_change_address(&self) -> Result<AddressInfo, WalletError> {
+        let (sender, receiver) = unbounded();
+        self.sender
+            .send(WalletOperation::NewChangeAddress(sender))
+            .map_err(|e| WalletError::SendMessage(e.to_string()))?;
+        Ok(receiver.recv()?)
+    }
+
+    /// Send `amount` from the [WalletAccounts::sends] account to `address`. Fails with
+    /// [WalletError::InsufficientFunds] when the amount and fee are more than the spendable
+    /// balance, and with [WalletError::BelowDustLimit] for dust amounts unless `allow_dust` is
+    /// set. Without `broadcast` the signed transaction is returned in [SendResult::raw_tx] and
+    /// the wallet does not consider its inputs spent.
+    pub fn send_to_address(
+        &self,
+        address: Address,
+        amount: Amount,
+        fee_rate: FeeRate,
+        allow_dust: bool,
+        broadcast: bool,
+    ) -> Result<SendResult, WalletError> {
+        tracing::info!(
+            address = address.to_string(),
+            amount =? amount,
+            "Sending transaction."
+        );
+        self.master_key()?;
+        let (sender, receiver) = unbounded();
+        self.send_operation(
+            self.accounts.sends,
+            WalletOperation::SendToAddress(
+                address, amount, fee_rate, self.coin_selection, allow_dust, broadcast, sender,
+            ),
+        )?;
+        receiver.recv()?
+    }
+
+    /// Parse an address or BIP-21 URI entered by a user. Addresses for another network fail
+    /// with [WalletError::WrongNetwork].
+    pub fn parse_payment_uri(&self, uri: &str) -> Result<PaymentRequest, WalletError> {
+        payment::parse_payment_uri(uri, self.network)
+    }
+
+    /// A BIP-21 URI requesting a payment to a fresh receive address.
+    pub fn create_payment_uri(
+        &self,
+        amount: Option<Amount>,
+        label: Option<&str>,
+    ) -> Result<String, WalletError> {
+        let address = self.new_external_address()?.address;
+        let request = PaymentRequest {
+            amount,
+            label: label.map(str::to_string),
+            ..PaymentRequest::new(address)
+        };
+        Ok(request.to_string())
+    }
+
+    /// Pay a request from [DlcDevKitWallet::parse_payment_uri] and broadcast the transaction.
+    /// The request must name an amount.
+    pub fn send_payment(
+        &self,
+        request: PaymentRequest,
+        fee_rate: FeeRate,
+    ) -> Result<SendResult, WalletError> {
+        if !request.address.as_unchecked().is_valid_for_network(self.network) {
+            return Err(WalletError::WrongNetwork {
+                address: request.address.to_string(),
+                expected: self.network,
+            });
+        }
+        let amount = request.amount.ok_or_else(|| {
+            WalletError::InvalidPaymentUri("Payment request has no amount.".to_string())
+        })?;
+        self.send_to_address(request.address, amount, fee_rate, false, true)
+    }
+
+    /// Build an unsigned payment from the [WalletAccounts::sends] account to `recipients` for
+    /// signing outside the node, e.g. on a hardware wallet. Broadcast it with
+    /// [DlcDevKitWallet::broadcast_signed_psbt].
+    pub fn build_psbt(
+        &self,
+        recipients: Vec<(Address, Amount)>,
+        fee_rate: FeeRate,
+    ) -> Result<Psbt, WalletError> {
+        let (sender, receiver) = unbounded();
+        self.send_operation(
+            self.accounts.sends,
+            WalletOperation::BuildPsbt(recipients, fee_rate, sender),
+        )?;
+        receiver.recv()?
+    }
+
+    /// Finalize and broadcast a PSBT from [DlcDevKitWallet::build_psbt] once every input is
+    /// signed.
+    pub fn broadcast_signed_psbt(&self, psbt: Psbt) -> Result<Txid, WalletError> {
+        let (sender, receiver) = unbounded();
+        self.send_operation(self.accounts.sends, WalletOperation::BroadcastPsbt(psbt, sender))?;
+        let txid = receiver.recv()??;
+        tracing::info!(txid = txid.to_string(), "Broadcast externally signed transaction.");
+        Ok(txid)
+    }
+
+    /// Send every spendable UTXO of the [WalletAccounts::sends] account to `address` without a
+    /// change output. The fee is deducted from the sent amount. UTXOs reserved for contracts
+    /// are left in the wallet.
+    pub fn send_all_to_address(
+        &self,
+        address: Address,
+        fee_rate: FeeRate,
+        allow_dust: bool,
+    ) -> Result<Txid, WalletError> {
+        tracing::info!(address = address.to_string(), "Sending all funds.");
+        self.master_key()?;
+        let (sender, receiver) = unbounded();
+        self.send_operation(
+            self.accounts.sends,
+            WalletOperation::SendAllToAddress(address, fee_rate, allow_dust, sender),
+        )?;
+        receiver.recv()?
+    }
+
+    /// Every wallet transaction, labelled with the contract it belongs to.
+    pub fn get_transactions(&self) -> Result<Vec<TransactionDetails>, WalletError> {
+        let mut transactions = vec![];
+        for account in self.available_accounts() {
+            let (sender, receiver) = unbounded();
+            self.send_operation(account, WalletOperation::GetTransactions(sender))?;
+            transactions.extend(receiver.recv()?);
+        }
+        let contract_transactions = self.contract_transactions()?;
+        for transaction in transactions.iter_mut() {
+            transaction.label = history::label(&transaction.txid, &contract_transactions);
+        }
+        Ok(transactions)
+    }
+
+    pub fn get_transaction(&self, txid: Txid) -> Result<Option<TransactionDetails>, WalletError> {
+        let mut transaction = None;
+        for account in self.available_accounts() {
+            let (sender, receiver) = unbounded();
+            self.send_operation(account, WalletOperation::GetTransaction(txid, sender))?;
+            transaction = receiver.recv()?;
+            if transaction.is_some() {
+                break;
+            }
+        }
+        if let Some(transaction) = transaction.as_mut() {
+            transaction.label = history::label(&transaction.txid, &self.contract_transactions()?);
+        }
+        Ok(transaction)
+    }
+
+    /// Recorded contract transactions and the ones of contracts that are not recorded yet.
+    fn contract_transactions(&self) -> Result<HashMap<Txid, ContractTransaction>, WalletError> {
+        let mut records = self
+            .derive_signer
+            .list_contract_transactions()
+            .map_err(|e| WalletError::StorageLookup(e.to_string()))?;
+        let contracts = self
+            .derive_signer
+            .get_contracts()
+            .map_err(|e| WalletError::StorageLookup(e.to_string()))?;
+        records.extend(contracts.iter().flat_map(history::contract_transactions));
+        Ok(records.into_iter().map(|record| (record.txid, record)).collect())
+    }
+
+    /// Wallet UTXOs flagged with their reservations and the contracts they fund, followed by
+    /// the funding outputs of signed and confirmed contracts. Funding outputs are not spendable
+    /// by the wallet and have no keychain.
+    pub fn list_utxos(&self) -> Result<Vec<UtxoInfo>, WalletError> {
+        let outputs = self.local_outputs()?;
+        let (sender, receiver) = unbounded();
+        self.sender
+            .send(WalletOperation::ChainTip(sender))
+            .map_err(|e| WalletError::SendMessage(e.to_string()))?;
+        let tip_height = receiver.recv()?;
+        let contracts = self
+            .derive_signer
+            .get_contracts()
+            .map_err(|e| WalletError::StorageLookup(e.to_string()))?;
+
+        let mut utxos = utxos::wallet_utxos(
+            outputs,
+            tip_height,
+            self.network,
+            &self.reservations.reserved(),
+            &self.reservations.frozen(),
+            &contracts,
+        );
+        for contract in &contracts {
+            let (Contract::Signed(signed) | Contract::Confirmed(signed)) = contract else {
+                continue;
+            };
+            let mut funding = utxos::funding_output(signed, self.network);
+            funding.confirmations = self
+                .blockchain
+                .get_transaction_confirmations(&funding.outpoint.txid)
+                .unwrap_or_default();
+            utxos.push(funding);
+        }
+        Ok(utxos)
+    }
+
+    /// Exclude a wallet UTXO from DLC funding until it is unfrozen. Sends can still spend it.
+    pub fn freeze_utxo(&self, outpoint: OutPoint) -> Result<(), WalletError> {
+        if !self.local_outputs()?.iter().any(|utxo| utxo.outpoint == outpoint) {
+            return Err(WalletError::UnknownUtxo(outpoint));
+        }
+        self.reservations.freeze(outpoint)
+    }
+
+    /// Make a frozen UTXO available to DLC funding again. Returns false if it was not frozen.
+    pub fn unfreeze_utxo(&self, outpoint: OutPoint) -> Result<bool, WalletError> {
+        self.reservations.unfreeze(outpoint)
+    }
+
+    fn local_outputs(&self) -> Result<Vec<LocalOutput>, WalletError> {
+        self.account_outputs(&self.available_accounts())
+    }
+
+    /// Unspent outputs of `accounts`.
+    fn account_outputs(&self, accounts: &[Account]) -> Result<Vec<LocalOutput>, WalletError> {
+        let mut outputs = vec![];
+        for account in accounts {
+            let (sender, receiver) = unbounded();
+            self.send_operation(*account, WalletOperation::ListUtxos(sender))?;
+            outputs.extend(receiver.recv()?);
+        }
+        Ok(outputs)
+    }
+
+    /// Unspent outputs that may fund contracts.
+    fn funding_outputs(&self) -> Result<Vec<LocalOutput>, WalletError> {
+        self.account_outputs(&self.accounts.funding)
+    }
+
+    pub fn stats(&self) -> Result<WalletStats, WalletError> {
+        let (sender, receiver) = unbounded();
+        self.sender
+            .send(WalletOperation::Stats(sender))
+            .map_err(|e| WalletError::SendMessage(e.to_string()))?;
+        Ok(receiver.recv()?)
+    }
+
+    /// Whether the address is on the wallet network and derived from the wallet keychains.
+    pub fn is_mine(&self, address: &Address) -> Result<bool, WalletError> {
+        if !address.as_unchecked().is_valid_for_network(self.network) {
+            return Ok(false);
+        }
+        Ok(self.derivation_of(address)?.is_some())
+    }
+
+    fn derivation_of(
+        &self,
+        address: &Address,
+    ) -> Result<Option<(Account, KeychainKind, u32)>, WalletError> {
+        for account in self.available_accounts() {
+            let (sender, receiver) = unbounded();
+            self.send_operation(
+                account,
+                WalletOperation::DerivationOfSpk(address.script_pubkey(), sender),
+            )?;
+            if let Some((keychain, index)) = receiver.recv()? {
+                return Ok(Some((account, keychain, index)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Prove control of a wallet address by signing `challenge` (BIP-322 simple signature).
+    pub fn sign_address_proof(
+        &self,
+        address: &Address,
+        challenge: &[u8],
+    ) -> Result<AddressProof, WalletError> {
+        if !address.as_unchecked().is_valid_for_network(self.network) {
+            return Err(WalletError::AddressProof(format!(
+                "Address is not valid for {}.",
+                self.network
+            )));
+        }
+        let Some((account, keychain, index)) = self.derivation_of(address)? else {
+            return Err(WalletError::AddressProof(
+                "Address does not belong to the wallet.".into(),
+            ));
+        };
+
+        let coin_type = if self.network == Network::Bitcoin { 0 } else { 1 };
+        let change = match keychain {
+            KeychainKind::External => 0,
+            KeychainKind::Internal => 1,
+        };
+        let path = format!("m/84'/{}'/{}'/{}/{}", coin_type, account.index(), change, index);
+        let path = DerivationPath::from_str(&path)
+            .map_err(|e| WalletError::AddressProof(e.to_string()))?;
+        let child = self
+            .master_key()?
+            .derive_priv(&self.secp, &path)
+            .map_err(|e| WalletError::AddressProof(e.to_string()))?;
+
+        address_proof::sign_address_proof(&self.secp, &child.private_key, address, challenge)
+    }
+
+    /// Verify that the owner of `address` signed `challenge`.
+    pub fn verify_address_proof(
+        &self,
+        address: &Address,
+        challenge: &[u8],
+        proof: &AddressProof,
+    ) -> bool {
+        address_proof::verify_address_proof(&self.secp, address, challenge, proof)
+    }
+
+    /// Refresh the fee rate of every [ConfirmationTarget] from the chain backend's estimates.
+    pub fn update_fee_estimates(&self) -> Result<(), WalletError> {
+        let estimates = self
+            .blockchain
+            .fee_estimates()
+            .map_err(|e| WalletError::Blockchain(e.to_string()))?;
+        fees::apply_fee_estimates(&self.fees, &estimates);
+        tracing::debug!(targets = estimates.len(), "Updated fee estimates.");
+        if estimates.is_empty() {
+            return Ok(());
+        }
+        let stored = fees::StoredFees::new(&self.fees, unix_now());
+        if let Err(e) = self.derive_signer.save_fee_estimates(&stored) {
+            tracing::warn!(error = e.to_string(), "Could not store fee estimates.");
+        }
+        Ok(())
+    }
+
+    /// The most the [WalletAccounts::funding] accounts can lock as collateral at `fee_rate`
+    /// after paying for every input.
+    pub fn max_collateral(&self, fee_rate: FeeRate) -> Result<Amount, WalletError> {
+        let utxos = self.funding_outputs()?;
+        Ok(coin_selection::max_spendable(&utxos, fee_rate, &self.reservations.unavailable()))
+    }
+
+    /// Check that the wallet can fund `collateral` of a contract at `fee_rate` sats/vbyte.
+    /// UTXOs are selected the way the DLC manager selects them for an offer or accept, without
+    /// reserving them. Returns the amount the manager will ask for, collateral and fees.
+    pub fn check_dlc_funding(&self, collateral: u64, fee_rate: u64) -> Result<Amount, WalletError> {
+        let target = coin_selection::dlc_funding_target(collateral, fee_rate)?;
+        self.reservations.select(
+            self.coin_selection,
+            self.funding_outputs()?,
+            target,
+            FeeRate::from_sat_per_vb_unchecked(fee_rate),
+            false,
+        )?;
+        Ok(target)
+    }
+
+    /// Contract signer key at `index`.
+    fn signer_key(&self, index: u32) -> Result<Xpriv, WalletError> {
+        let child_path = DerivationPath::from_str(&format!("m/84'/0'/0'/0'/{}", index))
+            .map_err(|e| WalletError::SignerError(e.to_string()))?;
+        self.master_key()?
+            .derive_priv(&self.secp, &child_path)
+            .map_err(|e| WalletError::SignerError(e.to_string()))
+    }
+
+    /// Take the index of the next contract signer key from the counter saved in storage.
+    /// The counter is saved before the index is handed out and only counts up, so a key is
+    /// never derived twice, even after its signer was vacuumed. Negotiations running at the
+    /// same time never share a key.
+    fn next_signer_index(&self) -> Result<u32, WalletError> {
+        let storage_error = |e| WalletError::SignerError(format!("{:?}", e));
+        let mut next = self.signer_index.lock().unwrap();
+        let index = match *next {
+            Some(index) => index,
+            None => match self.derive_signer.signer_index().map_err(storage_error)? {
+                Some(index) => index,
+                None => self.first_signer_index()?,
+            },
+        };
+        self.derive_signer
+            .save_signer_index(index + 1)
+            .map_err(storage_error)?;
+        *next = Some(index + 1);
+        Ok(index)
+    }
+
+    /// Start of the signer counter for a store that has none saved yet. Stores from before
+    /// the counter derived keys past the next unused address and the stored signers, so the
+    /// counter starts past both. Runs once, the counter is saved right after.
+    fn first_signer_index(&self) -> Result<u32, WalletError> {
+        let (sender, receiver) = unbounded();
+        self.sender
+            .send(WalletOperation::NextDerivationIndex(sender))
+            .map_err(|e| WalletError::SendMessage(e.to_string()))?;
+        let derivation_index = receiver.recv()?;
+        let stored = self
+            .derive_signer
+            .list_signers()
+            .map_err(|e| WalletError::SignerError(format!("{:?}", e)))?
+            .iter()
+            .map(|(_, info)| info.index + 1)
+            .max()
+            .unwrap_or(0);
+        Ok(derivation_index.max(stored))
+    }
+
+    /// Scan the chain for the wallet's scripts until `stop_gap` unused scripts in a row, then
+    /// report its UTXOs and the outputs that may fund open contracts. Funding transactions
+    /// confirmed before `birthday_height` are left out.
+    pub fn scan_for_recovery(&self, stop_gap: usize, birthday_height: u32) -> Result<WalletRecovery, WalletError> {
+        tracing::info!(stop_gap, birthday_height, "Scanning wallet for recovery.");
+        let scan = self.full_scan(Account::Dlc, stop_gap)?;
+        let (sender, receiver) = unbounded();
+        self.sender
+            .send(WalletOperation::Recover(scan, birthday_height, sender))
+            .map_err(|e| WalletError::SendMessage(e.to_string()))?;
+        receiver.recv()?
+    }
+
+    /// Check stored signer keys against the seed and re-derive the ones that are missing or
+    /// wrong. Key ids are found from the stored signers and key usage records, and searched
+    /// up to `stop_gap` indexes past the next unused address and the recorded key usages.
+    pub fn rebuild_signers(&self, stop_gap: u32) -> Result<SignerRecovery, WalletError> {
+        let storage_error = |e| WalletError::SignerError(format!("{:?}", e));
+        let signers = self.derive_signer.list_signers().map_err(storage_error)?;
+        let usages = self
+            .derive_signer
+            .list_key_usage()
+            .map_err(storage_error)?
+            .into_iter()
+            .collect::<HashMap<_, _>>();
+
+        let (sender, receiver) = unbounded();
+        self.sender
+            .send(WalletOperation::NextDerivationIndex(sender))
+            .map_err(|e| WalletError::SendMessage(e.to_string()))?;
+        // Signer indexes count up once per negotiation, past the address index.
+        let max_index = receiver
+            .recv()?
+            .saturating_add(usages.len() as u32)
+            .saturating_add(stop_gap);
+
+        let mut report = SignerRecovery::default();
+        let mut stored = HashSet::new();
+        let mut missing = vec![];
+        for (key_id, info) in signers {
+            stored.insert(key_id);
+            if self.signer_key(info.index)?.private_key == info.secret_key {
+                report.verified += 1;
+            } else {
+                missing.push(key_id);
+            }
+        }
+        missing.extend(usages.keys().filter(|key_id| !stored.contains(*key_id)));
+        let children = if missing.is_empty() {
+            vec![]
+        } else {
+            (0..=max_index)
+                .map(|index| self.signer_key(index))
+                .collect::<Result<Vec<_>, _>>()?
+        };
+
+        for key_id in missing {
+            let Some(usage) = usages.get(&key_id) else {
+                report.unrecovered.push(hex::encode(key_id));
+                continue;
+            };
+            let found = children
+                .iter()
+                .enumerate()
+                .find(|(_, child)| signer_key_id(usage.temporary_id, child) == key_id);
+            match found {
+                Some((index, child)) => {
+                    let signer_info = SignerInformation {
+                        index: index as u32,
+                        public_key: PublicKey::from_secret_key(&self.secp, &child.private_key),
+                        secret_key: child.private_key,
+                    };
+                    self.derive_signer
+                        .store_derived_key_id(key_id, signer_info)
+                        .map_err(storage_error)?;
+                    report.restored += 1;
+                }
+                None => report.unrecovered.push(hex::encode(key_id)),
+            }
+        }
+
+        tracing::info!(
+            verified = report.verified,
+            restored = report.restored,
+            unrecovered = report.unrecovered.len(),
+            "Rebuilt signer keys."
+        );
+        Ok(report)
+    }
+
+    /// Replace-by-fee. Rebroadcasts the unconfirmed transaction `txid` of the
+    /// [WalletAccounts::sends] account spending the same inputs at `fee_rate`. Only transactions
+    /// the wallet built and signaled as replaceable can be bumped, so use
+    /// [DlcDevKitWallet::bump_fee_cpfp] for contract transactions.
+    pub fn bump_fee(&self, txid: Txid, fee_rate: FeeRate) -> Result<Txid, WalletError> {
+        tracing::info!(txid = txid.to_string(), fee_rate =? fee_rate, "Bumping fee with replacement.");
+        self.master_key()?;
+        let (sender, receiver) = unbounded();
+        self.send_operation(self.accounts.sends, WalletOperation::BumpFee(txid, fee_rate, sender))?;
+        receiver.recv()?
+    }
+
+    /// Child-pays-for-parent. Spends the wallet owned outputs of a broadcast `parent` so the
+    /// package of parent and child reaches `fee_rate`. `parent_fee` is the fee the parent pays.
+    /// Wallet UTXOs are added when the parent's outputs do not cover the fee. Returns the
+    /// broadcast child transaction.
+    pub fn bump_fee_cpfp(
+        &self,
+        parent: Transaction,
+        parent_fee: Amount,
+        fee_rate: FeeRate,
+    ) -> Result<Transaction, WalletError> {
+        tracing::info!(
+            parent = parent.compute_txid().to_string(),
+            fee_rate =? fee_rate,
+            "Bumping fee with child transaction."
+        );
+        self.master_key()?;
+        let (sender, receiver) = unbounded();
+        self.sender
+            .send(WalletOperation::Cpfp(parent, parent_fee, fee_rate, sender))
+            .map_err(|e| WalletError::SendMessage(e.to_string()))?;
+        receiver.recv()?
+    }
+
+    /// Whether the stored contract with signer `key_id` funds with the master key, as
+    /// contracts created before per contract keys do.
+    fn is_legacy_contract(&self, key_id: [u8; 32]) -> bool {
+        let master = self.get_pubkey();
+        match self.derive_signer.get_contracts() {
+            Ok(contracts) => contracts
+                .iter()
+                .filter_map(own_funding_key)
+                .any(|(id, pubkey)| id == key_id && pubkey == master),
+            Err(e) => {
+                tracing::error!(error = e.to_string(), "Could not list contracts to find legacy signers.");
+                false
+            }
+        }
+    }
+
+    /// Whether `pubkey` is the master key and a stored contract or channel funds with it.
+    fn is_legacy_funding_key(&self, pubkey: &PublicKey) -> bool {
+        if *pubkey != self.get_pubkey() {
+            return false;
+        }
+        let contracts = match self.derive_signer.get_contracts() {
+            Ok(contracts) => contracts,
+            Err(e) => {
+                tracing::error!(error = e.to_string(), "Could not list contracts to find legacy signers.");
+                return false;
+            }
+        };
+        if contracts.iter().filter_map(own_funding_key).any(|(_, own)| own == *pubkey) {
+            return true;
+        }
+        match self.derive_signer.get_signed_channels(None) {
+            Ok(channels) => channels.iter().any(|channel| channel.own_params.fund_pubkey == *pubkey),
+            Err(e) => {
+                tracing::error!(error = e.to_string(), "Could not list channels to find legacy signers.");
+                false
+            }
+        }
+    }
+}
+
+/// Signer key id and our funding pubkey of a contract we put up funding parameters for.
+fn own_funding_key(contract: &Contract) -> Option<([u8; 32], PublicKey)> {
+    let accepted_key = |accepted: &AcceptedContract| {
+        let offered = &accepted.offered_contract;
+        let pubkey = if offered.is_offer_party {
+            offered.offer_params.fund_pubkey
+        } else {
+            accepted.accept_params.fund_pubkey
+        };
+        Some((offered.keys_id, pubkey))
+    };
+    match contract {
+        Contract::Offered(offered) | Contract::Rejected(offered) if offered.is_offer_party => {
+            Some((offered.keys_id, offered.offer_params.fund_pubkey))
+        }
+        Contract::Accepted(accepted) => accepted_key(accepted),
+        Contract::Signed(signed) | Contract::Confirmed(signed) | Contract::Refunded(signed) => {
+            accepted_key(&signed.accepted_contract)
+        }
+        Contract::PreClosed(pre_closed) => accepted_key(&pre_closed.signed_contract.accepted_contract),
+        Contract::FailedSign(failed) => accepted_key(&failed.accepted_contract),
+        _ => None,
+    }
+}
+
+/// Seconds since the unix epoch.
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// A rejected broadcast, classified by the reject reason the backend relays.
+fn broadcast_error(e: ManagerError) -> WalletError {
+    let message = e.to_string();
+    WalletError::Broadcast {
+        kind: BroadcastErrorKind::from_message(&message),
+        message,
+    }
+}
+
+/// Key id of a contract signer derived for the negotiation `temp_id`.
+fn signer_key_id(temp_id: [u8; 32], child_key: &Xpriv) -> [u8; 32] {
+    let mut hasher = HashEngine::default();
+    hasher.write_all(&temp_id).unwrap();
+    hasher.write_all(&child_key.encode()).unwrap();
+    let hash: Sha256Hash = Hash::from_engine(hasher);
+    hash.to_byte_array()
+}
+
+impl<S: DdkStorage, B: DdkBlockchain> FeeEstimator for DlcDevKitWallet<S, B> {
+    fn get_est_sat_per_1000_weight(&self, confirmation_target: ConfirmationTarget) -> u32 {
+        self.fees
+            .get(&confirmation_target)
+            .map_or(fees::MIN_FEERATE, |fee| fee.load(Ordering::Acquire))
+    }
+}
+
+impl<S: DdkStorage, B: DdkBlockchain> dlc_manager::ContractSignerProvider for DlcDevKitWallet<S, B> {
+    type Signer = SimpleSigner;
+
+    // Using the data deterministically generate a key id. From a child key.
+    fn derive_signer_key_id(&self, _is_offer_party: bool, temp_id: [u8; 32]) -> [u8; 32] {
+        // Nothing is stored, so deriving the contract signer for the id fails without a panic.
+        if self.is_watch_only() {
+            tracing::error!("Watch-only wallet cannot derive contract signers.");
+            return temp_id;
+        }
+        let newest_index = self
+            .next_signer_index()
+            .expect("Could not get the next signer index.");
+        let child_key = self
+            .signer_key(newest_index)
+            .expect("Could not get child key for derivation path.");
+        let key_id = signer_key_id(temp_id, &child_key);
+        let public_key = PublicKey::from_secret_key(&self.secp, &child_key.private_key);
+        let signer_info = SignerInformation {
+            index: newest_index,
+            public_key,
+            secret_key: child_key.private_key,
+        };
+        self.derive_signer
+            .store_derived_key_id(key_id, signer_info).unwrap();
+
+        let usage = KeyUsage {
+            temporary_id: temp_id,
+            created_at: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+        };
+        if let Err(e) = self.derive_signer.record_key_usage(key_id, usage) {
+            tracing::error!(error=?e, "Could not record key usage.");
+        }
+
+        let key_id_string = hex::encode(&key_id);
+        tracing::info!(key_id = key_id_string, "Derived new key id for signer.");
+        key_id
+    }
+
+    fn derive_contract_signer(&self, key_id: [u8; 32]) -> Result<Self::Signer, ManagerError> {
+        match self.derive_signer.get_key_information(key_id) {
+            Ok(info) => {
+                tracing::info!("Derived new contract signer.");
+                Ok(SimpleSigner::new(info.secret_key))
+            }
+            // Contracts created before per contract keys stored no signer and used the master
+            // key. Any other contract without a signer lost its key, e.g. to a vacuum.
+            Err(WalletError::SignerError(_)) if self.is_legacy_contract(key_id) => {
+                tracing::warn!(
+                    key_id = hex::encode(key_id),
+                    "No signer stored for key id. Using the legacy master key."
+                );
+                let master = self
+                    .master_key()
+                    .map_err(|e| ManagerError::WalletError(Box::new(e)))?;
+                Ok(SimpleSigner::new(master.private_key))
+            }
+            Err(e) => Err(ManagerError::WalletError(Box::new(e))),
+        }
+    }
+
+    fn get_secret_key_for_pubkey(&self, pubkey: &PublicKey) -> Result<SecretKey, ManagerError> {
+        tracing::info!(
+            pubkey = pubkey.to_string(),
+            "Getting secret key from pubkey"
+        );
+        match self.derive_signer.get_secret_key(pubkey) {
+            Ok(secret_key) => Ok(secret_key),
+            Err(WalletError::SignerError(_)) if self.is_legacy_funding_key(pubkey) => {
+                self.master_key()
+                    .map(|master| master.private_key)
+                    .map_err(|e| ManagerError::WalletError(Box::new(e)))
+            }
+            Err(e) => Err(ManagerError::WalletError(Box::new(e))),
+        }
+    }
+
+    fn get_new_secret_key(&self) -> Result<SecretKey, ManagerError> {
+        let master = self
+            .master_key()
+            .map_err(|e| ManagerError::WalletError(Box::new(e)))?;
+        let (sender, receiver) = unbounded();
+        self.sender
+            .send(WalletOperation::NextDerivationIndex(sender))
+            .expect("sender.");
+        let newest_index = receiver.recv().expect("recv error");
+        let derivation_path = format!("m/86'/0'/0'/0'/{}", newest_index);
+        let child_path = DerivationPath::from_str(&derivation_path)
+            .expect("Not a valid derivation path to derive signer key.");
+        let child_key = master
+            .derive_priv(&self.secp, &child_path)
+            .expect("Could not get child key for derivation path.");
+        tracing::info!("Retrieved new secret key.");
+        Ok(child_key.private_key)
+    }
+}
+
+impl<S: DdkStorage, B: DdkBlockchain> dlc_manager::Wallet for DlcDevKitWallet<S, B> {
+    fn get_new_address(&self) -> Result<bitcoin::Address, ManagerError> {
+        tracing::info!("Retrieving new address for dlc manager");
+        let (sender, receiver) = unbounded();
+        self.sender
+            .send(WalletOperation::NewExternalAddress(sender))
+            .expect("couldn't send new address");
+        Ok(receiver
+            .recv()
+            .expect("no receive")
+            .address)
+    }
+
+    fn get_new_change_address(&self) -> Result<bitcoin::Address, ManagerError> {
+        tracing::info!("Retrieving new change address for dlc manager");
+        let (sender, receiver) = unbounded();
+        self.sender
+            .send(WalletOperation::NewChangeAddress(sender))
+            .expect("couldn't send new address");
+        Ok(receiver
+            .recv()
+            .expect("no receive")
+            .address)
+    }
+
+    fn sign_psbt_input(
+        &self,
+        psbt: &mut bitcoin::psbt::Psbt,
+        input_index: usize,
+    ) -> Result<(), ManagerError> {
+        tracing::info!("Signing psbt input for dlc manager.");
+        if let Some(external) = &self.external_signatures {
+            external
+                .sign_input(psbt, input_index)
+                .map_err(|e| ManagerError::WalletError(Box::new(e)))?;
+            if psbt.inputs[input_index].final_script_witness.is_none() {
+                let (sender, receiver) = unbounded();
+                self.sender
+                    .send(WalletOperation::FinalizePsbt(psbt.to_owned(), sender))
+                    .expect("no send finalize psbt");
+                let finalized = receiver
+                    .recv()
+                    .expect("no finalize")
+                    .map_err(|e| ManagerError::WalletError(Box::new(e)))?;
+                psbt.inputs[input_index] = finalized.inputs[input_index].clone();
+            }
+            return Ok(());
+        }
+        self.master_key()
+            .map_err(|e| ManagerError::WalletError(Box::new(e)))?;
+        // The input is from one of the funding accounts. Each wallet signs only its own.
+        for account in self.available_accounts() {
+            let (sender, receiver) = unbounded();
+            self.send_operation(
+                account,
+                WalletOperation::SignPsbtInput(psbt.to_owned(), input_index, sender),
+            )
+            .expect("no send psbt input");
+            let signed = receiver
+                .recv()
+                .expect("no sign")
+                .map_err(|e| ManagerError::WalletError(Box::new(e)))?;
+            psbt.inputs[input_index] = signed.inputs[input_index].clone();
+            if psbt.inputs[input_index].final_script_witness.is_some() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn unreserve_utxos(&self, outpoints: &[bitcoin::OutPoint]) -> Result<(), ManagerError> {
+        self.reservations
+            .release(outpoints)
+            .map_err(|e| ManagerError::WalletError(Box::new(e)))
+    }
+
+    fn import_address(&self, address: &bitcoin::Address) -> Result<(), ManagerError> {
+        // might be ok, might not
+        Ok(self
+            .derive_signer
+            .import_address_to_storage(address)
+            .unwrap())
+    }
+
+    fn get_utxos_for_amount(
+        &self,
+        amount: u64,
+        fee_rate: u64,
+        lock_utxos: bool,
+    ) -> Result<Vec<dlc_manager::Utxo>, ManagerError> {
+        let local_utxos = self
+            .funding_outputs()
+            .map_err(|e| ManagerError::WalletError(Box::new(e)))?;
+
+        let local_utxos = self
+            .reservations
+            .select(
+                self.coin_selection,
+                local_utxos,
+                Amount::from_sat(amount),
+                FeeRate::from_sat_per_vb_unchecked(fee_rate),
+                lock_utxos,
+            )
+            .map_err(|e| ManagerError::WalletError(Box::new(e)))?;
+
+        let dlc_utxos = local_utxos
+            .iter()
+            .map(|utxo| {
+                let address =
+                    Address::from_script(&utxo.txout.script_pubkey, self.network).unwrap();
+                dlc_manager::Utxo {
+                    tx_out: utxo.txout.clone(),
+                    outpoint: utxo.outpoint,
+                    address,
+                    redeem_script: ScriptBuf::new(),
+                    reserved: lock_utxos,
+                }
+            })
+            .collect();
+
+        Ok(dlc_utxos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::absolute::LockTime;
+    use bitcoin::hashes::Hash;
+    use bitcoin::secp256k1::{PublicKey, SecretKey};
+    use bitcoin::transaction::Version;
+    use bitcoin::{key::rand::Fill, AddressType};
+    use bitcoin::{Address, Amount, FeeRate, Network, OutPoint, ScriptBuf, Transaction, TxIn, TxOut, Txid, WScriptHash};
+    use bitcoin::consensus::encode::deserialize_hex;
+    use bitcoin::psbt::Psbt;
+    use dlc_manager::contract::offered_contract::OfferedContract;
+    use dlc_manager::contract::ser::Serializable;
+    use dlc_manager::{Blockchain, ContractSigner, ContractSignerProvider, Storage};
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+
+    use crate::signer::{DeriveSigner, SignerInformation};
+    use lightning::chain::chaininterface::{ConfirmationTarget, FeeEstimator};
+
+    use crate::error::{BroadcastErrorKind, WalletError};
+    use crate::storage::{SignerVacuumOptions, SledStorageProvider};
+    use crate::test_util::{InMemorySigner, TestWallet};
+    use crate::wallet::{fees, unix_now, Account, DlcDevKitWallet, SignerBackend, DEFAULT_FEE_ESTIMATES_MAX_AGE};
+    use crate::DdkStorage;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn slow_scans_do_not_block_the_wallet() {
+        let test = TestWallet::create_wallet("slow_scan");
+        test.blockchain.set_scan_delay(Duration::from_secs(3));
+        let wallet = test.wallet.clone();
+        let sync = std::thread::spawn(move || wallet.sync());
+        std::thread::sleep(Duration::from_millis(200));
+
+        let started = Instant::now();
+        let queries = (0..8)
+            .map(|_| {
+                let wallet = test.wallet.clone();
+                std::thread::spawn(move || {
+                    wallet.get_balance().unwrap();
+                    wallet.list_utxos().unwrap();
+                    wallet.new_external_address().unwrap();
+                })
+            })
+            .collect::<Vec<_>>();
+        for query in queries {
+            query.join().unwrap();
+        }
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert!(!sync.is_finished());
+        sync.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn address_is_p2wpkh() {
+        let test = TestWallet::create_wallet("p2wpkh-address");
+        let address = test.wallet.new_external_address().unwrap();
+        assert_eq!(address.address.address_type().unwrap(), AddressType::P2wpkh)
+    }
+
+    #[test]
+    fn derive_contract_signer() {
+        let test = TestWallet::create_wallet("derive_contract_signer");
+        let mut temp_key_id = [0u8; 32];
+        temp_key_id.try_fill(&mut bitcoin::key::rand::thread_rng()).unwrap();
+        let gen_key_id = test.wallet.derive_signer_key_id(true, temp_key_id);
+        println!("GEN {}", hex::encode(gen_key_id));
+        let key_info = test.wallet.derive_contract_signer(gen_key_id);
+        assert!(key_info.is_ok())
+    }
+
+    #[test]
+    fn contracts_get_their_own_keys() {
+        let test = TestWallet::create_wallet("contracts_get_their_own_keys");
+        let first = test.wallet.derive_signer_key_id(true, [1u8; 32]);
+        let second = test.wallet.derive_signer_key_id(true, [2u8; 32]);
+        let first_signer = test.wallet.derive_contract_signer(first).unwrap();
+        let second_signer = test.wallet.derive_contract_signer(second).unwrap();
+
+        let master = test.wallet.get_pubkey();
+        assert_ne!(first_signer.get_public_key(&test.wallet.secp).unwrap(), master);
+        assert_ne!(
+            first_signer.get_public_key(&test.wallet.secp).unwrap(),
+            second_signer.get_public_key(&test.wallet.secp).unwrap()
+        );
+
+        let public_key = second_signer.get_public_key(&test.wallet.secp).unwrap();
+        assert_eq!(
+            test.wallet.get_secret_key_for_pubkey(&public_key).unwrap(),
+            test.wallet.derive_contract_signer(second).unwrap().get_secret_key().unwrap()
+        );
+    }
+
+    #[test]
+    fn concurrent_negotiations_get_their_own_keys() {
+        let test = TestWallet::create_wallet("concurrent_signer_keys");
+        let key_ids = (0..10)
+            .map(|_| {
+                let wallet = test.wallet.clone();
+                std::thread::spawn(move || wallet.derive_signer_key_id(true, [7u8; 32]))
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect::<std::collections::HashSet<_>>();
+        assert_eq!(key_ids.len(), 10);
+
+        let keys = key_ids
+            .iter()
+            .map(|key_id| test.wallet.derive_contract_signer(*key_id).unwrap().get_secret_key().unwrap())
+            .collect::<std::collections::HashSet<_>>();
+        assert_eq!(keys.len(), 10);
+    }
+
+    #[test]
+    fn fee_estimates_refresh_from_chain() {
+        let test = TestWallet::create_wallet("fee_estimates_refresh");
+        let target = ConfirmationTarget::NonAnchorChannelFee;
+        let before = test.wallet.get_est_sat_per_1000_weight(target);
+
+        test.blockchain.set_fee_estimates([(12, 12.0)].into_iter().collect());
+        test.wallet.update_fee_estimates().unwrap();
+        assert_ne!(before, 3_000);
+        assert_eq!(test.wallet.get_est_sat_per_1000_weight(target), 3_000);
+
+        // The refreshed rates are stored for the next start.
+        let stored = test.wallet.derive_signer.get_fee_estimates().unwrap().unwrap();
+        assert_eq!(stored.fees[fees::target_key(target)], 3_000);
+        let restored = fees::restored_fees(
+            test.wallet.network,
+            Some(&stored),
+            DEFAULT_FEE_ESTIMATES_MAX_AGE,
+            unix_now(),
+        );
+        assert_eq!(restored[&target].load(Ordering::Acquire), 3_000);
+    }
+
+    #[test]
+    fn legacy_contracts_use_the_master_key() {
+        let test = TestWallet::create_wallet("legacy_contract_signer");
+        let master = test.wallet.get_pubkey();
+        // No signer stored and no contract funding with the master key.
+        assert!(test.wallet.derive_contract_signer([7u8; 32]).is_err());
+        assert!(test.wallet.get_secret_key_for_pubkey(&master).is_err());
+
+        // Contracts created before per contract keys stored no signer and funded with the
+        // master key.
+        let mut cursor = ::lightning::io::Cursor::new(include_bytes!(
+            "../../tests/data/dlc_storage/sled/Offered"
+        ));
+        let mut offered = OfferedContract::deserialize(&mut cursor).unwrap();
+        offered.keys_id = [7u8; 32];
+        offered.is_offer_party = true;
+        offered.offer_params.fund_pubkey = master;
+        test.storage.create_contract(&offered).unwrap();
+
+        let signer = test.wallet.derive_contract_signer([7u8; 32]).unwrap();
+        assert_eq!(signer.get_public_key(&test.wallet.secp).unwrap(), master);
+        assert_eq!(
+            test.wallet.get_secret_key_for_pubkey(&master).unwrap(),
+            test.wallet.xprv.unwrap().private_key
+        );
+
+        let unknown = PublicKey::from_secret_key(
+            &test.wallet.secp,
+            &SecretKey::from_slice(&[3u8; 32]).unwrap(),
+        );
+        assert!(test.wallet.get_secret_key_for_pubkey(&unknown).is_err());
+    }
+
+    #[test]
+    fn vacuumed_signers_do_not_fall_back_to_the_master_key() {
+        let test = TestWallet::create_wallet("vacuumed_signer");
+        let key_id = test.wallet.derive_signer_key_id(true, [5u8; 32]);
+        let pubkey = test
+            .wallet
+            .derive_contract_signer(key_id)
+            .unwrap()
+            .get_public_key(&test.wallet.secp)
+            .unwrap();
+
+        let options = SignerVacuumOptions {
+            retention: Duration::ZERO,
+            ..Default::default()
+        };
+        assert_eq!(test.storage.vacuum_signers(&options).unwrap().removed.len(), 1);
+        assert!(test.wallet.derive_contract_signer(key_id).is_err());
+        assert!(test.wallet.get_secret_key_for_pubkey(&pubkey).is_err());
+    }
+
+    #[test]
+    fn signer_index_is_not_lowered_by_vacuum() {
+        let test = TestWallet::create_wallet("signer_index_counter");
+        let first = test.wallet.derive_signer_key_id(true, [1u8; 32]);
+        let first_index = test.storage.get_key_information(first).unwrap().index;
+        let options = SignerVacuumOptions {
+            retention: Duration::ZERO,
+            ..Default::default()
+        };
+        assert_eq!(test.storage.vacuum_signers(&options).unwrap().removed.len(), 1);
+        assert_eq!(test.storage.signer_index().unwrap(), Some(first_index + 1));
+
+        // As after a restart, the next index comes from the saved counter.
+        test.wallet.signer_index.lock().unwrap().take();
+        let second = test.wallet.derive_signer_key_id(true, [2u8; 32]);
+        assert_eq!(test.storage.get_key_information(second).unwrap().index, first_index + 1);
+    }
+
+    #[test]
+    fn rebuild_signers_restores_wrong_keys() {
+        let test = TestWallet::create_wallet("rebuild_signers");
+        let key_id = test.wallet.derive_signer_key_id(true, [4u8; 32]);
+        let expected = test.wallet.derive_contract_signer(key_id).unwrap().get_secret_key().unwrap();
+
+        let wrong = SecretKey::from_slice(&[9u8; 32]).unwrap();
+        test.storage
+            .store_derived_key_id(
+                key_id,
+                SignerInformation {
+                    index: 1_000,
+                    public_key: PublicKey::from_secret_key(&test.wallet.secp, &wrong),
+                    secret_key: wrong,
+                },
+            )
+            .unwrap();
+
+        let report = test.wallet.rebuild_signers(20).unwrap();
+        assert_eq!(report.restored, 1);
+        assert!(report.unrecovered.is_empty());
+        assert_eq!(
+            test.wallet.derive_contract_signer(key_id).unwrap().get_secret_key().unwrap(),
+            expected
+        );
+        assert_eq!(test.wallet.rebuild_signers(20).unwrap().verified, 1);
+    }
+
+    #[test]
+    fn recovery_scan_finds_utxos_and_funding_outputs() {
+        let test = TestWallet::create_wallet("recovery_scan");
+        let receive = test.wallet.new_external_address().unwrap();
+        let deposit = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::from_byte_array([1u8; 32]), 0),
+                ..Default::default()
+            }],
+            output: vec![
+                TxOut {
+                    value: Amount::from_sat(50_000),
+                    script_pubkey: receive.script_pubkey(),
+                },
+                TxOut {
+                    value: Amount::from_sat(100_000),
+                    script_pubkey: receive.script_pubkey(),
+                },
+            ],
+        };
+        let change = test.wallet.new_change_address().unwrap();
+        let funding = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(deposit.compute_txid(), 1),
+                ..Default::default()
+            }],
+            output: vec![
+                TxOut {
+                    value: Amount::from_sat(80_000),
+                    script_pubkey: ScriptBuf::new_p2wsh(&WScriptHash::from_byte_array([3u8; 32])),
+                },
+                TxOut {
+                    value: Amount::from_sat(19_000),
+                    script_pubkey: change.script_pubkey(),
+                },
+            ],
+        };
+        test.blockchain.send_transaction(&deposit).unwrap();
+        test.blockchain.send_transaction(&funding).unwrap();
+
+        let recovery = test.wallet.scan_for_recovery(20, 0).unwrap();
+        let mut values = recovery.utxos.iter().map(|utxo| utxo.value.to_sat()).collect::<Vec<_>>();
+        values.sort();
+        assert_eq!(values, vec![19_000, 50_000]);
+        assert_eq!(recovery.potential_contracts.len(), 1);
+        let potential = &recovery.potential_contracts[0];
+        assert_eq!(potential.funding_outpoint, OutPoint::new(funding.compute_txid(), 0));
+        assert_eq!(potential.our_contribution, Amount::from_sat(100_000));
+        assert!(potential.spent_by.is_none());
+    }
+
+    #[test]
+    fn watch_only_wallet_sees_the_same_utxos() {
+        let test = TestWallet::create_wallet("watch_only_wallet");
+        let descriptors = test.wallet.export_descriptors().unwrap();
+        assert!(descriptors.external.starts_with("wpkh(tpub"));
+        assert!(descriptors.external.contains('#'));
+        assert!(!descriptors.internal.contains("tprv"));
+
+        let watch_path = format!("{}/watch", test.path);
+        let watch_storage = Arc::new(SledStorageProvider::new(&format!("{}/sled", watch_path)).unwrap());
+        let watch = DlcDevKitWallet::new_watch_only(
+            "watch",
+            &descriptors,
+            test.blockchain.clone(),
+            Network::Regtest,
+            &watch_path,
+            watch_storage,
+        )
+        .unwrap();
+        assert!(watch.is_watch_only());
+        assert_eq!(watch.export_descriptors().unwrap(), descriptors);
+
+        let receive = test.wallet.new_external_address().unwrap();
+        let change = test.wallet.new_change_address().unwrap();
+        let deposit = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::from_byte_array([2u8; 32]), 0),
+                ..Default::default()
+            }],
+            output: vec![
+                TxOut {
+                    value: Amount::from_sat(40_000),
+                    script_pubkey: receive.script_pubkey(),
+                },
+                TxOut {
+                    value: Amount::from_sat(60_000),
+                    script_pubkey: change.script_pubkey(),
+                },
+            ],
+        };
+        test.blockchain.send_transaction(&deposit).unwrap();
+        test.wallet.sync().unwrap();
+        watch.sync().unwrap();
+
+        let outpoints = |wallet: &DlcDevKitWallet<SledStorageProvider, _>| {
+            let mut outpoints = wallet
+                .list_utxos()
+                .unwrap()
+                .into_iter()
+                .map(|utxo| utxo.outpoint)
+                .collect::<Vec<_>>();
+            outpoints.sort();
+            outpoints
+        };
+        assert_eq!(outpoints(&test.wallet).len(), 2);
+        assert_eq!(outpoints(&watch), outpoints(&test.wallet));
+        assert_eq!(watch.get_balance().unwrap(), test.wallet.get_balance().unwrap());
+
+        let send = watch.send_to_address(
+            receive.address.clone(),
+            Amount::from_sat(10_000),
+            FeeRate::from_sat_per_vb_unchecked(1),
+            false,
+            true,
+        );
+        assert!(matches!(send, Err(WalletError::WatchOnly)));
+        let mut psbt = Psbt::from_unsigned_tx(deposit).unwrap();
+        assert!(dlc_manager::Wallet::sign_psbt_input(&watch, &mut psbt, 0).is_err());
+        let key_id = watch.derive_signer_key_id(true, [1u8; 32]);
+        assert!(watch.derive_contract_signer(key_id).is_err());
+        assert!(watch.get_new_secret_key().is_err());
+    }
+
+    /// Pay `value` to a new address of the test wallet and sync it.
+    fn deposit(test: &TestWallet, value: u64) -> Transaction {
+        deposit_to(test, Account::Dlc, value)
+    }
+
+    /// Pay `value` to a new address of `account` and sync the wallet.
+    fn deposit_to(test: &TestWallet, account: Account, value: u64) -> Transaction {
+        let receive = test.wallet.new_external_address_for(account).unwrap();
+        let deposit = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                // A distinct input per deposit so deposits do not conflict.
+                previous_output: OutPoint::new(Txid::hash(receive.script_pubkey().as_bytes()), 0),
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(value),
+                script_pubkey: receive.script_pubkey(),
+            }],
+        };
+        test.blockchain.send_transaction(&deposit).unwrap();
+        test.wallet.sync().unwrap();
+        deposit
+    }
+
+    #[test]
+    fn contracts_are_funded_from_the_dlc_account() {
+        let test = TestWallet::create_wallet("funding_account");
+        let spending = deposit_to(&test, Account::Spending, 200_000);
+        let selected = dlc_manager::Wallet::get_utxos_for_amount(&*test.wallet, 50_000, 2, false);
+        assert!(selected.is_err());
+
+        let dlc = deposit_to(&test, Account::Dlc, 100_000);
+        let balances = test.wallet.get_balance_detailed().unwrap();
+        assert_eq!(balances.dlc.total(), Amount::from_sat(100_000));
+        assert_eq!(balances.spending.total(), Amount::from_sat(200_000));
+        assert_eq!(test.wallet.get_balance().unwrap().total(), Amount::from_sat(300_000));
+
+        let selected =
+            dlc_manager::Wallet::get_utxos_for_amount(&*test.wallet, 50_000, 2, false).unwrap();
+        assert_eq!(selected.len(), 1);
+        assert_eq!(selected[0].outpoint.txid, dlc.compute_txid());
+        assert_ne!(selected[0].outpoint.txid, spending.compute_txid());
+
+        let spending_address = test.wallet.new_external_address_for(Account::Spending).unwrap();
+        assert!(test.wallet.is_mine(&spending_address.address).unwrap());
+        let proof = test
+            .wallet
+            .sign_address_proof(&spending_address.address, b"challenge")
+            .unwrap();
+        assert!(test
+            .wallet
+            .verify_address_proof(&spending_address.address, b"challenge", &proof));
+    }
+
+    #[test]
+    fn send_without_broadcast_returns_the_signed_transaction() {
+        let test = TestWallet::create_wallet("send_without_broadcast");
+        let deposit = deposit(&test, 100_000);
+        let fee_rate = FeeRate::from_sat_per_vb_unchecked(2);
+
+        let recipient = Address::p2wsh(&ScriptBuf::new(), Network::Regtest);
+        let sent = test
+            .wallet
+            .send_to_address(recipient, Amount::from_sat(20_000), fee_rate, false, false)
+            .unwrap();
+        let tx: Transaction = deserialize_hex(sent.raw_tx.as_ref().unwrap()).unwrap();
+        assert_eq!(tx.compute_txid(), sent.txid);
+        assert_eq!(tx.vsize() as u64, sent.vsize);
+        assert!(sent.fee_paid >= fee_rate.fee_vb(sent.vsize).unwrap());
+        assert_eq!(test.blockchain.broadcasts(), vec![deposit.compute_txid()]);
+    }
+
+    #[test]
+    fn broadcast_rejections_are_typed() {
+        let test = TestWallet::create_wallet("broadcast_rejections");
+        deposit(&test, 100_000);
+        let recipient = Address::p2wsh(&ScriptBuf::new(), Network::Regtest);
+        let send = || {
+            test.wallet.send_to_address(
+                recipient.clone(),
+                Amount::from_sat(20_000),
+                FeeRate::from_sat_per_vb_unchecked(1),
+                false,
+                true,
+            )
+        };
+
+        for (reason, expected) in [
+            ("min relay fee not met, 100 < 141", BroadcastErrorKind::MinRelayFee),
+            ("txn-mempool-conflict", BroadcastErrorKind::MempoolConflict),
+            ("txn-already-in-mempool", BroadcastErrorKind::AlreadyKnown),
+            ("non-final", BroadcastErrorKind::Other),
+        ] {
+            test.blockchain.reject_broadcasts(Some(reason));
+            match send() {
+                Err(WalletError::Broadcast { kind, message }) => {
+                    assert_eq!(kind, expected);
+                    assert!(message.contains(reason));
+                }
+                other => panic!("Expected a broadcast error, got {:?}", other),
+            }
+        }
+
+        test.blockchain.reject_broadcasts(None);
+        let sent = send().unwrap();
+        assert!(sent.raw_tx.is_none());
+        assert!(test.blockchain.broadcasts().contains(&sent.txid));
+    }
+
+    #[test]
+    fn external_signer_signs_for_watch_only_wallet() {
+        let test = TestWallet::create_wallet("external_signer");
+        let descriptors = test.wallet.export_descriptors().unwrap();
+        let watch_path = format!("{}/watch", test.path);
+        let watch_storage = Arc::new(SledStorageProvider::new(&format!("{}/sled", watch_path)).unwrap());
+        let signer = InMemorySigner::new(test.wallet.xprv.unwrap());
+        let watch = DlcDevKitWallet::new_watch_only(
+            "watch",
+            &descriptors,
+            test.blockchain.clone(),
+            Network::Regtest,
+            &watch_path,
+            watch_storage,
+        )
+        .unwrap()
+        .with_signer_backend(SignerBackend::External(Arc::new(signer)), Duration::from_secs(60));
+
+        let receive = watch.new_external_address().unwrap();
+        let deposit = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::from_byte_array([3u8; 32]), 0),
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(100_000),
+                script_pubkey: receive.script_pubkey(),
+            }],
+        };
+        test.blockchain.send_transaction(&deposit).unwrap();
+        watch.sync().unwrap();
+
+        let recipient = Address::p2wsh(&ScriptBuf::new(), Network::Regtest);
+        let mut psbt = watch
+            .build_psbt(
+                vec![(recipient, Amount::from_sat(30_000))],
+                FeeRate::from_sat_per_vb_unchecked(2),
+            )
+            .unwrap();
+        let unsigned_txid = psbt.unsigned_tx.compute_txid();
+
+        // The first attempt hands the input to the signer instead of blocking.
+        assert!(dlc_manager::Wallet::sign_psbt_input(&watch, &mut psbt, 0).is_err());
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while dlc_manager::Wallet::sign_psbt_input(&watch, &mut psbt, 0).is_err() {
+            assert!(Instant::now() < deadline, "No signature from external signer.");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(psbt.inputs[0].final_script_witness.is_some());
+
+        let txid = watch.broadcast_signed_psbt(psbt).unwrap();
+        assert_eq!(txid, unsigned_txid);
+    }
+}
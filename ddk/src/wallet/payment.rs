@@ -0,0 +1,266 @@
+//! BIP-21 payment URIs and addresses entered by users.
+use crate::error::WalletError;
+use bitcoin::address::NetworkUnchecked;
+use bitcoin::{Address, Amount, Denomination, Network};
+use std::fmt;
+use std::str::FromStr;
+
+const SCHEME: &str = "bitcoin:";
+
+/// A payment parsed from an address or a BIP-21 URI, with the address checked against the
+/// wallet's network.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PaymentRequest {
+    pub address: Address,
+    pub amount: Option<Amount>,
+    pub label: Option<String>,
+    pub message: Option<String>,
+}
+
+impl PaymentRequest {
+    pub fn new(address: Address) -> PaymentRequest {
+        PaymentRequest {
+            address,
+            amount: None,
+            label: None,
+            message: None,
+        }
+    }
+}
+
+/// Formats the request as a BIP-21 URI.
+impl fmt::Display for PaymentRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", SCHEME, self.address)?;
+        let mut params = vec![];
+        if let Some(amount) = self.amount {
+            params.push(format!("amount={}", format_btc(amount)));
+        }
+        if let Some(label) = &self.label {
+            params.push(format!("label={}", percent_encode(label)));
+        }
+        if let Some(message) = &self.message {
+            params.push(format!("message={}", percent_encode(message)));
+        }
+        if !params.is_empty() {
+            write!(f, "?{}", params.join("&"))?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse a raw address or a `bitcoin:` URI. The address must be valid on `network`, else
+/// [WalletError::WrongNetwork]. Unknown parameters are ignored unless they start with `req-`,
+/// which BIP-21 requires to be understood.
+pub fn parse_payment_uri(uri: &str, network: Network) -> Result<PaymentRequest, WalletError> {
+    let invalid = |reason: &str| WalletError::InvalidPaymentUri(reason.to_string());
+    let uri = uri.trim();
+    let (address, query) = match uri.get(..SCHEME.len()) {
+        Some(scheme) if scheme.eq_ignore_ascii_case(SCHEME) => {
+            let rest = &uri[SCHEME.len()..];
+            match rest.split_once('?') {
+                Some((address, query)) => (address, Some(query)),
+                None => (rest, None),
+            }
+        }
+        _ if uri.contains(':') => return Err(invalid("Scheme is not bitcoin.")),
+        _ => (uri, None),
+    };
+    if address.is_empty() {
+        return Err(invalid("Address is missing."));
+    }
+
+    let address = Address::<NetworkUnchecked>::from_str(address)
+        .map_err(|e| WalletError::InvalidPaymentUri(format!("Invalid address. {}", e)))?;
+    if !address.is_valid_for_network(network) {
+        return Err(WalletError::WrongNetwork {
+            address: address.assume_checked().to_string(),
+            expected: network,
+        });
+    }
+    let mut request = PaymentRequest::new(address.assume_checked());
+
+    for param in query.into_iter().flat_map(|query| query.split('&')) {
+        if param.is_empty() {
+            continue;
+        }
+        let (key, value) = param
+            .split_once('=')
+            .ok_or_else(|| invalid("Parameter has no value."))?;
+        let value = percent_decode(value)?;
+        let duplicate = match key {
+            "amount" => {
+                let amount = Amount::from_str_in(&value, Denomination::Bitcoin).map_err(|e| {
+                    WalletError::InvalidPaymentUri(format!("Invalid amount. {}", e))
+                })?;
+                request.amount.replace(amount).is_some()
+            }
+            "label" => request.label.replace(value).is_some(),
+            "message" => request.message.replace(value).is_some(),
+            key if key.starts_with("req-") => {
+                return Err(WalletError::InvalidPaymentUri(format!(
+                    "Required parameter {} is not supported.",
+                    key
+                )))
+            }
+            _ => false,
+        };
+        if duplicate {
+            return Err(WalletError::InvalidPaymentUri(format!(
+                "Parameter {} is given twice.",
+                key
+            )));
+        }
+    }
+    Ok(request)
+}
+
+/// BTC with up to 8 decimals and no trailing zeros, as BIP-21 amounts are written.
+fn format_btc(amount: Amount) -> String {
+    let sats = amount.to_sat();
+    let whole = sats / 100_000_000;
+    let fraction = sats % 100_000_000;
+    if fraction == 0 {
+        return whole.to_string();
+    }
+    let fraction = format!("{:08}", fraction);
+    format!("{}.{}", whole, fraction.trim_end_matches('0'))
+}
+
+/// Percent-encode everything but RFC 3986 unreserved characters.
+fn percent_encode(value: &str) -> String {
+    let mut encoded = String::new();
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+fn percent_decode(value: &str) -> Result<String, WalletError> {
+    let invalid = || WalletError::InvalidPaymentUri("Invalid percent-encoding.".to_string());
+    let mut bytes = vec![];
+    let mut chars = value.bytes();
+    while let Some(byte) = chars.next() {
+        if byte != b'%' {
+            bytes.push(byte);
+            continue;
+        }
+        let hex = [
+            chars.next().ok_or_else(invalid)?,
+            chars.next().ok_or_else(invalid)?,
+        ];
+        let hex = std::str::from_utf8(&hex).map_err(|_| invalid())?;
+        bytes.push(u8::from_str_radix(hex, 16).map_err(|_| invalid())?);
+    }
+    String::from_utf8(bytes).map_err(|_| invalid())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::secp256k1::{Secp256k1, SecretKey};
+    use bitcoin::CompressedPublicKey;
+
+    fn address(network: Network) -> Address {
+        let secp = Secp256k1::new();
+        let secret_key = SecretKey::from_slice(&[7u8; 32]).unwrap();
+        let public_key = CompressedPublicKey(secret_key.public_key(&secp));
+        Address::p2wpkh(&public_key, network)
+    }
+
+    #[test]
+    fn raw_addresses_and_uris() {
+        let regtest = address(Network::Regtest);
+        let request = parse_payment_uri(&format!(" {} ", regtest), Network::Regtest).unwrap();
+        assert_eq!(request, PaymentRequest::new(regtest.clone()));
+
+        let uri = format!(
+            "bitcoin:{}?amount=0.0015&label=Alice%27s%20bet&message=For%20the%20%E2%82%AC%20game&lightning=lnbc1",
+            regtest
+        );
+        let request = parse_payment_uri(&uri, Network::Regtest).unwrap();
+        assert_eq!(request.address, regtest);
+        assert_eq!(request.amount, Some(Amount::from_sat(150_000)));
+        assert_eq!(request.label.as_deref(), Some("Alice's bet"));
+        assert_eq!(request.message.as_deref(), Some("For the € game"));
+
+        // QR codes upper-case the whole URI.
+        let upper = format!("BITCOIN:{}?amount=1", regtest.to_string().to_uppercase());
+        let request = parse_payment_uri(&upper, Network::Regtest).unwrap();
+        assert_eq!(request.address, regtest);
+        assert_eq!(request.amount, Some(Amount::ONE_BTC));
+    }
+
+    #[test]
+    fn uris_round_trip() {
+        let request = PaymentRequest {
+            address: address(Network::Bitcoin),
+            amount: Some(Amount::from_sat(123_450_000)),
+            label: Some("Bet #4 & more".to_string()),
+            message: None,
+        };
+        let uri = request.to_string();
+        assert!(uri.starts_with("bitcoin:bc1"));
+        assert!(uri.contains("amount=1.2345&label=Bet%20%234%20%26%20more"));
+        assert_eq!(parse_payment_uri(&uri, Network::Bitcoin).unwrap(), request);
+
+        let bare = PaymentRequest::new(address(Network::Bitcoin));
+        assert_eq!(bare.to_string(), format!("bitcoin:{}", bare.address));
+    }
+
+    #[test]
+    fn addresses_must_match_the_network() {
+        let testnet = address(Network::Testnet).to_string();
+        assert!(matches!(
+            parse_payment_uri(&testnet, Network::Bitcoin),
+            Err(WalletError::WrongNetwork {
+                expected: Network::Bitcoin,
+                ..
+            })
+        ));
+        assert!(matches!(
+            parse_payment_uri(
+                &format!("bitcoin:{}", address(Network::Bitcoin)),
+                Network::Regtest
+            ),
+            Err(WalletError::WrongNetwork {
+                expected: Network::Regtest,
+                ..
+            })
+        ));
+        // Testnet and signet share addresses.
+        assert!(parse_payment_uri(&testnet, Network::Signet).is_ok());
+    }
+
+    #[test]
+    fn malformed_uris_are_rejected() {
+        let address = address(Network::Regtest);
+        for uri in [
+            "".to_string(),
+            "bitcoin:".to_string(),
+            "bitcoin:notanaddress".to_string(),
+            format!("litecoin:{}", address),
+            format!("bitcoin:{}?amount=1,5", address),
+            format!("bitcoin:{}?amount=-1", address),
+            format!("bitcoin:{}?amount=0.000000001", address),
+            format!("bitcoin:{}?amount=1&amount=2", address),
+            format!("bitcoin:{}?label", address),
+            format!("bitcoin:{}?label=%ZZ", address),
+            format!("bitcoin:{}?label=%E2%82", address),
+            format!("bitcoin:{}?req-somethingyoudontunderstand=50", address),
+        ] {
+            assert!(
+                matches!(
+                    parse_payment_uri(&uri, Network::Regtest),
+                    Err(WalletError::InvalidPaymentUri(_))
+                ),
+                "{} should be invalid",
+                uri
+            );
+        }
+    }
+}
@@ -0,0 +1,226 @@
+//! UTXOs locked for DLC funding, so offers made in quick succession do not select the same
+//! inputs, and UTXOs the operator froze to keep them out of DLC funding. Both are persisted and
+//! survive a restart.
+use bdk_wallet::LocalOutput;
+use bitcoin::{Amount, FeeRate, OutPoint};
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+use super::coin_selection::{self, CoinSelectionStrategy};
+use crate::error::WalletError;
+use crate::DdkStorage;
+
+pub struct UtxoReservations<S> {
+    storage: Arc<S>,
+    reserved: Mutex<HashSet<OutPoint>>,
+    frozen: Mutex<HashSet<OutPoint>>,
+}
+
+impl<S: DdkStorage> UtxoReservations<S> {
+    /// Load the reservations persisted in `storage`.
+    pub fn load(storage: Arc<S>) -> anyhow::Result<UtxoReservations<S>> {
+        let reserved = storage.list_reserved_utxos()?.into_iter().collect();
+        let frozen = storage.list_frozen_utxos()?.into_iter().collect();
+        Ok(UtxoReservations {
+            storage,
+            reserved: Mutex::new(reserved),
+            frozen: Mutex::new(frozen),
+        })
+    }
+
+    pub fn reserved(&self) -> HashSet<OutPoint> {
+        self.reserved.lock().unwrap().clone()
+    }
+
+    pub fn frozen(&self) -> HashSet<OutPoint> {
+        self.frozen.lock().unwrap().clone()
+    }
+
+    /// Reserved and frozen UTXOs, which DLC funding does not select.
+    pub fn unavailable(&self) -> HashSet<OutPoint> {
+        let mut unavailable = self.reserved();
+        unavailable.extend(self.frozen());
+        unavailable
+    }
+
+    /// Keep a UTXO out of DLC funding until it is unfrozen.
+    pub fn freeze(&self, outpoint: OutPoint) -> Result<(), WalletError> {
+        let mut frozen = self.frozen.lock().unwrap();
+        if frozen.insert(outpoint) {
+            self.persist_frozen(&frozen)?;
+        }
+        Ok(())
+    }
+
+    /// Make a frozen UTXO available to DLC funding again. Returns false if it was not frozen.
+    pub fn unfreeze(&self, outpoint: OutPoint) -> Result<bool, WalletError> {
+        let mut frozen = self.frozen.lock().unwrap();
+        if !frozen.remove(&outpoint) {
+            return Ok(false);
+        }
+        self.persist_frozen(&frozen)?;
+        Ok(true)
+    }
+
+    /// Select UTXOs covering `target` that are not reserved or frozen. With `lock` the selected
+    /// UTXOs are reserved before another selection can see them.
+    pub fn select(
+        &self,
+        strategy: CoinSelectionStrategy,
+        candidates: Vec<LocalOutput>,
+        target: Amount,
+        fee_rate: FeeRate,
+        lock: bool,
+    ) -> Result<Vec<LocalOutput>, WalletError> {
+        let mut reserved = self.reserved.lock().unwrap();
+        let before = reserved.len();
+        // Reserved UTXOs that are no longer unspent were spent by their funding transaction.
+        reserved.retain(|outpoint| candidates.iter().any(|utxo| utxo.outpoint == *outpoint));
+
+        let mut excluded = reserved.clone();
+        excluded.extend(self.frozen.lock().unwrap().iter().copied());
+        let selected =
+            coin_selection::select_coins(strategy, candidates, target, fee_rate, &excluded);
+        if let Ok(selected) = &selected {
+            if lock {
+                reserved.extend(selected.iter().map(|utxo| utxo.outpoint));
+            }
+        }
+        if reserved.len() != before || (lock && selected.is_ok()) {
+            self.persist(&reserved)?;
+        }
+        selected
+    }
+
+    /// Release reservations, ex. when a negotiation fails.
+    pub fn release(&self, outpoints: &[OutPoint]) -> Result<(), WalletError> {
+        let mut reserved = self.reserved.lock().unwrap();
+        for outpoint in outpoints {
+            reserved.remove(outpoint);
+        }
+        self.persist(&reserved)
+    }
+
+    fn persist(&self, reserved: &HashSet<OutPoint>) -> Result<(), WalletError> {
+        let outpoints = reserved.iter().copied().collect::<Vec<_>>();
+        self.storage
+            .save_reserved_utxos(&outpoints)
+            .map_err(|e| WalletError::Reservation(e.to_string()))
+    }
+
+    fn persist_frozen(&self, frozen: &HashSet<OutPoint>) -> Result<(), WalletError> {
+        let outpoints = frozen.iter().copied().collect::<Vec<_>>();
+        self.storage
+            .save_frozen_utxos(&outpoints)
+            .map_err(|e| WalletError::Reservation(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorageProvider;
+    use bdk_chain::{BlockId, ChainPosition, ConfirmationBlockTime};
+    use bdk_wallet::KeychainKind;
+    use bitcoin::hashes::Hash;
+    use bitcoin::{BlockHash, ScriptBuf, TxOut, Txid};
+
+    fn utxos(values: &[u64]) -> Vec<LocalOutput> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(vout, value)| LocalOutput {
+                outpoint: OutPoint::new(Txid::all_zeros(), vout as u32),
+                txout: TxOut {
+                    value: Amount::from_sat(*value),
+                    script_pubkey: ScriptBuf::new(),
+                },
+                keychain: KeychainKind::External,
+                is_spent: false,
+                derivation_index: vout as u32,
+                chain_position: ChainPosition::Confirmed(ConfirmationBlockTime {
+                    block_id: BlockId {
+                        height: 1,
+                        hash: BlockHash::all_zeros(),
+                    },
+                    confirmation_time: 1,
+                }),
+            })
+            .collect()
+    }
+
+    fn fund(
+        reservations: &UtxoReservations<MemoryStorageProvider>,
+        sats: u64,
+    ) -> Result<Vec<LocalOutput>, WalletError> {
+        reservations.select(
+            CoinSelectionStrategy::LargestFirst,
+            utxos(&[40_000, 40_000, 40_000]),
+            Amount::from_sat(sats),
+            FeeRate::from_sat_per_vb_unchecked(1),
+            true,
+        )
+    }
+
+    #[test]
+    fn concurrent_offers_do_not_share_utxos() {
+        let reservations = UtxoReservations::load(Arc::new(MemoryStorageProvider::new())).unwrap();
+        let results = std::thread::scope(|scope| {
+            let first = scope.spawn(|| fund(&reservations, 70_000));
+            let second = scope.spawn(|| fund(&reservations, 70_000));
+            [first.join().unwrap(), second.join().unwrap()]
+        });
+
+        assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 1);
+        assert!(results
+            .iter()
+            .any(|r| matches!(r, Err(WalletError::InsufficientFunds { .. }))));
+        assert_eq!(reservations.reserved().len(), 2);
+    }
+
+    #[test]
+    fn reservations_survive_restart_and_release() {
+        let storage = Arc::new(MemoryStorageProvider::new());
+        let selected = fund(&UtxoReservations::load(storage.clone()).unwrap(), 70_000).unwrap();
+        let outpoints = selected.iter().map(|u| u.outpoint).collect::<Vec<_>>();
+
+        let reopened = UtxoReservations::load(storage.clone()).unwrap();
+        assert_eq!(reopened.reserved(), outpoints.iter().copied().collect());
+        assert!(fund(&reopened, 70_000).is_err());
+
+        reopened.release(&outpoints).unwrap();
+        assert!(fund(&UtxoReservations::load(storage).unwrap(), 70_000).is_ok());
+    }
+
+    #[test]
+    fn frozen_utxos_are_not_selected() {
+        let storage = Arc::new(MemoryStorageProvider::new());
+        let reservations = UtxoReservations::load(storage.clone()).unwrap();
+        let frozen = OutPoint::new(Txid::all_zeros(), 0);
+        reservations.freeze(frozen).unwrap();
+        let selected = fund(&reservations, 70_000).unwrap();
+        assert!(selected.iter().all(|utxo| utxo.outpoint != frozen));
+        assert!(fund(&reservations, 30_000).is_err());
+
+        let reopened = UtxoReservations::load(storage).unwrap();
+        assert_eq!(reopened.frozen(), HashSet::from([frozen]));
+        assert!(reopened.unfreeze(frozen).unwrap());
+        assert!(!reopened.unfreeze(frozen).unwrap());
+        assert!(fund(&reopened, 30_000).is_ok());
+    }
+
+    #[test]
+    fn unlocked_selection_reserves_nothing() {
+        let reservations = UtxoReservations::load(Arc::new(MemoryStorageProvider::new())).unwrap();
+        reservations
+            .select(
+                CoinSelectionStrategy::LargestFirst,
+                utxos(&[40_000, 40_000, 40_000]),
+                Amount::from_sat(70_000),
+                FeeRate::from_sat_per_vb_unchecked(1),
+                false,
+            )
+            .unwrap();
+        assert!(reservations.reserved().is_empty());
+    }
+}
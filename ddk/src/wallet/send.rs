@@ -0,0 +1,371 @@
+//! Payments from the wallet to an external address.
+use bdk_wallet::error::CreateTxError;
+use bdk_wallet::Wallet;
+use bitcoin::psbt::Psbt;
+use bitcoin::{Address, Amount, FeeRate, OutPoint, Txid};
+use std::collections::HashSet;
+
+use super::coin_selection::{self, CoinSelectionStrategy, P2WPKH_INPUT_VBYTES, P2WPKH_OUTPUT_VBYTES};
+use crate::error::WalletError;
+
+/// Size of a transaction without inputs paying to a recipient and change, both P2WPKH.
+const SEND_BASE_VBYTES: u64 = 11 + 2 * P2WPKH_OUTPUT_VBYTES;
+/// Size of a transaction without inputs paying to a single P2WPKH output.
+const DRAIN_BASE_VBYTES: u64 = 11 + P2WPKH_OUTPUT_VBYTES;
+
+/// A signed payment from [crate::wallet::DlcDevKitWallet::send_to_address].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SendResult {
+    pub txid: Txid,
+    pub fee_paid: Amount,
+    pub vsize: u64,
+    /// The signed transaction in hex when it was not broadcast.
+    pub raw_tx: Option<String>,
+}
+
+/// Refuse outputs that nodes would not relay, unless `allow_dust` is set.
+pub fn check_dust(address: &Address, amount: Amount, allow_dust: bool) -> Result<(), WalletError> {
+    let dust_limit = address.script_pubkey().minimal_non_dust();
+    if !allow_dust && amount < dust_limit {
+        return Err(WalletError::BelowDustLimit { amount, dust_limit });
+    }
+    Ok(())
+}
+
+/// Build a payment of `amount` to `address`. UTXOs in `reserved` are never spent.
+pub(crate) fn build_send(
+    wallet: &mut Wallet,
+    address: &Address,
+    amount: Amount,
+    fee_rate: FeeRate,
+    strategy: CoinSelectionStrategy,
+    allow_dust: bool,
+    reserved: &HashSet<OutPoint>,
+) -> Result<Psbt, WalletError> {
+    check_dust(address, amount, allow_dust)?;
+    let base_fee = fee_rate.fee_vb(SEND_BASE_VBYTES).unwrap_or(Amount::MAX_MONEY);
+    let candidates = wallet.list_unspent().collect::<Vec<_>>();
+    let available = coin_selection::max_spendable(&candidates, fee_rate, reserved);
+    let selected = coin_selection::select_coins(
+        strategy,
+        candidates,
+        amount + base_fee,
+        fee_rate,
+        reserved,
+    )?;
+    let outpoints = selected.iter().map(|utxo| utxo.outpoint).collect::<Vec<_>>();
+
+    let mut txn_builder = wallet.build_tx();
+    txn_builder
+        .add_utxos(&outpoints)?
+        .manually_selected_only()
+        .add_recipient(address.script_pubkey(), amount)
+        .enable_rbf()
+        .allow_dust(allow_dust)
+        .fee_rate(fee_rate);
+
+    txn_builder
+        .finish()
+        .map_err(|e| insufficient_funds(e, amount + base_fee, available))
+}
+
+/// Build a payment of every spendable UTXO to `address`. The fee is taken from the sent
+/// amount and there is no change output. UTXOs in `reserved` are never spent.
+pub(crate) fn build_send_all(
+    wallet: &mut Wallet,
+    address: &Address,
+    fee_rate: FeeRate,
+    allow_dust: bool,
+    reserved: &HashSet<OutPoint>,
+) -> Result<Psbt, WalletError> {
+    let spendable = wallet
+        .list_unspent()
+        .filter(|utxo| !reserved.contains(&utxo.outpoint))
+        .collect::<Vec<_>>();
+    let available = spendable.iter().map(|utxo| utxo.txout.value).sum::<Amount>();
+    let fee = fee_rate
+        .fee_vb(DRAIN_BASE_VBYTES + P2WPKH_INPUT_VBYTES * spendable.len() as u64)
+        .unwrap_or(Amount::MAX_MONEY);
+    if spendable.is_empty() || available <= fee {
+        return Err(WalletError::InsufficientFunds {
+            needed: fee,
+            available,
+        });
+    }
+
+    let mut txn_builder = wallet.build_tx();
+    txn_builder
+        .drain_wallet()
+        .drain_to(address.script_pubkey())
+        .unspendable(reserved.iter().copied().collect())
+        .enable_rbf()
+        .allow_dust(allow_dust)
+        .fee_rate(fee_rate);
+
+    let psbt = txn_builder
+        .finish()
+        .map_err(|e| insufficient_funds(e, fee, available))?;
+    let sent = psbt
+        .unsigned_tx
+        .output
+        .iter()
+        .map(|output| output.value)
+        .sum::<Amount>();
+    check_dust(address, sent, allow_dust)?;
+    Ok(psbt)
+}
+
+/// Build an unsigned payment to several recipients for signing outside the wallet. UTXOs in
+/// `reserved` are never spent.
+pub(crate) fn build_payment(
+    wallet: &mut Wallet,
+    recipients: &[(Address, Amount)],
+    fee_rate: FeeRate,
+    reserved: &HashSet<OutPoint>,
+) -> Result<Psbt, WalletError> {
+    for (address, amount) in recipients {
+        check_dust(address, *amount, false)?;
+    }
+    let total = recipients.iter().map(|(_, amount)| *amount).sum::<Amount>();
+    let base_fee = fee_rate.fee_vb(SEND_BASE_VBYTES).unwrap_or(Amount::MAX_MONEY);
+    let candidates = wallet.list_unspent().collect::<Vec<_>>();
+    let available = coin_selection::max_spendable(&candidates, fee_rate, reserved);
+
+    let mut txn_builder = wallet.build_tx();
+    txn_builder
+        .set_recipients(
+            recipients
+                .iter()
+                .map(|(address, amount)| (address.script_pubkey(), *amount))
+                .collect(),
+        )
+        .unspendable(reserved.iter().copied().collect())
+        .enable_rbf()
+        .fee_rate(fee_rate);
+
+    txn_builder
+        .finish()
+        .map_err(|e| insufficient_funds(e, total + base_fee, available))
+}
+
+/// Build a replacement of the unconfirmed wallet transaction `txid` paying `fee_rate`. The
+/// replacement spends the same inputs so only one of them can confirm. Extra inputs are added
+/// when the change cannot cover the higher fee, but never from `reserved`.
+pub(crate) fn build_fee_bump(
+    wallet: &mut Wallet,
+    txid: Txid,
+    fee_rate: FeeRate,
+    reserved: &HashSet<OutPoint>,
+) -> Result<Psbt, WalletError> {
+    let mut txn_builder = wallet.build_fee_bump(txid)?;
+    txn_builder
+        .fee_rate(fee_rate)
+        .unspendable(reserved.iter().copied().collect())
+        .enable_rbf();
+    Ok(txn_builder.finish()?)
+}
+
+/// The transaction builder reports missing funds as a coin selection error.
+fn insufficient_funds(e: CreateTxError, needed: Amount, available: Amount) -> WalletError {
+    match e {
+        CreateTxError::CoinSelection(_) | CreateTxError::NoUtxosSelected => {
+            WalletError::InsufficientFunds { needed, available }
+        }
+        e => e.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bdk_chain::TxGraph;
+    use bdk_wallet::template::Bip84;
+    use bdk_wallet::{KeychainKind, SignOptions, Update};
+    use bitcoin::absolute::LockTime;
+    use bitcoin::bip32::Xpriv;
+    use bitcoin::hashes::Hash;
+    use bitcoin::transaction::Version;
+    use bitcoin::{Network, Transaction, TxIn, TxOut, Txid};
+
+    fn wallet() -> Wallet {
+        let xprv = Xpriv::new_master(Network::Regtest, &[5u8; 64]).unwrap();
+        Wallet::create(
+            Bip84(xprv, KeychainKind::External),
+            Bip84(xprv, KeychainKind::Internal),
+        )
+        .network(Network::Regtest)
+        .create_wallet_no_persist()
+        .unwrap()
+    }
+
+    fn fund(wallet: &mut Wallet, values: &[u64]) {
+        let address = wallet.next_unused_address(KeychainKind::External);
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::from_byte_array([1u8; 32]), 0),
+                ..Default::default()
+            }],
+            output: values
+                .iter()
+                .map(|value| TxOut {
+                    value: Amount::from_sat(*value),
+                    script_pubkey: address.script_pubkey(),
+                })
+                .collect(),
+        };
+        apply_unconfirmed(wallet, tx);
+    }
+
+    fn apply_unconfirmed(wallet: &mut Wallet, tx: Transaction) {
+        let txid = tx.compute_txid();
+        let mut graph = TxGraph::default();
+        let _ = graph.insert_tx(tx);
+        let _ = graph.insert_seen_at(txid, 1);
+        wallet
+            .apply_update(Update {
+                graph,
+                ..Default::default()
+            })
+            .unwrap();
+    }
+
+    fn recipient() -> Address {
+        let mut other = wallet();
+        other.next_unused_address(KeychainKind::External).address
+    }
+
+    #[test]
+    fn send_all_empties_the_wallet() {
+        let mut wallet = wallet();
+        fund(&mut wallet, &[60_000, 40_000]);
+        let fee_rate = FeeRate::from_sat_per_vb_unchecked(2);
+
+        let mut psbt =
+            build_send_all(&mut wallet, &recipient(), fee_rate, false, &HashSet::new()).unwrap();
+        wallet.sign(&mut psbt, SignOptions::default()).unwrap();
+        let tx = psbt.extract_tx().unwrap();
+        assert_eq!(tx.input.len(), 2);
+        assert_eq!(tx.output.len(), 1);
+        let fee = Amount::from_sat(100_000) - tx.output[0].value;
+        assert!(fee >= fee_rate.fee_vb(tx.vsize() as u64).unwrap());
+
+        apply_unconfirmed(&mut wallet, tx);
+        assert_eq!(wallet.balance().total(), Amount::ZERO);
+    }
+
+    #[test]
+    fn send_all_leaves_reserved_utxos() {
+        let mut wallet = wallet();
+        fund(&mut wallet, &[60_000, 40_000]);
+        let reserved = wallet
+            .list_unspent()
+            .filter(|utxo| utxo.txout.value == Amount::from_sat(40_000))
+            .map(|utxo| utxo.outpoint)
+            .collect::<HashSet<_>>();
+
+        let psbt = build_send_all(
+            &mut wallet,
+            &recipient(),
+            FeeRate::from_sat_per_vb_unchecked(1),
+            false,
+            &reserved,
+        )
+        .unwrap();
+        assert_eq!(psbt.unsigned_tx.input.len(), 1);
+        assert!(!reserved.contains(&psbt.unsigned_tx.input[0].previous_output));
+    }
+
+    #[test]
+    fn send_all_without_funds() {
+        let mut wallet = wallet();
+        let result = build_send_all(
+            &mut wallet,
+            &recipient(),
+            FeeRate::from_sat_per_vb_unchecked(1),
+            false,
+            &HashSet::new(),
+        );
+        assert!(matches!(result, Err(WalletError::InsufficientFunds { available, .. }) if available == Amount::ZERO));
+    }
+
+    #[test]
+    fn send_more_than_balance() {
+        let mut wallet = wallet();
+        fund(&mut wallet, &[50_000]);
+        let result = build_send(
+            &mut wallet,
+            &recipient(),
+            Amount::from_sat(50_000),
+            FeeRate::from_sat_per_vb_unchecked(1),
+            CoinSelectionStrategy::default(),
+            false,
+            &HashSet::new(),
+        );
+        assert!(matches!(result, Err(WalletError::InsufficientFunds { .. })));
+    }
+
+    #[test]
+    fn fee_bump_replaces_the_original() {
+        let mut wallet = wallet();
+        fund(&mut wallet, &[100_000]);
+        let mut psbt = build_send(
+            &mut wallet,
+            &recipient(),
+            Amount::from_sat(30_000),
+            FeeRate::from_sat_per_vb_unchecked(1),
+            CoinSelectionStrategy::default(),
+            false,
+            &HashSet::new(),
+        )
+        .unwrap();
+        wallet.sign(&mut psbt, SignOptions::default()).unwrap();
+        let original = psbt.extract_tx().unwrap();
+        assert!(original.is_explicitly_rbf());
+        let original_rate = wallet.calculate_fee_rate(&original).unwrap();
+        apply_unconfirmed(&mut wallet, original.clone());
+
+        let mut psbt = build_fee_bump(
+            &mut wallet,
+            original.compute_txid(),
+            FeeRate::from_sat_per_vb_unchecked(10),
+            &HashSet::new(),
+        )
+        .unwrap();
+        wallet.sign(&mut psbt, SignOptions::default()).unwrap();
+        let replacement = psbt.extract_tx().unwrap();
+
+        assert_ne!(replacement.compute_txid(), original.compute_txid());
+        assert!(replacement
+            .input
+            .iter()
+            .any(|input| original.input.iter().any(|o| o.previous_output == input.previous_output)));
+        let replacement_rate = wallet.calculate_fee_rate(&replacement).unwrap();
+        assert!(replacement_rate > original_rate);
+        assert!(replacement_rate >= FeeRate::from_sat_per_vb_unchecked(10));
+    }
+
+    #[test]
+    fn fee_bump_unknown_transaction() {
+        let mut wallet = wallet();
+        let result = build_fee_bump(
+            &mut wallet,
+            Txid::from_byte_array([9u8; 32]),
+            FeeRate::from_sat_per_vb_unchecked(10),
+            &HashSet::new(),
+        );
+        assert!(matches!(result, Err(WalletError::FeeBump(_))));
+    }
+
+    #[test]
+    fn dust_needs_opt_in() {
+        let address = recipient();
+        assert!(matches!(
+            check_dust(&address, Amount::from_sat(100), false),
+            Err(WalletError::BelowDustLimit { .. })
+        ));
+        assert!(check_dust(&address, Amount::from_sat(100), true).is_ok());
+        assert!(check_dust(&address, Amount::from_sat(1_000), false).is_ok());
+    }
+}
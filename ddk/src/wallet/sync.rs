@@ -0,0 +1,164 @@
+//! Background wallet sync that survives chain backend outages.
+use crate::error::WalletError;
+use crate::events::{DdkEvent, EventBus};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Longest wait between sync attempts while the chain backend keeps failing.
+pub const MAX_SYNC_BACKOFF: Duration = Duration::from_secs(5 * 60);
+
+/// Outcome of the recent wallet syncs.
+#[derive(Debug, Clone, Default)]
+pub struct SyncStatus {
+    pub last_successful_sync: Option<Instant>,
+    /// Start of the current run of failed syncs. `None` when the last sync succeeded.
+    pub failing_since: Option<Instant>,
+    pub consecutive_failures: u32,
+    pub last_error: Option<String>,
+}
+
+/// Records the result of every sync, including ones requested outside the background task.
+#[derive(Debug, Default)]
+pub struct SyncTracker {
+    status: Mutex<SyncStatus>,
+}
+
+impl SyncTracker {
+    pub fn record(&self, result: &Result<(), WalletError>) {
+        let mut status = self.status.lock().unwrap();
+        match result {
+            Ok(()) => {
+                status.last_successful_sync = Some(Instant::now());
+                status.failing_since = None;
+                status.consecutive_failures = 0;
+                status.last_error = None;
+            }
+            Err(e) => {
+                status.failing_since.get_or_insert_with(Instant::now);
+                status.consecutive_failures += 1;
+                status.last_error = Some(e.to_string());
+            }
+        }
+    }
+
+    pub fn status(&self) -> SyncStatus {
+        self.status.lock().unwrap().clone()
+    }
+}
+
+/// Sync every `interval`. Failures are retried with a doubling delay up to [MAX_SYNC_BACKOFF],
+/// and once syncs have failed for `warn_after` a [DdkEvent::WalletSyncFailing] is emitted.
+pub async fn sync_loop<F>(
+    sync: F,
+    tracker: Arc<SyncTracker>,
+    events: Arc<EventBus>,
+    interval: Duration,
+    warn_after: Duration,
+) where
+    F: Fn() -> Result<(), WalletError>,
+{
+    let mut delay = interval;
+    let mut warned = false;
+    loop {
+        match sync() {
+            Ok(()) => {
+                if warned {
+                    tracing::info!("Wallet sync recovered.");
+                    events.emit(DdkEvent::WalletSyncRecovered);
+                    warned = false;
+                }
+                delay = interval;
+            }
+            Err(e) => {
+                let status = tracker.status();
+                delay = (delay * 2).min(MAX_SYNC_BACKOFF.max(interval));
+                tracing::warn!(
+                    failures = status.consecutive_failures,
+                    retry_in_secs = delay.as_secs(),
+                    error = e.to_string(),
+                    "Wallet sync failed."
+                );
+                let failing_for = status
+                    .failing_since
+                    .map(|since| since.elapsed())
+                    .unwrap_or_default();
+                if !warned && failing_for >= warn_after {
+                    tracing::error!(
+                        failing_for_secs = failing_for.as_secs(),
+                        error = e.to_string(),
+                        "Wallet has not synced for too long."
+                    );
+                    events.emit(DdkEvent::WalletSyncFailing {
+                        failing_for,
+                        error: e.to_string(),
+                    });
+                    warned = true;
+                }
+            }
+        }
+        tokio::time::sleep(delay).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[tokio::test]
+    async fn loop_survives_failing_backend() {
+        let tracker = Arc::new(SyncTracker::default());
+        let events = Arc::new(EventBus::default());
+        let subscriber = events.subscribe();
+        let attempts = Arc::new(AtomicU32::new(0));
+
+        let (sync_tracker, sync_attempts) = (tracker.clone(), attempts.clone());
+        let sync = move || {
+            let result = if sync_attempts.fetch_add(1, Ordering::SeqCst) < 3 {
+                Err(WalletError::SendMessage("esplora is down".into()))
+            } else {
+                Ok(())
+            };
+            sync_tracker.record(&result);
+            result
+        };
+        let task = tokio::spawn(sync_loop(
+            sync,
+            tracker.clone(),
+            events,
+            Duration::from_millis(1),
+            Duration::ZERO,
+        ));
+
+        let mut received = Vec::new();
+        while !received.contains(&DdkEvent::WalletSyncRecovered) {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+            received.extend(subscriber.try_iter());
+        }
+        task.abort();
+
+        let status = tracker.status();
+        assert!(status.last_successful_sync.is_some());
+        assert_eq!(status.consecutive_failures, 0);
+        assert!(status.failing_since.is_none());
+        assert!(attempts.load(Ordering::SeqCst) >= 4);
+
+        assert_eq!(received.len(), 2);
+        assert!(matches!(&received[0], DdkEvent::WalletSyncFailing { error, .. } if error.contains("esplora is down")));
+        assert_eq!(received[1], DdkEvent::WalletSyncRecovered);
+    }
+
+    #[test]
+    fn failures_keep_the_first_failure_time() {
+        let tracker = SyncTracker::default();
+        tracker.record(&Err(WalletError::SendMessage("first".into())));
+        let since = tracker.status().failing_since.unwrap();
+        tracker.record(&Err(WalletError::SendMessage("second".into())));
+
+        let status = tracker.status();
+        assert_eq!(status.failing_since, Some(since));
+        assert_eq!(status.consecutive_failures, 2);
+        assert_eq!(status.last_error.as_deref(), Some(WalletError::SendMessage("second".into()).to_string().as_str()));
+        assert!(status.last_successful_sync.is_none());
+    }
+}
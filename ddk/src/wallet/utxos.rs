@@ -0,0 +1,188 @@
+//! Wallet UTXOs with what keeps them from being spent, for operators deciding which coins are
+//! free, which are reserved for in-flight offers, and which are locked in contracts.
+use bdk_chain::ChainPosition;
+use bdk_wallet::{KeychainKind, LocalOutput};
+use bitcoin::{Address, Amount, Network, OutPoint, Transaction};
+use dlc_manager::contract::signed_contract::SignedContract;
+use dlc_manager::contract::Contract;
+use dlc_manager::ContractId;
+use serde::{Serialize, Serializer};
+use std::collections::{HashMap, HashSet};
+
+use crate::chain::watch::funding_outpoint;
+
+/// A UTXO of the wallet, or the funding output of a signed or confirmed contract.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UtxoInfo {
+    pub outpoint: OutPoint,
+    pub value: Amount,
+    /// None for scripts without an address form.
+    pub address: Option<Address>,
+    /// Zero while the transaction is unconfirmed.
+    pub confirmations: u32,
+    /// None for contract funding outputs, which the wallet does not derive.
+    pub keychain: Option<KeychainKind>,
+    /// Selected as an input of an offer or accept that has not been funded yet.
+    pub reserved: bool,
+    /// Excluded from DLC funding by the operator.
+    pub frozen: bool,
+    /// The contract funded by the UTXO, or the contract of a funding output. The temporary id
+    /// while the contract is an offer.
+    #[serde(serialize_with = "serialize_contract_id")]
+    pub dlc_collateral: Option<ContractId>,
+}
+
+fn serialize_contract_id<S: Serializer>(
+    contract_id: &Option<ContractId>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    contract_id.map(hex::encode).serialize(serializer)
+}
+
+/// Annotate the wallet's unspent `outputs` with their reservations and contracts.
+pub(crate) fn wallet_utxos(
+    outputs: Vec<LocalOutput>,
+    tip_height: u32,
+    network: Network,
+    reserved: &HashSet<OutPoint>,
+    frozen: &HashSet<OutPoint>,
+    contracts: &[Contract],
+) -> Vec<UtxoInfo> {
+    let funding_inputs = funding_inputs(contracts);
+    outputs
+        .into_iter()
+        .map(|output| UtxoInfo {
+            outpoint: output.outpoint,
+            value: output.txout.value,
+            address: Address::from_script(&output.txout.script_pubkey, network).ok(),
+            confirmations: match output.chain_position {
+                ChainPosition::Confirmed(anchor) => {
+                    (tip_height + 1).saturating_sub(anchor.block_id.height)
+                }
+                ChainPosition::Unconfirmed(_) => 0,
+            },
+            keychain: Some(output.keychain),
+            reserved: reserved.contains(&output.outpoint),
+            frozen: frozen.contains(&output.outpoint),
+            dlc_collateral: funding_inputs.get(&output.outpoint).copied(),
+        })
+        .collect()
+}
+
+/// The funding output of a contract, with its confirmations left at zero.
+pub(crate) fn funding_output(contract: &SignedContract, network: Network) -> UtxoInfo {
+    let outpoint = funding_outpoint(contract);
+    let output = &contract.accepted_contract.dlc_transactions.fund.output[outpoint.vout as usize];
+    UtxoInfo {
+        outpoint,
+        value: output.value,
+        address: Address::from_script(&output.script_pubkey, network).ok(),
+        confirmations: 0,
+        keychain: None,
+        reserved: false,
+        frozen: false,
+        dlc_collateral: Some(contract.accepted_contract.get_contract_id()),
+    }
+}
+
+/// Inputs of contracts that are not funded yet, by contract. Both parties' inputs are listed,
+/// only ours match wallet UTXOs.
+fn funding_inputs(contracts: &[Contract]) -> HashMap<OutPoint, ContractId> {
+    let mut inputs = HashMap::new();
+    for contract in contracts {
+        let (offered, accepted) = match contract {
+            Contract::Offered(o) => (o, None),
+            Contract::Accepted(a) => (&a.offered_contract, Some(a)),
+            Contract::Signed(s) => (
+                &s.accepted_contract.offered_contract,
+                Some(&s.accepted_contract),
+            ),
+            _ => continue,
+        };
+        let funding_inputs = offered
+            .funding_inputs
+            .iter()
+            .chain(accepted.iter().flat_map(|a| a.funding_inputs.iter()));
+        for input in funding_inputs {
+            let Ok(prev_tx) =
+                bitcoin::consensus::deserialize::<Transaction>(&input.funding_input.prev_tx)
+            else {
+                continue;
+            };
+            let outpoint = OutPoint::new(prev_tx.compute_txid(), input.funding_input.prev_tx_vout);
+            inputs.insert(outpoint, contract.get_id());
+        }
+    }
+    inputs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bdk_chain::{BlockId, ConfirmationBlockTime};
+    use bitcoin::hashes::Hash;
+    use bitcoin::{BlockHash, TxOut};
+    use dlc_manager::contract::ser::Serializable;
+
+    fn signed() -> SignedContract {
+        let mut cursor = ::lightning::io::Cursor::new(include_bytes!(
+            "../../tests/data/dlc_storage/sled/Signed"
+        ));
+        SignedContract::deserialize(&mut cursor).unwrap()
+    }
+
+    fn output(outpoint: OutPoint, height: Option<u32>) -> LocalOutput {
+        LocalOutput {
+            outpoint,
+            txout: TxOut {
+                value: Amount::from_sat(50_000),
+                script_pubkey: bitcoin::ScriptBuf::new(),
+            },
+            keychain: KeychainKind::External,
+            is_spent: false,
+            derivation_index: 0,
+            chain_position: match height {
+                Some(height) => ChainPosition::Confirmed(ConfirmationBlockTime {
+                    block_id: BlockId {
+                        height,
+                        hash: BlockHash::all_zeros(),
+                    },
+                    confirmation_time: 1,
+                }),
+                None => ChainPosition::Unconfirmed(1),
+            },
+        }
+    }
+
+    #[test]
+    fn utxos_are_flagged() {
+        let signed = signed();
+        let contract_id = signed.accepted_contract.get_contract_id();
+        let funding = signed.accepted_contract.dlc_transactions.fund.input[0].previous_output;
+        let free = OutPoint::new(bitcoin::Txid::all_zeros(), 7);
+        let outputs = vec![output(funding, None), output(free, Some(98))];
+
+        let utxos = wallet_utxos(
+            outputs,
+            100,
+            Network::Regtest,
+            &HashSet::from([funding]),
+            &HashSet::from([free]),
+            &[Contract::Signed(signed.clone())],
+        );
+        assert_eq!(utxos[0].dlc_collateral, Some(contract_id));
+        assert!(utxos[0].reserved && !utxos[0].frozen);
+        assert_eq!(utxos[0].confirmations, 0);
+        assert_eq!(utxos[1].dlc_collateral, None);
+        assert!(!utxos[1].reserved && utxos[1].frozen);
+        assert_eq!(utxos[1].confirmations, 3);
+        assert_eq!(utxos[1].keychain, Some(KeychainKind::External));
+
+        let funding_output = funding_output(&signed, Network::Regtest);
+        assert_eq!(funding_output.outpoint, funding_outpoint(&signed));
+        assert_eq!(funding_output.keychain, None);
+        assert!(funding_output.address.is_some());
+        let json = serde_json::to_value(&funding_output).unwrap();
+        assert_eq!(json["dlc_collateral"], hex::encode(contract_id));
+    }
+}
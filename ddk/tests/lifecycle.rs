@@ -0,0 +1,76 @@
+//! The whole contract lifecycle on the testkit's mock chain, oracle and transport, without
+//! network access.
+use ddk::contract::ContractState;
+use ddk::testkit::harness::{enum_contract_input, wait_for_state, TestHarness, TestNode};
+use dlc::{EnumerationPayout, Payout};
+use dlc_manager::{Blockchain, ContractId};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const WAIT: Duration = Duration::from_secs(60);
+
+/// Check `node` until it stores the contract as closed.
+fn wait_for_close(node: &TestNode, contract_id: ContractId) {
+    let deadline = Instant::now() + WAIT;
+    loop {
+        node.force_check().unwrap();
+        let closed = wait_for_state(node, contract_id, ContractState::Closed, Duration::from_secs(1));
+        if closed.is_ok() {
+            return;
+        }
+        assert!(Instant::now() < deadline, "{}", closed.unwrap_err());
+    }
+}
+
+#[test]
+fn contract_lifecycle_on_mock_chain() {
+    let harness = TestHarness::new_pair();
+    let maturity = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as u32
+        + 86_400;
+    let announcement = harness
+        .oracle
+        .create_enum_event("lifecycle", &["yes", "no"], maturity)
+        .unwrap();
+    let payouts = [("yes", 100_000), ("no", 0)]
+        .into_iter()
+        .map(|(outcome, offer)| EnumerationPayout {
+            outcome: outcome.to_string(),
+            payout: Payout {
+                offer,
+                accept: 100_000 - offer,
+            },
+        })
+        .collect();
+    let contract_input = enum_contract_input(&announcement, payouts);
+
+    // Offered, accepted and signed, with the funding transaction broadcast.
+    let contract_id = harness.offer_and_accept(&contract_input).unwrap();
+    let funding_txid = harness
+        .alice
+        .get_contract(contract_id)
+        .unwrap()
+        .unwrap()
+        .funding_txid
+        .unwrap();
+    assert!(harness.blockchain.broadcasts().contains(&funding_txid));
+
+    harness.mine_blocks(6);
+    for node in [&harness.alice, &harness.bob] {
+        node.force_check().unwrap();
+        wait_for_state(node, contract_id, ContractState::Confirmed, WAIT).unwrap();
+    }
+
+    // Alice closes with the CET of the attested outcome, fetched from the oracle.
+    harness.attest("lifecycle", "yes").unwrap();
+    let cet_txid = harness.alice.close_contract(contract_id, vec![]).unwrap();
+    harness.mine_blocks(6);
+    assert!(harness.blockchain.get_transaction_confirmations(&cet_txid).unwrap() >= 1);
+
+    for node in [&harness.alice, &harness.bob] {
+        wait_for_close(node, contract_id);
+        let closed = node.get_contract(contract_id).unwrap().unwrap();
+        assert_eq!(closed.summary.closing_txid, Some(cet_txid));
+    }
+}
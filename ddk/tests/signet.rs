@@ -0,0 +1,40 @@
+#![cfg(feature = "test-util")]
+use bitcoin::bip32::Xpriv;
+use bitcoin::key::rand::Fill;
+use bitcoin::{Amount, Network};
+use ddk::chain::network::ChainName;
+use ddk::chain::EsploraClient;
+use ddk::config::DdkConfig;
+use ddk::storage::SledStorageProvider;
+use ddk::testkit::signet_faucet_fund;
+use ddk::wallet::DlcDevKitWallet;
+use ddk::DdkBlockchain;
+use std::sync::Arc;
+
+#[test]
+#[ignore = "requests coins from the public mutinynet faucet"]
+fn mutinynet_faucet_funds_wallet() {
+    let path = "tests/data/mutinynet_faucet";
+    {
+        let config = DdkConfig::for_chain(ChainName::Mutinynet);
+        let blockchain = Arc::new(EsploraClient::from_config(&config).unwrap());
+        let storage = Arc::new(SledStorageProvider::new(path).unwrap());
+        let mut entropy = [0u8; 64];
+        entropy
+            .try_fill(&mut bitcoin::key::rand::thread_rng())
+            .unwrap();
+        let xprv = Xpriv::new_master(Network::Signet, &entropy).unwrap();
+        let wallet =
+            DlcDevKitWallet::new("mutinynet", xprv, blockchain, Network::Signet, path, storage)
+                .unwrap();
+
+        let address = wallet.new_external_address().unwrap().address;
+        let outpoint = signet_faucet_fund(&wallet, &address, Amount::from_sat(100_000)).unwrap();
+        assert!(wallet
+            .list_utxos()
+            .unwrap()
+            .iter()
+            .any(|utxo| utxo.outpoint == outpoint));
+    }
+    std::fs::remove_dir_all(path).unwrap();
+}
@@ -0,0 +1,484 @@
+//! Payout curves of numeric contracts built from points instead of curve pieces.
+//!
+//! A [PayoutCurveBuilder] takes the offer party's payout at some outcomes, interpolates linearly
+//! between them and extends the first and last payout to the ends of the oracle's outcome range.
+//! [linear_payout], [long_call] and [short_put] return builders for common contracts.
+use anyhow::{anyhow, bail};
+use bitcoin::key::XOnlyPublicKey;
+use dlc_manager::contract::contract_input::{ContractInput, ContractInputInfo, OracleInput};
+use dlc_manager::contract::numerical_descriptor::NumericalDescriptor;
+use dlc_manager::contract::ContractDescriptor;
+use dlc_manager::payout_curve::{
+    PayoutFunction, PayoutFunctionPiece, PayoutPoint, PolynomialPayoutCurvePiece, RoundingInterval,
+    RoundingIntervals,
+};
+use dlc_messages::oracle_msgs::{EventDescriptor, OracleAnnouncement};
+use dlc_trie::OracleNumericInfo;
+
+/// Largest outcome an oracle can attest with `nb_digits` digits in `base`.
+pub fn max_outcome(base: usize, nb_digits: usize) -> anyhow::Result<u64> {
+    (base as u64)
+        .checked_pow(nb_digits as u32)
+        .map(|outcomes| outcomes - 1)
+        .ok_or_else(|| anyhow!("{} digits in base {} do not fit in a u64.", nb_digits, base))
+}
+
+/// Payout curve of a numeric contract from (outcome, offer payout) points.
+///
+/// The accept party gets the rest of the total collateral at every outcome.
+#[derive(Debug, Clone)]
+pub struct PayoutCurveBuilder {
+    offer_collateral: u64,
+    accept_collateral: u64,
+    points: Vec<(u64, u64)>,
+    rounding_intervals: Vec<RoundingInterval>,
+}
+
+impl PayoutCurveBuilder {
+    /// A curve without points, rounding payouts to the sat.
+    pub fn new(offer_collateral: u64, accept_collateral: u64) -> PayoutCurveBuilder {
+        PayoutCurveBuilder {
+            offer_collateral,
+            accept_collateral,
+            points: Vec::new(),
+            rounding_intervals: Vec::new(),
+        }
+    }
+
+    /// Pay the offer party `payout` sats at `outcome`. Points are added in increasing outcome
+    /// order.
+    pub fn point(mut self, outcome: u64, payout: u64) -> Self {
+        self.points.push((outcome, payout));
+        self
+    }
+
+    pub fn points(mut self, points: impl IntoIterator<Item = (u64, u64)>) -> Self {
+        self.points.extend(points);
+        self
+    }
+
+    /// Round payouts to a multiple of `rounding_mod` sats from `outcome` on, which cuts the
+    /// number of CETs of steep curves.
+    pub fn rounding(mut self, outcome: u64, rounding_mod: u64) -> Self {
+        self.rounding_intervals.push(RoundingInterval {
+            begin_interval: outcome,
+            rounding_mod,
+        });
+        self
+    }
+
+    pub fn total_collateral(&self) -> u64 {
+        self.offer_collateral + self.accept_collateral
+    }
+
+    /// Check that outcomes strictly increase up to `max_outcome` and that no payout exceeds
+    /// the total collateral.
+    pub fn validate(&self, max_outcome: u64) -> anyhow::Result<()> {
+        let total = self.total_collateral();
+        if self.points.is_empty() {
+            bail!("Payout curve has no points.");
+        }
+        if let Some(pair) = self.points.windows(2).find(|pair| pair[0].0 >= pair[1].0) {
+            bail!(
+                "Outcomes must increase, but {} follows {}.",
+                pair[1].0,
+                pair[0].0
+            );
+        }
+        if let Some((outcome, payout)) = self.points.iter().find(|(_, payout)| *payout > total) {
+            bail!(
+                "Payout of {} sats at outcome {} exceeds the total collateral of {} sats.",
+                payout,
+                outcome,
+                total
+            );
+        }
+        if let Some((outcome, _)) = self.points.last().filter(|(o, _)| *o > max_outcome) {
+            bail!(
+                "Outcome {} is above the largest outcome of the oracle, {}.",
+                outcome,
+                max_outcome
+            );
+        }
+        if let Some(pair) = self
+            .rounding_intervals
+            .windows(2)
+            .find(|pair| pair[0].begin_interval >= pair[1].begin_interval)
+        {
+            bail!(
+                "Rounding intervals must increase, but {} follows {}.",
+                pair[1].begin_interval,
+                pair[0].begin_interval
+            );
+        }
+        if self.rounding_intervals.iter().any(|i| i.rounding_mod == 0) {
+            bail!("Rounding modulus must be at least 1.");
+        }
+        Ok(())
+    }
+
+    /// Linear pieces between the points, with the first and last payout held flat to outcome
+    /// zero and `max_outcome`.
+    pub fn payout_function(&self, max_outcome: u64) -> anyhow::Result<PayoutFunction> {
+        self.validate(max_outcome)?;
+        let first = self.points[0];
+        let last = self.points[self.points.len() - 1];
+        let points = (first.0 > 0)
+            .then_some((0, first.1))
+            .into_iter()
+            .chain(self.points.iter().copied())
+            .chain((last.0 < max_outcome).then_some((max_outcome, last.1)))
+            .map(|(event_outcome, outcome_payout)| PayoutPoint {
+                event_outcome,
+                outcome_payout,
+                extra_precision: 0,
+            })
+            .collect::<Vec<_>>();
+        if points.len() < 2 {
+            bail!("Payout curve needs at least two outcomes.");
+        }
+        let pieces = points
+            .windows(2)
+            .map(|pair| {
+                PolynomialPayoutCurvePiece::new(pair.to_vec())
+                    .map(PayoutFunctionPiece::PolynomialPayoutCurvePiece)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(PayoutFunction::new(pieces)?)
+    }
+
+    /// The rounding set with [PayoutCurveBuilder::rounding], or rounding to the sat.
+    pub fn rounding_intervals(&self) -> RoundingIntervals {
+        let mut intervals = self.rounding_intervals.clone();
+        if !matches!(intervals.first(), Some(i) if i.begin_interval == 0) {
+            intervals.insert(
+                0,
+                RoundingInterval {
+                    begin_interval: 0,
+                    rounding_mod: 1,
+                },
+            );
+        }
+        RoundingIntervals { intervals }
+    }
+
+    /// Descriptor for an event with `nb_digits` digits in `base`.
+    pub fn numerical_descriptor(
+        &self,
+        base: usize,
+        nb_digits: usize,
+    ) -> anyhow::Result<NumericalDescriptor> {
+        Ok(NumericalDescriptor {
+            payout_function: self.payout_function(max_outcome(base, nb_digits)?)?,
+            rounding_intervals: self.rounding_intervals(),
+            difference_params: None,
+            oracle_numeric_infos: OracleNumericInfo {
+                base,
+                nb_digits: vec![nb_digits],
+            },
+        })
+    }
+
+    /// A contract on the announced numeric event, with the digits and base of the announcement.
+    pub fn contract_input(
+        &self,
+        announcement: &OracleAnnouncement,
+        fee_rate: u64,
+    ) -> anyhow::Result<ContractInput> {
+        let EventDescriptor::DigitDecompositionEvent(event) =
+            &announcement.oracle_event.event_descriptor
+        else {
+            bail!(
+                "Event {} is not numeric.",
+                announcement.oracle_event.event_id
+            );
+        };
+        if event.is_signed {
+            bail!(
+                "Event {} has signed outcomes, which numeric contracts do not support.",
+                announcement.oracle_event.event_id
+            );
+        }
+        let descriptor =
+            self.numerical_descriptor(event.base as usize, event.nb_digits as usize)?;
+        Ok(self.input(
+            descriptor,
+            announcement.oracle_public_key,
+            announcement.oracle_event.event_id.clone(),
+            fee_rate,
+        ))
+    }
+
+    fn input(
+        &self,
+        descriptor: NumericalDescriptor,
+        oracle: XOnlyPublicKey,
+        event_id: String,
+        fee_rate: u64,
+    ) -> ContractInput {
+        ContractInput {
+            offer_collateral: self.offer_collateral,
+            accept_collateral: self.accept_collateral,
+            fee_rate,
+            contract_infos: vec![ContractInputInfo {
+                contract_descriptor: ContractDescriptor::Numerical(descriptor),
+                oracles: OracleInput {
+                    public_keys: vec![oracle],
+                    event_id,
+                    threshold: 1,
+                },
+            }],
+        }
+    }
+}
+
+/// The offer party gets nothing up to `min_price`, everything from `max_price` on, and a share
+/// growing linearly with the price in between.
+pub fn linear_payout(
+    min_price: u64,
+    max_price: u64,
+    offer_collateral: u64,
+    accept_collateral: u64,
+) -> anyhow::Result<PayoutCurveBuilder> {
+    if min_price >= max_price {
+        bail!(
+            "Minimum price {} must be below the maximum price {}.",
+            min_price,
+            max_price
+        );
+    }
+    let builder = PayoutCurveBuilder::new(offer_collateral, accept_collateral);
+    let total = builder.total_collateral();
+    Ok(builder.point(min_price, 0).point(max_price, total))
+}
+
+/// The offer party bought a call: it gets `contract_size` sats for every unit the price ends
+/// above `strike`, up to the total collateral.
+pub fn long_call(
+    strike: u64,
+    contract_size: u64,
+    offer_collateral: u64,
+    accept_collateral: u64,
+) -> anyhow::Result<PayoutCurveBuilder> {
+    let builder = PayoutCurveBuilder::new(offer_collateral, accept_collateral);
+    let total = builder.total_collateral();
+    let (full_units, capped) = units_to_cap(contract_size, total)?;
+    let mut points = vec![(strike, 0)];
+    if full_units > 0 {
+        points.push((strike + full_units, full_units * contract_size));
+    }
+    if capped {
+        points.push((strike + full_units + 1, total));
+    }
+    Ok(builder.points(points))
+}
+
+/// The offer party sold a put: it pays `contract_size` sats for every unit the price ends
+/// below `strike` out of the total collateral it gets otherwise.
+pub fn short_put(
+    strike: u64,
+    contract_size: u64,
+    offer_collateral: u64,
+    accept_collateral: u64,
+) -> anyhow::Result<PayoutCurveBuilder> {
+    let builder = PayoutCurveBuilder::new(offer_collateral, accept_collateral);
+    let total = builder.total_collateral();
+    let (full_units, capped) = units_to_cap(contract_size, total)?;
+    let mut points = vec![(strike, total)];
+    if full_units > 0 && strike > 0 {
+        let units = full_units.min(strike);
+        points.push((strike - units, total - units * contract_size));
+    }
+    if capped && strike > full_units {
+        points.push((strike - full_units - 1, 0));
+    }
+    points.reverse();
+    Ok(builder.points(points))
+}
+
+/// Units of price movement paying whole contract sizes without reaching the total collateral,
+/// and whether one more unit is capped at it.
+fn units_to_cap(contract_size: u64, total: u64) -> anyhow::Result<(u64, bool)> {
+    if contract_size == 0 {
+        bail!("Contract size must be at least one sat.");
+    }
+    let full_units = total / contract_size;
+    Ok((full_units, full_units * contract_size < total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dlc::RangePayout;
+    use proptest::prelude::*;
+
+    const BASE: usize = 2;
+    const DIGITS: usize = 17;
+
+    /// The offer payout of the CET covering `outcome`.
+    fn cet_payout(builder: &PayoutCurveBuilder, outcome: u64) -> u64 {
+        let descriptor = builder.numerical_descriptor(BASE, DIGITS).unwrap();
+        let ranges: Vec<RangePayout> = descriptor
+            .payout_function
+            .to_range_payouts(builder.total_collateral(), &descriptor.rounding_intervals)
+            .unwrap();
+        let outcome = outcome as usize;
+        ranges
+            .iter()
+            .find(|range| range.start <= outcome && outcome < range.start + range.count)
+            .map(|range| range.payout.offer)
+            .expect("every outcome has a CET")
+    }
+
+    fn assert_close(actual: u64, expected: u64) {
+        assert!(
+            actual.abs_diff(expected) <= 1,
+            "CET pays {} where the curve pays {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn invalid_curves_are_rejected() {
+        let max = max_outcome(BASE, DIGITS).unwrap();
+        assert!(PayoutCurveBuilder::new(1, 1).validate(max).is_err());
+        let decreasing = PayoutCurveBuilder::new(50, 50).point(10, 0).point(5, 100);
+        assert!(decreasing.validate(max).is_err());
+        let overpaid = PayoutCurveBuilder::new(50, 50).point(5, 0).point(10, 101);
+        assert!(overpaid.validate(max).is_err());
+        let out_of_range = PayoutCurveBuilder::new(50, 50)
+            .point(5, 0)
+            .point(max + 1, 100);
+        assert!(out_of_range.validate(max).is_err());
+        let zero_mod = PayoutCurveBuilder::new(50, 50).point(5, 0).rounding(0, 0);
+        assert!(zero_mod.validate(max).is_err());
+        assert!(linear_payout(10, 10, 50, 50).is_err());
+        assert!(long_call(10, 0, 50, 50).is_err());
+        assert!(max_outcome(2, 64).is_err());
+    }
+
+    #[test]
+    fn curve_spans_the_oracle_outcomes() {
+        let builder = linear_payout(1_000, 2_000, 50_000, 50_000).unwrap();
+        let function = builder
+            .payout_function(max_outcome(BASE, DIGITS).unwrap())
+            .unwrap();
+        assert_eq!(function.payout_function_pieces.len(), 3);
+        let rounding = builder.rounding(10_000, 100).rounding_intervals().intervals;
+        assert_eq!(rounding.len(), 2);
+        assert_eq!(
+            (rounding[0].begin_interval, rounding[0].rounding_mod),
+            (0, 1)
+        );
+        let builder = linear_payout(1_000, 2_000, 50_000, 50_000).unwrap();
+        assert_eq!(cet_payout(&builder, 0), 0);
+        assert_eq!(cet_payout(&builder, 1_500), 50_000);
+        assert_eq!(cet_payout(&builder, (1 << DIGITS) - 1), 100_000);
+    }
+
+    #[test]
+    fn contract_input_follows_the_announcement() {
+        use bitcoin::secp256k1::schnorr::Signature;
+        use dlc_messages::oracle_msgs::{
+            DigitDecompositionEventDescriptor, EnumEventDescriptor, OracleEvent,
+        };
+        use std::str::FromStr;
+
+        let oracle_public_key = XOnlyPublicKey::from_str(
+            "0d829c1cc556aa59060df5a9543c5357199ace5db9bcd5a8ddd6ee2fc7b6d174",
+        )
+        .unwrap();
+        let mut announcement = OracleAnnouncement {
+            announcement_signature: Signature::from_slice(&[1u8; 64]).unwrap(),
+            oracle_public_key,
+            oracle_event: OracleEvent {
+                oracle_nonces: vec![oracle_public_key; 10],
+                event_maturity_epoch: 1_700_000_000,
+                event_descriptor: EventDescriptor::DigitDecompositionEvent(
+                    DigitDecompositionEventDescriptor {
+                        base: 2,
+                        is_signed: false,
+                        unit: "usd".to_string(),
+                        precision: 0,
+                        nb_digits: 10,
+                    },
+                ),
+                event_id: "price".to_string(),
+            },
+        };
+        let builder = long_call(500, 100, 20_000, 30_000).unwrap();
+        let input = builder.contract_input(&announcement, 2).unwrap();
+        assert_eq!(input.contract_infos[0].oracles.event_id, "price");
+        let ContractDescriptor::Numerical(descriptor) =
+            &input.contract_infos[0].contract_descriptor
+        else {
+            panic!("Contract is not numeric.");
+        };
+        assert_eq!(descriptor.oracle_numeric_infos.nb_digits, vec![10]);
+        // This call caps at 5500, above the largest outcome of a 10 digit event.
+        assert!(long_call(500, 10, 20_000, 30_000)
+            .unwrap()
+            .contract_input(&announcement, 2)
+            .is_err());
+
+        announcement.oracle_event.event_descriptor =
+            EventDescriptor::EnumEvent(EnumEventDescriptor {
+                outcomes: vec!["up".to_string()],
+            });
+        assert!(builder.contract_input(&announcement, 2).is_err());
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn linear_cets_follow_the_line(
+            min_price in 0..50_000u64,
+            width in 1..50_000u64,
+            offer in 0..1_000_000u64,
+            accept in 1..1_000_000u64,
+            sample in 0..131_072u64,
+        ) {
+            let max_price = min_price + width;
+            let builder = linear_payout(min_price, max_price, offer, accept).unwrap();
+            let total = offer + accept;
+            let expected = match sample {
+                s if s <= min_price => 0,
+                s if s >= max_price => total,
+                s => ((s - min_price) as u128 * total as u128 / width as u128) as u64,
+            };
+            assert_close(cet_payout(&builder, sample), expected);
+        }
+
+        #[test]
+        fn long_call_cets_pay_the_intrinsic_value(
+            strike in 0..100_000u64,
+            contract_size in 100..10_000u64,
+            offer in 0..1_000_000u64,
+            accept in 1..1_000_000u64,
+            sample in 0..131_072u64,
+        ) {
+            let builder = long_call(strike, contract_size, offer, accept).unwrap();
+            let total = offer + accept;
+            let expected = (sample.saturating_sub(strike) as u128 * contract_size as u128)
+                .min(total as u128) as u64;
+            assert_close(cet_payout(&builder, sample), expected);
+        }
+
+        #[test]
+        fn short_put_cets_pay_out_the_intrinsic_value(
+            strike in 0..100_000u64,
+            contract_size in 100..10_000u64,
+            offer in 0..1_000_000u64,
+            accept in 1..1_000_000u64,
+            sample in 0..131_072u64,
+        ) {
+            let builder = short_put(strike, contract_size, offer, accept).unwrap();
+            let total = offer + accept;
+            let owed = (strike.saturating_sub(sample) as u128 * contract_size as u128)
+                .min(total as u128) as u64;
+            assert_close(cet_payout(&builder, sample), total - owed);
+        }
+    }
+}
@@ -1,12 +1,38 @@
 use std::str::FromStr;
 
 use bitcoin::key::XOnlyPublicKey;
-use dlc::EnumerationPayout;
+use dlc::{EnumerationPayout, Payout};
 use dlc_manager::contract::enum_descriptor::EnumDescriptor;
 use dlc_manager::contract::{
     contract_input::{ContractInput, ContractInputInfo, OracleInput},
     ContractDescriptor,
 };
+use dlc_messages::oracle_msgs::{EventDescriptor, OracleAnnouncement};
+
+/// Why outcome payouts cannot make a contract on an announced event.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum EnumContractError {
+    #[error("Event {0} is not an enum event.")]
+    NotEnumEvent(String),
+    #[error("Outcome {0} has more than one payout.")]
+    DuplicateOutcome(String),
+    #[error("Payouts do not match the announced outcomes. Missing: {missing:?}. Not announced: {extra:?}.")]
+    OutcomeMismatch {
+        missing: Vec<String>,
+        extra: Vec<String>,
+    },
+    #[error("Outcomes must be in the announced order {expected:?}, not {actual:?}.")]
+    OutcomeOrder {
+        expected: Vec<String>,
+        actual: Vec<String>,
+    },
+    #[error("Payout of {payout} sats for outcome {outcome} exceeds the total collateral of {total} sats.")]
+    PayoutExceedsCollateral {
+        outcome: String,
+        payout: u64,
+        total: u64,
+    },
+}
 
 pub fn create_contract_input(
     outcome_payouts: Vec<EnumerationPayout>,
@@ -36,3 +62,228 @@ pub fn create_contract_input(
         contract_infos,
     }
 }
+
+/// A contract on the announced enum event paying the offer party the sats paired with each
+/// outcome. The accept party gets the rest of the total collateral.
+///
+/// The outcomes must be the announced ones, spelled and ordered as in the announcement.
+pub fn enum_contract(
+    outcomes: Vec<(String, u64)>,
+    offer_collateral: u64,
+    accept_collateral: u64,
+    fee_rate: u64,
+    announcement: &OracleAnnouncement,
+) -> Result<ContractInput, EnumContractError> {
+    let event = &announcement.oracle_event;
+    let EventDescriptor::EnumEvent(descriptor) = &event.event_descriptor else {
+        return Err(EnumContractError::NotEnumEvent(event.event_id.clone()));
+    };
+    let actual = outcomes
+        .iter()
+        .map(|(outcome, _)| outcome.clone())
+        .collect::<Vec<_>>();
+    if let Some(duplicate) = actual
+        .iter()
+        .enumerate()
+        .find(|(i, outcome)| actual[..*i].contains(*outcome))
+    {
+        return Err(EnumContractError::DuplicateOutcome(duplicate.1.clone()));
+    }
+    let missing = descriptor
+        .outcomes
+        .iter()
+        .filter(|outcome| !actual.contains(*outcome))
+        .cloned()
+        .collect::<Vec<_>>();
+    let extra = actual
+        .iter()
+        .filter(|outcome| !descriptor.outcomes.contains(*outcome))
+        .cloned()
+        .collect::<Vec<_>>();
+    if !missing.is_empty() || !extra.is_empty() {
+        return Err(EnumContractError::OutcomeMismatch { missing, extra });
+    }
+    if actual != descriptor.outcomes {
+        return Err(EnumContractError::OutcomeOrder {
+            expected: descriptor.outcomes.clone(),
+            actual,
+        });
+    }
+
+    let total = offer_collateral + accept_collateral;
+    let outcome_payouts = outcomes
+        .into_iter()
+        .map(|(outcome, payout)| {
+            if payout > total {
+                return Err(EnumContractError::PayoutExceedsCollateral {
+                    outcome,
+                    payout,
+                    total,
+                });
+            }
+            Ok(EnumerationPayout {
+                outcome,
+                payout: Payout {
+                    offer: payout,
+                    accept: total - payout,
+                },
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(ContractInput {
+        offer_collateral,
+        accept_collateral,
+        fee_rate,
+        contract_infos: vec![ContractInputInfo {
+            contract_descriptor: ContractDescriptor::Enum(EnumDescriptor { outcome_payouts }),
+            oracles: OracleInput {
+                public_keys: vec![announcement.oracle_public_key],
+                event_id: event.event_id.clone(),
+                threshold: 1,
+            },
+        }],
+    })
+}
+
+/// A bet on an event with two outcomes. Both parties put up `collateral` and the offer party
+/// takes all on `outcome_a`, the accept party on `outcome_b`.
+pub fn even_odds(
+    outcome_a: &str,
+    outcome_b: &str,
+    collateral: u64,
+    fee_rate: u64,
+    announcement: &OracleAnnouncement,
+) -> Result<ContractInput, EnumContractError> {
+    let mut outcomes = vec![
+        (outcome_a.to_string(), collateral * 2),
+        (outcome_b.to_string(), 0),
+    ];
+    if let EventDescriptor::EnumEvent(descriptor) = &announcement.oracle_event.event_descriptor {
+        if descriptor.outcomes.first().map(String::as_str) == Some(outcome_b) {
+            outcomes.reverse();
+        }
+    }
+    enum_contract(outcomes, collateral, collateral, fee_rate, announcement)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::secp256k1::schnorr::Signature;
+    use dlc_messages::oracle_msgs::{EnumEventDescriptor, OracleEvent};
+
+    fn announcement(outcomes: &[&str]) -> OracleAnnouncement {
+        let oracle_public_key = XOnlyPublicKey::from_str(
+            "0d829c1cc556aa59060df5a9543c5357199ace5db9bcd5a8ddd6ee2fc7b6d174",
+        )
+        .unwrap();
+        OracleAnnouncement {
+            announcement_signature: Signature::from_slice(&[1u8; 64]).unwrap(),
+            oracle_public_key,
+            oracle_event: OracleEvent {
+                oracle_nonces: vec![oracle_public_key],
+                event_maturity_epoch: 1_700_000_000,
+                event_descriptor: EventDescriptor::EnumEvent(EnumEventDescriptor {
+                    outcomes: outcomes.iter().map(|o| o.to_string()).collect(),
+                }),
+                event_id: "match".to_string(),
+            },
+        }
+    }
+
+    fn payouts(payouts: &[(&str, u64)]) -> Vec<(String, u64)> {
+        payouts.iter().map(|(o, p)| (o.to_string(), *p)).collect()
+    }
+
+    #[test]
+    fn outcomes_must_match_the_announcement() {
+        let announcement = announcement(&["home", "draw", "away"]);
+        let input = enum_contract(
+            payouts(&[("home", 100), ("draw", 50), ("away", 0)]),
+            50,
+            50,
+            2,
+            &announcement,
+        )
+        .unwrap();
+        let ContractDescriptor::Enum(descriptor) = &input.contract_infos[0].contract_descriptor
+        else {
+            panic!("Contract is not an enum contract.");
+        };
+        assert_eq!(
+            descriptor.outcome_payouts[1].payout,
+            Payout {
+                offer: 50,
+                accept: 50
+            }
+        );
+        assert_eq!(input.contract_infos[0].oracles.event_id, "match");
+
+        let error = enum_contract(
+            payouts(&[("Home", 100), ("draw", 50)]),
+            50,
+            50,
+            2,
+            &announcement,
+        )
+        .unwrap_err();
+        assert_eq!(
+            error,
+            EnumContractError::OutcomeMismatch {
+                missing: vec!["home".to_string(), "away".to_string()],
+                extra: vec!["Home".to_string()],
+            }
+        );
+        assert!(error.to_string().contains("Missing: [\"home\", \"away\"]"));
+
+        let error = enum_contract(
+            payouts(&[("away", 0), ("home", 100), ("draw", 50)]),
+            50,
+            50,
+            2,
+            &announcement,
+        )
+        .unwrap_err();
+        assert!(matches!(error, EnumContractError::OutcomeOrder { .. }));
+
+        let error = enum_contract(
+            payouts(&[("home", 101), ("draw", 50), ("away", 0)]),
+            50,
+            50,
+            2,
+            &announcement,
+        )
+        .unwrap_err();
+        assert!(matches!(
+            error,
+            EnumContractError::PayoutExceedsCollateral { payout: 101, .. }
+        ));
+
+        let error = enum_contract(
+            payouts(&[("home", 100), ("home", 50), ("away", 0)]),
+            50,
+            50,
+            2,
+            &announcement,
+        )
+        .unwrap_err();
+        assert_eq!(
+            error,
+            EnumContractError::DuplicateOutcome("home".to_string())
+        );
+    }
+
+    #[test]
+    fn even_odds_follow_the_announced_order() {
+        let input = even_odds("yes", "no", 10_000, 2, &announcement(&["no", "yes"])).unwrap();
+        let ContractDescriptor::Enum(descriptor) = &input.contract_infos[0].contract_descriptor
+        else {
+            panic!("Contract is not an enum contract.");
+        };
+        assert_eq!(descriptor.outcome_payouts[0].outcome, "no");
+        assert_eq!(descriptor.outcome_payouts[0].payout.offer, 0);
+        assert_eq!(descriptor.outcome_payouts[1].payout.offer, 20_000);
+        assert_eq!(input.offer_collateral + input.accept_collateral, 20_000);
+        assert!(even_odds("yes", "maybe", 10_000, 2, &announcement(&["no", "yes"])).is_err());
+    }
+}
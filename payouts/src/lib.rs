@@ -1,3 +1,4 @@
+pub mod curve;
 pub mod enumeration;
 
 use std::str::FromStr;